@@ -0,0 +1,6 @@
+//! Build script.
+
+fn main() {
+    // `sqlx::migrate!` embeds the migrations, so changes to them must trigger a rebuild.
+    println!("cargo:rerun-if-changed=migrations");
+}
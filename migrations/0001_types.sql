@@ -0,0 +1,40 @@
+-- FIXME: Impose consistent row-lock order.
+
+CREATE EXTENSION IF NOT EXISTS citext;
+CREATE EXTENSION IF NOT EXISTS btree_gist;
+
+CREATE DOMAIN USERNAME AS TEXT CONSTRAINT valid_username CHECK (
+    VALUE ~ '^[[:word:]-]{3,20}$'
+);
+
+-- NOTE: This is the HTML5 specification, specifically incompatible with RFC5322.
+CREATE DOMAIN EMAIL AS citext CONSTRAINT valid_email CHECK (
+    VALUE ~ '^[a-zA-Z0-9.!#$%&''*+/=?^_`{|}~-]+@[a-zA-Z0-9](?:[a-zA-Z0-9-]{0,61}[a-zA-Z0-9])?(?:\.[a-zA-Z0-9](?:[a-zA-Z0-9-]{0,61}[a-zA-Z0-9])?)*$'
+);
+
+CREATE DOMAIN NONFUTURE_TIMESTAMP AS TIMESTAMP CHECK (VALUE <= CURRENT_TIMESTAMP);
+
+-- Arbitrary choice of precision and scale.
+CREATE DOMAIN TWOPOINT_UDEC AS DECIMAL(10, 2) CHECK (VALUE >= 0);
+
+-- TODO: Use more suitable integer types. For example, all current uses of `UINT` and
+-- `POSITIVE_INT` would work just as well with a backing `SMALLINT`, while IDs in some of the
+-- larger tables should possibly be `BIGINT`.
+
+CREATE DOMAIN UINT AS INT CHECK (VALUE >= 0);
+
+CREATE DOMAIN POSITIVE_INT AS INT CHECK (VALUE > 0);
+
+CREATE DOMAIN RATING AS INT CHECK (VALUE BETWEEN 1 AND 5);
+
+-- TODO: Improve URL representation or replace entirely (server storage).
+CREATE DOMAIN URL AS TEXT;
+
+-- TODO: Enforce format.
+CREATE DOMAIN PHC_STRING AS TEXT;
+
+CREATE TYPE VOTE AS ENUM ('like', 'dislike');
+
+CREATE TYPE ROLE AS ENUM ('customer', 'vendor', 'administrator');
+
+CREATE TYPE ORDER_STATUS AS ENUM ('pending', 'shipped', 'received');
@@ -0,0 +1,213 @@
+CREATE TABLE users (
+    id INT GENERATED ALWAYS AS IDENTITY PRIMARY KEY,
+    username USERNAME UNIQUE NOT NULL,
+    email EMAIL UNIQUE NOT NULL,
+    password_hash PHC_STRING NOT NULL,
+
+    deleted BOOLEAN NOT NULL DEFAULT FALSE,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE FUNCTION creation_time() RETURNS TRIGGER
+LANGUAGE plpgsql AS $$
+BEGIN
+    NEW.created_at = CASE TG_OP
+        WHEN 'INSERT' THEN CURRENT_TIMESTAMP
+        WHEN 'UPDATE' THEN OLD.created_at
+    END;
+
+    RETURN NEW;
+END;
+$$;
+
+CREATE TRIGGER users_creation_time
+BEFORE INSERT OR UPDATE ON users
+FOR EACH ROW EXECUTE FUNCTION creation_time();
+
+CREATE FUNCTION update_time() RETURNS TRIGGER
+LANGUAGE plpgsql AS $$
+BEGIN
+    NEW.updated_at := CURRENT_TIMESTAMP;
+    RETURN NEW;
+END;
+$$;
+
+CREATE TRIGGER users_update_time
+BEFORE UPDATE OF username, email, password_hash ON users
+FOR EACH ROW EXECUTE FUNCTION update_time();
+
+-- Only a hash of each session token is stored, so that read access to the database does not grant
+-- the ability to impersonate users. Tokens are hex-encoded by the server.
+CREATE TABLE sessions (
+    id INT GENERATED ALWAYS AS IDENTITY PRIMARY KEY,
+    token_hash BYTEA UNIQUE NOT NULL,
+    user_id INT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    expires_at TIMESTAMP NOT NULL,
+    revoked_at TIMESTAMP DEFAULT NULL,
+    CHECK (expires_at > created_at)
+);
+
+CREATE INDEX sessions_by_user ON sessions (user_id);
+
+CREATE VIEW active_sessions AS
+SELECT *
+FROM sessions
+WHERE revoked_at IS NULL AND expires_at > CURRENT_TIMESTAMP;
+
+CREATE TABLE customers (
+    id INT NOT NULL PRIMARY KEY REFERENCES users(id) ON DELETE CASCADE,
+    profile_picture URL,
+    member_since NONFUTURE_TIMESTAMP DEFAULT NULL,
+    member BOOLEAN GENERATED ALWAYS AS (member_since IS NOT NULL) STORED,
+    can_review BOOLEAN NOT NULL DEFAULT TRUE
+);
+
+CREATE TABLE vendors (
+    id INT NOT NULL PRIMARY KEY REFERENCES users(id) ON DELETE CASCADE,
+    profile_picture URL,
+    display_name TEXT UNIQUE NOT NULL,
+    description TEXT NOT NULL
+);
+
+-- A dummy table makes triggers resistent to schema changes, as otherwise they'd have to rely on
+-- the absence of a row in the other tables as meaning "administrator", which could be confused
+-- with a newly-added role.
+CREATE TABLE administrators (
+    id INT NOT NULL PRIMARY KEY REFERENCES users(id) ON DELETE CASCADE
+);
+
+CREATE FUNCTION role_of(id users.id%TYPE) RETURNS ROLE
+LANGUAGE plpgsql STABLE STRICT PARALLEL SAFE AS $$
+DECLARE
+    role ROLE;
+BEGIN
+    CASE
+        WHEN EXISTS(SELECT 1 FROM customers WHERE customers.id = role_of.id)
+            THEN role := 'customer';
+        WHEN EXISTS(SELECT 1 FROM vendors WHERE vendors.id = role_of.id)
+            THEN role := 'vendor';
+        WHEN EXISTS(SELECT 1 FROM administrators WHERE administrators.id = role_of.id) 
+            THEN role := 'administrator';
+        WHEN NOT EXISTS(SELECT 1 FROM users WHERE users.id = role_of.id)
+            THEN RAISE EXCEPTION 'User % does not exist.', id;
+        ELSE RAISE EXCEPTION 'User % is of no or unknown role.', id;
+    END CASE;
+
+    RETURN role;
+END;
+$$;
+
+CREATE FUNCTION update_time_user_super() RETURNS TRIGGER
+LANGUAGE plpgsql AS $$
+BEGIN
+    UPDATE users SET updated_at = CURRENT_TIMESTAMP
+    WHERE id = NEW.id;
+    RETURN NEW;
+END;
+$$;
+
+CREATE TRIGGER customers_update_time_super
+BEFORE UPDATE OF id, profile_picture, member_since ON customers
+FOR EACH ROW EXECUTE FUNCTION update_time_user_super();
+
+CREATE TRIGGER vendors_update_time_super
+BEFORE UPDATE OF id, profile_picture, display_name, description ON vendors
+FOR EACH ROW EXECUTE FUNCTION update_time_user_super();
+
+CREATE TRIGGER administrators_update_time_super
+BEFORE UPDATE ON administrators
+FOR EACH ROW EXECUTE FUNCTION update_time_user_super();
+
+CREATE FUNCTION validate_user_role() RETURNS TRIGGER
+LANGUAGE plpgsql STABLE AS $$
+DECLARE
+    user_id users.id%TYPE;
+BEGIN
+    -- `NEW` is null on deletion, but then `OLD` is non-null.
+    user_id := COALESCE(NEW.id, OLD.id);
+    IF
+        EXISTS (SELECT 1 FROM users WHERE id = user_id)
+        AND EXISTS (SELECT 1 FROM customers      WHERE id = user_id)::INT
+          + EXISTS (SELECT 1 FROM vendors        WHERE id = user_id)::INT
+          + EXISTS (SELECT 1 FROM administrators WHERE id = user_id)::INT
+         != 1
+    THEN
+        RAISE EXCEPTION 'User (%) must have exactly one role.', user_id;
+    END IF;
+
+    RETURN NEW;
+END;
+$$;
+
+CREATE CONSTRAINT TRIGGER users_valid_subclass
+AFTER INSERT ON users
+DEFERRABLE INITIALLY DEFERRED
+FOR EACH ROW EXECUTE FUNCTION validate_user_role();
+
+CREATE CONSTRAINT TRIGGER customers_valid_superclass
+AFTER INSERT OR UPDATE OF id OR DELETE ON customers
+DEFERRABLE INITIALLY DEFERRED
+FOR EACH ROW EXECUTE FUNCTION validate_user_role();
+
+CREATE CONSTRAINT TRIGGER vendors_valid_superclass
+AFTER INSERT OR UPDATE OF id OR DELETE ON vendors
+DEFERRABLE INITIALLY DEFERRED
+FOR EACH ROW EXECUTE FUNCTION validate_user_role();
+
+CREATE CONSTRAINT TRIGGER administrators_valid_superclass
+AFTER INSERT OR UPDATE OF id OR DELETE ON administrators
+DEFERRABLE INITIALLY DEFERRED
+FOR EACH ROW EXECUTE FUNCTION validate_user_role();
+
+-- TODO: Handle reactivation of deleted users.
+
+CREATE PROCEDURE create_customer(
+    username users.username%TYPE,
+    email users.email%TYPE,
+    password_hash users.password_hash%TYPE,
+    profile_picture customers.profile_picture%TYPE
+) LANGUAGE sql AS $$
+    WITH new_user AS (
+        INSERT INTO users (username, email, password_hash)
+        VALUES (username, email, password_hash)
+        RETURNING id
+    )
+    INSERT INTO customers (id, profile_picture)
+    SELECT id, profile_picture
+    FROM new_user
+$$;
+
+CREATE PROCEDURE create_vendor(
+    username users.username%TYPE,
+    email users.email%TYPE,
+    password_hash users.password_hash%TYPE,
+    profile_picture vendors.profile_picture%TYPE,
+    display_name vendors.display_name%TYPE,
+    description vendors.description%TYPE
+) LANGUAGE sql AS $$
+    WITH new_user AS (
+        INSERT INTO users (username, email, password_hash)
+        VALUES (username, email, password_hash)
+        RETURNING id
+    )
+    INSERT INTO vendors (id, profile_picture, display_name, description)
+    SELECT id, profile_picture, display_name, description
+    FROM new_user
+$$;
+
+CREATE PROCEDURE create_administrator(
+    username users.username%TYPE,
+    email users.email%TYPE,
+    password_hash users.password_hash%TYPE
+) LANGUAGE sql AS $$
+    WITH new_user AS (
+        INSERT INTO users (username, email, password_hash)
+        VALUES (username, email, password_hash)
+        RETURNING id
+    )
+    INSERT INTO administrators (id)
+    SELECT id
+    FROM new_user
+$$;
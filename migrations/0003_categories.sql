@@ -0,0 +1,66 @@
+CREATE TABLE categories (
+    id INT GENERATED ALWAYS AS IDENTITY PRIMARY KEY,
+    name TEXT UNIQUE NOT NULL,
+    parent INT REFERENCES categories(id) ON DELETE CASCADE
+);
+
+CREATE INDEX categories_by_parent_name ON categories (parent NULLS FIRST, name);
+
+CREATE TYPE CATEGORY_PATH_SEGMENT AS (id INT, name TEXT);
+CREATE FUNCTION category_path(start_id categories.id%TYPE) RETURNS CATEGORY_PATH_SEGMENT[]
+LANGUAGE plpgsql STRICT PARALLEL SAFE AS $$
+DECLARE
+    path category_path_segment[] := ARRAY[]::CATEGORY_PATH_SEGMENT[];
+    current_id categories.id%TYPE := start_id;
+    current_name categories.name%TYPE;
+    current_parent categories.parent%TYPE;
+BEGIN
+    LOOP
+        SELECT id, name, parent
+        INTO STRICT current_id, current_name, current_parent
+        FROM categories WHERE id = current_id;
+        IF EXISTS (SELECT 1 FROM UNNEST(path) WHERE id = current_id) THEN
+            RAISE EXCEPTION 'Cycle detected in path of category %.', start_id;
+        END IF;
+
+        path := path || (current_id, current_name)::CATEGORY_PATH_SEGMENT;
+
+        IF current_parent IS NULL THEN
+            EXIT;
+        END IF;
+
+        current_id := current_parent;
+    END LOOP;
+
+    RETURN path;
+END;
+$$;
+
+-- NOTE: Can't use `category_path` since the row being validated is not yet visible to it.
+CREATE FUNCTION categories_validate_tree() RETURNS TRIGGER
+LANGUAGE plpgsql STABLE AS $$
+DECLARE
+    visited INT[] := ARRAY[NEW.id];
+    current_id INT := NEW.parent;
+    current_parent INT;
+BEGIN
+    WHILE current_id IS NOT NULL LOOP
+        IF current_id = ANY(visited) THEN
+            RAISE EXCEPTION 'Cycle detected in path of category %.', NEW.id;
+        END IF;
+        visited := visited || current_id;
+
+        SELECT parent
+        INTO STRICT current_parent
+        FROM categories
+        WHERE id = current_id;
+        current_id := current_parent;
+    END LOOP;
+
+    RETURN NEW;
+END;
+$$;
+
+CREATE TRIGGER categories_valid_tree
+BEFORE INSERT OR UPDATE OF parent ON categories
+FOR EACH ROW EXECUTE FUNCTION categories_validate_tree();
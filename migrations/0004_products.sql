@@ -0,0 +1,222 @@
+CREATE TABLE products (
+    id INT GENERATED ALWAYS AS IDENTITY PRIMARY KEY,
+    name TEXT UNIQUE NOT NULL,
+    thumbnail URL NOT NULL,
+    gallery URL[] NOT NULL,
+    price TWOPOINT_UDEC NOT NULL CHECK (price > 0),
+    overview TEXT NOT NULL,
+    description TEXT NOT NULL,
+    in_stock UINT NOT NULL DEFAULT 0,
+    visible BOOLEAN NOT NULL DEFAULT TRUE,
+    vendor INT NOT NULL REFERENCES vendors(id) ON DELETE CASCADE,
+    category INT NOT NULL REFERENCES categories(id) ON DELETE RESTRICT,
+    origin TEXT NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    -- NOTE: Does not track changes to stock or visibility.
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    search_vector TSVECTOR NOT NULL,
+    amount_per_unit TWOPOINT_UDEC NOT NULL DEFAULT 1,
+    -- Null: discrete amount.
+    measurement_unit TEXT,
+    CONSTRAINT valid_without_unit CHECK (measurement_unit IS NOT NULL OR amount_per_unit % 1 = 0)
+);
+
+CREATE INDEX products_by_vendor ON products (vendor);
+CREATE INDEX products_by_category ON products (category);
+CREATE INDEX visible_products_by_time ON products (created_at DESC)
+WHERE visible AND in_stock > 0;
+
+CREATE TRIGGER products_creation_time
+BEFORE INSERT OR UPDATE ON products
+FOR EACH ROW EXECUTE FUNCTION creation_time();
+
+CREATE TRIGGER products_update_time
+BEFORE UPDATE OF name, thumbnail, gallery, price, overview, description,
+                 vendor, category, origin, amount_per_unit, measurement_unit
+ON products
+FOR EACH ROW EXECUTE FUNCTION update_time();
+
+-- NOTE: Takes the fields rather than the ID of a product, as the row being inserted or updated is not
+-- visible from the trigger setting it.
+CREATE FUNCTION products_search_vector(
+    name products.name%TYPE,
+    overview products.overview%TYPE,
+    description products.description%TYPE,
+    category_name categories.name%TYPE
+) RETURNS TSVECTOR
+LANGUAGE sql IMMUTABLE STRICT PARALLEL SAFE AS $$
+    SELECT setweight(to_tsvector('english', name), 'A')
+        || setweight(to_tsvector('english', overview), 'D')
+        || setweight(to_tsvector('english', description), 'D')
+        || setweight(to_tsvector('english', category_name), 'B');
+$$;
+
+CREATE FUNCTION products_set_search_vector() RETURNS TRIGGER
+LANGUAGE plpgsql AS $$
+BEGIN
+    NEW.search_vector := products_search_vector(
+        NEW.name,
+        NEW.overview,
+        NEW.description,
+        (SELECT name FROM categories WHERE id = NEW.category)
+    );
+    RETURN NEW;
+END;
+$$;
+
+CREATE TRIGGER products_update_search_vector
+BEFORE INSERT OR UPDATE OF name, overview, description, category ON products
+FOR EACH ROW EXECUTE FUNCTION products_set_search_vector();
+
+CREATE FUNCTION categories_set_products_search_vector() RETURNS TRIGGER
+LANGUAGE plpgsql AS $$
+BEGIN
+    UPDATE products
+    SET search_vector = products_search_vector(name, overview, description, NEW.name)
+    WHERE category = NEW.id;
+
+    RETURN NULL;
+END;
+$$;
+
+CREATE TRIGGER categories_update_products_search_vector
+AFTER UPDATE OF name ON categories
+FOR EACH ROW EXECUTE FUNCTION categories_set_products_search_vector();
+
+CREATE INDEX products_by_search_vector ON products USING GIN(search_vector);
+
+CREATE TABLE special_offers (
+    id INT GENERATED ALWAYS AS IDENTITY PRIMARY KEY,
+    product INT NOT NULL REFERENCES products(id) ON DELETE CASCADE,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    members_only BOOLEAN NOT NULL DEFAULT FALSE,
+    -- Measured in number of batches (per unit if there is no concept of a batch).
+    limit_per_customer POSITIVE_INT,
+    valid_from TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    -- Null: offer must be removed manually.
+    valid_until TIMESTAMP CONSTRAINT end_after_start CHECK (valid_until IS NULL OR valid_until > valid_from),
+
+    -- The lack of sum types is noticeable here. There are three variants:
+    -- 1. "NEW PRICE `new_price`" (sale) has both quantities as `NULL`.
+    -- 2. "TAKE `quantity1` PAY FOR `quantity2`" has `new_price` as `NULL`.
+    -- 3. "TAKE `quantity1` PAY `new_price`" has `quantity2` as `NULL`.
+    new_price TWOPOINT_UDEC,
+    quantity1 INT CHECK (quantity1 IS NULL OR quantity1 > 1),
+    quantity2 INT CHECK (quantity2 IS NULL OR quantity2 >= 1),
+
+    -- There is no technical or logical reason why there couldn't be several active special offers:
+    -- the  price calculator would just have to choose the better price.
+    CONSTRAINT no_overlap EXCLUDE USING gist (
+        product WITH =,
+        tsrange(valid_from, valid_until) WITH &&
+    )
+);
+
+-- Can't make partial to active offers as that depends on the time.
+CREATE INDEX offers_by_product ON special_offers (product);
+
+CREATE VIEW active_special_offers AS
+SELECT *
+FROM special_offers
+WHERE valid_from < CURRENT_TIMESTAMP AND (valid_until IS NULL OR valid_until > CURRENT_TIMESTAMP);
+
+CREATE TRIGGER offers_update_time
+BEFORE UPDATE ON special_offers
+FOR EACH ROW EXECUTE FUNCTION update_time();
+
+CREATE FUNCTION average_discount(
+    base_price products.price%TYPE,
+    new_price special_offers.new_price%TYPE,
+    quantity1 special_offers.quantity1%TYPE,
+    quantity2 special_offers.quantity2%TYPE
+) RETURNS TWOPOINT_UDEC
+LANGUAGE plpgsql IMMUTABLE PARALLEL SAFE AS $$
+DECLARE
+    discount TWOPOINT_UDEC;
+BEGIN
+    -- No special offer.
+    IF new_price IS NULL AND quantity1 IS NULL AND quantity2 IS NULL THEN
+        RETURN NULL;
+    -- Variant 1.
+    ELSIF new_price IS NOT NULL AND quantity1 IS NULL AND quantity2 IS NULL THEN
+        IF new_price >= base_price THEN
+            RAISE EXCEPTION 'New price (%) is not less than base price (%).', new_price, base_price;
+        END IF;
+        
+        IF base_price = 0 THEN
+            discount := 1;
+        ELSE
+            discount := 1 - new_price / base_price;
+        END IF;
+    -- Variant 2.
+    ELSIF new_price IS NULL AND quantity1 IS NOT NULL AND quantity2 IS NOT NULL THEN
+        IF quantity1 <= 1 THEN
+            RAISE EXCEPTION 'Must be asked to take more than 1 (found %).', quantity1;
+        ELSIF quantity2 < 1 THEN
+            RAISE EXCEPTION 'Must be asked to pay for at least 1 (found 0).';
+        ELSIF quantity1 <= quantity2 THEN
+            RAISE EXCEPTION 'Must be asked to pay for less than taken (found % for the price of %).', quantity1, quantity2;
+        END IF;
+        discount := 1 - quantity2::TWOPOINT_UDEC / quantity1::TWOPOINT_UDEC;
+    -- Variant 3.
+    ELSIF new_price IS NOT NULL AND quantity1 IS NOT NULL AND quantity2 IS NULL THEN
+        IF quantity1 <= 1 THEN
+            RAISE EXCEPTION 'Must be asked to take more than 1 (found %).', quantity1;
+        ELSIF new_price >= base_price * quantity1 THEN
+            RAISE EXCEPTION 'Must be asked to pay less in bulk (found % for %).', quantity1, new_price;
+        END IF;
+        
+        IF base_price = 0 THEN
+            discount := 1;
+        ELSE
+            discount := 1 - new_price / (base_price * quantity1);
+        END IF;
+    ELSE
+        RAISE EXCEPTION 'Invalid variant.';
+    END IF;
+
+    RETURN discount;
+END;
+$$;
+
+CREATE FUNCTION offers_validate_discount() RETURNS TRIGGER
+LANGUAGE plpgsql STABLE AS $$
+BEGIN
+    PERFORM average_discount(price, NEW.new_price, NEW.quantity1, NEW.quantity2)
+    FROM products
+    WHERE id = NEW.product;
+
+    RETURN NEW;
+END;
+$$;
+
+CREATE TRIGGER offers_valid_discount
+BEFORE INSERT OR UPDATE OF product, new_price, quantity1, quantity2 ON special_offers
+FOR EACH ROW EXECUTE FUNCTION offers_validate_discount();
+
+CREATE FUNCTION products_validate_discounts() RETURNS TRIGGER
+LANGUAGE plpgsql STABLE AS $$
+BEGIN
+    PERFORM average_discount(NEW.price, new_price, quantity1, quantity2)
+    FROM special_offers so
+    WHERE so.product = NEW.id AND (so.valid_until IS NULL OR so.valid_until > CURRENT_TIMESTAMP);
+
+    RETURN NEW;
+END;
+$$;
+
+CREATE TRIGGER products_valid_discounts
+BEFORE UPDATE OF price ON products
+FOR EACH ROW EXECUTE FUNCTION products_validate_discounts();
+
+-- NOTE: It is possible for a customer to have used a special offer more times than the limit
+-- allows due to the limit having changed. Similarly, it is possible for a non-member to have used
+-- members-only special offer due to the status of the latter having changed. These are not errors
+-- and nothing should be changed about the history, it should only prevent future uses.
+-- NOTE: Rows with 0 uses are allowed to serve as locks in `checkout`.
+CREATE TABLE special_offer_uses (
+    customer INT NOT NULL REFERENCES customers(id) ON DELETE CASCADE,
+    special_offer INT NOT NULL REFERENCES special_offers(id) ON DELETE CASCADE,
+    number UINT NOT NULL DEFAULT 0,
+    PRIMARY KEY (special_offer, customer)
+);
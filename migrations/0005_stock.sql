@@ -0,0 +1,147 @@
+-- NOTE: Tracks historical expiries as well. When products are sold, their next expiry should be
+-- decremented and removed if 0.
+CREATE TABLE expiries (
+    product INT NOT NULL REFERENCES products(id) ON DELETE CASCADE,
+    expiry DATE NOT NULL,
+    number POSITIVE_INT NOT NULL,
+    -- Null: not processed yet.
+    processed_at NONFUTURE_TIMESTAMP CONSTRAINT processed_after_expiry CHECK (processed_at >= expiry),
+    CONSTRAINT aggregate_expiries UNIQUE (product, expiry)
+);
+
+CREATE INDEX expiries_by_product_processed ON expiries (product, processed_at NULLS FIRST);
+CREATE INDEX expiries_by_product_date ON expiries (product, expiry);
+
+CREATE VIEW pending_expiries AS
+SELECT *
+FROM expiries
+WHERE processed_at IS NULL AND expiry <= CURRENT_DATE;
+
+-- PERF: Not currently supported by an index.
+CREATE FUNCTION process_expiries() RETURNS TABLE (
+    product INT,
+    -- NOTE: This is the naive sum of the listed number of expiries. It could be the case that
+    -- stock has been reduced for reasons other than expiries or purchases, in which case this
+    -- does *not* represent the number of products that actually expired.
+    total BIGINT
+) LANGUAGE sql AS $$
+    WITH processed AS (
+        UPDATE pending_expiries
+        SET processed_at = CURRENT_TIMESTAMP
+        RETURNING product, number
+    ),
+    counts AS (
+        SELECT product, SUM(number) AS total
+        FROM processed
+        GROUP BY product
+    )
+    UPDATE products
+    -- We accept that there might have "disappeared" products due to manual intervention. Maybe some
+    -- units arrived with broken packaging.
+    SET in_stock = GREATEST(products.in_stock - counts.total, 0)
+    FROM counts
+    WHERE products.id = counts.product
+    RETURNING products.id, counts.total
+$$;
+
+-- WARN: Only actually runs at midnight. If the database is down at that time, expiries will be
+-- missed. Hence, call this function on establishing a connection to the database. If this is done,
+-- there will be no issues with data integrity as the downage would also prevent orders from being
+-- placed.
+-- Safer alternatives would be to have the database automatically call this at startup (is this
+-- possible?) or to run it on each relevant access to affected tables (is this feasible?).
+-- NOTE: `pg_cron` is not available everywhere (e.g. in local development databases), and can only
+-- be installed in the database it is configured for. Without it, expiries are only processed on
+-- establishing a connection.
+DO $do$
+BEGIN
+    IF EXISTS (SELECT 1 FROM pg_available_extensions WHERE name = 'pg_cron') THEN
+        CREATE EXTENSION IF NOT EXISTS pg_cron;
+        PERFORM cron.schedule(
+            'process_daily_expiries',
+            -- Daily at midnight.
+            '0 0 * * *',
+            $$
+            SELECT process_expiries();
+            $$
+        );
+    ELSE
+        RAISE NOTICE 'pg_cron is not available, expiries will not be processed automatically.';
+    END IF;
+EXCEPTION
+    WHEN OTHERS THEN
+        RAISE NOTICE 'Failed to schedule processing of expiries: %', SQLERRM;
+END;
+$do$;
+
+CREATE FUNCTION add_stock(
+    product_id INT,
+    added INT,
+    expires DATE = NULL
+) RETURNS INT
+LANGUAGE plpgsql AS $$
+DECLARE
+    new_stock INT;
+BEGIN
+    -- Consistent lock order with `checkout`.
+    PERFORM 1
+    FROM products
+    WHERE id = product_id
+    FOR KEY SHARE;
+    IF NOT FOUND THEN
+        RAISE EXCEPTION 'Product % does not exist.', product_id;
+    END IF;
+
+    IF expires IS NOT NULL THEN
+        INSERT INTO expiries (product, expiry, number)
+        VALUES (product_id, expires, added)
+        ON CONFLICT (product, expiry) DO UPDATE
+        SET number = expiries.number + EXCLUDED.number;
+    END IF;
+    
+    UPDATE products
+    SET in_stock = in_stock + added
+    WHERE id = product_id
+    RETURNING in_stock INTO STRICT new_stock;
+
+    RETURN new_stock;
+END;
+$$;
+
+CREATE FUNCTION sale_remove_expiries(
+    product_id expiries.product%TYPE,
+    sold INT
+) RETURNS INT
+LANGUAGE plpgsql STRICT AS $$
+DECLARE
+    remaining INT := sold;
+    current_expiry expiries.expiry%TYPE;
+    current_number expiries.number%TYPE;
+BEGIN
+    IF remaining < 0 THEN
+        RAISE EXCEPTION 'Sold units must be non-negative (found %).', remaining;
+    END IF;
+
+    FOR current_expiry, current_number IN
+        SELECT expiry, number
+        FROM expiries
+        WHERE product = product_id AND processed_at IS NULL
+        ORDER BY expiry
+        FOR NO KEY UPDATE
+    LOOP
+        IF current_number > remaining THEN
+            UPDATE expiries
+            SET number = number - remaining
+            WHERE product = product_id AND expiry = current_expiry;
+
+            RETURN 0;
+        ELSE
+            remaining := remaining - current_number;
+            DELETE FROM expiries
+            WHERE product = product_id AND expiry = current_expiry;
+        END IF;
+    END LOOP;
+
+    RETURN remaining;
+END;
+$$;
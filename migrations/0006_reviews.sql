@@ -0,0 +1,178 @@
+-- Only customers are allowed to rate and review products. Vendors woulf use these only to inflate
+-- scores on their own products, and administrators have no reason to. However, all users can reply
+-- to reviews and comments, as they might want to answer questions or clear up confusions.
+
+CREATE TABLE ratings (
+    customer INT NOT NULL REFERENCES customers(id) ON DELETE CASCADE,
+    product INT NOT NULL REFERENCES products(id) ON DELETE CASCADE,
+    rating RATING NOT NULL,
+    PRIMARY KEY (product, customer)
+);
+
+CREATE FUNCTION rater_has_purchase() RETURNS TRIGGER
+LANGUAGE plpgsql STABLE AS $$
+BEGIN
+    IF NOT EXISTS (SELECT 1 FROM orders WHERE customer = NEW.customer AND product = NEW.product) THEN
+        RAISE EXCEPTION 'Customer (%) must have previously bought the product to rate it.', NEW.customer;
+    END IF;
+
+    RETURN NEW;
+END;
+$$;
+
+CREATE TRIGGER validate_rater
+BEFORE INSERT OR UPDATE OF customer, product ON ratings
+FOR EACH ROW EXECUTE FUNCTION rater_has_purchase();
+
+CREATE TABLE reviews (
+    id INT GENERATED ALWAYS AS IDENTITY PRIMARY KEY,
+    customer INT NOT NULL REFERENCES customers(id) ON DELETE CASCADE,
+    product INT NOT NULL REFERENCES products(id) ON DELETE CASCADE,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    title TEXT NOT NULL,
+    content TEXT NOT NULL,
+    CONSTRAINT one_review_per_customer_per_product UNIQUE (product, customer),
+    -- Deleting their review is probably not what a customer intends when unsetting their rating.
+    FOREIGN KEY (product, customer) REFERENCES ratings ON DELETE RESTRICT
+);
+
+CREATE INDEX reviews_by_customer_update ON reviews (customer, updated_at DESC);
+CREATE INDEX reviews_by_product ON reviews (product);
+
+CREATE TRIGGER reviews_creation_time
+BEFORE INSERT OR UPDATE ON reviews
+FOR EACH ROW EXECUTE FUNCTION creation_time();
+
+CREATE TRIGGER reviews_update_time
+BEFORE UPDATE ON reviews
+FOR EACH ROW EXECUTE FUNCTION update_time();
+
+CREATE FUNCTION reviewer_can_review() RETURNS TRIGGER
+LANGUAGE plpgsql STABLE AS $$
+BEGIN
+    IF NOT (SELECT can_review FROM customers WHERE id = NEW.customer) THEN
+        RAISE EXCEPTION 'Customer (%) must be able to place reviews.', NEW.customer;
+    END IF;
+
+    RETURN NEW;
+END;
+$$;
+
+CREATE TRIGGER validate_reviewer
+BEFORE INSERT OR UPDATE OF customer ON reviews
+FOR EACH ROW EXECUTE FUNCTION reviewer_can_review();
+
+CREATE TABLE review_votes (
+    customer INT NOT NULL REFERENCES customers(id) ON DELETE CASCADE,
+    review INT NOT NULL REFERENCES reviews(id) ON DELETE CASCADE,
+    grade VOTE NOT NULL,
+    PRIMARY KEY (review, customer)
+);
+
+CREATE FUNCTION no_vote_on_own_review() RETURNS TRIGGER
+LANGUAGE plpgsql STABLE AS $$
+BEGIN
+    IF NEW.customer = (SELECT customer FROM reviews WHERE id = NEW.review) THEN
+        RAISE EXCEPTION 'Customer (%) can not vote on their own review.', NEW.customer;
+    END IF;
+
+    RETURN NEW;
+END;
+$$;
+
+CREATE TRIGGER deny_own_review_vote
+BEFORE INSERT OR UPDATE OF customer, review ON review_votes
+FOR EACH ROW EXECUTE FUNCTION no_vote_on_own_review();
+
+CREATE TABLE comments (
+    id INT GENERATED ALWAYS AS IDENTITY PRIMARY KEY,
+    -- We allow vendors (and administrators) to place comments, for example to respond to critique.
+    user_id INT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    content TEXT NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+
+    -- Child comments also have this set for easier queries.
+    review INT NOT NULL REFERENCES reviews(id) ON DELETE CASCADE,
+    -- Null: belongs to review directly.
+    parent INT DEFAULT NULL REFERENCES comments(id) ON DELETE CASCADE
+);
+
+CREATE INDEX comments_by_review_parent ON comments (review, parent NULLS FIRST);
+
+CREATE TRIGGER comments_creation_time
+BEFORE INSERT OR UPDATE ON comments
+FOR EACH ROW EXECUTE FUNCTION creation_time();
+
+CREATE TRIGGER comments_update_time
+BEFORE UPDATE ON comments
+FOR EACH ROW EXECUTE FUNCTION update_time();
+
+CREATE FUNCTION comment_parent_same_review() RETURNS TRIGGER
+LANGUAGE plpgsql STABLE AS $$
+BEGIN
+
+    IF NEW.parent IS NOT NULL
+        AND NEW.review != (SELECT review FROM comments WHERE id = NEW.parent)
+    THEN
+        RAISE EXCEPTION 'Reply (%) must belong to the same review (%) as its parent (%).', NEW.id, NEW.review, NEW.parent;
+    END IF;
+
+    RETURN NEW;
+END;
+$$;
+
+CREATE TRIGGER comment_same_review
+BEFORE INSERT OR UPDATE OF parent, review ON comments
+FOR EACH ROW EXECUTE FUNCTION comment_parent_same_review();
+
+CREATE FUNCTION comments_validate_tree() RETURNS TRIGGER
+LANGUAGE plpgsql STABLE AS $$
+DECLARE
+    visited INT[] := ARRAY[NEW.id];
+    current_id INT := NEW.parent;
+    current_parent INT;
+BEGIN
+    WHILE current_id IS NOT NULL LOOP
+        IF current_id = ANY(visited) THEN
+            RAISE EXCEPTION 'Cycle detected in path of comment %.', NEW.id;
+        END IF;
+        visited := visited || current_id;
+
+        SELECT parent 
+        INTO STRICT current_parent
+        FROM comments
+        WHERE id = current_id;
+        current_id := current_parent;
+    END LOOP;
+    
+    RETURN NEW;
+END;
+$$;
+
+CREATE TRIGGER comments_valid_tree
+BEFORE INSERT OR UPDATE OF parent ON comments
+FOR EACH ROW EXECUTE FUNCTION comments_validate_tree();
+
+CREATE TABLE comment_votes (
+    customer INT NOT NULL REFERENCES customers(id) ON DELETE CASCADE,
+    comment INT NOT NULL REFERENCES comments(id) ON DELETE CASCADE,
+    grade VOTE NOT NULL,
+    PRIMARY KEY (comment, customer)
+);
+
+CREATE FUNCTION no_vote_on_own_comment() RETURNS TRIGGER
+LANGUAGE plpgsql STABLE AS $$
+BEGIN
+    IF NEW.customer = (SELECT user_id FROM comments WHERE id = NEW.comment) THEN
+        RAISE EXCEPTION 'Customer (%) can not vote on their own comment.', NEW.customer;
+    END IF;
+
+    RETURN NEW;
+END;
+$$;
+
+CREATE TRIGGER deny_own_comment_vote
+BEFORE INSERT OR UPDATE OF customer, comment ON comment_votes
+FOR EACH ROW EXECUTE FUNCTION no_vote_on_own_comment();
@@ -0,0 +1,115 @@
+CREATE TABLE shopping_cart_items (
+    customer INT NOT NULL REFERENCES customers(id) ON DELETE CASCADE,
+    -- Null: product was deleted since being added to cart. The customer can see that this has
+    -- happened, but not what the product was.
+    product INT REFERENCES products(id) ON DELETE SET NULL,
+    number UINT NOT NULL,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+-- Unique `(customer, product)` pairs except that `NULL = NULL` for `product`.
+CREATE UNIQUE INDEX items_valid ON shopping_cart_items (customer, product)
+WHERE product IS NOT NULL;
+CREATE UNIQUE INDEX items_removed ON shopping_cart_items (customer)
+WHERE product IS NULL;
+
+CREATE TRIGGER cart_update_time
+BEFORE UPDATE ON shopping_cart_items
+FOR EACH ROW EXECUTE FUNCTION update_time();
+
+-- Replace a product in all carts with the placeholder for removed products. Since customers have at
+-- most one placeholder, rows that would create a second one are deleted instead.
+CREATE FUNCTION remove_from_carts(product_id products.id%TYPE) RETURNS VOID
+LANGUAGE sql AS $$
+    DELETE FROM shopping_cart_items sci
+    WHERE sci.product = product_id AND EXISTS (
+        SELECT 1
+        FROM shopping_cart_items placeholder
+        WHERE placeholder.customer = sci.customer AND placeholder.product IS NULL
+    );
+
+    UPDATE shopping_cart_items
+    SET product = NULL
+    WHERE product = product_id;
+$$;
+
+CREATE FUNCTION hide_from_carts_on_invisible() RETURNS TRIGGER
+LANGUAGE plpgsql AS $$
+BEGIN
+    IF NOT NEW.visible THEN
+        PERFORM remove_from_carts(NEW.id);
+    END IF;
+
+    RETURN NEW;
+END;
+$$;
+
+CREATE TRIGGER products_hide_invisible_from_carts
+AFTER UPDATE OF visible ON products
+FOR EACH ROW EXECUTE FUNCTION hide_from_carts_on_invisible();
+
+-- `ON DELETE SET NULL` alone would violate `items_removed` for customers already having a
+-- placeholder.
+CREATE FUNCTION remove_deleted_from_carts() RETURNS TRIGGER
+LANGUAGE plpgsql AS $$
+BEGIN
+    PERFORM remove_from_carts(OLD.id);
+    RETURN OLD;
+END;
+$$;
+
+CREATE TRIGGER products_remove_deleted_from_carts
+BEFORE DELETE ON products
+FOR EACH ROW EXECUTE FUNCTION remove_deleted_from_carts();
+
+CREATE FUNCTION calculate_price(
+    base_price products.price%TYPE,
+    number shopping_cart_items.number%TYPE,
+    new_price special_offers.new_price%TYPE,
+    quantity1 special_offers.quantity1%TYPE,
+    quantity2 special_offers.quantity2%TYPE,
+    remaining_uses UINT,
+    OUT price DECIMAL(10, 2),
+    OUT uses INT
+) LANGUAGE plpgsql IMMUTABLE PARALLEL SAFE AS $$
+BEGIN
+    IF base_price IS NULL THEN
+        RAISE EXCEPTION 'Base price must not be null.';
+    ELSIF number IS NULL THEN
+        RAISE EXCEPTION 'Number of units must not be null.';
+    ELSIF remaining_uses IS NULL THEN
+        RAISE EXCEPTION 'Remaining uses must not be null.';
+    END IF;
+
+    -- No special offer.
+    IF new_price IS NULL AND quantity1 IS NULL AND quantity2 IS NULL THEN
+        uses := 0;
+        price := base_price * number;
+    -- Variant 1.
+    ELSIF new_price IS NOT NULL AND quantity1 IS NULL AND quantity2 IS NULL THEN
+        uses := LEAST(number, remaining_uses);
+        price := uses * (new_price - base_price) + base_price * number;
+    -- Variant 2.
+    ELSIF new_price IS NULL AND quantity1 IS NOT NULL AND quantity2 IS NOT NULL THEN
+        uses := LEAST(number / quantity1, remaining_uses);
+        price := base_price * (number - uses * (quantity1 - quantity2));
+    -- Variant 3.
+    ELSIF new_price IS NOT NULL AND quantity1 IS NOT NULL AND quantity2 IS NULL THEN
+        uses := LEAST(number / quantity1, remaining_uses);
+        price := new_price * uses + base_price * (number - quantity1 * uses);
+    ELSE
+        RAISE EXCEPTION 'Invalid variant.';
+    END IF;
+END;
+$$;
+
+CREATE TABLE customer_favorites (
+    customer INT NOT NULL REFERENCES customers(id) ON DELETE CASCADE,
+    product INT NOT NULL REFERENCES products(id) ON DELETE CASCADE,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (product, customer)
+);
+
+CREATE TRIGGER favorites_creation_time
+BEFORE INSERT OR UPDATE ON customer_favorites
+FOR EACH ROW EXECUTE FUNCTION creation_time();
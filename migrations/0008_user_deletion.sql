@@ -0,0 +1,48 @@
+CREATE PROCEDURE delete_user(deleted_id users.id%TYPE)
+LANGUAGE plpgsql AS $$
+BEGIN
+    -- NOTE: Soft deletion. Possible corresponding row in role-specific table is also kept.
+    UPDATE users
+    SET deleted = true
+    WHERE id = deleted_id;
+    IF NOT FOUND THEN
+        RAISE EXCEPTION 'User % does not exist.', deleted_id;
+    END IF;
+
+    -- PERF: Several of these queries are not supported by indices: we imagine account deletions
+    -- are rare.
+    IF EXISTS (SELECT 1 FROM customers WHERE id = deleted_id) THEN
+        DELETE FROM special_offer_uses
+        WHERE customer = deleted_id;
+
+        -- NOTE: Reviews must be deleted before ratings.
+        DELETE FROM reviews
+        WHERE customer = deleted_id;
+
+        DELETE FROM ratings
+        WHERE customer = deleted_id;
+
+        DELETE FROM review_votes
+        WHERE customer = deleted_id;
+
+        DELETE FROM comment_votes
+        WHERE customer = deleted_id;
+
+        DELETE FROM shopping_cart_items
+        WHERE customer = deleted_id;
+
+        DELETE FROM customer_favorites
+        WHERE customer = deleted_id;
+    ELSIF EXISTS (SELECT 1 FROM vendors WHERE id = deleted_id) THEN
+        DELETE FROM products
+        WHERE vendor = deleted_id;
+    END IF;
+
+    DELETE FROM comments
+    WHERE user_id = deleted_id;
+
+    UPDATE sessions
+    SET revoked_at = CURRENT_TIMESTAMP
+    WHERE user_id = deleted_id AND revoked_at IS NULL;
+END;
+$$;
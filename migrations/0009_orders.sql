@@ -0,0 +1,166 @@
+CREATE TABLE orders (
+    id INT GENERATED ALWAYS AS IDENTITY PRIMARY KEY,
+    -- It's realistic to require a complete log of purchases, so customers are only "soft deleted"
+    -- by having their `deleted` column set to true.
+    customer INT NOT NULL REFERENCES customers(id) ON DELETE RESTRICT,
+    -- Restricting product deletion would practically require also restricting product
+    -- modification, as changing the name, price, etc. of a product invalidates order logs as much
+    -- as deleting it. The important part, that being the user and the price, is still kept. If
+    -- proper audit logging is important, the product table needs a redesign.
+    product INT REFERENCES products(id) ON DELETE SET NULL,
+    placed_at NONFUTURE_TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    -- TODO: Add constraints on updates following a stricter state machine.
+    status ORDER_STATUS NOT NULL,
+    -- A more strict history could be maintained. For example, it might be desirable to store a
+    -- copy of the deal from the special offers used (or at least the discount), and basic
+    -- information in the case that the product is deleted.
+    number POSITIVE_INT NOT NULL,
+    paid TWOPOINT_UDEC NOT NULL
+);
+
+CREATE INDEX orders_per_customer_by_time ON orders (customer, placed_at DESC);
+CREATE INDEX orders_by_customer_product ON orders (customer, product);
+
+CREATE TYPE CHECKOUT_ITEM AS (
+    product INT,
+    number POSITIVE_INT,
+    special_offer INT,
+    expected_price TWOPOINT_UDEC
+);
+CREATE PROCEDURE checkout(
+    customer_id customers.id%TYPE,
+    -- These are NOT necessarily connected to the contents of the customer's rows in,
+    -- `shopping_cart_items`, though the numbers of those rows are decremented on success.
+    items CHECKOUT_ITEM[],
+    seen_at NONFUTURE_TIMESTAMP
+)
+LANGUAGE plpgsql AS $$
+BEGIN
+    IF seen_at IS NULL THEN
+        RAISE EXCEPTION 'Must include time cart was seen.';
+    END IF;
+
+    CREATE TEMP TABLE cart (
+        product INT PRIMARY KEY,
+        number POSITIVE_INT NOT NULL,
+        special_offer INT,
+        expected_price TWOPOINT_UDEC NOT NULL
+    ) ON COMMIT DROP;
+    INSERT INTO cart
+    SELECT *
+    FROM UNNEST(items);
+
+    -- We allow concurrent updates to membership status as it is only read once.
+    PERFORM 1
+    FROM customers
+    WHERE id = customer_id
+    FOR KEY SHARE;
+    IF NOT FOUND THEN
+        RAISE EXCEPTION 'Customer % does not exist.', customer_id;
+    END IF;
+
+    IF (SELECT COUNT(*) FROM cart) = 0 THEN
+        RAISE INFO 'Checkout with no items for customer %.', customer_id;
+        RETURN;
+    ELSIF EXISTS (
+        SELECT 1
+        FROM cart
+        JOIN products ON id = product
+        WHERE NOT visible
+    ) THEN
+        RAISE EXCEPTION 'Cart of customer % contains invisible products.', customer_id;
+    END IF;
+
+    PERFORM 1
+    FROM products p
+    JOIN cart ON id = product
+    FOR SHARE OF p;
+
+    PERFORM 1
+    FROM special_offers s
+    JOIN cart ON id = special_offer
+    FOR KEY SHARE OF s;
+    
+    IF EXISTS (
+        SELECT 1
+        FROM cart
+        JOIN products ON id = product
+        WHERE updated_at > seen_at
+    ) THEN
+        RAISE EXCEPTION 'Stale data: Product has changed.';
+    ELSIF EXISTS (
+        SELECT 1
+        FROM cart
+        LEFT JOIN active_special_offers aso ON aso.id = special_offer
+        WHERE special_offer IS NOT NULL AND aso.updated_at IS NULL OR aso.updated_at > seen_at
+    ) THEN
+        RAISE EXCEPTION 'Stale data: Special offer has expired.';
+    ELSIF EXISTS (
+        SELECT 1
+        FROM cart
+        JOIN active_special_offers aso ON aso.id = special_offer
+        JOIN customers c ON c.id = customer_id
+        WHERE members_only AND NOT member OR aso.updated_at > seen_at
+    ) THEN
+        RAISE EXCEPTION 'Stale data: Customer (%) is not eligible.', customer_id;
+    END IF;
+
+    -- Insert zeros to prevent other calls from double-counting, and do dummy update on existing
+    -- rows to lock them.
+    INSERT INTO special_offer_uses (special_offer, customer, number)
+    SELECT special_offer, customer_id, 0
+    FROM cart
+    WHERE special_offer IS NOT NULL
+    ON CONFLICT (special_offer, customer) DO UPDATE
+    SET number = special_offer_uses.number;
+
+    CREATE TEMP TABLE results
+    ON COMMIT DROP AS
+    SELECT cart.*, calc.price, calc.uses
+    FROM cart
+    JOIN products p ON p.id = product
+    LEFT JOIN active_special_offers aso ON aso.id = special_offer
+    LEFT JOIN special_offer_uses sou ON sou.special_offer = cart.special_offer AND customer = customer_id
+    CROSS JOIN LATERAL calculate_price(
+        price, cart.number, new_price, quantity1, quantity2,
+        CASE
+            -- Unlimited: the offer can at most be used once per unit.
+            WHEN limit_per_customer IS NULL THEN cart.number
+            ELSE GREATEST(limit_per_customer - COALESCE(sou.number, 0), 0)
+        END
+    ) AS calc;
+
+    IF EXISTS (
+        SELECT 1
+        FROM results
+        WHERE price != expected_price
+    ) THEN
+        RAISE EXCEPTION 'Stale data: Special offer has been used enough times to create a price discrepancy.';
+    END IF;
+
+    -- Fails if product runs out of stock.
+    UPDATE products
+    SET in_stock = in_stock - number
+    FROM cart
+    WHERE id = product;
+
+    PERFORM sale_remove_expiries(product, number)
+    FROM cart;
+
+    UPDATE shopping_cart_items
+    SET number = GREATEST(shopping_cart_items.number - r.number, 0)
+    FROM results r
+    WHERE shopping_cart_items.product = r.product AND customer = customer_id;
+    DELETE FROM shopping_cart_items
+    WHERE customer = customer_id AND number = 0;
+
+    UPDATE special_offer_uses
+    SET number = special_offer_uses.number + r.uses
+    FROM results r
+    WHERE r.special_offer = special_offer_uses.special_offer AND customer = customer_id AND uses > 0;
+
+    INSERT INTO orders (customer, product, status, number, paid)
+    SELECT customer_id, product, 'pending', number, price
+    FROM results;
+END;
+$$;
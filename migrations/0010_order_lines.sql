@@ -0,0 +1,238 @@
+-- Orders used to be stored as one row per product, with the rows of an order only connected by
+-- sharing a timestamp. They are now split into a header per checkout and one line per product.
+ALTER TABLE orders RENAME TO legacy_orders;
+DROP INDEX orders_per_customer_by_time;
+DROP INDEX orders_by_customer_product;
+
+CREATE TABLE orders (
+    id INT GENERATED ALWAYS AS IDENTITY PRIMARY KEY,
+    -- It's realistic to require a complete log of purchases, so customers are only "soft deleted"
+    -- by having their `deleted` column set to true.
+    customer INT NOT NULL REFERENCES customers(id) ON DELETE RESTRICT,
+    placed_at NONFUTURE_TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    -- TODO: Add constraints on updates following a stricter state machine.
+    status ORDER_STATUS NOT NULL DEFAULT 'pending',
+    -- The sum of `paid` over the lines of the order.
+    total TWOPOINT_UDEC NOT NULL
+);
+
+CREATE INDEX orders_per_customer_by_time ON orders (customer, placed_at DESC);
+
+CREATE TABLE order_lines (
+    order_id INT NOT NULL REFERENCES orders(id) ON DELETE CASCADE,
+    -- Restricting product deletion would practically require also restricting product
+    -- modification, as changing the name, price, etc. of a product invalidates order logs as much
+    -- as deleting it. The price and deal are instead copied into the line below.
+    product INT REFERENCES products(id) ON DELETE SET NULL,
+    number POSITIVE_INT NOT NULL,
+    -- The price of a single unit at the time of purchase, before any discount.
+    unit_price TWOPOINT_UDEC NOT NULL,
+    -- The special offer applied, if it was used at least once. Its deal is copied as it was at the
+    -- time of purchase, in the same representation as in `special_offers`.
+    special_offer INT REFERENCES special_offers(id) ON DELETE SET NULL,
+    new_price TWOPOINT_UDEC,
+    quantity1 INT CHECK (quantity1 IS NULL OR quantity1 > 1),
+    quantity2 INT CHECK (quantity2 IS NULL OR quantity2 >= 1),
+    special_offer_uses UINT NOT NULL DEFAULT 0,
+    paid TWOPOINT_UDEC NOT NULL,
+    CONSTRAINT one_line_per_product UNIQUE (order_id, product),
+    CONSTRAINT deal_iff_used CHECK (
+        (special_offer_uses > 0) = (new_price IS NOT NULL OR quantity1 IS NOT NULL)
+    )
+);
+
+CREATE INDEX order_lines_by_product ON order_lines (product);
+
+-- Rows placed by the same customer at the same time are assumed to belong to the same order. Their
+-- deals were never recorded, so the average price paid per unit is used as the unit price.
+INSERT INTO orders (customer, placed_at, status, total)
+SELECT customer, placed_at, MIN(status), SUM(paid)
+FROM legacy_orders
+GROUP BY customer, placed_at;
+
+INSERT INTO order_lines (order_id, product, number, unit_price, paid)
+SELECT o.id, l.product, SUM(l.number), ROUND(SUM(l.paid) / SUM(l.number), 2), SUM(l.paid)
+FROM legacy_orders l
+JOIN orders o ON o.customer = l.customer AND o.placed_at = l.placed_at
+-- Lines of deleted products can't be told apart, so they are kept separate.
+GROUP BY o.id, l.product, CASE WHEN l.product IS NULL THEN l.id END;
+
+DROP TABLE legacy_orders;
+
+CREATE OR REPLACE FUNCTION rater_has_purchase() RETURNS TRIGGER
+LANGUAGE plpgsql STABLE AS $$
+BEGIN
+    IF NOT EXISTS (
+        SELECT 1
+        FROM orders o
+        JOIN order_lines l ON l.order_id = o.id
+        WHERE customer = NEW.customer AND product = NEW.product
+    ) THEN
+        RAISE EXCEPTION 'Customer (%) must have previously bought the product to rate it.', NEW.customer;
+    END IF;
+
+    RETURN NEW;
+END;
+$$;
+
+-- Replaced by a function returning the ID of the placed order.
+DROP PROCEDURE checkout;
+CREATE FUNCTION checkout(
+    customer_id customers.id%TYPE,
+    -- These are NOT necessarily connected to the contents of the customer's rows in,
+    -- `shopping_cart_items`, though the numbers of those rows are decremented on success.
+    items CHECKOUT_ITEM[],
+    seen_at NONFUTURE_TIMESTAMP
+) RETURNS orders.id%TYPE
+LANGUAGE plpgsql AS $$
+DECLARE
+    new_order orders.id%TYPE;
+BEGIN
+    IF seen_at IS NULL THEN
+        RAISE EXCEPTION 'Must include time cart was seen.';
+    END IF;
+
+    CREATE TEMP TABLE cart (
+        product INT PRIMARY KEY,
+        number POSITIVE_INT NOT NULL,
+        special_offer INT,
+        expected_price TWOPOINT_UDEC NOT NULL
+    ) ON COMMIT DROP;
+    INSERT INTO cart
+    SELECT *
+    FROM UNNEST(items);
+
+    -- We allow concurrent updates to membership status as it is only read once.
+    PERFORM 1
+    FROM customers
+    WHERE id = customer_id
+    FOR KEY SHARE;
+    IF NOT FOUND THEN
+        RAISE EXCEPTION 'Customer % does not exist.', customer_id;
+    END IF;
+
+    IF (SELECT COUNT(*) FROM cart) = 0 THEN
+        RAISE EXCEPTION 'Checkout with no items for customer %.', customer_id;
+    ELSIF EXISTS (
+        SELECT 1
+        FROM cart
+        JOIN products ON id = product
+        WHERE NOT visible
+    ) THEN
+        RAISE EXCEPTION 'Cart of customer % contains invisible products.', customer_id;
+    END IF;
+
+    -- The stock is decremented below, so the lock is taken up front and in a consistent order to
+    -- avoid deadlocks between concurrent checkouts of the same products.
+    PERFORM 1
+    FROM products p
+    JOIN cart ON id = product
+    ORDER BY id
+    FOR NO KEY UPDATE OF p;
+
+    PERFORM 1
+    FROM special_offers s
+    JOIN cart ON id = special_offer
+    FOR KEY SHARE OF s;
+
+    IF EXISTS (
+        SELECT 1
+        FROM cart
+        JOIN products ON id = product
+        WHERE updated_at > seen_at
+    ) THEN
+        RAISE EXCEPTION 'Stale data: Product has changed.';
+    ELSIF EXISTS (
+        SELECT 1
+        FROM cart
+        LEFT JOIN active_special_offers aso ON aso.id = special_offer
+        WHERE special_offer IS NOT NULL AND aso.updated_at IS NULL OR aso.updated_at > seen_at
+    ) THEN
+        RAISE EXCEPTION 'Stale data: Special offer has expired.';
+    ELSIF EXISTS (
+        SELECT 1
+        FROM cart
+        JOIN active_special_offers aso ON aso.id = special_offer
+        JOIN customers c ON c.id = customer_id
+        WHERE members_only AND NOT member OR aso.updated_at > seen_at
+    ) THEN
+        RAISE EXCEPTION 'Stale data: Customer (%) is not eligible.', customer_id;
+    END IF;
+
+    -- Insert zeros to prevent other calls from double-counting, and do dummy update on existing
+    -- rows to lock them.
+    INSERT INTO special_offer_uses (special_offer, customer, number)
+    SELECT special_offer, customer_id, 0
+    FROM cart
+    WHERE special_offer IS NOT NULL
+    ON CONFLICT (special_offer, customer) DO UPDATE
+    SET number = special_offer_uses.number;
+
+    CREATE TEMP TABLE results
+    ON COMMIT DROP AS
+    SELECT
+        cart.*, p.price AS unit_price, aso.new_price, aso.quantity1, aso.quantity2, calc.price,
+        calc.uses
+    FROM cart
+    JOIN products p ON p.id = product
+    LEFT JOIN active_special_offers aso ON aso.id = special_offer
+    LEFT JOIN special_offer_uses sou ON sou.special_offer = cart.special_offer AND customer = customer_id
+    CROSS JOIN LATERAL calculate_price(
+        price, cart.number, new_price, quantity1, quantity2,
+        CASE
+            -- Unlimited: the offer can at most be used once per unit.
+            WHEN limit_per_customer IS NULL THEN cart.number
+            ELSE GREATEST(limit_per_customer - COALESCE(sou.number, 0), 0)
+        END
+    ) AS calc;
+
+    IF EXISTS (
+        SELECT 1
+        FROM results
+        WHERE price != expected_price
+    ) THEN
+        RAISE EXCEPTION 'Stale data: Special offer has been used enough times to create a price discrepancy.';
+    END IF;
+
+    -- Fails if product runs out of stock.
+    UPDATE products
+    SET in_stock = in_stock - number
+    FROM cart
+    WHERE id = product;
+
+    PERFORM sale_remove_expiries(product, number)
+    FROM cart;
+
+    UPDATE shopping_cart_items
+    SET number = GREATEST(shopping_cart_items.number - r.number, 0)
+    FROM results r
+    WHERE shopping_cart_items.product = r.product AND customer = customer_id;
+    DELETE FROM shopping_cart_items
+    WHERE customer = customer_id AND number = 0;
+
+    UPDATE special_offer_uses
+    SET number = special_offer_uses.number + r.uses
+    FROM results r
+    WHERE r.special_offer = special_offer_uses.special_offer AND customer = customer_id AND uses > 0;
+
+    INSERT INTO orders (customer, total)
+    SELECT customer_id, SUM(price)
+    FROM results
+    RETURNING id INTO new_order;
+
+    INSERT INTO order_lines (
+        order_id, product, number, unit_price, special_offer, new_price, quantity1, quantity2,
+        special_offer_uses, paid
+    )
+    SELECT
+        new_order, product, number, unit_price,
+        CASE WHEN uses > 0 THEN special_offer END,
+        CASE WHEN uses > 0 THEN new_price END,
+        CASE WHEN uses > 0 THEN quantity1 END,
+        CASE WHEN uses > 0 THEN quantity2 END,
+        uses, price
+    FROM results;
+
+    RETURN new_order;
+END;
+$$;
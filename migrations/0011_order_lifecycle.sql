@@ -0,0 +1,108 @@
+-- New values can't be used in the same transaction as they are added, which is fine as they are
+-- only referenced from function bodies below.
+ALTER TYPE ORDER_STATUS ADD VALUE 'cancelled';
+ALTER TYPE ORDER_STATUS ADD VALUE 'return_requested';
+ALTER TYPE ORDER_STATUS ADD VALUE 'returned';
+ALTER TYPE ORDER_STATUS ADD VALUE 'refunded';
+
+CREATE TABLE order_status_changes (
+    id INT GENERATED ALWAYS AS IDENTITY PRIMARY KEY,
+    order_id INT NOT NULL REFERENCES orders(id) ON DELETE CASCADE,
+    status ORDER_STATUS NOT NULL,
+    changed_at NONFUTURE_TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX order_status_changes_by_order ON order_status_changes (order_id, id);
+
+-- When existing orders left the pending status was never recorded, so the time of the migration is
+-- used instead.
+INSERT INTO order_status_changes (order_id, status, changed_at)
+SELECT id, 'pending', placed_at
+FROM orders;
+INSERT INTO order_status_changes (order_id, status)
+SELECT id, status
+FROM orders
+WHERE status != 'pending';
+
+-- Which party may perform a transition is decided by the application. This only guarantees that the
+-- history of an order makes sense.
+CREATE FUNCTION valid_order_transition(
+    old_status ORDER_STATUS,
+    new_status ORDER_STATUS
+) RETURNS BOOLEAN
+LANGUAGE plpgsql IMMUTABLE PARALLEL SAFE AS $$
+BEGIN
+    RETURN CASE old_status
+        WHEN 'pending' THEN new_status IN ('shipped', 'cancelled')
+        WHEN 'shipped' THEN new_status = 'received'
+        WHEN 'received' THEN new_status = 'return_requested'
+        -- Going back to received means the return was rejected.
+        WHEN 'return_requested' THEN new_status IN ('returned', 'received')
+        WHEN 'cancelled' THEN new_status = 'refunded'
+        WHEN 'returned' THEN new_status = 'refunded'
+        ELSE FALSE
+    END;
+END;
+$$;
+
+CREATE FUNCTION validate_order_transition() RETURNS TRIGGER
+LANGUAGE plpgsql STABLE AS $$
+BEGIN
+    IF TG_OP = 'INSERT' AND NEW.status != 'pending' THEN
+        RAISE EXCEPTION 'Orders must be placed as pending.';
+    ELSIF TG_OP = 'UPDATE' AND NEW.status != OLD.status
+        AND NOT valid_order_transition(OLD.status, NEW.status)
+    THEN
+        RAISE EXCEPTION 'Order % can not go from % to %.', NEW.id, OLD.status, NEW.status;
+    END IF;
+
+    RETURN NEW;
+END;
+$$;
+
+CREATE TRIGGER validate_order_transition
+BEFORE INSERT OR UPDATE OF status ON orders
+FOR EACH ROW EXECUTE FUNCTION validate_order_transition();
+
+CREATE FUNCTION record_order_transition() RETURNS TRIGGER
+LANGUAGE plpgsql AS $$
+BEGIN
+    INSERT INTO order_status_changes (order_id, status)
+    VALUES (NEW.id, NEW.status);
+
+    -- The goods are back with the vendor, so they can be sold again and the customer may make use of
+    -- any special offers again.
+    IF NEW.status IN ('cancelled', 'returned') THEN
+        -- Consistent lock order with checkout.
+        PERFORM 1
+        FROM products p
+        JOIN order_lines l ON l.product = p.id
+        WHERE l.order_id = NEW.id
+        ORDER BY p.id
+        FOR NO KEY UPDATE OF p;
+
+        UPDATE products p
+        SET in_stock = in_stock + l.number
+        FROM order_lines l
+        WHERE l.order_id = NEW.id AND l.product = p.id;
+
+        UPDATE special_offer_uses sou
+        SET number = GREATEST(sou.number - l.special_offer_uses, 0)
+        FROM order_lines l
+        WHERE l.order_id = NEW.id AND sou.special_offer = l.special_offer
+            AND sou.customer = NEW.customer;
+    END IF;
+
+    RETURN NULL;
+END;
+$$;
+
+CREATE TRIGGER record_order_placement
+AFTER INSERT ON orders
+FOR EACH ROW EXECUTE FUNCTION record_order_transition();
+
+CREATE TRIGGER record_order_transition
+AFTER UPDATE OF status ON orders
+FOR EACH ROW
+WHEN (OLD.status IS DISTINCT FROM NEW.status)
+EXECUTE FUNCTION record_order_transition();
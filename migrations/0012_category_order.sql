@@ -0,0 +1,84 @@
+-- Siblings are sorted by position, then by name for categories sharing a position.
+ALTER TABLE categories ADD COLUMN position INT NOT NULL DEFAULT 0;
+
+DROP INDEX categories_by_parent_name;
+CREATE INDEX categories_by_parent_position ON categories (parent NULLS FIRST, position, name);
+
+-- Existing categories keep their alphabetical order.
+UPDATE categories c
+SET position = o.position
+FROM (
+    SELECT id, ROW_NUMBER() OVER (PARTITION BY parent ORDER BY name) - 1 AS position
+    FROM categories
+) o
+WHERE c.id = o.id;
+
+-- The constraint name lets the server tell cycles apart from other errors.
+CREATE OR REPLACE FUNCTION categories_validate_tree() RETURNS TRIGGER
+LANGUAGE plpgsql STABLE AS $$
+DECLARE
+    visited INT[] := ARRAY[NEW.id];
+    current_id INT := NEW.parent;
+    current_parent INT;
+BEGIN
+    WHILE current_id IS NOT NULL LOOP
+        IF current_id = ANY(visited) THEN
+            RAISE EXCEPTION 'Cycle detected in path of category %.', NEW.id
+            USING ERRCODE = 'check_violation', CONSTRAINT = 'categories_valid_tree';
+        END IF;
+        visited := visited || current_id;
+
+        SELECT parent
+        INTO STRICT current_parent
+        FROM categories
+        WHERE id = current_id;
+        current_id := current_parent;
+    END LOOP;
+
+    RETURN NEW;
+END;
+$$;
+
+-- The position after the last child of a category, or after the last root category if `parent_id`
+-- is null.
+CREATE FUNCTION next_category_position(parent_id categories.parent%TYPE) RETURNS INT
+LANGUAGE sql STABLE PARALLEL SAFE AS $$
+    SELECT COALESCE(MAX(position) + 1, 0)
+    FROM categories
+    WHERE parent IS NOT DISTINCT FROM parent_id;
+$$;
+
+-- `ordered` must contain every child of the category exactly once, or every root category if
+-- `parent_id` is null.
+CREATE PROCEDURE reorder_categories(parent_id categories.parent%TYPE, ordered INT[])
+LANGUAGE plpgsql AS $$
+BEGIN
+    IF ARRAY(SELECT UNNEST(ordered) ORDER BY 1) IS DISTINCT FROM ARRAY(
+        SELECT id
+        FROM categories
+        WHERE parent IS NOT DISTINCT FROM parent_id
+        ORDER BY id
+    ) THEN
+        RAISE EXCEPTION 'Order does not contain exactly the children of category %.', parent_id;
+    END IF;
+
+    UPDATE categories c
+    SET position = o.position - 1
+    FROM UNNEST(ordered) WITH ORDINALITY AS o(id, position)
+    WHERE c.id = o.id;
+END;
+$$;
+
+-- All categories in the subtree rooted at a category, including the category itself.
+CREATE FUNCTION category_subtree(root_id categories.id%TYPE) RETURNS SETOF INT
+LANGUAGE sql STABLE STRICT PARALLEL SAFE AS $$
+    WITH RECURSIVE subtree AS (
+        SELECT root_id AS id
+        UNION ALL
+        SELECT c.id
+        FROM categories c
+        JOIN subtree s ON c.parent = s.id
+    )
+    SELECT id
+    FROM subtree;
+$$;
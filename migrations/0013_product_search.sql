@@ -0,0 +1,65 @@
+CREATE TYPE SEARCH_ORDER AS ENUM (
+    'relevance',
+    'price_ascending',
+    'price_descending',
+    'unit_price',
+    'newest',
+    'rating',
+    'best_discount'
+);
+
+-- Visible products matching a search, along with which of the filters each product passes. Filters
+-- are reported rather than applied so that facet counts can ignore their own filter. Null filters
+-- pass every product, as does an empty query.
+CREATE FUNCTION product_search(
+    search_text TEXT,
+    category_id categories.id%TYPE,
+    vendor_id vendors.id%TYPE,
+    price_min DECIMAL,
+    price_max DECIMAL,
+    origin_name products.origin%TYPE,
+    in_stock_only BOOLEAN,
+    with_offer BOOLEAN,
+    offer_members_only BOOLEAN,
+    rating_min INT
+) RETURNS TABLE (
+    product INT,
+    relevance REAL,
+    average_rating FLOAT,
+    rating_count BIGINT,
+    in_category BOOLEAN,
+    by_vendor BOOLEAN,
+    in_price_range BOOLEAN,
+    from_origin BOOLEAN,
+    stocked BOOLEAN,
+    offered BOOLEAN,
+    rated BOOLEAN
+)
+LANGUAGE sql STABLE PARALLEL SAFE AS $$
+    WITH search AS (
+        SELECT plainto_tsquery('english', search_text) AS query
+    ),
+    product_ratings AS (
+        SELECT r.product, AVG(r.rating::FLOAT) AS average, COUNT(*) AS count
+        FROM ratings r
+        GROUP BY r.product
+    )
+    SELECT p.id,
+        ts_rank(p.search_vector, s.query),
+        pr.average,
+        COALESCE(pr.count, 0),
+        category_id IS NULL OR p.category IN (SELECT category_subtree(category_id)),
+        vendor_id IS NULL OR p.vendor = vendor_id,
+        (price_min IS NULL OR p.price >= price_min) AND (price_max IS NULL OR p.price <= price_max),
+        origin_name IS NULL OR p.origin = origin_name,
+        NOT in_stock_only OR p.in_stock > 0,
+        NOT with_offer
+            OR aso.id IS NOT NULL
+            AND (offer_members_only IS NULL OR aso.members_only = offer_members_only),
+        rating_min IS NULL OR COALESCE(pr.average >= rating_min, FALSE)
+    FROM products p
+    CROSS JOIN search s
+    LEFT JOIN active_special_offers aso ON aso.product = p.id
+    LEFT JOIN product_ratings pr ON pr.product = p.id
+    WHERE p.visible AND (numnode(s.query) = 0 OR p.search_vector @@ s.query);
+$$;
@@ -0,0 +1,139 @@
+CREATE EXTENSION IF NOT EXISTS pg_trgm;
+CREATE EXTENSION IF NOT EXISTS unaccent;
+
+-- Each value is the name of a text search configuration.
+CREATE TYPE SEARCH_LANGUAGE AS ENUM ('swedish', 'english');
+
+ALTER TABLE products ADD COLUMN search_language SEARCH_LANGUAGE NOT NULL DEFAULT 'swedish';
+
+-- NOTE: `unaccent` is only stable as its dictionary may be changed, which is never done here. This
+-- wrapper allows it to be used in indexes.
+CREATE FUNCTION unaccented(string TEXT) RETURNS TEXT
+LANGUAGE sql IMMUTABLE STRICT PARALLEL SAFE AS $$
+    SELECT public.unaccent('public.unaccent'::REGDICTIONARY, string);
+$$;
+
+CREATE INDEX products_by_unaccented_name ON products USING GIN (unaccented(name) gin_trgm_ops);
+
+DROP FUNCTION products_search_vector(TEXT, TEXT, TEXT, TEXT);
+
+CREATE FUNCTION products_search_vector(
+    name products.name%TYPE,
+    overview products.overview%TYPE,
+    description products.description%TYPE,
+    category_name categories.name%TYPE,
+    language SEARCH_LANGUAGE
+) RETURNS TSVECTOR
+LANGUAGE sql IMMUTABLE STRICT PARALLEL SAFE AS $$
+    SELECT setweight(to_tsvector(language::TEXT::REGCONFIG, name), 'A')
+        || setweight(to_tsvector(language::TEXT::REGCONFIG, overview), 'D')
+        || setweight(to_tsvector(language::TEXT::REGCONFIG, description), 'D')
+        || setweight(to_tsvector(language::TEXT::REGCONFIG, category_name), 'B');
+$$;
+
+CREATE OR REPLACE FUNCTION products_set_search_vector() RETURNS TRIGGER
+LANGUAGE plpgsql AS $$
+BEGIN
+    NEW.search_vector := products_search_vector(
+        NEW.name,
+        NEW.overview,
+        NEW.description,
+        (SELECT name FROM categories WHERE id = NEW.category),
+        NEW.search_language
+    );
+    RETURN NEW;
+END;
+$$;
+
+DROP TRIGGER products_update_search_vector ON products;
+CREATE TRIGGER products_update_search_vector
+BEFORE INSERT OR UPDATE OF name, overview, description, category, search_language ON products
+FOR EACH ROW EXECUTE FUNCTION products_set_search_vector();
+
+CREATE OR REPLACE FUNCTION categories_set_products_search_vector() RETURNS TRIGGER
+LANGUAGE plpgsql AS $$
+BEGIN
+    UPDATE products
+    SET search_vector = products_search_vector(
+        name,
+        overview,
+        description,
+        NEW.name,
+        search_language
+    )
+    WHERE category = NEW.id;
+
+    RETURN NULL;
+END;
+$$;
+
+UPDATE products p
+SET search_vector = products_search_vector(
+    p.name,
+    p.overview,
+    p.description,
+    c.name,
+    p.search_language
+)
+FROM categories c
+WHERE c.id = p.category;
+
+-- Queries are parsed in the language of each product. Names similar to the query also match, to
+-- tolerate typos and missing diacritics; relevance is the sum of both scores.
+CREATE OR REPLACE FUNCTION product_search(
+    search_text TEXT,
+    category_id categories.id%TYPE,
+    vendor_id vendors.id%TYPE,
+    price_min DECIMAL,
+    price_max DECIMAL,
+    origin_name products.origin%TYPE,
+    in_stock_only BOOLEAN,
+    with_offer BOOLEAN,
+    offer_members_only BOOLEAN,
+    rating_min INT
+) RETURNS TABLE (
+    product INT,
+    relevance REAL,
+    average_rating FLOAT,
+    rating_count BIGINT,
+    in_category BOOLEAN,
+    by_vendor BOOLEAN,
+    in_price_range BOOLEAN,
+    from_origin BOOLEAN,
+    stocked BOOLEAN,
+    offered BOOLEAN,
+    rated BOOLEAN
+)
+LANGUAGE sql STABLE PARALLEL SAFE AS $$
+    WITH product_ratings AS (
+        SELECT r.product, AVG(r.rating::FLOAT) AS average, COUNT(*) AS count
+        FROM ratings r
+        GROUP BY r.product
+    )
+    SELECT p.id,
+        ts_rank(p.search_vector, s.query) + word_similarity(s.unaccented, unaccented(p.name)),
+        pr.average,
+        COALESCE(pr.count, 0),
+        category_id IS NULL OR p.category IN (SELECT category_subtree(category_id)),
+        vendor_id IS NULL OR p.vendor = vendor_id,
+        (price_min IS NULL OR p.price >= price_min) AND (price_max IS NULL OR p.price <= price_max),
+        origin_name IS NULL OR p.origin = origin_name,
+        NOT in_stock_only OR p.in_stock > 0,
+        NOT with_offer
+            OR aso.id IS NOT NULL
+            AND (offer_members_only IS NULL OR aso.members_only = offer_members_only),
+        rating_min IS NULL OR COALESCE(pr.average >= rating_min, FALSE)
+    FROM products p
+    CROSS JOIN LATERAL (
+        SELECT plainto_tsquery(p.search_language::TEXT::REGCONFIG, search_text) AS query,
+            unaccented(search_text) AS unaccented
+    ) s
+    LEFT JOIN active_special_offers aso ON aso.product = p.id
+    LEFT JOIN product_ratings pr ON pr.product = p.id
+    WHERE p.visible
+        AND (
+            btrim(search_text) = ''
+            OR p.search_vector @@ s.query
+            OR s.unaccented <% unaccented(p.name)
+        );
+$$;
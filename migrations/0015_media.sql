@@ -0,0 +1,15 @@
+CREATE TYPE MEDIA_TYPE AS ENUM ('jpeg', 'png', 'webp');
+
+-- Metadata of uploaded images. The files themselves, along with their thumbnails, are kept in media
+-- storage outside the database, keyed by ID.
+CREATE TABLE media (
+    id INT GENERATED ALWAYS AS IDENTITY PRIMARY KEY,
+    uploader INT REFERENCES users(id) ON DELETE SET NULL,
+    media_type MEDIA_TYPE NOT NULL,
+    width POSITIVE_INT NOT NULL,
+    height POSITIVE_INT NOT NULL,
+    size POSITIVE_INT NOT NULL,
+    created_at NONFUTURE_TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX media_by_uploader ON media (uploader);
@@ -0,0 +1,38 @@
+ALTER TABLE users ADD COLUMN email_verified_at NONFUTURE_TIMESTAMP DEFAULT NULL;
+
+-- Users registered before verification was introduced are trusted.
+UPDATE users
+SET email_verified_at = created_at;
+
+-- A verification only applies to the address that was verified.
+CREATE FUNCTION reset_email_verification() RETURNS TRIGGER
+LANGUAGE plpgsql AS $$
+BEGIN
+    IF NEW.email IS DISTINCT FROM OLD.email THEN
+        NEW.email_verified_at := NULL;
+    END IF;
+    RETURN NEW;
+END;
+$$;
+
+CREATE TRIGGER users_reset_email_verification
+BEFORE UPDATE OF email ON users
+FOR EACH ROW EXECUTE FUNCTION reset_email_verification();
+
+CREATE TYPE ACCOUNT_TOKEN_PURPOSE AS ENUM ('verify_email', 'reset_password');
+
+-- Single-use tokens sent to users by email. As with sessions, only hashes are stored. Each token is
+-- bound to the address it was sent to, so that it stops working if the user's email changes.
+CREATE TABLE account_tokens (
+    id INT GENERATED ALWAYS AS IDENTITY PRIMARY KEY,
+    token_hash BYTEA UNIQUE NOT NULL,
+    user_id INT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    purpose ACCOUNT_TOKEN_PURPOSE NOT NULL,
+    email EMAIL NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    expires_at TIMESTAMP NOT NULL,
+    used_at TIMESTAMP DEFAULT NULL,
+    CHECK (expires_at > created_at)
+);
+
+CREATE INDEX account_tokens_by_user ON account_tokens (user_id, purpose);
@@ -0,0 +1,13 @@
+-- When the user last proved their identity with their password in each session. Sensitive account
+-- changes require this to be recent, so that a stolen or forgotten session is not enough.
+ALTER TABLE sessions ADD COLUMN authenticated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP;
+
+UPDATE sessions
+SET authenticated_at = created_at;
+
+ALTER TABLE sessions ADD CHECK (authenticated_at >= created_at);
+
+CREATE OR REPLACE VIEW active_sessions AS
+SELECT *
+FROM sessions
+WHERE revoked_at IS NULL AND expires_at > CURRENT_TIMESTAMP;
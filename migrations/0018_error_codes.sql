@@ -0,0 +1,326 @@
+-- Errors that users can act on are raised with distinct SQLSTATE codes, which the server maps to
+-- typed errors:
+-- - `no_data_found` when a referenced row does not exist.
+-- - `insufficient_privilege` when the action is not permitted.
+-- - `check_violation` for invalid data, naming the violated rule as the constraint.
+-- - `BP001` when a cart has gone stale, naming the reason as the constraint.
+-- - `BP002` when a product is out of stock, with the product as the detail.
+
+CREATE OR REPLACE FUNCTION checkout(
+    customer_id customers.id%TYPE,
+    -- These are NOT necessarily connected to the contents of the customer's rows in,
+    -- `shopping_cart_items`, though the numbers of those rows are decremented on success.
+    items CHECKOUT_ITEM[],
+    seen_at NONFUTURE_TIMESTAMP
+) RETURNS orders.id%TYPE
+LANGUAGE plpgsql AS $$
+DECLARE
+    new_order orders.id%TYPE;
+    short_product products.id%TYPE;
+BEGIN
+    IF seen_at IS NULL THEN
+        RAISE EXCEPTION 'Must include time cart was seen.'
+        USING ERRCODE = 'null_value_not_allowed', COLUMN = 'seen_at';
+    END IF;
+
+    CREATE TEMP TABLE cart (
+        product INT PRIMARY KEY,
+        number POSITIVE_INT NOT NULL,
+        special_offer INT,
+        expected_price TWOPOINT_UDEC NOT NULL
+    ) ON COMMIT DROP;
+    INSERT INTO cart
+    SELECT *
+    FROM UNNEST(items);
+
+    -- We allow concurrent updates to membership status as it is only read once.
+    PERFORM 1
+    FROM customers
+    WHERE id = customer_id
+    FOR KEY SHARE;
+    IF NOT FOUND THEN
+        RAISE EXCEPTION 'Customer % does not exist.', customer_id
+        USING ERRCODE = 'no_data_found';
+    END IF;
+
+    IF (SELECT COUNT(*) FROM cart) = 0 THEN
+        RAISE EXCEPTION 'Checkout with no items for customer %.', customer_id
+        USING ERRCODE = 'check_violation', CONSTRAINT = 'nonempty_checkout';
+    ELSIF EXISTS (
+        SELECT 1
+        FROM cart
+        JOIN products ON id = product
+        WHERE NOT visible
+    ) THEN
+        RAISE EXCEPTION 'Cart of customer % contains invisible products.', customer_id
+        USING ERRCODE = 'BP001', CONSTRAINT = 'product_unavailable';
+    END IF;
+
+    -- The stock is decremented below, so the lock is taken up front and in a consistent order to
+    -- avoid deadlocks between concurrent checkouts of the same products.
+    PERFORM 1
+    FROM products p
+    JOIN cart ON id = product
+    ORDER BY id
+    FOR NO KEY UPDATE OF p;
+
+    PERFORM 1
+    FROM special_offers s
+    JOIN cart ON id = special_offer
+    FOR KEY SHARE OF s;
+
+    IF EXISTS (
+        SELECT 1
+        FROM cart
+        JOIN products ON id = product
+        WHERE updated_at > seen_at
+    ) THEN
+        RAISE EXCEPTION 'Stale data: Product has changed.'
+        USING ERRCODE = 'BP001', CONSTRAINT = 'product_changed';
+    ELSIF EXISTS (
+        SELECT 1
+        FROM cart
+        LEFT JOIN active_special_offers aso ON aso.id = special_offer
+        WHERE special_offer IS NOT NULL AND aso.updated_at IS NULL OR aso.updated_at > seen_at
+    ) THEN
+        RAISE EXCEPTION 'Stale data: Special offer has expired.'
+        USING ERRCODE = 'BP001', CONSTRAINT = 'offer_expired';
+    ELSIF EXISTS (
+        SELECT 1
+        FROM cart
+        JOIN active_special_offers aso ON aso.id = special_offer
+        JOIN customers c ON c.id = customer_id
+        WHERE members_only AND NOT member OR aso.updated_at > seen_at
+    ) THEN
+        RAISE EXCEPTION 'Stale data: Customer (%) is not eligible.', customer_id
+        USING ERRCODE = 'BP001', CONSTRAINT = 'not_eligible';
+    END IF;
+
+    -- Insert zeros to prevent other calls from double-counting, and do dummy update on existing
+    -- rows to lock them.
+    INSERT INTO special_offer_uses (special_offer, customer, number)
+    SELECT special_offer, customer_id, 0
+    FROM cart
+    WHERE special_offer IS NOT NULL
+    ON CONFLICT (special_offer, customer) DO UPDATE
+    SET number = special_offer_uses.number;
+
+    CREATE TEMP TABLE results
+    ON COMMIT DROP AS
+    SELECT
+        cart.*, p.price AS unit_price, aso.new_price, aso.quantity1, aso.quantity2, calc.price,
+        calc.uses
+    FROM cart
+    JOIN products p ON p.id = product
+    LEFT JOIN active_special_offers aso ON aso.id = special_offer
+    LEFT JOIN special_offer_uses sou ON sou.special_offer = cart.special_offer AND customer = customer_id
+    CROSS JOIN LATERAL calculate_price(
+        price, cart.number, new_price, quantity1, quantity2,
+        CASE
+            -- Unlimited: the offer can at most be used once per unit.
+            WHEN limit_per_customer IS NULL THEN cart.number
+            ELSE GREATEST(limit_per_customer - COALESCE(sou.number, 0), 0)
+        END
+    ) AS calc;
+
+    IF EXISTS (
+        SELECT 1
+        FROM results
+        WHERE price != expected_price
+    ) THEN
+        RAISE EXCEPTION 'Stale data: Special offer has been used enough times to create a price discrepancy.'
+        USING ERRCODE = 'BP001', CONSTRAINT = 'offer_used_up';
+    END IF;
+
+    SELECT id
+    INTO short_product
+    FROM cart
+    JOIN products ON id = product
+    WHERE in_stock < number
+    ORDER BY id
+    LIMIT 1;
+    IF FOUND THEN
+        RAISE EXCEPTION 'Product % does not have enough stock.', short_product
+        USING ERRCODE = 'BP002', DETAIL = short_product::TEXT;
+    END IF;
+
+    UPDATE products
+    SET in_stock = in_stock - number
+    FROM cart
+    WHERE id = product;
+
+    PERFORM sale_remove_expiries(product, number)
+    FROM cart;
+
+    UPDATE shopping_cart_items
+    SET number = GREATEST(shopping_cart_items.number - r.number, 0)
+    FROM results r
+    WHERE shopping_cart_items.product = r.product AND customer = customer_id;
+    DELETE FROM shopping_cart_items
+    WHERE customer = customer_id AND number = 0;
+
+    UPDATE special_offer_uses
+    SET number = special_offer_uses.number + r.uses
+    FROM results r
+    WHERE r.special_offer = special_offer_uses.special_offer AND customer = customer_id AND uses > 0;
+
+    INSERT INTO orders (customer, total)
+    SELECT customer_id, SUM(price)
+    FROM results
+    RETURNING id INTO new_order;
+
+    INSERT INTO order_lines (
+        order_id, product, number, unit_price, special_offer, new_price, quantity1, quantity2,
+        special_offer_uses, paid
+    )
+    SELECT
+        new_order, product, number, unit_price,
+        CASE WHEN uses > 0 THEN special_offer END,
+        CASE WHEN uses > 0 THEN new_price END,
+        CASE WHEN uses > 0 THEN quantity1 END,
+        CASE WHEN uses > 0 THEN quantity2 END,
+        uses, price
+    FROM results;
+
+    RETURN new_order;
+END;
+$$;
+
+
+CREATE OR REPLACE FUNCTION add_stock(
+    product_id INT,
+    added INT,
+    expires DATE = NULL
+) RETURNS INT
+LANGUAGE plpgsql AS $$
+DECLARE
+    new_stock INT;
+BEGIN
+    -- Consistent lock order with `checkout`.
+    PERFORM 1
+    FROM products
+    WHERE id = product_id
+    FOR KEY SHARE;
+    IF NOT FOUND THEN
+        RAISE EXCEPTION 'Product % does not exist.', product_id
+        USING ERRCODE = 'no_data_found';
+    END IF;
+
+    IF expires IS NOT NULL THEN
+        INSERT INTO expiries (product, expiry, number)
+        VALUES (product_id, expires, added)
+        ON CONFLICT (product, expiry) DO UPDATE
+        SET number = expiries.number + EXCLUDED.number;
+    END IF;
+
+    UPDATE products
+    SET in_stock = in_stock + added
+    WHERE id = product_id
+    RETURNING in_stock INTO STRICT new_stock;
+
+    RETURN new_stock;
+END;
+$$;
+
+CREATE OR REPLACE PROCEDURE delete_user(deleted_id users.id%TYPE)
+LANGUAGE plpgsql AS $$
+BEGIN
+    -- NOTE: Soft deletion. Possible corresponding row in role-specific table is also kept.
+    UPDATE users
+    SET deleted = true
+    WHERE id = deleted_id;
+    IF NOT FOUND THEN
+        RAISE EXCEPTION 'User % does not exist.', deleted_id
+        USING ERRCODE = 'no_data_found';
+    END IF;
+
+    -- PERF: Several of these queries are not supported by indices: we imagine account deletions
+    -- are rare.
+    IF EXISTS (SELECT 1 FROM customers WHERE id = deleted_id) THEN
+        DELETE FROM special_offer_uses
+        WHERE customer = deleted_id;
+
+        -- NOTE: Reviews must be deleted before ratings.
+        DELETE FROM reviews
+        WHERE customer = deleted_id;
+
+        DELETE FROM ratings
+        WHERE customer = deleted_id;
+
+        DELETE FROM review_votes
+        WHERE customer = deleted_id;
+
+        DELETE FROM comment_votes
+        WHERE customer = deleted_id;
+
+        DELETE FROM shopping_cart_items
+        WHERE customer = deleted_id;
+
+        DELETE FROM customer_favorites
+        WHERE customer = deleted_id;
+    ELSIF EXISTS (SELECT 1 FROM vendors WHERE id = deleted_id) THEN
+        DELETE FROM products
+        WHERE vendor = deleted_id;
+    END IF;
+
+    DELETE FROM comments
+    WHERE user_id = deleted_id;
+
+    UPDATE sessions
+    SET revoked_at = CURRENT_TIMESTAMP
+    WHERE user_id = deleted_id AND revoked_at IS NULL;
+END;
+$$;
+
+CREATE OR REPLACE FUNCTION rater_has_purchase() RETURNS TRIGGER
+LANGUAGE plpgsql STABLE AS $$
+BEGIN
+    IF NOT EXISTS (
+        SELECT 1
+        FROM orders o
+        JOIN order_lines l ON l.order_id = o.id
+        WHERE customer = NEW.customer AND product = NEW.product
+    ) THEN
+        RAISE EXCEPTION 'Customer (%) must have previously bought the product to rate it.', NEW.customer
+        USING ERRCODE = 'insufficient_privilege';
+    END IF;
+
+    RETURN NEW;
+END;
+$$;
+
+CREATE OR REPLACE FUNCTION reviewer_can_review() RETURNS TRIGGER
+LANGUAGE plpgsql STABLE AS $$
+BEGIN
+    IF NOT (SELECT can_review FROM customers WHERE id = NEW.customer) THEN
+        RAISE EXCEPTION 'Customer (%) must be able to place reviews.', NEW.customer
+        USING ERRCODE = 'insufficient_privilege';
+    END IF;
+
+    RETURN NEW;
+END;
+$$;
+
+CREATE OR REPLACE FUNCTION no_vote_on_own_review() RETURNS TRIGGER
+LANGUAGE plpgsql STABLE AS $$
+BEGIN
+    IF NEW.customer = (SELECT customer FROM reviews WHERE id = NEW.review) THEN
+        RAISE EXCEPTION 'Customer (%) can not vote on their own review.', NEW.customer
+        USING ERRCODE = 'insufficient_privilege';
+    END IF;
+
+    RETURN NEW;
+END;
+$$;
+
+CREATE OR REPLACE FUNCTION no_vote_on_own_comment() RETURNS TRIGGER
+LANGUAGE plpgsql STABLE AS $$
+BEGIN
+    IF NEW.customer = (SELECT user_id FROM comments WHERE id = NEW.comment) THEN
+        RAISE EXCEPTION 'Customer (%) can not vote on their own comment.', NEW.customer
+        USING ERRCODE = 'insufficient_privilege';
+    END IF;
+
+    RETURN NEW;
+END;
+$$;
@@ -0,0 +1,77 @@
+CREATE TYPE CART_MERGE_POLICY AS ENUM ('sum', 'max', 'prefer_latest');
+
+-- Carts of shoppers who are not logged in, identified by a token in a cookie. As with sessions, only
+-- hashes are stored.
+CREATE TABLE guest_carts (
+    id INT GENERATED ALWAYS AS IDENTITY PRIMARY KEY,
+    token_hash BYTEA UNIQUE NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    expires_at TIMESTAMP NOT NULL,
+    CHECK (expires_at > created_at)
+);
+
+CREATE VIEW active_guest_carts AS
+SELECT *
+FROM guest_carts
+WHERE expires_at > CURRENT_TIMESTAMP;
+
+-- Unlike customers' carts, guest carts do not keep placeholders for removed products, as guests have
+-- nothing to review before checking out.
+CREATE TABLE guest_cart_items (
+    cart INT NOT NULL REFERENCES guest_carts(id) ON DELETE CASCADE,
+    product INT NOT NULL REFERENCES products(id) ON DELETE CASCADE,
+    number UINT NOT NULL CHECK (number > 0),
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (cart, product)
+);
+
+CREATE TRIGGER guest_cart_update_time
+BEFORE UPDATE ON guest_cart_items
+FOR EACH ROW EXECUTE FUNCTION update_time();
+
+CREATE OR REPLACE FUNCTION remove_from_carts(product_id products.id%TYPE) RETURNS VOID
+LANGUAGE sql AS $$
+    DELETE FROM shopping_cart_items sci
+    WHERE sci.product = product_id AND EXISTS (
+        SELECT 1
+        FROM shopping_cart_items placeholder
+        WHERE placeholder.customer = sci.customer AND placeholder.product IS NULL
+    );
+
+    UPDATE shopping_cart_items
+    SET product = NULL
+    WHERE product = product_id;
+
+    DELETE FROM guest_cart_items
+    WHERE product = product_id;
+$$;
+
+-- Move the items of a guest cart to a customer's cart. Products in both carts get a number chosen
+-- by `policy`:
+-- - `sum`: The sum of both numbers.
+-- - `max`: The larger of both numbers.
+-- - `prefer_latest`: The number in the cart where the product was updated most recently.
+CREATE PROCEDURE merge_guest_cart(
+    customer_id customers.id%TYPE,
+    cart_token_hash guest_carts.token_hash%TYPE,
+    policy CART_MERGE_POLICY
+) LANGUAGE sql AS $$
+    INSERT INTO shopping_cart_items (customer, product, number, updated_at)
+    SELECT customer_id, product, number, gci.updated_at
+    FROM active_guest_carts gc
+    JOIN guest_cart_items gci ON gci.cart = gc.id
+    WHERE gc.token_hash = cart_token_hash
+    ON CONFLICT (customer, product) WHERE product IS NOT NULL DO UPDATE
+    SET number = CASE policy
+        WHEN 'sum' THEN shopping_cart_items.number + EXCLUDED.number
+        WHEN 'max' THEN GREATEST(shopping_cart_items.number, EXCLUDED.number)
+        WHEN 'prefer_latest' THEN CASE
+            WHEN EXCLUDED.updated_at > shopping_cart_items.updated_at THEN EXCLUDED.number
+            ELSE shopping_cart_items.number
+        END
+    END;
+
+    DELETE FROM guest_cart_items gci
+    USING guest_carts gc
+    WHERE gci.cart = gc.id AND gc.token_hash = cart_token_hash;
+$$;
@@ -0,0 +1,403 @@
+-- Addresses a customer can have their orders delivered to. Orders keep a copy of the address they
+-- were placed with, so addresses may be changed or removed freely.
+CREATE TABLE addresses (
+    id INT GENERATED ALWAYS AS IDENTITY PRIMARY KEY,
+    customer INT NOT NULL REFERENCES customers(id) ON DELETE CASCADE,
+    recipient TEXT NOT NULL,
+    street TEXT NOT NULL,
+    postal_code TEXT NOT NULL,
+    city TEXT NOT NULL,
+    country TEXT NOT NULL,
+    CONSTRAINT complete_address CHECK (
+        recipient != '' AND street != '' AND postal_code != '' AND city != '' AND country != ''
+    )
+);
+
+CREATE INDEX addresses_per_customer ON addresses (customer);
+
+-- Convert a quantity in a unit of mass to grams. Null if `unit` is not a known unit of mass, in
+-- the same representation as `measurement_unit` of products.
+CREATE FUNCTION grams(quantity DECIMAL, unit TEXT) RETURNS DECIMAL
+LANGUAGE sql IMMUTABLE PARALLEL SAFE AS $$
+    SELECT quantity * CASE unit
+        WHEN 'mg' THEN 0.001
+        WHEN 'g' THEN 1
+        WHEN 'hg' THEN 100
+        WHEN 'kg' THEN 1000
+        WHEN 't' THEN 1000000
+    END;
+$$;
+
+-- Ways a vendor ships their products. Every method charges `fee`, while weight-based methods
+-- additionally charge `fee_per_weight` for each started `weight_step` (in `weight_unit`, a unit of
+-- mass) of products shipped. Products not measured in a unit of mass are considered weightless.
+CREATE TABLE shipping_methods (
+    id INT GENERATED ALWAYS AS IDENTITY PRIMARY KEY,
+    vendor INT NOT NULL REFERENCES vendors(id) ON DELETE CASCADE,
+    name TEXT NOT NULL CHECK (name != ''),
+    fee TWOPOINT_UDEC NOT NULL,
+    fee_per_weight TWOPOINT_UDEC,
+    weight_step TWOPOINT_UDEC CHECK (weight_step > 0),
+    weight_unit TEXT,
+    CONSTRAINT shipping_methods_name_key UNIQUE (vendor, name),
+    CONSTRAINT complete_weight_fee CHECK (
+        (fee_per_weight IS NULL) = (weight_step IS NULL)
+        AND (weight_step IS NULL) = (weight_unit IS NULL)
+    ),
+    CONSTRAINT weight_in_mass CHECK (weight_unit IS NULL OR grams(1, weight_unit) IS NOT NULL)
+);
+
+CREATE INDEX shipping_methods_by_vendor ON shipping_methods (vendor);
+
+-- The fee of shipping `weight` grams of products with `method`.
+CREATE FUNCTION shipping_fee(
+    method shipping_methods,
+    weight DECIMAL
+) RETURNS DECIMAL(10, 2)
+LANGUAGE sql IMMUTABLE PARALLEL SAFE AS $$
+    SELECT method.fee + COALESCE(
+        method.fee_per_weight * CEIL(weight / grams(method.weight_step, method.weight_unit)),
+        0
+    );
+$$;
+
+-- Orders placed before delivery information was recorded have no address.
+ALTER TABLE orders
+ADD COLUMN recipient TEXT,
+ADD COLUMN street TEXT,
+ADD COLUMN postal_code TEXT,
+ADD COLUMN city TEXT,
+ADD COLUMN country TEXT,
+ADD CONSTRAINT complete_address CHECK (
+    (recipient IS NULL) = (street IS NULL)
+    AND (street IS NULL) = (postal_code IS NULL)
+    AND (postal_code IS NULL) = (city IS NULL)
+    AND (city IS NULL) = (country IS NULL)
+);
+
+-- The shipping of the products of one vendor as part of an order. As with order lines, the method
+-- is copied as it was at the time of purchase.
+CREATE TABLE order_shipments (
+    order_id INT NOT NULL REFERENCES orders(id) ON DELETE CASCADE,
+    vendor INT NOT NULL REFERENCES vendors(id) ON DELETE RESTRICT,
+    shipping_method INT REFERENCES shipping_methods(id) ON DELETE SET NULL,
+    method_name TEXT NOT NULL,
+    fee TWOPOINT_UDEC NOT NULL,
+    PRIMARY KEY (order_id, vendor)
+);
+
+CREATE TYPE CHECKOUT_SHIPMENT AS (
+    shipping_method INT,
+    expected_fee TWOPOINT_UDEC
+);
+
+-- Replaced by a function also taking the delivery address and one shipping method per vendor.
+DROP FUNCTION checkout;
+CREATE FUNCTION checkout(
+    customer_id customers.id%TYPE,
+    -- These are NOT necessarily connected to the contents of the customer's rows in,
+    -- `shopping_cart_items`, though the numbers of those rows are decremented on success.
+    items CHECKOUT_ITEM[],
+    seen_at NONFUTURE_TIMESTAMP,
+    address_id addresses.id%TYPE,
+    -- Exactly one per vendor of the products in `items`.
+    shipments CHECKOUT_SHIPMENT[]
+) RETURNS orders.id%TYPE
+LANGUAGE plpgsql AS $$
+DECLARE
+    new_order orders.id%TYPE;
+    short_product products.id%TYPE;
+    delivery addresses%ROWTYPE;
+BEGIN
+    IF seen_at IS NULL THEN
+        RAISE EXCEPTION 'Must include time cart was seen.'
+        USING ERRCODE = 'null_value_not_allowed', COLUMN = 'seen_at';
+    END IF;
+
+    CREATE TEMP TABLE cart (
+        product INT PRIMARY KEY,
+        number POSITIVE_INT NOT NULL,
+        special_offer INT,
+        expected_price TWOPOINT_UDEC NOT NULL
+    ) ON COMMIT DROP;
+    INSERT INTO cart
+    SELECT *
+    FROM UNNEST(items);
+
+    -- We allow concurrent updates to membership status as it is only read once.
+    PERFORM 1
+    FROM customers
+    WHERE id = customer_id
+    FOR KEY SHARE;
+    IF NOT FOUND THEN
+        RAISE EXCEPTION 'Customer % does not exist.', customer_id
+        USING ERRCODE = 'no_data_found';
+    END IF;
+
+    SELECT *
+    INTO delivery
+    FROM addresses
+    WHERE id = address_id AND customer = customer_id;
+    IF NOT FOUND THEN
+        RAISE EXCEPTION 'Customer % has no address %.', customer_id, address_id
+        USING ERRCODE = 'no_data_found';
+    END IF;
+
+    IF (SELECT COUNT(*) FROM cart) = 0 THEN
+        RAISE EXCEPTION 'Checkout with no items for customer %.', customer_id
+        USING ERRCODE = 'check_violation', CONSTRAINT = 'nonempty_checkout';
+    ELSIF EXISTS (
+        SELECT 1
+        FROM cart
+        JOIN products ON id = product
+        WHERE NOT visible
+    ) THEN
+        RAISE EXCEPTION 'Cart of customer % contains invisible products.', customer_id
+        USING ERRCODE = 'BP001', CONSTRAINT = 'product_unavailable';
+    END IF;
+
+    -- The stock is decremented below, so the lock is taken up front and in a consistent order to
+    -- avoid deadlocks between concurrent checkouts of the same products.
+    PERFORM 1
+    FROM products p
+    JOIN cart ON id = product
+    ORDER BY id
+    FOR NO KEY UPDATE OF p;
+
+    PERFORM 1
+    FROM special_offers s
+    JOIN cart ON id = special_offer
+    FOR KEY SHARE OF s;
+
+    IF EXISTS (
+        SELECT 1
+        FROM cart
+        JOIN products ON id = product
+        WHERE updated_at > seen_at
+    ) THEN
+        RAISE EXCEPTION 'Stale data: Product has changed.'
+        USING ERRCODE = 'BP001', CONSTRAINT = 'product_changed';
+    ELSIF EXISTS (
+        SELECT 1
+        FROM cart
+        LEFT JOIN active_special_offers aso ON aso.id = special_offer
+        WHERE special_offer IS NOT NULL AND aso.updated_at IS NULL OR aso.updated_at > seen_at
+    ) THEN
+        RAISE EXCEPTION 'Stale data: Special offer has expired.'
+        USING ERRCODE = 'BP001', CONSTRAINT = 'offer_expired';
+    ELSIF EXISTS (
+        SELECT 1
+        FROM cart
+        JOIN active_special_offers aso ON aso.id = special_offer
+        JOIN customers c ON c.id = customer_id
+        WHERE members_only AND NOT member OR aso.updated_at > seen_at
+    ) THEN
+        RAISE EXCEPTION 'Stale data: Customer (%) is not eligible.', customer_id
+        USING ERRCODE = 'BP001', CONSTRAINT = 'not_eligible';
+    END IF;
+
+    -- Shipping methods are locked so that their fees can't change before the order is placed.
+    PERFORM 1
+    FROM shipping_methods m
+    JOIN UNNEST(shipments) s ON s.shipping_method = m.id
+    ORDER BY id
+    FOR SHARE OF m;
+
+    CREATE TEMP TABLE shipping
+    ON COMMIT DROP AS
+    SELECT s.shipping_method, s.expected_fee, m.vendor, m.name, shipping_fee(m, weight) AS fee
+    FROM UNNEST(shipments) s
+    LEFT JOIN shipping_methods m ON m.id = s.shipping_method
+    LEFT JOIN LATERAL (
+        SELECT COALESCE(SUM(cart.number * grams(amount_per_unit, measurement_unit)), 0) AS weight
+        FROM cart
+        JOIN products p ON p.id = product
+        WHERE p.vendor = m.vendor
+    ) w ON TRUE;
+
+    IF EXISTS (
+        SELECT 1
+        FROM shipping
+        WHERE vendor IS NULL
+    ) THEN
+        RAISE EXCEPTION 'Stale data: Shipping method has been removed.'
+        USING ERRCODE = 'BP001', CONSTRAINT = 'shipping_changed';
+    ELSIF (SELECT COUNT(*) FROM shipping) != (SELECT COUNT(DISTINCT vendor) FROM shipping)
+        OR EXISTS (
+            SELECT p.vendor
+            FROM cart
+            JOIN products p ON p.id = product
+            EXCEPT
+            SELECT vendor
+            FROM shipping
+        ) OR EXISTS (
+            SELECT vendor
+            FROM shipping
+            EXCEPT
+            SELECT p.vendor
+            FROM cart
+            JOIN products p ON p.id = product
+        )
+    THEN
+        RAISE EXCEPTION 'Checkout must have one shipping method per vendor.'
+        USING ERRCODE = 'check_violation', CONSTRAINT = 'one_shipment_per_vendor';
+    ELSIF EXISTS (
+        SELECT 1
+        FROM shipping
+        WHERE fee != expected_fee
+    ) THEN
+        RAISE EXCEPTION 'Stale data: Shipping fee has changed.'
+        USING ERRCODE = 'BP001', CONSTRAINT = 'shipping_changed';
+    END IF;
+
+    -- Insert zeros to prevent other calls from double-counting, and do dummy update on existing
+    -- rows to lock them.
+    INSERT INTO special_offer_uses (special_offer, customer, number)
+    SELECT special_offer, customer_id, 0
+    FROM cart
+    WHERE special_offer IS NOT NULL
+    ON CONFLICT (special_offer, customer) DO UPDATE
+    SET number = special_offer_uses.number;
+
+    CREATE TEMP TABLE results
+    ON COMMIT DROP AS
+    SELECT
+        cart.*, p.price AS unit_price, aso.new_price, aso.quantity1, aso.quantity2, calc.price,
+        calc.uses
+    FROM cart
+    JOIN products p ON p.id = product
+    LEFT JOIN active_special_offers aso ON aso.id = special_offer
+    LEFT JOIN special_offer_uses sou ON sou.special_offer = cart.special_offer AND customer = customer_id
+    CROSS JOIN LATERAL calculate_price(
+        price, cart.number, new_price, quantity1, quantity2,
+        CASE
+            -- Unlimited: the offer can at most be used once per unit.
+            WHEN limit_per_customer IS NULL THEN cart.number
+            ELSE GREATEST(limit_per_customer - COALESCE(sou.number, 0), 0)
+        END
+    ) AS calc;
+
+    IF EXISTS (
+        SELECT 1
+        FROM results
+        WHERE price != expected_price
+    ) THEN
+        RAISE EXCEPTION 'Stale data: Special offer has been used enough times to create a price discrepancy.'
+        USING ERRCODE = 'BP001', CONSTRAINT = 'offer_used_up';
+    END IF;
+
+    SELECT id
+    INTO short_product
+    FROM cart
+    JOIN products ON id = product
+    WHERE in_stock < number
+    ORDER BY id
+    LIMIT 1;
+    IF FOUND THEN
+        RAISE EXCEPTION 'Product % does not have enough stock.', short_product
+        USING ERRCODE = 'BP002', DETAIL = short_product::TEXT;
+    END IF;
+
+    UPDATE products
+    SET in_stock = in_stock - number
+    FROM cart
+    WHERE id = product;
+
+    PERFORM sale_remove_expiries(product, number)
+    FROM cart;
+
+    UPDATE shopping_cart_items
+    SET number = GREATEST(shopping_cart_items.number - r.number, 0)
+    FROM results r
+    WHERE shopping_cart_items.product = r.product AND customer = customer_id;
+    DELETE FROM shopping_cart_items
+    WHERE customer = customer_id AND number = 0;
+
+    UPDATE special_offer_uses
+    SET number = special_offer_uses.number + r.uses
+    FROM results r
+    WHERE r.special_offer = special_offer_uses.special_offer AND customer = customer_id AND uses > 0;
+
+    INSERT INTO orders (customer, total, recipient, street, postal_code, city, country)
+    SELECT
+        customer_id, SUM(price), delivery.recipient, delivery.street, delivery.postal_code,
+        delivery.city, delivery.country
+    FROM results
+    RETURNING id INTO new_order;
+
+    INSERT INTO order_lines (
+        order_id, product, number, unit_price, special_offer, new_price, quantity1, quantity2,
+        special_offer_uses, paid
+    )
+    SELECT
+        new_order, product, number, unit_price,
+        CASE WHEN uses > 0 THEN special_offer END,
+        CASE WHEN uses > 0 THEN new_price END,
+        CASE WHEN uses > 0 THEN quantity1 END,
+        CASE WHEN uses > 0 THEN quantity2 END,
+        uses, price
+    FROM results;
+
+    INSERT INTO order_shipments (order_id, vendor, shipping_method, method_name, fee)
+    SELECT new_order, vendor, shipping_method, name, fee
+    FROM shipping;
+
+    RETURN new_order;
+END;
+$$;
+
+CREATE OR REPLACE PROCEDURE delete_user(deleted_id users.id%TYPE)
+LANGUAGE plpgsql AS $$
+BEGIN
+    -- NOTE: Soft deletion. Possible corresponding row in role-specific table is also kept.
+    UPDATE users
+    SET deleted = true
+    WHERE id = deleted_id;
+    IF NOT FOUND THEN
+        RAISE EXCEPTION 'User % does not exist.', deleted_id
+        USING ERRCODE = 'no_data_found';
+    END IF;
+
+    -- PERF: Several of these queries are not supported by indices: we imagine account deletions
+    -- are rare.
+    IF EXISTS (SELECT 1 FROM customers WHERE id = deleted_id) THEN
+        DELETE FROM special_offer_uses
+        WHERE customer = deleted_id;
+
+        -- NOTE: Reviews must be deleted before ratings.
+        DELETE FROM reviews
+        WHERE customer = deleted_id;
+
+        DELETE FROM ratings
+        WHERE customer = deleted_id;
+
+        DELETE FROM review_votes
+        WHERE customer = deleted_id;
+
+        DELETE FROM comment_votes
+        WHERE customer = deleted_id;
+
+        DELETE FROM shopping_cart_items
+        WHERE customer = deleted_id;
+
+        DELETE FROM customer_favorites
+        WHERE customer = deleted_id;
+
+        DELETE FROM addresses
+        WHERE customer = deleted_id;
+    ELSIF EXISTS (SELECT 1 FROM vendors WHERE id = deleted_id) THEN
+        DELETE FROM products
+        WHERE vendor = deleted_id;
+
+        DELETE FROM shipping_methods
+        WHERE vendor = deleted_id;
+    END IF;
+
+    DELETE FROM comments
+    WHERE user_id = deleted_id;
+
+    UPDATE sessions
+    SET revoked_at = CURRENT_TIMESTAMP
+    WHERE user_id = deleted_id AND revoked_at IS NULL;
+END;
+$$;
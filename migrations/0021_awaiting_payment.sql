@@ -0,0 +1,3 @@
+-- Added in a migration of its own, as new values can't be used in the same transaction as they are
+-- added, and the next migration makes it the status orders are placed with.
+ALTER TYPE ORDER_STATUS ADD VALUE 'awaiting_payment' BEFORE 'pending';
@@ -0,0 +1,77 @@
+-- Orders are placed before they are paid for, so that their stock is reserved while the payment is
+-- authorized. They become pending once it has been.
+ALTER TABLE orders ALTER COLUMN status SET DEFAULT 'awaiting_payment';
+
+CREATE TYPE PAYMENT_STATUS AS ENUM ('authorized', 'captured', 'refunded');
+
+-- The payment of an order, as handled by the payment provider. Card details are never stored.
+CREATE TABLE payments (
+    order_id INT PRIMARY KEY REFERENCES orders(id) ON DELETE CASCADE,
+    -- The provider's reference to the authorization.
+    reference TEXT NOT NULL CHECK (reference != ''),
+    amount TWOPOINT_UDEC NOT NULL,
+    status PAYMENT_STATUS NOT NULL DEFAULT 'authorized',
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE TRIGGER payment_update_time
+BEFORE UPDATE ON payments
+FOR EACH ROW EXECUTE FUNCTION update_time();
+
+CREATE OR REPLACE FUNCTION valid_order_transition(
+    old_status ORDER_STATUS,
+    new_status ORDER_STATUS
+) RETURNS BOOLEAN
+LANGUAGE plpgsql IMMUTABLE PARALLEL SAFE AS $$
+BEGIN
+    RETURN CASE old_status
+        -- Cancelling means the payment failed.
+        WHEN 'awaiting_payment' THEN new_status IN ('pending', 'cancelled')
+        WHEN 'pending' THEN new_status IN ('shipped', 'cancelled')
+        WHEN 'shipped' THEN new_status = 'received'
+        WHEN 'received' THEN new_status = 'return_requested'
+        -- Going back to received means the return was rejected.
+        WHEN 'return_requested' THEN new_status IN ('returned', 'received')
+        WHEN 'cancelled' THEN new_status = 'refunded'
+        WHEN 'returned' THEN new_status = 'refunded'
+        ELSE FALSE
+    END;
+END;
+$$;
+
+CREATE OR REPLACE FUNCTION validate_order_transition() RETURNS TRIGGER
+LANGUAGE plpgsql STABLE AS $$
+BEGIN
+    IF TG_OP = 'INSERT' AND NEW.status != 'awaiting_payment' THEN
+        RAISE EXCEPTION 'Orders must be placed as awaiting payment.';
+    ELSIF TG_OP = 'UPDATE' AND NEW.status != OLD.status
+        AND NOT valid_order_transition(OLD.status, NEW.status)
+    THEN
+        RAISE EXCEPTION 'Order % can not go from % to %.', NEW.id, OLD.status, NEW.status;
+    END IF;
+
+    RETURN NEW;
+END;
+$$;
+
+-- Cancel an order awaiting a payment that failed, and put its products back in the customer's cart
+-- so that they can try again.
+CREATE PROCEDURE fail_payment(failed_order orders.id%TYPE)
+LANGUAGE plpgsql AS $$
+BEGIN
+    UPDATE orders
+    SET status = 'cancelled'
+    WHERE id = failed_order AND status = 'awaiting_payment';
+    IF NOT FOUND THEN
+        RAISE EXCEPTION no_data_found;
+    END IF;
+
+    INSERT INTO shopping_cart_items (customer, product, number)
+    SELECT o.customer, l.product, l.number
+    FROM orders o
+    JOIN order_lines l ON l.order_id = o.id
+    WHERE o.id = failed_order AND l.product IS NOT NULL
+    ON CONFLICT (customer, product) WHERE product IS NOT NULL DO UPDATE
+    SET number = shopping_cart_items.number + EXCLUDED.number;
+END;
+$$;
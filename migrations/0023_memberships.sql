@@ -0,0 +1,65 @@
+-- Membership is free and lasts until cancelled, unless the customer has paid for a tier, in which
+-- case it lasts until the end of the paid period.
+CREATE TABLE membership_tiers (
+    id INT GENERATED ALWAYS AS IDENTITY PRIMARY KEY,
+    name TEXT UNIQUE NOT NULL CHECK (name != ''),
+    fee TWOPOINT_UDEC NOT NULL CONSTRAINT paid_tier CHECK (fee > 0),
+    -- The length of the period paid for by each fee.
+    months POSITIVE_INT NOT NULL,
+    created_at NONFUTURE_TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+ALTER TABLE customers
+    ADD COLUMN membership_tier INT REFERENCES membership_tiers(id),
+    -- The first day the customer is no longer a member, unless they pay again before it.
+    ADD COLUMN member_until DATE,
+    ADD CONSTRAINT paid_membership CHECK (
+        (membership_tier IS NULL) = (member_until IS NULL)
+        AND (membership_tier IS NULL OR member_since IS NOT NULL)
+    );
+
+CREATE INDEX customers_by_member_until ON customers (member_until);
+
+-- NOTE: Payments are kept when the customer stops being a member, as a record of what they paid.
+CREATE TABLE membership_payments (
+    id INT GENERATED ALWAYS AS IDENTITY PRIMARY KEY,
+    customer INT NOT NULL REFERENCES customers(id) ON DELETE CASCADE,
+    tier INT NOT NULL REFERENCES membership_tiers(id),
+    reference TEXT NOT NULL CHECK (reference != ''),
+    amount TWOPOINT_UDEC NOT NULL,
+    paid_at NONFUTURE_TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX membership_payments_by_customer ON membership_payments (customer);
+
+CREATE FUNCTION process_membership_expiries() RETURNS TABLE (
+    customer INT
+) LANGUAGE sql AS $$
+    UPDATE customers
+    SET member_since = NULL, membership_tier = NULL, member_until = NULL
+    WHERE member_until <= CURRENT_DATE
+    RETURNING id
+$$;
+
+-- WARN: As with `process_expiries`, this only runs at midnight, so it must also be called on
+-- establishing a connection to the database.
+DO $do$
+BEGIN
+    IF EXISTS (SELECT 1 FROM pg_available_extensions WHERE name = 'pg_cron') THEN
+        CREATE EXTENSION IF NOT EXISTS pg_cron;
+        PERFORM cron.schedule(
+            'process_daily_membership_expiries',
+            -- Daily at midnight.
+            '0 0 * * *',
+            $$
+            SELECT process_membership_expiries();
+            $$
+        );
+    ELSE
+        RAISE NOTICE 'pg_cron is not available, memberships will not expire automatically.';
+    END IF;
+EXCEPTION
+    WHEN OTHERS THEN
+        RAISE NOTICE 'Failed to schedule expiry of memberships: %', SQLERRM;
+END;
+$do$;
@@ -0,0 +1,412 @@
+-- Codes customers can enter at checkout for a discount on their whole order. Each promotion gives
+-- exactly one of a percentage off the products, a fixed amount off the products, or free shipping.
+CREATE TABLE promotions (
+    id INT GENERATED ALWAYS AS IDENTITY PRIMARY KEY,
+    code CITEXT UNIQUE NOT NULL CHECK (code != ''),
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    percent_off DECIMAL(5, 2) CHECK (percent_off > 0 AND percent_off <= 100),
+    amount_off TWOPOINT_UDEC CHECK (amount_off > 0),
+    free_shipping BOOLEAN NOT NULL DEFAULT FALSE,
+    -- The products of an order must cost at least this much, after special offers.
+    minimum_spend TWOPOINT_UDEC NOT NULL DEFAULT 0,
+    -- Null: unlimited.
+    max_uses POSITIVE_INT,
+    limit_per_customer POSITIVE_INT,
+    valid_from TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    -- Null: promotion must be removed manually.
+    valid_until TIMESTAMP CONSTRAINT end_after_start CHECK (valid_until IS NULL OR valid_until > valid_from),
+    CONSTRAINT one_benefit CHECK (num_nonnulls(percent_off, amount_off, NULLIF(free_shipping, FALSE)) = 1)
+);
+
+CREATE TRIGGER promotions_update_time
+BEFORE UPDATE ON promotions
+FOR EACH ROW EXECUTE FUNCTION update_time();
+
+CREATE VIEW active_promotions AS
+SELECT *
+FROM promotions
+WHERE valid_from < CURRENT_TIMESTAMP AND (valid_until IS NULL OR valid_until > CURRENT_TIMESTAMP);
+
+-- NOTE: As with `special_offer_uses`, customers may have used a promotion more times than its
+-- limits allow after the limits have changed. This only prevents future uses.
+CREATE TABLE promotion_uses (
+    customer INT NOT NULL REFERENCES customers(id) ON DELETE CASCADE,
+    promotion INT NOT NULL REFERENCES promotions(id) ON DELETE CASCADE,
+    number UINT NOT NULL DEFAULT 0,
+    PRIMARY KEY (promotion, customer)
+);
+
+-- The discount `promotion` gives on an order of products costing `total`, shipped for `shipping`.
+-- Null if `total` does not reach the minimum spend.
+CREATE FUNCTION promotion_discount(
+    promotion promotions,
+    total DECIMAL,
+    shipping DECIMAL
+) RETURNS DECIMAL(10, 2)
+LANGUAGE sql IMMUTABLE PARALLEL SAFE AS $$
+    SELECT CASE
+        WHEN total < promotion.minimum_spend THEN NULL
+        WHEN promotion.free_shipping THEN shipping
+        WHEN promotion.amount_off IS NOT NULL THEN LEAST(promotion.amount_off, total)
+        ELSE ROUND(total * promotion.percent_off / 100, 2)
+    END;
+$$;
+
+-- The promotion is copied as it was at the time of purchase, as with shipping methods. The discount
+-- is taken off `total` plus the shipping fees.
+ALTER TABLE orders
+ADD COLUMN promotion INT REFERENCES promotions(id) ON DELETE SET NULL,
+ADD COLUMN promotion_code TEXT,
+ADD COLUMN discount TWOPOINT_UDEC NOT NULL DEFAULT 0;
+
+CREATE TYPE CHECKOUT_PROMOTION AS (
+    promotion INT,
+    expected_discount TWOPOINT_UDEC
+);
+
+-- Replaced by a function also taking the promotion code entered, if any.
+DROP FUNCTION checkout;
+CREATE FUNCTION checkout(
+    customer_id customers.id%TYPE,
+    -- These are NOT necessarily connected to the contents of the customer's rows in,
+    -- `shopping_cart_items`, though the numbers of those rows are decremented on success.
+    items CHECKOUT_ITEM[],
+    seen_at NONFUTURE_TIMESTAMP,
+    address_id addresses.id%TYPE,
+    -- Exactly one per vendor of the products in `items`.
+    shipments CHECKOUT_SHIPMENT[],
+    -- Null: no promotion code was entered.
+    promotion_use CHECKOUT_PROMOTION
+) RETURNS orders.id%TYPE
+LANGUAGE plpgsql AS $$
+DECLARE
+    new_order orders.id%TYPE;
+    short_product products.id%TYPE;
+    delivery addresses%ROWTYPE;
+    applied promotions%ROWTYPE;
+    order_discount TWOPOINT_UDEC := 0;
+BEGIN
+    IF seen_at IS NULL THEN
+        RAISE EXCEPTION 'Must include time cart was seen.'
+        USING ERRCODE = 'null_value_not_allowed', COLUMN = 'seen_at';
+    END IF;
+
+    CREATE TEMP TABLE cart (
+        product INT PRIMARY KEY,
+        number POSITIVE_INT NOT NULL,
+        special_offer INT,
+        expected_price TWOPOINT_UDEC NOT NULL
+    ) ON COMMIT DROP;
+    INSERT INTO cart
+    SELECT *
+    FROM UNNEST(items);
+
+    -- We allow concurrent updates to membership status as it is only read once.
+    PERFORM 1
+    FROM customers
+    WHERE id = customer_id
+    FOR KEY SHARE;
+    IF NOT FOUND THEN
+        RAISE EXCEPTION 'Customer % does not exist.', customer_id
+        USING ERRCODE = 'no_data_found';
+    END IF;
+
+    SELECT *
+    INTO delivery
+    FROM addresses
+    WHERE id = address_id AND customer = customer_id;
+    IF NOT FOUND THEN
+        RAISE EXCEPTION 'Customer % has no address %.', customer_id, address_id
+        USING ERRCODE = 'no_data_found';
+    END IF;
+
+    IF (SELECT COUNT(*) FROM cart) = 0 THEN
+        RAISE EXCEPTION 'Checkout with no items for customer %.', customer_id
+        USING ERRCODE = 'check_violation', CONSTRAINT = 'nonempty_checkout';
+    ELSIF EXISTS (
+        SELECT 1
+        FROM cart
+        JOIN products ON id = product
+        WHERE NOT visible
+    ) THEN
+        RAISE EXCEPTION 'Cart of customer % contains invisible products.', customer_id
+        USING ERRCODE = 'BP001', CONSTRAINT = 'product_unavailable';
+    END IF;
+
+    -- The stock is decremented below, so the lock is taken up front and in a consistent order to
+    -- avoid deadlocks between concurrent checkouts of the same products.
+    PERFORM 1
+    FROM products p
+    JOIN cart ON id = product
+    ORDER BY id
+    FOR NO KEY UPDATE OF p;
+
+    PERFORM 1
+    FROM special_offers s
+    JOIN cart ON id = special_offer
+    FOR KEY SHARE OF s;
+
+    IF EXISTS (
+        SELECT 1
+        FROM cart
+        JOIN products ON id = product
+        WHERE updated_at > seen_at
+    ) THEN
+        RAISE EXCEPTION 'Stale data: Product has changed.'
+        USING ERRCODE = 'BP001', CONSTRAINT = 'product_changed';
+    ELSIF EXISTS (
+        SELECT 1
+        FROM cart
+        LEFT JOIN active_special_offers aso ON aso.id = special_offer
+        WHERE special_offer IS NOT NULL AND aso.updated_at IS NULL OR aso.updated_at > seen_at
+    ) THEN
+        RAISE EXCEPTION 'Stale data: Special offer has expired.'
+        USING ERRCODE = 'BP001', CONSTRAINT = 'offer_expired';
+    ELSIF EXISTS (
+        SELECT 1
+        FROM cart
+        JOIN active_special_offers aso ON aso.id = special_offer
+        JOIN customers c ON c.id = customer_id
+        WHERE members_only AND NOT member OR aso.updated_at > seen_at
+    ) THEN
+        RAISE EXCEPTION 'Stale data: Customer (%) is not eligible.', customer_id
+        USING ERRCODE = 'BP001', CONSTRAINT = 'not_eligible';
+    END IF;
+
+    -- Shipping methods are locked so that their fees can't change before the order is placed.
+    PERFORM 1
+    FROM shipping_methods m
+    JOIN UNNEST(shipments) s ON s.shipping_method = m.id
+    ORDER BY id
+    FOR SHARE OF m;
+
+    CREATE TEMP TABLE shipping
+    ON COMMIT DROP AS
+    SELECT s.shipping_method, s.expected_fee, m.vendor, m.name, shipping_fee(m, weight) AS fee
+    FROM UNNEST(shipments) s
+    LEFT JOIN shipping_methods m ON m.id = s.shipping_method
+    LEFT JOIN LATERAL (
+        SELECT COALESCE(SUM(cart.number * grams(amount_per_unit, measurement_unit)), 0) AS weight
+        FROM cart
+        JOIN products p ON p.id = product
+        WHERE p.vendor = m.vendor
+    ) w ON TRUE;
+
+    IF EXISTS (
+        SELECT 1
+        FROM shipping
+        WHERE vendor IS NULL
+    ) THEN
+        RAISE EXCEPTION 'Stale data: Shipping method has been removed.'
+        USING ERRCODE = 'BP001', CONSTRAINT = 'shipping_changed';
+    ELSIF (SELECT COUNT(*) FROM shipping) != (SELECT COUNT(DISTINCT vendor) FROM shipping)
+        OR EXISTS (
+            SELECT p.vendor
+            FROM cart
+            JOIN products p ON p.id = product
+            EXCEPT
+            SELECT vendor
+            FROM shipping
+        ) OR EXISTS (
+            SELECT vendor
+            FROM shipping
+            EXCEPT
+            SELECT p.vendor
+            FROM cart
+            JOIN products p ON p.id = product
+        )
+    THEN
+        RAISE EXCEPTION 'Checkout must have one shipping method per vendor.'
+        USING ERRCODE = 'check_violation', CONSTRAINT = 'one_shipment_per_vendor';
+    ELSIF EXISTS (
+        SELECT 1
+        FROM shipping
+        WHERE fee != expected_fee
+    ) THEN
+        RAISE EXCEPTION 'Stale data: Shipping fee has changed.'
+        USING ERRCODE = 'BP001', CONSTRAINT = 'shipping_changed';
+    END IF;
+
+    -- Insert zeros to prevent other calls from double-counting, and do dummy update on existing
+    -- rows to lock them.
+    INSERT INTO special_offer_uses (special_offer, customer, number)
+    SELECT special_offer, customer_id, 0
+    FROM cart
+    WHERE special_offer IS NOT NULL
+    ON CONFLICT (special_offer, customer) DO UPDATE
+    SET number = special_offer_uses.number;
+
+    CREATE TEMP TABLE results
+    ON COMMIT DROP AS
+    SELECT
+        cart.*, p.price AS unit_price, aso.new_price, aso.quantity1, aso.quantity2, calc.price,
+        calc.uses
+    FROM cart
+    JOIN products p ON p.id = product
+    LEFT JOIN active_special_offers aso ON aso.id = special_offer
+    LEFT JOIN special_offer_uses sou ON sou.special_offer = cart.special_offer AND customer = customer_id
+    CROSS JOIN LATERAL calculate_price(
+        price, cart.number, new_price, quantity1, quantity2,
+        CASE
+            -- Unlimited: the offer can at most be used once per unit.
+            WHEN limit_per_customer IS NULL THEN cart.number
+            ELSE GREATEST(limit_per_customer - COALESCE(sou.number, 0), 0)
+        END
+    ) AS calc;
+
+    IF EXISTS (
+        SELECT 1
+        FROM results
+        WHERE price != expected_price
+    ) THEN
+        RAISE EXCEPTION 'Stale data: Special offer has been used enough times to create a price discrepancy.'
+        USING ERRCODE = 'BP001', CONSTRAINT = 'offer_used_up';
+    END IF;
+
+    IF promotion_use IS NOT NULL THEN
+        -- Locked so that concurrent checkouts with the same code count each other's uses.
+        SELECT *
+        INTO applied
+        FROM promotions
+        WHERE id = (promotion_use).promotion
+        FOR NO KEY UPDATE;
+        IF NOT FOUND
+            OR applied.updated_at > seen_at
+            OR applied.valid_from > CURRENT_TIMESTAMP
+            OR applied.valid_until <= CURRENT_TIMESTAMP
+        THEN
+            RAISE EXCEPTION 'Stale data: Promotion has expired or changed.'
+            USING ERRCODE = 'BP001', CONSTRAINT = 'promotion_changed';
+        ELSIF applied.max_uses <= (
+            SELECT COALESCE(SUM(number), 0)
+            FROM promotion_uses
+            WHERE promotion_uses.promotion = applied.id
+        ) OR applied.limit_per_customer <= (
+            SELECT COALESCE(SUM(number), 0)
+            FROM promotion_uses
+            WHERE promotion_uses.promotion = applied.id AND customer = customer_id
+        ) THEN
+            RAISE EXCEPTION 'Stale data: Promotion has been used up.'
+            USING ERRCODE = 'BP001', CONSTRAINT = 'promotion_used_up';
+        END IF;
+
+        order_discount := promotion_discount(
+            applied,
+            (SELECT SUM(price) FROM results),
+            (SELECT SUM(fee) FROM shipping)
+        );
+        IF order_discount IS NULL THEN
+            RAISE EXCEPTION 'Order does not reach the minimum spend of promotion %.', applied.id
+            USING ERRCODE = 'check_violation', CONSTRAINT = 'minimum_spend';
+        ELSIF order_discount != (promotion_use).expected_discount THEN
+            RAISE EXCEPTION 'Stale data: Promotion gives a different discount.'
+            USING ERRCODE = 'BP001', CONSTRAINT = 'promotion_changed';
+        END IF;
+    END IF;
+
+    SELECT id
+    INTO short_product
+    FROM cart
+    JOIN products ON id = product
+    WHERE in_stock < number
+    ORDER BY id
+    LIMIT 1;
+    IF FOUND THEN
+        RAISE EXCEPTION 'Product % does not have enough stock.', short_product
+        USING ERRCODE = 'BP002', DETAIL = short_product::TEXT;
+    END IF;
+
+    UPDATE products
+    SET in_stock = in_stock - number
+    FROM cart
+    WHERE id = product;
+
+    PERFORM sale_remove_expiries(product, number)
+    FROM cart;
+
+    UPDATE shopping_cart_items
+    SET number = GREATEST(shopping_cart_items.number - r.number, 0)
+    FROM results r
+    WHERE shopping_cart_items.product = r.product AND customer = customer_id;
+    DELETE FROM shopping_cart_items
+    WHERE customer = customer_id AND number = 0;
+
+    UPDATE special_offer_uses
+    SET number = special_offer_uses.number + r.uses
+    FROM results r
+    WHERE r.special_offer = special_offer_uses.special_offer AND customer = customer_id AND uses > 0;
+
+    IF promotion_use IS NOT NULL THEN
+        INSERT INTO promotion_uses (promotion, customer, number)
+        VALUES (applied.id, customer_id, 1)
+        ON CONFLICT (promotion, customer) DO UPDATE
+        SET number = promotion_uses.number + 1;
+    END IF;
+
+    INSERT INTO orders (
+        customer, total, recipient, street, postal_code, city, country, promotion, promotion_code,
+        discount
+    )
+    SELECT
+        customer_id, SUM(price), delivery.recipient, delivery.street, delivery.postal_code,
+        delivery.city, delivery.country, applied.id, applied.code, order_discount
+    FROM results
+    RETURNING id INTO new_order;
+
+    INSERT INTO order_lines (
+        order_id, product, number, unit_price, special_offer, new_price, quantity1, quantity2,
+        special_offer_uses, paid
+    )
+    SELECT
+        new_order, product, number, unit_price,
+        CASE WHEN uses > 0 THEN special_offer END,
+        CASE WHEN uses > 0 THEN new_price END,
+        CASE WHEN uses > 0 THEN quantity1 END,
+        CASE WHEN uses > 0 THEN quantity2 END,
+        uses, price
+    FROM results;
+
+    INSERT INTO order_shipments (order_id, vendor, shipping_method, method_name, fee)
+    SELECT new_order, vendor, shipping_method, name, fee
+    FROM shipping;
+
+    RETURN new_order;
+END;
+$$;
+
+CREATE OR REPLACE FUNCTION record_order_transition() RETURNS TRIGGER
+LANGUAGE plpgsql AS $$
+BEGIN
+    INSERT INTO order_status_changes (order_id, status)
+    VALUES (NEW.id, NEW.status);
+
+    -- The goods are back with the vendor, so they can be sold again and the customer may make use of
+    -- any special offers or promotion codes again.
+    IF NEW.status IN ('cancelled', 'returned') THEN
+        -- Consistent lock order with checkout.
+        PERFORM 1
+        FROM products p
+        JOIN order_lines l ON l.product = p.id
+        WHERE l.order_id = NEW.id
+        ORDER BY p.id
+        FOR NO KEY UPDATE OF p;
+
+        UPDATE products p
+        SET in_stock = in_stock + l.number
+        FROM order_lines l
+        WHERE l.order_id = NEW.id AND l.product = p.id;
+
+        UPDATE special_offer_uses sou
+        SET number = GREATEST(sou.number - l.special_offer_uses, 0)
+        FROM order_lines l
+        WHERE l.order_id = NEW.id AND sou.special_offer = l.special_offer
+            AND sou.customer = NEW.customer;
+
+        UPDATE promotion_uses pu
+        SET number = GREATEST(pu.number - 1, 0)
+        WHERE pu.promotion = NEW.promotion AND pu.customer = NEW.customer;
+    END IF;
+
+    RETURN NULL;
+END;
+$$;
@@ -0,0 +1,106 @@
+-- Several special offers may now be active on a product at once, e.g. one for members and a smaller
+-- one for everyone. Each customer gets the best one they are eligible for.
+ALTER TABLE special_offers DROP CONSTRAINT no_overlap;
+
+-- The special offer on a product that gives `customer_id` the lowest price for `number` units,
+-- taking into account how many times they have already used each offer. Ties are broken by the
+-- best average discount, and then by the oldest offer.
+--
+-- Offers the customer is not eligible for are only returned if there is no other offer, so that
+-- they can be advertised. Callers pricing a purchase must check eligibility themselves.
+CREATE FUNCTION best_special_offer(
+    product_id products.id%TYPE,
+    -- Null: not logged in, so neither a member nor having used any offer.
+    customer_id customers.id%TYPE,
+    -- Null: no number of units is known, so offers are ranked by average discount only. Qualified
+    -- with the function name below, as `special_offer_uses` has a column of the same name.
+    number INT
+) RETURNS SETOF special_offers
+LANGUAGE sql STABLE AS $$
+    SELECT aso.*
+    FROM active_special_offers aso
+    JOIN products p ON p.id = aso.product
+    LEFT JOIN customers c ON c.id = customer_id
+    LEFT JOIN special_offer_uses sou ON sou.special_offer = aso.id AND sou.customer = customer_id
+    CROSS JOIN LATERAL (
+        -- Null: unlimited.
+        SELECT CASE
+            WHEN aso.limit_per_customer IS NOT NULL
+            THEN GREATEST(aso.limit_per_customer - COALESCE(sou.number, 0), 0)
+        END AS remaining_uses
+    ) r
+    WHERE aso.product = product_id
+    ORDER BY
+        aso.members_only AND NOT COALESCE(c.member, FALSE),
+        CASE WHEN best_special_offer.number IS NOT NULL THEN (
+            calculate_price(
+                p.price, best_special_offer.number, aso.new_price, aso.quantity1, aso.quantity2,
+                COALESCE(r.remaining_uses, best_special_offer.number)
+            )
+        ).price END,
+        COALESCE(r.remaining_uses = 0, FALSE),
+        average_discount(p.price, aso.new_price, aso.quantity1, aso.quantity2) DESC,
+        aso.id
+    LIMIT 1
+$$;
+
+-- Products with several offers would otherwise be returned once per offer.
+CREATE OR REPLACE FUNCTION product_search(
+    search_text TEXT,
+    category_id categories.id%TYPE,
+    vendor_id vendors.id%TYPE,
+    price_min DECIMAL,
+    price_max DECIMAL,
+    origin_name products.origin%TYPE,
+    in_stock_only BOOLEAN,
+    with_offer BOOLEAN,
+    offer_members_only BOOLEAN,
+    rating_min INT
+) RETURNS TABLE (
+    product INT,
+    relevance REAL,
+    average_rating FLOAT,
+    rating_count BIGINT,
+    in_category BOOLEAN,
+    by_vendor BOOLEAN,
+    in_price_range BOOLEAN,
+    from_origin BOOLEAN,
+    stocked BOOLEAN,
+    offered BOOLEAN,
+    rated BOOLEAN
+)
+LANGUAGE sql STABLE PARALLEL SAFE AS $$
+    WITH product_ratings AS (
+        SELECT r.product, AVG(r.rating::FLOAT) AS average, COUNT(*) AS count
+        FROM ratings r
+        GROUP BY r.product
+    )
+    SELECT p.id,
+        ts_rank(p.search_vector, s.query) + word_similarity(s.unaccented, unaccented(p.name)),
+        pr.average,
+        COALESCE(pr.count, 0),
+        category_id IS NULL OR p.category IN (SELECT category_subtree(category_id)),
+        vendor_id IS NULL OR p.vendor = vendor_id,
+        (price_min IS NULL OR p.price >= price_min) AND (price_max IS NULL OR p.price <= price_max),
+        origin_name IS NULL OR p.origin = origin_name,
+        NOT in_stock_only OR p.in_stock > 0,
+        NOT with_offer OR EXISTS (
+            SELECT 1
+            FROM active_special_offers aso
+            WHERE aso.product = p.id
+                AND (offer_members_only IS NULL OR aso.members_only = offer_members_only)
+        ),
+        rating_min IS NULL OR COALESCE(pr.average >= rating_min, FALSE)
+    FROM products p
+    CROSS JOIN LATERAL (
+        SELECT plainto_tsquery(p.search_language::TEXT::REGCONFIG, search_text) AS query,
+            unaccented(search_text) AS unaccented
+    ) s
+    LEFT JOIN product_ratings pr ON pr.product = p.id
+    WHERE p.visible
+        AND (
+            btrim(search_text) = ''
+            OR p.search_vector @@ s.query
+            OR s.unaccented <% unaccented(p.name)
+        );
+$$;
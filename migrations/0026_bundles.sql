@@ -0,0 +1,568 @@
+-- Deals spanning several products of a vendor. There are two variants:
+-- 1. "ANY `quantity` PAY `price`" (mix and match) over the products in `bundle_products`, or over
+--    the vendor's products in `category` and its subcategories.
+-- 2. "ALL OF `bundle_products` PAY `price`" (fixed bundle) has `quantity` as `NULL`, and takes
+--    `number` units of each product.
+CREATE TABLE bundles (
+    id INT GENERATED ALWAYS AS IDENTITY PRIMARY KEY,
+    vendor INT NOT NULL REFERENCES vendors(id) ON DELETE CASCADE,
+    name TEXT NOT NULL CHECK (name != ''),
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    price TWOPOINT_UDEC NOT NULL,
+    quantity INT CHECK (quantity IS NULL OR quantity > 1),
+    category INT REFERENCES categories(id) ON DELETE CASCADE,
+    valid_from TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    -- Null: bundle must be ended manually.
+    valid_until TIMESTAMP CONSTRAINT end_after_start CHECK (valid_until IS NULL OR valid_until > valid_from),
+    CONSTRAINT category_mix_and_match CHECK (category IS NULL OR quantity IS NOT NULL)
+);
+
+CREATE TABLE bundle_products (
+    bundle INT NOT NULL REFERENCES bundles(id) ON DELETE CASCADE,
+    product INT NOT NULL REFERENCES products(id) ON DELETE CASCADE,
+    -- Always 1 for mix and match.
+    number POSITIVE_INT NOT NULL DEFAULT 1,
+    PRIMARY KEY (bundle, product)
+);
+
+CREATE INDEX bundles_by_product ON bundle_products (product);
+
+CREATE VIEW active_bundles AS
+SELECT *
+FROM bundles
+WHERE valid_from < CURRENT_TIMESTAMP AND (valid_until IS NULL OR valid_until > CURRENT_TIMESTAMP);
+
+CREATE TRIGGER bundles_update_time
+BEFORE UPDATE ON bundles
+FOR EACH ROW EXECUTE FUNCTION update_time();
+
+-- A fixed bundle missing one of its products would be sold for the same price with fewer products.
+CREATE FUNCTION delete_incomplete_bundle() RETURNS TRIGGER
+LANGUAGE plpgsql AS $$
+BEGIN
+    DELETE FROM bundles
+    WHERE id = OLD.bundle AND quantity IS NULL;
+    RETURN NULL;
+END;
+$$;
+
+CREATE TRIGGER bundle_product_deleted
+AFTER DELETE ON bundle_products
+FOR EACH ROW EXECUTE FUNCTION delete_incomplete_bundle();
+
+-- Whether units of product `p` may be allocated to bundle `b`.
+CREATE FUNCTION bundle_includes(b bundles, p products) RETURNS BOOLEAN
+LANGUAGE sql STABLE AS $$
+    SELECT b.vendor = p.vendor AND (
+        EXISTS (
+            SELECT 1
+            FROM bundle_products bp
+            WHERE bp.bundle = b.id AND bp.product = p.id
+        )
+        OR b.category IS NOT NULL AND p.category IN (SELECT category_subtree(b.category))
+    );
+$$;
+
+-- Whether a bundle has started, ended or been changed since `since`, and so might price a cart
+-- differently than it was seen at that time.
+CREATE FUNCTION bundle_changed(bundle bundles, since TIMESTAMP) RETURNS BOOLEAN
+LANGUAGE sql STABLE AS $$
+    SELECT bundle.valid_from <= CURRENT_TIMESTAMP
+        AND (bundle.valid_until IS NULL OR bundle.valid_until > since)
+        AND (
+            bundle.updated_at > since
+            OR bundle.valid_from > since
+            OR bundle.valid_until <= CURRENT_TIMESTAMP
+        );
+$$;
+
+-- Allocate the units of a cart to the active bundles, where `cart_numbers[i]` units of
+-- `cart_products[i]` are bought. Returns how many units of each product are allocated to each
+-- bundle, and the share of the bundle price paid for them.
+--
+-- The allocation is deterministic: bundles are applied in the order they were created, each as
+-- many times as possible before the next. Mix and match takes the most expensive units first, ties
+-- broken by product ID, and is only applied while it is cheaper than the base prices of the units.
+-- The price of a bundle is split between its products in proportion to their base prices, rounded
+-- to whole öre, with the product with the greatest ID paying the remainder.
+--
+-- Units allocated to a bundle are not discounted by special offers.
+CREATE FUNCTION allocate_bundles(
+    cart_products INT[],
+    cart_numbers INT[]
+) RETURNS TABLE (bundle INT, product INT, units INT, price DECIMAL(10, 2))
+LANGUAGE plpgsql STABLE AS $$
+#variable_conflict use_column
+DECLARE
+    remaining INT[] := cart_numbers;
+    applied bundles%ROWTYPE;
+    uses INT;
+    set_price DECIMAL;
+    unit_products INT[];
+    unit_prices DECIMAL[];
+    taken_products INT[];
+    taken_units INT[];
+    taken_values DECIMAL[];
+    total DECIMAL;
+    total_value DECIMAL;
+    allotted DECIMAL;
+    k INT;
+BEGIN
+    FOR applied IN
+        SELECT b.*
+        FROM bundles b
+        WHERE b.id IN (SELECT id FROM active_bundles)
+            AND EXISTS (
+                SELECT 1
+                FROM products p
+                WHERE p.id = ANY(cart_products) AND bundle_includes(b, p)
+            )
+        ORDER BY b.id
+    LOOP
+        IF applied.quantity IS NULL THEN
+            SELECT
+                MIN(COALESCE(remaining[array_position(cart_products, bp.product)], 0) / bp.number),
+                SUM(bp.number * p.price)
+            INTO uses, set_price
+            FROM bundle_products bp
+            JOIN products p ON p.id = bp.product
+            WHERE bp.bundle = applied.id;
+            IF COALESCE(uses, 0) = 0 OR set_price <= applied.price THEN
+                CONTINUE;
+            END IF;
+
+            SELECT
+                ARRAY_AGG(bp.product ORDER BY bp.product),
+                ARRAY_AGG(uses * bp.number ORDER BY bp.product),
+                ARRAY_AGG(uses * bp.number * p.price ORDER BY bp.product)
+            INTO taken_products, taken_units, taken_values
+            FROM bundle_products bp
+            JOIN products p ON p.id = bp.product
+            WHERE bp.bundle = applied.id;
+        ELSE
+            SELECT
+                ARRAY_AGG(u.product ORDER BY u.price DESC, u.product),
+                ARRAY_AGG(u.price ORDER BY u.price DESC, u.product)
+            INTO unit_products, unit_prices
+            FROM (
+                SELECT p.id AS product, p.price
+                FROM UNNEST(cart_products, remaining) c(product, number)
+                JOIN products p ON p.id = c.product
+                CROSS JOIN generate_series(1, c.number)
+                WHERE bundle_includes(applied, p)
+            ) u;
+
+            uses := 0;
+            WHILE (uses + 1) * applied.quantity <= COALESCE(array_length(unit_products, 1), 0) LOOP
+                SELECT SUM(u)
+                INTO set_price
+                FROM UNNEST(
+                    unit_prices[uses * applied.quantity + 1 : (uses + 1) * applied.quantity]
+                ) u;
+                EXIT WHEN set_price <= applied.price;
+                uses := uses + 1;
+            END LOOP;
+            IF uses = 0 THEN
+                CONTINUE;
+            END IF;
+
+            SELECT
+                ARRAY_AGG(t.product ORDER BY t.product),
+                ARRAY_AGG(t.units ORDER BY t.product),
+                ARRAY_AGG(t.value ORDER BY t.product)
+            INTO taken_products, taken_units, taken_values
+            FROM (
+                SELECT u.product, COUNT(*)::INT AS units, SUM(u.price) AS value
+                FROM UNNEST(
+                    unit_products[1 : uses * applied.quantity],
+                    unit_prices[1 : uses * applied.quantity]
+                ) u(product, price)
+                GROUP BY u.product
+            ) t;
+        END IF;
+
+        total := uses * applied.price;
+        SELECT SUM(v)
+        INTO total_value
+        FROM UNNEST(taken_values) v;
+        allotted := 0;
+        FOR k IN 1 .. array_length(taken_products, 1) LOOP
+            bundle := applied.id;
+            product := taken_products[k];
+            units := taken_units[k];
+            price := CASE
+                WHEN k = array_length(taken_products, 1) THEN total - allotted
+                ELSE ROUND(total * taken_values[k] / total_value, 2)
+            END;
+            allotted := allotted + price;
+            remaining[array_position(cart_products, product)] :=
+                remaining[array_position(cart_products, product)] - units;
+            RETURN NEXT;
+        END LOOP;
+    END LOOP;
+END;
+$$;
+
+-- `allocate_bundles` for the shopping cart of a customer.
+CREATE FUNCTION cart_bundles(
+    customer_id customers.id%TYPE
+) RETURNS TABLE (bundle INT, product INT, units INT, price DECIMAL(10, 2))
+LANGUAGE sql STABLE AS $$
+    SELECT a.*
+    FROM (
+        SELECT ARRAY_AGG(sci.product ORDER BY sci.product) AS products,
+            ARRAY_AGG(sci.number ORDER BY sci.product) AS numbers
+        FROM shopping_cart_items sci
+        WHERE sci.customer = customer_id AND sci.product IS NOT NULL AND sci.number > 0
+    ) s
+    CROSS JOIN LATERAL allocate_bundles(s.products, s.numbers) a;
+$$;
+
+-- The bundles applied to an order, copied as they were at the time of purchase as with special
+-- offers. `paid` is included in the `paid` of the order line for the product.
+CREATE TABLE order_bundles (
+    order_id INT NOT NULL REFERENCES orders(id) ON DELETE CASCADE,
+    bundle INT REFERENCES bundles(id) ON DELETE SET NULL,
+    name TEXT NOT NULL,
+    product INT REFERENCES products(id) ON DELETE SET NULL,
+    units POSITIVE_INT NOT NULL,
+    paid TWOPOINT_UDEC NOT NULL
+);
+
+CREATE INDEX order_bundles_by_order ON order_bundles (order_id);
+
+-- Units of the cart are now first allocated to bundles, and special offers only apply to the rest.
+CREATE OR REPLACE FUNCTION checkout(
+    customer_id customers.id%TYPE,
+    -- These are NOT necessarily connected to the contents of the customer's rows in,
+    -- `shopping_cart_items`, though the numbers of those rows are decremented on success.
+    items CHECKOUT_ITEM[],
+    seen_at NONFUTURE_TIMESTAMP,
+    address_id addresses.id%TYPE,
+    -- Exactly one per vendor of the products in `items`.
+    shipments CHECKOUT_SHIPMENT[],
+    -- Null: no promotion code was entered.
+    promotion_use CHECKOUT_PROMOTION
+) RETURNS orders.id%TYPE
+LANGUAGE plpgsql AS $$
+DECLARE
+    new_order orders.id%TYPE;
+    short_product products.id%TYPE;
+    delivery addresses%ROWTYPE;
+    applied promotions%ROWTYPE;
+    order_discount TWOPOINT_UDEC := 0;
+BEGIN
+    IF seen_at IS NULL THEN
+        RAISE EXCEPTION 'Must include time cart was seen.'
+        USING ERRCODE = 'null_value_not_allowed', COLUMN = 'seen_at';
+    END IF;
+
+    CREATE TEMP TABLE cart (
+        product INT PRIMARY KEY,
+        number POSITIVE_INT NOT NULL,
+        special_offer INT,
+        expected_price TWOPOINT_UDEC NOT NULL
+    ) ON COMMIT DROP;
+    INSERT INTO cart
+    SELECT *
+    FROM UNNEST(items);
+
+    -- We allow concurrent updates to membership status as it is only read once.
+    PERFORM 1
+    FROM customers
+    WHERE id = customer_id
+    FOR KEY SHARE;
+    IF NOT FOUND THEN
+        RAISE EXCEPTION 'Customer % does not exist.', customer_id
+        USING ERRCODE = 'no_data_found';
+    END IF;
+
+    SELECT *
+    INTO delivery
+    FROM addresses
+    WHERE id = address_id AND customer = customer_id;
+    IF NOT FOUND THEN
+        RAISE EXCEPTION 'Customer % has no address %.', customer_id, address_id
+        USING ERRCODE = 'no_data_found';
+    END IF;
+
+    IF (SELECT COUNT(*) FROM cart) = 0 THEN
+        RAISE EXCEPTION 'Checkout with no items for customer %.', customer_id
+        USING ERRCODE = 'check_violation', CONSTRAINT = 'nonempty_checkout';
+    ELSIF EXISTS (
+        SELECT 1
+        FROM cart
+        JOIN products ON id = product
+        WHERE NOT visible
+    ) THEN
+        RAISE EXCEPTION 'Cart of customer % contains invisible products.', customer_id
+        USING ERRCODE = 'BP001', CONSTRAINT = 'product_unavailable';
+    END IF;
+
+    -- The stock is decremented below, so the lock is taken up front and in a consistent order to
+    -- avoid deadlocks between concurrent checkouts of the same products.
+    PERFORM 1
+    FROM products p
+    JOIN cart ON id = product
+    ORDER BY id
+    FOR NO KEY UPDATE OF p;
+
+    PERFORM 1
+    FROM special_offers s
+    JOIN cart ON id = special_offer
+    FOR KEY SHARE OF s;
+
+    IF EXISTS (
+        SELECT 1
+        FROM cart
+        JOIN products ON id = product
+        WHERE updated_at > seen_at
+    ) THEN
+        RAISE EXCEPTION 'Stale data: Product has changed.'
+        USING ERRCODE = 'BP001', CONSTRAINT = 'product_changed';
+    ELSIF EXISTS (
+        SELECT 1
+        FROM cart
+        LEFT JOIN active_special_offers aso ON aso.id = special_offer
+        WHERE special_offer IS NOT NULL AND aso.updated_at IS NULL OR aso.updated_at > seen_at
+    ) THEN
+        RAISE EXCEPTION 'Stale data: Special offer has expired.'
+        USING ERRCODE = 'BP001', CONSTRAINT = 'offer_expired';
+    ELSIF EXISTS (
+        SELECT 1
+        FROM cart
+        JOIN active_special_offers aso ON aso.id = special_offer
+        JOIN customers c ON c.id = customer_id
+        WHERE members_only AND NOT member OR aso.updated_at > seen_at
+    ) THEN
+        RAISE EXCEPTION 'Stale data: Customer (%) is not eligible.', customer_id
+        USING ERRCODE = 'BP001', CONSTRAINT = 'not_eligible';
+    ELSIF EXISTS (
+        SELECT 1
+        FROM cart
+        JOIN products p ON p.id = product
+        JOIN bundles b ON bundle_includes(b, p)
+        WHERE bundle_changed(b, seen_at)
+    ) THEN
+        RAISE EXCEPTION 'Stale data: Bundle has started, ended or changed.'
+        USING ERRCODE = 'BP001', CONSTRAINT = 'bundle_changed';
+    END IF;
+
+    -- Shipping methods are locked so that their fees can't change before the order is placed.
+    PERFORM 1
+    FROM shipping_methods m
+    JOIN UNNEST(shipments) s ON s.shipping_method = m.id
+    ORDER BY id
+    FOR SHARE OF m;
+
+    CREATE TEMP TABLE shipping
+    ON COMMIT DROP AS
+    SELECT s.shipping_method, s.expected_fee, m.vendor, m.name, shipping_fee(m, weight) AS fee
+    FROM UNNEST(shipments) s
+    LEFT JOIN shipping_methods m ON m.id = s.shipping_method
+    LEFT JOIN LATERAL (
+        SELECT COALESCE(SUM(cart.number * grams(amount_per_unit, measurement_unit)), 0) AS weight
+        FROM cart
+        JOIN products p ON p.id = product
+        WHERE p.vendor = m.vendor
+    ) w ON TRUE;
+
+    IF EXISTS (
+        SELECT 1
+        FROM shipping
+        WHERE vendor IS NULL
+    ) THEN
+        RAISE EXCEPTION 'Stale data: Shipping method has been removed.'
+        USING ERRCODE = 'BP001', CONSTRAINT = 'shipping_changed';
+    ELSIF (SELECT COUNT(*) FROM shipping) != (SELECT COUNT(DISTINCT vendor) FROM shipping)
+        OR EXISTS (
+            SELECT p.vendor
+            FROM cart
+            JOIN products p ON p.id = product
+            EXCEPT
+            SELECT vendor
+            FROM shipping
+        ) OR EXISTS (
+            SELECT vendor
+            FROM shipping
+            EXCEPT
+            SELECT p.vendor
+            FROM cart
+            JOIN products p ON p.id = product
+        )
+    THEN
+        RAISE EXCEPTION 'Checkout must have one shipping method per vendor.'
+        USING ERRCODE = 'check_violation', CONSTRAINT = 'one_shipment_per_vendor';
+    ELSIF EXISTS (
+        SELECT 1
+        FROM shipping
+        WHERE fee != expected_fee
+    ) THEN
+        RAISE EXCEPTION 'Stale data: Shipping fee has changed.'
+        USING ERRCODE = 'BP001', CONSTRAINT = 'shipping_changed';
+    END IF;
+
+    -- Insert zeros to prevent other calls from double-counting, and do dummy update on existing
+    -- rows to lock them.
+    INSERT INTO special_offer_uses (special_offer, customer, number)
+    SELECT special_offer, customer_id, 0
+    FROM cart
+    WHERE special_offer IS NOT NULL
+    ON CONFLICT (special_offer, customer) DO UPDATE
+    SET number = special_offer_uses.number;
+
+    CREATE TEMP TABLE bundled
+    ON COMMIT DROP AS
+    SELECT a.*, b.name
+    FROM allocate_bundles(
+        ARRAY(SELECT product FROM cart ORDER BY product),
+        ARRAY(SELECT number FROM cart ORDER BY product)
+    ) a
+    JOIN bundles b ON b.id = a.bundle;
+
+    CREATE TEMP TABLE results
+    ON COMMIT DROP AS
+    SELECT
+        cart.*, p.price AS unit_price, aso.new_price, aso.quantity1, aso.quantity2,
+        COALESCE(bl.price, 0) + calc.price AS price, calc.uses
+    FROM cart
+    JOIN products p ON p.id = product
+    LEFT JOIN (
+        SELECT bundled.product, SUM(units)::INT AS units, SUM(price) AS price
+        FROM bundled
+        GROUP BY bundled.product
+    ) bl ON bl.product = cart.product
+    LEFT JOIN active_special_offers aso ON aso.id = special_offer
+    LEFT JOIN special_offer_uses sou ON sou.special_offer = cart.special_offer AND customer = customer_id
+    CROSS JOIN LATERAL calculate_price(
+        p.price, cart.number - COALESCE(bl.units, 0), new_price, quantity1, quantity2,
+        CASE
+            -- Unlimited: the offer can at most be used once per unit.
+            WHEN limit_per_customer IS NULL THEN cart.number
+            ELSE GREATEST(limit_per_customer - COALESCE(sou.number, 0), 0)
+        END
+    ) AS calc;
+
+    IF EXISTS (
+        SELECT 1
+        FROM results
+        WHERE price != expected_price
+    ) THEN
+        RAISE EXCEPTION 'Stale data: Special offer has been used enough times to create a price discrepancy.'
+        USING ERRCODE = 'BP001', CONSTRAINT = 'offer_used_up';
+    END IF;
+
+    IF promotion_use IS NOT NULL THEN
+        -- Locked so that concurrent checkouts with the same code count each other's uses.
+        SELECT *
+        INTO applied
+        FROM promotions
+        WHERE id = (promotion_use).promotion
+        FOR NO KEY UPDATE;
+        IF NOT FOUND
+            OR applied.updated_at > seen_at
+            OR applied.valid_from > CURRENT_TIMESTAMP
+            OR applied.valid_until <= CURRENT_TIMESTAMP
+        THEN
+            RAISE EXCEPTION 'Stale data: Promotion has expired or changed.'
+            USING ERRCODE = 'BP001', CONSTRAINT = 'promotion_changed';
+        ELSIF applied.max_uses <= (
+            SELECT COALESCE(SUM(number), 0)
+            FROM promotion_uses
+            WHERE promotion_uses.promotion = applied.id
+        ) OR applied.limit_per_customer <= (
+            SELECT COALESCE(SUM(number), 0)
+            FROM promotion_uses
+            WHERE promotion_uses.promotion = applied.id AND customer = customer_id
+        ) THEN
+            RAISE EXCEPTION 'Stale data: Promotion has been used up.'
+            USING ERRCODE = 'BP001', CONSTRAINT = 'promotion_used_up';
+        END IF;
+
+        order_discount := promotion_discount(
+            applied,
+            (SELECT SUM(price) FROM results),
+            (SELECT SUM(fee) FROM shipping)
+        );
+        IF order_discount IS NULL THEN
+            RAISE EXCEPTION 'Order does not reach the minimum spend of promotion %.', applied.id
+            USING ERRCODE = 'check_violation', CONSTRAINT = 'minimum_spend';
+        ELSIF order_discount != (promotion_use).expected_discount THEN
+            RAISE EXCEPTION 'Stale data: Promotion gives a different discount.'
+            USING ERRCODE = 'BP001', CONSTRAINT = 'promotion_changed';
+        END IF;
+    END IF;
+
+    SELECT id
+    INTO short_product
+    FROM cart
+    JOIN products ON id = product
+    WHERE in_stock < number
+    ORDER BY id
+    LIMIT 1;
+    IF FOUND THEN
+        RAISE EXCEPTION 'Product % does not have enough stock.', short_product
+        USING ERRCODE = 'BP002', DETAIL = short_product::TEXT;
+    END IF;
+
+    UPDATE products
+    SET in_stock = in_stock - number
+    FROM cart
+    WHERE id = product;
+
+    PERFORM sale_remove_expiries(product, number)
+    FROM cart;
+
+    UPDATE shopping_cart_items
+    SET number = GREATEST(shopping_cart_items.number - r.number, 0)
+    FROM results r
+    WHERE shopping_cart_items.product = r.product AND customer = customer_id;
+    DELETE FROM shopping_cart_items
+    WHERE customer = customer_id AND number = 0;
+
+    UPDATE special_offer_uses
+    SET number = special_offer_uses.number + r.uses
+    FROM results r
+    WHERE r.special_offer = special_offer_uses.special_offer AND customer = customer_id AND uses > 0;
+
+    IF promotion_use IS NOT NULL THEN
+        INSERT INTO promotion_uses (promotion, customer, number)
+        VALUES (applied.id, customer_id, 1)
+        ON CONFLICT (promotion, customer) DO UPDATE
+        SET number = promotion_uses.number + 1;
+    END IF;
+
+    INSERT INTO orders (
+        customer, total, recipient, street, postal_code, city, country, promotion, promotion_code,
+        discount
+    )
+    SELECT
+        customer_id, SUM(price), delivery.recipient, delivery.street, delivery.postal_code,
+        delivery.city, delivery.country, applied.id, applied.code, order_discount
+    FROM results
+    RETURNING id INTO new_order;
+
+    INSERT INTO order_lines (
+        order_id, product, number, unit_price, special_offer, new_price, quantity1, quantity2,
+        special_offer_uses, paid
+    )
+    SELECT
+        new_order, product, number, unit_price,
+        CASE WHEN uses > 0 THEN special_offer END,
+        CASE WHEN uses > 0 THEN new_price END,
+        CASE WHEN uses > 0 THEN quantity1 END,
+        CASE WHEN uses > 0 THEN quantity2 END,
+        uses, price
+    FROM results;
+
+    INSERT INTO order_bundles (order_id, bundle, name, product, units, paid)
+    SELECT new_order, bundle, name, product, units, price
+    FROM bundled;
+
+    INSERT INTO order_shipments (order_id, vendor, shipping_method, method_name, fee)
+    SELECT new_order, vendor, shipping_method, name, fee
+    FROM shipping;
+
+    RETURN new_order;
+END;
+$$;
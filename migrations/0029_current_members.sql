@@ -0,0 +1,395 @@
+-- Whether `customer` is a member today. A paid membership ends on `member_until`, which
+-- `process_membership_expiries` only clears at midnight, so `member` alone may still be true after
+-- it has ended.
+CREATE FUNCTION current_member(customer customers) RETURNS BOOLEAN
+LANGUAGE sql STABLE AS $$
+    SELECT customer.member
+        AND (customer.member_until IS NULL OR customer.member_until > CURRENT_DATE)
+$$;
+
+CREATE OR REPLACE FUNCTION best_special_offer(
+    product_id products.id%TYPE,
+    -- Null: not logged in, so neither a member nor having used any offer.
+    customer_id customers.id%TYPE,
+    -- Null: no number of units is known, so offers are ranked by average discount only. Qualified
+    -- with the function name below, as `special_offer_uses` has a column of the same name.
+    number INT
+) RETURNS SETOF special_offers
+LANGUAGE sql STABLE AS $$
+    SELECT aso.*
+    FROM active_special_offers aso
+    JOIN products p ON p.id = aso.product
+    LEFT JOIN customers c ON c.id = customer_id
+    LEFT JOIN special_offer_uses sou ON sou.special_offer = aso.id AND sou.customer = customer_id
+    CROSS JOIN LATERAL (
+        -- Null: unlimited.
+        SELECT CASE
+            WHEN aso.limit_per_customer IS NOT NULL
+            THEN GREATEST(aso.limit_per_customer - COALESCE(sou.number, 0), 0)
+        END AS remaining_uses
+    ) r
+    WHERE aso.product = product_id
+    ORDER BY
+        aso.members_only AND NOT COALESCE(current_member(c), FALSE),
+        CASE WHEN best_special_offer.number IS NOT NULL THEN (
+            calculate_price(
+                tier_price(p.id, best_special_offer.number), best_special_offer.number,
+                aso.new_price, aso.quantity1, aso.quantity2,
+                COALESCE(r.remaining_uses, best_special_offer.number)
+            )
+        ).price END,
+        COALESCE(r.remaining_uses = 0, FALSE),
+        average_discount(p.price, aso.new_price, aso.quantity1, aso.quantity2) DESC,
+        aso.id
+    LIMIT 1
+$$;
+
+CREATE OR REPLACE FUNCTION checkout(
+    customer_id customers.id%TYPE,
+    -- These are NOT necessarily connected to the contents of the customer's rows in,
+    -- `shopping_cart_items`, though the numbers of those rows are decremented on success.
+    items CHECKOUT_ITEM[],
+    seen_at NONFUTURE_TIMESTAMP,
+    address_id addresses.id%TYPE,
+    -- Exactly one per vendor of the products in `items`.
+    shipments CHECKOUT_SHIPMENT[],
+    -- Null: no promotion code was entered.
+    promotion_use CHECKOUT_PROMOTION
+) RETURNS orders.id%TYPE
+LANGUAGE plpgsql AS $$
+DECLARE
+    new_order orders.id%TYPE;
+    short_product products.id%TYPE;
+    delivery addresses%ROWTYPE;
+    applied promotions%ROWTYPE;
+    order_discount TWOPOINT_UDEC := 0;
+BEGIN
+    IF seen_at IS NULL THEN
+        RAISE EXCEPTION 'Must include time cart was seen.'
+        USING ERRCODE = 'null_value_not_allowed', COLUMN = 'seen_at';
+    END IF;
+
+    CREATE TEMP TABLE cart (
+        product INT PRIMARY KEY,
+        number POSITIVE_INT NOT NULL,
+        special_offer INT,
+        expected_price TWOPOINT_UDEC NOT NULL
+    ) ON COMMIT DROP;
+    INSERT INTO cart
+    SELECT *
+    FROM UNNEST(items);
+
+    -- We allow concurrent updates to membership status as it is only read once.
+    PERFORM 1
+    FROM customers
+    WHERE id = customer_id
+    FOR KEY SHARE;
+    IF NOT FOUND THEN
+        RAISE EXCEPTION 'Customer % does not exist.', customer_id
+        USING ERRCODE = 'no_data_found';
+    END IF;
+
+    SELECT *
+    INTO delivery
+    FROM addresses
+    WHERE id = address_id AND customer = customer_id;
+    IF NOT FOUND THEN
+        RAISE EXCEPTION 'Customer % has no address %.', customer_id, address_id
+        USING ERRCODE = 'no_data_found';
+    END IF;
+
+    IF (SELECT COUNT(*) FROM cart) = 0 THEN
+        RAISE EXCEPTION 'Checkout with no items for customer %.', customer_id
+        USING ERRCODE = 'check_violation', CONSTRAINT = 'nonempty_checkout';
+    ELSIF EXISTS (
+        SELECT 1
+        FROM cart
+        JOIN products ON id = product
+        WHERE NOT visible
+    ) THEN
+        RAISE EXCEPTION 'Cart of customer % contains invisible products.', customer_id
+        USING ERRCODE = 'BP001', CONSTRAINT = 'product_unavailable';
+    END IF;
+
+    -- The stock is decremented below, so the lock is taken up front and in a consistent order to
+    -- avoid deadlocks between concurrent checkouts of the same products.
+    PERFORM 1
+    FROM products p
+    JOIN cart ON id = product
+    ORDER BY id
+    FOR NO KEY UPDATE OF p;
+
+    PERFORM 1
+    FROM special_offers s
+    JOIN cart ON id = special_offer
+    FOR KEY SHARE OF s;
+
+    IF EXISTS (
+        SELECT 1
+        FROM cart
+        JOIN products ON id = product
+        WHERE updated_at > seen_at
+    ) THEN
+        RAISE EXCEPTION 'Stale data: Product has changed.'
+        USING ERRCODE = 'BP001', CONSTRAINT = 'product_changed';
+    ELSIF EXISTS (
+        SELECT 1
+        FROM cart
+        LEFT JOIN active_special_offers aso
+            ON aso.id = special_offer AND aso.product = cart.product
+        WHERE special_offer IS NOT NULL AND (aso.updated_at IS NULL OR aso.updated_at > seen_at)
+    ) THEN
+        RAISE EXCEPTION 'Stale data: Special offer has expired.'
+        USING ERRCODE = 'BP001', CONSTRAINT = 'offer_expired';
+    ELSIF EXISTS (
+        SELECT 1
+        FROM cart
+        JOIN active_special_offers aso ON aso.id = special_offer AND aso.product = cart.product
+        JOIN customers c ON c.id = customer_id
+        WHERE members_only AND NOT current_member(c)
+    ) THEN
+        RAISE EXCEPTION 'Stale data: Customer (%) is not eligible.', customer_id
+        USING ERRCODE = 'BP001', CONSTRAINT = 'not_eligible';
+    ELSIF EXISTS (
+        SELECT 1
+        FROM cart
+        WHERE special_offer IS NOT NULL AND special_offer IS DISTINCT FROM (
+            SELECT id
+            FROM best_special_offer(product, customer_id, number)
+        )
+    ) THEN
+        -- As in `stale_lines`, once used up the offer may have been replaced by the next best one.
+        RAISE EXCEPTION 'Stale data: Special offer is not the best one for the customer.'
+        USING ERRCODE = 'BP001', CONSTRAINT = 'offer_used_up';
+    ELSIF EXISTS (
+        SELECT 1
+        FROM cart
+        JOIN products p ON p.id = product
+        JOIN bundles b ON bundle_includes(b, p)
+        WHERE bundle_changed(b, seen_at)
+    ) THEN
+        RAISE EXCEPTION 'Stale data: Bundle has started, ended or changed.'
+        USING ERRCODE = 'BP001', CONSTRAINT = 'bundle_changed';
+    END IF;
+
+    -- Shipping methods are locked so that their fees can't change before the order is placed.
+    PERFORM 1
+    FROM shipping_methods m
+    JOIN UNNEST(shipments) s ON s.shipping_method = m.id
+    ORDER BY id
+    FOR SHARE OF m;
+
+    CREATE TEMP TABLE shipping
+    ON COMMIT DROP AS
+    SELECT s.shipping_method, s.expected_fee, m.vendor, m.name, shipping_fee(m, weight) AS fee
+    FROM UNNEST(shipments) s
+    LEFT JOIN shipping_methods m ON m.id = s.shipping_method
+    LEFT JOIN LATERAL (
+        SELECT COALESCE(SUM(cart.number * grams(amount_per_unit, measurement_unit)), 0) AS weight
+        FROM cart
+        JOIN products p ON p.id = product
+        WHERE p.vendor = m.vendor
+    ) w ON TRUE;
+
+    IF EXISTS (
+        SELECT 1
+        FROM shipping
+        WHERE vendor IS NULL
+    ) THEN
+        RAISE EXCEPTION 'Stale data: Shipping method has been removed.'
+        USING ERRCODE = 'BP001', CONSTRAINT = 'shipping_changed';
+    ELSIF (SELECT COUNT(*) FROM shipping) != (SELECT COUNT(DISTINCT vendor) FROM shipping)
+        OR EXISTS (
+            SELECT p.vendor
+            FROM cart
+            JOIN products p ON p.id = product
+            EXCEPT
+            SELECT vendor
+            FROM shipping
+        ) OR EXISTS (
+            SELECT vendor
+            FROM shipping
+            EXCEPT
+            SELECT p.vendor
+            FROM cart
+            JOIN products p ON p.id = product
+        )
+    THEN
+        RAISE EXCEPTION 'Checkout must have one shipping method per vendor.'
+        USING ERRCODE = 'check_violation', CONSTRAINT = 'one_shipment_per_vendor';
+    ELSIF EXISTS (
+        SELECT 1
+        FROM shipping
+        WHERE fee != expected_fee
+    ) THEN
+        RAISE EXCEPTION 'Stale data: Shipping fee has changed.'
+        USING ERRCODE = 'BP001', CONSTRAINT = 'shipping_changed';
+    END IF;
+
+    -- Insert zeros to prevent other calls from double-counting, and do dummy update on existing
+    -- rows to lock them.
+    INSERT INTO special_offer_uses (special_offer, customer, number)
+    SELECT special_offer, customer_id, 0
+    FROM cart
+    WHERE special_offer IS NOT NULL
+    ON CONFLICT (special_offer, customer) DO UPDATE
+    SET number = special_offer_uses.number;
+
+    CREATE TEMP TABLE bundled
+    ON COMMIT DROP AS
+    SELECT a.*, b.name
+    FROM allocate_bundles(
+        ARRAY(SELECT product FROM cart ORDER BY product),
+        ARRAY(SELECT number FROM cart ORDER BY product)
+    ) a
+    JOIN bundles b ON b.id = a.bundle;
+
+    CREATE TEMP TABLE results
+    ON COMMIT DROP AS
+    SELECT
+        cart.*, unit_price, aso.new_price, aso.quantity1, aso.quantity2,
+        COALESCE(bl.price, 0) + calc.price AS price, calc.uses
+    FROM cart
+    JOIN products p ON p.id = product
+    -- Recorded as the unit price of the order line, the tier being a price rather than a deal.
+    CROSS JOIN LATERAL tier_price(p.id, cart.number) AS unit_price
+    LEFT JOIN (
+        SELECT bundled.product, SUM(units)::INT AS units, SUM(price) AS price
+        FROM bundled
+        GROUP BY bundled.product
+    ) bl ON bl.product = cart.product
+    LEFT JOIN active_special_offers aso ON aso.id = special_offer AND aso.product = cart.product
+    LEFT JOIN special_offer_uses sou ON sou.special_offer = cart.special_offer AND customer = customer_id
+    CROSS JOIN LATERAL calculate_price(
+        unit_price, cart.number - COALESCE(bl.units, 0),
+        new_price, quantity1, quantity2,
+        CASE
+            -- Unlimited: the offer can at most be used once per unit.
+            WHEN limit_per_customer IS NULL THEN cart.number
+            ELSE GREATEST(limit_per_customer - COALESCE(sou.number, 0), 0)
+        END
+    ) AS calc;
+
+    IF EXISTS (
+        SELECT 1
+        FROM results
+        WHERE price != expected_price
+    ) THEN
+        RAISE EXCEPTION 'Stale data: Special offer has been used enough times to create a price discrepancy.'
+        USING ERRCODE = 'BP001', CONSTRAINT = 'offer_used_up';
+    END IF;
+
+    IF promotion_use IS NOT NULL THEN
+        -- Locked so that concurrent checkouts with the same code count each other's uses.
+        SELECT *
+        INTO applied
+        FROM promotions
+        WHERE id = (promotion_use).promotion
+        FOR NO KEY UPDATE;
+        IF NOT FOUND
+            OR applied.updated_at > seen_at
+            OR applied.valid_from > CURRENT_TIMESTAMP
+            OR applied.valid_until <= CURRENT_TIMESTAMP
+        THEN
+            RAISE EXCEPTION 'Stale data: Promotion has expired or changed.'
+            USING ERRCODE = 'BP001', CONSTRAINT = 'promotion_changed';
+        ELSIF applied.max_uses <= (
+            SELECT COALESCE(SUM(number), 0)
+            FROM promotion_uses
+            WHERE promotion_uses.promotion = applied.id
+        ) OR applied.limit_per_customer <= (
+            SELECT COALESCE(SUM(number), 0)
+            FROM promotion_uses
+            WHERE promotion_uses.promotion = applied.id AND customer = customer_id
+        ) THEN
+            RAISE EXCEPTION 'Stale data: Promotion has been used up.'
+            USING ERRCODE = 'BP001', CONSTRAINT = 'promotion_used_up';
+        END IF;
+
+        order_discount := promotion_discount(
+            applied,
+            (SELECT SUM(price) FROM results),
+            (SELECT SUM(fee) FROM shipping)
+        );
+        IF order_discount IS NULL THEN
+            RAISE EXCEPTION 'Order does not reach the minimum spend of promotion %.', applied.id
+            USING ERRCODE = 'check_violation', CONSTRAINT = 'minimum_spend';
+        ELSIF order_discount != (promotion_use).expected_discount THEN
+            RAISE EXCEPTION 'Stale data: Promotion gives a different discount.'
+            USING ERRCODE = 'BP001', CONSTRAINT = 'promotion_changed';
+        END IF;
+    END IF;
+
+    SELECT id
+    INTO short_product
+    FROM cart
+    JOIN products ON id = product
+    WHERE in_stock < number
+    ORDER BY id
+    LIMIT 1;
+    IF FOUND THEN
+        RAISE EXCEPTION 'Product % does not have enough stock.', short_product
+        USING ERRCODE = 'BP002', DETAIL = short_product::TEXT;
+    END IF;
+
+    UPDATE products
+    SET in_stock = in_stock - number
+    FROM cart
+    WHERE id = product;
+
+    PERFORM sale_remove_expiries(product, number)
+    FROM cart;
+
+    UPDATE shopping_cart_items
+    SET number = GREATEST(shopping_cart_items.number - r.number, 0)
+    FROM results r
+    WHERE shopping_cart_items.product = r.product AND customer = customer_id;
+    DELETE FROM shopping_cart_items
+    WHERE customer = customer_id AND number = 0;
+
+    UPDATE special_offer_uses
+    SET number = special_offer_uses.number + r.uses
+    FROM results r
+    WHERE r.special_offer = special_offer_uses.special_offer AND customer = customer_id AND uses > 0;
+
+    IF promotion_use IS NOT NULL THEN
+        INSERT INTO promotion_uses (promotion, customer, number)
+        VALUES (applied.id, customer_id, 1)
+        ON CONFLICT (promotion, customer) DO UPDATE
+        SET number = promotion_uses.number + 1;
+    END IF;
+
+    INSERT INTO orders (
+        customer, total, recipient, street, postal_code, city, country, promotion, promotion_code,
+        discount
+    )
+    SELECT
+        customer_id, SUM(price), delivery.recipient, delivery.street, delivery.postal_code,
+        delivery.city, delivery.country, applied.id, applied.code, order_discount
+    FROM results
+    RETURNING id INTO new_order;
+
+    INSERT INTO order_lines (
+        order_id, product, number, unit_price, special_offer, new_price, quantity1, quantity2,
+        special_offer_uses, paid
+    )
+    SELECT
+        new_order, product, number, unit_price,
+        CASE WHEN uses > 0 THEN special_offer END,
+        CASE WHEN uses > 0 THEN new_price END,
+        CASE WHEN uses > 0 THEN quantity1 END,
+        CASE WHEN uses > 0 THEN quantity2 END,
+        uses, price
+    FROM results;
+
+    INSERT INTO order_bundles (order_id, bundle, name, product, units, paid)
+    SELECT new_order, bundle, name, product, units, price
+    FROM bundled;
+
+    INSERT INTO order_shipments (order_id, vendor, shipping_method, method_name, fee)
+    SELECT new_order, vendor, shipping_method, name, fee
+    FROM shipping;
+
+    RETURN new_order;
+END;
+$$;
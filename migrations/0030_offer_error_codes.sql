@@ -0,0 +1,64 @@
+-- Invalid deals are reported as check violations, so that they can be told apart from other
+-- errors, as for price tiers. They are checked both when setting the deal of a special offer and
+-- when setting the price of its product.
+CREATE OR REPLACE FUNCTION average_discount(
+    base_price products.price%TYPE,
+    new_price special_offers.new_price%TYPE,
+    quantity1 special_offers.quantity1%TYPE,
+    quantity2 special_offers.quantity2%TYPE
+) RETURNS TWOPOINT_UDEC
+LANGUAGE plpgsql IMMUTABLE PARALLEL SAFE AS $$
+DECLARE
+    discount TWOPOINT_UDEC;
+BEGIN
+    -- No special offer.
+    IF new_price IS NULL AND quantity1 IS NULL AND quantity2 IS NULL THEN
+        RETURN NULL;
+    -- Variant 1.
+    ELSIF new_price IS NOT NULL AND quantity1 IS NULL AND quantity2 IS NULL THEN
+        IF new_price >= base_price THEN
+            RAISE EXCEPTION 'New price (%) is not less than base price (%).', new_price, base_price
+            USING ERRCODE = 'check_violation', CONSTRAINT = 'special_offers_discount';
+        END IF;
+
+        IF base_price = 0 THEN
+            discount := 1;
+        ELSE
+            discount := 1 - new_price / base_price;
+        END IF;
+    -- Variant 2.
+    ELSIF new_price IS NULL AND quantity1 IS NOT NULL AND quantity2 IS NOT NULL THEN
+        IF quantity1 <= 1 THEN
+            RAISE EXCEPTION 'Must be asked to take more than 1 (found %).', quantity1
+            USING ERRCODE = 'check_violation', CONSTRAINT = 'special_offers_discount';
+        ELSIF quantity2 < 1 THEN
+            RAISE EXCEPTION 'Must be asked to pay for at least 1 (found 0).'
+            USING ERRCODE = 'check_violation', CONSTRAINT = 'special_offers_discount';
+        ELSIF quantity1 <= quantity2 THEN
+            RAISE EXCEPTION 'Must be asked to pay for less than taken (found % for the price of %).', quantity1, quantity2
+            USING ERRCODE = 'check_violation', CONSTRAINT = 'special_offers_discount';
+        END IF;
+        discount := 1 - quantity2::TWOPOINT_UDEC / quantity1::TWOPOINT_UDEC;
+    -- Variant 3.
+    ELSIF new_price IS NOT NULL AND quantity1 IS NOT NULL AND quantity2 IS NULL THEN
+        IF quantity1 <= 1 THEN
+            RAISE EXCEPTION 'Must be asked to take more than 1 (found %).', quantity1
+            USING ERRCODE = 'check_violation', CONSTRAINT = 'special_offers_discount';
+        ELSIF new_price >= base_price * quantity1 THEN
+            RAISE EXCEPTION 'Must be asked to pay less in bulk (found % for %).', quantity1, new_price
+            USING ERRCODE = 'check_violation', CONSTRAINT = 'special_offers_discount';
+        END IF;
+
+        IF base_price = 0 THEN
+            discount := 1;
+        ELSE
+            discount := 1 - new_price / (base_price * quantity1);
+        END IF;
+    ELSE
+        RAISE EXCEPTION 'Invalid variant.'
+        USING ERRCODE = 'check_violation', CONSTRAINT = 'special_offers_variant';
+    END IF;
+
+    RETURN discount;
+END;
+$$;
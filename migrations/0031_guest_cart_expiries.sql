@@ -0,0 +1,61 @@
+-- Delete guest carts that have expired or have no items left, such as carts emptied by merging
+-- them before `merge_guest_cart` deleted them.
+CREATE FUNCTION process_guest_cart_expiries() RETURNS TABLE (
+    cart INT
+) LANGUAGE sql AS $$
+    DELETE FROM guest_carts gc
+    WHERE gc.expires_at <= CURRENT_TIMESTAMP OR NOT EXISTS (
+        SELECT 1
+        FROM guest_cart_items gci
+        WHERE gci.cart = gc.id
+    )
+    RETURNING gc.id
+$$;
+
+-- As before, but the merged guest cart is deleted along with its items.
+CREATE OR REPLACE PROCEDURE merge_guest_cart(
+    customer_id customers.id%TYPE,
+    cart_token_hash guest_carts.token_hash%TYPE,
+    policy CART_MERGE_POLICY
+) LANGUAGE sql AS $$
+    INSERT INTO shopping_cart_items (customer, product, number, updated_at)
+    SELECT customer_id, product, number, gci.updated_at
+    FROM active_guest_carts gc
+    JOIN guest_cart_items gci ON gci.cart = gc.id
+    WHERE gc.token_hash = cart_token_hash
+    ON CONFLICT (customer, product) WHERE product IS NOT NULL DO UPDATE
+    SET number = CASE policy
+        WHEN 'sum' THEN shopping_cart_items.number + EXCLUDED.number
+        WHEN 'max' THEN GREATEST(shopping_cart_items.number, EXCLUDED.number)
+        WHEN 'prefer_latest' THEN CASE
+            WHEN EXCLUDED.updated_at > shopping_cart_items.updated_at THEN EXCLUDED.number
+            ELSE shopping_cart_items.number
+        END
+    END;
+
+    DELETE FROM guest_carts
+    WHERE token_hash = cart_token_hash;
+$$;
+
+-- WARN: As with `process_expiries`, this only runs at midnight, so it must also be called on
+-- establishing a connection to the database.
+DO $do$
+BEGIN
+    IF EXISTS (SELECT 1 FROM pg_available_extensions WHERE name = 'pg_cron') THEN
+        CREATE EXTENSION IF NOT EXISTS pg_cron;
+        PERFORM cron.schedule(
+            'process_daily_guest_cart_expiries',
+            -- Daily at midnight.
+            '0 0 * * *',
+            $$
+            SELECT process_guest_cart_expiries();
+            $$
+        );
+    ELSE
+        RAISE NOTICE 'pg_cron is not available, guest carts will not be deleted automatically.';
+    END IF;
+EXCEPTION
+    WHEN OTHERS THEN
+        RAISE NOTICE 'Failed to schedule deletion of guest carts: %', SQLERRM;
+END;
+$do$;
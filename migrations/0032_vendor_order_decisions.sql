@@ -0,0 +1,26 @@
+-- The status each vendor has asked an order to be set to. Vendors only set the status of an order
+-- once every vendor selling one of the ordered products has asked for the same one, so that no
+-- vendor can ship, take back or refund the products of another. Decisions apply to the current
+-- status of the order only.
+CREATE TABLE order_vendor_decisions (
+    order_id INT NOT NULL REFERENCES orders(id) ON DELETE CASCADE,
+    vendor INT NOT NULL REFERENCES vendors(id) ON DELETE CASCADE,
+    status ORDER_STATUS NOT NULL,
+    PRIMARY KEY (order_id, vendor)
+);
+
+CREATE FUNCTION clear_order_vendor_decisions() RETURNS TRIGGER
+LANGUAGE plpgsql AS $$
+BEGIN
+    DELETE FROM order_vendor_decisions
+    WHERE order_id = NEW.id;
+
+    RETURN NULL;
+END;
+$$;
+
+CREATE TRIGGER clear_order_vendor_decisions
+AFTER UPDATE OF status ON orders
+FOR EACH ROW
+WHEN (OLD.status IS DISTINCT FROM NEW.status)
+EXECUTE FUNCTION clear_order_vendor_decisions();
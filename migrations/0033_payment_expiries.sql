@@ -0,0 +1,49 @@
+CREATE INDEX orders_awaiting_payment_by_time ON orders (placed_at)
+WHERE status = 'awaiting_payment';
+
+-- Fail the payment of orders that have been awaiting it for too long, e.g. because the request
+-- placing them failed before the payment was authorized. Their stock and any uses of special offers
+-- or promotion codes are then released, and their products put back in the customer's cart.
+CREATE FUNCTION process_payment_expiries() RETURNS TABLE (
+    order_id INT
+) LANGUAGE plpgsql AS $$
+DECLARE
+    expired orders.id%TYPE;
+BEGIN
+    FOR expired IN
+        SELECT id
+        FROM orders
+        WHERE status = 'awaiting_payment' AND placed_at <= CURRENT_TIMESTAMP - INTERVAL '30 minutes'
+        ORDER BY id
+        -- Orders being paid for right now are left to the request paying for them.
+        FOR UPDATE SKIP LOCKED
+    LOOP
+        CALL fail_payment(expired);
+        order_id := expired;
+        RETURN NEXT;
+    END LOOP;
+END;
+$$;
+
+-- WARN: As with `process_expiries`, runs are missed while the database is down, so this must also
+-- be called on establishing a connection to the database.
+DO $do$
+BEGIN
+    IF EXISTS (SELECT 1 FROM pg_available_extensions WHERE name = 'pg_cron') THEN
+        CREATE EXTENSION IF NOT EXISTS pg_cron;
+        PERFORM cron.schedule(
+            'process_payment_expiries',
+            -- Every five minutes.
+            '*/5 * * * *',
+            $$
+            SELECT process_payment_expiries();
+            $$
+        );
+    ELSE
+        RAISE NOTICE 'pg_cron is not available, unpaid orders will not be cancelled automatically.';
+    END IF;
+EXCEPTION
+    WHEN OTHERS THEN
+        RAISE NOTICE 'Failed to schedule cancellation of unpaid orders: %', SQLERRM;
+END;
+$do$;
@@ -40,6 +40,24 @@ rsx! {
                     }
                 }
 
+                if let LoginId::Administrator(_) = user.id {
+                    Link {
+                        to: Route::Admin {},
+                        class: "flex items-center gap-3 px-3 py-2 rounded-xl hover:bg-gray-50 text-sm text-gray-700 transition",
+                        onclick: move |_| on_close.call(()),
+                        i { class: "fa-solid fa-user-shield w-4" }
+                        "Administration"
+                    }
+                }
+
+                Link {
+                    to: Route::ChangePassword {},
+                    class: "flex items-center gap-3 px-3 py-2 rounded-xl hover:bg-gray-50 text-sm text-gray-700 transition",
+                    onclick: move |_| on_close.call(()),
+                    i { class: "fa-solid fa-key w-4" }
+                    "Byt lösenord"
+                }
+
                 div { class: "border-t my-2" }
                 button {
                     class: "flex items-center gap-3 px-3 py-2 rounded-xl hover:bg-red-50 text-sm text-red-600 transition w-full",
@@ -50,6 +68,7 @@ rsx! {
                         let _task = spawn(async move {
                             drop(log_out().await);
                             gs.write().login = None;
+                            gs.write().cart.clear();
                             on_close.call(());
                             let _unused = nav.push(Route::Home {});
                         });
@@ -1,5 +1,5 @@
 use crate::Route;
-use crate::state::GlobalState;
+use crate::state::{save_cart_item, GlobalState};
 use dioxus::prelude::*;
  
 /// Kundvagns-dropdown som visas när man klickar på kundvagnsknappen.
@@ -60,20 +60,8 @@ pub fn CartDropdown(on_close: EventHandler<()>) -> Element {
                                         move |_| {
                                             let new_qty = qty.saturating_sub(1);
                                             global_state.write().set_quantity(id, new_qty);
-                                            if let Some(cid) = customer_id {
-                                                #[allow(unused_results)]
-                                                spawn(async move {
-                                                    use crate::database::{Id, Product};
-                                                    drop(
-                                                        crate::database::cart::set_in_shopping_cart(
-                                                                cid,
-                                                                Id::<Product>::from(id),
-                                                                new_qty,
-                                                            )
-                                                            .await,
-                                                    );
-                                                });
-                                            }
+                                            #[expect(unused_results, reason = "Kundvagnen sparas i bakgrunden.")]
+                                            spawn(save_cart_item(customer_id, id, new_qty));
                                         }
                                     },
                                     "−"
@@ -89,20 +77,8 @@ pub fn CartDropdown(on_close: EventHandler<()>) -> Element {
                                         move |_| {
                                             let new_qty = qty + 1;
                                             global_state.write().set_quantity(id, new_qty);
-                                            if let Some(cid) = customer_id {
-                                                #[allow(unused_results)]
-                                                spawn(async move {
-                                                    use crate::database::{Id, Product};
-                                                    drop(
-                                                        crate::database::cart::set_in_shopping_cart(
-                                                                cid,
-                                                                Id::<Product>::from(id),
-                                                                new_qty,
-                                                            )
-                                                            .await,
-                                                    );
-                                                });
-                                            }
+                                            #[expect(unused_results, reason = "Kundvagnen sparas i bakgrunden.")]
+                                            spawn(save_cart_item(customer_id, id, new_qty));
                                         }
                                     },
                                     "+"
@@ -114,20 +90,8 @@ pub fn CartDropdown(on_close: EventHandler<()>) -> Element {
                                     let id = item.product_id;
                                     move |_| {
                                         global_state.write().remove_from_cart(id);
-                                        if let Some(cid) = customer_id {
-                                            #[allow(unused_results)]
-                                            spawn(async move {
-                                                use crate::database::{Id, Product};
-                                                drop(
-                                                    crate::database::cart::set_in_shopping_cart(
-                                                            cid,
-                                                            Id::<Product>::from(id),
-                                                            0,
-                                                        )
-                                                        .await,
-                                                );
-                                            });
-                                        }
+                                        #[expect(unused_results, reason = "Kundvagnen sparas i bakgrunden.")]
+                                        spawn(save_cart_item(customer_id, id, 0));
                                     }
                                 },
                                 i { class: "fa-solid fa-trash text-sm" }
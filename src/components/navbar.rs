@@ -1,15 +1,20 @@
 use crate::Route;
 use crate::components::auth_dropdown::AuthDropdown;
 use crate::components::cart_dropdown::CartDropdown;
-use crate::database::categories::category_trees;
-use crate::database::search::search_products;
+use crate::database::categories::{category_trees, CategoryTree};
+use crate::database::search::{search_products, SearchFilters, SearchOrder};
 use crate::state::GlobalState;
 use dioxus::prelude::*;
 
 // Class for the category navigation bar
 #[allow(non_snake_case)]
 #[component]
-fn SidebarCategory(title: String, id: i32, subcategories: Vec<(String, i32)>) -> Element {
+fn SidebarCategory(
+    /// Kategorin som visas, med sina underkategorier.
+    tree: CategoryTree,
+    /// Anropas när användaren navigerar till en kategori.
+    on_navigate: EventHandler<()>,
+) -> Element {
     let mut is_open = use_signal(|| false);
     let rotation = if is_open() { "rotate-180" } else { "" };
 
@@ -19,22 +24,19 @@ fn SidebarCategory(title: String, id: i32, subcategories: Vec<(String, i32)>) ->
                 class: "flex justify-between items-center py-4 px-2 cursor-pointer hover:bg-green-50 transition-colors",
                 onclick: move |_| is_open.toggle(),
                 Link {
-                    to: Route::Category { id: id.into() },
+                    to: Route::Category { id: tree.id },
                     class: "font-bold text-gray-800 hover:text-green-700 flex-grow",
-                    "{title}"
+                    onclick: move |_| on_navigate.call(()),
+                    "{tree.name}"
+                }
+                if !tree.subcategories.is_empty() {
+                    i { class: "fa-solid fa-chevron-down transition-transform duration-300 {rotation}" }
                 }
-                i { class: "fa-solid fa-chevron-down transition-transform duration-300 {rotation}" }
             }
-            if is_open() {
-                div { class: "bg-gray-50 flex flex-col pb-2",
-                    for (name , sub_id) in subcategories.into_iter() {
-                        Link {
-                            to: Route::Category {
-                                id: sub_id.into(),
-                            },
-                            class: "pl-6 py-2 text-gray-600 hover:text-green-700 hover:bg-gray-100 text-sm transition-colors",
-                            "{name}"
-                        }
+            if is_open() && !tree.subcategories.is_empty() {
+                div { class: "bg-gray-50 flex flex-col pl-4 pb-2",
+                    for sub in tree.subcategories.iter() {
+                        SidebarCategory { tree: sub.clone(), on_navigate }
                     }
                 }
             }
@@ -62,9 +64,9 @@ pub fn Navbar() -> Element {
         let q = search_query();
         async move {
             if q.trim().is_empty() {
-                Ok(Vec::new())
+                Ok(Box::default())
             } else {
-                search_products(q.into(), 8).await
+                search_products(q.into(), SearchFilters::default(), SearchOrder::Relevance, 8, 0).await
             }
         }
     });
@@ -228,9 +230,7 @@ pub fn Navbar() -> Element {
                             }
                         }
                         div { class: "flex-grow overflow-y-auto p-4",
-                            // hårdkodade kategorier
-                            // TODO(db): Ersätt hårdkodade SidebarCategory-anrop med kategorier från databasen
-                            // TODO(db): Subkategorier ska också hämtas från databasen per kategori
+                            // Kategoriträdet från databasen, i den ordning administratörer har valt
                             match &*categories.read() {
                                 None => rsx! {
                                     p { class: "text-gray-400 text-sm p-4", "Laddar..." }
@@ -238,9 +238,8 @@ pub fn Navbar() -> Element {
                                 Some(trees) => rsx! {
                                     for tree in trees.iter() {
                                         SidebarCategory {
-                                            title: tree.name.to_string(),
-                                            id: tree.id.get(),
-                                            subcategories: tree.subcategories.iter().map(|s| (s.name.to_string(), s.id.get())).collect(),
+                                            tree: tree.clone(),
+                                            on_navigate: move |()| show_sidebar.set(false),
                                         }
                                     }
                                 },
@@ -1,6 +1,6 @@
 use crate::Route;
 use crate::database::Deal;
-use crate::state::GlobalState;
+use crate::state::{save_cart_item, GlobalState};
 use dioxus::prelude::*;
  
 /// Builds a short display string for a special offer badge.
@@ -35,12 +35,12 @@ pub fn offer_label(deal: Option<Deal>, base_price: rust_decimal::Decimal) -> Opt
     })
 }
  
-#[derive(Props, Debug, Clone, PartialEq)]
+#[derive(Props, Debug, Clone, PartialEq, Eq)]
 #[expect(missing_docs, reason = "TODO")]
 pub struct ProductProps {
     pub id: i32,
     pub name: String,
-    pub price: f64,
+    pub price: rust_decimal::Decimal,
     pub image_url: String,
     pub comparison_price: String,
     pub in_stock: u32,
@@ -126,24 +126,15 @@ pub fn ProductCard(props: ProductProps) -> Element {
                                     product_price,
                                     product_image.clone(),
                                 );
-                            if let Some(cid) = global_state.read().customer_id() {
-                                let new_qty = global_state
-                                    .read()
-                                    .cart
-                                    .iter()
-                                    .find(|i| i.product_id == product_id)
-                                    .map(|i| i.quantity)
-                                    .unwrap_or(1);
-                                #[allow(unused_results)]
-                                spawn(async move {
-                                    let pid = crate::database::Id::<
-                                        crate::database::Product,
-                                    >::from(product_id);
-                                    drop(
-                                        crate::database::cart::set_in_shopping_cart(cid, pid, new_qty).await,
-                                    );
-                                });
-                            }
+                            let customer = global_state.read().customer_id();
+                            let new_qty = global_state
+                                .read()
+                                .cart
+                                .iter()
+                                .find(|i| i.product_id == product_id)
+                                .map_or(1, |i| i.quantity);
+                            #[expect(unused_results, reason = "Kundvagnen sparas i bakgrunden.")]
+                            spawn(save_cart_item(customer, product_id, new_qty));
                         },
                         i { class: "fas fa-shopping-cart" }
                     }
@@ -154,17 +145,9 @@ pub fn ProductCard(props: ProductProps) -> Element {
                             onclick: move |_| {
                                 let new_qty = quantity - 1;
                                 global_state.write().set_quantity(product_id, new_qty);
-                                if let Some(cid) = global_state.read().customer_id() {
-                                    #[allow(unused_results)]
-                                    spawn(async move {
-                                        let pid = crate::database::Id::<
-                                            crate::database::Product,
-                                        >::from(product_id);
-                                        drop(
-                                            crate::database::cart::set_in_shopping_cart(cid, pid, new_qty).await,
-                                        );
-                                    });
-                                }
+                                let customer = global_state.read().customer_id();
+                                #[expect(unused_results, reason = "Kundvagnen sparas i bakgrunden.")]
+                                spawn(save_cart_item(customer, product_id, new_qty));
                             },
                             i { class: "fas fa-minus" }
                         }
@@ -174,17 +157,9 @@ pub fn ProductCard(props: ProductProps) -> Element {
                             onclick: move |_| {
                                 let new_qty = quantity + 1;
                                 global_state.write().set_quantity(product_id, new_qty);
-                                if let Some(cid) = global_state.read().customer_id() {
-                                    #[allow(unused_results)]
-                                    spawn(async move {
-                                        let pid = crate::database::Id::<
-                                            crate::database::Product,
-                                        >::from(product_id);
-                                        drop(
-                                            crate::database::cart::set_in_shopping_cart(cid, pid, new_qty).await,
-                                        );
-                                    });
-                                }
+                                let customer = global_state.read().customer_id();
+                                #[expect(unused_results, reason = "Kundvagnen sparas i bakgrunden.")]
+                                spawn(save_cart_item(customer, product_id, new_qty));
                             },
                             i { class: "fas fa-plus" }
                         }
@@ -11,8 +11,7 @@
 #[cfg(feature = "server")]
 use {
     crate::dioxus_fullstack::Lazy,
-    sqlx::{PgPool as Pool, postgres::PgQueryResult as QueryResult, query},
-    thiserror::Error,
+    sqlx::{PgPool as Pool, migrate, postgres::PgQueryResult as QueryResult, query},
 };
 
 mod types;
@@ -24,6 +23,12 @@ pub use id::*;
 pub mod auth;
 pub use auth::*;
 
+mod access;
+pub use access::*;
+
+mod error;
+pub use error::*;
+
 // FIXME: It's possible that `Decimal`s will have to be rescaled, clamped, truncated or rounded
 // before insertion into the database. This might warrant a newtype.
 
@@ -31,14 +36,29 @@ pub use auth::*;
 
 // TODO: Consider having functions that create or update rows return the IDs.
 
+pub mod admin;
+pub mod bundles;
 pub mod cart;
 pub mod categories;
+pub mod delivery;
+#[cfg(feature = "server")]
+pub mod mail;
+pub mod media;
+pub mod memberships;
 pub mod offers;
+pub mod payments;
 pub mod products;
+pub mod promotions;
+#[cfg(feature = "server")]
+pub mod rate_limit;
 pub mod reviews;
 pub mod search;
 pub mod users;
 
+#[cfg(test)]
+#[cfg(feature = "server")]
+mod tests;
+
 #[cfg(feature = "server")]
 static POOL: Lazy<Pool> = Lazy::new(|| async move {
     let database_url = dotenvy::var("DATABASE_URL").expect("`DATABASE_URL` not set.");
@@ -47,21 +67,36 @@ static POOL: Lazy<Pool> = Lazy::new(|| async move {
         .await
         .expect("Failed to establish a connection to the database.");
 
+    migrate!()
+        .run(&pool)
+        .await
+        .expect("Failed to apply database migrations.");
+
     #[expect(clippy::unwrap_used, reason = "Cell was just initialized.")]
     let res = query!("SELECT process_expiries();")
         .fetch_all(&pool)
         .await
         .expect("Failed to run database startup code.");
     drop(res);
+    let res = query!("SELECT process_membership_expiries();")
+        .fetch_all(&pool)
+        .await
+        .expect("Failed to run database startup code.");
+    drop(res);
+    let res = query!("SELECT process_guest_cart_expiries();")
+        .fetch_all(&pool)
+        .await
+        .expect("Failed to run database startup code.");
+    drop(res);
+    let res = query!("SELECT process_payment_expiries();")
+        .fetch_all(&pool)
+        .await
+        .expect("Failed to run database startup code.");
+    drop(res);
 
     Ok::<_, !>(pool)
 });
 
-#[cfg(feature = "server")]
-#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Error)]
-#[error("The provided key was invalid.")]
-struct InvalidKey;
-
 /// Extension trait to make decisions based on the number of rows affected by a query.
 ///
 /// See [`QueryResult`].
@@ -98,14 +133,13 @@ trait QueryResultExt: Sized {
     ///
     /// # Errors
     ///
-    /// Fails with the output of `on_zero` if the key did not exist or the query for some other
+    /// Fails with [`AppError::NotFound`] if the key did not exist or the query for some other
     /// reason did not affect any rows.
     ///
     /// # Panics
     ///
     /// Panics if the query affected multiple rows, i.e. the key wasn't unique.
-    // TODO: Have this return `CapturedError` and perform conversion in the method.
-    fn by_unique_key(self) -> Result<(), InvalidKey>;
+    fn by_unique_key(self) -> Result<(), AppError>;
 }
 
 #[cfg(feature = "server")]
@@ -126,13 +160,13 @@ impl QueryResultExt for QueryResult {
     }
 
     fn procedure(self) {
-        assert!(self.rows_affected() != 0, "Query was not a procedure call.");
+        assert!(self.rows_affected() == 0, "Query was not a procedure call.");
     }
 
     #[expect(clippy::unreachable, reason = "Key enforces uniqueness.")]
-    fn by_unique_key(self) -> Result<(), InvalidKey> {
+    fn by_unique_key(self) -> Result<(), AppError> {
         match self.rows_affected() {
-            0 => Err(InvalidKey),
+            0 => Err(AppError::NotFound),
             1 => Ok(()),
             _ => unreachable!("Non-unique key."),
         }
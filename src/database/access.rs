@@ -0,0 +1,507 @@
+//! Authorization of server function calls.
+//!
+//! Server functions receive the IDs of the acting users as arguments from the client, which can
+//! not be trusted. The functions in this module derive the caller from the current session and
+//! verify that they are permitted to act as, or on behalf of, the given users.
+
+use dioxus::{CapturedError, prelude::*};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+#[cfg(feature = "server")]
+use {
+    crate::database::{
+        Address, Bundle, Comment, Customer, Id, LoginId, Order, POOL, Product, Review,
+        ShippingMethod, SpecialOffer, User, Vendor, recently_authenticated, session_caller,
+    },
+    sqlx::query_scalar,
+};
+
+/// A server function was called by a user who is not permitted to call it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Error, Serialize, Deserialize)]
+pub enum AuthError {
+    /// The caller is not logged in.
+    #[error("Not logged in.")]
+    Unauthenticated,
+    /// The caller is logged in, but not permitted to perform the action.
+    #[error("Not permitted to perform this action.")]
+    Forbidden,
+    /// The caller must confirm their password before performing the action, see
+    /// [`reauthenticate`](crate::database::reauthenticate).
+    #[error("Confirm your password to perform this action.")]
+    ReauthenticationRequired,
+}
+
+impl AuthError {
+    /// Get the HTTP status code corresponding to the error.
+    #[must_use]
+    pub const fn status(self) -> StatusCode {
+        match self {
+            Self::Unauthenticated => StatusCode::UNAUTHORIZED,
+            Self::Forbidden => StatusCode::FORBIDDEN,
+            Self::ReauthenticationRequired => StatusCode::PRECONDITION_REQUIRED,
+        }
+    }
+
+    /// Recover an authorization error from an error returned by a server function.
+    ///
+    /// Returns `None` if the error was not caused by a failed authorization.
+    #[must_use]
+    pub fn from_error(error: &CapturedError) -> Option<Self> {
+        let code = match error.downcast_ref::<ServerFnError>() {
+            Some(&ServerFnError::ServerError { code, .. }) => code,
+            Some(_) => return None,
+            None => error.downcast_ref::<HttpError>()?.status.as_u16(),
+        };
+        match StatusCode::from_u16(code).ok()? {
+            StatusCode::UNAUTHORIZED => Some(Self::Unauthenticated),
+            StatusCode::FORBIDDEN => Some(Self::Forbidden),
+            StatusCode::PRECONDITION_REQUIRED => Some(Self::ReauthenticationRequired),
+            _ => None,
+        }
+    }
+}
+
+impl From<AuthError> for HttpError {
+    fn from(error: AuthError) -> Self {
+        Self::new(error.status(), error.to_string())
+    }
+}
+
+/// Fail with a [`HttpError`] carrying `error`.
+///
+/// # Errors
+///
+/// Always fails.
+#[cfg(feature = "server")]
+fn deny<T>(error: AuthError) -> Result<T> {
+    Err(HttpError::from(error).into())
+}
+
+/// Fail with [`AuthError::Forbidden`] unless `permitted`.
+///
+/// # Errors
+///
+/// Fails if `permitted` is false.
+#[cfg(feature = "server")]
+fn permit(permitted: bool) -> Result<()> {
+    if permitted {
+        Ok(())
+    } else {
+        deny(AuthError::Forbidden)
+    }
+}
+
+/// Get the user logged in with the current session.
+///
+/// # Errors
+///
+/// Fails if:
+/// - The caller is not logged in.
+/// - An error occurs during communication with the database.
+#[cfg(feature = "server")]
+pub(crate) async fn caller() -> Result<LoginId> {
+    session_caller()
+        .await?
+        .map_or_else(|| deny(AuthError::Unauthenticated), Ok)
+}
+
+/// Verify that the caller is the user `user`, regardless of role.
+///
+/// # Errors
+///
+/// Fails if:
+/// - The caller is not logged in, or is logged in as someone else.
+/// - An error occurs during communication with the database.
+#[cfg(feature = "server")]
+pub(crate) async fn authorize_user(user: Id<User>) -> Result<()> {
+    permit(Id::<User>::from(caller().await?) == user)
+}
+
+/// Verify that the caller has confirmed their password recently in the current session.
+///
+/// Sensitive account changes require this on top of other authorization.
+///
+/// # Errors
+///
+/// Fails if:
+/// - The caller is not logged in.
+/// - The caller has not confirmed their password recently, see
+///   [`AuthError::ReauthenticationRequired`].
+/// - An error occurs during communication with the database.
+#[cfg(feature = "server")]
+pub(crate) async fn authorize_recent_authentication() -> Result<()> {
+    caller().await.map(drop)?;
+    if recently_authenticated().await? {
+        Ok(())
+    } else {
+        deny(AuthError::ReauthenticationRequired)
+    }
+}
+
+/// Verify that the caller is either the user `user` or an administrator.
+///
+/// # Errors
+///
+/// Fails if:
+/// - The caller is not logged in, or is logged in as someone else who is not an administrator.
+/// - An error occurs during communication with the database.
+#[cfg(feature = "server")]
+pub(crate) async fn authorize_user_or_administrator(user: Id<User>) -> Result<()> {
+    let caller = caller().await?;
+    permit(matches!(caller, LoginId::Administrator(_)) || Id::<User>::from(caller) == user)
+}
+
+/// Verify that the caller is the customer `customer`.
+///
+/// # Errors
+///
+/// Fails if:
+/// - The caller is not logged in, or is logged in as someone else.
+/// - An error occurs during communication with the database.
+#[cfg(feature = "server")]
+pub(crate) async fn authorize_customer(customer: Id<Customer>) -> Result<()> {
+    permit(caller().await? == customer)
+}
+
+/// Verify that the caller is the customer `customer`, and has verified their email.
+///
+/// # Errors
+///
+/// Fails if:
+/// - The caller is not logged in, or is logged in as someone else.
+/// - The caller has not verified their email, with [`AuthError::Forbidden`].
+/// - An error occurs during communication with the database.
+#[cfg(feature = "server")]
+pub(crate) async fn authorize_verified_customer(customer: Id<Customer>) -> Result<()> {
+    authorize_customer(customer).await?;
+    let verified = query_scalar!(
+        r#"
+        SELECT email_verified_at IS NOT NULL AS "verified!"
+        FROM users
+        WHERE id = $1
+        "#,
+        customer.get(),
+    )
+    .fetch_one(&*POOL)
+    .await?;
+    permit(verified)
+}
+
+/// Verify that the caller may view data personalized for `customer`, if any.
+///
+/// Anyone may view data that is not personalized.
+///
+/// # Errors
+///
+/// Fails if:
+/// - `customer` is `Some` and the caller is not logged in as them.
+/// - An error occurs during communication with the database.
+#[cfg(feature = "server")]
+pub(crate) async fn authorize_viewer(customer: Option<Id<Customer>>) -> Result<()> {
+    match customer {
+        Some(customer) => authorize_customer(customer).await,
+        None => Ok(()),
+    }
+}
+
+/// Verify that the caller is the vendor `vendor`.
+///
+/// # Errors
+///
+/// Fails if:
+/// - The caller is not logged in, or is logged in as someone else.
+/// - An error occurs during communication with the database.
+#[cfg(feature = "server")]
+pub(crate) async fn authorize_vendor(vendor: Id<Vendor>) -> Result<()> {
+    permit(caller().await? == vendor)
+}
+
+/// Verify that the caller is an administrator.
+///
+/// # Errors
+///
+/// Fails if:
+/// - The caller is not logged in, or is not an administrator.
+/// - An error occurs during communication with the database.
+#[cfg(feature = "server")]
+pub(crate) async fn authorize_administrator() -> Result<()> {
+    permit(matches!(caller().await?, LoginId::Administrator(_)))
+}
+
+/// Verify that the caller is the vendor selling a product, or is an administrator if `moderate` is
+/// set.
+///
+/// # Errors
+///
+/// Fails if:
+/// - The caller is not logged in, or is neither the vendor selling `product` nor a permitted
+///   administrator.
+/// - `product` is invalid.
+/// - An error occurs during communication with the database.
+#[cfg(feature = "server")]
+pub(crate) async fn authorize_product_owner(product: Id<Product>, moderate: bool) -> Result<()> {
+    let vendor = match caller().await? {
+        LoginId::Vendor(vendor) => vendor,
+        LoginId::Administrator(_) if moderate => return Ok(()),
+        LoginId::Administrator(_) | LoginId::Customer(_) => return deny(AuthError::Forbidden),
+    };
+    permit(
+        query_scalar!(
+            r#"
+            SELECT EXISTS (
+                SELECT 1
+                FROM products
+                WHERE id = $1 AND vendor = $2
+            ) AS "owned!"
+            "#,
+            product.get(),
+            vendor.get(),
+        )
+        .fetch_one(&*POOL)
+        .await?,
+    )
+}
+
+/// Verify that the caller is the vendor selling the product a special offer applies to.
+///
+/// # Errors
+///
+/// Fails if:
+/// - The caller is not logged in, or is not the vendor selling the product.
+/// - `special_offer` is invalid.
+/// - An error occurs during communication with the database.
+#[cfg(feature = "server")]
+pub(crate) async fn authorize_special_offer_owner(special_offer: Id<SpecialOffer>) -> Result<()> {
+    let LoginId::Vendor(vendor) = caller().await? else {
+        return deny(AuthError::Forbidden);
+    };
+    permit(
+        query_scalar!(
+            r#"
+            SELECT EXISTS (
+                SELECT 1
+                FROM special_offers o
+                JOIN products p ON p.id = o.product
+                WHERE o.id = $1 AND p.vendor = $2
+            ) AS "owned!"
+            "#,
+            special_offer.get(),
+            vendor.get(),
+        )
+        .fetch_one(&*POOL)
+        .await?,
+    )
+}
+
+/// Verify that the caller is the vendor offering a bundle.
+///
+/// # Errors
+///
+/// Fails if:
+/// - The caller is not logged in, or is not the vendor offering the bundle.
+/// - `bundle` is invalid.
+/// - An error occurs during communication with the database.
+#[cfg(feature = "server")]
+pub(crate) async fn authorize_bundle_owner(bundle: Id<Bundle>) -> Result<()> {
+    let LoginId::Vendor(vendor) = caller().await? else {
+        return deny(AuthError::Forbidden);
+    };
+    permit(
+        query_scalar!(
+            r#"
+            SELECT EXISTS (
+                SELECT 1
+                FROM bundles
+                WHERE id = $1 AND vendor = $2
+            ) AS "owned!"
+            "#,
+            bundle.get(),
+            vendor.get(),
+        )
+        .fetch_one(&*POOL)
+        .await?,
+    )
+}
+
+/// Verify that the caller is the customer who saved a delivery address.
+///
+/// # Errors
+///
+/// Fails if:
+/// - The caller is not logged in, or is not the customer who saved the address.
+/// - `address` is invalid.
+/// - An error occurs during communication with the database.
+#[cfg(feature = "server")]
+pub(crate) async fn authorize_address_owner(address: Id<Address>) -> Result<()> {
+    let LoginId::Customer(customer) = caller().await? else {
+        return deny(AuthError::Forbidden);
+    };
+    permit(
+        query_scalar!(
+            r#"
+            SELECT EXISTS (
+                SELECT 1
+                FROM addresses
+                WHERE id = $1 AND customer = $2
+            ) AS "owned!"
+            "#,
+            address.get(),
+            customer.get(),
+        )
+        .fetch_one(&*POOL)
+        .await?,
+    )
+}
+
+/// Verify that the caller is the vendor offering a shipping method.
+///
+/// # Errors
+///
+/// Fails if:
+/// - The caller is not logged in, or is not the vendor offering the shipping method.
+/// - `method` is invalid.
+/// - An error occurs during communication with the database.
+#[cfg(feature = "server")]
+pub(crate) async fn authorize_shipping_method_owner(method: Id<ShippingMethod>) -> Result<()> {
+    let LoginId::Vendor(vendor) = caller().await? else {
+        return deny(AuthError::Forbidden);
+    };
+    permit(
+        query_scalar!(
+            r#"
+            SELECT EXISTS (
+                SELECT 1
+                FROM shipping_methods
+                WHERE id = $1 AND vendor = $2
+            ) AS "owned!"
+            "#,
+            method.get(),
+            vendor.get(),
+        )
+        .fetch_one(&*POOL)
+        .await?,
+    )
+}
+
+/// Verify that the caller wrote a review, or is an administrator if `moderate` is set.
+///
+/// # Errors
+///
+/// Fails if:
+/// - The caller is not logged in, or is neither the author nor a permitted administrator.
+/// - `review` is invalid.
+/// - An error occurs during communication with the database.
+#[cfg(feature = "server")]
+pub(crate) async fn authorize_review_author(review: Id<Review>, moderate: bool) -> Result<()> {
+    let customer = match caller().await? {
+        LoginId::Customer(customer) => customer,
+        LoginId::Administrator(_) if moderate => return Ok(()),
+        LoginId::Administrator(_) | LoginId::Vendor(_) => return deny(AuthError::Forbidden),
+    };
+    permit(
+        query_scalar!(
+            r#"
+            SELECT EXISTS (
+                SELECT 1
+                FROM reviews
+                WHERE id = $1 AND customer = $2
+            ) AS "owned!"
+            "#,
+            review.get(),
+            customer.get(),
+        )
+        .fetch_one(&*POOL)
+        .await?,
+    )
+}
+
+/// Verify that the caller wrote a comment, or is an administrator if `moderate` is set.
+///
+/// # Errors
+///
+/// Fails if:
+/// - The caller is not logged in, or is neither the author nor a permitted administrator.
+/// - `comment` is invalid.
+/// - An error occurs during communication with the database.
+#[cfg(feature = "server")]
+pub(crate) async fn authorize_comment_author(comment: Id<Comment>, moderate: bool) -> Result<()> {
+    let caller = caller().await?;
+    if moderate && matches!(caller, LoginId::Administrator(_)) {
+        return Ok(());
+    }
+    permit(
+        query_scalar!(
+            r#"
+            SELECT EXISTS (
+                SELECT 1
+                FROM comments
+                WHERE id = $1 AND user_id = $2
+            ) AS "owned!"
+            "#,
+            comment.get(),
+            Id::<User>::from(caller).get(),
+        )
+        .fetch_one(&*POOL)
+        .await?,
+    )
+}
+
+/// A party to an order, as established by [`authorize_order_party`].
+#[cfg(feature = "server")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum OrderParty {
+    /// The customer who placed the order.
+    Customer,
+    /// A vendor selling one of the ordered products.
+    Vendor(Id<Vendor>),
+}
+
+/// Verify that the caller is either the customer who placed an order, or a vendor selling one of
+/// the ordered products.
+///
+/// # Errors
+///
+/// Fails if:
+/// - The caller is not logged in, or is not a party to the order.
+/// - `order` is invalid.
+/// - An error occurs during communication with the database.
+#[cfg(feature = "server")]
+pub(crate) async fn authorize_order_party(order: Id<Order>) -> Result<OrderParty> {
+    let (party, owned) = match caller().await? {
+        LoginId::Customer(customer) => (
+            OrderParty::Customer,
+            query_scalar!(
+                r#"
+                SELECT EXISTS (
+                    SELECT 1
+                    FROM orders
+                    WHERE id = $1 AND customer = $2
+                ) AS "owned!"
+                "#,
+                order.get(),
+                customer.get(),
+            )
+            .fetch_one(&*POOL)
+            .await?,
+        ),
+        LoginId::Vendor(vendor) => (
+            OrderParty::Vendor(vendor),
+            query_scalar!(
+                r#"
+                SELECT EXISTS (
+                    SELECT 1
+                    FROM order_lines l
+                    JOIN products p ON p.id = l.product
+                    WHERE l.order_id = $1 AND p.vendor = $2
+                ) AS "owned!"
+                "#,
+                order.get(),
+                vendor.get(),
+            )
+            .fetch_one(&*POOL)
+            .await?,
+        ),
+        LoginId::Administrator(_) => return deny(AuthError::Forbidden),
+    };
+    permit(owned).map(|()| party)
+}
@@ -0,0 +1,430 @@
+//! Database functions for the administrator dashboard.
+//!
+//! Every function in this module may only be called by administrators. Actions that are also
+//! available to other users, such as deleting users or moderating reviews, are not repeated here;
+//! see [`delete_user`](crate::database::users::delete_user),
+//! [`set_visibility`](crate::database::products::set_visibility),
+//! [`delete_review`](crate::database::reviews::delete_review) and
+//! [`delete_comment`](crate::database::reviews::delete_comment).
+
+use crate::database::{
+    Comment, Customer, Email, Id, Product, Review, Role, User, Username, Vendor,
+};
+use dioxus::prelude::*;
+use serde::{Deserialize, Serialize};
+use time::PrimitiveDateTime;
+#[cfg(feature = "server")]
+use {
+    crate::database::{POOL, QueryResultExt as _, RawId, authorize_administrator},
+    sqlx::{query, query_as},
+};
+
+/// Escape `search` for use in a `LIKE` pattern, matching it anywhere in a string.
+#[cfg(feature = "server")]
+fn contains_pattern(search: &str) -> String {
+    let mut pattern = String::with_capacity(search.len() + 2);
+    pattern.push('%');
+    for c in search.chars() {
+        if matches!(c, '\\' | '%' | '_') {
+            pattern.push('\\');
+        }
+        pattern.push(c);
+    }
+    pattern.push('%');
+    pattern
+}
+
+/// A user, for display in the administrator dashboard.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UserOverview {
+    /// The ID of the user.
+    pub id: Id<User>,
+    /// The username of the user.
+    pub username: Username,
+    /// The email of the user.
+    pub email: Email,
+    /// The role of the user.
+    pub role: Role,
+    /// Whether the user has been deleted.
+    pub deleted: bool,
+    /// Whether the user may write reviews. `None` if the user is not a customer.
+    pub can_review: Option<bool>,
+    /// When the user was created.
+    pub created_at: PrimitiveDateTime,
+}
+
+#[cfg(feature = "server")]
+struct UserOverviewRepr {
+    id: RawId,
+    username: String,
+    email: String,
+    role: Role,
+    deleted: bool,
+    can_review: Option<bool>,
+    created_at: PrimitiveDateTime,
+}
+
+#[cfg(feature = "server")]
+impl From<UserOverviewRepr> for UserOverview {
+    fn from(
+        UserOverviewRepr {
+            id,
+            username,
+            email,
+            role,
+            deleted,
+            can_review,
+            created_at,
+        }: UserOverviewRepr,
+    ) -> Self {
+        Self {
+            id: id.into(),
+            username: Username::new(username.into()).expect("Invalid username."),
+            email: Email::new(email.into()).expect("Invalid email."),
+            role,
+            deleted,
+            can_review,
+            created_at,
+        }
+    }
+}
+
+/// Get users whose username or email contains `search`, sorted by username.
+///
+/// Deleted users are included.
+///
+/// # Errors
+///
+/// Fails if:
+/// - The caller is not an administrator.
+/// - `limit > i64::MAX`.
+/// - `offset > i64::MAX`.
+/// - An error occurs during communication with the database.
+#[server]
+pub async fn users(search: Box<str>, limit: usize, offset: usize) -> Result<Box<[UserOverview]>> {
+    authorize_administrator().await?;
+
+    query_as!(
+        UserOverviewRepr,
+        r#"
+        SELECT u.id,
+            username,
+            email,
+            role_of(u.id) AS "role!: Role",
+            deleted,
+            can_review AS "can_review?",
+            created_at
+        FROM users u
+        LEFT JOIN customers c ON c.id = u.id
+        WHERE username ILIKE $1 OR email ILIKE $1
+        ORDER BY username
+        LIMIT $2
+        OFFSET $3
+        "#,
+        contains_pattern(&search),
+        i64::try_from(limit)?,
+        i64::try_from(offset)?,
+    )
+    .fetch_all(&*POOL)
+    .await
+    .map(|users| users.into_iter().map(Into::into).collect())
+    .map_err(Into::into)
+}
+
+/// Set whether a customer may write reviews.
+///
+/// Existing reviews are kept.
+///
+/// # Errors
+///
+/// Fails if:
+/// - `customer` is invalid.
+/// - The caller is not an administrator.
+/// - An error occurs during communication with the database.
+#[server]
+pub async fn set_can_review(customer: Id<Customer>, can_review: bool) -> Result<()> {
+    authorize_administrator().await?;
+
+    query!(
+        "
+        UPDATE customers
+        SET can_review = $2
+        WHERE id = $1
+        ",
+        customer.get(),
+        can_review,
+    )
+    .execute(&*POOL)
+    .await?
+    .by_unique_key()
+    .map_err(Into::into)
+}
+
+/// A product, for display in the administrator dashboard.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProductOverviewAdministrator {
+    /// The ID of the product.
+    pub id: Id<Product>,
+    /// The name of the product.
+    pub name: Box<str>,
+    /// The ID of the vendor selling the product.
+    pub vendor: Id<Vendor>,
+    /// The display name of the vendor selling the product.
+    pub vendor_name: Box<str>,
+    /// Whether the product is visible.
+    pub visible: bool,
+}
+
+#[cfg(feature = "server")]
+struct ProductOverviewAdministratorRepr {
+    id: RawId,
+    name: String,
+    vendor: RawId,
+    vendor_name: String,
+    visible: bool,
+}
+
+#[cfg(feature = "server")]
+impl From<ProductOverviewAdministratorRepr> for ProductOverviewAdministrator {
+    fn from(
+        ProductOverviewAdministratorRepr {
+            id,
+            name,
+            vendor,
+            vendor_name,
+            visible,
+        }: ProductOverviewAdministratorRepr,
+    ) -> Self {
+        Self {
+            id: id.into(),
+            name: name.into(),
+            vendor: vendor.into(),
+            vendor_name: vendor_name.into(),
+            visible,
+        }
+    }
+}
+
+/// Get products whose name or vendor contains `search`, sorted by name.
+///
+/// Invisible products and products out of stock are included.
+///
+/// # Errors
+///
+/// Fails if:
+/// - The caller is not an administrator.
+/// - `limit > i64::MAX`.
+/// - `offset > i64::MAX`.
+/// - An error occurs during communication with the database.
+#[server]
+pub async fn products(
+    search: Box<str>,
+    limit: usize,
+    offset: usize,
+) -> Result<Box<[ProductOverviewAdministrator]>> {
+    authorize_administrator().await?;
+
+    query_as!(
+        ProductOverviewAdministratorRepr,
+        "
+        SELECT p.id, name, vendor, display_name AS vendor_name, visible
+        FROM products p
+        JOIN vendors v ON v.id = p.vendor
+        WHERE name ILIKE $1 OR display_name ILIKE $1
+        ORDER BY name
+        LIMIT $2
+        OFFSET $3
+        ",
+        contains_pattern(&search),
+        i64::try_from(limit)?,
+        i64::try_from(offset)?,
+    )
+    .fetch_all(&*POOL)
+    .await
+    .map(|products| products.into_iter().map(Into::into).collect())
+    .map_err(Into::into)
+}
+
+/// A review, for moderation in the administrator dashboard.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReviewOverview {
+    /// The ID of the review.
+    pub id: Id<Review>,
+    /// The ID of the reviewed product.
+    pub product: Id<Product>,
+    /// The name of the reviewed product.
+    pub product_name: Box<str>,
+    /// The ID of the authoring customer.
+    pub customer: Id<Customer>,
+    /// The username of the authoring customer.
+    pub username: Username,
+    /// The title of the review.
+    pub title: Box<str>,
+    /// The content of the review.
+    pub content: Box<str>,
+    /// When the review was last updated.
+    pub updated_at: PrimitiveDateTime,
+}
+
+#[cfg(feature = "server")]
+struct ReviewOverviewRepr {
+    id: RawId,
+    product: RawId,
+    product_name: String,
+    customer: RawId,
+    username: String,
+    title: String,
+    content: String,
+    updated_at: PrimitiveDateTime,
+}
+
+#[cfg(feature = "server")]
+impl From<ReviewOverviewRepr> for ReviewOverview {
+    fn from(
+        ReviewOverviewRepr {
+            id,
+            product,
+            product_name,
+            customer,
+            username,
+            title,
+            content,
+            updated_at,
+        }: ReviewOverviewRepr,
+    ) -> Self {
+        Self {
+            id: id.into(),
+            product: product.into(),
+            product_name: product_name.into(),
+            customer: customer.into(),
+            username: Username::new(username.into()).expect("Invalid username."),
+            title: title.into(),
+            content: content.into(),
+            updated_at,
+        }
+    }
+}
+
+/// Get reviews on all products, sorted by most recently updated.
+///
+/// # Errors
+///
+/// Fails if:
+/// - The caller is not an administrator.
+/// - `limit > i64::MAX`.
+/// - `offset > i64::MAX`.
+/// - An error occurs during communication with the database.
+#[server]
+pub async fn recent_reviews(limit: usize, offset: usize) -> Result<Box<[ReviewOverview]>> {
+    authorize_administrator().await?;
+
+    query_as!(
+        ReviewOverviewRepr,
+        "
+        SELECT r.id, product, p.name AS product_name, customer, username, title, content,
+            r.updated_at
+        FROM reviews r
+        JOIN products p ON p.id = r.product
+        JOIN users u ON u.id = r.customer
+        ORDER BY r.updated_at DESC
+        LIMIT $1
+        OFFSET $2
+        ",
+        i64::try_from(limit)?,
+        i64::try_from(offset)?,
+    )
+    .fetch_all(&*POOL)
+    .await
+    .map(|reviews| reviews.into_iter().map(Into::into).collect())
+    .map_err(Into::into)
+}
+
+/// A comment, for moderation in the administrator dashboard.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CommentOverview {
+    /// The ID of the comment.
+    pub id: Id<Comment>,
+    /// The ID of the product the comment was made under.
+    pub product: Id<Product>,
+    /// The name of the product the comment was made under.
+    pub product_name: Box<str>,
+    /// The ID of the author.
+    pub user_id: Id<User>,
+    /// The username of the author.
+    pub username: Username,
+    /// The content of the comment.
+    pub content: Box<str>,
+    /// When the comment was last updated.
+    pub updated_at: PrimitiveDateTime,
+}
+
+#[cfg(feature = "server")]
+struct CommentOverviewRepr {
+    id: RawId,
+    product: RawId,
+    product_name: String,
+    user_id: RawId,
+    username: String,
+    content: String,
+    updated_at: PrimitiveDateTime,
+}
+
+#[cfg(feature = "server")]
+impl From<CommentOverviewRepr> for CommentOverview {
+    fn from(
+        CommentOverviewRepr {
+            id,
+            product,
+            product_name,
+            user_id,
+            username,
+            content,
+            updated_at,
+        }: CommentOverviewRepr,
+    ) -> Self {
+        Self {
+            id: id.into(),
+            product: product.into(),
+            product_name: product_name.into(),
+            user_id: user_id.into(),
+            username: Username::new(username.into()).expect("Invalid username."),
+            content: content.into(),
+            updated_at,
+        }
+    }
+}
+
+/// Get comments on all reviews, sorted by most recently updated.
+///
+/// # Errors
+///
+/// Fails if:
+/// - The caller is not an administrator.
+/// - `limit > i64::MAX`.
+/// - `offset > i64::MAX`.
+/// - An error occurs during communication with the database.
+#[server]
+pub async fn recent_comments(limit: usize, offset: usize) -> Result<Box<[CommentOverview]>> {
+    authorize_administrator().await?;
+
+    query_as!(
+        CommentOverviewRepr,
+        "
+        SELECT c.id, r.product, p.name AS product_name, user_id, username, c.content,
+            c.updated_at
+        FROM comments c
+        JOIN reviews r ON r.id = c.review
+        JOIN products p ON p.id = r.product
+        JOIN users u ON u.id = c.user_id
+        ORDER BY c.updated_at DESC
+        LIMIT $1
+        OFFSET $2
+        ",
+        i64::try_from(limit)?,
+        i64::try_from(offset)?,
+    )
+    .fetch_all(&*POOL)
+    .await
+    .map(|comments| comments.into_iter().map(Into::into).collect())
+    .map_err(Into::into)
+}
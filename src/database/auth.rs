@@ -1,24 +1,43 @@
 //! Rudimentary authentication.
+//!
+//! Users verify their email with a link mailed to them on registration, see [`verify_email`].
+//! Until they have, customers may not place orders or write reviews. Resetting a password verifies
+//! the email as well, as the link to do so was mailed to it.
 
 use crate::database::{
-    Administrator, Customer, Email, Id, ProfilePicture, Role, Url, User, Username, Vendor,
+    Administrator, Customer, Email, Id, Media, ProfilePicture, Role, User, Username, Vendor,
 };
 use dioxus::prelude::*;
 use dioxus_fullstack::response::Response;
 use serde::{Deserialize, Serialize};
+use thiserror::Error;
 #[cfg(feature = "server")]
 use {
-    crate::database::{POOL, QueryResultExt},
+    crate::database::{
+        AppError, AuthError, POOL, QueryResultExt, Url, authorize_administrator, caller,
+        cart::{GUEST_CART_COOKIE, merge_into, merge_policy, removed_guest_cart_cookie},
+        classify,
+        mail::{Mail, link, send_mail},
+        media::verify_stored,
+        rate_limit::{Action, check_lockout, limit, record_password_attempt},
+    },
     argon2::{
         Argon2, PasswordHasher as _, PasswordVerifier as _,
         password_hash::{
-            PasswordHash, Salt, SaltString, errors::Error as PasswordHashError, rand_core::OsRng,
+            PasswordHash, Salt, SaltString,
+            errors::Error as PasswordHashError,
+            rand_core::{OsRng, RngCore as _},
         },
     },
-    dioxus_fullstack::response::IntoResponse as _,
-    http::header::SET_COOKIE,
-    sqlx::{query, query_as},
-    thiserror::Error,
+    dioxus_fullstack::{
+        FullstackContext, HeaderMap,
+        headers::{Cookie, HeaderMapExt as _},
+        response::IntoResponse as _,
+    },
+    http::{HeaderValue, header::SET_COOKIE},
+    sqlx::{PgConnection, Type, postgres::types::PgInterval, query, query_as, query_scalar},
+    std::fmt::Write as _,
+    time::Duration,
 };
 
 #[cfg(feature = "server")]
@@ -45,12 +64,12 @@ pub enum NewUserData {
     ///
     Customer {
         ///
-        profile_picture: Url,
+        profile_picture: Option<Id<Media>>,
     },
     ///
     Vendor {
         ///
-        profile_picture: Url,
+        profile_picture: Option<Id<Media>>,
         ///
         display_name: Box<str>,
         ///
@@ -62,10 +81,19 @@ pub enum NewUserData {
 
 /// Create a new user.
 ///
+/// A link to verify their email is sent to `email`, see [`verify_email`]. The user is not
+/// created if the mail could not be sent.
+///
 /// # Errors
 ///
 /// Fails if:
-/// - `username` or `email` is not unique.
+/// - The caller has created too many users, see
+///   [`AppError::RateLimited`](crate::database::AppError::RateLimited).
+/// - `username` or `email` is not unique, see
+///   [`AppError::Conflict`](crate::database::AppError::Conflict).
+/// - The verification mail could not be sent.
+/// - `data` is [`NewUserData::Administrator`] and the caller is not an administrator.
+/// - The profile picture in `data` is invalid.
 /// - An error occurs during communication with the database.
 #[server]
 pub async fn create_user(
@@ -74,20 +102,35 @@ pub async fn create_user(
     password: Box<str>,
     data: NewUserData,
 ) -> Result<()> {
+    limit(Action::CreateUser, None).await?;
+    if matches!(data, NewUserData::Administrator) {
+        authorize_administrator().await?;
+    }
+
+    if let NewUserData::Customer { profile_picture }
+    | NewUserData::Vendor {
+        profile_picture, ..
+    } = &data
+    {
+        verify_stored(profile_picture.as_slice()).await?;
+    }
+
     let password_hash = hash_password(&password, (&SaltString::generate(OsRng)).into())
         .unwrap()
         .serialize();
 
-    // HACK: `password_hash` Must be `PHC_STRING` for the call to resolve to the correct procedure,
-    // but must be `TEXT` to coerce to the domain type.
+    let mut tx = POOL.begin().await?;
+
+    // HACK: Arguments must have their domain types (e.g. `PHC_STRING`) for the call to resolve to
+    // the correct procedure, but must be `TEXT` to coerce to the domain types.
     match data {
         NewUserData::Customer { profile_picture } => {
             query!(
-                "CALL create_customer($1, $2, ($3::TEXT)::PHC_STRING, $4)",
-                username as Username,
-                email as Email,
+                "CALL create_customer(($1::TEXT)::USERNAME, ($2::TEXT)::EMAIL, ($3::TEXT)::PHC_STRING, ($4::TEXT)::URL)",
+                &username as &Username,
+                &email as &Email,
                 password_hash.as_str(),
-                profile_picture as Url,
+                profile_picture.map(Url::from) as Option<Url>,
             )
         },
         NewUserData::Vendor {
@@ -96,119 +139,517 @@ pub async fn create_user(
             description,
         } => {
             query!(
-                "CALL create_vendor($1, $2, ($3::TEXT)::PHC_STRING, $4, $5, $6)",
-                username as Username,
-                email as Email,
+                "CALL create_vendor(($1::TEXT)::USERNAME, ($2::TEXT)::EMAIL, ($3::TEXT)::PHC_STRING, ($4::TEXT)::URL, $5, $6)",
+                &username as &Username,
+                &email as &Email,
                 password_hash.as_str(),
-                profile_picture as Url,
+                profile_picture.map(Url::from) as Option<Url>,
                 &display_name,
                 &description,
             )
         },
         NewUserData::Administrator => {
             query!(
-                "CALL create_administrator($1, $2, ($3::TEXT)::PHC_STRING)",
-                username as Username,
-                email as Email,
+                "CALL create_administrator(($1::TEXT)::USERNAME, ($2::TEXT)::EMAIL, ($3::TEXT)::PHC_STRING)",
+                &username as &Username,
+                &email as &Email,
                 password_hash.as_str(),
             )
         },
     }
-    .execute(&*POOL)
+    .execute(&mut *tx)
     .await
-    .map(QueryResultExt::expect_one)
-    .map_err(Into::into)
+    .map(QueryResultExt::procedure)
+    .map_err(classify)?;
+
+    let user = query_scalar!(
+        "
+        SELECT id
+        FROM users
+        WHERE username = $1
+        ",
+        &username as &Username,
+    )
+    .fetch_one(&mut *tx)
+    .await?
+    .into();
+    send_verification_email(&mut tx, user, &email).await?;
+
+    tx.commit().await.map_err(Into::into)
+}
+
+/// The name of the cookie holding the session token.
+#[cfg(feature = "server")]
+pub(crate) const SESSION_COOKIE: &str = "session";
+
+/// How long a session remains valid after logging in.
+#[cfg(feature = "server")]
+const SESSION_LIFETIME: Duration = Duration::days(30);
+
+/// How long after confirming their password a user may make sensitive account changes.
+#[cfg(feature = "server")]
+const REAUTHENTICATION_WINDOW: Duration = Duration::minutes(10);
+
+/// The number of random bytes in a session or account token, before hex encoding.
+#[cfg(feature = "server")]
+const TOKEN_BYTES: usize = 32;
+
+/// Generate a new random, hex-encoded session or account token.
+#[cfg(feature = "server")]
+pub(crate) fn generate_token() -> String {
+    let mut bytes = [0; TOKEN_BYTES];
+    OsRng.fill_bytes(&mut bytes);
+    bytes
+        .iter()
+        .fold(String::with_capacity(2 * TOKEN_BYTES), |mut token, byte| {
+            write!(token, "{byte:02x}").expect("Writing to a `String` is infallible.");
+            token
+        })
 }
 
+/// Check whether a token could have been produced by [`generate_token`].
 #[cfg(feature = "server")]
-#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Error)]
-#[error("No user exists with the provided username.")]
-struct IncorrectPassword;
+fn well_formed(token: &str) -> bool {
+    token.len() == 2 * TOKEN_BYTES && token.bytes().all(|byte| byte.is_ascii_hexdigit())
+}
 
+/// Get the token in the cookie `name` sent with the current request, if any.
+///
+/// Malformed tokens are treated as absent, as they can never have been issued.
+///
+/// # Errors
+///
+/// Fails if the request headers could not be extracted.
+#[cfg(feature = "server")]
+pub(crate) async fn cookie_token(name: &str) -> Result<Option<Box<str>>> {
+    let headers = FullstackContext::extract::<HeaderMap, _>().await?;
+    Ok(headers.typed_get::<Cookie>().and_then(|cookie| {
+        cookie
+            .get(name)
+            .filter(|token| well_formed(token))
+            .map(Into::into)
+    }))
+}
+
+/// Get the session token sent with the current request, if any.
+///
+/// # Errors
+///
+/// Fails if the request headers could not be extracted.
 #[cfg(feature = "server")]
-#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Error)]
-#[error("The provided password is incorrect.")]
-struct InvalidUsername;
+pub(crate) async fn session_token() -> Result<Option<Box<str>>> {
+    cookie_token(SESSION_COOKIE).await
+}
 
+/// Log in as a user, starting a new session.
+///
+/// On success, the response sets an HTTP-only cookie holding an opaque session token. The
+/// session itself is stored server-side and can be inspected with [`login_info`].
+///
+/// If the caller has a guest cart and logs in as a customer, the guest cart is merged into theirs
+/// and the response removes its cookie, see
+/// [`merge_guest_cart`](crate::database::cart::merge_guest_cart).
+///
+/// # Errors
+///
+/// Fails if:
+/// - The caller or `username` has attempted to log in too often, or the user is locked out after
+///   repeated incorrect passwords, see
+///   [`AppError::RateLimited`](crate::database::AppError::RateLimited).
+/// - No user exists with the username `username`, see
+///   [`AppError::UnknownUsername`](crate::database::AppError::UnknownUsername).
+/// - `password` is incorrect, see
+///   [`AppError::IncorrectPassword`](crate::database::AppError::IncorrectPassword).
+/// - An error occurs during communication with the database.
 #[server]
 pub async fn log_in(username: Username, password: Box<str>) -> Result<Response> {
     struct User {
         id: i32,
         password_hash: String,
+        customer: bool,
     }
-    let User { id, password_hash } = query_as!(
+
+    limit(Action::LogIn, Some(&**username)).await?;
+    let User {
+        id,
+        password_hash,
+        customer,
+    } = query_as!(
         User,
-        "
-        SELECT id, password_hash
+        r#"
+        SELECT id, password_hash, EXISTS (
+            SELECT 1
+            FROM customers
+            WHERE customers.id = users.id
+        ) AS "customer!"
         FROM users
-        WHERE username = $1
-        ",
+        WHERE username = $1 AND NOT deleted
+        "#,
         &username,
     )
     .fetch_optional(&*POOL)
     .await?
-    .ok_or(InvalidUsername)?;
+    .ok_or(AppError::UnknownUsername)?;
 
-    verify_password(&password, &PasswordHash::new(&password_hash).unwrap())?
-        .then(|| {
-            (
-                [(SET_COOKIE, format!("user_id={}; Path=/; SameSite=Lax", id))],
-                "",
-            )
-                .into_response()
-        })
-        .ok_or(IncorrectPassword)
-        .map_err(Into::into)
+    check_lockout(id.into())?;
+    // Users without a well-formed hash have no usable password.
+    let correct = match PasswordHash::new(&password_hash) {
+        Ok(hash) => verify_password(&password, &hash)?,
+        Err(_) => false,
+    };
+    record_password_attempt(id.into(), correct);
+    if !correct {
+        return Err(AppError::IncorrectPassword.into());
+    }
+
+    let mut tx = POOL.begin().await?;
+
+    let token = generate_token();
+    query!(
+        "
+        INSERT INTO sessions (token_hash, user_id, expires_at)
+        VALUES (sha256(decode($1, 'hex')), $2, CURRENT_TIMESTAMP + $3)
+        ",
+        token,
+        id,
+        PgInterval::try_from(SESSION_LIFETIME).expect("Session lifetime is representable."),
+    )
+    .execute(&mut *tx)
+    .await
+    .map(QueryResultExt::expect_one)?;
+
+    let guest_token = if customer {
+        cookie_token(GUEST_CART_COOKIE).await?
+    } else {
+        None
+    };
+    if let Some(guest_token) = &guest_token {
+        merge_into(&mut tx, id.into(), guest_token, merge_policy()).await?;
+    }
+
+    tx.commit().await?;
+
+    let mut response = (
+        [(
+            SET_COOKIE,
+            format!(
+                "{SESSION_COOKIE}={token}; Path=/; Max-Age={}; HttpOnly; Secure; SameSite=Lax",
+                SESSION_LIFETIME.whole_seconds(),
+            ),
+        )],
+        "",
+    )
+        .into_response();
+    if guest_token.is_some() {
+        let _appended = response.headers_mut().append(
+            SET_COOKIE,
+            HeaderValue::try_from(removed_guest_cart_cookie())?,
+        );
+    }
+    Ok(response)
 }
 
+/// Log out, revoking the current session.
+///
+/// Succeeds even if no session is active.
+///
+/// # Errors
+///
+/// Fails if an error occurs during communication with the database.
 #[server]
 pub async fn log_out() -> Result<Response> {
-    Ok(([(SET_COOKIE, "user_id=; Path=/; Max-Age=0")], "").into_response())
+    if let Some(token) = session_token().await? {
+        query!(
+            "
+            UPDATE sessions
+            SET revoked_at = CURRENT_TIMESTAMP
+            WHERE token_hash = sha256(decode($1, 'hex')) AND revoked_at IS NULL
+            ",
+            &*token,
+        )
+        .execute(&*POOL)
+        .await
+        .map(QueryResultExt::expect_maybe)?;
+    }
+
+    Ok((
+        [(
+            SET_COOKIE,
+            format!("{SESSION_COOKIE}=; Path=/; Max-Age=0; HttpOnly; Secure; SameSite=Lax"),
+        )],
+        "",
+    )
+        .into_response())
+}
+
+/// Check `password` against the password of the user logged in with the current session, and
+/// record that they have just authenticated in it.
+///
+/// Returns the user and the session token.
+///
+/// # Errors
+///
+/// Fails if:
+/// - The caller is not logged in.
+/// - The user is locked out after repeated incorrect passwords, see
+///   [`AppError::RateLimited`](crate::database::AppError::RateLimited).
+/// - `password` is incorrect.
+/// - An error occurs during communication with the database.
+#[cfg(feature = "server")]
+async fn confirm_password(tx: &mut PgConnection, password: &str) -> Result<(Id<User>, Box<str>)> {
+    struct Session {
+        user_id: i32,
+        password_hash: String,
+    }
+
+    let Some(token) = session_token().await? else {
+        return Err(HttpError::from(AuthError::Unauthenticated).into());
+    };
+    let Some(Session {
+        user_id,
+        password_hash,
+    }) = query_as!(
+        Session,
+        "
+        SELECT u.id AS user_id, u.password_hash
+        FROM active_sessions s
+        JOIN users u ON u.id = s.user_id
+        WHERE s.token_hash = sha256(decode($1, 'hex')) AND NOT u.deleted
+        ",
+        &*token,
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    else {
+        return Err(HttpError::from(AuthError::Unauthenticated).into());
+    };
+
+    check_lockout(user_id.into())?;
+    // Users without a well-formed hash have no usable password.
+    let correct = match PasswordHash::new(&password_hash) {
+        Ok(hash) => verify_password(password, &hash)?,
+        Err(_) => false,
+    };
+    record_password_attempt(user_id.into(), correct);
+    if !correct {
+        return Err(AppError::IncorrectPassword.into());
+    }
+
+    query!(
+        "
+        UPDATE sessions
+        SET authenticated_at = CURRENT_TIMESTAMP
+        WHERE token_hash = sha256(decode($1, 'hex'))
+        ",
+        &*token,
+    )
+    .execute(&mut *tx)
+    .await
+    .map(QueryResultExt::expect_one)?;
+
+    Ok((user_id.into(), token))
+}
+
+/// Confirm the caller's password, permitting sensitive account changes in the current session for
+/// a while.
+///
+/// # Errors
+///
+/// Fails if:
+/// - The caller is not logged in.
+/// - The user is locked out after repeated incorrect passwords, see
+///   [`AppError::RateLimited`](crate::database::AppError::RateLimited).
+/// - `password` is incorrect.
+/// - An error occurs during communication with the database.
+#[server]
+pub async fn reauthenticate(password: Box<str>) -> Result<()> {
+    let mut tx = POOL.begin().await?;
+    confirm_password(&mut tx, &password).await.map(drop)?;
+    tx.commit().await.map_err(Into::into)
+}
+
+/// Check whether the caller has logged in or confirmed their password recently in the current
+/// session, see [`reauthenticate`].
+///
+/// Returns `false` if no valid session is active.
+///
+/// # Errors
+///
+/// Fails if an error occurs during communication with the database.
+///
+/// # Panics
+///
+/// Panics if the reauthentication window is not representable as an interval, which it always
+/// is.
+#[cfg(feature = "server")]
+pub(crate) async fn recently_authenticated() -> Result<bool> {
+    let Some(token) = session_token().await? else {
+        return Ok(false);
+    };
+
+    query_scalar!(
+        r#"
+        SELECT EXISTS (
+            SELECT
+            FROM active_sessions
+            WHERE token_hash = sha256(decode($1, 'hex'))
+                AND authenticated_at > CURRENT_TIMESTAMP - $2::INTERVAL
+        ) AS "recent!"
+        "#,
+        &*token,
+        PgInterval::try_from(REAUTHENTICATION_WINDOW)
+            .expect("Reauthentication window is representable."),
+    )
+    .fetch_one(&*POOL)
+    .await
+    .map_err(Into::into)
+}
+
+/// Change the caller's password, logging them out of all other sessions.
+///
+/// # Errors
+///
+/// Fails if:
+/// - The caller is not logged in.
+/// - The user is locked out after repeated incorrect passwords, see
+///   [`AppError::RateLimited`](crate::database::AppError::RateLimited).
+/// - `current_password` is incorrect.
+/// - An error occurs during communication with the database.
+///
+/// # Panics
+///
+/// Panics if hashing the password fails, which it can not with a freshly generated salt.
+#[server]
+pub async fn change_password(current_password: Box<str>, new_password: Box<str>) -> Result<()> {
+    let mut tx = POOL.begin().await?;
+    let (user, token) = confirm_password(&mut tx, &current_password).await?;
+
+    let password_hash = hash_password(&new_password, (&SaltString::generate(OsRng)).into())
+        .expect("Hashing with a generated salt is infallible.")
+        .serialize();
+    query!(
+        "
+        UPDATE users
+        SET password_hash = ($2::TEXT)::PHC_STRING
+        WHERE id = $1
+        ",
+        user.get(),
+        password_hash.as_str(),
+    )
+    .execute(&mut *tx)
+    .await
+    .map(QueryResultExt::expect_one)?;
+
+    query!(
+        "
+        UPDATE sessions
+        SET revoked_at = CURRENT_TIMESTAMP
+        WHERE user_id = $1
+            AND token_hash != sha256(decode($2, 'hex'))
+            AND revoked_at IS NULL
+        ",
+        user.get(),
+        &*token,
+    )
+    .execute(&mut *tx)
+    .await
+    .map(QueryResultExt::allow_any)?;
+
+    tx.commit().await.map_err(Into::into)
 }
 
 #[cfg(feature = "server")]
 struct LoginRepr {
+    id: i32,
     username: String,
     role: Role,
     profile_picture: Option<String>,
 }
 
 #[cfg(feature = "server")]
-impl Login {
-    fn from_repr(
-        id: Id<User>,
+impl From<LoginRepr> for Login {
+    fn from(
         LoginRepr {
+            id,
             username,
             role,
             profile_picture,
         }: LoginRepr,
     ) -> Self {
         Self {
-            id: LoginId::classify(id, role),
+            id: LoginId::classify(id.into(), role),
             username: Username::new(username.into()).expect("Invalid username."),
             profile_picture: ProfilePicture::from_repr(profile_picture, role),
         }
     }
 }
 
+/// Get information about the user logged in with the current session.
+///
+/// Returns `None` if no valid session is active, such as when the session has expired or been
+/// revoked, or when the user has been deleted.
+///
+/// # Errors
+///
+/// Fails if an error occurs during communication with the database.
 #[server]
-pub async fn login_info(user: Id<User>) -> Result<Login> {
+pub async fn login_info() -> Result<Option<Login>> {
+    let Some(token) = session_token().await? else {
+        return Ok(None);
+    };
+
     query_as!(
         LoginRepr,
         r#"
-        SELECT username,
+        SELECT u.id,
+            username,
             role_of(u.id) AS "role!: Role",
             COALESCE(c.profile_picture, v.profile_picture) AS profile_picture
-        FROM users u
+        FROM active_sessions s
+        JOIN users u ON u.id = s.user_id
         LEFT JOIN customers c ON c.id = u.id
         LEFT JOIN vendors v ON v.id = u.id
-        WHERE u.id = $1
+        WHERE s.token_hash = sha256(decode($1, 'hex')) AND NOT u.deleted
         "#,
-        user.get(),
+        &*token,
     )
-    .fetch_one(&*POOL)
+    .fetch_optional(&*POOL)
+    .await
+    .map(|repr| repr.map(Into::into))
+    .map_err(Into::into)
+}
+
+/// Get the ID and role of the user logged in with the current session, if any.
+///
+/// This is the server-side counterpart to [`login_info`].
+///
+/// # Errors
+///
+/// Fails if an error occurs during communication with the database.
+#[cfg(feature = "server")]
+pub(crate) async fn session_caller() -> Result<Option<LoginId>> {
+    struct Caller {
+        id: i32,
+        role: Role,
+    }
+
+    let Some(token) = session_token().await? else {
+        return Ok(None);
+    };
+
+    query_as!(
+        Caller,
+        r#"
+        SELECT u.id, role_of(u.id) AS "role!: Role"
+        FROM active_sessions s
+        JOIN users u ON u.id = s.user_id
+        WHERE s.token_hash = sha256(decode($1, 'hex')) AND NOT u.deleted
+        "#,
+        &*token,
+    )
+    .fetch_optional(&*POOL)
     .await
-    .map(|repr| Login::from_repr(user, repr))
+    .map(|caller| caller.map(|Caller { id, role }| LoginId::classify(id.into(), role)))
     .map_err(Into::into)
 }
 
@@ -271,6 +712,16 @@ impl PartialEq<Id<Administrator>> for LoginId {
     }
 }
 
+impl From<LoginId> for Id<User> {
+    fn from(value: LoginId) -> Self {
+        match value {
+            LoginId::Customer(id) => id.into(),
+            LoginId::Vendor(id) => id.into(),
+            LoginId::Administrator(id) => id.into(),
+        }
+    }
+}
+
 impl LoginId {
     /// Construct a `LoginId` from a generic user ID and a role.
     pub fn classify(id: Id<User>, role: Role) -> Self {
@@ -283,46 +734,337 @@ impl LoginId {
     }
 }
 
-// TODO: Remove.
-#[cfg(false)]
-mod usage {
-    use super::login_info;
-    use crate::database::{Id, RawId, User};
-    use dioxus::prelude::*;
-
-    #[component]
-    fn Example() -> Element {
-        let mut user_id = use_signal(|| None);
-        _ = use_effect(move || {
-            use web_sys::{HtmlDocument, wasm_bindgen::JsCast as _, window};
-            if let Some(window) = window()
-                && let Some(document) = window.document()
-                && let Ok(html) = document.dyn_into::<HtmlDocument>()
-                && let Ok(cookies) = html.cookie()
-                && let Some(value) = cookies
-                    .split(';')
-                    .filter_map(|pair| pair.split_once('='))
-                    .find(|(key, _)| key.trim() == "user_id")
-                    .map(|(_, value)| value)
-                && let Ok(id) = value.parse::<RawId>()
-            {
-                user_id.set(Some(Id::<User>::from(id)))
-            }
-        });
-        let login_info = use_server_future(move || async move {
-            if let Some(user_id) = user_id() {
-                Some(login_info(user_id).await.unwrap())
-            } else {
-                None
-            }
-        })?;
-
-        rsx! {
-            match login_info() {
-                Some(Some(login)) => rsx! { "Logged in as {login.username}" },
-                Some(None) => rsx! { "Not logged in." },
-                None => rsx! { "Loading..." },
-            }
+/// A link sent by email was invalid, has already been used or has expired.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Error, Serialize, Deserialize)]
+#[error("The link is invalid or has expired.")]
+pub struct InvalidToken;
+
+/// What a token sent by email can be used for.
+#[cfg(feature = "server")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Type)]
+#[sqlx(type_name = "account_token_purpose", rename_all = "snake_case")]
+enum TokenPurpose {
+    VerifyEmail,
+    ResetPassword,
+}
+
+#[cfg(feature = "server")]
+impl TokenPurpose {
+    /// How long a token remains valid after being sent.
+    const fn lifetime(self) -> Duration {
+        match self {
+            Self::VerifyEmail => Duration::days(2),
+            Self::ResetPassword => Duration::hours(1),
+        }
+    }
+
+    /// Write the mail carrying `token` to `to`.
+    fn mail(self, to: Email, token: &str) -> Mail {
+        let (subject, body) = match self {
+            Self::VerifyEmail => (
+                "Bekräfta din e-postadress",
+                format!(
+                    "Hej!\n\nBekräfta din e-postadress hos boop genom att öppna länken nedan. \
+                    Länken är giltig i två dygn.\n\n{}\n\n\
+                    Om du inte har skapat ett konto kan du bortse från det här meddelandet.",
+                    link(&format!("/verify/{token}")),
+                ),
+            ),
+            Self::ResetPassword => (
+                "Återställ ditt lösenord",
+                format!(
+                    "Hej!\n\nNågon har begärt att lösenordet till ditt konto hos boop ska \
+                    återställas. Öppna länken nedan för att välja ett nytt lösenord. Länken är \
+                    giltig i en timme.\n\n{}\n\n\
+                    Om det inte var du kan du bortse från det här meddelandet.",
+                    link(&format!("/reset/{token}")),
+                ),
+            ),
+        };
+        Mail {
+            to,
+            subject: subject.into(),
+            body: body.into(),
         }
     }
 }
+
+/// Issue a single-use token for `user` and mail it to `email`.
+///
+/// Earlier unused tokens with the same purpose are revoked. The token only becomes usable once
+/// `tx` is committed.
+///
+/// # Errors
+///
+/// Fails if:
+/// - The mail could not be sent.
+/// - An error occurs during communication with the database.
+///
+/// # Panics
+///
+/// Panics if the token lifetime is not representable as an interval, which it always is.
+#[cfg(feature = "server")]
+async fn send_token(
+    tx: &mut PgConnection,
+    user: Id<User>,
+    email: &Email,
+    purpose: TokenPurpose,
+) -> Result<()> {
+    query!(
+        "
+        DELETE FROM account_tokens
+        WHERE user_id = $1 AND purpose = $2 AND used_at IS NULL
+        ",
+        user.get(),
+        purpose as TokenPurpose,
+    )
+    .execute(&mut *tx)
+    .await
+    .map(QueryResultExt::allow_any)?;
+
+    let token = generate_token();
+    query!(
+        "
+        INSERT INTO account_tokens (token_hash, user_id, purpose, email, expires_at)
+        VALUES (sha256(decode($1, 'hex')), $2, $3, ($4::TEXT)::EMAIL, CURRENT_TIMESTAMP + $5)
+        ",
+        token,
+        user.get(),
+        purpose as TokenPurpose,
+        email as &Email,
+        PgInterval::try_from(purpose.lifetime()).expect("Token lifetime is representable."),
+    )
+    .execute(&mut *tx)
+    .await
+    .map(QueryResultExt::expect_one)?;
+
+    send_mail(purpose.mail(email.clone(), &token)).await
+}
+
+/// Mail a link to verify `email` to `user`, see [`verify_email`].
+///
+/// The link only becomes usable once `tx` is committed.
+///
+/// # Errors
+///
+/// Fails if:
+/// - The mail could not be sent.
+/// - An error occurs during communication with the database.
+#[cfg(feature = "server")]
+pub(crate) async fn send_verification_email(
+    tx: &mut PgConnection,
+    user: Id<User>,
+    email: &Email,
+) -> Result<()> {
+    send_token(tx, user, email, TokenPurpose::VerifyEmail).await
+}
+
+/// Use up a token, returning the user it was issued to.
+///
+/// Tokens sent to an address that is no longer the user's are rejected.
+///
+/// # Errors
+///
+/// Fails if:
+/// - `token` is invalid, see [`InvalidToken`].
+/// - An error occurs during communication with the database.
+#[cfg(feature = "server")]
+async fn consume_token(
+    tx: &mut PgConnection,
+    token: &str,
+    purpose: TokenPurpose,
+) -> Result<Id<User>> {
+    if !well_formed(token) {
+        return Err(InvalidToken.into());
+    }
+
+    query_scalar!(
+        "
+        UPDATE account_tokens t
+        SET used_at = CURRENT_TIMESTAMP
+        FROM users u
+        WHERE t.token_hash = sha256(decode($1, 'hex'))
+            AND t.purpose = $2
+            AND t.used_at IS NULL
+            AND t.expires_at > CURRENT_TIMESTAMP
+            AND u.id = t.user_id
+            AND u.email = t.email
+            AND NOT u.deleted
+        RETURNING t.user_id
+        ",
+        token,
+        purpose as TokenPurpose,
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    .map(Into::into)
+    .ok_or_else(|| InvalidToken.into())
+}
+
+/// Verify a user's email using a token mailed to them.
+///
+/// # Errors
+///
+/// Fails if:
+/// - `token` is invalid, see [`InvalidToken`].
+/// - An error occurs during communication with the database.
+#[server]
+pub async fn verify_email(token: Box<str>) -> Result<()> {
+    let mut tx = POOL.begin().await?;
+    let user = consume_token(&mut tx, &token, TokenPurpose::VerifyEmail).await?;
+
+    query!(
+        "
+        UPDATE users
+        SET email_verified_at = CURRENT_TIMESTAMP
+        WHERE id = $1
+        ",
+        user.get(),
+    )
+    .execute(&mut *tx)
+    .await
+    .map(QueryResultExt::expect_one)?;
+
+    tx.commit().await.map_err(Into::into)
+}
+
+/// Check whether the caller has verified their email.
+///
+/// # Errors
+///
+/// Fails if:
+/// - The caller is not logged in.
+/// - An error occurs during communication with the database.
+#[server]
+pub async fn email_verified() -> Result<bool> {
+    let user = Id::<User>::from(caller().await?);
+
+    query_scalar!(
+        r#"
+        SELECT email_verified_at IS NOT NULL AS "verified!"
+        FROM users
+        WHERE id = $1
+        "#,
+        user.get(),
+    )
+    .fetch_one(&*POOL)
+    .await
+    .map_err(Into::into)
+}
+
+/// Send the caller a new link to verify their email, revoking any earlier ones.
+///
+/// Does nothing if their email is already verified.
+///
+/// # Errors
+///
+/// Fails if:
+/// - The caller is not logged in.
+/// - The mail could not be sent.
+/// - An error occurs during communication with the database.
+#[server]
+pub async fn resend_verification_email() -> Result<()> {
+    let user = Id::<User>::from(caller().await?);
+
+    let mut tx = POOL.begin().await?;
+    let email = query_scalar!(
+        r#"
+        SELECT email AS "email: Email"
+        FROM users
+        WHERE id = $1 AND email_verified_at IS NULL
+        "#,
+        user.get(),
+    )
+    .fetch_optional(&mut *tx)
+    .await?;
+    if let Some(email) = email {
+        send_verification_email(&mut tx, user, &email).await?;
+    }
+
+    tx.commit().await.map_err(Into::into)
+}
+
+/// Mail a link to reset the password of the user with the email `email`, see [`reset_password`].
+///
+/// Succeeds without sending anything if no such user exists, so as to not reveal which addresses
+/// are registered.
+///
+/// # Errors
+///
+/// Fails if:
+/// - The caller or `email` has requested too many resets, see
+///   [`AppError::RateLimited`](crate::database::AppError::RateLimited).
+/// - The mail could not be sent.
+/// - An error occurs during communication with the database.
+#[server]
+pub async fn request_password_reset(email: Email) -> Result<()> {
+    limit(Action::RequestPasswordReset, Some(&**email)).await?;
+
+    let mut tx = POOL.begin().await?;
+    let user = query_scalar!(
+        "
+        SELECT id
+        FROM users
+        WHERE email = $1::CITEXT AND NOT deleted
+        ",
+        &email,
+    )
+    .fetch_optional(&mut *tx)
+    .await?;
+    if let Some(user) = user {
+        send_token(&mut tx, user.into(), &email, TokenPurpose::ResetPassword).await?;
+    }
+
+    tx.commit().await.map_err(Into::into)
+}
+
+/// Set a new password using a token mailed to the user, logging them out everywhere.
+///
+/// As the token proves that the user can read mail sent to their address, their email is also
+/// considered verified.
+///
+/// # Errors
+///
+/// Fails if:
+/// - `token` is invalid, see [`InvalidToken`].
+/// - An error occurs during communication with the database.
+///
+/// # Panics
+///
+/// Panics if hashing the password fails, which it can not with a freshly generated salt.
+#[server]
+pub async fn reset_password(token: Box<str>, password: Box<str>) -> Result<()> {
+    let mut tx = POOL.begin().await?;
+    let user = consume_token(&mut tx, &token, TokenPurpose::ResetPassword).await?;
+
+    let password_hash = hash_password(&password, (&SaltString::generate(OsRng)).into())
+        .expect("Hashing with a generated salt is infallible.")
+        .serialize();
+    query!(
+        "
+        UPDATE users
+        SET password_hash = ($2::TEXT)::PHC_STRING,
+            email_verified_at = COALESCE(email_verified_at, CURRENT_TIMESTAMP)
+        WHERE id = $1
+        ",
+        user.get(),
+        password_hash.as_str(),
+    )
+    .execute(&mut *tx)
+    .await
+    .map(QueryResultExt::expect_one)?;
+
+    query!(
+        "
+        UPDATE sessions
+        SET revoked_at = CURRENT_TIMESTAMP
+        WHERE user_id = $1 AND revoked_at IS NULL
+        ",
+        user.get(),
+    )
+    .execute(&mut *tx)
+    .await
+    .map(QueryResultExt::allow_any)?;
+
+    tx.commit().await.map_err(Into::into)
+}
@@ -0,0 +1,360 @@
+//! Database functions for interacting with bundles.
+//!
+//! Unlike special offers, which discount a single product, bundles are deals spanning several
+//! products of a vendor, e.g. "any 3 yoghurts for 40 kr" or "pasta and sauce for 35 kr". The units
+//! of a cart are allocated to bundles deterministically, and the rest are priced with special
+//! offers as usual. See [`QuoteLine::bundles`](crate::database::cart::QuoteLine::bundles) for how
+//! the allocation is shown, and [`checkout`](crate::database::cart::checkout) for how it is
+//! charged.
+//!
+//! Bundles can not be changed once created, only ended, so that carts priced with a bundle are
+//! known to be stale when it changes.
+
+use crate::database::{Bundle, Category, Id, Product, Vendor};
+use dioxus::prelude::*;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::num::NonZeroU32;
+use time::PrimitiveDateTime;
+#[cfg(feature = "server")]
+use {
+    crate::database::{
+        AppError, POOL, QueryResultExt as _, RawId, authorize_bundle_owner,
+        authorize_product_owner, authorize_vendor, classify,
+    },
+    sqlx::{query, query_as, query_scalar},
+};
+
+/// The columns `quantity` and `category` of `bundles`, and the columns `product` and `number` of
+/// `bundle_products`, see [`BundleDeal::database_repr`].
+#[cfg(feature = "server")]
+type BundleDealRepr = (Option<i32>, Option<RawId>, Vec<RawId>, Vec<i32>);
+
+/// Which units a bundle takes.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum BundleDeal {
+    /// Any `quantity` units among `products`, e.g. "any 3 yoghurts".
+    MixAndMatch {
+        /// How many units are taken each time, at least 2.
+        quantity: NonZeroU32,
+        /// The products the units may be of.
+        products: Box<[Id<Product>]>,
+    },
+    /// Any `quantity` units of the vendor's products in `category` or its subcategories.
+    Category {
+        /// How many units are taken each time, at least 2.
+        quantity: NonZeroU32,
+        /// The category.
+        category: Id<Category>,
+    },
+    /// A fixed number of units of each of several products, e.g. "pasta and sauce".
+    Fixed {
+        /// The products along with how many units of each are taken each time.
+        products: Box<[(Id<Product>, NonZeroU32)]>,
+    },
+}
+
+impl BundleDeal {
+    /// Construct a [`BundleDeal`] from its representation in the database.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the values do not uphold any of the database's invariants.
+    #[cfg(feature = "server")]
+    #[expect(clippy::unreachable, reason = "Database validation only.")]
+    fn from_repr(
+        quantity: Option<i32>,
+        category: Option<RawId>,
+        products: Vec<RawId>,
+        numbers: Vec<i32>,
+    ) -> Self {
+        let positive = |n: i32| {
+            u32::try_from(n)
+                .ok()
+                .and_then(NonZeroU32::new)
+                .expect("Database returned non-positive bundle quantity.")
+        };
+        match (quantity.map(positive), category) {
+            (Some(quantity), Some(category)) => Self::Category {
+                quantity,
+                category: category.into(),
+            },
+            (Some(quantity), None) => Self::MixAndMatch {
+                quantity,
+                products: products.into_iter().map(Into::into).collect(),
+            },
+            (None, None) => Self::Fixed {
+                products: products
+                    .into_iter()
+                    .map(Into::into)
+                    .zip(numbers.into_iter().map(positive))
+                    .collect(),
+            },
+            (None, Some(_)) => unreachable!("Database returned inconsistent bundle."),
+        }
+    }
+
+    /// Convert a [`BundleDeal`] into the format used in the database.
+    ///
+    /// # Errors
+    ///
+    /// Fails if a quantity or number is greater than [`i32::MAX`].
+    #[cfg(feature = "server")]
+    fn database_repr(&self) -> Result<BundleDealRepr> {
+        Ok(match self {
+            Self::MixAndMatch { quantity, products } => (
+                Some(i32::try_from(quantity.get())?),
+                None,
+                products.iter().map(|product| product.get()).collect(),
+                vec![1; products.len()],
+            ),
+            Self::Category { quantity, category } => (
+                Some(i32::try_from(quantity.get())?),
+                Some(category.get()),
+                Vec::new(),
+                Vec::new(),
+            ),
+            Self::Fixed { products } => (
+                None,
+                None,
+                products.iter().map(|(product, _)| product.get()).collect(),
+                products
+                    .iter()
+                    .map(|(_, number)| i32::try_from(number.get()))
+                    .collect::<Result<_, _>>()?,
+            ),
+        })
+    }
+
+    /// The products listed in the deal. Empty for [`BundleDeal::Category`].
+    #[cfg(feature = "server")]
+    fn products(&self) -> Box<[Id<Product>]> {
+        match self {
+            Self::MixAndMatch { products, .. } => products.clone(),
+            Self::Category { .. } => Box::default(),
+            Self::Fixed { products } => products.iter().map(|&(product, _)| product).collect(),
+        }
+    }
+}
+
+/// A bundle offered by a vendor.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BundleInfo {
+    /// The ID of the bundle.
+    pub id: Id<Bundle>,
+    /// The vendor offering the bundle.
+    pub vendor: Id<Vendor>,
+    /// The name shown to customers, e.g. "Valfria 3 yoghurtar för 40 kr".
+    pub name: Box<str>,
+    /// Which units the bundle takes.
+    pub deal: BundleDeal,
+    /// What is paid for the units each time the bundle is applied.
+    pub price: Decimal,
+    /// When the bundle starts.
+    pub valid_from: PrimitiveDateTime,
+    /// When the bundle ends, if ever.
+    pub valid_until: Option<PrimitiveDateTime>,
+}
+
+#[cfg(feature = "server")]
+struct BundleRepr {
+    id: RawId,
+    vendor: RawId,
+    name: String,
+    price: Decimal,
+    quantity: Option<i32>,
+    category: Option<RawId>,
+    products: Vec<RawId>,
+    numbers: Vec<i32>,
+    valid_from: PrimitiveDateTime,
+    valid_until: Option<PrimitiveDateTime>,
+}
+
+#[cfg(feature = "server")]
+impl From<BundleRepr> for BundleInfo {
+    fn from(
+        BundleRepr {
+            id,
+            vendor,
+            name,
+            price,
+            quantity,
+            category,
+            products,
+            numbers,
+            valid_from,
+            valid_until,
+        }: BundleRepr,
+    ) -> Self {
+        Self {
+            id: id.into(),
+            vendor: vendor.into(),
+            name: name.into(),
+            deal: BundleDeal::from_repr(quantity, category, products, numbers),
+            price,
+            valid_from,
+            valid_until,
+        }
+    }
+}
+
+/// Get the active bundles a product may be bought in, oldest first.
+///
+/// # Errors
+///
+/// Fails if an error occurs during communication with the database.
+#[server]
+pub async fn product_bundles(product: Id<Product>) -> Result<Box<[BundleInfo]>> {
+    query_as!(
+        BundleRepr,
+        r#"
+        SELECT b.id, b.vendor, b.name, b.price,
+            b.quantity, b.category,
+            ARRAY(
+                SELECT bp.product
+                FROM bundle_products bp
+                WHERE bp.bundle = b.id
+                ORDER BY bp.product
+            ) AS "products!",
+            ARRAY(
+                SELECT bp.number::INT
+                FROM bundle_products bp
+                WHERE bp.bundle = b.id
+                ORDER BY bp.product
+            ) AS "numbers!",
+            b.valid_from, b.valid_until
+        FROM bundles b
+        JOIN products p ON p.id = $1
+        WHERE b.id IN (SELECT id FROM active_bundles) AND bundle_includes(b, p)
+        ORDER BY b.id
+        "#,
+        product.get(),
+    )
+    .fetch_all(&*POOL)
+    .await
+    .map(|bundles| bundles.into_iter().map(Into::into).collect())
+    .map_err(Into::into)
+}
+
+/// Create a bundle, returning its ID.
+///
+/// Bundles with an end time of `None` must be ended manually, see [`end_bundle`].
+///
+/// # Errors
+///
+/// Fails if:
+/// - `vendor` or the category of `deal` is invalid.
+/// - `name` is empty.
+/// - `deal` takes fewer than 2 units each time, lists no products, or lists fewer than 2 products
+///   for [`BundleDeal::Fixed`], see [`AppError::Invalid`].
+/// - `deal` lists a product more than once.
+/// - A quantity or number in `deal` is greater than [`i32::MAX`].
+/// - `valid_until` is not after `valid_from`.
+/// - The caller is not `vendor`, or `deal` lists a product not sold by `vendor`.
+/// - An error occurs during communication with the database.
+#[server]
+pub async fn create_bundle(
+    vendor: Id<Vendor>,
+    name: Box<str>,
+    deal: BundleDeal,
+    price: Decimal,
+    valid_from: PrimitiveDateTime,
+    valid_until: Option<PrimitiveDateTime>,
+) -> Result<Id<Bundle>> {
+    authorize_vendor(vendor).await?;
+    let listed = deal.products();
+    for &product in &listed {
+        authorize_product_owner(product, false).await?;
+    }
+    let too_few = match deal {
+        BundleDeal::MixAndMatch { .. } => listed.is_empty(),
+        BundleDeal::Category { .. } => false,
+        BundleDeal::Fixed { .. } => listed.len() < 2,
+    };
+    if too_few {
+        return Err(AppError::Invalid {
+            constraint: "bundle_products".into(),
+        }
+        .into());
+    }
+
+    let (quantity, category, products, numbers) = deal.database_repr()?;
+
+    let mut tx = POOL.begin().await?;
+    let bundle = query_scalar!(
+        "
+        INSERT INTO bundles (vendor, name, price, quantity, category, valid_from, valid_until)
+        VALUES ($1, $2, $3::DECIMAL, $4, $5, $6, $7)
+        RETURNING id
+        ",
+        vendor.get(),
+        name.trim(),
+        price,
+        quantity,
+        category,
+        valid_from,
+        valid_until,
+    )
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(classify)?;
+    let _inserted = query!(
+        "
+        INSERT INTO bundle_products (bundle, product, number)
+        SELECT $1, product, number
+        FROM UNNEST($2::INT[], $3::INT[]) AS i(product, number)
+        ",
+        bundle,
+        &products,
+        &numbers,
+    )
+    .execute(&mut *tx)
+    .await
+    .map_err(classify)?;
+    tx.commit().await?;
+
+    Ok(bundle.into())
+}
+
+/// End a bundle at once, or delete it if it has not started yet.
+///
+/// Carts priced with the bundle are then stale, see
+/// [`StaleReason::BundleChanged`](crate::database::StaleReason::BundleChanged). Orders placed with
+/// the bundle keep its name and what was paid.
+///
+/// # Errors
+///
+/// Fails if:
+/// - `bundle` is invalid.
+/// - The caller is not the vendor offering `bundle`.
+/// - An error occurs during communication with the database.
+#[server]
+pub async fn end_bundle(bundle: Id<Bundle>) -> Result<()> {
+    authorize_bundle_owner(bundle).await?;
+
+    let mut tx = POOL.begin().await?;
+    query!(
+        "
+        DELETE FROM bundles
+        WHERE id = $1 AND valid_from >= CURRENT_TIMESTAMP
+        ",
+        bundle.get(),
+    )
+    .execute(&mut *tx)
+    .await?
+    .expect_maybe();
+    query!(
+        "
+        UPDATE bundles
+        SET valid_until = CURRENT_TIMESTAMP
+        WHERE id = $1
+            AND valid_from < CURRENT_TIMESTAMP
+            AND (valid_until IS NULL OR valid_until > CURRENT_TIMESTAMP)
+        ",
+        bundle.get(),
+    )
+    .execute(&mut *tx)
+    .await?
+    .expect_maybe();
+    tx.commit().await.map_err(Into::into)
+}
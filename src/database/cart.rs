@@ -1,6 +1,9 @@
 //! Database functions for interacting with a customer's shopping cart.
 
-use crate::database::{Customer, Deal, Id, Product, SpecialOffer, Url};
+use crate::database::{
+    Address, Bundle, Customer, Deal, Id, Order, PriceTiers, Product, SpecialOffer, StaleReason,
+    Url, delivery::Shipment, payments::CardNumber, promotions::PromotionUse,
+};
 use dioxus::prelude::*;
 use hashbrown::HashMap;
 use rust_decimal::Decimal;
@@ -9,13 +12,17 @@ use std::num::NonZeroU32;
 use time::PrimitiveDateTime;
 #[cfg(feature = "server")]
 use {
-    crate::database::{POOL, QueryResultExt},
+    crate::database::{
+        AppError, POOL, PriceTierRepr, QueryResultExt, authorize_customer,
+        authorize_verified_customer, classify, delivery::ShipmentRepr, payments,
+        promotions::PromotionUseRepr,
+    },
     sqlx::{Type, query, query_as, query_scalar},
     std::num::{NonZero, TryFromIntError},
 };
 
-// TODO: Function to get items in cart for display on cart page, include prices with discounts,
-// separate member/nonmember prices? Must include time of reading, see `checkout`.
+mod guest;
+pub use guest::*;
 
 #[cfg(feature = "server")]
 struct CartCountRepr {
@@ -36,9 +43,12 @@ pub type Counts = HashMap<Id<Product>, NonZeroU32>;
 ///
 /// Fails if:
 /// - `customer` is invalid.
+/// - The caller is not logged in as `customer`.
 /// - An error occurs during communication with the database.
 #[server]
 pub async fn cart_counts(customer: Id<Customer>) -> Result<HashMap<Id<Product>, NonZeroU32>> {
+    authorize_customer(customer).await?;
+
     query_as!(
         CartCountRepr,
         r#"
@@ -75,6 +85,7 @@ pub async fn cart_counts(customer: Id<Customer>) -> Result<HashMap<Id<Product>,
 /// Fails if:
 /// - `customer` or `product` is invalid.
 /// - `number > i32::MAX`.
+/// - The caller is not logged in as `customer`.
 /// - An error occurs during communication with the database.
 #[server]
 pub async fn set_in_shopping_cart(
@@ -82,6 +93,8 @@ pub async fn set_in_shopping_cart(
     product: Id<Product>,
     number: u32,
 ) -> Result<()> {
+    authorize_customer(customer).await?;
+
     if number == 0 {
         query!(
             "
@@ -94,13 +107,13 @@ pub async fn set_in_shopping_cart(
         .execute(&*POOL)
         .await
         .map(QueryResultExt::expect_maybe)
-        .map_err(Into::into)
+        .map_err(classify)
     } else {
         query!(
             "
             INSERT INTO shopping_cart_items (customer, product, number)
             VALUES ($1, $2, $3::INT)
-            ON CONFLICT (customer, product) DO UPDATE
+            ON CONFLICT (customer, product) WHERE product IS NOT NULL DO UPDATE
             SET number = EXCLUDED.number
             ",
             customer.get(),
@@ -110,7 +123,7 @@ pub async fn set_in_shopping_cart(
         .execute(&*POOL)
         .await
         .map(QueryResultExt::allow_any)
-        .map_err(Into::into)
+        .map_err(classify)
     }
 }
 
@@ -120,9 +133,12 @@ pub async fn set_in_shopping_cart(
 ///
 /// Fails if:
 /// - `customer` is invalid.
+/// - The caller is not logged in as `customer`.
 /// - An error occurs during communication with the database.
 #[server]
 pub async fn remove_deleted_from_cart(customer: Id<Customer>) -> Result<()> {
+    authorize_customer(customer).await?;
+
     query!(
         "
         DELETE FROM shopping_cart_items
@@ -147,14 +163,17 @@ pub struct CartProduct {
     pub thumbnail: Url,
     /// The price of the product before any discounts.
     pub price: Decimal,
+    /// The volume pricing of the product, see [`unit_price`](Self::unit_price).
+    pub price_tiers: PriceTiers,
     /// How many units are in stock. This should not be displayed directly, but may be used
     /// together with `count` to display "low stock".
     pub in_stock: u32,
     /// How many units are in the cart.
     pub count: NonZeroU32,
-    /// special offer ID
-    pub special_offer_id: Option<Id<crate::database::SpecialOffer>>,
-    /// The currently active special offer on the product, if any and if the user is eligible.
+    /// The ID of the special offer in `special_offer_deal`, if any.
+    pub special_offer_id: Option<Id<SpecialOffer>>,
+    /// The special offer giving the lowest price for `count` units among the active ones the
+    /// customer is eligible for, if any.
     pub special_offer_deal: Option<Deal>,
     /// How many more times the customer can benefit from the special offer, if there's a limit.
     /// Value is unspecified if `special_offer_deal` is `None`.
@@ -167,12 +186,21 @@ pub struct CartProduct {
     pub favorited: bool,
 }
 
+impl CartProduct {
+    /// The price per unit for `count` units at the price tier reached, before special offers.
+    #[must_use]
+    pub fn unit_price(&self) -> Decimal {
+        self.price_tiers.unit_price(self.count, self.price)
+    }
+}
+
 #[cfg(feature = "server")]
 struct CartProductRepr {
     id: i32,
     name: String,
     thumbnail: Url,
     price: Decimal,
+    price_tiers: Vec<PriceTierRepr>,
     in_stock: i32,
     count: i32,
     special_offer_id: Option<i32>,
@@ -192,6 +220,7 @@ impl From<CartProductRepr> for CartProduct {
             name,
             thumbnail,
             price,
+            price_tiers,
             in_stock,
             count,
             special_offer_id,
@@ -208,6 +237,7 @@ impl From<CartProductRepr> for CartProduct {
             name: name.into(),
             thumbnail,
             price,
+            price_tiers: PriceTiers::from_repr(price_tiers, price),
             in_stock: in_stock
                 .try_into()
                 .expect("Database returned negative stock."),
@@ -232,10 +262,18 @@ impl From<CartProductRepr> for CartProduct {
 /// valid.
 ///
 /// The timestamp returned from this function is the one that should be passed to [`checkout`].
+///
+/// # Errors
+///
+/// Fails if:
+/// - The caller is not logged in as `customer`.
+/// - An error occurs during communication with the database.
 #[server]
 pub async fn cart_products(
     customer: Id<Customer>,
 ) -> Result<(Box<[CartProduct]>, PrimitiveDateTime)> {
+    authorize_customer(customer).await?;
+
     let mut tx = POOL.begin().await?;
 
     let time = query_scalar!(r#"SELECT CURRENT_TIMESTAMP::TIMESTAMP AS "time!""#)
@@ -245,7 +283,9 @@ pub async fn cart_products(
     let products = query_as!(
         CartProductRepr,
         r#"
-        SELECT p.id, name, thumbnail, price, in_stock, s.number AS count,
+        SELECT p.id, name, thumbnail, price,
+            product_price_tiers(p.id) AS "price_tiers!: Vec<PriceTierRepr>",
+            in_stock, s.number AS count,
             aso.id AS special_offer_id,
             new_price, quantity1, quantity2, COALESCE(members_only, FALSE) AS "members_only!",
             limit_per_customer - COALESCE(sou.number, 0) AS remaining_uses,
@@ -257,8 +297,8 @@ pub async fn cart_products(
         FROM shopping_cart_items s
         JOIN products p ON p.id = s.product
         JOIN customers ON customers.id = $1
-        LEFT JOIN active_special_offers aso ON aso.product = s.product
-            AND (NOT members_only OR member_since IS NOT NULL)
+        LEFT JOIN LATERAL best_special_offer(p.id, $1, s.number) aso
+            ON NOT aso.members_only OR current_member(customers)
         LEFT JOIN special_offer_uses sou ON special_offer = aso.id AND sou.customer = $1
         WHERE s.customer = $1 AND s.number > 0
         "#,
@@ -275,6 +315,277 @@ pub async fn cart_products(
     Ok((products, time))
 }
 
+/// A line of a [`Quote`].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QuoteLine {
+    /// The product, as it would be returned by [`cart_products`].
+    pub product: CartProduct,
+    /// The price of all units after discounts. This is the price charged by [`checkout`].
+    pub price: Decimal,
+    /// How units are allocated to bundles, see [`bundles`](crate::database::bundles), in the order
+    /// the bundles were created.
+    pub bundles: Box<[BundleShare]>,
+    /// How many times the special offer is applied to get `price`. It only applies to units not
+    /// allocated to a bundle.
+    pub offer_uses: u32,
+    /// How many more times the customer can benefit from the special offer after this order, if
+    /// there's a limit. `None` if there is no special offer.
+    pub remaining_uses: Option<u32>,
+}
+
+impl QuoteLine {
+    /// The price of all units before price tiers and discounts.
+    #[must_use]
+    pub fn base_price(&self) -> Decimal {
+        self.product.price * Decimal::from(self.product.count.get())
+    }
+}
+
+/// Units of a [`QuoteLine`] allocated to a bundle.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BundleShare {
+    /// The ID of the bundle.
+    pub bundle: Id<Bundle>,
+    /// The name of the bundle.
+    pub name: Box<str>,
+    /// How many units of the product are allocated to the bundle.
+    pub units: NonZeroU32,
+    /// The part of the price of the bundle paid for the units.
+    pub price: Decimal,
+}
+
+#[cfg(feature = "server")]
+struct BundleShareRepr {
+    bundle: i32,
+    name: String,
+    product: i32,
+    units: i32,
+    price: Decimal,
+}
+
+#[cfg(feature = "server")]
+impl From<BundleShareRepr> for (Id<Product>, BundleShare) {
+    fn from(
+        BundleShareRepr {
+            bundle,
+            name,
+            product,
+            units,
+            price,
+        }: BundleShareRepr,
+    ) -> Self {
+        (
+            product.into(),
+            BundleShare {
+                bundle: bundle.into(),
+                name: name.into(),
+                units: u32::try_from(units)
+                    .ok()
+                    .and_then(NonZeroU32::new)
+                    .expect("Database returned non-positive bundled units."),
+                price,
+            },
+        )
+    }
+}
+
+/// The contents of a customer's cart along with what checking out would cost, see [`cart_quote`].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Quote {
+    /// The products in the cart.
+    pub lines: Box<[QuoteLine]>,
+    /// The price of all products before discounts.
+    pub subtotal: Decimal,
+    /// How much is saved by price tiers, special offers and bundles.
+    pub discount: Decimal,
+    /// The price to pay.
+    pub total: Decimal,
+    /// The time when the quote was known to be valid, to be passed to [`checkout`].
+    pub seen_at: PrimitiveDateTime,
+}
+
+impl Quote {
+    /// The items to check out with to pay exactly what is quoted.
+    #[must_use]
+    pub fn items(&self) -> Vec<CheckoutItem> {
+        self.lines.iter().map(CheckoutItem::from).collect()
+    }
+}
+
+#[cfg(feature = "server")]
+struct QuoteLineRepr {
+    id: i32,
+    name: String,
+    thumbnail: Url,
+    price: Decimal,
+    price_tiers: Vec<PriceTierRepr>,
+    in_stock: i32,
+    count: i32,
+    special_offer_id: Option<i32>,
+    new_price: Option<Decimal>,
+    quantity1: Option<i32>,
+    quantity2: Option<i32>,
+    members_only: bool,
+    remaining_uses: Option<i32>,
+    favorited: bool,
+    line_price: Decimal,
+    offer_uses: i32,
+}
+
+#[cfg(feature = "server")]
+impl From<QuoteLineRepr> for QuoteLine {
+    fn from(
+        QuoteLineRepr {
+            id,
+            name,
+            thumbnail,
+            price,
+            price_tiers,
+            in_stock,
+            count,
+            special_offer_id,
+            new_price,
+            quantity1,
+            quantity2,
+            members_only,
+            remaining_uses,
+            favorited,
+            line_price,
+            offer_uses,
+        }: QuoteLineRepr,
+    ) -> Self {
+        let offer_uses = offer_uses
+            .try_into()
+            .expect("Database returned negative special offer uses.");
+        let product = CartProduct::from(CartProductRepr {
+            id,
+            name,
+            thumbnail,
+            price,
+            price_tiers,
+            in_stock,
+            count,
+            special_offer_id,
+            new_price,
+            quantity1,
+            quantity2,
+            members_only,
+            remaining_uses,
+            favorited,
+        });
+        Self {
+            remaining_uses: product
+                .special_offer_deal
+                .and(product.special_offer_remaining_uses)
+                .map(|remaining| remaining.saturating_sub(offer_uses)),
+            product,
+            price: line_price,
+            bundles: Box::default(),
+            offer_uses,
+        }
+    }
+}
+
+/// Get the contents of a customer's cart priced exactly as [`checkout`] would price it.
+///
+/// # Errors
+///
+/// Fails if:
+/// - The caller is not logged in as `customer`.
+/// - An error occurs during communication with the database.
+#[server]
+pub async fn cart_quote(customer: Id<Customer>) -> Result<Quote> {
+    authorize_customer(customer).await?;
+
+    let mut tx = POOL.begin().await?;
+
+    let seen_at = query_scalar!(r#"SELECT CURRENT_TIMESTAMP::TIMESTAMP AS "time!""#)
+        .fetch_one(&mut *tx)
+        .await?;
+
+    let mut shares = HashMap::<_, Vec<_>>::new();
+    for (product, share) in query_as!(
+        BundleShareRepr,
+        r#"
+        SELECT cb.bundle AS "bundle!", b.name, cb.product AS "product!", cb.units AS "units!",
+            cb.price AS "price!"
+        FROM cart_bundles($1) cb
+        JOIN bundles b ON b.id = cb.bundle
+        ORDER BY cb.bundle, cb.product
+        "#,
+        customer.get()
+    )
+    .fetch_all(&mut *tx)
+    .await?
+    .into_iter()
+    .map(<(Id<Product>, BundleShare)>::from)
+    {
+        shares.entry(product).or_default().push(share);
+    }
+
+    let lines = query_as!(
+        QuoteLineRepr,
+        r#"
+        WITH bundled AS (
+            SELECT product, SUM(units)::INT AS units, SUM(price) AS price
+            FROM cart_bundles($1)
+            GROUP BY product
+        )
+        SELECT p.id, name, thumbnail, p.price,
+            product_price_tiers(p.id) AS "price_tiers!: Vec<PriceTierRepr>",
+            in_stock, s.number AS count,
+            aso.id AS special_offer_id,
+            new_price, quantity1, quantity2, COALESCE(members_only, FALSE) AS "members_only!",
+            limit_per_customer - COALESCE(sou.number, 0) AS remaining_uses,
+            EXISTS (
+                SELECT 1
+                FROM customer_favorites cf
+                WHERE cf.customer = $1 AND cf.product = p.id
+            ) AS "favorited!",
+            COALESCE(bl.price, 0) + calc.price AS "line_price!", calc.uses AS "offer_uses!"
+        FROM shopping_cart_items s
+        JOIN products p ON p.id = s.product
+        JOIN customers ON customers.id = $1
+        LEFT JOIN bundled bl ON bl.product = p.id
+        LEFT JOIN LATERAL best_special_offer(p.id, $1, s.number) aso
+            ON NOT aso.members_only OR current_member(customers)
+        LEFT JOIN special_offer_uses sou ON special_offer = aso.id AND sou.customer = $1
+        CROSS JOIN LATERAL calculate_price(
+            tier_price(p.id, s.number), s.number - COALESCE(bl.units, 0),
+            new_price, quantity1, quantity2,
+            CASE
+                WHEN limit_per_customer IS NULL THEN s.number
+                ELSE GREATEST(limit_per_customer - COALESCE(sou.number, 0), 0)
+            END
+        ) AS calc
+        WHERE s.customer = $1 AND s.number > 0
+        ORDER BY p.id
+        "#,
+        customer.get()
+    )
+    .fetch_all(&mut *tx)
+    .await?
+    .into_iter()
+    .map(|repr| {
+        let mut line = QuoteLine::from(repr);
+        line.bundles = shares.remove(&line.product.id).unwrap_or_default().into();
+        line
+    })
+    .collect::<Box<_>>();
+
+    tx.commit().await?;
+
+    let subtotal = lines.iter().map(QuoteLine::base_price).sum();
+    let total = lines.iter().map(|line| line.price).sum();
+    Ok(Quote {
+        lines,
+        subtotal,
+        discount: subtotal - total,
+        total,
+        seen_at,
+    })
+}
+
 /// An item a customer wants to check out with.
 ///
 /// This is to ensure the customer proceeds with what they see in the cart, which might be
@@ -295,7 +606,7 @@ pub struct CheckoutItem {
 #[cfg(feature = "server")]
 #[derive(Type)]
 #[sqlx(type_name = "CHECKOUT_ITEM")]
-struct CheckoutItemRepr {
+pub(super) struct CheckoutItemRepr {
     product: i32,
     number: i32,
     special_offer: Option<i32>,
@@ -323,44 +634,366 @@ impl TryFrom<CheckoutItem> for CheckoutItemRepr {
     }
 }
 
+/// A line of a checkout that no longer matches the store, see [`CheckoutOutcome::Stale`].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StaleLine {
+    /// The item as it was given to [`checkout`].
+    pub item: CheckoutItem,
+    /// What has changed since the cart was seen. Empty if the only problem is the stock.
+    pub reasons: Box<[StaleReason]>,
+    /// The product as it currently is, with `count` being the number of units in `item`. `None`
+    /// if the product is no longer available.
+    pub current: Option<CartProduct>,
+}
+
+impl StaleLine {
+    /// Whether the product does not have enough units in stock for the line.
+    #[must_use]
+    pub fn out_of_stock(&self) -> bool {
+        self.current
+            .as_ref()
+            .is_some_and(|current| current.in_stock < current.count.get())
+    }
+}
+
+/// The result of a call to [`checkout`].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[expect(variant_size_differences, reason = "Only returned once per call.")]
+pub enum CheckoutOutcome {
+    /// The order was placed.
+    Placed(Id<Order>),
+    /// No order was placed, as the cart did not match the store.
+    Stale {
+        /// The lines that did not match, in the order they were given.
+        lines: Box<[StaleLine]>,
+        /// The time at which the current data in `lines` was known to be valid, to be passed to
+        /// [`checkout`] when retrying.
+        seen_at: PrimitiveDateTime,
+    },
+}
+
+impl From<&CartProduct> for CheckoutItem {
+    /// Check out with a product at the price shown in the cart.
+    ///
+    /// Bundles are not taken into account, as they depend on the rest of the cart. Use
+    /// [`Quote::items`] to check out with products that may be in a bundle.
+    fn from(product: &CartProduct) -> Self {
+        Self {
+            product: product.id,
+            number: product.count,
+            special_offer: product.special_offer_deal.and(product.special_offer_id),
+            expected_price: product.special_offer_deal.map_or_else(
+                || product.unit_price() * Decimal::from(product.count.get()),
+                |deal| {
+                    deal.discounted_price(
+                        product.count,
+                        product.unit_price(),
+                        product.special_offer_remaining_uses,
+                    )
+                    .0
+                },
+            ),
+        }
+    }
+}
+
+impl From<&QuoteLine> for CheckoutItem {
+    /// Check out with a product at the quoted price.
+    fn from(line: &QuoteLine) -> Self {
+        Self {
+            product: line.product.id,
+            number: line.product.count,
+            special_offer: line
+                .product
+                .special_offer_deal
+                .and(line.product.special_offer_id),
+            expected_price: line.price,
+        }
+    }
+}
+
+/// Bring the contents of a cart up to date with the lines reported by [`CheckoutOutcome::Stale`].
+///
+/// Stale products are replaced by their current state, products that are no longer available are
+/// removed, and the number of units is lowered to what is in stock.
+#[must_use]
+pub fn apply_stale_lines(products: &[CartProduct], lines: &[StaleLine]) -> Box<[CartProduct]> {
+    products
+        .iter()
+        .filter_map(|product| {
+            let Some(line) = lines.iter().find(|line| line.item.product == product.id) else {
+                return Some(product.clone());
+            };
+            let mut current = line.current.clone()?;
+            current.count = NonZeroU32::new(current.count.get().min(current.in_stock))?;
+            Some(current)
+        })
+        .collect()
+}
+
+#[cfg(feature = "server")]
+#[expect(
+    clippy::struct_excessive_bools,
+    reason = "Each column is a separate check."
+)]
+struct LineStatusRepr {
+    product: i32,
+    available: bool,
+    changed: bool,
+    offer_expired: bool,
+    not_eligible: bool,
+    bundle_changed: bool,
+    bundled: bool,
+}
+
+#[cfg(feature = "server")]
+impl LineStatusRepr {
+    /// What has changed about `item`, given the product as it currently is.
+    fn reasons(&self, item: CheckoutItem, current: Option<&CartProduct>) -> Box<[StaleReason]> {
+        let mut reasons = Vec::new();
+        if !self.available || current.is_none() {
+            reasons.push(StaleReason::ProductUnavailable);
+        }
+        if self.changed {
+            reasons.push(StaleReason::ProductChanged);
+        }
+        if self.offer_expired {
+            reasons.push(StaleReason::OfferExpired);
+        } else if self.not_eligible {
+            reasons.push(StaleReason::NotEligible);
+        } else if item.special_offer.is_some()
+            // The price of bundled units depends on the rest of the cart.
+            && !self.bundled
+            && reasons.is_empty()
+            && current.is_some_and(|current| {
+                // Once used up, the offer may have been replaced by the next best one.
+                let expected = CheckoutItem::from(current);
+                expected.special_offer != item.special_offer
+                    || expected.expected_price != item.expected_price
+            })
+        {
+            reasons.push(StaleReason::OfferUsedUp);
+        }
+        if self.bundle_changed {
+            reasons.push(StaleReason::BundleChanged);
+        }
+        reasons.into()
+    }
+}
+
+/// Find the items that do not match the store as seen at `seen_at`.
+///
+/// # Errors
+///
+/// Fails if an error occurs during communication with the database.
+#[cfg(feature = "server")]
+async fn stale_lines(
+    customer: Id<Customer>,
+    items: &[CheckoutItem],
+    seen_at: PrimitiveDateTime,
+) -> Result<(Box<[StaleLine]>, PrimitiveDateTime)> {
+    let reprs = items
+        .iter()
+        .copied()
+        .map(TryInto::try_into)
+        .collect::<Result<Box<_>, _>>()?;
+    let mut tx = POOL.begin().await?;
+
+    let time = query_scalar!(r#"SELECT CURRENT_TIMESTAMP::TIMESTAMP AS "time!""#)
+        .fetch_one(&mut *tx)
+        .await?;
+
+    let mut statuses = query_as!(
+        LineStatusRepr,
+        r#"
+        SELECT i.product AS "product!",
+            COALESCE(p.visible, FALSE) AS "available!",
+            COALESCE(p.updated_at > $3, FALSE) AS "changed!",
+            i.special_offer IS NOT NULL AND (eo.id IS NULL OR eo.updated_at > $3)
+                AS "offer_expired!",
+            COALESCE(eo.members_only AND NOT current_member(c), FALSE) AS "not_eligible!",
+            EXISTS (
+                SELECT 1
+                FROM bundles b
+                WHERE bundle_includes(b, p) AND bundle_changed(b, $3)
+            ) AS "bundle_changed!",
+            i.product IN (
+                SELECT a.product
+                FROM allocate_bundles(
+                    ARRAY(SELECT product FROM UNNEST($2::CHECKOUT_ITEM[])),
+                    ARRAY(SELECT number FROM UNNEST($2::CHECKOUT_ITEM[]))
+                ) a
+            ) AS "bundled!"
+        FROM UNNEST($2::CHECKOUT_ITEM[]) i
+        JOIN customers c ON c.id = $1
+        LEFT JOIN products p ON p.id = i.product
+        LEFT JOIN active_special_offers eo ON eo.id = i.special_offer AND eo.product = i.product
+        "#,
+        customer.get(),
+        &reprs as &[CheckoutItemRepr],
+        seen_at,
+    )
+    .fetch_all(&mut *tx)
+    .await?
+    .into_iter()
+    .map(|status| (Id::<Product>::from(status.product), status))
+    .collect::<HashMap<_, _>>();
+
+    let mut current = query_as!(
+        CartProductRepr,
+        r#"
+        SELECT p.id, name, thumbnail, price,
+            product_price_tiers(p.id) AS "price_tiers!: Vec<PriceTierRepr>",
+            in_stock, i.number AS "count!",
+            aso.id AS special_offer_id,
+            new_price, quantity1, quantity2, COALESCE(members_only, FALSE) AS "members_only!",
+            limit_per_customer - COALESCE(sou.number, 0) AS remaining_uses,
+            EXISTS (
+                SELECT 1
+                FROM customer_favorites cf
+                WHERE cf.customer = $1 AND cf.product = p.id
+            ) AS "favorited!"
+        FROM UNNEST($2::CHECKOUT_ITEM[]) i
+        JOIN products p ON p.id = i.product AND visible
+        JOIN customers ON customers.id = $1
+        LEFT JOIN LATERAL best_special_offer(p.id, $1, i.number) aso
+            ON NOT aso.members_only OR current_member(customers)
+        LEFT JOIN special_offer_uses sou ON sou.special_offer = aso.id AND sou.customer = $1
+        "#,
+        customer.get(),
+        &reprs as &[CheckoutItemRepr],
+    )
+    .fetch_all(&mut *tx)
+    .await?
+    .into_iter()
+    .map(|repr| (Id::from(repr.id), CartProduct::from(repr)))
+    .collect::<HashMap<_, _>>();
+
+    tx.commit().await?;
+
+    let lines = items
+        .iter()
+        .filter_map(|&item| {
+            let status = statuses.remove(&item.product)?;
+            let current = current.remove(&item.product);
+            let line = StaleLine {
+                item,
+                reasons: status.reasons(item, current.as_ref()),
+                current,
+            };
+            (!line.reasons.is_empty() || line.out_of_stock()).then_some(line)
+        })
+        .collect();
+    Ok((lines, time))
+}
+
 /// Complete an order for a customer.
 ///
 /// Requires specifying the exact contents of the cart as the customer sees it, as well as the time
 /// that data was loaded. This is to deny checkout frm proceeding with stale data. The time should
 /// be the one returned from [`cart_products`].
 ///
+/// The order is delivered to `address`, and the products of each vendor are shipped with the one
+/// method in `shipments` offered by that vendor, see
+/// [`shipping_options`](crate::database::delivery::shipping_options). Both are copied into the
+/// order.
+///
+/// If the customer entered a promotion code, `promotion` is the promotion along with the discount
+/// they expect, see [`PromotionInfo::use_on`](crate::database::promotions::PromotionInfo::use_on).
+/// The discount is recorded with the order, and taken off what is paid.
+///
+/// Units are allocated to bundles as in [`cart_quote`], and each line pays its share of the
+/// bundles along with the price of the rest of its units. The allocation is recorded with the
+/// order.
+///
+/// The order is paid for with `card`, see [`payments`](crate::database::payments). It is only
+/// placed once the payment has been authorized.
+///
+/// If any data in `items` is stale, no order is placed and [`CheckoutOutcome::Stale`] describes
+/// each affected line. Stale data includes:
+/// - A product having changed (e.g. new name, price or price tiers).
+/// - A product no longer having enough stock.
+/// - A product no longer being visible.
+/// - A special offer having expired.
+/// - The customer no longer being eligible for a special offer due to a membership change.
+/// - The customer not being able to apply a special offer enough times to achieve the expected
+///   price due to e.g. a concurrent checkout with the same account.
+/// - A bundle a product may be bought in having started, ended or changed.
+///
 /// # Errors
 ///
 /// Fails if:
 /// - `customer` is invalid.
-/// - Any data in `items` is stale, including:
-///   - A product having changed (e.g. new name or price).
-///   - A product no longer having enough stock.
-///   - A product no longer being visible.
-///   - A special offer having expired.
-///   - The customer no longer being eligible for a special offer due to a membership change.
-///   - The customer not being able to apply a special offer enough times to achieve the expected
-///     price due to e.g. a concurrent checkout with the same account.
+/// - `items` is empty.
+/// - `address` is invalid or was not saved by `customer`.
+/// - `shipments` does not have exactly one shipping method for each vendor of the products in
+///   `items`.
+/// - A shipping method in `shipments` has been deleted or its fee has changed, see
+///   [`AppError::StaleCart`](crate::database::AppError::StaleCart).
+/// - `promotion` has expired, changed, been used up or no longer gives the expected discount, see
+///   [`AppError::StaleCart`](crate::database::AppError::StaleCart).
+/// - The products do not reach the minimum spend of `promotion`.
+/// - Data in `items` was stale, but is no longer, see
+///   [`AppError::StaleCart`](crate::database::AppError::StaleCart) and
+///   [`AppError::OutOfStock`](crate::database::AppError::OutOfStock).
 /// - `seen_at` is in the future.
+/// - The payment was not authorized, see
+///   [`AppError::PaymentFailed`](crate::database::AppError::PaymentFailed). The products are then
+///   put back in the cart.
+/// - The caller is not logged in as `customer`, or has not verified their email.
 /// - An error occurs during communication with the database.
 #[server]
 pub async fn checkout(
     customer: Id<Customer>,
     items: Vec<CheckoutItem>,
     seen_at: PrimitiveDateTime,
-) -> Result<()> {
-    let items = items
-        .into_iter()
+    address: Id<Address>,
+    shipments: Vec<Shipment>,
+    promotion: Option<PromotionUse>,
+    card: CardNumber,
+) -> Result<CheckoutOutcome> {
+    authorize_verified_customer(customer).await?;
+
+    let reprs = items
+        .iter()
+        .copied()
         .map(TryInto::try_into)
         .collect::<Result<Box<_>, _>>()?;
-    query!(
-        "CALL checkout($1, $2, ($3::TIMESTAMP)::NONFUTURE_TIMESTAMP)",
+    let shipments = shipments
+        .into_iter()
+        .map(ShipmentRepr::from)
+        .collect::<Box<_>>();
+    let error = match query_scalar!(
+        r#"
+        SELECT checkout($1, $2, ($3::TIMESTAMP)::NONFUTURE_TIMESTAMP, $4, $5, $6) AS "order!"
+        "#,
         customer.get(),
-        &items as &[CheckoutItemRepr],
+        &reprs as &[CheckoutItemRepr],
         seen_at,
+        address.get(),
+        &shipments as &[ShipmentRepr],
+        promotion.map(PromotionUseRepr::from) as Option<PromotionUseRepr>,
     )
-    .execute(&*POOL)
+    .fetch_one(&*POOL)
     .await
-    .map(QueryResultExt::allow_any)
-    .map_err(Into::into)
+    {
+        Ok(order) => {
+            payments::authorize(order.into(), card).await?;
+            return Ok(CheckoutOutcome::Placed(order.into()));
+        },
+        Err(error) => classify(error),
+    };
+
+    if !matches!(
+        AppError::from_error(&error),
+        Some(AppError::StaleCart { .. } | AppError::OutOfStock { .. })
+    ) {
+        return Err(error);
+    }
+    let (lines, seen_at) = stale_lines(customer, &items, seen_at).await?;
+    if lines.is_empty() {
+        // The data changed back before it could be diagnosed.
+        return Err(error);
+    }
+    Ok(CheckoutOutcome::Stale { lines, seen_at })
 }
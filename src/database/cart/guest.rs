@@ -0,0 +1,288 @@
+//! Shopping carts of shoppers who are not logged in.
+//!
+//! A guest cart is identified by an opaque token in an HTTP-only cookie, issued the first time the
+//! shopper puts something in their cart, and is stored server-side like the cart of a customer.
+//! When the shopper logs in as a customer, the guest cart is merged into theirs according to the
+//! [`MergePolicy`] in use, see [`set_merge_policy`], and the guest cart is deleted.
+
+use crate::database::{Customer, Id, Product, cart::CartProduct};
+use dioxus::prelude::*;
+use dioxus_fullstack::response::Response;
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "server")]
+use {
+    crate::database::{
+        POOL, PriceTierRepr, QueryResultExt, authorize_customer,
+        cart::CartProductRepr,
+        classify, cookie_token, generate_token,
+        rate_limit::{Action, limit},
+    },
+    dioxus_fullstack::response::IntoResponse as _,
+    http::header::SET_COOKIE,
+    sqlx::{PgConnection, Type, postgres::types::PgInterval, query, query_as, query_scalar},
+    std::sync::OnceLock,
+    time::Duration,
+};
+
+/// How the number of units of a product in both a guest cart and a customer's cart is chosen when
+/// merging them.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[cfg_attr(feature = "server", derive(Type))]
+#[cfg_attr(
+    feature = "server",
+    sqlx(type_name = "cart_merge_policy", rename_all = "snake_case")
+)]
+pub enum MergePolicy {
+    /// Add the numbers together.
+    #[default]
+    Sum,
+    /// Keep the larger number.
+    Max,
+    /// Keep the number from the cart where the product was updated most recently.
+    PreferLatest,
+}
+
+/// The name of the cookie holding the guest cart token.
+#[cfg(feature = "server")]
+pub(crate) const GUEST_CART_COOKIE: &str = "guest_cart";
+
+/// How long a guest cart is kept after it was last changed.
+#[cfg(feature = "server")]
+const GUEST_CART_LIFETIME: Duration = Duration::days(30);
+
+#[cfg(feature = "server")]
+static MERGE_POLICY: OnceLock<MergePolicy> = OnceLock::new();
+
+/// Get a `Set-Cookie` header value removing the guest cart cookie, once the cart is merged.
+#[cfg(feature = "server")]
+pub(crate) fn removed_guest_cart_cookie() -> String {
+    format!("{GUEST_CART_COOKIE}=; Path=/; Max-Age=0; HttpOnly; Secure; SameSite=Lax")
+}
+
+/// Merge guest carts with `policy` instead of the default.
+///
+/// # Panics
+///
+/// Panics if a merge policy has already been used or set.
+#[cfg(feature = "server")]
+pub fn set_merge_policy(policy: MergePolicy) {
+    MERGE_POLICY
+        .set(policy)
+        .expect("Merge policy was used or set before being set.");
+}
+
+/// Get the merge policy in use, defaulting to [`MergePolicy::default`] if none has been set.
+#[cfg(feature = "server")]
+pub(crate) fn merge_policy() -> MergePolicy {
+    *MERGE_POLICY.get_or_init(MergePolicy::default)
+}
+
+/// Get the contents of the caller's guest cart.
+///
+/// Returns an empty cart if the caller has none. Special offers only for members are not applied,
+/// and `favorited` is always `false`.
+///
+/// # Errors
+///
+/// Fails if:
+/// - The request headers could not be extracted.
+/// - An error occurs during communication with the database.
+#[server]
+pub async fn guest_cart_products() -> Result<Box<[CartProduct]>> {
+    let Some(token) = cookie_token(GUEST_CART_COOKIE).await? else {
+        return Ok(Box::default());
+    };
+
+    query_as!(
+        CartProductRepr,
+        r#"
+        SELECT p.id, name, thumbnail, price,
+            product_price_tiers(p.id) AS "price_tiers!: Vec<PriceTierRepr>",
+            in_stock, gci.number AS count,
+            aso.id AS "special_offer_id?", new_price, quantity1, quantity2,
+            COALESCE(members_only, FALSE) AS "members_only!",
+            limit_per_customer::INT AS remaining_uses,
+            FALSE AS "favorited!"
+        FROM active_guest_carts gc
+        JOIN guest_cart_items gci ON gci.cart = gc.id
+        JOIN products p ON p.id = gci.product AND visible
+        LEFT JOIN LATERAL best_special_offer(p.id, NULL, gci.number) aso ON NOT aso.members_only
+        WHERE gc.token_hash = sha256(decode($1, 'hex'))
+        ORDER BY p.id
+        "#,
+        &*token,
+    )
+    .fetch_all(&*POOL)
+    .await
+    .map(|products| products.into_iter().map(Into::into).collect())
+    .map_err(Into::into)
+}
+
+/// Put `number` units of a product in the caller's guest cart, *overriding any number already
+/// there*. Setting `number = 0` removes the product from the cart.
+///
+/// If the caller has no guest cart, one is created and the response sets an HTTP-only cookie
+/// identifying it.
+///
+/// # Errors
+///
+/// Fails if:
+/// - The caller has made too many changes to guest carts, see
+///   [`AppError::RateLimited`](crate::database::AppError::RateLimited).
+/// - `product` is invalid, see [`AppError::NotFound`](crate::database::AppError::NotFound).
+/// - `number > i32::MAX`.
+/// - The request headers could not be extracted.
+/// - An error occurs during communication with the database.
+///
+/// # Panics
+///
+/// Panics if the guest cart lifetime is not representable as an interval, which it always is.
+#[server]
+pub async fn set_in_guest_cart(product: Id<Product>, number: u32) -> Result<Response> {
+    limit(Action::SetInGuestCart, None).await?;
+    let number = i32::try_from(number)?;
+    let lifetime =
+        PgInterval::try_from(GUEST_CART_LIFETIME).expect("Guest cart lifetime is representable.");
+    let mut tx = POOL.begin().await?;
+
+    let existing = match cookie_token(GUEST_CART_COOKIE).await? {
+        Some(token) => {
+            query_scalar!(
+                "
+                UPDATE guest_carts
+                SET expires_at = CURRENT_TIMESTAMP + $2
+                WHERE token_hash = sha256(decode($1, 'hex')) AND expires_at > CURRENT_TIMESTAMP
+                RETURNING id
+                ",
+                &*token,
+                lifetime,
+            )
+            .fetch_optional(&mut *tx)
+            .await?
+        },
+        None => None,
+    };
+    let (cart, new_token) = if let Some(cart) = existing {
+        (cart, None)
+    } else {
+        let token = generate_token();
+        let cart = query_scalar!(
+            "
+            INSERT INTO guest_carts (token_hash, expires_at)
+            VALUES (sha256(decode($1, 'hex')), CURRENT_TIMESTAMP + $2)
+            RETURNING id
+            ",
+            token,
+            lifetime,
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+        (cart, Some(token))
+    };
+
+    if number == 0 {
+        query!(
+            "
+            DELETE FROM guest_cart_items
+            WHERE cart = $1 AND product = $2
+            ",
+            cart,
+            product.get(),
+        )
+        .execute(&mut *tx)
+        .await
+        .map(QueryResultExt::expect_maybe)?;
+    } else {
+        query!(
+            "
+            INSERT INTO guest_cart_items (cart, product, number)
+            VALUES ($1, $2, $3::INT)
+            ON CONFLICT (cart, product) DO UPDATE
+            SET number = EXCLUDED.number
+            ",
+            cart,
+            product.get(),
+            number,
+        )
+        .execute(&mut *tx)
+        .await
+        .map(QueryResultExt::expect_one)
+        .map_err(classify)?;
+    }
+
+    tx.commit().await?;
+
+    Ok(new_token.map_or_else(
+        || "".into_response(),
+        |token| {
+            (
+                [(
+                    SET_COOKIE,
+                    format!(
+                        "{GUEST_CART_COOKIE}={token}; Path=/; Max-Age={}; HttpOnly; Secure; SameSite=Lax",
+                        GUEST_CART_LIFETIME.whole_seconds(),
+                    ),
+                )],
+                "",
+            )
+                .into_response()
+        },
+    ))
+}
+
+/// Move the contents of the caller's guest cart to the cart of `customer`, using the
+/// [`MergePolicy`] in use for products in both, and delete the guest cart.
+///
+/// Does nothing if the caller has no guest cart. Otherwise, the response removes the cookie
+/// identifying it. This is done automatically when logging in, see
+/// [`log_in`](crate::database::log_in), but may be needed if products were added to the guest
+/// cart while logged in.
+///
+/// # Errors
+///
+/// Fails if:
+/// - The caller is not logged in as `customer`.
+/// - The request headers could not be extracted.
+/// - An error occurs during communication with the database.
+#[server]
+pub async fn merge_guest_cart(customer: Id<Customer>) -> Result<Response> {
+    authorize_customer(customer).await?;
+
+    let Some(token) = cookie_token(GUEST_CART_COOKIE).await? else {
+        return Ok("".into_response());
+    };
+    merge_into(
+        &mut *POOL.acquire().await?,
+        customer,
+        &token,
+        merge_policy(),
+    )
+    .await?;
+
+    Ok(([(SET_COOKIE, removed_guest_cart_cookie())], "").into_response())
+}
+
+/// Move the contents of the guest cart identified by `token` to the cart of `customer`, using
+/// `policy` for products in both, and delete the guest cart.
+///
+/// # Errors
+///
+/// Fails if an error occurs during communication with the database.
+#[cfg(feature = "server")]
+pub(crate) async fn merge_into(
+    connection: &mut PgConnection,
+    customer: Id<Customer>,
+    token: &str,
+    policy: MergePolicy,
+) -> Result<()> {
+    query!(
+        "CALL merge_guest_cart($1, sha256(decode($2, 'hex')), $3)",
+        customer.get(),
+        token,
+        policy as MergePolicy,
+    )
+    .execute(connection)
+    .await
+    .map(QueryResultExt::procedure)
+    .map_err(Into::into)
+}
@@ -3,11 +3,13 @@
 use crate::database::{Category, Id};
 use dioxus::prelude::*;
 use serde::{Deserialize, Serialize};
+use thiserror::Error;
 #[cfg(feature = "server")]
 use {
-    crate::database::{POOL, QueryResultExt, RawId},
+    crate::database::{POOL, QueryResultExt, RawId, authorize_administrator, classify},
+    dioxus::CapturedError,
     hashbrown::HashMap,
-    sqlx::{query, query_as},
+    sqlx::{Error as SqlxError, query, query_as},
 };
 
 #[cfg(feature = "server")]
@@ -15,10 +17,28 @@ use {
 #[derive(PartialEq, PartialOrd)]
 struct CategoryRepr {
     parent: Option<RawId>,
+    position: i32,
     name: String,
     id: RawId,
 }
 
+/// A category could not be moved, as it would become its own ancestor.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Error, Serialize, Deserialize)]
+#[error("Category can not be moved into its own subtree.")]
+pub struct CategoryCycle;
+
+/// Turn a violation of the category tree constraint into [`CategoryCycle`], and classify other
+/// errors with [`classify`].
+#[cfg(feature = "server")]
+fn detect_cycle(error: SqlxError) -> CapturedError {
+    if let SqlxError::Database(e) = &error
+        && e.constraint() == Some("categories_valid_tree")
+    {
+        return CategoryCycle.into();
+    }
+    classify(error)
+}
+
 /// A category with its subcategories, for display in a tree.
 ///
 /// Created by [`category_trees`].
@@ -51,7 +71,9 @@ fn build_tree(
     }
 }
 
-/// Get the hierarchy of categories as a forest, with roots and each subtree sorted by name.
+/// Get the hierarchy of categories as a forest, with roots and each subtree in their sort order.
+///
+/// Siblings are sorted by their position, as set by [`reorder_categories`], and then by name.
 ///
 /// # Errors
 ///
@@ -62,9 +84,9 @@ pub async fn category_trees() -> Result<Box<[CategoryTree]>> {
     let categories = query_as!(
         CategoryRepr,
         "
-        SELECT *
+        SELECT id, name, parent, position
         FROM categories
-        ORDER BY parent NULLS FIRST, name
+        ORDER BY parent NULLS FIRST, position, name
         ",
     )
     .fetch_all(&*POOL)
@@ -81,7 +103,10 @@ pub async fn category_trees() -> Result<Box<[CategoryTree]>> {
         roots.push((id.into(), name.into()));
     }
 
-    for CategoryRepr { id, parent, name } in iter {
+    for CategoryRepr {
+        id, parent, name, ..
+    } in iter
+    {
         #[expect(
             clippy::unwrap_used,
             reason = "Nodes without parents have already been traversed in the previous loop."
@@ -97,33 +122,26 @@ pub async fn category_trees() -> Result<Box<[CategoryTree]>> {
         .map(|(id, name)| build_tree(id, name, &mut by_parent))
         .collect::<Box<_>>();
 
-    debug_assert!({
-        fn categories_sorted(categories: &[CategoryTree]) -> bool {
-            categories.is_sorted_by_key(|c| &c.name)
-                && categories
-                    .iter()
-                    .all(|c| categories_sorted(&c.subcategories))
-        }
-        categories_sorted(&trees)
-    });
-
     Ok(trees)
 }
 
-/// Create a category.
+/// Create a category, placing it after all of its siblings.
 ///
 /// # Errors
 ///
 /// Fails if:
 /// - `parent` (if [`Some`]) is invalid.
 /// - `name` is not unique.
+/// - The caller is not an administrator.
 /// - An error occurs during communication with the database.
 #[server]
 pub async fn create_category(parent: Option<Id<Category>>, name: Box<str>) -> Result<()> {
+    authorize_administrator().await?;
+
     query!(
         "
-        INSERT INTO categories (parent, name)
-        VALUES ($1, $2)
+        INSERT INTO categories (parent, name, position)
+        VALUES ($1, $2, next_category_position($1))
         ",
         parent.map(Id::get),
         &name,
@@ -131,7 +149,7 @@ pub async fn create_category(parent: Option<Id<Category>>, name: Box<str>) -> Re
     .execute(&*POOL)
     .await
     .map(QueryResultExt::expect_one)
-    .map_err(Into::into)
+    .map_err(classify)
 }
 
 /// Delete a category and all of its subcategories.
@@ -141,9 +159,12 @@ pub async fn create_category(parent: Option<Id<Category>>, name: Box<str>) -> Re
 /// Fails if:
 /// - `category` is invalid.
 /// - Any products belong to the category.
+/// - The caller is not an administrator.
 /// - An error occurs during communication with the database.
 #[server]
 pub async fn delete_category(category: Id<Category>) -> Result<()> {
+    authorize_administrator().await?;
+
     query!(
         "
         DELETE FROM categories
@@ -156,3 +177,91 @@ pub async fn delete_category(category: Id<Category>) -> Result<()> {
     .by_unique_key()
     .map_err(Into::into)
 }
+
+/// Rename a category.
+///
+/// # Errors
+///
+/// Fails if:
+/// - `category` is invalid.
+/// - `name` is not unique.
+/// - The caller is not an administrator.
+/// - An error occurs during communication with the database.
+#[server]
+pub async fn rename_category(category: Id<Category>, name: Box<str>) -> Result<()> {
+    authorize_administrator().await?;
+
+    query!(
+        "
+        UPDATE categories
+        SET name = $2
+        WHERE id = $1
+        ",
+        category.get(),
+        &name,
+    )
+    .execute(&*POOL)
+    .await
+    .map_err(classify)?
+    .by_unique_key()
+    .map_err(Into::into)
+}
+
+/// Move a category, along with its subcategories, to a new parent. The category is placed after
+/// all of its new siblings.
+///
+/// Setting `parent` to `None` makes the category a root.
+///
+/// # Errors
+///
+/// Fails if:
+/// - `category` or `parent` (if [`Some`]) is invalid.
+/// - `parent` is `category` or one of its subcategories, with [`CategoryCycle`].
+/// - The caller is not an administrator.
+/// - An error occurs during communication with the database.
+#[server]
+pub async fn move_category(category: Id<Category>, parent: Option<Id<Category>>) -> Result<()> {
+    authorize_administrator().await?;
+
+    query!(
+        "
+        UPDATE categories
+        SET parent = $2, position = next_category_position($2)
+        WHERE id = $1
+        ",
+        category.get(),
+        parent.map(Id::get),
+    )
+    .execute(&*POOL)
+    .await
+    .map_err(detect_cycle)?
+    .by_unique_key()
+    .map_err(Into::into)
+}
+
+/// Set the sort order of the subcategories of a category, or of the roots if `parent` is `None`.
+///
+/// # Errors
+///
+/// Fails if:
+/// - `parent` (if [`Some`]) is invalid.
+/// - `order` does not contain every subcategory of `parent` exactly once.
+/// - The caller is not an administrator.
+/// - An error occurs during communication with the database.
+#[server]
+pub async fn reorder_categories(
+    parent: Option<Id<Category>>,
+    order: Box<[Id<Category>]>,
+) -> Result<()> {
+    authorize_administrator().await?;
+
+    query!(
+        "CALL reorder_categories($1, $2)",
+        parent.map(Id::get),
+        &order.iter().copied().map(Id::get).collect::<Vec<_>>(),
+    )
+    .execute(&*POOL)
+    .await
+    .map(QueryResultExt::procedure)
+    .map_err(Into::into)
+}
@@ -0,0 +1,562 @@
+//! Database functions for interacting with delivery addresses and shipping methods.
+//!
+//! Customers may save any number of addresses, and vendors define the ways they ship their
+//! products. At checkout, the customer picks an address and one shipping method per vendor of the
+//! products in their cart, which are copied into the order, see
+//! [`checkout`](crate::database::cart::checkout).
+
+use crate::database::{Address, Amount, Customer, Id, ShippingMethod, Vendor, cart::CheckoutItem};
+use dioxus::prelude::*;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "server")]
+use {
+    crate::database::{
+        POOL, QueryResultExt as _, RawId, authorize_address_owner, authorize_customer,
+        authorize_shipping_method_owner, authorize_vendor, cart::CheckoutItemRepr, classify,
+    },
+    sqlx::{Type, query, query_as, query_scalar},
+};
+
+/// A postal address to deliver orders to.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct PostalAddress {
+    /// The name of the person or organization receiving the delivery.
+    pub recipient: Box<str>,
+    /// The street address, including any house and apartment numbers.
+    pub street: Box<str>,
+    /// The postal code.
+    pub postal_code: Box<str>,
+    /// The city or town.
+    pub city: Box<str>,
+    /// The country.
+    pub country: Box<str>,
+}
+
+#[cfg(feature = "server")]
+struct AddressRepr {
+    id: RawId,
+    recipient: String,
+    street: String,
+    postal_code: String,
+    city: String,
+    country: String,
+}
+
+#[cfg(feature = "server")]
+impl From<AddressRepr> for (Id<Address>, PostalAddress) {
+    fn from(
+        AddressRepr {
+            id,
+            recipient,
+            street,
+            postal_code,
+            city,
+            country,
+        }: AddressRepr,
+    ) -> Self {
+        (
+            id.into(),
+            PostalAddress {
+                recipient: recipient.into(),
+                street: street.into(),
+                postal_code: postal_code.into(),
+                city: city.into(),
+                country: country.into(),
+            },
+        )
+    }
+}
+
+/// How the fee of a shipping method is calculated.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ShippingRate {
+    /// The same fee regardless of what is shipped.
+    Flat {
+        /// The fee.
+        fee: Decimal,
+    },
+    /// A fixed fee, plus a fee for each started step of weight of the products shipped.
+    ///
+    /// Products whose amount is not in a unit of mass, see [`Amount::grams`], are considered
+    /// weightless.
+    ByWeight {
+        /// The fee charged regardless of weight.
+        fee: Decimal,
+        /// The fee charged for each started `step`.
+        fee_per_step: Decimal,
+        /// The weight covered by each `fee_per_step`. Must be in a unit of mass.
+        step: Box<Amount>,
+    },
+}
+
+impl ShippingRate {
+    /// Construct a [`ShippingRate`] from its representation in the database.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the values do not uphold any of the database's invariants.
+    #[cfg(feature = "server")]
+    #[expect(clippy::unreachable, reason = "Database validation only.")]
+    fn from_repr(
+        fee: Decimal,
+        fee_per_weight: Option<Decimal>,
+        weight_step: Option<Decimal>,
+        weight_unit: Option<String>,
+    ) -> Self {
+        match (fee_per_weight, weight_step, weight_unit) {
+            (None, None, None) => Self::Flat { fee },
+            (Some(fee_per_step), Some(step), Some(unit)) => Self::ByWeight {
+                fee,
+                fee_per_step,
+                step: Box::new(Amount::with_unit(step, unit.into())),
+            },
+            _ => unreachable!("Database returned inconsistent shipping rate."),
+        }
+    }
+
+    /// Convert a [`ShippingRate`] into the format used in the database.
+    ///
+    /// Specifically, this returns a tuple representing the columns `fee`, `fee_per_weight`,
+    /// `weight_step` and `weight_unit` respectively.
+    #[cfg(feature = "server")]
+    fn database_repr(&self) -> (Decimal, Option<Decimal>, Option<Decimal>, Option<&str>) {
+        match self {
+            &Self::Flat { fee } => (fee, None, None, None),
+            Self::ByWeight {
+                fee,
+                fee_per_step,
+                step,
+            } => (
+                *fee,
+                Some(*fee_per_step),
+                Some(step.quantity()),
+                step.unit(),
+            ),
+        }
+    }
+}
+
+/// A way a vendor ships their products.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ShippingMethodInfo {
+    /// The ID of the shipping method.
+    pub id: Id<ShippingMethod>,
+    /// The name of the shipping method, e.g. "Hemleverans".
+    pub name: Box<str>,
+    /// How the fee is calculated.
+    pub rate: ShippingRate,
+}
+
+#[cfg(feature = "server")]
+struct ShippingMethodRepr {
+    id: RawId,
+    name: String,
+    fee: Decimal,
+    fee_per_weight: Option<Decimal>,
+    weight_step: Option<Decimal>,
+    weight_unit: Option<String>,
+}
+
+#[cfg(feature = "server")]
+impl From<ShippingMethodRepr> for ShippingMethodInfo {
+    fn from(
+        ShippingMethodRepr {
+            id,
+            name,
+            fee,
+            fee_per_weight,
+            weight_step,
+            weight_unit,
+        }: ShippingMethodRepr,
+    ) -> Self {
+        Self {
+            id: id.into(),
+            name: name.into(),
+            rate: ShippingRate::from_repr(fee, fee_per_weight, weight_step, weight_unit),
+        }
+    }
+}
+
+/// A shipping method available for the products of a vendor at checkout, along with its fee.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ShippingOption {
+    /// The ID of the vendor.
+    pub vendor: Id<Vendor>,
+    /// The name of the vendor.
+    pub vendor_name: Box<str>,
+    /// The shipping method.
+    pub method: ShippingMethodInfo,
+    /// The fee of shipping the vendor's products with the method.
+    pub fee: Decimal,
+}
+
+#[cfg(feature = "server")]
+struct ShippingOptionRepr {
+    vendor: RawId,
+    vendor_name: String,
+    id: RawId,
+    name: String,
+    fee: Decimal,
+    fee_per_weight: Option<Decimal>,
+    weight_step: Option<Decimal>,
+    weight_unit: Option<String>,
+    total_fee: Decimal,
+}
+
+#[cfg(feature = "server")]
+impl From<ShippingOptionRepr> for ShippingOption {
+    fn from(
+        ShippingOptionRepr {
+            vendor,
+            vendor_name,
+            id,
+            name,
+            fee,
+            fee_per_weight,
+            weight_step,
+            weight_unit,
+            total_fee,
+        }: ShippingOptionRepr,
+    ) -> Self {
+        Self {
+            vendor: vendor.into(),
+            vendor_name: vendor_name.into(),
+            method: ShippingMethodRepr {
+                id,
+                name,
+                fee,
+                fee_per_weight,
+                weight_step,
+                weight_unit,
+            }
+            .into(),
+            fee: total_fee,
+        }
+    }
+}
+
+/// A shipping method a customer wants to check out with.
+///
+/// As with [`CheckoutItem`], this is to ensure the customer pays the fee they were shown.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Shipment {
+    /// The ID of the shipping method.
+    pub method: Id<ShippingMethod>,
+    /// The fee the customer expects to pay.
+    pub expected_fee: Decimal,
+}
+
+#[cfg(feature = "server")]
+#[derive(Type)]
+#[sqlx(type_name = "CHECKOUT_SHIPMENT")]
+pub(super) struct ShipmentRepr {
+    shipping_method: i32,
+    expected_fee: Decimal,
+}
+
+#[cfg(feature = "server")]
+impl From<Shipment> for ShipmentRepr {
+    fn from(
+        Shipment {
+            method,
+            expected_fee,
+        }: Shipment,
+    ) -> Self {
+        Self {
+            shipping_method: method.get(),
+            expected_fee,
+        }
+    }
+}
+
+impl From<&ShippingOption> for Shipment {
+    /// Check out with a shipping method at the fee shown.
+    fn from(option: &ShippingOption) -> Self {
+        Self {
+            method: option.method.id,
+            expected_fee: option.fee,
+        }
+    }
+}
+
+/// Get the delivery addresses saved by a customer, in the order they were saved.
+///
+/// # Errors
+///
+/// Fails if:
+/// - `customer` is invalid.
+/// - The caller is not logged in as `customer`.
+/// - An error occurs during communication with the database.
+#[server]
+pub async fn customer_addresses(
+    customer: Id<Customer>,
+) -> Result<Box<[(Id<Address>, PostalAddress)]>> {
+    authorize_customer(customer).await?;
+
+    query_as!(
+        AddressRepr,
+        "
+        SELECT id, recipient, street, postal_code, city, country
+        FROM addresses
+        WHERE customer = $1
+        ORDER BY id
+        ",
+        customer.get(),
+    )
+    .fetch_all(&*POOL)
+    .await
+    .map(|addresses| addresses.into_iter().map(Into::into).collect())
+    .map_err(Into::into)
+}
+
+/// Save a delivery address for a customer, returning its ID.
+///
+/// # Errors
+///
+/// Fails if:
+/// - `customer` is invalid.
+/// - Any part of `address` is empty.
+/// - The caller is not logged in as `customer`.
+/// - An error occurs during communication with the database.
+#[server]
+pub async fn create_address(customer: Id<Customer>, address: PostalAddress) -> Result<Id<Address>> {
+    authorize_customer(customer).await?;
+
+    query_scalar!(
+        "
+        INSERT INTO addresses (customer, recipient, street, postal_code, city, country)
+        VALUES ($1, $2, $3, $4, $5, $6)
+        RETURNING id
+        ",
+        customer.get(),
+        &*address.recipient,
+        &*address.street,
+        &*address.postal_code,
+        &*address.city,
+        &*address.country,
+    )
+    .fetch_one(&*POOL)
+    .await
+    .map(Into::into)
+    .map_err(classify)
+}
+
+/// Change a saved delivery address.
+///
+/// Orders already placed keep the address they were placed with.
+///
+/// # Errors
+///
+/// Fails if:
+/// - `address` is invalid.
+/// - Any part of `new` is empty.
+/// - The caller is not the customer who saved `address`.
+/// - An error occurs during communication with the database.
+#[server]
+pub async fn set_address(address: Id<Address>, new: PostalAddress) -> Result<()> {
+    authorize_address_owner(address).await?;
+
+    query!(
+        "
+        UPDATE addresses
+        SET recipient = $2, street = $3, postal_code = $4, city = $5, country = $6
+        WHERE id = $1
+        ",
+        address.get(),
+        &*new.recipient,
+        &*new.street,
+        &*new.postal_code,
+        &*new.city,
+        &*new.country,
+    )
+    .execute(&*POOL)
+    .await
+    .map_err(classify)?
+    .by_unique_key()
+    .map_err(Into::into)
+}
+
+/// Delete a saved delivery address.
+///
+/// # Errors
+///
+/// Fails if:
+/// - `address` is invalid.
+/// - The caller is not the customer who saved `address`.
+/// - An error occurs during communication with the database.
+#[server]
+pub async fn delete_address(address: Id<Address>) -> Result<()> {
+    authorize_address_owner(address).await?;
+
+    query!("DELETE FROM addresses WHERE id = $1", address.get())
+        .execute(&*POOL)
+        .await?
+        .by_unique_key()
+        .map_err(Into::into)
+}
+
+/// Get the shipping methods of a vendor, sorted by name.
+///
+/// # Errors
+///
+/// Fails if an error occurs during communication with the database.
+#[server]
+pub async fn shipping_methods(vendor: Id<Vendor>) -> Result<Box<[ShippingMethodInfo]>> {
+    query_as!(
+        ShippingMethodRepr,
+        "
+        SELECT id, name, fee, fee_per_weight, weight_step, weight_unit
+        FROM shipping_methods
+        WHERE vendor = $1
+        ORDER BY name
+        ",
+        vendor.get(),
+    )
+    .fetch_all(&*POOL)
+    .await
+    .map(|methods| methods.into_iter().map(Into::into).collect())
+    .map_err(Into::into)
+}
+
+/// Create a shipping method for a vendor's products, returning its ID.
+///
+/// # Errors
+///
+/// Fails if:
+/// - `vendor` is invalid.
+/// - `name` is empty or already used by another of the vendor's shipping methods.
+/// - `rate` is [`ByWeight`](ShippingRate::ByWeight) with a `step` that is zero or not in a unit
+///   of mass.
+/// - The caller is not logged in as `vendor`.
+/// - An error occurs during communication with the database.
+#[server]
+pub async fn create_shipping_method(
+    vendor: Id<Vendor>,
+    name: Box<str>,
+    rate: ShippingRate,
+) -> Result<Id<ShippingMethod>> {
+    authorize_vendor(vendor).await?;
+
+    let (fee, fee_per_weight, weight_step, weight_unit) = rate.database_repr();
+    query_scalar!(
+        "
+        INSERT INTO shipping_methods (vendor, name, fee, fee_per_weight, weight_step, weight_unit)
+        VALUES ($1, $2, $3::DECIMAL(10, 2), $4::DECIMAL(10, 2), $5::DECIMAL(10, 2), $6)
+        RETURNING id
+        ",
+        vendor.get(),
+        &*name,
+        fee,
+        fee_per_weight,
+        weight_step,
+        weight_unit,
+    )
+    .fetch_one(&*POOL)
+    .await
+    .map(Into::into)
+    .map_err(classify)
+}
+
+/// Set how the fee of a shipping method is calculated.
+///
+/// Orders already placed keep the fee they were placed with, while customers who have seen the old
+/// fee must review it before checking out.
+///
+/// # Errors
+///
+/// Fails if:
+/// - `method` is invalid.
+/// - `rate` is [`ByWeight`](ShippingRate::ByWeight) with a `step` that is zero or not in a unit
+///   of mass.
+/// - The caller is not the vendor offering `method`.
+/// - An error occurs during communication with the database.
+#[server]
+pub async fn set_shipping_rate(method: Id<ShippingMethod>, rate: ShippingRate) -> Result<()> {
+    authorize_shipping_method_owner(method).await?;
+
+    let (fee, fee_per_weight, weight_step, weight_unit) = rate.database_repr();
+    query!(
+        "
+        UPDATE shipping_methods
+        SET fee = $2::DECIMAL(10, 2), fee_per_weight = $3::DECIMAL(10, 2),
+            weight_step = $4::DECIMAL(10, 2), weight_unit = $5
+        WHERE id = $1
+        ",
+        method.get(),
+        fee,
+        fee_per_weight,
+        weight_step,
+        weight_unit,
+    )
+    .execute(&*POOL)
+    .await
+    .map_err(classify)?
+    .by_unique_key()
+    .map_err(Into::into)
+}
+
+/// Delete a shipping method.
+///
+/// Orders already placed keep the name and fee of the method.
+///
+/// # Errors
+///
+/// Fails if:
+/// - `method` is invalid.
+/// - The caller is not the vendor offering `method`.
+/// - An error occurs during communication with the database.
+#[server]
+pub async fn delete_shipping_method(method: Id<ShippingMethod>) -> Result<()> {
+    authorize_shipping_method_owner(method).await?;
+
+    query!("DELETE FROM shipping_methods WHERE id = $1", method.get())
+        .execute(&*POOL)
+        .await?
+        .by_unique_key()
+        .map_err(Into::into)
+}
+
+/// Get the shipping methods that may be chosen when checking out with `items`, along with their
+/// fees for the items.
+///
+/// Options are grouped by vendor, and sorted by fee within each vendor. Exactly one option per
+/// vendor must be passed to [`checkout`](crate::database::cart::checkout). A vendor of a product
+/// in `items` with no shipping methods has no options, in which case checkout is not possible.
+///
+/// # Errors
+///
+/// Fails if:
+/// - The number of units of an item is greater than `i32::MAX`.
+/// - An error occurs during communication with the database.
+#[server]
+pub async fn shipping_options(items: Vec<CheckoutItem>) -> Result<Box<[ShippingOption]>> {
+    let reprs = items
+        .into_iter()
+        .map(TryInto::try_into)
+        .collect::<Result<Box<_>, _>>()?;
+
+    query_as!(
+        ShippingOptionRepr,
+        r#"
+        WITH weights AS (
+            SELECT p.vendor,
+                COALESCE(SUM(i.number * grams(amount_per_unit, measurement_unit)), 0) AS weight
+            FROM UNNEST($1::CHECKOUT_ITEM[]) i
+            JOIN products p ON p.id = i.product
+            GROUP BY p.vendor
+        )
+        SELECT m.vendor, display_name AS vendor_name, m.id, m.name, fee, fee_per_weight,
+            weight_step, weight_unit, shipping_fee(m, weight) AS "total_fee!"
+        FROM weights w
+        JOIN shipping_methods m ON m.vendor = w.vendor
+        JOIN vendors v ON v.id = m.vendor
+        ORDER BY m.vendor, shipping_fee(m, weight), m.id
+        "#,
+        &reprs as &[CheckoutItemRepr],
+    )
+    .fetch_all(&*POOL)
+    .await
+    .map(|options| options.into_iter().map(Into::into).collect())
+    .map_err(Into::into)
+}
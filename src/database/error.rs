@@ -0,0 +1,283 @@
+//! Errors that are meant to be shown to users.
+//!
+//! Server functions fail with [`AppError`] when the cause is something the user can act on, such as
+//! a taken username or a cart that has gone stale. Unlike other errors, these keep their type when
+//! sent to the client, where they are recovered with [`AppError::from_error`] and shown with
+//! [`AppError::localized`].
+//!
+//! Database errors are classified by [`classify`], based on their SQLSTATE code and constraint
+//! name. Functions in the database raise errors meant for users with one of the following codes:
+//! - `P0002` (`no_data_found`) if a referenced row does not exist.
+//! - `42501` (`insufficient_privilege`) if the user may not perform the action.
+//! - `23514` (`check_violation`) if the data is invalid, naming the rule as the constraint.
+//! - `BP001` if a cart has gone stale, naming the [`StaleReason`] as the constraint.
+//! - `BP002` if a product is out of stock, with the ID of the product as the detail.
+
+use crate::database::{AuthError, Id, Product, payments::PaymentFailure};
+use derive_more::Display;
+use dioxus::{CapturedError, prelude::*};
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "server")]
+use sqlx::{Error as SqlxError, postgres::PgDatabaseError};
+
+/// Why a cart could not be checked out as seen by the customer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize, Display)]
+pub enum StaleReason {
+    /// A product has changed, e.g. its name or price.
+    #[display("a product has changed")]
+    ProductChanged,
+    /// A product is no longer sold.
+    #[display("a product is no longer available")]
+    ProductUnavailable,
+    /// A special offer has expired or been removed.
+    #[display("a special offer has expired")]
+    OfferExpired,
+    /// The customer is no longer eligible for a special offer, e.g. after their membership ended.
+    #[display("the customer is not eligible for a special offer")]
+    NotEligible,
+    /// A special offer has been used too many times to give the expected price, e.g. due to a
+    /// concurrent checkout with the same account.
+    #[display("a special offer has been used up")]
+    OfferUsedUp,
+    /// A bundle a product may be bought in has started, ended or changed.
+    #[display("a bundle has changed")]
+    BundleChanged,
+    /// A shipping method has been removed or its fee for the cart has changed.
+    #[display("a shipping fee has changed")]
+    ShippingChanged,
+    /// A promotion code has expired, been removed or changed, or no longer gives the expected
+    /// discount.
+    #[display("a promotion code has changed")]
+    PromotionChanged,
+    /// A promotion code has been used as many times as it may be, in total or by the customer.
+    #[display("a promotion code has been used up")]
+    PromotionUsedUp,
+}
+
+impl StaleReason {
+    /// Describe the reason to the user, in Swedish.
+    #[must_use]
+    pub const fn localized(self) -> &'static str {
+        match self {
+            Self::ProductChanged => "Produkten har ändrats.",
+            Self::ProductUnavailable => "Produkten säljs inte längre.",
+            Self::OfferExpired => "Erbjudandet har gått ut.",
+            Self::NotEligible => "Du omfattas inte längre av erbjudandet.",
+            Self::OfferUsedUp => "Erbjudandet har redan utnyttjats.",
+            Self::BundleChanged => "Ett paketerbjudande har börjat, gått ut eller ändrats.",
+            Self::ShippingChanged => "Fraktavgiften har ändrats.",
+            Self::PromotionChanged => "Rabattkoden har gått ut eller ändrats.",
+            Self::PromotionUsedUp => "Rabattkoden har redan använts så många gånger som den får.",
+        }
+    }
+
+    /// Get the reason named by a constraint in the database.
+    #[cfg(feature = "server")]
+    fn from_constraint(constraint: &str) -> Option<Self> {
+        Some(match constraint {
+            "product_changed" => Self::ProductChanged,
+            "product_unavailable" => Self::ProductUnavailable,
+            "offer_expired" => Self::OfferExpired,
+            "not_eligible" => Self::NotEligible,
+            "offer_used_up" => Self::OfferUsedUp,
+            "bundle_changed" => Self::BundleChanged,
+            "shipping_changed" => Self::ShippingChanged,
+            "promotion_changed" => Self::PromotionChanged,
+            "promotion_used_up" => Self::PromotionUsedUp,
+            _ => return None,
+        })
+    }
+}
+
+/// A server function failed for a reason that can be shown to the user.
+///
+/// This intentionally does not implement [`std::error::Error`], so that conversion into
+/// [`CapturedError`] goes through [`ServerFnError`], which carries the error to the client.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, Display)]
+pub enum AppError {
+    /// The requested or referenced item does not exist.
+    #[display("The requested item does not exist.")]
+    NotFound,
+    /// The value of `field` is already taken by another item.
+    #[display("The {field} is already taken.")]
+    Conflict {
+        /// The column that must be unique, or the name of the constraint if it spans several.
+        field: Box<str>,
+    },
+    /// The cart has changed since it was loaded, and must be reviewed before checking out.
+    #[display("The cart is out of date: {reason}.")]
+    StaleCart {
+        /// What has changed.
+        reason: StaleReason,
+    },
+    /// A product does not have enough units in stock.
+    #[display("Product {product} does not have enough stock.")]
+    OutOfStock {
+        /// The product.
+        product: Id<Product>,
+    },
+    /// The payment provider did not complete a payment.
+    #[display("The payment failed: {reason}.")]
+    PaymentFailed {
+        /// Why the payment failed.
+        reason: PaymentFailure,
+    },
+    /// The caller is not permitted to perform the action.
+    #[display("Not permitted to perform this action.")]
+    Forbidden,
+    /// The data does not satisfy a rule of the database.
+    #[display("The data is invalid ({constraint}).")]
+    Invalid {
+        /// The name of the violated constraint.
+        constraint: Box<str>,
+    },
+    /// No user exists with the given username.
+    #[display("No user exists with the provided username.")]
+    UnknownUsername,
+    /// The given password is incorrect.
+    #[display("The provided password is incorrect.")]
+    IncorrectPassword,
+    /// The caller has made too many attempts, and must wait before trying again.
+    #[display("Too many attempts. Try again in {retry_after} seconds.")]
+    RateLimited {
+        /// The number of seconds until the next attempt is permitted.
+        retry_after: u64,
+    },
+}
+
+impl AppError {
+    /// Get the HTTP status code corresponding to the error.
+    #[must_use]
+    pub const fn status(&self) -> StatusCode {
+        match self {
+            Self::NotFound => StatusCode::NOT_FOUND,
+            Self::Conflict { .. } | Self::StaleCart { .. } | Self::OutOfStock { .. } => {
+                StatusCode::CONFLICT
+            },
+            Self::PaymentFailed { .. } => StatusCode::PAYMENT_REQUIRED,
+            Self::Forbidden => StatusCode::FORBIDDEN,
+            Self::Invalid { .. } => StatusCode::UNPROCESSABLE_ENTITY,
+            Self::UnknownUsername | Self::IncorrectPassword => StatusCode::BAD_REQUEST,
+            Self::RateLimited { .. } => StatusCode::TOO_MANY_REQUESTS,
+        }
+    }
+
+    /// Recover an error returned by a server function.
+    ///
+    /// Failed authorizations are recognized as [`AppError::Forbidden`]. Returns `None` for any
+    /// other error.
+    #[must_use]
+    pub fn from_error(error: &CapturedError) -> Option<Self> {
+        if let Some(ServerFnError::ServerError {
+            details: Some(details),
+            ..
+        }) = error.downcast_ref::<ServerFnError>()
+            && let Ok(error) = serde_json::from_value(details.clone())
+        {
+            return Some(error);
+        }
+        (AuthError::from_error(error) == Some(AuthError::Forbidden)).then_some(Self::Forbidden)
+    }
+
+    /// Describe the error to the user, in Swedish.
+    #[must_use]
+    pub fn localized(&self) -> String {
+        match self {
+            Self::NotFound => "Det du letar efter finns inte längre.".to_owned(),
+            Self::Conflict { field } => match &**field {
+                "username" => "Användarnamnet är redan taget.".to_owned(),
+                "email" => "E-postadressen används redan av ett annat konto.".to_owned(),
+                "display_name" => "Företagsnamnet är redan taget.".to_owned(),
+                "name" => "Namnet är redan taget.".to_owned(),
+                _ => "Det finns redan en likadan post.".to_owned(),
+            },
+            Self::StaleCart { reason } => format!(
+                "Varukorgen har ändrats sedan du öppnade den. {} Kontrollera den och försök igen.",
+                reason.localized()
+            ),
+            Self::OutOfStock { .. } => "En produkt i varukorgen finns inte i lager.".to_owned(),
+            Self::PaymentFailed { reason } => {
+                format!("Betalningen gick inte igenom. {}", reason.localized())
+            },
+            Self::Forbidden => "Du har inte behörighet att göra det här.".to_owned(),
+            Self::Invalid { .. } => "Uppgifterna är ogiltiga.".to_owned(),
+            Self::UnknownUsername => "Det finns ingen användare med det användarnamnet.".to_owned(),
+            Self::IncorrectPassword => "Fel lösenord.".to_owned(),
+            Self::RateLimited { retry_after } => {
+                format!("För många försök. Försök igen om {retry_after} sekunder.")
+            },
+        }
+    }
+
+    /// Describe any error returned by a server function to the user, in Swedish.
+    ///
+    /// Errors that are not [`AppError`]s are described generically.
+    #[must_use]
+    pub fn describe(error: &CapturedError) -> String {
+        Self::from_error(error).map_or_else(
+            || "Något gick fel. Försök igen senare.".to_owned(),
+            |error| error.localized(),
+        )
+    }
+}
+
+impl From<AppError> for ServerFnError {
+    fn from(error: AppError) -> Self {
+        Self::ServerError {
+            message: error.to_string(),
+            code: error.status().as_u16(),
+            details: serde_json::to_value(&error).ok(),
+        }
+    }
+}
+
+impl From<AppError> for CapturedError {
+    fn from(error: AppError) -> Self {
+        ServerFnError::from(error).into()
+    }
+}
+
+/// Turn a database error into an [`AppError`] if it was caused by the user, see the module
+/// documentation.
+///
+/// Other errors are passed through unchanged.
+#[cfg(feature = "server")]
+pub(crate) fn classify(error: SqlxError) -> CapturedError {
+    let classified = if let SqlxError::Database(e) = &error {
+        let e = e.downcast_ref::<PgDatabaseError>();
+        let constraint = e.constraint().unwrap_or_default();
+        match e.code() {
+            "23503" | "P0002" => Some(AppError::NotFound),
+            "23505" => Some(AppError::Conflict {
+                field: e
+                    .table()
+                    .and_then(|table| constraint.strip_prefix(table)?.strip_prefix('_'))
+                    .and_then(|rest| rest.strip_suffix("_key"))
+                    .unwrap_or(constraint)
+                    .into(),
+            }),
+            "23502" | "23514" => Some(AppError::Invalid {
+                constraint: e
+                    .constraint()
+                    .or_else(|| e.column())
+                    .unwrap_or_default()
+                    .into(),
+            }),
+            "42501" => Some(AppError::Forbidden),
+            "BP001" => StaleReason::from_constraint(constraint)
+                .map(|reason| AppError::StaleCart { reason }),
+            "BP002" => e
+                .detail()
+                .and_then(|product| product.parse().ok())
+                .map(|product: i32| AppError::OutOfStock {
+                    product: product.into(),
+                }),
+            _ => None,
+        }
+    } else if matches!(error, SqlxError::RowNotFound) {
+        Some(AppError::NotFound)
+    } else {
+        None
+    };
+    classified.map_or_else(|| error.into(), Into::into)
+}
@@ -196,3 +196,39 @@ impl Key for SpecialOffer {}
 pub struct Order;
 impl Sealed for Order {}
 impl Key for Order {}
+
+/// Marker for media IDs.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Media;
+impl Sealed for Media {}
+impl Key for Media {}
+
+/// Marker for delivery address IDs.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Address;
+impl Sealed for Address {}
+impl Key for Address {}
+
+/// Marker for shipping method IDs.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct ShippingMethod;
+impl Sealed for ShippingMethod {}
+impl Key for ShippingMethod {}
+
+/// Marker for membership tier IDs.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct MembershipTier;
+impl Sealed for MembershipTier {}
+impl Key for MembershipTier {}
+
+/// Marker for promotion IDs.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Promotion;
+impl Sealed for Promotion {}
+impl Key for Promotion {}
+
+/// Marker for bundle IDs.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Bundle;
+impl Sealed for Bundle {}
+impl Key for Bundle {}
@@ -0,0 +1,481 @@
+//! Uploading and serving images stored by the server.
+//!
+//! Uploaded images are validated, given a thumbnail and handed to a [`MediaStorage`], while their
+//! metadata is kept in the database. The resulting [`Id<Media>`] is what product images and profile
+//! pictures are set with, and converts into the [`Url`] they are shown from.
+
+use crate::database::{Id, Media, RawId, Url};
+use dioxus::prelude::*;
+use dioxus_fullstack::{FileStream, response::Response};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+#[cfg(feature = "server")]
+use {
+    crate::database::{AppError, POOL, User, caller},
+    dioxus_fullstack::response::IntoResponse as _,
+    futures_util::StreamExt as _,
+    http::header::{CACHE_CONTROL, CONTENT_TYPE},
+    image::{DynamicImage, ImageFormat, ImageReader, Limits},
+    sqlx::{Type, query_scalar},
+    std::{
+        fmt::Debug,
+        fs,
+        io::{self, Cursor},
+        path::PathBuf,
+        sync::{Arc, OnceLock},
+    },
+    tokio::task::spawn_blocking,
+};
+
+/// The largest accepted upload, in bytes.
+pub const MAX_SIZE: u64 = 8 * 1024 * 1024;
+
+/// The largest accepted width and height of an uploaded image, in pixels.
+pub const MAX_DIMENSION: u32 = 8192;
+
+/// The size of the square that thumbnails are scaled to fit within, in pixels.
+pub const THUMBNAIL_SIZE: u32 = 400;
+
+/// An uploaded image was rejected.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Error, Serialize, Deserialize)]
+pub enum MediaError {
+    /// The file is not a JPEG, PNG or WebP image, or does not match its declared type.
+    #[error("Only JPEG, PNG and WebP images are accepted.")]
+    UnsupportedType,
+    /// The file is larger than [`MAX_SIZE`].
+    #[error("The image is too large.")]
+    TooLarge,
+    /// The file could not be decoded, or its dimensions exceed [`MAX_DIMENSION`].
+    #[error("The image is corrupt or has too large dimensions.")]
+    InvalidImage,
+}
+
+/// The format of a stored image.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[cfg_attr(feature = "server", derive(Type))]
+#[cfg_attr(
+    feature = "server",
+    sqlx(type_name = "media_type", rename_all = "lowercase")
+)]
+pub enum MediaType {
+    /// A JPEG image.
+    Jpeg,
+    /// A PNG image.
+    Png,
+    /// A WebP image.
+    Webp,
+}
+
+impl MediaType {
+    /// Get the MIME type of the format.
+    #[must_use]
+    pub const fn content_type(self) -> &'static str {
+        match self {
+            Self::Jpeg => "image/jpeg",
+            Self::Png => "image/png",
+            Self::Webp => "image/webp",
+        }
+    }
+
+    /// Parse a MIME type, returning `None` if the format is not supported.
+    #[must_use]
+    pub fn from_content_type(content_type: &str) -> Option<Self> {
+        match content_type.trim().to_ascii_lowercase().as_str() {
+            "image/jpeg" | "image/jpg" => Some(Self::Jpeg),
+            "image/png" => Some(Self::Png),
+            "image/webp" => Some(Self::Webp),
+            _ => None,
+        }
+    }
+
+    /// Get the format that thumbnails of images in this format are stored in.
+    ///
+    /// Photos stay JPEG, while everything else becomes PNG so that transparency is preserved.
+    #[must_use]
+    pub const fn thumbnail_type(self) -> Self {
+        match self {
+            Self::Jpeg => Self::Jpeg,
+            Self::Png | Self::Webp => Self::Png,
+        }
+    }
+
+    #[cfg(feature = "server")]
+    #[expect(
+        clippy::wildcard_enum_match_arm,
+        reason = "Every other format is unsupported."
+    )]
+    const fn from_format(format: ImageFormat) -> Option<Self> {
+        match format {
+            ImageFormat::Jpeg => Some(Self::Jpeg),
+            ImageFormat::Png => Some(Self::Png),
+            ImageFormat::WebP => Some(Self::Webp),
+            _ => None,
+        }
+    }
+
+    #[cfg(feature = "server")]
+    const fn format(self) -> ImageFormat {
+        match self {
+            Self::Jpeg => ImageFormat::Jpeg,
+            Self::Png => ImageFormat::Png,
+            Self::Webp => ImageFormat::WebP,
+        }
+    }
+}
+
+/// One of the files stored for each uploaded image.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum MediaVariant {
+    /// The image as uploaded.
+    Original,
+    /// A downscaled version of the image, see [`THUMBNAIL_SIZE`].
+    Thumbnail,
+}
+
+impl From<Id<Media>> for Url {
+    fn from(value: Id<Media>) -> Self {
+        format!("/media/{value}").into()
+    }
+}
+
+/// Get the URL of the thumbnail of an uploaded image.
+///
+/// The full image is available at the URL that `media` converts into.
+#[must_use]
+pub fn thumbnail_url(media: Id<Media>) -> Url {
+    format!("/media/{media}/thumbnail").into()
+}
+
+/// Verify that every image in `media` has been uploaded, before storing its [`Url`].
+///
+/// # Errors
+///
+/// Fails if:
+/// - An image in `media` does not exist, see [`AppError::NotFound`].
+/// - An error occurs during communication with the database.
+#[cfg(feature = "server")]
+pub(crate) async fn verify_stored(media: &[Id<Media>]) -> Result<()> {
+    let media = media.iter().map(|media| media.get()).collect::<Vec<_>>();
+    let stored = query_scalar!(
+        r#"
+        SELECT COALESCE(bool_and(m.id IS NOT NULL), TRUE) AS "stored!"
+        FROM UNNEST($1::INT[]) AS i(id)
+        LEFT JOIN media m ON m.id = i.id
+        "#,
+        &media,
+    )
+    .fetch_one(&*POOL)
+    .await?;
+    if stored {
+        Ok(())
+    } else {
+        Err(AppError::NotFound.into())
+    }
+}
+
+/// A place to keep the files of uploaded images.
+///
+/// Methods are blocking, and are always called from a thread where that is permitted.
+#[cfg(feature = "server")]
+pub trait MediaStorage: Debug + Send + Sync {
+    /// Store a file, replacing any previous file stored for the same image and variant.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the file could not be stored.
+    fn store(&self, media: Id<Media>, variant: MediaVariant, data: &[u8]) -> io::Result<()>;
+
+    /// Load a stored file.
+    ///
+    /// # Errors
+    ///
+    /// Fails if no such file is stored, or if it could not be loaded.
+    fn load(&self, media: Id<Media>, variant: MediaVariant) -> io::Result<Vec<u8>>;
+}
+
+/// Storage of media files in a directory on the local file system.
+///
+/// This is the default storage, using the directory given by `MEDIA_DIR`, falling back to `media`
+/// in the working directory.
+#[cfg(feature = "server")]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LocalStorage {
+    /// The directory files are stored in. Created when needed.
+    root: PathBuf,
+}
+
+#[cfg(feature = "server")]
+impl LocalStorage {
+    /// Create a storage keeping files in `root`.
+    #[must_use]
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    fn path(&self, media: Id<Media>, variant: MediaVariant) -> PathBuf {
+        self.root.join(match variant {
+            MediaVariant::Original => format!("{media}"),
+            MediaVariant::Thumbnail => format!("{media}.thumbnail"),
+        })
+    }
+}
+
+#[cfg(feature = "server")]
+impl MediaStorage for LocalStorage {
+    fn store(&self, media: Id<Media>, variant: MediaVariant, data: &[u8]) -> io::Result<()> {
+        fs::create_dir_all(&self.root)?;
+        // Written in full before being moved into place, so that readers never see partial files.
+        let path = self.path(media, variant);
+        let mut partial = path.clone().into_os_string();
+        partial.push(".partial");
+        fs::write(&partial, data)?;
+        fs::rename(partial, path)
+    }
+
+    fn load(&self, media: Id<Media>, variant: MediaVariant) -> io::Result<Vec<u8>> {
+        fs::read(self.path(media, variant))
+    }
+}
+
+#[cfg(feature = "server")]
+static STORAGE: OnceLock<Arc<dyn MediaStorage>> = OnceLock::new();
+
+/// Use `storage` for all media instead of the default [`LocalStorage`].
+///
+/// # Panics
+///
+/// Panics if media storage has already been used or set.
+#[cfg(feature = "server")]
+pub fn set_media_storage(storage: impl MediaStorage + 'static) {
+    STORAGE
+        .set(Arc::new(storage))
+        .expect("Media storage was used or set before being set.");
+}
+
+#[cfg(feature = "server")]
+fn storage() -> Arc<dyn MediaStorage> {
+    Arc::clone(STORAGE.get_or_init(|| {
+        let root = dotenvy::var("MEDIA_DIR").unwrap_or_else(|_| "media".to_owned());
+        Arc::new(LocalStorage::new(root))
+    }))
+}
+
+/// A validated upload, ready to be stored.
+#[cfg(feature = "server")]
+struct Processed {
+    media_type: MediaType,
+    width: u32,
+    height: u32,
+    thumbnail: Vec<u8>,
+}
+
+/// Validate an uploaded file and generate its thumbnail.
+///
+/// # Errors
+///
+/// Fails if:
+/// - `data` is not a supported image, or is not of the type `declared`.
+/// - `data` could not be decoded, or is too large.
+///
+/// # Panics
+///
+/// Panics if the thumbnail could not be encoded, which should be impossible when writing to memory.
+#[cfg(feature = "server")]
+fn process(data: &[u8], declared: Option<MediaType>) -> Result<Processed, MediaError> {
+    let media_type = image::guess_format(data)
+        .ok()
+        .and_then(MediaType::from_format)
+        .filter(|&sniffed| declared.is_none_or(|declared| declared == sniffed))
+        .ok_or(MediaError::UnsupportedType)?;
+
+    let mut limits = Limits::default();
+    limits.max_image_width = Some(MAX_DIMENSION);
+    limits.max_image_height = Some(MAX_DIMENSION);
+    let mut reader = ImageReader::with_format(Cursor::new(data), media_type.format());
+    reader.limits(limits);
+    let image = reader.decode().ok().ok_or(MediaError::InvalidImage)?;
+
+    let thumbnail_type = media_type.thumbnail_type();
+    let thumbnail = match image.thumbnail(THUMBNAIL_SIZE, THUMBNAIL_SIZE) {
+        // JPEG has no alpha channel.
+        thumbnail if thumbnail_type == MediaType::Jpeg => DynamicImage::from(thumbnail.to_rgb8()),
+        thumbnail => thumbnail,
+    };
+    let mut encoded = Cursor::new(Vec::new());
+    thumbnail
+        .write_to(&mut encoded, thumbnail_type.format())
+        .expect("Encoding to memory failed.");
+
+    Ok(Processed {
+        media_type,
+        width: image.width(),
+        height: image.height(),
+        thumbnail: encoded.into_inner(),
+    })
+}
+
+/// Validate and store an uploaded image.
+///
+/// # Errors
+///
+/// Fails if:
+/// - The image is rejected, see [`MediaError`].
+/// - The image could not be stored.
+/// - An error occurs during communication with the database.
+#[cfg(feature = "server")]
+async fn store_media(
+    uploader: Id<User>,
+    data: Vec<u8>,
+    declared: Option<MediaType>,
+) -> Result<Id<Media>> {
+    let data = Arc::<[u8]>::from(data);
+    let Processed {
+        media_type,
+        width,
+        height,
+        thumbnail,
+    } = spawn_blocking({
+        let data = Arc::clone(&data);
+        move || process(&data, declared)
+    })
+    .await??;
+
+    // The row is only committed once the files are in place, so that every ID handed out can be
+    // served.
+    let mut tx = POOL.begin().await?;
+    let media = query_scalar!(
+        "
+        INSERT INTO media (uploader, media_type, width, height, size)
+        VALUES ($1, $2, $3, $4, $5)
+        RETURNING id
+        ",
+        uploader.get(),
+        media_type as MediaType,
+        width as i32,
+        height as i32,
+        data.len() as i32,
+    )
+    .fetch_one(&mut *tx)
+    .await?
+    .into();
+
+    spawn_blocking(move || {
+        let storage = storage();
+        storage.store(media, MediaVariant::Original, &data)?;
+        storage.store(media, MediaVariant::Thumbnail, &thumbnail)
+    })
+    .await??;
+
+    tx.commit().await?;
+    Ok(media)
+}
+
+/// Upload an image, returning the ID it was stored with.
+///
+/// JPEG, PNG and WebP images of up to [`MAX_SIZE`] bytes are accepted. The ID is used to set a
+/// product image or profile picture, and converts into the [`Url`] of the image. A thumbnail is
+/// generated as well, see [`thumbnail_url`].
+///
+/// # Errors
+///
+/// Fails if:
+/// - The caller is not logged in.
+/// - The image is rejected, see [`MediaError`].
+/// - The upload is interrupted.
+/// - The image could not be stored.
+/// - An error occurs during communication with the database.
+#[post("/api/media")]
+pub async fn upload_media(mut file: FileStream) -> Result<Id<Media>> {
+    let uploader = caller().await?.into();
+
+    let declared = file
+        .content_type()
+        .map(|content_type| {
+            MediaType::from_content_type(content_type).ok_or(MediaError::UnsupportedType)
+        })
+        .transpose()?;
+    if file.size().is_some_and(|size| size > MAX_SIZE) {
+        return Err(MediaError::TooLarge.into());
+    }
+
+    let mut data = Vec::new();
+    while let Some(chunk) = file.next().await {
+        let chunk = chunk?;
+        if (data.len() + chunk.len()) as u64 > MAX_SIZE {
+            return Err(MediaError::TooLarge.into());
+        }
+        data.extend_from_slice(&chunk);
+    }
+
+    store_media(uploader, data, declared).await
+}
+
+/// Respond with a stored file.
+///
+/// # Errors
+///
+/// Fails if:
+/// - `media` is invalid.
+/// - The file could not be loaded.
+/// - An error occurs during communication with the database.
+#[cfg(feature = "server")]
+async fn serve(media: Id<Media>, variant: MediaVariant) -> Result<Response> {
+    let media_type = query_scalar!(
+        r#"
+        SELECT media_type AS "media_type: MediaType"
+        FROM media
+        WHERE id = $1
+        "#,
+        media.get(),
+    )
+    .fetch_optional(&*POOL)
+    .await?
+    .ok_or_else(|| HttpError::new(StatusCode::NOT_FOUND, "No such media."))?;
+    let content_type = match variant {
+        MediaVariant::Original => media_type,
+        MediaVariant::Thumbnail => media_type.thumbnail_type(),
+    }
+    .content_type();
+
+    let data = spawn_blocking(move || storage().load(media, variant)).await??;
+
+    Ok((
+        [
+            (CONTENT_TYPE, content_type),
+            // Stored files never change.
+            (CACHE_CONTROL, "public, max-age=31536000, immutable"),
+        ],
+        data,
+    )
+        .into_response())
+}
+
+/// Get an uploaded image.
+///
+/// This is what the [`Url`] of an [`Id<Media>`] points to.
+///
+/// # Errors
+///
+/// Fails if:
+/// - `media` is invalid.
+/// - The image could not be loaded.
+/// - An error occurs during communication with the database.
+#[get("/media/{media}")]
+pub async fn media_file(media: RawId) -> Result<Response> {
+    serve(media.into(), MediaVariant::Original).await
+}
+
+/// Get the thumbnail of an uploaded image.
+///
+/// This is what the [`Url`] given by [`thumbnail_url`] points to.
+///
+/// # Errors
+///
+/// Fails if:
+/// - `media` is invalid.
+/// - The image could not be loaded.
+/// - An error occurs during communication with the database.
+#[get("/media/{media}/thumbnail")]
+pub async fn media_thumbnail(media: RawId) -> Result<Response> {
+    serve(media.into(), MediaVariant::Thumbnail).await
+}
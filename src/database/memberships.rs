@@ -0,0 +1,307 @@
+//! Database functions for interacting with customer memberships.
+//!
+//! Members may use special offers only for members. Membership is free and lasts until cancelled,
+//! but customers may also pay for a membership tier, which lasts until its renewal date. Paying
+//! again for the same tier before then extends the membership by another period. Memberships that
+//! are not renewed expire at midnight on their renewal date.
+
+use crate::database::{Customer, Id, MembershipTier, payments::CardNumber};
+use dioxus::prelude::*;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use time::{Date, PrimitiveDateTime};
+#[cfg(feature = "server")]
+use {
+    crate::database::{
+        POOL, QueryResultExt as _, RawId, authorize_administrator, authorize_customer, classify,
+        payments,
+    },
+    sqlx::{query, query_as, query_scalar},
+};
+
+/// A membership tier customers may pay for.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct MembershipTierInfo {
+    /// The ID of the tier.
+    pub id: Id<MembershipTier>,
+    /// The name of the tier, e.g. "Årsmedlemskap".
+    pub name: Box<str>,
+    /// The fee paid for each period.
+    pub fee: Decimal,
+    /// The length of each period in months.
+    pub months: u32,
+}
+
+#[cfg(feature = "server")]
+struct MembershipTierRepr {
+    id: RawId,
+    name: String,
+    fee: Decimal,
+    months: i32,
+}
+
+#[cfg(feature = "server")]
+impl From<MembershipTierRepr> for MembershipTierInfo {
+    #[expect(clippy::cast_sign_loss, reason = "Database validation.")]
+    fn from(
+        MembershipTierRepr {
+            id,
+            name,
+            fee,
+            months,
+        }: MembershipTierRepr,
+    ) -> Self {
+        Self {
+            id: id.into(),
+            name: name.into(),
+            fee,
+            months: months as u32,
+        }
+    }
+}
+
+/// The membership of a customer.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Membership {
+    /// When the customer became a member.
+    pub since: PrimitiveDateTime,
+    /// The tier paid for and its renewal date, i.e. the first day the customer is no longer a
+    /// member unless they pay again. `None` if the membership is free.
+    pub paid: Option<(MembershipTierInfo, Date)>,
+}
+
+/// Get the membership tiers customers may pay for, from the cheapest.
+///
+/// # Errors
+///
+/// Fails if an error occurs during communication with the database.
+#[server]
+pub async fn membership_tiers() -> Result<Box<[MembershipTierInfo]>> {
+    query_as!(
+        MembershipTierRepr,
+        "
+        SELECT id, name, fee, months
+        FROM membership_tiers
+        ORDER BY fee, id
+        ",
+    )
+    .fetch_all(&*POOL)
+    .await
+    .map(|tiers| tiers.into_iter().map(Into::into).collect())
+    .map_err(Into::into)
+}
+
+/// Create a membership tier, returning its ID.
+///
+/// # Errors
+///
+/// Fails if:
+/// - `name` is empty or already taken, see
+///   [`AppError::Conflict`](crate::database::AppError::Conflict).
+/// - `fee` is not positive or `months` is zero.
+/// - The caller is not an administrator.
+/// - An error occurs during communication with the database.
+#[server]
+pub async fn create_membership_tier(
+    name: Box<str>,
+    fee: Decimal,
+    months: u32,
+) -> Result<Id<MembershipTier>> {
+    authorize_administrator().await?;
+
+    query_scalar!(
+        "
+        INSERT INTO membership_tiers (name, fee, months)
+        VALUES ($1, $2::DECIMAL, $3::INT)
+        RETURNING id
+        ",
+        &*name,
+        fee,
+        i32::try_from(months)?,
+    )
+    .fetch_one(&*POOL)
+    .await
+    .map(Into::into)
+    .map_err(classify)
+}
+
+#[cfg(feature = "server")]
+struct MembershipRepr {
+    since: PrimitiveDateTime,
+    id: Option<RawId>,
+    name: Option<String>,
+    fee: Option<Decimal>,
+    months: Option<i32>,
+    until: Option<Date>,
+}
+
+/// Get the membership of a customer, or `None` if they are not a member.
+///
+/// # Errors
+///
+/// Fails if:
+/// - `customer` is invalid.
+/// - The caller is not logged in as `customer`.
+/// - An error occurs during communication with the database.
+#[server]
+pub async fn membership(customer: Id<Customer>) -> Result<Option<Membership>> {
+    authorize_customer(customer).await?;
+
+    query_as!(
+        MembershipRepr,
+        r#"
+        SELECT member_since AS "since!", mt.id AS "id?", mt.name AS "name?", mt.fee AS "fee?",
+            mt.months AS "months?", member_until AS until
+        FROM customers
+        LEFT JOIN membership_tiers mt ON mt.id = membership_tier
+        WHERE customers.id = $1 AND current_member(customers)
+        "#,
+        customer.get(),
+    )
+    .fetch_optional(&*POOL)
+    .await
+    .map(|membership| {
+        membership.map(
+            |MembershipRepr {
+                 since,
+                 id,
+                 name,
+                 fee,
+                 months,
+                 until,
+             }| Membership {
+                since,
+                paid: Option::zip(id.zip(name).zip(fee).zip(months), until).map(
+                    |((((id, name), fee), months), until)| {
+                        (
+                            MembershipTierRepr {
+                                id,
+                                name,
+                                fee,
+                                months,
+                            }
+                            .into(),
+                            until,
+                        )
+                    },
+                ),
+            },
+        )
+    })
+    .map_err(Into::into)
+}
+
+/// Make a customer a member.
+///
+/// With `paid` as `None`, the membership is free, and lasts until cancelled. Joining for free has
+/// no effect on customers who are already members.
+///
+/// Otherwise, the fee of the tier is charged to the card, and the membership lasts for the months
+/// of the tier. If the customer has already paid for the same tier, the period is added to their
+/// current one, otherwise it starts today and replaces their current membership.
+///
+/// # Errors
+///
+/// Fails if:
+/// - `customer` or the tier is invalid.
+/// - The payment provider did not complete the payment, see
+///   [`AppError::PaymentFailed`](crate::database::AppError::PaymentFailed). The membership is then
+///   left as it was.
+/// - The caller is not logged in as `customer`.
+/// - An error occurs during communication with the database.
+#[server]
+pub async fn join_membership(
+    customer: Id<Customer>,
+    paid: Option<(Id<MembershipTier>, CardNumber)>,
+) -> Result<()> {
+    authorize_customer(customer).await?;
+
+    let Some((tier, card)) = paid else {
+        return query!(
+            "
+            UPDATE customers
+            SET member_since = COALESCE(member_since, CURRENT_TIMESTAMP)
+            WHERE id = $1
+            ",
+            customer.get(),
+        )
+        .execute(&*POOL)
+        .await?
+        .by_unique_key()
+        .map_err(Into::into);
+    };
+
+    let fee = query_scalar!("SELECT fee FROM membership_tiers WHERE id = $1", tier.get())
+        .fetch_one(&*POOL)
+        .await
+        .map_err(classify)?;
+    let reference = payments::charge(card, fee).await?;
+
+    // NOTE: The payment has been made at this point, so the membership must be granted even if the
+    // customer has since joined another tier.
+    let mut tx = POOL.begin().await?;
+    query!(
+        "
+        INSERT INTO membership_payments (customer, tier, reference, amount)
+        VALUES ($1, $2, $3, $4::DECIMAL)
+        ",
+        customer.get(),
+        tier.get(),
+        &*reference,
+        fee,
+    )
+    .execute(&mut *tx)
+    .await
+    .map_err(classify)?
+    .expect_one();
+    query!(
+        "
+        UPDATE customers
+        SET
+            member_since = COALESCE(member_since, CURRENT_TIMESTAMP),
+            member_until = (
+                CASE
+                    WHEN membership_tier = mt.id THEN GREATEST(member_until, CURRENT_DATE)
+                    ELSE CURRENT_DATE
+                END + make_interval(months => mt.months)
+            )::DATE,
+            membership_tier = mt.id
+        FROM membership_tiers mt
+        WHERE customers.id = $1 AND mt.id = $2
+        ",
+        customer.get(),
+        tier.get(),
+    )
+    .execute(&mut *tx)
+    .await?
+    .expect_one();
+    tx.commit().await.map_err(Into::into)
+}
+
+/// End the membership of a customer at once.
+///
+/// Fees already paid are not refunded.
+///
+/// # Errors
+///
+/// Fails if:
+/// - `customer` is invalid.
+/// - The caller is not logged in as `customer`.
+/// - An error occurs during communication with the database.
+#[server]
+pub async fn cancel_membership(customer: Id<Customer>) -> Result<()> {
+    authorize_customer(customer).await?;
+
+    query!(
+        "
+        UPDATE customers
+        SET member_since = NULL, membership_tier = NULL, member_until = NULL
+        WHERE id = $1
+        ",
+        customer.get(),
+    )
+    .execute(&*POOL)
+    .await?
+    .by_unique_key()
+    .map_err(Into::into)
+}
@@ -1,4 +1,7 @@
 //! Database functions for interacting with special offers.
+//!
+//! Several special offers may be active on a product at once. Each customer gets the one giving
+//! them the lowest price among those they are eligible for.
 
 use crate::database::{Deal, Id, Product, SpecialOffer};
 use dioxus::prelude::*;
@@ -6,7 +9,9 @@ use std::num::NonZeroU32;
 use time::PrimitiveDateTime;
 #[cfg(feature = "server")]
 use {
-    crate::database::{POOL, QueryResultExt},
+    crate::database::{
+        POOL, QueryResultExt, authorize_product_owner, authorize_special_offer_owner, classify,
+    },
     sqlx::query,
 };
 
@@ -22,8 +27,9 @@ use {
 /// - `pay_for > i32::MAX` (if [`BatchPrice`]).
 /// - `limit_per_customer > i32::MAX` (if [`Some`]).
 /// - `valid_until` is in the past.
-/// - The special offer overlaps with an existing one.
-/// - The special offer does not actually provide a discount compared to the current price.
+/// - The special offer does not actually provide a discount compared to the current price, see
+///   [`AppError::Invalid`](crate::database::AppError::Invalid).
+/// - The caller is not the vendor selling `product`.
 /// - An error occurs during communication with the database.
 #[server]
 pub async fn create_special_offer(
@@ -34,6 +40,8 @@ pub async fn create_special_offer(
     valid_from: PrimitiveDateTime,
     valid_until: Option<PrimitiveDateTime>,
 ) -> Result<()> {
+    authorize_product_owner(product, false).await?;
+
     // NOTE: `valid_until` intentionally not checked for being in the past as even then the database
     // might see it at a later time where it then is in the past.
 
@@ -61,7 +69,7 @@ pub async fn create_special_offer(
     .execute(&*POOL)
     .await
     .map(QueryResultExt::expect_one)
-    .map_err(Into::into)
+    .map_err(classify)
 }
 
 /// Set the limit per customer of a special offer.
@@ -75,12 +83,15 @@ pub async fn create_special_offer(
 /// Fails if:
 /// - `special_offer` is invalid.
 /// - `limit_per_customer > i32::MAX`.
+/// - The caller is not the vendor selling the product `special_offer` applies to.
 /// - An error occurs during communication with the database.
 #[server]
 pub async fn set_special_offer_limit(
     special_offer: Id<SpecialOffer>,
     limit_per_customer: NonZeroU32,
 ) -> Result<()> {
+    authorize_special_offer_owner(special_offer).await?;
+
     query!(
         "
         UPDATE special_offers
@@ -91,7 +102,8 @@ pub async fn set_special_offer_limit(
         i32::try_from(limit_per_customer.get())?,
     )
     .execute(&*POOL)
-    .await?
+    .await
+    .map_err(classify)?
     .by_unique_key()
     .map_err(Into::into)
 }
@@ -102,12 +114,15 @@ pub async fn set_special_offer_limit(
 ///
 /// Fails if:
 /// - `special_offer` is invalid.
+/// - The caller is not the vendor selling the product `special_offer` applies to.
 /// - An error occurs during communication with the database.
 #[server]
 pub async fn set_special_offer_members_only(
     special_offer: Id<SpecialOffer>,
     members_only: bool,
 ) -> Result<()> {
+    authorize_special_offer_owner(special_offer).await?;
+
     query!(
         "
         UPDATE special_offers
@@ -118,7 +133,8 @@ pub async fn set_special_offer_members_only(
         members_only,
     )
     .execute(&*POOL)
-    .await?
+    .await
+    .map_err(classify)?
     .by_unique_key()
     .map_err(Into::into)
 }
@@ -131,13 +147,15 @@ pub async fn set_special_offer_members_only(
 /// - `special_offer` is invalid.
 /// - `valid_from` is in the past (see [`set_special_offer_start_now`] if the intent is to activate
 ///   it).
-/// - The special offer now overlaps with an existing one.
+/// - The caller is not the vendor selling the product `special_offer` applies to.
 /// - An error occurs during communication with the database.
 #[server]
 pub async fn set_special_offer_start(
     special_offer: Id<SpecialOffer>,
     valid_from: PrimitiveDateTime,
 ) -> Result<()> {
+    authorize_special_offer_owner(special_offer).await?;
+
     // NOTE: `valid_from` intentionally not checked for being in the past as even then the database
     // might see it at a later time where it then is in the past.
 
@@ -151,7 +169,8 @@ pub async fn set_special_offer_start(
         valid_from,
     )
     .execute(&*POOL)
-    .await?
+    .await
+    .map_err(classify)?
     .by_unique_key()
     .map_err(Into::into)
 }
@@ -163,9 +182,12 @@ pub async fn set_special_offer_start(
 /// Fails if:
 /// - `special_offer` is invalid.
 /// - Another special offer is already active.
+/// - The caller is not the vendor selling the product `special_offer` applies to.
 /// - An error occurs during communication with the database.
 #[server]
 pub async fn set_special_offer_start_now(special_offer: Id<SpecialOffer>) -> Result<()> {
+    authorize_special_offer_owner(special_offer).await?;
+
     query!(
         "
         UPDATE special_offers
@@ -175,7 +197,8 @@ pub async fn set_special_offer_start_now(special_offer: Id<SpecialOffer>) -> Res
         special_offer.get(),
     )
     .execute(&*POOL)
-    .await?
+    .await
+    .map_err(classify)?
     .by_unique_key()
     .map_err(Into::into)
 }
@@ -189,13 +212,15 @@ pub async fn set_special_offer_start_now(special_offer: Id<SpecialOffer>) -> Res
 /// Fails if:
 /// - `special_offer` is invalid.
 /// - `valid_until` is in the past (see [`delete_special_offer`] if the intent is to delete it).
-/// - The special offer now overlaps with an existing one.
+/// - The caller is not the vendor selling the product `special_offer` applies to.
 /// - An error occurs during communication with the database.
 #[server]
 pub async fn set_special_offer_end(
     special_offer: Id<SpecialOffer>,
     valid_until: Option<PrimitiveDateTime>,
 ) -> Result<()> {
+    authorize_special_offer_owner(special_offer).await?;
+
     // NOTE: `valid_until` intentionally not checked for being in the past as even then the database
     // might see it at a later time where it then is in the past.
 
@@ -209,7 +234,8 @@ pub async fn set_special_offer_end(
         valid_until,
     )
     .execute(&*POOL)
-    .await?
+    .await
+    .map_err(classify)?
     .by_unique_key()
     .map_err(Into::into)
 }
@@ -220,9 +246,12 @@ pub async fn set_special_offer_end(
 ///
 /// Fails if:
 /// - `special_offer` is invalid.
+/// - The caller is not the vendor selling the product `special_offer` applies to.
 /// - An error occurs during communication with the database.
 #[server]
 pub async fn delete_special_offer(special_offer: Id<SpecialOffer>) -> Result<()> {
+    authorize_special_offer_owner(special_offer).await?;
+
     query!(
         "
         DELETE FROM special_offers
@@ -231,7 +260,8 @@ pub async fn delete_special_offer(special_offer: Id<SpecialOffer>) -> Result<()>
         special_offer.get(),
     )
     .execute(&*POOL)
-    .await?
+    .await
+    .map_err(classify)?
     .by_unique_key()
     .map_err(Into::into)
 }
@@ -242,9 +272,14 @@ pub async fn delete_special_offer(special_offer: Id<SpecialOffer>) -> Result<()>
 ///
 /// Fails if:
 /// - `special_offer` is invalid.
+/// - `deal` does not actually provide a discount compared to the current price, see
+///   [`AppError::Invalid`](crate::database::AppError::Invalid).
+/// - The caller is not the vendor selling the product `special_offer` applies to.
 /// - An error occurs during communication with the database.
 #[server]
 pub async fn set_special_offer_deal(special_offer: Id<SpecialOffer>, deal: Deal) -> Result<()> {
+    authorize_special_offer_owner(special_offer).await?;
+
     let (new_price, quantity1, quantity2) = deal.database_repr()?;
 
     query!(
@@ -259,7 +294,8 @@ pub async fn set_special_offer_deal(special_offer: Id<SpecialOffer>, deal: Deal)
         quantity2,
     )
     .execute(&*POOL)
-    .await?
+    .await
+    .map_err(classify)?
     .by_unique_key()
     .map_err(Into::into)
 }
@@ -0,0 +1,441 @@
+//! Paying for orders.
+//!
+//! Orders are placed awaiting payment, which reserves their stock. The customer's card is then
+//! authorized for the amount of the order by a [`PaymentProvider`], after which the order is
+//! pending. The payment is captured when the order is shipped, and refunded when the order is
+//! cancelled or refunded. If the authorization fails, or the order has been awaiting payment for
+//! half an hour, the order is cancelled and its products are put back in the customer's cart.
+//! Membership tiers are instead charged at once, see
+//! [`join_membership`](crate::database::memberships::join_membership).
+//!
+//! No real payments are made. The default provider is [`MockProvider`], which runs in-process and
+//! decides the outcome of each payment by the card number.
+
+use derive_more::Display;
+use serde::{Deserialize, Deserializer, Serialize, de::Error as _};
+use std::fmt::{self, Debug, Formatter};
+#[cfg(feature = "server")]
+use {
+    crate::database::{AppError, Id, Order, POOL, QueryResultExt, products::OrderStatus},
+    dioxus::prelude::*,
+    rust_decimal::Decimal,
+    sqlx::{PgConnection, Type, query, query_as, query_scalar},
+    std::sync::{
+        Arc, OnceLock,
+        atomic::{AtomicU64, Ordering},
+    },
+    tokio::task::spawn_blocking,
+};
+
+/// The number of a payment card, with a valid length and check digit.
+///
+/// Debug formatting only shows the last four digits, so that card numbers do not end up in logs.
+/// Deserialization fails for numbers rejected by [`new`](Self::new).
+#[derive(Clone, PartialEq, Eq, Hash, Serialize)]
+pub struct CardNumber(Box<str>);
+
+impl CardNumber {
+    /// Verify the format, ignoring spaces, and construct a `CardNumber` on success.
+    #[must_use]
+    pub fn new(s: &str) -> Option<Self> {
+        let digits = s.chars().filter(|&c| c != ' ').collect::<Box<str>>();
+        if !(12..=19).contains(&digits.len()) || !digits.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+
+        // The Luhn algorithm: every second digit from the right is doubled, and the sum of all
+        // digits must be divisible by ten.
+        let sum = digits
+            .bytes()
+            .rev()
+            .enumerate()
+            .map(|(i, b)| {
+                let digit = u32::from(b - b'0');
+                if i % 2 == 0 {
+                    digit
+                } else if digit < 5 {
+                    digit * 2
+                } else {
+                    digit * 2 - 9
+                }
+            })
+            .sum::<u32>();
+        (sum % 10 == 0).then_some(Self(digits))
+    }
+
+    /// Get the last four digits of the number.
+    #[must_use]
+    pub fn last_digits(&self) -> &str {
+        self.0.get(self.0.len() - 4..).unwrap_or_default()
+    }
+}
+
+impl<'de> Deserialize<'de> for CardNumber {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        Self::new(&s).ok_or_else(|| D::Error::custom("invalid card number"))
+    }
+}
+
+impl Debug for CardNumber {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "CardNumber(**** {})", self.last_digits())
+    }
+}
+
+/// Why a payment provider did not complete a payment.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize, Display)]
+pub enum PaymentFailure {
+    /// The card was declined, e.g. due to insufficient funds.
+    #[display("the card was declined")]
+    Declined,
+    /// The provider did not respond in time.
+    #[display("the payment provider did not respond in time")]
+    TimedOut,
+    /// The provider could not be reached or failed to handle the request.
+    #[display("the payment provider is unavailable")]
+    Unavailable,
+}
+
+impl PaymentFailure {
+    /// Describe the failure to the user, in Swedish.
+    #[must_use]
+    pub const fn localized(self) -> &'static str {
+        match self {
+            Self::Declined => "Kortet nekades.",
+            Self::TimedOut => "Betalningen tog för lång tid.",
+            Self::Unavailable => "Betalningstjänsten är inte tillgänglig just nu.",
+        }
+    }
+}
+
+/// A way of taking payments by card.
+///
+/// Methods are blocking, and are always called from a thread where that is permitted.
+#[cfg(feature = "server")]
+pub trait PaymentProvider: Debug + Send + Sync {
+    /// Reserve `amount` on `card` for `order`, returning the provider's reference to the
+    /// authorization.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the amount could not be reserved.
+    fn authorize(
+        &self,
+        order: Id<Order>,
+        card: &CardNumber,
+        amount: Decimal,
+    ) -> Result<Box<str>, PaymentFailure>;
+
+    /// Charge `amount` to `card` at once, returning the provider's reference to the payment.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the amount could not be charged.
+    fn charge(&self, card: &CardNumber, amount: Decimal) -> Result<Box<str>, PaymentFailure>;
+
+    /// Charge `amount` of an authorization.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the amount could not be charged.
+    fn capture(&self, reference: &str, amount: Decimal) -> Result<(), PaymentFailure>;
+
+    /// Return `amount` of a captured authorization to the card, or release an authorization that
+    /// was never captured.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the amount could not be returned.
+    fn refund(&self, reference: &str, amount: Decimal) -> Result<(), PaymentFailure>;
+}
+
+/// A payment provider for development and testing, which never contacts an outside service.
+///
+/// The outcome of a payment depends only on the card number:
+/// - [`SUCCEEDING_CARD`](Self::SUCCEEDING_CARD) always succeeds.
+/// - [`DECLINED_CARD`](Self::DECLINED_CARD) is declined when authorizing.
+/// - [`TIMING_OUT_CARD`](Self::TIMING_OUT_CARD) times out when authorizing.
+/// - [`UNCAPTURABLE_CARD`](Self::UNCAPTURABLE_CARD) is authorized, but declined when capturing or
+///   charging.
+/// - Any other card is declined.
+#[cfg(feature = "server")]
+#[derive(Debug, Default)]
+pub struct MockProvider {
+    /// The number of payments made so far, to give each a unique reference.
+    payments: AtomicU64,
+}
+
+#[cfg(feature = "server")]
+impl MockProvider {
+    /// A card for which every payment succeeds.
+    pub const SUCCEEDING_CARD: &str = "4242424242424242";
+
+    /// A card which is declined.
+    pub const DECLINED_CARD: &str = "4000000000000002";
+
+    /// A card for which the provider times out.
+    pub const TIMING_OUT_CARD: &str = "4000000000000119";
+
+    /// A card which can be authorized, but is declined when the payment is captured.
+    pub const UNCAPTURABLE_CARD: &str = "4000000000000341";
+
+    /// Make a unique reference to a payment with `card`.
+    fn reference(&self, card: &CardNumber, purpose: impl fmt::Display) -> Box<str> {
+        let n = self.payments.fetch_add(1, Ordering::Relaxed);
+        format!("mock_{}_{purpose}_{n}", card.last_digits()).into()
+    }
+
+    /// Get the last digits of the card a payment was made with.
+    ///
+    /// # Errors
+    ///
+    /// Fails if `reference` was not made by this provider.
+    fn card_digits(reference: &str) -> Result<&str, PaymentFailure> {
+        reference
+            .strip_prefix("mock_")
+            .and_then(|rest| rest.split_once('_'))
+            .map(|(digits, _)| digits)
+            .ok_or(PaymentFailure::Declined)
+    }
+}
+
+#[cfg(feature = "server")]
+impl PaymentProvider for MockProvider {
+    fn authorize(
+        &self,
+        order: Id<Order>,
+        card: &CardNumber,
+        _amount: Decimal,
+    ) -> Result<Box<str>, PaymentFailure> {
+        match &*card.0 {
+            Self::SUCCEEDING_CARD | Self::UNCAPTURABLE_CARD => Ok(self.reference(card, order)),
+            Self::TIMING_OUT_CARD => Err(PaymentFailure::TimedOut),
+            _ => Err(PaymentFailure::Declined),
+        }
+    }
+
+    fn charge(&self, card: &CardNumber, _amount: Decimal) -> Result<Box<str>, PaymentFailure> {
+        match &*card.0 {
+            Self::SUCCEEDING_CARD => Ok(self.reference(card, "charge")),
+            Self::TIMING_OUT_CARD => Err(PaymentFailure::TimedOut),
+            _ => Err(PaymentFailure::Declined),
+        }
+    }
+
+    fn capture(&self, reference: &str, _amount: Decimal) -> Result<(), PaymentFailure> {
+        if Self::UNCAPTURABLE_CARD.ends_with(Self::card_digits(reference)?) {
+            Err(PaymentFailure::Declined)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn refund(&self, reference: &str, _amount: Decimal) -> Result<(), PaymentFailure> {
+        Self::card_digits(reference).map(drop)
+    }
+}
+
+#[cfg(feature = "server")]
+static PROVIDER: OnceLock<Arc<dyn PaymentProvider>> = OnceLock::new();
+
+/// Use `provider` for all payments instead of the default [`MockProvider`].
+///
+/// # Panics
+///
+/// Panics if a payment provider has already been used or set.
+#[cfg(feature = "server")]
+pub fn set_payment_provider(provider: impl PaymentProvider + 'static) {
+    PROVIDER
+        .set(Arc::new(provider))
+        .expect("Payment provider was used or set before being set.");
+}
+
+#[cfg(feature = "server")]
+fn provider() -> Arc<dyn PaymentProvider> {
+    Arc::clone(PROVIDER.get_or_init(|| Arc::new(MockProvider::default())))
+}
+
+/// The progress of the payment of an order.
+#[cfg(feature = "server")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Type)]
+#[sqlx(type_name = "payment_status", rename_all = "snake_case")]
+enum PaymentStatus {
+    Authorized,
+    Captured,
+    Refunded,
+}
+
+#[cfg(feature = "server")]
+struct Payment {
+    reference: String,
+    amount: Decimal,
+    status: PaymentStatus,
+}
+
+/// Authorize payment of a newly placed order with `card`, making the order pending.
+///
+/// # Errors
+///
+/// Fails if:
+/// - `order` is invalid or not awaiting payment.
+/// - The payment provider did not authorize the payment, see [`AppError::PaymentFailed`]. The
+///   order is then cancelled and its products put back in the customer's cart.
+/// - The payment could not be recorded, e.g. due to the order being cancelled meanwhile. The
+///   authorization is then released.
+/// - An error occurs during communication with the database.
+#[cfg(feature = "server")]
+pub(crate) async fn authorize(order: Id<Order>, card: CardNumber) -> Result<()> {
+    let amount = query_scalar!(
+        r#"
+        SELECT total - discount + COALESCE((
+            SELECT SUM(fee)
+            FROM order_shipments
+            WHERE order_id = id
+        ), 0) AS "amount!"
+        FROM orders
+        WHERE id = $1 AND status = 'awaiting_payment'
+        "#,
+        order.get(),
+    )
+    .fetch_one(&*POOL)
+    .await?;
+
+    match spawn_blocking(move || provider().authorize(order, &card, amount)).await? {
+        Ok(reference) => {
+            let recorded = record_authorization(order, &reference, amount).await;
+            if recorded.is_err() {
+                // Nothing would ever capture or release an authorization that is not recorded. The
+                // outcome is ignored, as the original error is the one to report.
+                let _released = spawn_blocking(move || provider().refund(&reference, amount)).await;
+            }
+            recorded
+        },
+        Err(reason) => {
+            query!("CALL fail_payment($1)", order.get())
+                .execute(&*POOL)
+                .await
+                .map(QueryResultExt::procedure)?;
+            Err(AppError::PaymentFailed { reason }.into())
+        },
+    }
+}
+
+/// Record that payment of `amount` for `order` was authorized as `reference`, making the order
+/// pending.
+///
+/// # Errors
+///
+/// Fails if:
+/// - `order` is no longer awaiting payment, e.g. due to being cancelled.
+/// - An error occurs during communication with the database.
+#[cfg(feature = "server")]
+async fn record_authorization(order: Id<Order>, reference: &str, amount: Decimal) -> Result<()> {
+    let mut tx = POOL.begin().await?;
+    query!(
+        "
+        INSERT INTO payments (order_id, reference, amount)
+        VALUES ($1, $2, $3::DECIMAL)
+        ",
+        order.get(),
+        reference,
+        amount,
+    )
+    .execute(&mut *tx)
+    .await
+    .map(QueryResultExt::expect_one)?;
+    query!(
+        "UPDATE orders SET status = 'pending' WHERE id = $1 AND status = 'awaiting_payment'",
+        order.get(),
+    )
+    .execute(&mut *tx)
+    .await?
+    .by_unique_key()?;
+    tx.commit().await.map_err(Into::into)
+}
+
+/// Capture or refund the payment of an order being set to `status`, if the status calls for it.
+///
+/// Meant to be called in the same transaction as the status is set, so that it is only set if the
+/// payment provider succeeds. As the whole payment is settled at once, the status must have been
+/// set by every vendor of the order, see [`set_status`](crate::database::products::set_status).
+/// Orders placed before payments were introduced have no payment, and are left as they are.
+///
+/// # Errors
+///
+/// Fails if:
+/// - The payment provider failed, see [`AppError::PaymentFailed`].
+/// - An error occurs during communication with the database.
+#[cfg(feature = "server")]
+pub(crate) async fn settle(
+    connection: &mut PgConnection,
+    order: Id<Order>,
+    status: OrderStatus,
+) -> Result<()> {
+    let (sources, target): (&[_], _) = match status {
+        OrderStatus::Shipped => (&[PaymentStatus::Authorized], PaymentStatus::Captured),
+        OrderStatus::Cancelled => (&[PaymentStatus::Authorized], PaymentStatus::Refunded),
+        OrderStatus::Refunded => (
+            &[PaymentStatus::Authorized, PaymentStatus::Captured],
+            PaymentStatus::Refunded,
+        ),
+        OrderStatus::AwaitingPayment
+        | OrderStatus::Pending
+        | OrderStatus::Received
+        | OrderStatus::ReturnRequested
+        | OrderStatus::Returned => return Ok(()),
+    };
+
+    let Some(payment) = query_as!(
+        Payment,
+        r#"
+        SELECT reference, amount, status AS "status: PaymentStatus"
+        FROM payments
+        WHERE order_id = $1
+        FOR UPDATE
+        "#,
+        order.get(),
+    )
+    .fetch_optional(&mut *connection)
+    .await?
+    .filter(|payment| sources.contains(&payment.status)) else {
+        return Ok(());
+    };
+
+    let Payment {
+        reference,
+        amount,
+        status: source,
+    } = payment;
+    spawn_blocking(move || {
+        if source == PaymentStatus::Authorized && target == PaymentStatus::Captured {
+            provider().capture(&reference, amount)
+        } else {
+            provider().refund(&reference, amount)
+        }
+    })
+    .await?
+    .map_err(|reason| AppError::PaymentFailed { reason })?;
+
+    query!(
+        "UPDATE payments SET status = $2 WHERE order_id = $1",
+        order.get(),
+        target as PaymentStatus,
+    )
+    .execute(connection)
+    .await
+    .map(QueryResultExt::expect_one)
+    .map_err(Into::into)
+}
+
+/// Charge `amount` to `card` at once, returning the payment provider's reference to the payment.
+///
+/// # Errors
+///
+/// Fails if the payment provider did not complete the payment, see [`AppError::PaymentFailed`].
+#[cfg(feature = "server")]
+pub(crate) async fn charge(card: CardNumber, amount: Decimal) -> Result<Box<str>> {
+    spawn_blocking(move || provider().charge(&card, amount))
+        .await?
+        .map_err(|reason| AppError::PaymentFailed { reason }.into())
+}
@@ -1,13 +1,15 @@
 //! Database functions for getting product overviews to be displayed on product cards.
 
-use crate::database::{Amount, Category, Customer, Deal, Id, Product, Url, Vendor};
+use crate::database::{
+    Amount, Category, Customer, Deal, Id, Product, Url, Vendor, search::SearchLanguage,
+};
 use dioxus::prelude::*;
 use rust_decimal::Decimal;
 use serde::{Deserialize, Serialize};
 use std::num::NonZeroU32;
 #[cfg(feature = "server")]
 use {
-    crate::database::{POOL, RawId},
+    crate::database::{POOL, RawId, authorize_customer, authorize_viewer},
     sqlx::query_as,
     std::cmp::Reverse,
 };
@@ -34,7 +36,7 @@ pub struct ProductOverview {
     pub vendor_name: Box<str>,
     /// The origin of the product. This may or may not be the name of a country.
     pub origin: Box<str>,
-    /// The currently active special offer on the product, if any.
+    /// The best active special offer on the product for the customer, if any.
     pub special_offer_deal: Option<Deal>,
     /// Whether the special offer only applies to members. Value is unspecified if
     /// `special_offer_deal` is `None`.
@@ -66,7 +68,7 @@ pub struct ProductOverviewDiscounted {
     pub vendor_name: Box<str>,
     /// The origin of the product. This may or may not be the name of a country.
     pub origin: Box<str>,
-    /// The currently active special offer on the product.
+    /// The best active special offer on the product for the customer.
     pub special_offer_deal: Deal,
     /// Whether the special offer only applies to members.
     pub special_offer_members_only: bool,
@@ -95,7 +97,7 @@ pub struct ProductOverviewVendor {
     pub amount_per_unit: Amount,
     /// The origin of the product. This may or may not be the name of a country.
     pub origin: Box<str>,
-    /// The currently active special offer on the product, if any.
+    /// The best active special offer on the product for the customer, if any.
     pub special_offer_deal: Option<Deal>,
     /// Whether the special offer only applies to members. Value is unspecified if
     /// `special_offer_deal` is `None`.
@@ -103,6 +105,8 @@ pub struct ProductOverviewVendor {
     /// Whether the customer has marked the product as a favorite. Value is unspecified if a
     /// customer ID was not provided.
     pub favorited: bool,
+    /// The language the product is described in.
+    pub search_language: SearchLanguage,
 }
 
 /// An overview of a customer's favorite product, for display on product cards.
@@ -127,7 +131,7 @@ pub struct ProductOverviewFavorited {
     pub vendor_name: Box<str>,
     /// The origin of the product. This may or may not be the name of a country.
     pub origin: Box<str>,
-    /// The currently active special offer on the product, if any.
+    /// The best active special offer on the product for the customer, if any.
     pub special_offer_deal: Option<Deal>,
     /// Whether the special offer only applies to members. Value is unspecified if
     /// `special_offer_deal` is `None`.
@@ -272,6 +276,7 @@ struct ProductReprVendor {
     quantity2: Option<i32>,
     members_only: bool,
     favorited: bool,
+    search_language: SearchLanguage,
 }
 
 #[cfg(feature = "server")]
@@ -292,6 +297,7 @@ impl From<ProductReprVendor> for ProductOverviewVendor {
             quantity2,
             members_only,
             favorited,
+            search_language,
         }: ProductReprVendor,
     ) -> Self {
         Self {
@@ -309,6 +315,7 @@ impl From<ProductReprVendor> for ProductOverviewVendor {
                 .expect("Database returned invalid special offer."),
             special_offer_members_only: members_only,
             favorited,
+            search_language,
         }
     }
 }
@@ -379,6 +386,7 @@ impl From<ProductReprFavorited> for ProductOverviewFavorited {
 /// Fails if:
 /// - `limit > i64::MAX`.
 /// - `offset > i64::MAX`.
+/// - `customer` is `Some` and the caller is not logged in as them.
 /// - An error occurs during communication with the database.
 #[server]
 pub async fn newest_products(
@@ -386,6 +394,8 @@ pub async fn newest_products(
     limit: usize,
     offset: usize,
 ) -> Result<Box<[ProductOverview]>> {
+    authorize_viewer(customer).await?;
+
     query_as!(
         ProductRepr,
         r#"
@@ -398,7 +408,7 @@ pub async fn newest_products(
                 WHERE cf.customer = $1 AND cf.product = p.id
             ) AS "favorited!"
         FROM products p
-        LEFT JOIN active_special_offers ON product = p.id
+        LEFT JOIN LATERAL best_special_offer(p.id, $1, NULL) ON TRUE
         JOIN vendors ON vendors.id = p.vendor
         WHERE visible AND in_stock > 0
         ORDER BY created_at DESC
@@ -426,15 +436,16 @@ pub async fn newest_products(
 /// Includes the option to exclude a specific product, which could be useful for getting a list of
 /// "similar" products.
 ///
-/// Only visible products with units in stock are considered.
+/// Only visible products with units in stock are considered, and only those directly in
+/// `category`; see [`products_in_category_tree`] to include subcategories.
 ///
 /// # Errors
 ///
 /// Fails if:
+/// - `customer` is `Some` and the caller is not logged in as them.
 /// - `limit > i64::MAX`.
 /// - `offset > i64::MAX`.
 /// - An error occurs during communication with the database.
-// TODO: Include products in subcategories.
 #[server]
 pub async fn products_by_category(
     customer: Option<Id<Customer>>,
@@ -443,6 +454,8 @@ pub async fn products_by_category(
     limit: usize,
     offset: usize,
 ) -> Result<Box<[ProductOverview]>> {
+    authorize_viewer(customer).await?;
+
     query_as!(
         ProductRepr,
         r#"
@@ -455,7 +468,7 @@ pub async fn products_by_category(
                 WHERE cf.customer = $1 AND cf.product = p.id
             ) AS "favorited!"
         FROM products p
-        LEFT JOIN active_special_offers ON product = p.id
+        LEFT JOIN LATERAL best_special_offer(p.id, $1, NULL) ON TRUE
         JOIN vendors ON vendors.id = p.vendor
         WHERE visible AND category = $2 AND in_stock > 0 AND ($3::INT IS NULL OR p.id != $3)
         ORDER BY average_discount(price, new_price, quantity1, quantity2) DESC NULLS LAST
@@ -481,6 +494,67 @@ pub async fn products_by_category(
     .map_err(Into::into)
 }
 
+/// Get other products in a given category or any of its subcategories, sorted by best discounts,
+/// as defined by [`average_discount`](VerifiedDeal::average_discount).
+///
+/// Behaves like [`products_by_category`] otherwise.
+///
+/// # Errors
+///
+/// Fails if:
+/// - `customer` is `Some` and the caller is not logged in as them.
+/// - `limit > i64::MAX`.
+/// - `offset > i64::MAX`.
+/// - An error occurs during communication with the database.
+#[server]
+pub async fn products_in_category_tree(
+    customer: Option<Id<Customer>>,
+    category: Id<Category>,
+    except: Option<Id<Product>>,
+    limit: usize,
+    offset: usize,
+) -> Result<Box<[ProductOverview]>> {
+    authorize_viewer(customer).await?;
+
+    query_as!(
+        ProductRepr,
+        r#"
+        SELECT p.id, name, thumbnail, price, overview, in_stock, origin, amount_per_unit, measurement_unit,
+            new_price, quantity1, quantity2, COALESCE(members_only, FALSE) AS "members_only!",
+            display_name AS vendor_name,
+            EXISTS (
+                SELECT 1
+                FROM customer_favorites cf
+                WHERE cf.customer = $1 AND cf.product = p.id
+            ) AS "favorited!"
+        FROM products p
+        LEFT JOIN LATERAL best_special_offer(p.id, $1, NULL) ON TRUE
+        JOIN vendors ON vendors.id = p.vendor
+        WHERE visible AND in_stock > 0 AND category IN (SELECT category_subtree($2))
+            AND ($3::INT IS NULL OR p.id != $3)
+        ORDER BY average_discount(price, new_price, quantity1, quantity2) DESC NULLS LAST
+        LIMIT $4
+        OFFSET $5
+        "#,
+        customer.map(Id::get),
+        category.get(),
+        except.map(Id::get),
+        i64::try_from(limit)?,
+        i64::try_from(offset)?,
+    )
+    .fetch_all(&*POOL)
+    .await
+    .map(|products| products.into_iter().map(Into::<ProductOverview>::into).collect::<Box<_>>())
+    .inspect(|products| {
+        debug_assert!(
+            products.is_sorted_by_key(|ProductOverview { special_offer_deal, price, .. }|
+                Reverse(special_offer_deal.map(|deal| deal.average_discount(*price)))
+            )
+        );
+    })
+    .map_err(Into::into)
+}
+
 /// Get products with active discounts sorted by best discounts, as defined by
 /// [`average_discount`](VerifiedDeal::average_discount).
 ///
@@ -491,6 +565,7 @@ pub async fn products_by_category(
 /// Fails if:
 /// - `limit > i64::MAX`.
 /// - `offset > i64::MAX`.
+/// - `customer` is `Some` and the caller is not logged in as them.
 /// - An error occurs during communication with the database.
 #[server]
 pub async fn best_discounts(
@@ -498,6 +573,8 @@ pub async fn best_discounts(
     limit: usize,
     offset: usize,
 ) -> Result<Box<[ProductOverviewDiscounted]>> {
+    authorize_viewer(customer).await?;
+
     query_as!(
         ProductReprDiscounted,
         r#"
@@ -510,7 +587,7 @@ pub async fn best_discounts(
                 WHERE cf.customer = $1 AND cf.product = p.id
             ) AS "favorited!"
         FROM products p
-        JOIN active_special_offers ON product = p.id
+        JOIN LATERAL best_special_offer(p.id, $1, NULL) ON TRUE
         JOIN vendors ON vendors.id = p.vendor
         WHERE visible AND in_stock > 0
         ORDER BY average_discount(price, new_price, quantity1, quantity2) DESC
@@ -544,6 +621,7 @@ pub async fn best_discounts(
 /// Fails if:
 /// - `limit > i64::MAX`.
 /// - `offset > i64::MAX`.
+/// - `customer` is `Some` and the caller is not logged in as them.
 /// - An error occurs during communication with the database.
 #[server]
 pub async fn vendor_products(
@@ -553,6 +631,8 @@ pub async fn vendor_products(
     offset: usize,
     include_invisible: bool,
 ) -> Result<Box<[ProductOverviewVendor]>> {
+    authorize_viewer(customer).await?;
+
     query_as!(
         ProductReprVendor,
         r#"
@@ -562,9 +642,10 @@ pub async fn vendor_products(
                 SELECT 1
                 FROM customer_favorites cf
                 WHERE cf.customer = $1 AND cf.product = p.id
-            ) AS "favorited!"
+            ) AS "favorited!",
+            search_language AS "search_language: SearchLanguage"
         FROM products p
-        LEFT JOIN active_special_offers ON product = p.id
+        LEFT JOIN LATERAL best_special_offer(p.id, $1, NULL) ON TRUE
         WHERE (p.visible OR $5) AND p.vendor = $2 AND p.in_stock > 0
         ORDER BY average_discount(p.price, new_price, quantity1, quantity2) DESC NULLS LAST, p.name
         LIMIT $3
@@ -598,6 +679,7 @@ pub async fn vendor_products(
 /// Fails if:
 /// - `limit > i64::MAX`.
 /// - `offset > i64::MAX`.
+/// - The caller is not logged in as `customer`.
 /// - An error occurs during communication with the database.
 #[server]
 pub async fn favorites(
@@ -605,6 +687,8 @@ pub async fn favorites(
     limit: usize,
     offset: usize,
 ) -> Result<Box<[ProductOverviewFavorited]>> {
+    authorize_customer(customer).await?;
+
     query_as!(
         ProductReprFavorited,
         r#"
@@ -612,7 +696,7 @@ pub async fn favorites(
             new_price, quantity1, quantity2, COALESCE(members_only, FALSE) AS "members_only!",
             display_name AS vendor_name
         FROM products p
-        LEFT JOIN active_special_offers ON product = p.id
+        LEFT JOIN LATERAL best_special_offer(p.id, $1, NULL) ON TRUE
         JOIN vendors ON vendors.id = p.vendor
         JOIN customer_favorites cf ON cf.product = p.id
         WHERE visible AND cf.customer = $1
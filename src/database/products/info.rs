@@ -2,17 +2,23 @@
 //! pages.
 
 use crate::database::{
-    Amount, AverageRating, Category, Customer, Deal, Id, Order, Product, Rating, Url, Vendor,
+    Amount, AverageRating, Category, Customer, Deal, Id, Order, PriceTiers, Product, Rating,
+    SpecialOffer, Url, Vendor, delivery::PostalAddress,
 };
 use dioxus::prelude::*;
 use rust_decimal::Decimal;
 use serde::{Deserialize, Serialize};
 use std::num::NonZeroU32;
+use thiserror::Error;
 use time::PrimitiveDateTime;
 #[cfg(feature = "server")]
 use {
-    crate::database::{POOL, QueryResultExt as _, RawId},
-    sqlx::{Type, query, query_as},
+    crate::database::{
+        AppError, AuthError, OrderParty, POOL, PriceTierRepr, QueryResultExt, RawId,
+        authorize_customer, authorize_order_party, authorize_vendor, authorize_viewer, payments,
+    },
+    dioxus::CapturedError,
+    sqlx::{PgConnection, Type, query, query_as, query_scalar},
     std::{cmp::Reverse, num::NonZero},
 };
 
@@ -27,12 +33,15 @@ pub struct ProductInfo {
     pub gallery: Box<[Url]>,
     /// The price of the product before any discounts.
     pub price: Decimal,
+    /// The volume pricing of the product.
+    pub price_tiers: PriceTiers,
     /// A long description of the product.
     pub description: Box<str>,
     /// How many units are in stock. This should not be displayed on the page directly, but may
     /// be used to display "low stock".
     pub in_stock: u32,
-    /// The id and name of the product's category and all of its parents, starting from the root.
+    /// The id and name of the product's category and all of its parents, starting from the category
+    /// itself and ending with the root.
     pub category: Box<[(Id<Category>, Box<str>)]>,
     /// How much of the product is included in one unit.
     pub amount_per_unit: Amount,
@@ -52,7 +61,10 @@ pub struct ProductInfo {
     pub updated_at: PrimitiveDateTime,
     /// The average rating of the product.
     pub rating: AverageRating,
-    /// The currently active special offer on the product, if any.
+    /// The ID of the special offer in `special_offer_deal`, if any.
+    pub special_offer_id: Option<Id<SpecialOffer>>,
+    /// The special offer applying to the customer: the best active one they are eligible for, or
+    /// if there is none, the best one for members only.
     pub special_offer_deal: Option<Deal>,
     /// How many times each customer can benefit from the special offer, if there's a limit. Value
     /// is unspecified if `special_offer_deal` is `None`.
@@ -84,6 +96,7 @@ struct ProductInfoRepr {
     gallery: Vec<Url>,
     thumbnail: String,
     price: Decimal,
+    price_tiers: Vec<PriceTierRepr>,
     description: String,
     in_stock: i32,
     amount_per_unit: Decimal,
@@ -92,6 +105,7 @@ struct ProductInfoRepr {
     visible: bool,
     created_at: PrimitiveDateTime,
     updated_at: PrimitiveDateTime,
+    special_offer_id: Option<RawId>,
     new_price: Option<Decimal>,
     quantity1: Option<i32>,
     quantity2: Option<i32>,
@@ -117,6 +131,7 @@ impl ProductInfo {
             gallery,
             thumbnail,
             price,
+            price_tiers,
             description,
             in_stock,
             amount_per_unit,
@@ -125,6 +140,7 @@ impl ProductInfo {
             visible,
             created_at,
             updated_at,
+            special_offer_id,
             new_price,
             quantity1,
             quantity2,
@@ -149,6 +165,7 @@ impl ProductInfo {
                 gallery.into_iter().map(Into::into).collect()
             },
             price,
+            price_tiers: PriceTiers::from_repr(price_tiers, price),
             description: description.into(),
             in_stock: in_stock
                 .try_into()
@@ -165,6 +182,7 @@ impl ProductInfo {
             created_at,
             updated_at,
             rating: AverageRating::from_repr(average_rating, rating_count),
+            special_offer_id: special_offer_id.map(Into::into),
             special_offer_deal: Deal::try_from_repr(new_price, quantity1, quantity2, price)
                 .expect("Database returned invalid special offer."),
             special_offer_limit_per_customer: limit_per_customer.map(|l| {
@@ -192,18 +210,23 @@ impl ProductInfo {
 ///
 /// Fails if:
 /// - `customer` (if [`Some`]) or `product` is invalid.
+/// - `customer` is `Some` and the caller is not logged in as them.
 /// - An error occurs during communication with the database.
 #[server]
 pub async fn product_info(
     customer: Option<Id<Customer>>,
     product: Id<Product>,
 ) -> Result<ProductInfo> {
+    authorize_viewer(customer).await?;
+
     query_as!(
         ProductInfoRepr,
         r#"
-        SELECT name, thumbnail, price, p.description, in_stock, origin,
+        SELECT name, thumbnail, price,
+            product_price_tiers(p.id) AS "price_tiers!: Vec<PriceTierRepr>",
+            p.description, in_stock, origin,
             gallery AS "gallery: Vec<Url>", amount_per_unit, measurement_unit, visible,
-            created_at, p.updated_at, new_price, quantity1, quantity2,
+            created_at, p.updated_at, aso.id AS special_offer_id, new_price, quantity1, quantity2,
             COALESCE(members_only, FALSE) AS "members_only!", limit_per_customer,
             vendors.id AS vendor_id, vendors.display_name AS vendor_name,
             category_path(category) AS "category_path!: Vec<CategoryPathSegment>",
@@ -221,15 +244,17 @@ pub async fn product_info(
             ) AS own_rating,
             EXISTS (
                 SELECT 1
-                FROM orders
-                WHERE customer = $1 AND product = $2
+                FROM orders o
+                JOIN order_lines l ON l.order_id = o.id
+                WHERE o.customer = $1 AND l.product = $2
             ) AS "has_purchased!"
         FROM products p
-        LEFT JOIN active_special_offers ON product = p.id
+        LEFT JOIN LATERAL best_special_offer(p.id, $1, NULL) aso ON TRUE
         JOIN vendors ON vendors.id = vendor
         LEFT JOIN ratings ON ratings.product = p.id
         WHERE p.id = $2
-        GROUP BY p.id, vendors.id, new_price, quantity1, quantity2, members_only, limit_per_customer
+        GROUP BY p.id, vendors.id, aso.id, new_price, quantity1, quantity2, members_only,
+            limit_per_customer
         "#,
         customer.map(Id::get),
         product.get()
@@ -243,24 +268,38 @@ pub async fn product_info(
 /// A customer's order.
 #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct OrderInfo {
+    /// The ID of the order.
+    pub id: Id<Order>,
     /// The time of purchase.
     pub time: PrimitiveDateTime,
-    /// Purchases included in this order.
+    /// The status of the order.
+    pub status: OrderStatus,
+    /// How much was paid for the products in the order.
+    pub total: Decimal,
+    /// How much was paid for shipping, in addition to `total`.
+    pub shipping_fee: Decimal,
+    /// How much a promotion code took off `total` plus `shipping_fee`.
+    pub discount: Decimal,
+    /// The promotion code used, if any.
+    pub promotion_code: Option<Box<str>>,
+    /// Purchases included in this order. Purchases of products that have since been deleted are
+    /// not included, but are still accounted for in `total`.
     pub purchases: Vec<Purchase>,
 }
 
-/// A record of a customer's purchase.
-///
-/// This does not include a timestamp, as they are intended to be grouped by timestamp in an
-/// [`Order`].
+/// A record of a customer's purchase of a single product, as part of an [`OrderInfo`].
 #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Purchase {
-    /// The ID of the order row.
-    pub id: Id<Order>,
     /// How much was paid.
     pub paid: Decimal,
     /// How many units were purchased.
     pub number: NonZeroU32,
+    /// The price of one unit at the time of purchase and the price tier reached, before any special
+    /// offer or bundle.
+    pub unit_price: Decimal,
+    /// The deal of the special offer applied to the purchase, if any, as it was at the time of
+    /// purchase.
+    pub deal: Option<Deal>,
     /// The name of the product.
     pub product_name: Box<str>,
     /// URL to an image of the product.
@@ -270,68 +309,143 @@ pub struct Purchase {
     /// Whether the product has changed since the order was made. This should be marked by an
     /// indicator.
     pub product_changed: bool,
-    /// The status of the purchase.
-    pub status: OrderStatus,
 }
 
 #[cfg(feature = "server")]
 struct PurchaseRepr {
     id: RawId,
     time: PrimitiveDateTime,
+    status: OrderStatus,
+    total: Decimal,
+    shipping_fee: Decimal,
+    discount: Decimal,
+    promotion_code: Option<String>,
     paid: Decimal,
     number: i32,
+    unit_price: Decimal,
+    new_price: Option<Decimal>,
+    quantity1: Option<i32>,
+    quantity2: Option<i32>,
     product_name: String,
     thumbnail: String,
     vendor_name: String,
     product_changed: bool,
-    status: OrderStatus,
 }
 
 #[cfg(feature = "server")]
 impl From<PurchaseRepr> for Purchase {
     fn from(
         PurchaseRepr {
-            id,
+            id: _,
             time: _,
+            status: _,
+            total: _,
+            shipping_fee: _,
+            discount: _,
+            promotion_code: _,
             paid,
             number,
+            unit_price,
+            new_price,
+            quantity1,
+            quantity2,
             product_name,
             thumbnail,
             vendor_name,
             product_changed,
-            status,
         }: PurchaseRepr,
     ) -> Self {
         Self {
-            id: id.into(),
             paid,
             number: u32::try_from(number)
                 .ok()
                 .and_then(NonZeroU32::new)
                 .expect("Database returned non-positive number in order."),
+            unit_price,
+            deal: Deal::try_from_repr(new_price, quantity1, quantity2, unit_price)
+                .expect("Database returned invalid deal in order."),
             product_name: product_name.into(),
             thumbnail: thumbnail.into(),
             vendor_name: vendor_name.into(),
             product_changed,
-            status,
         }
     }
 }
 
 /// The status of an order.
+///
+/// An order is placed as [`AwaitingPayment`](Self::AwaitingPayment), and moves through the
+/// following transitions:
+/// - `AwaitingPayment` to `Pending` once the payment is authorized, or to `Cancelled` if it fails
+///   or is not authorized in time, see [`payments`](crate::database::payments).
+/// - `AwaitingPayment` or `Pending` to `Cancelled` by the customer.
+/// - `Pending` to `Shipped` by a vendor.
+/// - `Shipped` to `Received` by the customer.
+/// - `Received` to `ReturnRequested` by the customer.
+/// - `ReturnRequested` to `Returned` by a vendor approving the return, or back to `Received` by a
+///   vendor rejecting it.
+/// - `Cancelled` or `Returned` to `Refunded` by a vendor.
+///
+/// When products from several vendors are ordered, vendors only set the status once all of them
+/// have asked for the same one, see [`OrderVendorView::decision`].
+///
+/// Stock is restored when an order is cancelled or returned. The payment is captured when an order is
+/// shipped, and refunded when it is cancelled or refunded.
 #[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 #[cfg_attr(feature = "server", derive(Type))]
 #[cfg_attr(
     feature = "server",
-    sqlx(type_name = "order_status", rename_all = "lowercase")
+    sqlx(type_name = "order_status", rename_all = "snake_case")
 )]
 pub enum OrderStatus {
-    /// Order has been placed.
+    /// Order has been placed, and the payment is being authorized.
+    AwaitingPayment,
+    /// Order has been placed and paid for.
     Pending,
     /// Vendor has sent the order.
     Shipped,
-    /// Customer has received the order; completed.
+    /// Customer has received the order; completed unless a return is requested.
     Received,
+    /// Customer cancelled the order before it was sent.
+    Cancelled,
+    /// Customer wants to return the order.
+    ReturnRequested,
+    /// Vendor has accepted the return of the order.
+    Returned,
+    /// Customer has been refunded for a cancelled or returned order; completed.
+    Refunded,
+}
+
+impl OrderStatus {
+    /// Get the statuses a party may move an order from to set it to `self`.
+    ///
+    /// Empty if the party may never set the status.
+    #[cfg(feature = "server")]
+    const fn sources(self, party: OrderParty) -> &'static [Self] {
+        match (party, self) {
+            (OrderParty::Customer, Self::Cancelled) => &[Self::AwaitingPayment, Self::Pending],
+            (OrderParty::Vendor(_), Self::Shipped) => &[Self::Pending],
+            (OrderParty::Customer, Self::Received) => &[Self::Shipped],
+            (OrderParty::Customer, Self::ReturnRequested) => &[Self::Received],
+            (OrderParty::Vendor(_), Self::Returned | Self::Received) => &[Self::ReturnRequested],
+            (OrderParty::Vendor(_), Self::Refunded) => &[Self::Cancelled, Self::Returned],
+            _ => &[],
+        }
+    }
+}
+
+/// An order could not be set to a status, as that status can not follow its current one.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Error, Serialize, Deserialize)]
+#[error("Order can not be set to {0:?} from its current status.")]
+pub struct InvalidTransition(pub OrderStatus);
+
+/// A change of the status of an order.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StatusChange {
+    /// The new status.
+    pub status: OrderStatus,
+    /// The time of the change.
+    pub time: PrimitiveDateTime,
 }
 
 /// Get orders made by a customer sorted by recency.
@@ -342,6 +456,7 @@ pub enum OrderStatus {
 /// - `customer` is invalid.
 /// - `limit > i64::MAX`.
 /// - `offset > i64::MAX`.
+/// - The caller is not logged in as `customer`.
 /// - An error occurs during communication with the database.
 #[server]
 pub async fn customer_orders(
@@ -349,18 +464,33 @@ pub async fn customer_orders(
     limit: usize,
     offset: usize,
 ) -> Result<Box<[OrderInfo]>> {
+    authorize_customer(customer).await?;
+
     let orders = query_as!(
         PurchaseRepr,
         r#"
-        SELECT o.id, time, paid, number, status AS "status: OrderStatus", p.name AS product_name, p.thumbnail,
-            display_name AS vendor_name, updated_at > time AS "product_changed!"
-        FROM orders o
-        JOIN products p ON p.id = o.product
+        WITH page AS (
+            SELECT id, placed_at, status, total, (
+                SELECT COALESCE(SUM(fee), 0)
+                FROM order_shipments
+                WHERE order_id = orders.id
+            ) AS shipping_fee, discount, promotion_code
+            FROM orders
+            WHERE customer = $1
+            ORDER BY placed_at DESC, id DESC
+            LIMIT $2
+            OFFSET $3
+        )
+        SELECT page.id AS "id!", placed_at AS "time!", status AS "status!: OrderStatus",
+            total AS "total!", shipping_fee AS "shipping_fee!", discount AS "discount!",
+            promotion_code, paid, number, unit_price, new_price, quantity1, quantity2,
+            p.name AS product_name, p.thumbnail, display_name AS vendor_name,
+            updated_at > placed_at AS "product_changed!"
+        FROM page
+        JOIN order_lines l ON l.order_id = page.id
+        JOIN products p ON p.id = l.product
         JOIN vendors ON vendors.id = p.vendor
-        WHERE customer = $1
-        ORDER BY time DESC
-        LIMIT $2
-        OFFSET $3
+        ORDER BY placed_at DESC, page.id DESC
         "#,
         customer.get(),
         i64::try_from(limit)?,
@@ -369,20 +499,42 @@ pub async fn customer_orders(
     .fetch_all(&*POOL)
     .await?
     .into_iter()
-    .map(|purchase| (purchase.time, Purchase::from(purchase)))
-    .fold(Vec::<OrderInfo>::new(), |mut acc, (time, purchase)| {
-        if let Some(last) = acc.last_mut()
-            && time == last.time
-        {
-            last.purchases.push(purchase);
-        } else {
-            acc.push(OrderInfo {
-                time,
-                purchases: vec![purchase],
-            });
-        }
-        acc
-    });
+    .map(|purchase| {
+        (
+            (
+                purchase.id,
+                purchase.time,
+                purchase.status,
+                purchase.total,
+                purchase.shipping_fee,
+                purchase.discount,
+                purchase.promotion_code.clone(),
+            ),
+            Purchase::from(purchase),
+        )
+    })
+    .fold(
+        Vec::<OrderInfo>::new(),
+        |mut acc, ((id, time, status, total, shipping_fee, discount, promotion_code), purchase)| {
+            if let Some(last) = acc.last_mut()
+                && last.id == id.into()
+            {
+                last.purchases.push(purchase);
+            } else {
+                acc.push(OrderInfo {
+                    id: id.into(),
+                    time,
+                    status,
+                    total,
+                    shipping_fee,
+                    discount,
+                    promotion_code: promotion_code.map(Into::into),
+                    purchases: vec![purchase],
+                });
+            }
+            acc
+        },
+    );
 
     debug_assert!(
         orders.is_sorted_by_key(|order| Reverse(order.time)),
@@ -407,10 +559,20 @@ pub struct OrderVendorView {
     /// Whether the product has changed since the order was made. This should be marked by an
     /// indicator.
     pub product_changed: bool,
-    /// The status of the purchase.
+    /// The status of the order.
     pub status: OrderStatus,
+    /// Where to deliver the order. `None` for orders placed before addresses were recorded.
+    pub address: Option<PostalAddress>,
+    /// The name of the shipping method chosen for the vendor's products, as it was at the time
+    /// of purchase. `None` for orders placed before shipping methods were recorded.
+    pub shipping_method: Option<Box<str>>,
+    /// How much the customer paid for shipping the vendor's products.
+    pub shipping_fee: Decimal,
+    /// The status the vendor has asked the order to be set to, while other vendors of the order
+    /// have yet to ask for it.
+    pub decision: Option<OrderStatus>,
 }
- 
+
 #[cfg(feature = "server")]
 struct OrderVendorViewRepr {
     id: RawId,
@@ -420,9 +582,18 @@ struct OrderVendorViewRepr {
     number: i32,
     product_changed: bool,
     status: OrderStatus,
+    recipient: Option<String>,
+    street: Option<String>,
+    postal_code: Option<String>,
+    city: Option<String>,
+    country: Option<String>,
+    shipping_method: Option<String>,
+    shipping_fee: Decimal,
+    decision: Option<OrderStatus>,
 }
- 
+
 #[cfg(feature = "server")]
+#[expect(clippy::unreachable, reason = "Database validation only.")]
 impl From<OrderVendorViewRepr> for OrderVendorView {
     fn from(
         OrderVendorViewRepr {
@@ -433,8 +604,29 @@ impl From<OrderVendorViewRepr> for OrderVendorView {
             number,
             product_changed,
             status,
+            recipient,
+            street,
+            postal_code,
+            city,
+            country,
+            shipping_method,
+            shipping_fee,
+            decision,
         }: OrderVendorViewRepr,
     ) -> Self {
+        let address = match (recipient, street, postal_code, city, country) {
+            (Some(recipient), Some(street), Some(postal_code), Some(city), Some(country)) => {
+                Some(PostalAddress {
+                    recipient: recipient.into(),
+                    street: street.into(),
+                    postal_code: postal_code.into(),
+                    city: city.into(),
+                    country: country.into(),
+                })
+            },
+            (None, None, None, None, None) => None,
+            _ => unreachable!("Database returned incomplete address."),
+        };
         Self {
             id: id.into(),
             time,
@@ -446,10 +638,14 @@ impl From<OrderVendorViewRepr> for OrderVendorView {
                 .expect("Database returned non-positive number in order."),
             product_changed,
             status,
+            address,
+            shipping_method: shipping_method.map(Into::into),
+            shipping_fee,
+            decision,
         }
     }
 }
- 
+
 /// Get orders for a vendor's products sorted by recency.
 ///
 /// # Errors
@@ -458,6 +654,7 @@ impl From<OrderVendorViewRepr> for OrderVendorView {
 /// - `vendor` is invalid.
 /// - `limit > i64::MAX`.
 /// - `offset > i64::MAX`.
+/// - The caller is not logged in as `vendor`.
 /// - An error occurs during communication with the database.
 #[server]
 pub async fn vendor_orders(
@@ -465,15 +662,23 @@ pub async fn vendor_orders(
     limit: usize,
     offset: usize,
 ) -> Result<Box<[OrderVendorView]>> {
+    authorize_vendor(vendor).await?;
+
     query_as!(
         OrderVendorViewRepr,
         r#"
-        SELECT o.id, time, number, status AS "status: OrderStatus",
-            p.id AS product, p.name AS product_name, updated_at > time AS "product_changed!"
-        FROM orders o
-        JOIN products p ON p.id = o.product
+        SELECT o.id, placed_at AS time, number, o.status AS "status: OrderStatus",
+            p.id AS product, p.name AS product_name, updated_at > placed_at AS "product_changed!",
+            recipient, street, postal_code, city, country,
+            method_name AS "shipping_method?", COALESCE(fee, 0) AS "shipping_fee!",
+            d.status AS "decision?: OrderStatus"
+        FROM order_lines l
+        JOIN orders o ON o.id = l.order_id
+        JOIN products p ON p.id = l.product
+        LEFT JOIN order_shipments s ON s.order_id = o.id AND s.vendor = p.vendor
+        LEFT JOIN order_vendor_decisions d ON d.order_id = o.id AND d.vendor = p.vendor
         WHERE p.vendor = $1
-        ORDER BY time DESC
+        ORDER BY placed_at DESC
         LIMIT $2
         OFFSET $3
         "#,
@@ -487,15 +692,142 @@ pub async fn vendor_orders(
     .map_err(Into::into)
 }
 
+/// Set the status of an order.
+///
+/// See [`OrderStatus`] for which party may set which status, and from which status. If other
+/// vendors of the order have yet to ask for the same status, a vendor's call only records that they
+/// have, see [`OrderVendorView::decision`].
+///
+/// # Errors
+///
+/// Fails if:
+/// - `order` is invalid.
+/// - The caller is not permitted to set the status to `status`.
+/// - The order is not in a status which `status` may follow, with [`InvalidTransition`].
+/// - Capturing or refunding the payment failed, see [`AppError::PaymentFailed`].
+/// - An error occurs during communication with the database.
 #[server]
 pub async fn set_status(order: Id<Order>, status: OrderStatus) -> Result<()> {
+    let party = authorize_order_party(order).await?;
+    let sources = status.sources(party);
+    if sources.is_empty() {
+        return Err(HttpError::from(AuthError::Forbidden).into());
+    }
+
+    let mut tx = POOL.begin().await?;
+    if let OrderParty::Vendor(vendor) = party
+        && !decide(&mut tx, order, vendor, status, sources).await?
+    {
+        return tx.commit().await.map_err(Into::into);
+    }
     query!(
-        "UPDATE orders SET status = $2 WHERE id = $1",
+        "UPDATE orders SET status = $2 WHERE id = $1 AND status = ANY($3)",
         order.get(),
         status as OrderStatus,
+        sources as &[OrderStatus],
     )
-    .execute(&*POOL)
+    .execute(&mut *tx)
     .await?
     .by_unique_key()
+    .map_err(|error| -> CapturedError {
+        if error == AppError::NotFound {
+            InvalidTransition(status).into()
+        } else {
+            error.into()
+        }
+    })?;
+    payments::settle(&mut tx, order, status).await?;
+    tx.commit().await.map_err(Into::into)
+}
+
+/// Record that `vendor` asks for `order` to be set to `status` from one of `sources`, returning
+/// whether every vendor of the order has now asked for it.
+///
+/// # Errors
+///
+/// Fails if:
+/// - The order is not in one of `sources`, with [`InvalidTransition`].
+/// - An error occurs during communication with the database.
+#[cfg(feature = "server")]
+async fn decide(
+    connection: &mut PgConnection,
+    order: Id<Order>,
+    vendor: Id<Vendor>,
+    status: OrderStatus,
+    sources: &[OrderStatus],
+) -> Result<bool> {
+    // Vendors deciding at once must see each other's decisions.
+    let _locked = query_scalar!(
+        r#"
+        SELECT TRUE AS "locked!"
+        FROM orders
+        WHERE id = $1 AND status = ANY($2)
+        FOR UPDATE
+        "#,
+        order.get(),
+        sources as &[OrderStatus],
+    )
+    .fetch_optional(&mut *connection)
+    .await?
+    .ok_or(InvalidTransition(status))?;
+
+    query!(
+        "
+        INSERT INTO order_vendor_decisions (order_id, vendor, status)
+        VALUES ($1, $2, $3)
+        ON CONFLICT (order_id, vendor) DO UPDATE
+        SET status = EXCLUDED.status
+        ",
+        order.get(),
+        vendor.get(),
+        status as OrderStatus,
+    )
+    .execute(&mut *connection)
+    .await
+    .map(QueryResultExt::expect_one)?;
+
+    query_scalar!(
+        r#"
+        SELECT NOT EXISTS (
+            SELECT 1
+            FROM order_lines l
+            JOIN products p ON p.id = l.product
+            LEFT JOIN order_vendor_decisions d ON d.order_id = l.order_id AND d.vendor = p.vendor
+            WHERE l.order_id = $1 AND d.status IS DISTINCT FROM $2
+        ) AS "agreed!"
+        "#,
+        order.get(),
+        status as OrderStatus,
+    )
+    .fetch_one(connection)
+    .await
+    .map_err(Into::into)
+}
+
+/// Get the history of the status of an order, starting with its placement.
+///
+/// # Errors
+///
+/// Fails if:
+/// - `order` is invalid.
+/// - The caller is not a party to the order.
+/// - An error occurs during communication with the database.
+#[server]
+pub async fn order_history(order: Id<Order>) -> Result<Box<[StatusChange]>> {
+    let _party = authorize_order_party(order).await?;
+
+    query_as!(
+        StatusChange,
+        r#"
+        SELECT status AS "status: OrderStatus", changed_at AS time
+        FROM order_status_changes
+        WHERE order_id = $1
+        ORDER BY id
+        "#,
+        order.get(),
+    )
+    .fetch_all(&*POOL)
+    .await
+    .map(Into::into)
     .map_err(Into::into)
 }
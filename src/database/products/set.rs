@@ -1,13 +1,20 @@
 //! Database functions for creating and editing products.
 
-use crate::database::{Amount, Category, Customer, Id, Product, Rating, Url, Vendor};
+use crate::database::{
+    Amount, Category, Customer, Id, Media, PriceTiers, Product, Rating, Url, Vendor,
+    search::SearchLanguage,
+};
 use dioxus::prelude::*;
 use rust_decimal::Decimal;
 use std::num::NonZeroU32;
 use time::Date;
 #[cfg(feature = "server")]
 use {
-    crate::database::{POOL, QueryResultExt},
+    crate::database::{
+        POOL, QueryResultExt, authorize_customer, authorize_product_owner, authorize_vendor,
+        classify,
+        media::{thumbnail_url, verify_stored},
+    },
     sqlx::{query, query_as, query_scalar},
     std::num::NonZero,
 };
@@ -17,15 +24,16 @@ use {
 /// # Errors
 ///
 /// Fails if:
-/// - `vendor` or `category` is invalid.
-/// - `name` is not unique.
+/// - `vendor`, `category`, `thumbnail` or an image in `gallery` is invalid.
+/// - `name` is not unique, see [`AppError::Conflict`](crate::database::AppError::Conflict).
+/// - The caller is not logged in as `vendor`.
 /// - An error occurs during communication with the database.
 #[server]
 pub async fn create_product(
     vendor: Id<Vendor>,
     name: Box<str>,
-    thumbnail: Url,
-    gallery: Box<[Url]>,
+    thumbnail: Id<Media>,
+    gallery: Box<[Id<Media>]>,
     price: Decimal,
     overview: Box<str>,
     description: Box<str>,
@@ -33,6 +41,10 @@ pub async fn create_product(
     amount: Amount,
     origin: Box<str>,
 ) -> Result<()> {
+    authorize_vendor(vendor).await?;
+    verify_stored(&[&[thumbnail][..], &gallery].concat()).await?;
+    let gallery = gallery.iter().copied().map(Url::from).collect::<Box<_>>();
+
     query!(
         "
         INSERT INTO products (
@@ -46,7 +58,7 @@ pub async fn create_product(
         ",
         vendor.get(),
         &name,
-        thumbnail as Url,
+        thumbnail_url(thumbnail) as Url,
         &*gallery as &[Url],
         price,
         &overview,
@@ -59,7 +71,7 @@ pub async fn create_product(
     .execute(&*POOL)
     .await
     .map(QueryResultExt::expect_one)
-    .map_err(Into::into)
+    .map_err(classify)
 }
 
 /// Set the name of a product.
@@ -68,9 +80,12 @@ pub async fn create_product(
 ///
 /// Fails if:
 /// - `product` is invalid.
+/// - The caller is not the vendor selling `product`.
 /// - An error occurs during communication with the database.
 #[server]
 pub async fn set_product_name(product: Id<Product>, name: Box<str>) -> Result<()> {
+    authorize_product_owner(product, false).await?;
+
     query!(
         "
         UPDATE products
@@ -81,7 +96,8 @@ pub async fn set_product_name(product: Id<Product>, name: Box<str>) -> Result<()
         &name,
     )
     .execute(&*POOL)
-    .await?
+    .await
+    .map_err(classify)?
     .by_unique_key()
     .map_err(Into::into)
 }
@@ -91,10 +107,14 @@ pub async fn set_product_name(product: Id<Product>, name: Box<str>) -> Result<()
 /// # Errors
 ///
 /// Fails if:
-/// - `product` is invalid.
+/// - `product` or `thumbnail` is invalid.
+/// - The caller is not the vendor selling `product`.
 /// - An error occurs during communication with the database.
 #[server]
-pub async fn set_thumbnail(product: Id<Product>, url: Url) -> Result<()> {
+pub async fn set_thumbnail(product: Id<Product>, thumbnail: Id<Media>) -> Result<()> {
+    authorize_product_owner(product, false).await?;
+    verify_stored(&[thumbnail]).await?;
+
     query!(
         "
         UPDATE products
@@ -102,10 +122,11 @@ pub async fn set_thumbnail(product: Id<Product>, url: Url) -> Result<()> {
         WHERE id = $1
         ",
         product.get(),
-        url as Url,
+        thumbnail_url(thumbnail) as Url,
     )
     .execute(&*POOL)
-    .await?
+    .await
+    .map_err(classify)?
     .by_unique_key()
     .map_err(Into::into)
 }
@@ -136,7 +157,7 @@ pub async fn gallery(product: Id<Product>) -> Result<Box<[Url]>> {
     .fetch_one(&*POOL)
     .await
     .map(|GalleryRepr { gallery }| gallery.into())
-    .map_err(Into::into)
+    .map_err(classify)
 }
 
 /// Set the gallery of a product.
@@ -144,10 +165,15 @@ pub async fn gallery(product: Id<Product>) -> Result<Box<[Url]>> {
 /// # Errors
 ///
 /// Fails if:
-/// - `product` is invalid.
+/// - `product` or an image in `gallery` is invalid.
+/// - The caller is not the vendor selling `product`.
 /// - An error occurs during communication with the database.
 #[server]
-pub async fn set_gallery(product: Id<Product>, gallery: Box<[Url]>) -> Result<()> {
+pub async fn set_gallery(product: Id<Product>, gallery: Box<[Id<Media>]>) -> Result<()> {
+    authorize_product_owner(product, false).await?;
+    verify_stored(&gallery).await?;
+    let gallery = gallery.iter().copied().map(Url::from).collect::<Box<_>>();
+
     query!(
         "
         UPDATE products
@@ -158,7 +184,8 @@ pub async fn set_gallery(product: Id<Product>, gallery: Box<[Url]>) -> Result<()
         &*gallery as &[Url],
     )
     .execute(&*POOL)
-    .await?
+    .await
+    .map_err(classify)?
     .by_unique_key()
     .map_err(Into::into)
 }
@@ -168,10 +195,15 @@ pub async fn set_gallery(product: Id<Product>, gallery: Box<[Url]>) -> Result<()
 /// # Errors
 ///
 /// Fails if:
-/// - `product` is invalid.
+/// - `product` or an image in `additions` is invalid.
+/// - The caller is not the vendor selling `product`.
 /// - An error occurs during communication with the database.
 #[server]
-pub async fn add_to_gallery(product: Id<Product>, additions: Box<[Url]>) -> Result<()> {
+pub async fn add_to_gallery(product: Id<Product>, additions: Box<[Id<Media>]>) -> Result<()> {
+    authorize_product_owner(product, false).await?;
+    verify_stored(&additions).await?;
+    let additions = additions.iter().copied().map(Url::from).collect::<Box<_>>();
+
     query!(
         "
         UPDATE products
@@ -179,10 +211,11 @@ pub async fn add_to_gallery(product: Id<Product>, additions: Box<[Url]>) -> Resu
         WHERE id = $1
         ",
         product.get(),
-        &additions as &[Url],
+        &*additions as &[Url],
     )
     .execute(&*POOL)
-    .await?
+    .await
+    .map_err(classify)?
     .by_unique_key()
     .map_err(Into::into)
 }
@@ -193,10 +226,15 @@ pub async fn add_to_gallery(product: Id<Product>, additions: Box<[Url]>) -> Resu
 ///
 /// Fails if:
 /// - `product` is invalid.
-/// - The new price is lower than one provided by an active special offer.
+/// - The new price is lower than one provided by an active special offer, or not higher than the
+///   first price tier, see [`set_price_tiers`] and
+///   [`AppError::Invalid`](crate::database::AppError::Invalid).
+/// - The caller is not the vendor selling `product`.
 /// - An error occurs during communication with the database.
 #[server]
 pub async fn set_price(product: Id<Product>, price: Decimal) -> Result<()> {
+    authorize_product_owner(product, false).await?;
+
     query!(
         "
         UPDATE products
@@ -207,20 +245,71 @@ pub async fn set_price(product: Id<Product>, price: Decimal) -> Result<()> {
         price,
     )
     .execute(&*POOL)
-    .await?
+    .await
+    .map_err(classify)?
     .by_unique_key()
     .map_err(Into::into)
 }
 
+/// Replace the price tiers of a product, see [`PriceTiers`]. Empty `tiers` removes volume pricing.
+///
+/// Carts seen before the change are stale, as the product counts as changed.
+///
+/// # Errors
+///
+/// Fails if:
+/// - `product` is invalid.
+/// - A tier is not cheaper than the current price of the product, see
+///   [`AppError::Invalid`](crate::database::AppError::Invalid).
+/// - A tier starts at more than [`i32::MAX`] units.
+/// - The caller is not the vendor selling `product`.
+/// - An error occurs during communication with the database.
+#[server]
+pub async fn set_price_tiers(product: Id<Product>, tiers: PriceTiers) -> Result<()> {
+    authorize_product_owner(product, false).await?;
+    let (min_units, prices) = tiers.database_repr()?;
+
+    let mut tx = POOL.begin().await?;
+    query!(
+        "
+        DELETE FROM price_tiers
+        WHERE product = $1
+        ",
+        product.get(),
+    )
+    .execute(&mut *tx)
+    .await?
+    .allow_any();
+    query!(
+        "
+        INSERT INTO price_tiers (product, min_units, price)
+        SELECT $1, min_units, price
+        FROM UNNEST($2::INT[], $3::DECIMAL[]) AS t(min_units, price)
+        ",
+        product.get(),
+        &min_units,
+        &prices,
+    )
+    .execute(&mut *tx)
+    .await
+    .map_err(classify)?
+    .allow_any();
+    // The tiers are validated when committing.
+    tx.commit().await.map_err(classify)
+}
+
 /// Set the overview of a product.
 ///
 /// # Errors
 ///
 /// Fails if:
 /// - `product` is invalid.
+/// - The caller is not the vendor selling `product`.
 /// - An error occurs during communication with the database.
 #[server]
 pub async fn set_overview(product: Id<Product>, overview: Box<str>) -> Result<()> {
+    authorize_product_owner(product, false).await?;
+
     query!(
         "
         UPDATE products
@@ -231,7 +320,8 @@ pub async fn set_overview(product: Id<Product>, overview: Box<str>) -> Result<()
         &overview,
     )
     .execute(&*POOL)
-    .await?
+    .await
+    .map_err(classify)?
     .by_unique_key()
     .map_err(Into::into)
 }
@@ -242,9 +332,12 @@ pub async fn set_overview(product: Id<Product>, overview: Box<str>) -> Result<()
 ///
 /// Fails if:
 /// - `product` is invalid.
+/// - The caller is not the vendor selling `product`.
 /// - An error occurs during communication with the database.
 #[server]
 pub async fn set_description(product: Id<Product>, description: Box<str>) -> Result<()> {
+    authorize_product_owner(product, false).await?;
+
     query!(
         "
         UPDATE products
@@ -255,7 +348,36 @@ pub async fn set_description(product: Id<Product>, description: Box<str>) -> Res
         &description,
     )
     .execute(&*POOL)
-    .await?
+    .await
+    .map_err(classify)?
+    .by_unique_key()
+    .map_err(Into::into)
+}
+
+/// Set the language a product is described in, which affects how it is found by searches.
+///
+/// # Errors
+///
+/// Fails if:
+/// - `product` is invalid.
+/// - The caller is not the vendor selling `product`.
+/// - An error occurs during communication with the database.
+#[server]
+pub async fn set_search_language(product: Id<Product>, language: SearchLanguage) -> Result<()> {
+    authorize_product_owner(product, false).await?;
+
+    query!(
+        "
+        UPDATE products
+        SET search_language = $2
+        WHERE id = $1
+        ",
+        product.get(),
+        language as SearchLanguage,
+    )
+    .execute(&*POOL)
+    .await
+    .map_err(classify)?
     .by_unique_key()
     .map_err(Into::into)
 }
@@ -266,9 +388,12 @@ pub async fn set_description(product: Id<Product>, description: Box<str>) -> Res
 ///
 /// Fails if:
 /// - `product` or `category` is invalid.
+/// - The caller is not the vendor selling `product`.
 /// - An error occurs during communication with the database.
 #[server]
 pub async fn set_category(product: Id<Product>, category: Id<Category>) -> Result<()> {
+    authorize_product_owner(product, false).await?;
+
     query!(
         "
         UPDATE products
@@ -279,7 +404,8 @@ pub async fn set_category(product: Id<Product>, category: Id<Category>) -> Resul
         category.get(),
     )
     .execute(&*POOL)
-    .await?
+    .await
+    .map_err(classify)?
     .by_unique_key()
     .map_err(Into::into)
 }
@@ -290,9 +416,12 @@ pub async fn set_category(product: Id<Product>, category: Id<Category>) -> Resul
 ///
 /// Fails if:
 /// - `product` is invalid.
+/// - The caller is not the vendor selling `product`.
 /// - An error occurs during communication with the database.
 #[server]
 pub async fn set_amount(product: Id<Product>, amount: Amount) -> Result<()> {
+    authorize_product_owner(product, false).await?;
+
     query!(
         "
         UPDATE products
@@ -304,7 +433,8 @@ pub async fn set_amount(product: Id<Product>, amount: Amount) -> Result<()> {
         amount.unit(),
     )
     .execute(&*POOL)
-    .await?
+    .await
+    .map_err(classify)?
     .by_unique_key()
     .map_err(Into::into)
 }
@@ -315,9 +445,12 @@ pub async fn set_amount(product: Id<Product>, amount: Amount) -> Result<()> {
 ///
 /// Fails if:
 /// - `product` is invalid.
+/// - The caller is not the vendor selling `product`.
 /// - An error occurs during communication with the database.
 #[server]
 pub async fn set_origin(product: Id<Product>, origin: Box<str>) -> Result<()> {
+    authorize_product_owner(product, false).await?;
+
     query!(
         "
         UPDATE products
@@ -328,7 +461,8 @@ pub async fn set_origin(product: Id<Product>, origin: Box<str>) -> Result<()> {
         &origin,
     )
     .execute(&*POOL)
-    .await?
+    .await
+    .map_err(classify)?
     .by_unique_key()
     .map_err(Into::into)
 }
@@ -341,6 +475,7 @@ pub async fn set_origin(product: Id<Product>, origin: Box<str>) -> Result<()> {
 /// - `product` is invalid.
 /// - `expiry` is in the past.
 /// - The number overflows.
+/// - The caller is not the vendor selling `product`.
 /// - An error occurs during communication with the database.
 #[server]
 #[expect(clippy::missing_panics_doc, reason = "Database validation only.")]
@@ -349,6 +484,8 @@ pub async fn add_stock(
     number: NonZeroU32,
     expiry: Option<Date>,
 ) -> Result<NonZeroU32> {
+    authorize_product_owner(product, false).await?;
+
     // NOTE: `expiry` intentionally not checked for being in the past as even then the database
     // might see it at a later time where it then is in the past.
 
@@ -368,7 +505,7 @@ pub async fn add_stock(
             .and_then(NonZero::new)
             .expect("Database returned non-positive new stock.")
     })
-    .map_err(Into::into)
+    .map_err(classify)
 }
 
 /// Set the visibility of a product.
@@ -380,9 +517,12 @@ pub async fn add_stock(
 ///
 /// Fails if:
 /// - `product` is invalid.
+/// - The caller is neither the vendor selling `product` nor an administrator.
 /// - An error occurs during communication with the database.
 #[server]
 pub async fn set_visibility(product: Id<Product>, visible: bool) -> Result<()> {
+    authorize_product_owner(product, true).await?;
+
     query!(
         "
         UPDATE products
@@ -394,7 +534,8 @@ pub async fn set_visibility(product: Id<Product>, visible: bool) -> Result<()> {
     )
     .execute(&*POOL)
     .await
-    .map(QueryResultExt::procedure)
+    .map_err(classify)?
+    .by_unique_key()
     .map_err(Into::into)
 }
 
@@ -404,6 +545,7 @@ pub async fn set_visibility(product: Id<Product>, visible: bool) -> Result<()> {
 ///
 /// Fails if:
 /// - `customer` or `product` is invalid.
+/// - The caller is not logged in as `customer`.
 /// - An error occurs during communication with the database.
 #[server]
 pub async fn set_favorite(
@@ -411,6 +553,8 @@ pub async fn set_favorite(
     product: Id<Product>,
     favorite: bool,
 ) -> Result<()> {
+    authorize_customer(customer).await?;
+
     if favorite {
         query!(
             "
@@ -434,7 +578,7 @@ pub async fn set_favorite(
     .execute(&*POOL)
     .await
     .map(QueryResultExt::expect_maybe)
-    .map_err(Into::into)
+    .map_err(classify)
 }
 
 /// Set a customer's rating on a product.
@@ -443,6 +587,7 @@ pub async fn set_favorite(
 ///
 /// Fails if:
 /// - `customer` or `product` is invalid.
+/// - The caller is not logged in as `customer`.
 /// - An error occurs during communication with the database.
 #[server]
 pub async fn set_rating(
@@ -450,6 +595,8 @@ pub async fn set_rating(
     product: Id<Product>,
     rating: Rating,
 ) -> Result<()> {
+    authorize_customer(customer).await?;
+
     #[expect(clippy::non_zero_suggestions, reason = "SQLx expects primitive.")]
     query!(
         "
@@ -465,7 +612,7 @@ pub async fn set_rating(
     .execute(&*POOL)
     .await
     .map(QueryResultExt::expect_one)
-    .map_err(Into::into)
+    .map_err(classify)
 }
 
 /// Remove a customer's rating on a product.
@@ -475,9 +622,12 @@ pub async fn set_rating(
 /// Fails if:
 /// - `customer` or `product` is invalid.
 /// - Customer has a review on the product.
+/// - The caller is not logged in as `customer`.
 /// - An error occurs during communication with the database.
 #[server]
 pub async fn remove_rating(customer: Id<Customer>, product: Id<Product>) -> Result<()> {
+    authorize_customer(customer).await?;
+
     query!(
         "
         DELETE FROM ratings
@@ -487,7 +637,8 @@ pub async fn remove_rating(customer: Id<Customer>, product: Id<Product>) -> Resu
         product.get(),
     )
     .execute(&*POOL)
-    .await?
+    .await
+    .map_err(classify)?
     .by_unique_key()
     .map_err(Into::into)
 }
@@ -0,0 +1,299 @@
+//! Database functions for interacting with promotion codes.
+//!
+//! Unlike special offers, which discount a single product, promotions are entered as a code at
+//! checkout and discount the whole order. They are created by administrators, and may require a
+//! minimum spend and limit how many times they are used, in total and per customer. See
+//! [`checkout`](crate::database::cart::checkout) for how they are applied.
+
+use crate::database::{Customer, Id, Promotion};
+use dioxus::prelude::*;
+use rust_decimal::{Decimal, RoundingStrategy};
+use serde::{Deserialize, Serialize};
+use std::num::NonZeroU32;
+use time::PrimitiveDateTime;
+#[cfg(feature = "server")]
+use {
+    crate::database::{
+        POOL, QueryResultExt as _, RawId, authorize_administrator, authorize_customer, classify,
+    },
+    sqlx::{Type, query, query_as, query_scalar},
+};
+
+/// What a promotion takes off an order.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum PromotionBenefit {
+    /// A percentage of the price of the products, rounded to whole öre.
+    PercentOff {
+        /// The percentage, greater than 0 and at most 100.
+        percent: Decimal,
+    },
+    /// A fixed amount off the price of the products, at most the price itself.
+    AmountOff {
+        /// The amount.
+        amount: Decimal,
+    },
+    /// All shipping fees.
+    FreeShipping,
+}
+
+impl PromotionBenefit {
+    /// Construct a [`PromotionBenefit`] from its representation in the database.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the values do not uphold any of the database's invariants.
+    #[cfg(feature = "server")]
+    #[expect(clippy::unreachable, reason = "Database validation only.")]
+    fn from_repr(
+        percent_off: Option<Decimal>,
+        amount_off: Option<Decimal>,
+        free_shipping: bool,
+    ) -> Self {
+        match (percent_off, amount_off, free_shipping) {
+            (Some(percent), None, false) => Self::PercentOff { percent },
+            (None, Some(amount), false) => Self::AmountOff { amount },
+            (None, None, true) => Self::FreeShipping,
+            _ => unreachable!("Database returned inconsistent promotion."),
+        }
+    }
+
+    /// Convert a [`PromotionBenefit`] into the format used in the database.
+    ///
+    /// Specifically, this returns a tuple representing the columns `percent_off`, `amount_off` and
+    /// `free_shipping` respectively.
+    #[cfg(feature = "server")]
+    const fn database_repr(self) -> (Option<Decimal>, Option<Decimal>, bool) {
+        match self {
+            Self::PercentOff { percent } => (Some(percent), None, false),
+            Self::AmountOff { amount } => (None, Some(amount), false),
+            Self::FreeShipping => (None, None, true),
+        }
+    }
+}
+
+/// A promotion a customer may use, see [`promotion`].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PromotionInfo {
+    /// The ID of the promotion.
+    pub id: Id<Promotion>,
+    /// The code the customer enters.
+    pub code: Box<str>,
+    /// What the promotion takes off.
+    pub benefit: PromotionBenefit,
+    /// How much the products of an order must cost, after special offers, for the promotion to
+    /// apply.
+    pub minimum_spend: Decimal,
+}
+
+impl PromotionInfo {
+    /// Get the discount the promotion gives on products costing `total` in all, shipped for
+    /// `shipping_fee`.
+    ///
+    /// Returns `None` if `total` does not reach the minimum spend. This is the discount charged by
+    /// [`checkout`](crate::database::cart::checkout).
+    #[must_use]
+    pub fn discount(&self, total: Decimal, shipping_fee: Decimal) -> Option<Decimal> {
+        (total >= self.minimum_spend).then(|| match self.benefit {
+            PromotionBenefit::PercentOff { percent } => (total * percent / Decimal::ONE_HUNDRED)
+                .round_dp_with_strategy(2, RoundingStrategy::MidpointAwayFromZero),
+            PromotionBenefit::AmountOff { amount } => amount.min(total),
+            PromotionBenefit::FreeShipping => shipping_fee,
+        })
+    }
+
+    /// Check out with the promotion, expecting the discount it gives on products costing `total`
+    /// shipped for `shipping_fee`. `None` if `total` does not reach the minimum spend.
+    #[must_use]
+    pub fn use_on(&self, total: Decimal, shipping_fee: Decimal) -> Option<PromotionUse> {
+        self.discount(total, shipping_fee)
+            .map(|expected_discount| PromotionUse {
+                promotion: self.id,
+                expected_discount,
+            })
+    }
+}
+
+#[cfg(feature = "server")]
+struct PromotionRepr {
+    id: RawId,
+    code: String,
+    percent_off: Option<Decimal>,
+    amount_off: Option<Decimal>,
+    free_shipping: bool,
+    minimum_spend: Decimal,
+}
+
+#[cfg(feature = "server")]
+impl From<PromotionRepr> for PromotionInfo {
+    fn from(
+        PromotionRepr {
+            id,
+            code,
+            percent_off,
+            amount_off,
+            free_shipping,
+            minimum_spend,
+        }: PromotionRepr,
+    ) -> Self {
+        Self {
+            id: id.into(),
+            code: code.into(),
+            benefit: PromotionBenefit::from_repr(percent_off, amount_off, free_shipping),
+            minimum_spend,
+        }
+    }
+}
+
+/// A promotion a customer wants to check out with.
+///
+/// As with [`CheckoutItem`](crate::database::cart::CheckoutItem), this is to ensure the customer
+/// gets the discount they were shown.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PromotionUse {
+    /// The ID of the promotion.
+    pub promotion: Id<Promotion>,
+    /// The discount the customer expects to get.
+    pub expected_discount: Decimal,
+}
+
+#[cfg(feature = "server")]
+#[derive(Type)]
+#[sqlx(type_name = "CHECKOUT_PROMOTION")]
+pub(super) struct PromotionUseRepr {
+    promotion: i32,
+    expected_discount: Decimal,
+}
+
+#[cfg(feature = "server")]
+impl From<PromotionUse> for PromotionUseRepr {
+    fn from(
+        PromotionUse {
+            promotion,
+            expected_discount,
+        }: PromotionUse,
+    ) -> Self {
+        Self {
+            promotion: promotion.get(),
+            expected_discount,
+        }
+    }
+}
+
+/// Look up an active promotion by its code, ignoring case.
+///
+/// Returns `None` if there is no such promotion, or if it has been used as many times as it may
+/// be, in total or by `customer`.
+///
+/// # Errors
+///
+/// Fails if:
+/// - `customer` is invalid.
+/// - The caller is not logged in as `customer`.
+/// - An error occurs during communication with the database.
+#[server]
+pub async fn promotion(customer: Id<Customer>, code: Box<str>) -> Result<Option<PromotionInfo>> {
+    authorize_customer(customer).await?;
+
+    query_as!(
+        PromotionRepr,
+        r#"
+        SELECT id AS "id!", code::TEXT AS "code!", percent_off, amount_off,
+            free_shipping AS "free_shipping!", minimum_spend AS "minimum_spend!"
+        FROM active_promotions ap
+        WHERE code = $2::CITEXT
+            AND (max_uses IS NULL OR max_uses > (
+                SELECT COALESCE(SUM(number), 0)
+                FROM promotion_uses
+                WHERE promotion = ap.id
+            ))
+            AND (limit_per_customer IS NULL OR limit_per_customer > (
+                SELECT COALESCE(SUM(number), 0)
+                FROM promotion_uses
+                WHERE promotion = ap.id AND customer = $1
+            ))
+        "#,
+        customer.get(),
+        code.trim(),
+    )
+    .fetch_optional(&*POOL)
+    .await
+    .map(|promotion| promotion.map(Into::into))
+    .map_err(Into::into)
+}
+
+/// Create a promotion, returning its ID.
+///
+/// Promotions with an end time of `None` must be deleted manually.
+///
+/// # Errors
+///
+/// Fails if:
+/// - `code` is empty or already taken, ignoring case, see
+///   [`AppError::Conflict`](crate::database::AppError::Conflict).
+/// - `benefit` is a percentage that is not greater than 0 and at most 100, or an amount that is not
+///   positive.
+/// - `max_uses > i32::MAX` or `limit_per_customer > i32::MAX` (if [`Some`]).
+/// - `valid_until` is not after `valid_from`.
+/// - The caller is not an administrator.
+/// - An error occurs during communication with the database.
+#[server]
+pub async fn create_promotion(
+    code: Box<str>,
+    benefit: PromotionBenefit,
+    minimum_spend: Decimal,
+    max_uses: Option<NonZeroU32>,
+    limit_per_customer: Option<NonZeroU32>,
+    valid_from: PrimitiveDateTime,
+    valid_until: Option<PrimitiveDateTime>,
+) -> Result<Id<Promotion>> {
+    authorize_administrator().await?;
+
+    let (percent_off, amount_off, free_shipping) = benefit.database_repr();
+
+    query_scalar!(
+        "
+        INSERT INTO promotions (
+            code, percent_off, amount_off, free_shipping, minimum_spend, max_uses,
+            limit_per_customer, valid_from, valid_until
+        )
+        VALUES ($1::CITEXT, $2, $3::DECIMAL, $4, $5::DECIMAL, $6::INT, $7::INT, $8, $9)
+        RETURNING id
+        ",
+        code.trim(),
+        percent_off,
+        amount_off,
+        free_shipping,
+        minimum_spend,
+        max_uses.map(|l| i32::try_from(l.get())).transpose()?,
+        limit_per_customer
+            .map(|l| i32::try_from(l.get()))
+            .transpose()?,
+        valid_from,
+        valid_until,
+    )
+    .fetch_one(&*POOL)
+    .await
+    .map(Into::into)
+    .map_err(classify)
+}
+
+/// Delete a promotion.
+///
+/// Orders placed with the promotion keep its code and the discount they got.
+///
+/// # Errors
+///
+/// Fails if:
+/// - `promotion` is invalid.
+/// - The caller is not an administrator.
+/// - An error occurs during communication with the database.
+#[server]
+pub async fn delete_promotion(promotion: Id<Promotion>) -> Result<()> {
+    authorize_administrator().await?;
+
+    query!("DELETE FROM promotions WHERE id = $1", promotion.get())
+        .execute(&*POOL)
+        .await?
+        .by_unique_key()
+        .map_err(Into::into)
+}
@@ -0,0 +1,425 @@
+//! Limiting how often clients may call sensitive or expensive server functions.
+//!
+//! Attempts are counted per client address and, where one is given, per account, in a
+//! [`RateLimitStore`]. Repeated incorrect passwords additionally lock the account out of password
+//! checks for progressively longer periods. The [`RateLimits`] in use are set with
+//! [`set_rate_limiter`], and default to [`RateLimits::default`] with an [`InMemoryStore`].
+//!
+//! The client address is the address of the connection, unless it comes from a trusted proxy, see
+//! [`set_trusted_proxies`]. Then the last entry of the `X-Forwarded-For` header not belonging to a
+//! trusted proxy is used instead.
+
+use crate::database::{AppError, Id, User};
+use dioxus::prelude::*;
+use dioxus_fullstack::{FullstackContext, HeaderMap, extract::ConnectInfo};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Debug,
+    net::{IpAddr, SocketAddr},
+    sync::{Arc, Mutex, MutexGuard, OnceLock, PoisonError},
+    time::{Duration, Instant},
+};
+
+/// A server function whose calls are limited.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Action {
+    /// See [`log_in`](crate::database::log_in).
+    LogIn,
+    /// See [`create_user`](crate::database::create_user).
+    CreateUser,
+    /// See [`search_products`](crate::database::search::search_products).
+    Search,
+    /// See [`request_password_reset`](crate::database::request_password_reset).
+    RequestPasswordReset,
+    /// See [`set_in_guest_cart`](crate::database::cart::set_in_guest_cart).
+    SetInGuestCart,
+}
+
+/// A maximum number of attempts within a period of time.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Limit {
+    /// The number of attempts permitted within each period.
+    pub attempts: u32,
+    /// The length of each period, starting with the first attempt.
+    pub period: Duration,
+}
+
+impl Limit {
+    /// Permit `attempts` attempts per `period`.
+    #[must_use]
+    pub const fn new(attempts: u32, period: Duration) -> Self {
+        Self { attempts, period }
+    }
+}
+
+/// How accounts are locked out after repeated incorrect passwords.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Lockout {
+    /// The number of incorrect passwords tolerated before the account is locked.
+    pub tolerated: u32,
+    /// How long the account is locked after the first failure that is not tolerated. Each
+    /// further failure doubles the duration.
+    pub initial: Duration,
+    /// The longest the account is locked after a single failure.
+    pub max: Duration,
+    /// How long failures are remembered after the first one, unless a correct password is given.
+    pub memory: Duration,
+}
+
+impl Lockout {
+    /// Get how long an account is locked after `failures` consecutive incorrect passwords.
+    #[must_use]
+    pub fn duration(&self, failures: u32) -> Duration {
+        let Some(excess) = failures.checked_sub(self.tolerated + 1) else {
+            return Duration::ZERO;
+        };
+        2_u32
+            .checked_pow(excess)
+            .and_then(|factor| self.initial.checked_mul(factor))
+            .map_or(self.max, |duration| duration.min(self.max))
+    }
+}
+
+/// The limits applied to each [`Action`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RateLimits {
+    /// Limits per client address.
+    pub per_address: HashMap<Action, Limit>,
+    /// Limits per account, identified by the username or email given, for actions given one.
+    pub per_account: HashMap<Action, Limit>,
+    /// Lockout after incorrect passwords.
+    pub lockout: Lockout,
+}
+
+impl Default for RateLimits {
+    fn default() -> Self {
+        const MINUTE: Duration = Duration::from_secs(60);
+        const HOUR: Duration = Duration::from_secs(60 * 60);
+        Self {
+            per_address: HashMap::from([
+                (Action::LogIn, Limit::new(20, MINUTE)),
+                (Action::CreateUser, Limit::new(5, HOUR)),
+                (Action::Search, Limit::new(120, MINUTE)),
+                (Action::RequestPasswordReset, Limit::new(10, HOUR)),
+                (Action::SetInGuestCart, Limit::new(60, MINUTE)),
+            ]),
+            per_account: HashMap::from([
+                (Action::LogIn, Limit::new(10, MINUTE)),
+                (Action::RequestPasswordReset, Limit::new(3, HOUR)),
+            ]),
+            lockout: Lockout {
+                tolerated: 5,
+                initial: MINUTE,
+                max: HOUR,
+                memory: 24 * HOUR,
+            },
+        }
+    }
+}
+
+/// The attempts recorded under a key.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Record {
+    /// The number of attempts.
+    pub count: u32,
+    /// When the first attempt was made.
+    pub first: Instant,
+    /// When the latest attempt was made.
+    pub last: Instant,
+}
+
+/// A way of counting attempts.
+///
+/// Methods are called while handling requests, and must not block for long.
+pub trait RateLimitStore: Debug + Send + Sync {
+    /// Record an attempt under `key` at `now`, returning all attempts recorded under it.
+    ///
+    /// Records are forgotten `ttl` after their first attempt, where `ttl` is the value given
+    /// along with that attempt.
+    fn record(&self, key: &str, now: Instant, ttl: Duration) -> Record;
+
+    /// Get the attempts recorded under `key` at `now`, if any.
+    fn get(&self, key: &str, now: Instant) -> Option<Record>;
+
+    /// Forget the attempts recorded under `key`.
+    fn clear(&self, key: &str);
+}
+
+/// Storage of attempts in the memory of the server process.
+///
+/// Limits are not shared between processes, and are reset when the server restarts.
+#[derive(Debug, Default)]
+pub struct InMemoryStore {
+    records: Mutex<Records>,
+}
+
+/// Records kept by an [`InMemoryStore`].
+#[derive(Debug, Default)]
+struct Records {
+    /// Each record along with when it expires.
+    entries: HashMap<String, (Record, Instant)>,
+    /// The number of entries at which expired ones are next removed.
+    prune_at: usize,
+}
+
+impl Records {
+    fn record(&mut self, key: &str, now: Instant, ttl: Duration) -> Record {
+        if self.entries.len() >= self.prune_at {
+            self.entries.retain(|_, &mut (_, expires)| expires > now);
+            self.prune_at = (2 * self.entries.len()).max(1024);
+        }
+
+        let first = Record {
+            count: 1,
+            first: now,
+            last: now,
+        };
+        let (record, _) = self
+            .entries
+            .entry(key.to_owned())
+            .and_modify(|(record, expires)| {
+                if *expires > now {
+                    record.count += 1;
+                    record.last = now;
+                } else {
+                    *record = first;
+                    *expires = now + ttl;
+                }
+            })
+            .or_insert((first, now + ttl));
+        *record
+    }
+}
+
+impl InMemoryStore {
+    /// Create an empty store.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get exclusive access to the records.
+    fn lock(&self) -> MutexGuard<'_, Records> {
+        self.records.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl RateLimitStore for InMemoryStore {
+    fn record(&self, key: &str, now: Instant, ttl: Duration) -> Record {
+        self.lock().record(key, now, ttl)
+    }
+
+    fn get(&self, key: &str, now: Instant) -> Option<Record> {
+        self.lock()
+            .entries
+            .get(key)
+            .filter(|&&(_, expires)| expires > now)
+            .map(|&(record, _)| record)
+    }
+
+    fn clear(&self, key: &str) {
+        let _removed = self.lock().entries.remove(key);
+    }
+}
+
+/// A store along with the limits it enforces.
+#[derive(Debug)]
+struct RateLimiter {
+    store: Arc<dyn RateLimitStore>,
+    limits: RateLimits,
+}
+
+static RATE_LIMITER: OnceLock<RateLimiter> = OnceLock::new();
+
+/// Enforce `limits` using `store` instead of the defaults.
+///
+/// # Panics
+///
+/// Panics if a rate limiter has already been used or set.
+pub fn set_rate_limiter(store: impl RateLimitStore + 'static, limits: RateLimits) {
+    RATE_LIMITER
+        .set(RateLimiter {
+            store: Arc::new(store),
+            limits,
+        })
+        .expect("Rate limiter was used or set before being set.");
+}
+
+/// Get the rate limiter in use, creating the default one if none has been set.
+fn rate_limiter() -> &'static RateLimiter {
+    RATE_LIMITER.get_or_init(|| RateLimiter {
+        store: Arc::new(InMemoryStore::new()),
+        limits: RateLimits::default(),
+    })
+}
+
+/// Get the number of whole seconds until `until`, rounding up.
+fn seconds_until(until: Instant, now: Instant) -> u64 {
+    let remaining = until.saturating_duration_since(now);
+    remaining.as_secs() + u64::from(remaining.subsec_nanos() > 0)
+}
+
+static TRUSTED_PROXIES: OnceLock<HashSet<IpAddr>> = OnceLock::new();
+
+/// Honor `X-Forwarded-For` on connections from `proxies` instead of those listed in
+/// `TRUSTED_PROXIES`.
+///
+/// # Panics
+///
+/// Panics if trusted proxies have already been used or set.
+pub fn set_trusted_proxies(proxies: impl IntoIterator<Item = IpAddr>) {
+    TRUSTED_PROXIES
+        .set(proxies.into_iter().collect())
+        .expect("Trusted proxies were used or set before being set.");
+}
+
+/// Get the trusted proxies, reading the comma-separated `TRUSTED_PROXIES` if none have been set.
+/// Without it, no proxies are trusted.
+///
+/// # Panics
+///
+/// Panics if `TRUSTED_PROXIES` is set but malformed.
+fn trusted_proxies() -> &'static HashSet<IpAddr> {
+    TRUSTED_PROXIES.get_or_init(|| {
+        dotenvy::var("TRUSTED_PROXIES").map_or_else(
+            |_| HashSet::new(),
+            |proxies| {
+                proxies
+                    .split(',')
+                    .map(|proxy| proxy.trim().parse().expect("Invalid `TRUSTED_PROXIES`."))
+                    .collect()
+            },
+        )
+    })
+}
+
+/// Get the address of the client making the current request.
+///
+/// Returns `None` if the address of the connection is unknown.
+///
+/// # Errors
+///
+/// Fails if the request headers could not be extracted.
+async fn client_address() -> Result<Option<IpAddr>> {
+    let Some(ConnectInfo(peer)) = FullstackContext::current()
+        .and_then(|context| context.extension::<ConnectInfo<SocketAddr>>())
+    else {
+        return Ok(None);
+    };
+    let proxies = trusted_proxies();
+    let mut address = peer.ip();
+    if !proxies.contains(&address) {
+        return Ok(Some(address));
+    }
+
+    let headers = FullstackContext::extract::<HeaderMap, _>().await?;
+    let forwarded = headers
+        .get_all("x-forwarded-for")
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .rev();
+    // Entries before the first untrusted one, or a malformed one, may have been made up by the
+    // client.
+    for entry in forwarded {
+        let Ok(entry) = entry.trim().parse() else {
+            break;
+        };
+        address = entry;
+        if !proxies.contains(&address) {
+            break;
+        }
+    }
+    Ok(Some(address))
+}
+
+/// Record an attempt under `key`, failing if it exceeds `limit`.
+///
+/// # Errors
+///
+/// Fails if too many attempts have been made, see [`AppError::RateLimited`].
+fn attempt(store: &dyn RateLimitStore, key: &str, limit: Limit, now: Instant) -> Result<()> {
+    let record = store.record(key, now, limit.period);
+    if record.count > limit.attempts {
+        Err(AppError::RateLimited {
+            retry_after: seconds_until(record.first + limit.period, now),
+        }
+        .into())
+    } else {
+        Ok(())
+    }
+}
+
+/// Record an attempt at `action` by the caller, and on `account` if given, which is a username or
+/// email. Accounts are compared case-insensitively, as in the database.
+///
+/// # Errors
+///
+/// Fails if:
+/// - Either the caller or `account` has made too many attempts, see [`AppError::RateLimited`].
+/// - The request headers could not be extracted.
+pub(crate) async fn limit(action: Action, account: Option<&str>) -> Result<()> {
+    let RateLimiter { store, limits } = rate_limiter();
+    let now = Instant::now();
+
+    if let (Some(address), Some(&limit)) =
+        (client_address().await?, limits.per_address.get(&action))
+    {
+        attempt(
+            &**store,
+            &format!("{action:?}:address:{address}"),
+            limit,
+            now,
+        )?;
+    }
+    if let (Some(account), Some(&limit)) = (account, limits.per_account.get(&action)) {
+        attempt(
+            &**store,
+            &format!("{action:?}:account:{}", account.to_lowercase()),
+            limit,
+            now,
+        )?;
+    }
+    Ok(())
+}
+
+/// Get the key under which incorrect passwords for `user` are recorded.
+fn lockout_key(user: Id<User>) -> String {
+    format!("lockout:{user}")
+}
+
+/// Verify that `user` is not locked out after incorrect passwords.
+///
+/// # Errors
+///
+/// Fails if `user` is locked out, see [`AppError::RateLimited`].
+pub(crate) fn check_lockout(user: Id<User>) -> Result<()> {
+    let RateLimiter { store, limits } = rate_limiter();
+    let now = Instant::now();
+
+    let Some(record) = store.get(&lockout_key(user), now) else {
+        return Ok(());
+    };
+    let until = record.last + limits.lockout.duration(record.count);
+    if until > now {
+        Err(AppError::RateLimited {
+            retry_after: seconds_until(until, now),
+        }
+        .into())
+    } else {
+        Ok(())
+    }
+}
+
+/// Record whether a password given for `user` was correct.
+///
+/// Incorrect passwords count towards a lockout, while a correct one resets it.
+pub(crate) fn record_password_attempt(user: Id<User>, correct: bool) {
+    let RateLimiter { store, limits } = rate_limiter();
+    let key = lockout_key(user);
+    if correct {
+        store.clear(&key);
+    } else {
+        let _record = store.record(&key, Instant::now(), limits.lockout.memory);
+    }
+}
@@ -8,7 +8,10 @@ use serde::{Deserialize, Serialize};
 use time::PrimitiveDateTime;
 #[cfg(feature = "server")]
 use {
-    crate::database::{POOL, QueryResultExt, RawId, Role},
+    crate::database::{
+        POOL, QueryResultExt, RawId, Role, authorize_comment_author, authorize_customer,
+        authorize_review_author, authorize_user, authorize_verified_customer, classify,
+    },
     hashbrown::HashMap,
     sqlx::{query, query_as},
     std::cmp::Reverse,
@@ -458,6 +461,7 @@ pub async fn product_reviews(
 /// - `customer` or `product` is invalid.
 /// - `limit > i64::MAX`.
 /// - `offset > i64::MAX`.
+/// - The caller is not logged in as `customer`.
 /// - An error occurs during communication with the database.
 #[server]
 #[expect(
@@ -470,6 +474,8 @@ pub async fn product_reviews_as(
     limit: usize,
     offset: usize,
 ) -> Result<(Option<OwnReview>, Box<[ProductReview]>)> {
+    authorize_customer(customer).await?;
+
     // Own review is excluded from the limit.
     let mut review_ids = Vec::with_capacity(limit + 1);
 
@@ -683,6 +689,7 @@ pub async fn product_reviews_as(
 /// - The customer already has a review on the product.
 /// - The customer has not placed a rating on the product.
 /// - The customer is not allowed to place reviews.
+/// - The caller is not logged in as `customer`, or has not verified their email.
 /// - An error occurs during communication with the database.
 #[server]
 pub async fn create_review(
@@ -691,6 +698,8 @@ pub async fn create_review(
     title: Box<str>,
     content: Box<str>,
 ) -> Result<()> {
+    authorize_verified_customer(customer).await?;
+
     query!(
         "
         INSERT INTO reviews (customer, product, title, content)
@@ -704,7 +713,7 @@ pub async fn create_review(
     .execute(&*POOL)
     .await
     .map(QueryResultExt::expect_one)
-    .map_err(Into::into)
+    .map_err(classify)
 }
 
 /// Update a review.
@@ -713,9 +722,12 @@ pub async fn create_review(
 ///
 /// Fails if:
 /// - `review` is invalid.
+/// - The caller did not write `review`.
 /// - An error occurs during communication with the database.
 #[server]
 pub async fn update_review(review: Id<Review>, title: Box<str>, content: Box<str>) -> Result<()> {
+    authorize_review_author(review, false).await?;
+
     query!(
         "
         UPDATE reviews
@@ -741,9 +753,12 @@ pub async fn update_review(review: Id<Review>, title: Box<str>, content: Box<str
 ///
 /// Fails if:
 /// - `review` is invalid.
+/// - The caller neither wrote `review` nor is an administrator.
 /// - An error occurs during communication with the database.
 #[server]
 pub async fn delete_review(review: Id<Review>) -> Result<()> {
+    authorize_review_author(review, true).await?;
+
     query!(
         "
         DELETE FROM reviews
@@ -763,9 +778,12 @@ pub async fn delete_review(review: Id<Review>) -> Result<()> {
 ///
 /// Fails if:
 /// - `user` or `parent` is invalid.
+/// - The caller is not logged in as `user`.
 /// - An error occurs during communication with the database.
 #[server]
 pub async fn create_comment(user: Id<User>, parent: Id<Review>, content: Box<str>) -> Result<()> {
+    authorize_user(user).await?;
+
     query!(
         "
         INSERT INTO comments (user_id, review, content)
@@ -778,7 +796,7 @@ pub async fn create_comment(user: Id<User>, parent: Id<Review>, content: Box<str
     .execute(&*POOL)
     .await
     .map(QueryResultExt::expect_one)
-    .map_err(Into::into)
+    .map_err(classify)
 }
 
 /// Create a comment on another comment.
@@ -787,9 +805,12 @@ pub async fn create_comment(user: Id<User>, parent: Id<Review>, content: Box<str
 ///
 /// Fails if:
 /// - `user` or `parent` is invalid.
+/// - The caller is not logged in as `user`.
 /// - An error occurs during communication with the database.
 #[server]
 pub async fn create_reply(user: Id<User>, parent: Id<Comment>, content: Box<str>) -> Result<()> {
+    authorize_user(user).await?;
+
     query!(
         "
         INSERT INTO comments (user_id, review, parent, content)
@@ -802,7 +823,7 @@ pub async fn create_reply(user: Id<User>, parent: Id<Comment>, content: Box<str>
     .execute(&*POOL)
     .await
     .map(QueryResultExt::expect_one)
-    .map_err(Into::into)
+    .map_err(classify)
 }
 
 /// Delete a comment and all replies to it.
@@ -811,9 +832,12 @@ pub async fn create_reply(user: Id<User>, parent: Id<Comment>, content: Box<str>
 ///
 /// Fails if:
 /// - `comment` is invalid.
+/// - The caller neither wrote `comment` nor is an administrator.
 /// - An error occurs during communication with the database.
 #[server]
 pub async fn delete_comment(comment: Id<Comment>) -> Result<()> {
+    authorize_comment_author(comment, true).await?;
+
     query!(
         "
         DELETE FROM comments
@@ -833,6 +857,7 @@ pub async fn delete_comment(comment: Id<Comment>) -> Result<()> {
 ///
 /// Fails if:
 /// - `customer` or `review` is invalid.
+/// - The caller is not logged in as `customer`.
 /// - An error occurs during communication with the database.
 #[server]
 pub async fn set_vote_review(
@@ -840,6 +865,8 @@ pub async fn set_vote_review(
     review: Id<Review>,
     vote: Option<Vote>,
 ) -> Result<()> {
+    authorize_customer(customer).await?;
+
     if let Some(vote) = vote {
         query!(
             "
@@ -855,7 +882,7 @@ pub async fn set_vote_review(
         .execute(&*POOL)
         .await
         .map(QueryResultExt::expect_one)
-        .map_err(Into::into)
+        .map_err(classify)
     } else {
         query!(
             "
@@ -866,7 +893,8 @@ pub async fn set_vote_review(
             review.get(),
         )
         .execute(&*POOL)
-        .await?
+        .await
+        .map_err(classify)?
         .by_unique_key()
         .map_err(Into::into)
     }
@@ -878,6 +906,7 @@ pub async fn set_vote_review(
 ///
 /// Fails if:
 /// - `customer` or `comment` is invalid.
+/// - The caller is not logged in as `customer`.
 /// - An error occurs during communication with the database.
 #[server]
 pub async fn set_vote_comment(
@@ -885,6 +914,8 @@ pub async fn set_vote_comment(
     comment: Id<Comment>,
     vote: Option<Vote>,
 ) -> Result<()> {
+    authorize_customer(customer).await?;
+
     if let Some(vote) = vote {
         query!(
             "
@@ -900,7 +931,7 @@ pub async fn set_vote_comment(
         .execute(&*POOL)
         .await
         .map(QueryResultExt::expect_one)
-        .map_err(Into::into)
+        .map_err(classify)
     } else {
         query!(
             "
@@ -911,7 +942,8 @@ pub async fn set_vote_comment(
             comment.get(),
         )
         .execute(&*POOL)
-        .await?
+        .await
+        .map_err(classify)?
         .by_unique_key()
         .map_err(Into::into)
     }
@@ -1,10 +1,143 @@
 //! Database functions for performing text searches.
 
-use crate::database::{Id, Product, Url};
+use crate::database::{Amount, AverageRating, Category, Deal, Id, Product, Rating, Url, Vendor};
 use dioxus::prelude::*;
+use rust_decimal::Decimal;
 use serde::{Deserialize, Serialize};
 #[cfg(feature = "server")]
-use {crate::database::POOL, sqlx::query_as};
+use {
+    crate::database::{
+        POOL, RawId,
+        rate_limit::{self, Action},
+    },
+    sqlx::{Type, query, query_as},
+    std::num::NonZeroI32,
+};
+
+/// The language a product is described in, used to parse search queries and product texts.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[cfg_attr(feature = "server", derive(Type))]
+#[cfg_attr(
+    feature = "server",
+    sqlx(type_name = "search_language", rename_all = "lowercase")
+)]
+pub enum SearchLanguage {
+    /// Swedish.
+    #[default]
+    Swedish,
+    /// English.
+    English,
+}
+
+/// Which special offers a product must have to be included in a search.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum OfferFilter {
+    /// Any active special offer.
+    Any,
+    /// An active special offer only applying to members.
+    MembersOnly,
+    /// An active special offer applying to all customers.
+    Public,
+}
+
+/// Filters narrowing down a search. The default filters include every visible product.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SearchFilters {
+    /// Only include products in the subtree rooted at this category.
+    pub category: Option<Id<Category>>,
+    /// Only include products sold by this vendor.
+    pub vendor: Option<Id<Vendor>>,
+    /// Only include products costing at least this much before any discounts.
+    pub min_price: Option<Decimal>,
+    /// Only include products costing at most this much before any discounts.
+    pub max_price: Option<Decimal>,
+    /// Only include products of this origin.
+    pub origin: Option<Box<str>>,
+    /// Only include products with units in stock.
+    pub in_stock_only: bool,
+    /// Only include products with a matching active special offer.
+    pub offer: Option<OfferFilter>,
+    /// Only include products with an average rating of at least this much. Products without
+    /// ratings are excluded.
+    pub min_rating: Option<Rating>,
+}
+
+/// The arguments of `product_search`.
+#[cfg(feature = "server")]
+struct SearchParams {
+    query: Box<str>,
+    category: Option<RawId>,
+    vendor: Option<RawId>,
+    min_price: Option<Decimal>,
+    max_price: Option<Decimal>,
+    origin: Option<Box<str>>,
+    in_stock_only: bool,
+    with_offer: bool,
+    offer_members_only: Option<bool>,
+    min_rating: Option<i32>,
+}
+
+#[cfg(feature = "server")]
+impl SearchParams {
+    fn new(
+        query: Box<str>,
+        SearchFilters {
+            category,
+            vendor,
+            min_price,
+            max_price,
+            origin,
+            in_stock_only,
+            offer,
+            min_rating,
+        }: SearchFilters,
+    ) -> Self {
+        let (with_offer, offer_members_only) = match offer {
+            None => (false, None),
+            Some(OfferFilter::Any) => (true, None),
+            Some(OfferFilter::MembersOnly) => (true, Some(true)),
+            Some(OfferFilter::Public) => (true, Some(false)),
+        };
+        Self {
+            query,
+            category: category.map(Id::get),
+            vendor: vendor.map(Id::get),
+            min_price,
+            max_price,
+            origin,
+            in_stock_only,
+            with_offer,
+            offer_members_only,
+            min_rating: min_rating.map(|rating| NonZeroI32::from(rating.get()).get()),
+        }
+    }
+}
+
+/// How to sort search results. Ties are broken arbitrarily, but consistently between pages.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[cfg_attr(feature = "server", derive(Type))]
+#[cfg_attr(
+    feature = "server",
+    sqlx(type_name = "search_order", rename_all = "snake_case")
+)]
+pub enum SearchOrder {
+    /// Best match for the query first, by both text content and similarity to the name.
+    #[default]
+    Relevance,
+    /// Cheapest first, before any discounts.
+    PriceAscending,
+    /// Most expensive first, before any discounts.
+    PriceDescending,
+    /// Cheapest first, per unit of measurement and before any discounts.
+    UnitPrice,
+    /// Most recently created first.
+    Newest,
+    /// Highest average rating first. Products without ratings are sorted last.
+    Rating,
+    /// Best discount first, as defined by [`average_discount`](Deal::average_discount). Products
+    /// without special offers are sorted last.
+    BestDiscount,
+}
 
 /// A product matching a search query.
 #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
@@ -15,13 +148,42 @@ pub struct SearchResult {
     pub name: Box<str>,
     /// URL to an image to display on the product card.
     pub thumbnail: Url,
+    /// The price of the product before any discounts.
+    pub price: Decimal,
+    /// How many units are in stock.
+    pub in_stock: u32,
+    /// How much of the product is included in one unit.
+    pub amount_per_unit: Amount,
+    /// The name of the vendor.
+    pub vendor_name: Box<str>,
+    /// The origin of the product. This may or may not be the name of a country.
+    pub origin: Box<str>,
+    /// The best active special offer on the product for customers who are not members, if any.
+    pub special_offer_deal: Option<Deal>,
+    /// Whether the special offer only applies to members. Value is unspecified if
+    /// `special_offer_deal` is `None`.
+    pub special_offer_members_only: bool,
+    /// The average rating of the product.
+    pub rating: AverageRating,
 }
 
 #[cfg(feature = "server")]
 struct SearchResultRepr {
-    id: i32,
+    id: RawId,
     name: String,
-    thumbnail: Url,
+    thumbnail: String,
+    price: Decimal,
+    in_stock: i32,
+    origin: String,
+    amount_per_unit: Decimal,
+    measurement_unit: Option<String>,
+    new_price: Option<Decimal>,
+    quantity1: Option<i32>,
+    quantity2: Option<i32>,
+    members_only: bool,
+    vendor_name: String,
+    average_rating: Option<f64>,
+    rating_count: i64,
 }
 
 #[cfg(feature = "server")]
@@ -31,39 +193,103 @@ impl From<SearchResultRepr> for SearchResult {
             id,
             name,
             thumbnail,
+            price,
+            in_stock,
+            origin,
+            amount_per_unit,
+            measurement_unit,
+            new_price,
+            quantity1,
+            quantity2,
+            members_only,
+            vendor_name,
+            average_rating,
+            rating_count,
         }: SearchResultRepr,
     ) -> Self {
         Self {
             id: id.into(),
             name: name.into(),
-            thumbnail,
+            thumbnail: thumbnail.into(),
+            price,
+            in_stock: in_stock
+                .try_into()
+                .expect("Database returned negative stock."),
+            amount_per_unit: Amount::from_repr(amount_per_unit, measurement_unit),
+            vendor_name: vendor_name.into(),
+            origin: origin.into(),
+            special_offer_deal: Deal::try_from_repr(new_price, quantity1, quantity2, price)
+                .expect("Database returned invalid special offer."),
+            special_offer_members_only: members_only,
+            rating: AverageRating::from_repr(average_rating, rating_count),
         }
     }
 }
 
 /// Search for products by name, category and description.
 ///
+/// Only visible products are considered. An empty query matches every product. Otherwise, the
+/// query is parsed in the [language](SearchLanguage) of each product, and products with names
+/// similar to the query also match so that typos and missing diacritics are tolerated.
+///
 /// # Errors
 ///
 /// Fails if:
+/// - The caller has searched too often, see
+///   [`AppError::RateLimited`](crate::database::AppError::RateLimited).
 /// - `limit > i64::MAX`.
+/// - `offset > i64::MAX`.
 /// - An error occurs during communication with the database.
 #[server]
-pub async fn search_products(query: Box<str>, limit: usize) -> Result<Vec<SearchResult>> {
+pub async fn search_products(
+    query: Box<str>,
+    filters: SearchFilters,
+    order: SearchOrder,
+    limit: usize,
+    offset: usize,
+) -> Result<Box<[SearchResult]>> {
+    rate_limit::limit(Action::Search, None).await?;
+    let params = SearchParams::new(query, filters);
+
     query_as!(
         SearchResultRepr,
-        "
-        WITH search AS (
-            SELECT plainto_tsquery('english', $1) AS query
-        )
-        SELECT id, name, thumbnail
-        FROM products, search
-        WHERE search_vector @@ query
-        ORDER BY ts_rank(search_vector, query) DESC
-        LIMIT $2
-        ",
-        &*query,
+        r#"
+        SELECT p.id, name, thumbnail, price, in_stock, origin, amount_per_unit, measurement_unit,
+            new_price, quantity1, quantity2, COALESCE(members_only, FALSE) AS "members_only!",
+            display_name AS vendor_name, average_rating, rating_count AS "rating_count!"
+        FROM product_search($1, $2, $3, $4, $5, $6, $7, $8, $9, $10) s
+        JOIN products p ON p.id = s.product
+        JOIN vendors v ON v.id = p.vendor
+        LEFT JOIN LATERAL best_special_offer(p.id, NULL, NULL) aso ON TRUE
+        WHERE in_category AND by_vendor AND in_price_range AND from_origin AND stocked
+            AND offered AND rated
+        ORDER BY
+            CASE WHEN $11 = 'relevance' THEN relevance END DESC,
+            CASE WHEN $11 = 'price_ascending' THEN price END,
+            CASE WHEN $11 = 'price_descending' THEN price END DESC,
+            CASE WHEN $11 = 'unit_price' THEN price / amount_per_unit END,
+            CASE WHEN $11 = 'newest' THEN p.created_at END DESC,
+            CASE WHEN $11 = 'rating' THEN average_rating END DESC NULLS LAST,
+            CASE WHEN $11 = 'best_discount'
+                THEN average_discount(price, new_price, quantity1, quantity2)
+            END DESC NULLS LAST,
+            p.id
+        LIMIT $12
+        OFFSET $13
+        "#,
+        &*params.query,
+        params.category,
+        params.vendor,
+        params.min_price,
+        params.max_price,
+        params.origin.as_deref(),
+        params.in_stock_only,
+        params.with_offer,
+        params.offer_members_only,
+        params.min_rating,
+        order as SearchOrder,
         i64::try_from(limit)?,
+        i64::try_from(offset)?,
     )
     .fetch_all(&*POOL)
     .await
@@ -71,6 +297,291 @@ pub async fn search_products(query: Box<str>, limit: usize) -> Result<Vec<Search
     .map_err(Into::into)
 }
 
+/// A filter value along with the number of search results matching it.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Facet<T> {
+    /// The value to filter by.
+    pub value: T,
+    /// The name to display for the value.
+    pub name: Box<str>,
+    /// The number of results matching the value.
+    pub count: u64,
+}
+
+#[cfg(feature = "server")]
+struct FacetRepr {
+    id: RawId,
+    name: String,
+    count: i64,
+}
+
+#[cfg(feature = "server")]
+impl<T: From<RawId>> From<FacetRepr> for Facet<T> {
+    fn from(FacetRepr { id, name, count }: FacetRepr) -> Self {
+        Self {
+            value: id.into(),
+            name: name.into(),
+            count: count_from_repr(count),
+        }
+    }
+}
+
+/// Convert a count from its representation in the database.
+///
+/// # Panics
+///
+/// Panics if the count is negative.
+#[cfg(feature = "server")]
+fn count_from_repr(count: i64) -> u64 {
+    count.try_into().expect("Database returned negative count.")
+}
+
+/// Counts of search results by filter, for narrowing down a search.
+///
+/// Each count applies every filter except its own, so it tells how many results there would be if
+/// only that filter were changed.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SearchFacets {
+    /// The number of results with all filters applied.
+    pub total: u64,
+    /// The categories results belong to directly, most common first.
+    pub categories: Box<[Facet<Id<Category>>]>,
+    /// The vendors selling results, most common first.
+    pub vendors: Box<[Facet<Id<Vendor>>]>,
+    /// The origins of results, most common first.
+    pub origins: Box<[Facet<Box<str>>]>,
+    /// The lowest and highest prices before any discounts, or `None` if there are no results.
+    pub price_range: Option<(Decimal, Decimal)>,
+    /// The number of results with units in stock.
+    pub in_stock: u64,
+    /// The number of results with each kind of active special offer.
+    pub offers: Box<[(OfferFilter, u64)]>,
+    /// The number of results averaging at least each rating.
+    pub ratings: Box<[(Rating, u64)]>,
+}
+
+/// Count search results by category, ignoring the category filter.
+///
+/// # Errors
+///
+/// Fails if an error occurs during communication with the database.
+#[cfg(feature = "server")]
+async fn category_facets(params: &SearchParams) -> Result<Box<[Facet<Id<Category>>]>> {
+    query_as!(
+        FacetRepr,
+        r#"
+        SELECT c.id, c.name, COUNT(*) AS "count!"
+        FROM product_search($1, $2, $3, $4, $5, $6, $7, $8, $9, $10) s
+        JOIN products p ON p.id = s.product
+        JOIN categories c ON c.id = p.category
+        WHERE by_vendor AND in_price_range AND from_origin AND stocked AND offered AND rated
+        GROUP BY c.id
+        ORDER BY "count!" DESC, c.name
+        "#,
+        &*params.query,
+        params.category,
+        params.vendor,
+        params.min_price,
+        params.max_price,
+        params.origin.as_deref(),
+        params.in_stock_only,
+        params.with_offer,
+        params.offer_members_only,
+        params.min_rating,
+    )
+    .fetch_all(&*POOL)
+    .await
+    .map(|facets| facets.into_iter().map(Into::into).collect())
+    .map_err(Into::into)
+}
+
+/// Count search results by vendor, ignoring the vendor filter.
+///
+/// # Errors
+///
+/// Fails if an error occurs during communication with the database.
+#[cfg(feature = "server")]
+async fn vendor_facets(params: &SearchParams) -> Result<Box<[Facet<Id<Vendor>>]>> {
+    query_as!(
+        FacetRepr,
+        r#"
+        SELECT v.id, display_name AS name, COUNT(*) AS "count!"
+        FROM product_search($1, $2, $3, $4, $5, $6, $7, $8, $9, $10) s
+        JOIN products p ON p.id = s.product
+        JOIN vendors v ON v.id = p.vendor
+        WHERE in_category AND in_price_range AND from_origin AND stocked AND offered AND rated
+        GROUP BY v.id
+        ORDER BY "count!" DESC, display_name
+        "#,
+        &*params.query,
+        params.category,
+        params.vendor,
+        params.min_price,
+        params.max_price,
+        params.origin.as_deref(),
+        params.in_stock_only,
+        params.with_offer,
+        params.offer_members_only,
+        params.min_rating,
+    )
+    .fetch_all(&*POOL)
+    .await
+    .map(|facets| facets.into_iter().map(Into::into).collect())
+    .map_err(Into::into)
+}
+
+/// Count search results by origin, ignoring the origin filter.
+///
+/// # Errors
+///
+/// Fails if an error occurs during communication with the database.
+#[cfg(feature = "server")]
+async fn origin_facets(params: &SearchParams) -> Result<Box<[Facet<Box<str>>]>> {
+    query!(
+        r#"
+        SELECT origin, COUNT(*) AS "count!"
+        FROM product_search($1, $2, $3, $4, $5, $6, $7, $8, $9, $10) s
+        JOIN products p ON p.id = s.product
+        WHERE in_category AND by_vendor AND in_price_range AND stocked AND offered AND rated
+        GROUP BY origin
+        ORDER BY "count!" DESC, origin
+        "#,
+        &*params.query,
+        params.category,
+        params.vendor,
+        params.min_price,
+        params.max_price,
+        params.origin.as_deref(),
+        params.in_stock_only,
+        params.with_offer,
+        params.offer_members_only,
+        params.min_rating,
+    )
+    .fetch_all(&*POOL)
+    .await
+    .map(|facets| {
+        facets
+            .into_iter()
+            .map(|row| Facet {
+                value: row.origin.as_str().into(),
+                name: row.origin.into(),
+                count: count_from_repr(row.count),
+            })
+            .collect()
+    })
+    .map_err(Into::into)
+}
+
+/// Count search results by filter.
+///
+/// See [`search_products`] for which products are considered.
+///
+/// Counting is as expensive as searching, so both count towards the same limit.
+///
+/// # Errors
+///
+/// Fails if:
+/// - The caller has searched too often, see
+///   [`AppError::RateLimited`](crate::database::AppError::RateLimited).
+/// - An error occurs during communication with the database.
+#[server]
+pub async fn search_facets(query: Box<str>, filters: SearchFilters) -> Result<SearchFacets> {
+    rate_limit::limit(Action::Search, None).await?;
+    let params = SearchParams::new(query, filters);
+
+    let summary = query!(
+        r#"
+        SELECT
+            COUNT(*) FILTER (
+                WHERE in_category AND by_vendor AND in_price_range AND from_origin AND stocked
+                    AND offered AND rated
+            ) AS "total!",
+            MIN(price) FILTER (
+                WHERE in_category AND by_vendor AND from_origin AND stocked AND offered AND rated
+            ) AS min_price,
+            MAX(price) FILTER (
+                WHERE in_category AND by_vendor AND from_origin AND stocked AND offered AND rated
+            ) AS max_price,
+            COUNT(*) FILTER (
+                WHERE in_category AND by_vendor AND in_price_range AND from_origin AND offered
+                    AND rated AND in_stock > 0
+            ) AS "in_stock!",
+            COUNT(*) FILTER (
+                WHERE in_category AND by_vendor AND in_price_range AND from_origin AND stocked
+                    AND rated AND aso.id IS NOT NULL
+            ) AS "with_offer!",
+            COUNT(*) FILTER (
+                WHERE in_category AND by_vendor AND in_price_range AND from_origin AND stocked
+                    AND rated AND EXISTS (
+                        SELECT 1
+                        FROM active_special_offers o
+                        WHERE o.product = p.id AND o.members_only
+                    )
+            ) AS "members_only_offer!",
+            ARRAY[
+                COUNT(*) FILTER (
+                    WHERE in_category AND by_vendor AND in_price_range AND from_origin AND stocked
+                        AND offered AND average_rating >= 1
+                ),
+                COUNT(*) FILTER (
+                    WHERE in_category AND by_vendor AND in_price_range AND from_origin AND stocked
+                        AND offered AND average_rating >= 2
+                ),
+                COUNT(*) FILTER (
+                    WHERE in_category AND by_vendor AND in_price_range AND from_origin AND stocked
+                        AND offered AND average_rating >= 3
+                ),
+                COUNT(*) FILTER (
+                    WHERE in_category AND by_vendor AND in_price_range AND from_origin AND stocked
+                        AND offered AND average_rating >= 4
+                ),
+                COUNT(*) FILTER (
+                    WHERE in_category AND by_vendor AND in_price_range AND from_origin AND stocked
+                        AND offered AND average_rating >= 5
+                )
+            ] AS "ratings!"
+        FROM product_search($1, $2, $3, $4, $5, $6, $7, $8, $9, $10) s
+        JOIN products p ON p.id = s.product
+        LEFT JOIN LATERAL best_special_offer(p.id, NULL, NULL) aso ON TRUE
+        "#,
+        &*params.query,
+        params.category,
+        params.vendor,
+        params.min_price,
+        params.max_price,
+        params.origin.as_deref(),
+        params.in_stock_only,
+        params.with_offer,
+        params.offer_members_only,
+        params.min_rating,
+    )
+    .fetch_one(&*POOL)
+    .await?;
+
+    Ok(SearchFacets {
+        total: count_from_repr(summary.total),
+        categories: category_facets(&params).await?,
+        vendors: vendor_facets(&params).await?,
+        origins: origin_facets(&params).await?,
+        price_range: summary.min_price.zip(summary.max_price),
+        in_stock: count_from_repr(summary.in_stock),
+        offers: [
+            (OfferFilter::Any, summary.with_offer),
+            (OfferFilter::MembersOnly, summary.members_only_offer),
+            (
+                OfferFilter::Public,
+                summary.with_offer - summary.members_only_offer,
+            ),
+        ]
+        .map(|(offer, count)| (offer, count_from_repr(count)))
+        .into(),
+        ratings: (1..=5)
+            .filter_map(Rating::new)
+            .zip(summary.ratings.into_iter().map(count_from_repr))
+            .collect(),
+    })
+}
+
 // TODO: Remove.
 #[cfg(feature = "web")]
 use gloo_timers as _;
@@ -110,9 +621,16 @@ mod usage {
             let query = debounced();
             async move {
                 if query.trim().is_empty() {
-                    Ok(Vec::new())
+                    Ok(Box::default())
                 } else {
-                    search_products(query.into(), LIMIT).await
+                    search_products(
+                        query.into(),
+                        SearchFilters::default(),
+                        SearchOrder::Relevance,
+                        LIMIT,
+                        0,
+                    )
+                    .await
                 }
             }
         });
@@ -0,0 +1,421 @@
+//! Tests exercising server functions and database code end to end against a live database.
+//!
+//! Each test run creates a throwaway database on the server given by `TEST_DATABASE_URL`, falling
+//! back to `DATABASE_URL`, and applies all migrations to it. Databases left behind by earlier runs
+//! are dropped at the start of the next one.
+//!
+//! Every test creates its own users, categories and products, so tests can run in parallel
+//! against the same database without interfering with each other.
+
+#![expect(clippy::unwrap_used, reason = "Tests.")]
+#![expect(
+    clippy::missing_panics_doc,
+    reason = "Fixtures panic on failure by design."
+)]
+
+use crate::database::{
+    Administrator, Category, Customer, Deal, Email, Id, POOL, Product, QueryResultExt, Role,
+    SESSION_COOKIE, SpecialOffer, User, Username, Vendor,
+    mail::{FileMailer, set_mailer},
+    media::{LocalStorage, set_media_storage},
+    rate_limit::set_trusted_proxies,
+};
+use dioxus::fullstack::{FullstackContext, extract::ConnectInfo};
+use http::{Request, header::COOKIE};
+use rust_decimal::Decimal;
+use sqlx::{
+    Connection as _, PgConnection, PgPool, migrate, postgres::PgConnectOptions, query,
+    query_scalar, raw_sql,
+};
+use std::{
+    env, fs,
+    net::SocketAddr,
+    path::PathBuf,
+    process,
+    str::FromStr as _,
+    sync::{
+        LazyLock,
+        atomic::{AtomicU32, Ordering},
+    },
+};
+use time::PrimitiveDateTime;
+use tokio::{runtime::Runtime, sync::OnceCell};
+
+mod admin;
+mod auth;
+mod bundles;
+mod catalog;
+mod checkout;
+mod delivery;
+mod guest_carts;
+mod media;
+mod memberships;
+mod orders;
+mod payments;
+mod price_tiers;
+mod pricing;
+mod promotions;
+mod search;
+
+/// The runtime shared by all tests.
+///
+/// Connections in [`POOL`] are bound to the runtime they were opened on, so every test must run on
+/// the same one.
+static RUNTIME: LazyLock<Runtime> =
+    LazyLock::new(|| Runtime::new().expect("Failed to start the test runtime."));
+
+/// Set once the test database has been created and [`POOL`] points to it.
+static DATABASE: OnceCell<()> = OnceCell::const_new();
+
+/// The address of the only trusted proxy.
+const PROXY: &str = "203.0.113.1";
+
+/// Counter used to generate unique names and session tokens.
+static NEXT: AtomicU32 = AtomicU32::new(0);
+
+/// Run a test on the shared runtime, creating the test database first if necessary.
+fn run(test: impl Future<Output = ()>) {
+    RUNTIME.block_on(async {
+        let &() = DATABASE.get_or_init(create_database).await;
+        test.await;
+    });
+}
+
+/// Create a fresh database for this test run, apply all migrations and point [`POOL`] to it.
+///
+/// Uploaded media and sent mail are stored in a temporary directory named after the database, and
+/// [`PROXY`] is trusted.
+async fn create_database() {
+    let url = dotenvy::var("TEST_DATABASE_URL")
+        .or_else(|_| dotenvy::var("DATABASE_URL"))
+        .expect("Neither `TEST_DATABASE_URL` nor `DATABASE_URL` set.");
+    let options = PgConnectOptions::from_str(&url).expect("Invalid database URL.");
+    let mut server = PgConnection::connect_with(&options)
+        .await
+        .expect("Failed to connect to the database server.");
+
+    let stale = query_scalar!(
+        r#"
+        SELECT datname AS "datname!"
+        FROM pg_database
+        WHERE datname LIKE 'boop\_test\_%'
+        "#
+    )
+    .fetch_all(&mut server)
+    .await
+    .expect("Failed to list test databases.");
+    for name in stale {
+        // Fails if the database is in use by a concurrent test run, in which case it is left be.
+        drop(
+            raw_sql(&format!(r#"DROP DATABASE "{name}""#))
+                .execute(&mut server)
+                .await,
+        );
+    }
+
+    let name = format!("boop_test_{}", process::id());
+    raw_sql(&format!(r#"CREATE DATABASE "{name}""#))
+        .execute(&mut server)
+        .await
+        .map(QueryResultExt::allow_any)
+        .expect("Failed to create test database.");
+    server
+        .close()
+        .await
+        .expect("Failed to close connection to the database server.");
+
+    let pool = PgPool::connect_with(options.database(&name))
+        .await
+        .expect("Failed to connect to the test database.");
+    migrate!()
+        .run(&pool)
+        .await
+        .expect("Failed to apply database migrations.");
+    POOL.set(pool)
+        .expect("Database pool was initialized before the test database was created.");
+
+    set_media_storage(LocalStorage::new(scratch().join("media")));
+    set_mailer(FileMailer::new(scratch().join("mail")));
+    set_trusted_proxies([PROXY.parse().unwrap()]);
+}
+
+/// Get the directory where files written during this test run are kept.
+fn scratch() -> PathBuf {
+    env::temp_dir().join(format!("boop_test_{}", process::id()))
+}
+
+/// Get the token from the last link to `path` mailed to `email`.
+fn mailed_token(email: &Email, path: &str) -> Box<str> {
+    let mailbox = fs::read_to_string(scratch().join("mail").join(email.to_string()))
+        .expect("No mail was sent to the address.");
+    let start = mailbox.rfind(path).expect("No link was mailed.") + path.len();
+    mailbox
+        .get(start..start + 64)
+        .expect("The link was cut short.")
+        .into()
+}
+
+/// Generate a name not used by any other fixture.
+fn unique(prefix: &str) -> String {
+    format!("{prefix}_{}", NEXT.fetch_add(1, Ordering::Relaxed))
+}
+
+/// A logged-in session.
+#[derive(Debug)]
+struct Session {
+    /// The value of the `Cookie` header identifying the session.
+    cookie: Box<str>,
+}
+
+impl Session {
+    /// Start a new session for a user, bypassing the need for a password.
+    async fn start(user: Id<User>) -> Self {
+        let token = format!("{:064x}", NEXT.fetch_add(1, Ordering::Relaxed));
+        query!(
+            "
+            INSERT INTO sessions (token_hash, user_id, expires_at)
+            VALUES (sha256(decode($1, 'hex')), $2, CURRENT_TIMESTAMP + INTERVAL '1 hour')
+            ",
+            token,
+            user.get(),
+        )
+        .execute(&*POOL)
+        .await
+        .map(QueryResultExt::expect_one)
+        .expect("Failed to start session.");
+
+        Self {
+            cookie: format!("{SESSION_COOKIE}={token}").into(),
+        }
+    }
+
+    /// Move the start of the session, and the last time the user authenticated in it, an hour
+    /// into the past.
+    async fn age(&self) {
+        let (_, token) = self.cookie.split_once('=').unwrap();
+        query!(
+            "
+            UPDATE sessions
+            SET created_at = created_at - INTERVAL '1 hour',
+                authenticated_at = authenticated_at - INTERVAL '1 hour'
+            WHERE token_hash = sha256(decode($1, 'hex'))
+            ",
+            token,
+        )
+        .execute(&*POOL)
+        .await
+        .map(QueryResultExt::expect_one)
+        .unwrap();
+    }
+
+    /// Run a future, e.g. a server function call, as part of a request made within this session.
+    async fn call<T>(&self, future: impl Future<Output = T>) -> T {
+        let (parts, ()) = Request::builder()
+            .header(COOKIE, &*self.cookie)
+            .body(())
+            .unwrap()
+            .into_parts();
+        FullstackContext::new(parts).scope(future).await
+    }
+}
+
+/// Run a future as part of an anonymous request forwarded by a trusted proxy on behalf of
+/// `address`.
+async fn call_from<T>(address: &str, future: impl Future<Output = T>) -> T {
+    // The first entry is made up by the client, and is ignored.
+    call_via(PROXY, &format!("192.0.2.1, {address}"), future).await
+}
+
+/// Run a future as part of an anonymous request from `peer` with the header
+/// `X-Forwarded-For: forwarded`.
+async fn call_via<T>(peer: &str, forwarded: &str, future: impl Future<Output = T>) -> T {
+    let (parts, ()) = Request::builder()
+        .header("x-forwarded-for", forwarded)
+        .extension(ConnectInfo(SocketAddr::new(peer.parse().unwrap(), 443)))
+        .body(())
+        .unwrap()
+        .into_parts();
+    FullstackContext::new(parts).scope(future).await
+}
+
+/// Create a user with a role-specific procedure, returning their ID.
+///
+/// Fixture users have no usable password; use [`Session::start`] to act as them. Their email is
+/// verified.
+async fn user(role: Role) -> Id<User> {
+    let username = Username::new(unique("user").into()).unwrap();
+    let email = Email::new(format!("{username}@example.com").into()).unwrap();
+    match role {
+        Role::Customer => {
+            query!(
+                "CALL create_customer(($1::TEXT)::USERNAME, ($2::TEXT)::EMAIL, '!', '/customer.png')",
+                &username as &Username,
+                &email as &Email,
+            )
+            .execute(&*POOL)
+            .await
+        },
+        Role::Vendor => {
+            query!(
+                "CALL create_vendor(($1::TEXT)::USERNAME, ($2::TEXT)::EMAIL, '!', '/vendor.png', $3, '')",
+                &username as &Username,
+                &email as &Email,
+                &*username,
+            )
+            .execute(&*POOL)
+            .await
+        },
+        Role::Administrator => {
+            query!(
+                "CALL create_administrator(($1::TEXT)::USERNAME, ($2::TEXT)::EMAIL, '!')",
+                &username as &Username,
+                &email as &Email,
+            )
+            .execute(&*POOL)
+            .await
+        },
+    }
+    .map(QueryResultExt::procedure)
+    .expect("Failed to create user.");
+
+    query_scalar!(
+        "
+        UPDATE users
+        SET email_verified_at = CURRENT_TIMESTAMP
+        WHERE username = $1
+        RETURNING id
+        ",
+        username as Username,
+    )
+    .fetch_one(&*POOL)
+    .await
+    .expect("Failed to find created user.")
+    .into()
+}
+
+/// Create a customer with a delivery address and log in as them.
+async fn customer() -> (Id<Customer>, Session) {
+    let id = user(Role::Customer).await;
+    query!(
+        "
+        INSERT INTO addresses (customer, recipient, street, postal_code, city, country)
+        VALUES ($1, 'Kund', 'Gatan 1', '123 45', 'Staden', 'Sverige')
+        ",
+        id.get(),
+    )
+    .execute(&*POOL)
+    .await
+    .map(QueryResultExt::expect_one)
+    .expect("Failed to create address.");
+    (id.get().into(), Session::start(id).await)
+}
+
+/// Create a vendor with free shipping and log in as them.
+async fn vendor() -> (Id<Vendor>, Session) {
+    let id = user(Role::Vendor).await;
+    query!(
+        "
+        INSERT INTO shipping_methods (vendor, name, fee)
+        VALUES ($1, 'Fri frakt', 0)
+        ",
+        id.get(),
+    )
+    .execute(&*POOL)
+    .await
+    .map(QueryResultExt::expect_one)
+    .expect("Failed to create shipping method.");
+    (id.get().into(), Session::start(id).await)
+}
+
+/// Create an administrator and log in as them.
+async fn administrator() -> (Id<Administrator>, Session) {
+    let id = user(Role::Administrator).await;
+    (id.get().into(), Session::start(id).await)
+}
+
+/// Create a category.
+async fn category(parent: Option<Id<Category>>) -> Id<Category> {
+    query_scalar!(
+        "
+        INSERT INTO categories (parent, name)
+        VALUES ($1, $2)
+        RETURNING id
+        ",
+        parent.map(Id::get),
+        unique("category"),
+    )
+    .fetch_one(&*POOL)
+    .await
+    .expect("Failed to create category.")
+    .into()
+}
+
+/// Create a visible product in a new category with `stock` units in stock.
+async fn product(vendor: Id<Vendor>, price: Decimal, stock: u32) -> Id<Product> {
+    let category = category(None).await;
+    query_scalar!(
+        "
+        INSERT INTO products (
+            vendor, name, thumbnail, gallery, price, overview, description, origin, category,
+            in_stock
+        )
+        VALUES ($1, $2, '/product.png', '{}', $3::DECIMAL(10, 2), '', '', 'Sverige', $4, $5::INT)
+        RETURNING id
+        ",
+        vendor.get(),
+        unique("product"),
+        price,
+        category.get(),
+        i32::try_from(stock).unwrap(),
+    )
+    .fetch_one(&*POOL)
+    .await
+    .expect("Failed to create product.")
+    .into()
+}
+
+/// Create an active special offer on a product.
+async fn special_offer(product: Id<Product>, deal: Deal, limit: Option<u32>) -> Id<SpecialOffer> {
+    let (new_price, quantity1, quantity2) = deal.database_repr().unwrap();
+    query_scalar!(
+        "
+        INSERT INTO special_offers (
+            product, limit_per_customer, valid_from, new_price, quantity1, quantity2
+        )
+        VALUES ($1, $2::INT, CURRENT_TIMESTAMP - INTERVAL '1 hour', $3::DECIMAL(10, 2), $4, $5)
+        RETURNING id
+        ",
+        product.get(),
+        limit.map(|limit| i32::try_from(limit).unwrap()),
+        new_price,
+        quantity1,
+        quantity2,
+    )
+    .fetch_one(&*POOL)
+    .await
+    .expect("Failed to create special offer.")
+    .into()
+}
+
+/// Get the current time according to the database.
+async fn now() -> PrimitiveDateTime {
+    query_scalar!(r#"SELECT CURRENT_TIMESTAMP::TIMESTAMP AS "now!""#)
+        .fetch_one(&*POOL)
+        .await
+        .expect("Failed to get current time.")
+}
+
+/// Get the number of units of a product in stock.
+async fn stock(product: Id<Product>) -> i32 {
+    query_scalar!(
+        "
+        SELECT in_stock
+        FROM products
+        WHERE id = $1
+        ",
+        product.get(),
+    )
+    .fetch_one(&*POOL)
+    .await
+    .expect("Failed to get stock.")
+}
@@ -0,0 +1,82 @@
+//! The administrator dashboard.
+
+use crate::database::{
+    AuthError,
+    admin::{products, set_can_review, users},
+    login_info,
+    products::{product_info, set_visibility},
+    tests::{administrator, customer, product, run, vendor},
+};
+use rust_decimal::Decimal;
+
+#[test]
+fn only_administrators_use_dashboard() {
+    run(async {
+        let (customer, customer_session) = customer().await;
+        let (_, vendor_session) = vendor().await;
+        for session in [&customer_session, &vendor_session] {
+            let error = session.call(users("".into(), 10, 0)).await.unwrap_err();
+            assert_eq!(AuthError::from_error(&error), Some(AuthError::Forbidden));
+            let error = session
+                .call(set_can_review(customer, false))
+                .await
+                .unwrap_err();
+            assert_eq!(AuthError::from_error(&error), Some(AuthError::Forbidden));
+        }
+    });
+}
+
+#[test]
+fn administrators_find_users_and_restrict_reviews() {
+    run(async {
+        let (customer, customer_session) = customer().await;
+        let (_, administrator) = administrator().await;
+        let username = customer_session
+            .call(login_info())
+            .await
+            .unwrap()
+            .unwrap()
+            .username;
+
+        // Wildcards are matched literally.
+        let found = administrator.call(users("%".into(), 10, 0)).await.unwrap();
+        assert!(found.is_empty());
+        let found = administrator
+            .call(users(username.clone().into(), 10, 0))
+            .await
+            .unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].id, customer.into());
+        assert_eq!(found[0].can_review, Some(true));
+
+        administrator
+            .call(set_can_review(customer, false))
+            .await
+            .unwrap();
+        let found = administrator
+            .call(users(username.clone().into(), 10, 0))
+            .await
+            .unwrap();
+        assert_eq!(found[0].can_review, Some(false));
+    });
+}
+
+#[test]
+fn administrators_hide_any_product() {
+    run(async {
+        let (vendor, _) = vendor().await;
+        let product = product(vendor, Decimal::TEN, 1).await;
+        let (_, administrator) = administrator().await;
+
+        administrator
+            .call(set_visibility(product, false))
+            .await
+            .unwrap();
+        assert!(!product_info(None, product).await.unwrap().visible);
+
+        let name = product_info(None, product).await.unwrap().name;
+        let found = administrator.call(products(name, 10, 0)).await.unwrap();
+        assert_eq!(found.len(), 1);
+        assert!(!found[0].visible);
+    });
+}
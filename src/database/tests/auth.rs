@@ -0,0 +1,509 @@
+//! Sessions and authorization.
+
+use crate::database::{
+    AppError, AuthError, Email, Id, InvalidToken, LoginId, NewUserData, User, Username,
+    cart::{cart_counts, checkout},
+    categories::create_category,
+    change_password, create_user, email_verified, log_in, log_out, login_info,
+    products::set_price,
+    reauthenticate, request_password_reset, reset_password,
+    reviews::create_review,
+    tests::{
+        PROXY, Session, administrator, call_from, call_via, checkout::card, customer, mailed_token,
+        now, product, run, scratch, unique, vendor,
+    },
+    users::{delete_user, set_email},
+    verify_email,
+};
+use dioxus::CapturedError;
+use http::header::SET_COOKIE;
+use rust_decimal::Decimal;
+
+#[test]
+fn log_in_and_out() {
+    run(async {
+        let username = Username::new(unique("user").into()).unwrap();
+        let email = Email::new(format!("{username}@example.com").into()).unwrap();
+        create_user(
+            username.clone(),
+            email,
+            "hunter2".into(),
+            NewUserData::Customer {
+                profile_picture: None,
+            },
+        )
+        .await
+        .unwrap();
+
+        assert!(
+            log_in(username.clone(), "hunter3".into()).await.is_err(),
+            "Logged in with incorrect password."
+        );
+
+        let response = log_in(username.clone(), "hunter2".into()).await.unwrap();
+        let cookie = response.headers()[SET_COOKIE].to_str().unwrap();
+        let session = Session {
+            cookie: cookie.split(';').next().unwrap().into(),
+        };
+
+        let login = session.call(login_info()).await.unwrap().unwrap();
+        assert_eq!(login.username, username);
+        assert!(matches!(login.id, LoginId::Customer(_)));
+
+        let response = session.call(log_out()).await.unwrap();
+        assert!(
+            response.headers()[SET_COOKIE]
+                .to_str()
+                .unwrap()
+                .contains("Max-Age=0")
+        );
+        assert_eq!(session.call(login_info()).await.unwrap(), None);
+    });
+}
+
+#[test]
+fn anonymous_callers_are_unauthenticated() {
+    run(async {
+        let (customer, _) = customer().await;
+        let error = cart_counts(customer).await.unwrap_err();
+        assert_eq!(
+            AuthError::from_error(&error),
+            Some(AuthError::Unauthenticated)
+        );
+        assert_eq!(login_info().await.unwrap(), None);
+    });
+}
+
+#[test]
+fn acting_for_others_is_forbidden() {
+    run(async {
+        let (owner, _) = customer().await;
+        let (_, other) = customer().await;
+        let error = other.call(cart_counts(owner)).await.unwrap_err();
+        assert_eq!(AuthError::from_error(&error), Some(AuthError::Forbidden));
+
+        let (owner, _) = vendor().await;
+        let (_, other) = vendor().await;
+        let product = product(owner, Decimal::TEN, 0).await;
+        let error = other
+            .call(set_price(product, Decimal::ONE))
+            .await
+            .unwrap_err();
+        assert_eq!(AuthError::from_error(&error), Some(AuthError::Forbidden));
+    });
+}
+
+#[test]
+fn administrators_manage_categories() {
+    run(async {
+        let (_, customer) = customer().await;
+        let error = customer
+            .call(create_category(None, unique("category").into()))
+            .await
+            .unwrap_err();
+        assert_eq!(AuthError::from_error(&error), Some(AuthError::Forbidden));
+
+        let (_, administrator) = administrator().await;
+        administrator
+            .call(create_category(None, unique("category").into()))
+            .await
+            .unwrap();
+    });
+}
+
+#[test]
+fn deleting_user_ends_sessions() {
+    run(async {
+        let (customer, session) = customer().await;
+        session
+            .call(delete_user(customer.get().into()))
+            .await
+            .unwrap();
+
+        assert_eq!(session.call(login_info()).await.unwrap(), None);
+        let error = session.call(cart_counts(customer)).await.unwrap_err();
+        assert_eq!(
+            AuthError::from_error(&error),
+            Some(AuthError::Unauthenticated)
+        );
+    });
+}
+
+/// Register a new customer, returning their username and email.
+pub(super) async fn register(password: &str) -> (Username, Email) {
+    let username = Username::new(unique("user").into()).unwrap();
+    let email = Email::new(format!("{username}@example.com").into()).unwrap();
+    create_user(
+        username.clone(),
+        email.clone(),
+        password.into(),
+        NewUserData::Customer {
+            profile_picture: None,
+        },
+    )
+    .await
+    .unwrap();
+    (username, email)
+}
+
+/// Log in with a password, returning the session.
+pub(super) async fn log_in_as(username: &Username, password: &str) -> Session {
+    let response = log_in(username.clone(), password.into()).await.unwrap();
+    let cookie = response.headers()[SET_COOKIE].to_str().unwrap();
+    Session {
+        cookie: cookie.split(';').next().unwrap().into(),
+    }
+}
+
+/// Check whether an error was caused by an unusable token.
+fn invalid_token(error: &CapturedError) -> bool {
+    error.downcast_ref::<InvalidToken>().is_some()
+}
+
+#[test]
+fn unverified_customers_can_not_order_or_review() {
+    run(async {
+        let (vendor, _) = vendor().await;
+        let product = product(vendor, Decimal::TEN, 5).await;
+        let (username, email) = register("hunter2").await;
+        let session = log_in_as(&username, "hunter2").await;
+        let LoginId::Customer(customer) = session.call(login_info()).await.unwrap().unwrap().id
+        else {
+            panic!("Registered as someone other than a customer.");
+        };
+
+        let order = async || {
+            let error = session
+                .call(checkout(
+                    customer,
+                    Vec::new(),
+                    now().await,
+                    Id::from(-1),
+                    Vec::new(),
+                    None,
+                    card(),
+                ))
+                .await
+                .unwrap_err();
+            AuthError::from_error(&error)
+        };
+        let review = async || {
+            let error = session
+                .call(create_review(customer, product, "Bra".into(), "".into()))
+                .await
+                .unwrap_err();
+            AuthError::from_error(&error)
+        };
+        assert_eq!(order().await, Some(AuthError::Forbidden));
+        assert_eq!(review().await, Some(AuthError::Forbidden));
+
+        verify_email(mailed_token(&email, "/verify/"))
+            .await
+            .unwrap();
+        // Both now fail for other reasons.
+        assert_eq!(order().await, None);
+        assert_eq!(review().await, None);
+    });
+}
+
+#[test]
+fn email_verification_tokens_are_single_use() {
+    run(async {
+        let (username, email) = register("hunter2").await;
+        let session = log_in_as(&username, "hunter2").await;
+        assert!(!session.call(email_verified()).await.unwrap());
+
+        let token = mailed_token(&email, "/verify/");
+        assert!(invalid_token(
+            &verify_email("0".repeat(64).into()).await.unwrap_err()
+        ));
+        verify_email(token.clone()).await.unwrap();
+        assert!(session.call(email_verified()).await.unwrap());
+        assert!(invalid_token(&verify_email(token).await.unwrap_err()));
+
+        // A new address must be verified anew, and links to the old one stop working.
+        let user = Id::<User>::from(session.call(login_info()).await.unwrap().unwrap().id);
+        let changed = Email::new(format!("changed_{email}").into()).unwrap();
+        session
+            .call(set_email(user, changed.clone()))
+            .await
+            .unwrap();
+        assert!(!session.call(email_verified()).await.unwrap());
+        let token = mailed_token(&changed, "/verify/");
+        session.call(set_email(user, email)).await.unwrap();
+        assert!(invalid_token(&verify_email(token).await.unwrap_err()));
+    });
+}
+
+#[test]
+fn taken_usernames_and_emails_conflict() {
+    run(async {
+        let (username, email) = register("hunter2").await;
+        let data = || NewUserData::Customer {
+            profile_picture: None,
+        };
+
+        let other_email = Email::new(format!("other_{email}").into()).unwrap();
+        let error = create_user(username, other_email, "hunter2".into(), data())
+            .await
+            .unwrap_err();
+        assert_eq!(
+            AppError::from_error(&error),
+            Some(AppError::Conflict {
+                field: "username".into()
+            })
+        );
+
+        let other_username = Username::new(unique("user").into()).unwrap();
+        let error = create_user(other_username, email, "hunter2".into(), data())
+            .await
+            .unwrap_err();
+        assert_eq!(
+            AppError::from_error(&error),
+            Some(AppError::Conflict {
+                field: "email".into()
+            })
+        );
+    });
+}
+
+#[test]
+fn passwords_are_reset_by_mail() {
+    run(async {
+        let (username, email) = register("hunter2").await;
+        let session = log_in_as(&username, "hunter2").await;
+
+        request_password_reset(email.clone()).await.unwrap();
+        let first = mailed_token(&email, "/reset/");
+        request_password_reset(email.clone()).await.unwrap();
+        let second = mailed_token(&email, "/reset/");
+        assert!(invalid_token(
+            &reset_password(first, "hunter3".into()).await.unwrap_err()
+        ));
+
+        reset_password(second.clone(), "hunter3".into())
+            .await
+            .unwrap();
+        assert_eq!(session.call(login_info()).await.unwrap(), None);
+        let old_password = log_in(username.clone(), "hunter2".into()).await;
+        assert!(old_password.is_err(), "Logged in with the old password.");
+        let session = log_in_as(&username, "hunter3").await;
+        assert!(session.call(email_verified()).await.unwrap());
+        assert!(invalid_token(
+            &reset_password(second, "hunter4".into()).await.unwrap_err()
+        ));
+
+        // Unknown addresses are not revealed.
+        let unknown = Email::new(format!("{}@example.com", unique("nobody")).into()).unwrap();
+        request_password_reset(unknown.clone()).await.unwrap();
+        assert!(!scratch().join("mail").join(unknown.to_string()).exists());
+    });
+}
+
+#[test]
+fn password_reset_requests_are_limited() {
+    run(async {
+        let (_, email) = register("hunter2").await;
+        for _ in 0..3 {
+            request_password_reset(email.clone()).await.unwrap();
+        }
+        // Addresses differing only in case belong to the same account.
+        let shouted = Email::new(email.to_uppercase().into()).unwrap();
+        let error = request_password_reset(shouted).await.unwrap_err();
+        assert!(retry_after(&error).is_some());
+
+        let nobody = || Email::new(format!("{}@example.com", unique("nobody")).into()).unwrap();
+        for _ in 0..10 {
+            call_from("198.51.100.3", request_password_reset(nobody()))
+                .await
+                .unwrap();
+        }
+        let error = call_from("198.51.100.3", request_password_reset(nobody()))
+            .await
+            .unwrap_err();
+        assert!(retry_after(&error).is_some());
+    });
+}
+
+#[test]
+fn sensitive_changes_require_recent_authentication() {
+    run(async {
+        let (username, email) = register("hunter2").await;
+        let session = log_in_as(&username, "hunter2").await;
+        let user = Id::<User>::from(session.call(login_info()).await.unwrap().unwrap().id);
+        session.age().await;
+
+        let changed = Email::new(format!("changed_{email}").into()).unwrap();
+        let error = session
+            .call(set_email(user, changed.clone()))
+            .await
+            .unwrap_err();
+        assert_eq!(
+            AuthError::from_error(&error),
+            Some(AuthError::ReauthenticationRequired)
+        );
+        let error = session.call(delete_user(user)).await.unwrap_err();
+        assert_eq!(
+            AuthError::from_error(&error),
+            Some(AuthError::ReauthenticationRequired)
+        );
+
+        let wrong = session.call(reauthenticate("hunter3".into())).await;
+        assert!(wrong.is_err(), "Reauthenticated with incorrect password.");
+        session
+            .call(reauthenticate("hunter2".into()))
+            .await
+            .unwrap();
+        session.call(set_email(user, changed)).await.unwrap();
+
+        // Administrators must also have authenticated recently to delete others.
+        let (_, administrator) = administrator().await;
+        administrator.age().await;
+        let error = administrator.call(delete_user(user)).await.unwrap_err();
+        assert_eq!(
+            AuthError::from_error(&error),
+            Some(AuthError::ReauthenticationRequired)
+        );
+    });
+}
+
+#[test]
+fn changing_password_ends_other_sessions() {
+    run(async {
+        let (username, _) = register("hunter2").await;
+        let session = log_in_as(&username, "hunter2").await;
+        let other = log_in_as(&username, "hunter2").await;
+        session.age().await;
+
+        let wrong = session
+            .call(change_password("hunter3".into(), "hunter4".into()))
+            .await;
+        assert!(wrong.is_err(), "Changed password without the current one.");
+        assert!(other.call(login_info()).await.unwrap().is_some());
+
+        session
+            .call(change_password("hunter2".into(), "hunter3".into()))
+            .await
+            .unwrap();
+        assert!(session.call(login_info()).await.unwrap().is_some());
+        assert_eq!(other.call(login_info()).await.unwrap(), None);
+
+        let old_password = log_in(username.clone(), "hunter2".into()).await;
+        assert!(old_password.is_err(), "Logged in with the old password.");
+        let session = log_in_as(&username, "hunter3").await;
+        assert!(session.call(login_info()).await.unwrap().is_some());
+
+        // Confirming the current password counts as authenticating.
+        let user = Id::<User>::from(session.call(login_info()).await.unwrap().unwrap().id);
+        session.age().await;
+        session
+            .call(change_password("hunter3".into(), "hunter4".into()))
+            .await
+            .unwrap();
+        session.call(delete_user(user)).await.unwrap();
+    });
+}
+
+/// Get the time to wait if an error was caused by too many attempts.
+fn retry_after(error: &CapturedError) -> Option<u64> {
+    if let Some(AppError::RateLimited { retry_after }) = AppError::from_error(error) {
+        Some(retry_after)
+    } else {
+        None
+    }
+}
+
+#[test]
+fn log_in_attempts_are_limited_per_address() {
+    run(async {
+        let nobody = || Username::new(unique("nobody").into()).unwrap();
+        for _ in 0..20 {
+            let error = call_from("198.51.100.1", log_in(nobody(), "hunter2".into()))
+                .await
+                .unwrap_err();
+            assert_eq!(
+                AppError::from_error(&error),
+                Some(AppError::UnknownUsername)
+            );
+        }
+        let error = call_from("198.51.100.1", log_in(nobody(), "hunter2".into()))
+            .await
+            .unwrap_err();
+        assert!(retry_after(&error).is_some_and(|seconds| (1..=60).contains(&seconds)));
+
+        // Other addresses are unaffected.
+        let error = call_from("198.51.100.2", log_in(nobody(), "hunter2".into()))
+            .await
+            .unwrap_err();
+        assert_eq!(retry_after(&error), None);
+    });
+}
+
+#[test]
+fn forwarded_addresses_are_only_trusted_from_proxies() {
+    run(async {
+        let nobody = || Username::new(unique("nobody").into()).unwrap();
+        // A client connecting directly cannot pose as others.
+        for i in 0..20 {
+            let error = call_via(
+                "198.51.100.5",
+                &format!("198.51.100.{}", 100 + i),
+                log_in(nobody(), "hunter2".into()),
+            )
+            .await
+            .unwrap_err();
+            assert_eq!(retry_after(&error), None);
+        }
+        let error = call_via(
+            "198.51.100.5",
+            "198.51.100.99",
+            log_in(nobody(), "hunter2".into()),
+        )
+        .await
+        .unwrap_err();
+        assert!(retry_after(&error).is_some());
+
+        // Nor can a client behind a proxy, by adding entries of its own.
+        let error = call_via(
+            PROXY,
+            "198.51.100.6, 198.51.100.5",
+            log_in(nobody(), "hunter2".into()),
+        )
+        .await
+        .unwrap_err();
+        assert!(retry_after(&error).is_some());
+        let error = call_from("198.51.100.6", log_in(nobody(), "hunter2".into()))
+            .await
+            .unwrap_err();
+        assert_eq!(retry_after(&error), None);
+    });
+}
+
+#[test]
+fn repeated_incorrect_passwords_lock_out() {
+    run(async {
+        let (username, _) = register("hunter2").await;
+        let session = log_in_as(&username, "hunter2").await;
+
+        for _ in 0..6 {
+            let error = log_in(username.clone(), "hunter3".into())
+                .await
+                .unwrap_err();
+            assert_eq!(
+                AppError::from_error(&error),
+                Some(AppError::IncorrectPassword)
+            );
+        }
+
+        // Even the correct password is refused while locked out.
+        let error = log_in(username.clone(), "hunter2".into())
+            .await
+            .unwrap_err();
+        assert!(retry_after(&error).is_some_and(|seconds| (1..=60).contains(&seconds)));
+        let error = session
+            .call(reauthenticate("hunter2".into()))
+            .await
+            .unwrap_err();
+        assert!(retry_after(&error).is_some());
+    });
+}
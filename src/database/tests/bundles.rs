@@ -0,0 +1,356 @@
+//! Creating bundles and allocating cart lines to them at checkout.
+
+use crate::database::{
+    AppError, AuthError, Bundle, Customer, Deal, Id, POOL, Product, StaleReason, Vendor,
+    bundles::{BundleDeal, create_bundle, end_bundle, product_bundles},
+    cart::{CheckoutOutcome, Quote, cart_quote, set_in_shopping_cart},
+    tests::{
+        Session, category,
+        checkout::{check_out, placed},
+        customer, now, product, run, special_offer, unique, vendor,
+    },
+};
+use dioxus::prelude::Result;
+use rust_decimal::Decimal;
+use sqlx::{query, query_scalar};
+use std::num::NonZeroU32;
+use time::Duration;
+
+/// Create an active bundle as `vendor`.
+///
+/// # Errors
+///
+/// Fails if [`create_bundle`] does.
+async fn bundle(
+    session: &Session,
+    vendor: Id<Vendor>,
+    deal: BundleDeal,
+    price: Decimal,
+) -> Result<Id<Bundle>> {
+    session
+        .call(create_bundle(
+            vendor,
+            unique("Paket").into(),
+            deal,
+            price,
+            now().await - Duration::hours(1),
+            None,
+        ))
+        .await
+}
+
+/// Put `number` units of each product in the cart of `customer`, and quote it.
+async fn quote(session: &Session, customer: Id<Customer>, items: &[(Id<Product>, u32)]) -> Quote {
+    for &(product, number) in items {
+        session
+            .call(set_in_shopping_cart(customer, product, number))
+            .await
+            .unwrap();
+    }
+    session.call(cart_quote(customer)).await.unwrap()
+}
+
+/// Check out exactly what is quoted, returning the total recorded with the order.
+async fn check_out_quote(session: &Session, customer: Id<Customer>, quote: &Quote) -> Decimal {
+    let order = placed(
+        check_out(session, customer, quote.items(), quote.seen_at)
+            .await
+            .unwrap(),
+    );
+    let (total, bundled) = query!(
+        r#"
+        SELECT total,
+            (SELECT SUM(paid) FROM order_bundles WHERE order_id = $1) AS bundled
+        FROM orders
+        WHERE id = $1
+        "#,
+        order.get(),
+    )
+    .fetch_one(&*POOL)
+    .await
+    .map(|order| (order.total, order.bundled))
+    .unwrap();
+    assert_eq!(
+        bundled.unwrap_or_default(),
+        quote
+            .lines
+            .iter()
+            .flat_map(|line| &line.bundles)
+            .map(|share| share.price)
+            .sum()
+    );
+    total
+}
+
+/// How many units of each line of `quote` are allocated to bundles, and what they cost.
+fn allocation(quote: &Quote) -> Vec<(u32, Decimal)> {
+    quote
+        .lines
+        .iter()
+        .map(|line| {
+            line.bundles
+                .iter()
+                .fold((0, Decimal::ZERO), |(units, price), share| {
+                    (units + share.units.get(), price + share.price)
+                })
+        })
+        .collect()
+}
+
+/// Create a mix and match bundle of any `quantity` units among `products`.
+fn any_of(quantity: u32, products: &[Id<Product>]) -> BundleDeal {
+    BundleDeal::MixAndMatch {
+        quantity: NonZeroU32::new(quantity).unwrap(),
+        products: products.into(),
+    }
+}
+
+#[test]
+fn mix_and_match_takes_most_expensive_units() {
+    run(async {
+        let (vendor, vendor_session) = vendor().await;
+        let first = product(vendor, Decimal::from(14), 10).await;
+        let second = product(vendor, Decimal::from(12), 10).await;
+        let third = product(vendor, Decimal::TEN, 10).await;
+        let id = bundle(
+            &vendor_session,
+            vendor,
+            any_of(3, &[first, second, third]),
+            Decimal::from(30),
+        )
+        .await
+        .unwrap();
+        let (customer, session) = customer().await;
+
+        // 14 + 14 + 12 for 30, split as 30 * 28 / 40 and the rest. The remaining two units of
+        // `third` are not enough for another use.
+        let quote = quote(&session, customer, &[(first, 2), (second, 1), (third, 2)]).await;
+        assert_eq!(
+            allocation(&quote),
+            [
+                (2, Decimal::from(21)),
+                (1, Decimal::from(9)),
+                (0, Decimal::ZERO)
+            ]
+        );
+        assert_eq!(quote.lines[0].bundles[0].bundle, id);
+        assert_eq!(quote.total, Decimal::from(50));
+        assert_eq!(quote.discount, Decimal::TEN);
+        assert!(
+            session
+                .call(product_bundles(third))
+                .await
+                .unwrap()
+                .iter()
+                .any(|bundle| bundle.id == id)
+        );
+
+        assert_eq!(
+            check_out_quote(&session, customer, &quote).await,
+            Decimal::from(50)
+        );
+    });
+}
+
+#[test]
+fn bundles_only_apply_when_cheaper() {
+    run(async {
+        let (vendor, vendor_session) = vendor().await;
+        let first = product(vendor, Decimal::TEN, 10).await;
+        let second = product(vendor, Decimal::TEN, 10).await;
+        let _id = bundle(
+            &vendor_session,
+            vendor,
+            any_of(2, &[first, second]),
+            Decimal::from(20),
+        )
+        .await
+        .unwrap();
+        let (customer, session) = customer().await;
+
+        let quote = quote(&session, customer, &[(first, 1), (second, 1)]).await;
+        assert_eq!(allocation(&quote), [(0, Decimal::ZERO); 2]);
+        assert_eq!(quote.total, Decimal::from(20));
+    });
+}
+
+#[test]
+fn fixed_bundle_takes_complete_sets_before_special_offers() {
+    run(async {
+        let (vendor, vendor_session) = vendor().await;
+        let pasta = product(vendor, Decimal::TEN, 10).await;
+        let sauce = product(vendor, Decimal::from(12), 10).await;
+        let _offer = special_offer(
+            pasta,
+            Deal::from_repr(Some(Decimal::from(8)), None, None, Decimal::TEN).unwrap(),
+            None,
+        )
+        .await;
+        let _id = bundle(
+            &vendor_session,
+            vendor,
+            BundleDeal::Fixed {
+                products: [pasta, sauce]
+                    .map(|product| (product, NonZeroU32::MIN))
+                    .into(),
+            },
+            Decimal::from(18),
+        )
+        .await
+        .unwrap();
+        let (customer, session) = customer().await;
+
+        // Two sets for 36, split as 36 * 20 / 44 and the rest, and the last pasta on sale.
+        let quote = quote(&session, customer, &[(pasta, 3), (sauce, 2)]).await;
+        assert_eq!(
+            allocation(&quote),
+            [(2, Decimal::new(1636, 2)), (2, Decimal::new(1964, 2))]
+        );
+        assert_eq!(quote.lines[0].offer_uses, 1);
+        assert_eq!(quote.lines[0].price, Decimal::new(2436, 2));
+        assert_eq!(quote.total, Decimal::from(44));
+
+        assert_eq!(
+            check_out_quote(&session, customer, &quote).await,
+            Decimal::from(44)
+        );
+    });
+}
+
+#[test]
+fn category_bundle_takes_own_products_in_subcategories() {
+    run(async {
+        let (other_vendor, _) = vendor().await;
+        let (vendor, vendor_session) = vendor().await;
+        let parent = category(None).await;
+        let child = category(Some(parent)).await;
+        let own = [
+            product(vendor, Decimal::TEN, 10).await,
+            product(vendor, Decimal::TEN, 10).await,
+        ];
+        let other = product(other_vendor, Decimal::TEN, 10).await;
+        let _updated = query!(
+            "UPDATE products SET category = $2 WHERE id = ANY($1)",
+            &[own[0].get(), own[1].get(), other.get()],
+            child.get(),
+        )
+        .execute(&*POOL)
+        .await
+        .unwrap();
+        let _id = bundle(
+            &vendor_session,
+            vendor,
+            BundleDeal::Category {
+                quantity: NonZeroU32::new(2).unwrap(),
+                category: parent,
+            },
+            Decimal::from(15),
+        )
+        .await
+        .unwrap();
+        let (customer, session) = customer().await;
+
+        let quote = quote(&session, customer, &[(own[0], 1), (own[1], 1), (other, 1)]).await;
+        assert_eq!(
+            allocation(&quote),
+            [
+                (1, Decimal::new(750, 2)),
+                (1, Decimal::new(750, 2)),
+                (0, Decimal::ZERO)
+            ]
+        );
+        assert_eq!(
+            check_out_quote(&session, customer, &quote).await,
+            Decimal::from(25)
+        );
+    });
+}
+
+#[test]
+fn ended_bundle_is_stale() {
+    run(async {
+        let (vendor, vendor_session) = vendor().await;
+        let first = product(vendor, Decimal::TEN, 10).await;
+        let second = product(vendor, Decimal::TEN, 10).await;
+        let id = bundle(
+            &vendor_session,
+            vendor,
+            any_of(2, &[first, second]),
+            Decimal::from(15),
+        )
+        .await
+        .unwrap();
+        let (customer, session) = customer().await;
+        let seen = quote(&session, customer, &[(first, 1), (second, 1)]).await;
+        assert_eq!(seen.total, Decimal::from(15));
+
+        vendor_session.call(end_bundle(id)).await.unwrap();
+        assert!(
+            session
+                .call(product_bundles(first))
+                .await
+                .unwrap()
+                .is_empty()
+        );
+        let CheckoutOutcome::Stale { lines, .. } =
+            check_out(&session, customer, seen.items(), seen.seen_at)
+                .await
+                .unwrap()
+        else {
+            panic!("Checkout with ended bundle was not stale.");
+        };
+        assert_eq!(lines.len(), 2);
+        assert!(
+            lines
+                .iter()
+                .all(|line| *line.reasons == [StaleReason::BundleChanged])
+        );
+
+        let fresh = session.call(cart_quote(customer)).await.unwrap();
+        assert_eq!(
+            check_out_quote(&session, customer, &fresh).await,
+            Decimal::from(20)
+        );
+    });
+}
+
+#[test]
+fn only_vendors_of_the_products_create_bundles() {
+    run(async {
+        let (other_vendor, _) = vendor().await;
+        let (vendor, session) = vendor().await;
+        let own = product(vendor, Decimal::TEN, 10).await;
+        let other = product(other_vendor, Decimal::TEN, 10).await;
+
+        let error = bundle(&session, vendor, any_of(2, &[own, other]), Decimal::ONE)
+            .await
+            .unwrap_err();
+        assert_eq!(AuthError::from_error(&error), Some(AuthError::Forbidden));
+
+        let error = bundle(
+            &session,
+            vendor,
+            BundleDeal::Fixed {
+                products: [(own, NonZeroU32::new(2).unwrap())].into(),
+            },
+            Decimal::ONE,
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(
+            AppError::from_error(&error),
+            Some(AppError::Invalid {
+                constraint: "bundle_products".into()
+            })
+        );
+
+        let count = query_scalar!(
+            r#"SELECT COUNT(*) AS "count!" FROM bundles WHERE vendor = $1"#,
+            vendor.get(),
+        )
+        .fetch_one(&*POOL)
+        .await
+        .unwrap();
+        assert_eq!(count, 0);
+    });
+}
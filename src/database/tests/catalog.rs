@@ -0,0 +1,219 @@
+//! Categories, stock and visibility.
+
+use crate::database::{
+    Category, Id, POOL, QueryResultExt,
+    cart::{cart_counts, set_in_shopping_cart},
+    categories::{
+        CategoryCycle, CategoryTree, category_trees, move_category, rename_category,
+        reorder_categories,
+    },
+    products::{product_info, products_in_category_tree, set_visibility},
+    tests::{administrator, category, customer, now, product, run, stock, vendor},
+};
+use rust_decimal::Decimal;
+use sqlx::{Error, query, query_scalar};
+use time::Duration;
+
+#[test]
+fn product_info_lists_category_path() {
+    run(async {
+        let root = category(None).await;
+        let child = category(Some(root)).await;
+        let grandchild = category(Some(child)).await;
+        let (vendor, _) = vendor().await;
+        let product = product(vendor, Decimal::TEN, 0).await;
+        query!(
+            "UPDATE products SET category = $2 WHERE id = $1",
+            product.get(),
+            grandchild.get(),
+        )
+        .execute(&*POOL)
+        .await
+        .map(QueryResultExt::expect_one)
+        .unwrap();
+
+        let info = product_info(None, product).await.unwrap();
+        let path = info.category.iter().map(|&(id, _)| id).collect::<Vec<_>>();
+        assert_eq!(path, [grandchild, child, root]);
+    });
+}
+
+#[test]
+fn categories_reject_cycles() {
+    run(async {
+        let root = category(None).await;
+        let child = category(Some(root)).await;
+        let grandchild = category(Some(child)).await;
+
+        for (category, parent) in [(root, grandchild), (child, child)] {
+            let result = query!(
+                "UPDATE categories SET parent = $2 WHERE id = $1",
+                category.get(),
+                parent.get(),
+            )
+            .execute(&*POOL)
+            .await;
+            assert!(matches!(result, Err(Error::Database(_))));
+        }
+    });
+}
+
+/// Find a category in a forest of category trees.
+fn find(trees: &[CategoryTree], id: Id<Category>) -> Option<&CategoryTree> {
+    trees.iter().find_map(|tree| {
+        (tree.id == id)
+            .then_some(tree)
+            .or_else(|| find(&tree.subcategories, id))
+    })
+}
+
+#[test]
+fn administrators_rearrange_categories() {
+    run(async {
+        let (_, administrator) = administrator().await;
+        let root = category(None).await;
+        let first = category(Some(root)).await;
+        let second = category(Some(root)).await;
+
+        administrator
+            .call(reorder_categories(Some(root), [second, first].into()))
+            .await
+            .unwrap();
+        administrator
+            .call(rename_category(first, "Omdöpt".into()))
+            .await
+            .unwrap();
+        let trees = category_trees().await.unwrap();
+        let children = &find(&trees, root).unwrap().subcategories;
+        assert_eq!(
+            children.iter().map(|tree| tree.id).collect::<Vec<_>>(),
+            [second, first]
+        );
+        assert_eq!(&*children[1].name, "Omdöpt");
+
+        // Every child must be listed exactly once.
+        for order in [&[first][..], &[first, first, second]] {
+            let result = administrator
+                .call(reorder_categories(Some(root), order.into()))
+                .await;
+            assert!(result.is_err());
+        }
+
+        let error = administrator
+            .call(move_category(root, Some(second)))
+            .await
+            .unwrap_err();
+        assert_eq!(error.downcast_ref::<CategoryCycle>(), Some(&CategoryCycle));
+
+        administrator
+            .call(move_category(second, Some(first)))
+            .await
+            .unwrap();
+        let trees = category_trees().await.unwrap();
+        let moved = &find(&trees, first).unwrap().subcategories;
+        assert_eq!(
+            moved.iter().map(|tree| tree.id).collect::<Vec<_>>(),
+            [second]
+        );
+    });
+}
+
+#[test]
+fn category_pages_include_subcategories() {
+    run(async {
+        let root = category(None).await;
+        let child = category(Some(root)).await;
+        let grandchild = category(Some(child)).await;
+        let (vendor, _) = vendor().await;
+        let product = product(vendor, Decimal::TEN, 1).await;
+        query!(
+            "UPDATE products SET category = $2 WHERE id = $1",
+            product.get(),
+            grandchild.get(),
+        )
+        .execute(&*POOL)
+        .await
+        .map(QueryResultExt::expect_one)
+        .unwrap();
+
+        for category in [root, child, grandchild] {
+            let found = products_in_category_tree(None, category, None, 10, 0)
+                .await
+                .unwrap();
+            assert_eq!(found.iter().map(|p| p.id).collect::<Vec<_>>(), [product]);
+        }
+        let found = products_in_category_tree(None, root, Some(product), 10, 0)
+            .await
+            .unwrap();
+        assert!(found.is_empty());
+    });
+}
+
+#[test]
+fn hidden_products_leave_carts() {
+    run(async {
+        let (vendor, vendor_session) = vendor().await;
+        let product = product(vendor, Decimal::TEN, 5).await;
+        let (customer, session) = customer().await;
+        session
+            .call(set_in_shopping_cart(customer, product, 2))
+            .await
+            .unwrap();
+
+        vendor_session
+            .call(set_visibility(product, false))
+            .await
+            .unwrap();
+        assert!(
+            session
+                .call(cart_counts(customer))
+                .await
+                .unwrap()
+                .is_empty()
+        );
+
+        vendor_session
+            .call(set_visibility(product, true))
+            .await
+            .unwrap();
+        assert!(
+            session
+                .call(cart_counts(customer))
+                .await
+                .unwrap()
+                .is_empty()
+        );
+    });
+}
+
+#[test]
+fn expired_stock_is_removed() {
+    run(async {
+        let (vendor, _) = vendor().await;
+        let product = product(vendor, Decimal::TEN, 0).await;
+        let today = now().await.date();
+        let new_stock = query_scalar!(
+            "SELECT add_stock($1, 3, $2)",
+            product.get(),
+            today - Duration::days(1),
+        )
+        .fetch_one(&*POOL)
+        .await
+        .unwrap();
+        assert_eq!(new_stock, Some(3));
+
+        let processed = query_scalar!(
+            r#"
+            SELECT total AS "total!"
+            FROM process_expiries()
+            WHERE product = $1
+            "#,
+            product.get(),
+        )
+        .fetch_all(&*POOL)
+        .await
+        .unwrap();
+        assert_eq!(processed, [3]);
+        assert_eq!(stock(product).await, 0);
+    });
+}
@@ -0,0 +1,761 @@
+//! Checkout, including stale-data rejection and concurrent checkouts.
+
+use crate::database::{
+    Address, AppError, AuthError, Customer, Deal, Id, Order, POOL, Product, StaleReason,
+    cart::{
+        CheckoutItem, CheckoutOutcome, StaleLine, apply_stale_lines, cart_counts, cart_products,
+        cart_quote, checkout, set_in_shopping_cart,
+    },
+    delivery::{Shipment, customer_addresses, shipping_options},
+    memberships::join_membership,
+    offers::{delete_special_offer, set_special_offer_members_only},
+    payments::{CardNumber, MockProvider},
+    products::{OrderStatus, add_stock, customer_orders, product_info, set_price, set_status},
+    tests::{Session, customer, now, product, run, special_offer, stock, vendor},
+};
+use dioxus::prelude::Result;
+use rust_decimal::Decimal;
+use sqlx::{query, query_scalar};
+use std::num::NonZeroU32;
+use time::{Duration, PrimitiveDateTime};
+use tokio::task::JoinHandle;
+
+/// Put `number` units of `product` in the cart of `customer` and load the cart.
+pub(super) async fn fill_cart(
+    customer: Id<Customer>,
+    session: &Session,
+    product: Id<Product>,
+    number: u32,
+) -> (Vec<CheckoutItem>, PrimitiveDateTime) {
+    session
+        .call(set_in_shopping_cart(customer, product, number))
+        .await
+        .unwrap();
+    let (cart, seen_at) = session.call(cart_products(customer)).await.unwrap();
+    (cart.iter().map(CheckoutItem::from).collect(), seen_at)
+}
+
+/// Get a card that is always accepted by the default payment provider.
+pub(super) fn card() -> CardNumber {
+    CardNumber::new(MockProvider::SUCCEEDING_CARD).unwrap()
+}
+
+/// Get the first address of `customer` and the cheapest shipping method of each vendor of `items`.
+pub(super) async fn delivery(
+    session: &Session,
+    customer: Id<Customer>,
+    items: Vec<CheckoutItem>,
+) -> (Id<Address>, Vec<Shipment>) {
+    let addresses = session.call(customer_addresses(customer)).await.unwrap();
+    let options = session.call(shipping_options(items)).await.unwrap();
+    let shipments = options
+        .chunk_by(|a, b| a.vendor == b.vendor)
+        .filter_map(<[_]>::first)
+        .map(Shipment::from)
+        .collect();
+    (addresses[0].0, shipments)
+}
+
+/// Check out `items`, delivered as chosen by [`delivery`] and paid for with [`card`].
+///
+/// # Errors
+///
+/// Fails if [`checkout`] does.
+pub(super) async fn check_out(
+    session: &Session,
+    customer: Id<Customer>,
+    items: Vec<CheckoutItem>,
+    seen_at: PrimitiveDateTime,
+) -> Result<CheckoutOutcome> {
+    let (address, shipments) = delivery(session, customer, items.clone()).await;
+    session
+        .call(checkout(
+            customer,
+            items,
+            seen_at,
+            address,
+            shipments,
+            None,
+            card(),
+        ))
+        .await
+}
+
+/// Get the order placed by a checkout, failing if the cart was stale.
+pub(super) fn placed(outcome: CheckoutOutcome) -> Id<Order> {
+    match outcome {
+        CheckoutOutcome::Placed(order) => order,
+        CheckoutOutcome::Stale { lines, .. } => panic!("Unexpectedly stale: {lines:?}"),
+    }
+}
+
+/// Check out, expecting the single line to be reported as stale for `reasons`.
+async fn stale(
+    session: &Session,
+    customer: Id<Customer>,
+    items: Vec<CheckoutItem>,
+    seen_at: PrimitiveDateTime,
+    reasons: &[StaleReason],
+) -> (StaleLine, PrimitiveDateTime) {
+    let outcome = check_out(session, customer, items, seen_at).await.unwrap();
+    let CheckoutOutcome::Stale { lines, seen_at } = outcome else {
+        panic!("Unexpectedly placed order: {outcome:?}");
+    };
+    let [line] = &*lines else {
+        panic!("Expected exactly one stale line, found {}.", lines.len());
+    };
+    assert_eq!(*line.reasons, *reasons);
+    (line.clone(), seen_at)
+}
+
+/// Check out, expecting to be rejected with `expected`.
+async fn reject(
+    session: &Session,
+    customer: Id<Customer>,
+    items: Vec<CheckoutItem>,
+    seen_at: PrimitiveDateTime,
+    expected: AppError,
+) {
+    let error = check_out(session, customer, items, seen_at)
+        .await
+        .unwrap_err();
+    assert_eq!(
+        AppError::from_error(&error),
+        Some(expected),
+        "Unexpected error: {error}"
+    );
+}
+
+/// Check out in a separate task, to allow several checkouts to run concurrently.
+fn spawn_checkout(
+    customer: Id<Customer>,
+    session: Session,
+    items: Vec<CheckoutItem>,
+    seen_at: PrimitiveDateTime,
+) -> JoinHandle<bool> {
+    tokio::spawn(async move {
+        check_out(&session, customer, items, seen_at)
+            .await
+            .is_ok_and(|outcome| matches!(outcome, CheckoutOutcome::Placed(_)))
+    })
+}
+
+/// Get the number of orders placed for a product.
+async fn orders(product: Id<Product>) -> i64 {
+    query_scalar!(
+        r#"
+        SELECT COUNT(*) AS "count!"
+        FROM order_lines
+        WHERE product = $1
+        "#,
+        product.get(),
+    )
+    .fetch_one(&*POOL)
+    .await
+    .unwrap()
+}
+
+#[test]
+fn checkout_places_order() {
+    run(async {
+        let (vendor, _) = vendor().await;
+        let product = product(vendor, Decimal::new(1250, 2), 5).await;
+        let (customer, session) = customer().await;
+
+        let (items, seen_at) = fill_cart(customer, &session, product, 2).await;
+        assert_eq!(items[0].expected_price, Decimal::new(2500, 2));
+        let id = placed(check_out(&session, customer, items, seen_at).await.unwrap());
+
+        assert_eq!(stock(product).await, 3);
+        assert!(
+            session
+                .call(cart_counts(customer))
+                .await
+                .unwrap()
+                .is_empty()
+        );
+        let orders = session
+            .call(customer_orders(customer, 10, 0))
+            .await
+            .unwrap();
+        let [order] = &*orders else {
+            panic!("Expected exactly one order, found {}.", orders.len());
+        };
+        assert_eq!(order.id, id);
+        assert_eq!(order.status, OrderStatus::Pending);
+        assert_eq!(order.total, Decimal::new(2500, 2));
+        let [purchase] = &*order.purchases else {
+            panic!("Expected exactly one purchase.");
+        };
+        assert_eq!(purchase.paid, Decimal::new(2500, 2));
+        assert_eq!(purchase.number.get(), 2);
+        assert_eq!(purchase.unit_price, Decimal::new(1250, 2));
+        assert_eq!(purchase.deal, None);
+    });
+}
+
+#[test]
+fn orders_record_price_and_deal() {
+    run(async {
+        let (vendor, vendor_session) = vendor().await;
+        let discounted = product(vendor, Decimal::TEN, 10).await;
+        let take_3_pay_for_2 = Deal::from_repr(None, Some(3), Some(2), Decimal::TEN).unwrap();
+        let offer = special_offer(discounted, take_3_pay_for_2, None).await;
+        let regular = product(vendor, Decimal::ONE, 10).await;
+        let (customer, session) = customer().await;
+
+        let _cart = fill_cart(customer, &session, discounted, 3).await;
+        let (items, seen_at) = fill_cart(customer, &session, regular, 4).await;
+        let first = placed(check_out(&session, customer, items, seen_at).await.unwrap());
+        // Prices may change after the order is placed without affecting it.
+        vendor_session
+            .call(delete_special_offer(offer))
+            .await
+            .unwrap();
+        vendor_session
+            .call(set_price(regular, Decimal::TWO))
+            .await
+            .unwrap();
+        let (items, seen_at) = fill_cart(customer, &session, regular, 1).await;
+        let second = placed(check_out(&session, customer, items, seen_at).await.unwrap());
+        assert_ne!(first, second);
+
+        let orders = session
+            .call(customer_orders(customer, 10, 0))
+            .await
+            .unwrap();
+        let [latest, earliest] = &*orders else {
+            panic!("Expected exactly two orders, found {}.", orders.len());
+        };
+        assert_eq!((latest.id, earliest.id), (second, first));
+        assert_eq!(latest.total, Decimal::TWO);
+        assert_eq!(earliest.total, Decimal::from(24));
+
+        let mut purchases = earliest
+            .purchases
+            .iter()
+            .map(|purchase| {
+                (
+                    purchase.unit_price,
+                    purchase.paid,
+                    purchase.deal,
+                    purchase.product_changed,
+                )
+            })
+            .collect::<Vec<_>>();
+        purchases.sort_by_key(|&(unit_price, ..)| unit_price);
+        assert_eq!(
+            purchases,
+            [
+                (Decimal::ONE, Decimal::from(4), None, true),
+                (
+                    Decimal::TEN,
+                    Decimal::from(20),
+                    Some(take_3_pay_for_2),
+                    false
+                ),
+            ]
+        );
+    });
+}
+
+#[test]
+fn cart_quote_matches_checkout() {
+    run(async {
+        let (vendor, _) = vendor().await;
+        let batch = product(vendor, Decimal::TEN, 10).await;
+        let take_3_pay_for_2 = Deal::from_repr(None, Some(3), Some(2), Decimal::TEN).unwrap();
+        let _offer = special_offer(batch, take_3_pay_for_2, Some(1)).await;
+        let discount = product(vendor, Decimal::TEN, 10).await;
+        let new_price = Deal::from_repr(Some(Decimal::from(8)), None, None, Decimal::TEN).unwrap();
+        let _offer = special_offer(discount, new_price, None).await;
+        let regular = product(vendor, Decimal::new(125, 2), 10).await;
+        let (customer, session) = customer().await;
+
+        let _cart = fill_cart(customer, &session, batch, 7).await;
+        let _cart = fill_cart(customer, &session, discount, 2).await;
+        let _cart = fill_cart(customer, &session, regular, 2).await;
+        let quote = session.call(cart_quote(customer)).await.unwrap();
+        let mut lines = quote
+            .lines
+            .iter()
+            .map(|line| {
+                (
+                    line.product.id,
+                    line.price,
+                    line.offer_uses,
+                    line.remaining_uses,
+                )
+            })
+            .collect::<Vec<_>>();
+        lines.sort_by_key(|&(_, price, ..)| price);
+        assert_eq!(
+            lines,
+            [
+                (regular, Decimal::new(250, 2), 0, None),
+                (discount, Decimal::from(16), 2, None),
+                (batch, Decimal::from(60), 1, Some(0)),
+            ]
+        );
+        assert_eq!(quote.subtotal, Decimal::new(9250, 2));
+        assert_eq!(quote.discount, Decimal::from(14));
+        assert_eq!(quote.total, Decimal::new(7850, 2));
+
+        let order = placed(
+            check_out(&session, customer, quote.items(), quote.seen_at)
+                .await
+                .unwrap(),
+        );
+        let orders = session
+            .call(customer_orders(customer, 10, 0))
+            .await
+            .unwrap();
+        assert_eq!(orders[0].id, order);
+        assert_eq!(orders[0].total, quote.total);
+    });
+}
+
+#[test]
+fn special_offer_applies_up_to_limit() {
+    run(async {
+        let (vendor, _) = vendor().await;
+        let product = product(vendor, Decimal::TEN, 20).await;
+        let take_3_pay_for_2 = Deal::from_repr(None, Some(3), Some(2), Decimal::TEN).unwrap();
+        let _offer = special_offer(product, take_3_pay_for_2, Some(1)).await;
+        let (customer, session) = customer().await;
+
+        let (items, seen_at) = fill_cart(customer, &session, product, 7).await;
+        assert_eq!(items[0].expected_price, Decimal::from(60));
+        let _order = placed(check_out(&session, customer, items, seen_at).await.unwrap());
+
+        // The limit has been reached, so checking out with the discount applied again fails.
+        let (items, seen_at) = fill_cart(customer, &session, product, 3).await;
+        assert_eq!(items[0].expected_price, Decimal::from(30));
+        let discounted = items
+            .iter()
+            .map(|&item| CheckoutItem {
+                expected_price: Decimal::from(20),
+                ..item
+            })
+            .collect();
+        let (line, _) = stale(
+            &session,
+            customer,
+            discounted,
+            seen_at,
+            &[StaleReason::OfferUsedUp],
+        )
+        .await;
+        assert_eq!(line.current.unwrap().special_offer_remaining_uses, Some(0));
+        let _order = placed(check_out(&session, customer, items, seen_at).await.unwrap());
+        assert_eq!(stock(product).await, 10);
+    });
+}
+
+#[test]
+fn members_and_everyone_get_their_best_offer() {
+    run(async {
+        let (vendor, vendor_session) = vendor().await;
+        let product = product(vendor, Decimal::TEN, 20).await;
+        let public = special_offer(
+            product,
+            Deal::from_repr(Some(Decimal::from(9)), None, None, Decimal::TEN).unwrap(),
+            None,
+        )
+        .await;
+        let members = special_offer(
+            product,
+            Deal::from_repr(Some(Decimal::from(7)), None, None, Decimal::TEN).unwrap(),
+            None,
+        )
+        .await;
+        vendor_session
+            .call(set_special_offer_members_only(members, true))
+            .await
+            .unwrap();
+        let (member, member_session) = customer().await;
+        member_session
+            .call(join_membership(member, None))
+            .await
+            .unwrap();
+        let (customer, session) = customer().await;
+
+        let info = session.call(product_info(None, product)).await.unwrap();
+        assert_eq!(info.special_offer_id, Some(public));
+        let info = member_session
+            .call(product_info(Some(member), product))
+            .await
+            .unwrap();
+        assert_eq!(info.special_offer_id, Some(members));
+
+        let (items, seen_at) = fill_cart(customer, &session, product, 2).await;
+        assert_eq!(items[0].special_offer, Some(public));
+        assert_eq!(items[0].expected_price, Decimal::from(18));
+        let members_price = vec![CheckoutItem {
+            special_offer: Some(members),
+            expected_price: Decimal::from(14),
+            ..items[0]
+        }];
+        let _line = stale(
+            &session,
+            customer,
+            members_price,
+            seen_at,
+            &[StaleReason::NotEligible],
+        )
+        .await;
+
+        let (items, seen_at) = fill_cart(member, &member_session, product, 2).await;
+        assert_eq!(items[0].special_offer, Some(members));
+        assert_eq!(items[0].expected_price, Decimal::from(14));
+        let _order = placed(
+            check_out(&member_session, member, items, seen_at)
+                .await
+                .unwrap(),
+        );
+    });
+}
+
+#[test]
+fn used_up_offer_gives_way_to_next_best() {
+    run(async {
+        let (vendor, _) = vendor().await;
+        let product = product(vendor, Decimal::TEN, 20).await;
+        let sample = special_offer(product, Deal::free(), Some(1)).await;
+        let sale = special_offer(
+            product,
+            Deal::from_repr(Some(Decimal::from(9)), None, None, Decimal::TEN).unwrap(),
+            None,
+        )
+        .await;
+        let (customer, session) = customer().await;
+
+        let (items, seen_at) = fill_cart(customer, &session, product, 1).await;
+        assert_eq!(items[0].special_offer, Some(sample));
+        assert_eq!(items[0].expected_price, Decimal::ZERO);
+        let _order = placed(
+            check_out(&session, customer, items.clone(), seen_at)
+                .await
+                .unwrap(),
+        );
+
+        // As if checking out the same cart twice at once.
+        let (line, seen_at) = stale(
+            &session,
+            customer,
+            items,
+            seen_at,
+            &[StaleReason::OfferUsedUp],
+        )
+        .await;
+        let current = line.current.unwrap();
+        assert_eq!(current.special_offer_id, Some(sale));
+        let _order = placed(
+            check_out(
+                &session,
+                customer,
+                vec![CheckoutItem::from(&current)],
+                seen_at,
+            )
+            .await
+            .unwrap(),
+        );
+        assert_eq!(orders(product).await, 2);
+    });
+}
+
+#[test]
+fn checkout_rejects_changed_product() {
+    run(async {
+        let (vendor, vendor_session) = vendor().await;
+        let product = product(vendor, Decimal::TEN, 5).await;
+        let (customer, session) = customer().await;
+
+        let (items, seen_at) = fill_cart(customer, &session, product, 1).await;
+        vendor_session
+            .call(set_price(product, Decimal::ONE))
+            .await
+            .unwrap();
+        let (line, new_seen_at) = stale(
+            &session,
+            customer,
+            items,
+            seen_at,
+            &[StaleReason::ProductChanged],
+        )
+        .await;
+        assert_eq!(line.current.as_ref().unwrap().price, Decimal::ONE);
+
+        assert_eq!(stock(product).await, 5);
+        assert_eq!(orders(product).await, 0);
+        assert_eq!(session.call(cart_counts(customer)).await.unwrap().len(), 1);
+
+        // Accepting the new price allows checking out without reloading the cart.
+        let (cart, _) = session.call(cart_products(customer)).await.unwrap();
+        let accepted = apply_stale_lines(&cart, &[line]);
+        let items = accepted.iter().map(CheckoutItem::from).collect();
+        let _order = placed(
+            check_out(&session, customer, items, new_seen_at)
+                .await
+                .unwrap(),
+        );
+        assert_eq!(stock(product).await, 4);
+    });
+}
+
+#[test]
+fn checkout_rejects_removed_special_offer() {
+    run(async {
+        let (vendor, vendor_session) = vendor().await;
+        let product = product(vendor, Decimal::TEN, 5).await;
+        let offer = special_offer(product, Deal::free(), None).await;
+        let (customer, session) = customer().await;
+
+        let (items, seen_at) = fill_cart(customer, &session, product, 1).await;
+        assert_eq!(items[0].special_offer, Some(offer));
+        vendor_session
+            .call(delete_special_offer(offer))
+            .await
+            .unwrap();
+        let (line, _) = stale(
+            &session,
+            customer,
+            items,
+            seen_at,
+            &[StaleReason::OfferExpired],
+        )
+        .await;
+        assert_eq!(line.current.unwrap().special_offer_deal, None);
+        assert_eq!(stock(product).await, 5);
+    });
+}
+
+#[test]
+fn checkout_rejects_offers_not_given_to_customer() {
+    run(async {
+        let (vendor, _) = vendor().await;
+        let other = product(vendor, Decimal::TEN, 5).await;
+        let free = special_offer(other, Deal::free(), None).await;
+        let product = product(vendor, Decimal::TEN, 5).await;
+        let sale = special_offer(
+            product,
+            Deal::from_repr(Some(Decimal::from(8)), None, None, Decimal::TEN).unwrap(),
+            None,
+        )
+        .await;
+        let worse = special_offer(
+            product,
+            Deal::from_repr(Some(Decimal::from(9)), None, None, Decimal::TEN).unwrap(),
+            None,
+        )
+        .await;
+        let (customer, session) = customer().await;
+
+        let (items, seen_at) = fill_cart(customer, &session, product, 1).await;
+        assert_eq!(items[0].special_offer, Some(sale));
+        let other_product = vec![CheckoutItem {
+            special_offer: Some(free),
+            expected_price: Decimal::ZERO,
+            ..items[0]
+        }];
+        let _line = stale(
+            &session,
+            customer,
+            other_product,
+            seen_at,
+            &[StaleReason::OfferExpired],
+        )
+        .await;
+        let not_best = vec![CheckoutItem {
+            special_offer: Some(worse),
+            expected_price: Decimal::from(9),
+            ..items[0]
+        }];
+        let _line = stale(
+            &session,
+            customer,
+            not_best,
+            seen_at,
+            &[StaleReason::OfferUsedUp],
+        )
+        .await;
+        assert_eq!(stock(product).await, 5);
+        assert_eq!(orders(product).await, 0);
+
+        let _order = placed(check_out(&session, customer, items, seen_at).await.unwrap());
+    });
+}
+
+#[test]
+fn checkout_rejects_insufficient_stock() {
+    run(async {
+        let (vendor, _) = vendor().await;
+        let product = product(vendor, Decimal::TEN, 1).await;
+        let (customer, session) = customer().await;
+
+        let (items, seen_at) = fill_cart(customer, &session, product, 2).await;
+        let (line, new_seen_at) = stale(&session, customer, items, seen_at, &[]).await;
+        assert!(line.out_of_stock());
+        assert_eq!(line.current.as_ref().unwrap().in_stock, 1);
+        assert_eq!(stock(product).await, 1);
+        assert_eq!(orders(product).await, 0);
+
+        // Accepting lowers the number of units to what is in stock.
+        let (cart, _) = session.call(cart_products(customer)).await.unwrap();
+        let accepted = apply_stale_lines(&cart, &[line]);
+        assert_eq!(accepted[0].count.get(), 1);
+        let items = accepted.iter().map(CheckoutItem::from).collect();
+        let _order = placed(
+            check_out(&session, customer, items, new_seen_at)
+                .await
+                .unwrap(),
+        );
+        assert_eq!(stock(product).await, 0);
+    });
+}
+
+#[test]
+fn checkout_rejects_future_timestamp() {
+    run(async {
+        let (vendor, _) = vendor().await;
+        let product = product(vendor, Decimal::TEN, 1).await;
+        let (customer, session) = customer().await;
+
+        let (items, _) = fill_cart(customer, &session, product, 1).await;
+        let seen_at = now().await + Duration::hours(1);
+        reject(
+            &session,
+            customer,
+            items,
+            seen_at,
+            AppError::Invalid {
+                constraint: "nonfuture_timestamp_check".into(),
+            },
+        )
+        .await;
+    });
+}
+
+#[test]
+fn concurrent_checkouts_do_not_oversell() {
+    run(async {
+        let (vendor, _) = vendor().await;
+        let product = product(vendor, Decimal::TEN, 1).await;
+
+        let mut checkouts = Vec::new();
+        for _ in 0..4 {
+            let (customer, session) = customer().await;
+            let (items, seen_at) = fill_cart(customer, &session, product, 1).await;
+            checkouts.push((customer, session, items, seen_at));
+        }
+        let handles = checkouts
+            .into_iter()
+            .map(|(customer, session, items, seen_at)| {
+                spawn_checkout(customer, session, items, seen_at)
+            })
+            .collect::<Vec<_>>();
+        let mut successes = 0;
+        for handle in handles {
+            successes += u32::from(handle.await.unwrap());
+        }
+
+        assert_eq!(successes, 1);
+        assert_eq!(stock(product).await, 0);
+        assert_eq!(orders(product).await, 1);
+    });
+}
+
+#[test]
+fn concurrent_checkouts_respect_special_offer_limit() {
+    run(async {
+        let (vendor, _) = vendor().await;
+        let product = product(vendor, Decimal::TEN, 10).await;
+        let half_price = Deal::from_repr(Some(Decimal::from(5)), None, None, Decimal::TEN).unwrap();
+        let _offer = special_offer(product, half_price, Some(1)).await;
+        let (customer, session) = customer().await;
+
+        let (items, seen_at) = fill_cart(customer, &session, product, 1).await;
+        assert_eq!(items[0].expected_price, Decimal::from(5));
+        let handles = [
+            spawn_checkout(
+                customer,
+                Session::start(customer.get().into()).await,
+                items.clone(),
+                seen_at,
+            ),
+            spawn_checkout(customer, session, items, seen_at),
+        ];
+        let mut successes = 0;
+        for handle in handles {
+            successes += u32::from(handle.await.unwrap());
+        }
+
+        assert_eq!(successes, 1);
+        assert_eq!(stock(product).await, 9);
+        assert_eq!(orders(product).await, 1);
+    });
+}
+
+#[test]
+fn checkout_removes_earliest_expiries() {
+    run(async {
+        let (vendor, vendor_session) = vendor().await;
+        let product = product(vendor, Decimal::TEN, 0).await;
+        let today = now().await.date();
+        let soon = today + Duration::days(1);
+        let later = today + Duration::days(10);
+        for (number, expiry) in [(2, soon), (3, later)] {
+            let number = NonZeroU32::new(number).unwrap();
+            let _stock = vendor_session
+                .call(add_stock(product, number, Some(expiry)))
+                .await
+                .unwrap();
+        }
+        let (customer, session) = customer().await;
+
+        let (items, seen_at) = fill_cart(customer, &session, product, 3).await;
+        let _order = placed(check_out(&session, customer, items, seen_at).await.unwrap());
+
+        let expiries = query!(
+            r#"
+            SELECT expiry, number::INT AS "number!"
+            FROM expiries
+            WHERE product = $1
+            "#,
+            product.get(),
+        )
+        .fetch_all(&*POOL)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|row| (row.expiry, row.number))
+        .collect::<Vec<_>>();
+        assert_eq!(expiries, [(later, 2)]);
+    });
+}
+
+#[test]
+fn order_parties_advance_status() {
+    run(async {
+        let (vendor, vendor_session) = vendor().await;
+        let product = product(vendor, Decimal::TEN, 1).await;
+        let (customer, session) = customer().await;
+
+        let (items, seen_at) = fill_cart(customer, &session, product, 1).await;
+        let order = placed(check_out(&session, customer, items, seen_at).await.unwrap());
+
+        let error = session
+            .call(set_status(order, OrderStatus::Shipped))
+            .await
+            .unwrap_err();
+        assert_eq!(AuthError::from_error(&error), Some(AuthError::Forbidden));
+        vendor_session
+            .call(set_status(order, OrderStatus::Shipped))
+            .await
+            .unwrap();
+        session
+            .call(set_status(order, OrderStatus::Received))
+            .await
+            .unwrap();
+    });
+}
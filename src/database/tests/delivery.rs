@@ -0,0 +1,252 @@
+//! Delivery addresses, shipping methods and shipping fees at checkout.
+
+use crate::database::{
+    Amount, AppError, AuthError, Customer, Id, POOL, Product, QueryResultExt, StaleReason,
+    cart::{CheckoutItem, checkout},
+    delivery::{
+        PostalAddress, Shipment, ShippingRate, create_address, create_shipping_method,
+        customer_addresses, set_address, set_shipping_rate, shipping_methods, shipping_options,
+    },
+    products::{customer_orders, vendor_orders},
+    tests::{
+        Session,
+        checkout::{card, delivery, fill_cart, placed},
+        customer, product, run, vendor,
+    },
+};
+use rust_decimal::Decimal;
+use sqlx::query;
+use time::PrimitiveDateTime;
+
+/// Make each unit of `product` weigh `grams` grams.
+async fn weigh(product: Id<Product>, grams: i64) {
+    query!(
+        "
+        UPDATE products
+        SET amount_per_unit = $2::DECIMAL, measurement_unit = 'g'
+        WHERE id = $1
+        ",
+        product.get(),
+        Decimal::from(grams),
+    )
+    .execute(&*POOL)
+    .await
+    .map(QueryResultExt::expect_one)
+    .unwrap();
+}
+
+/// Check out with the first address of `customer` and `shipments`, expecting to be rejected with
+/// `expected`.
+async fn reject(
+    session: &Session,
+    customer: Id<Customer>,
+    (items, seen_at): (Vec<CheckoutItem>, PrimitiveDateTime),
+    shipments: Vec<Shipment>,
+    expected: AppError,
+) {
+    let (address, _) = delivery(session, customer, items.clone()).await;
+    let error = session
+        .call(checkout(
+            customer,
+            items,
+            seen_at,
+            address,
+            shipments,
+            None,
+            card(),
+        ))
+        .await
+        .unwrap_err();
+    assert_eq!(
+        AppError::from_error(&error),
+        Some(expected),
+        "Unexpected error: {error}"
+    );
+}
+
+#[test]
+fn weight_based_fee_is_charged_and_kept_with_order() {
+    run(async {
+        let (vendor, vendor_session) = vendor().await;
+        let product = product(vendor, Decimal::TEN, 5).await;
+        weigh(product, 500).await;
+        let rate = ShippingRate::ByWeight {
+            fee: Decimal::from(29),
+            fee_per_step: Decimal::TEN,
+            step: Box::new(Amount::with_unit(Decimal::ONE, "kg".into())),
+        };
+        let method = vendor_session
+            .call(create_shipping_method(vendor, "Paket".into(), rate))
+            .await
+            .unwrap();
+        let (customer, session) = customer().await;
+
+        // 1.5 kg is two started steps.
+        let (items, seen_at) = fill_cart(customer, &session, product, 3).await;
+        let options = session.call(shipping_options(items.clone())).await.unwrap();
+        let fees: Vec<_> = options.iter().map(|o| (o.method.id, o.fee)).collect();
+        assert_eq!(fees[1], (method, Decimal::from(49)));
+
+        let (address, _) = delivery(&session, customer, items.clone()).await;
+        let order = placed(
+            session
+                .call(checkout(
+                    customer,
+                    items,
+                    seen_at,
+                    address,
+                    vec![Shipment::from(&options[1])],
+                    None,
+                    card(),
+                ))
+                .await
+                .unwrap(),
+        );
+
+        // Later changes to the method do not affect the order.
+        vendor_session
+            .call(set_shipping_rate(
+                method,
+                ShippingRate::Flat { fee: Decimal::ONE },
+            ))
+            .await
+            .unwrap();
+        let orders = session
+            .call(customer_orders(customer, 10, 0))
+            .await
+            .unwrap();
+        assert_eq!(orders[0].id, order);
+        assert_eq!(orders[0].total, Decimal::from(30));
+        assert_eq!(orders[0].shipping_fee, Decimal::from(49));
+        let vendor_orders = vendor_session
+            .call(vendor_orders(vendor, 10, 0))
+            .await
+            .unwrap();
+        assert_eq!(vendor_orders[0].id, order);
+        assert_eq!(vendor_orders[0].shipping_method.as_deref(), Some("Paket"));
+        assert_eq!(vendor_orders[0].shipping_fee, Decimal::from(49));
+        assert_eq!(
+            vendor_orders[0].address.as_ref().map(|a| &*a.recipient),
+            Some("Kund")
+        );
+    });
+}
+
+#[test]
+fn checkout_requires_one_shipment_per_vendor() {
+    run(async {
+        let (other_vendor, other_session) = vendor().await;
+        let (vendor, _) = vendor().await;
+        let product = product(vendor, Decimal::TEN, 5).await;
+        let (customer, session) = customer().await;
+        let invalid = AppError::Invalid {
+            constraint: "one_shipment_per_vendor".into(),
+        };
+
+        let cart = fill_cart(customer, &session, product, 1).await;
+        let (_, shipments) = delivery(&session, customer, cart.0.clone()).await;
+        reject(
+            &session,
+            customer,
+            cart.clone(),
+            Vec::new(),
+            invalid.clone(),
+        )
+        .await;
+
+        let doubled = shipments.iter().chain(&shipments).copied().collect();
+        reject(&session, customer, cart.clone(), doubled, invalid.clone()).await;
+
+        let other_methods = other_session
+            .call(shipping_methods(other_vendor))
+            .await
+            .unwrap();
+        let foreign = vec![Shipment {
+            method: other_methods[0].id,
+            expected_fee: Decimal::ZERO,
+        }];
+        reject(&session, customer, cart, foreign, invalid).await;
+    });
+}
+
+#[test]
+fn changed_shipping_fee_is_stale() {
+    run(async {
+        let (vendor, vendor_session) = vendor().await;
+        let product = product(vendor, Decimal::TEN, 5).await;
+        let (customer, session) = customer().await;
+
+        let cart = fill_cart(customer, &session, product, 1).await;
+        let (_, shipments) = delivery(&session, customer, cart.0.clone()).await;
+        vendor_session
+            .call(set_shipping_rate(
+                shipments[0].method,
+                ShippingRate::Flat {
+                    fee: Decimal::from(50),
+                },
+            ))
+            .await
+            .unwrap();
+        reject(
+            &session,
+            customer,
+            cart,
+            shipments,
+            AppError::StaleCart {
+                reason: StaleReason::ShippingChanged,
+            },
+        )
+        .await;
+    });
+}
+
+#[test]
+fn addresses_belong_to_their_customer() {
+    run(async {
+        let (vendor, _) = vendor().await;
+        let product = product(vendor, Decimal::TEN, 5).await;
+        let (other, other_session) = customer().await;
+        let (customer, session) = customer().await;
+        let address = PostalAddress {
+            recipient: "Annan".into(),
+            street: "Vägen 2".into(),
+            postal_code: "543 21".into(),
+            city: "Byn".into(),
+            country: "Sverige".into(),
+        };
+        let foreign = other_session
+            .call(create_address(other, address.clone()))
+            .await
+            .unwrap();
+        assert_eq!(
+            other_session
+                .call(customer_addresses(other))
+                .await
+                .unwrap()
+                .len(),
+            2
+        );
+
+        let error = session
+            .call(set_address(foreign, address))
+            .await
+            .unwrap_err();
+        assert_eq!(AuthError::from_error(&error), Some(AuthError::Forbidden));
+
+        let (items, seen_at) = fill_cart(customer, &session, product, 1).await;
+        let (_, shipments) = delivery(&session, customer, items.clone()).await;
+        let error = session
+            .call(checkout(
+                customer,
+                items,
+                seen_at,
+                foreign,
+                shipments,
+                None,
+                card(),
+            ))
+            .await
+            .unwrap_err();
+        assert_eq!(AppError::from_error(&error), Some(AppError::NotFound));
+    });
+}
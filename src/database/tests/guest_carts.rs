@@ -0,0 +1,204 @@
+//! Guest carts, merging them into customers' carts and deleting them.
+
+use crate::database::{
+    AppError, Customer, Id, LoginId, POOL, Product, QueryResultExt,
+    cart::{
+        MergePolicy, cart_counts, guest_cart_products, merge_into, set_in_guest_cart,
+        set_in_shopping_cart,
+    },
+    log_in, login_info,
+    tests::{
+        Session,
+        auth::{log_in_as, register},
+        call_from, customer, product, run, vendor,
+    },
+};
+use http::header::SET_COOKIE;
+use rust_decimal::Decimal;
+use sqlx::{query, query_scalar};
+
+/// Put `number` units of `product` in a new guest cart, returning a session identified by its
+/// cookie.
+async fn guest(product: Id<Product>, number: u32) -> Session {
+    let response = call_from("198.51.100.1", set_in_guest_cart(product, number))
+        .await
+        .unwrap();
+    let cookie = response.headers()[SET_COOKIE].to_str().unwrap();
+    Session {
+        cookie: cookie.split(';').next().unwrap().into(),
+    }
+}
+
+/// Get the ID of the guest cart identified by the cookie of `guest`, if it is stored.
+async fn stored_cart(guest: &Session) -> Option<i32> {
+    let (_, token) = guest.cookie.split_once('=').unwrap();
+    query_scalar!(
+        "SELECT id FROM guest_carts WHERE token_hash = sha256(decode($1, 'hex'))",
+        token,
+    )
+    .fetch_optional(&*POOL)
+    .await
+    .unwrap()
+}
+
+/// Get the number of units of each product in a customer's cart, ordered by product.
+async fn counts(customer: Id<Customer>, session: &Session) -> Vec<(Id<Product>, u32)> {
+    let mut counts = session
+        .call(cart_counts(customer))
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|(product, count)| (product, count.get()))
+        .collect::<Vec<_>>();
+    counts.sort_unstable_by_key(|&(product, _)| product.get());
+    counts
+}
+
+#[test]
+fn guest_carts_are_merged_on_login() {
+    run(async {
+        let (vendor, _) = vendor().await;
+        let first = product(vendor, Decimal::TEN, 10).await;
+        let second = product(vendor, Decimal::ONE, 10).await;
+
+        let guest = guest(first, 2).await;
+        let response = guest.call(set_in_guest_cart(second, 1)).await.unwrap();
+        assert!(
+            !response.headers().contains_key(SET_COOKIE),
+            "Issued a second guest cart."
+        );
+        let response = guest.call(set_in_guest_cart(second, 3)).await.unwrap();
+        assert!(!response.headers().contains_key(SET_COOKIE));
+        let cart = guest.call(guest_cart_products()).await.unwrap();
+        let contents = cart
+            .iter()
+            .map(|product| (product.id, product.count.get()))
+            .collect::<Vec<_>>();
+        assert_eq!(contents, [(first, 2), (second, 3)]);
+        assert!(guest_cart_products().await.unwrap().is_empty());
+
+        let (username, _) = register("hunter2").await;
+        let session = log_in_as(&username, "hunter2").await;
+        let Some(LoginId::Customer(customer)) = session
+            .call(login_info())
+            .await
+            .unwrap()
+            .map(|login| login.id)
+        else {
+            panic!("Registered user is not a customer.");
+        };
+        session
+            .call(set_in_shopping_cart(customer, first, 1))
+            .await
+            .unwrap();
+
+        let response = guest
+            .call(log_in(username, "hunter2".into()))
+            .await
+            .unwrap();
+        assert_eq!(counts(customer, &session).await, [(first, 3), (second, 3)]);
+        assert!(guest.call(guest_cart_products()).await.unwrap().is_empty());
+        assert_eq!(stored_cart(&guest).await, None);
+        assert!(
+            response
+                .headers()
+                .get_all(SET_COOKIE)
+                .iter()
+                .any(|cookie| cookie.to_str().unwrap().starts_with("guest_cart=;")),
+            "Kept the cookie of the merged guest cart."
+        );
+    });
+}
+
+#[test]
+fn merge_policies_choose_numbers() {
+    run(async {
+        let (vendor, _) = vendor().await;
+        let first = product(vendor, Decimal::TEN, 10).await;
+        let second = product(vendor, Decimal::ONE, 10).await;
+
+        for (policy, expected) in [
+            (MergePolicy::Sum, [(first, 8), (second, 5)]),
+            (MergePolicy::Max, [(first, 5), (second, 4)]),
+            (MergePolicy::PreferLatest, [(first, 3), (second, 4)]),
+        ] {
+            // The guest cart is more recent for `first`, and the customer's cart for `second`.
+            let (customer, session) = customer().await;
+            session
+                .call(set_in_shopping_cart(customer, first, 5))
+                .await
+                .unwrap();
+            let guest = guest(first, 3).await;
+            let _response = guest.call(set_in_guest_cart(second, 1)).await.unwrap();
+            session
+                .call(set_in_shopping_cart(customer, second, 4))
+                .await
+                .unwrap();
+
+            let (_, token) = guest.cookie.split_once('=').unwrap();
+            merge_into(&mut POOL.acquire().await.unwrap(), customer, token, policy)
+                .await
+                .unwrap();
+            assert_eq!(counts(customer, &session).await, expected, "{policy:?}");
+            assert!(guest.call(guest_cart_products()).await.unwrap().is_empty());
+        }
+    });
+}
+
+#[test]
+fn expired_and_empty_guest_carts_are_deleted() {
+    run(async {
+        let (vendor, _) = vendor().await;
+        let product = product(vendor, Decimal::TEN, 10).await;
+        let expired = guest(product, 1).await;
+        let emptied = guest(product, 1).await;
+        let active = guest(product, 1).await;
+
+        let expired_cart = stored_cart(&expired).await.unwrap();
+        query!(
+            "
+            UPDATE guest_carts
+            SET created_at = created_at - INTERVAL '60 days',
+                expires_at = CURRENT_TIMESTAMP - INTERVAL '1 day'
+            WHERE id = $1
+            ",
+            expired_cart,
+        )
+        .execute(&*POOL)
+        .await
+        .map(QueryResultExt::expect_one)
+        .unwrap();
+        let emptied_cart = stored_cart(&emptied).await.unwrap();
+        let _response = emptied.call(set_in_guest_cart(product, 0)).await.unwrap();
+
+        let deleted = query_scalar!(r#"SELECT cart AS "cart!" FROM process_guest_cart_expiries()"#)
+            .fetch_all(&*POOL)
+            .await
+            .unwrap();
+        assert!(deleted.contains(&expired_cart));
+        assert!(deleted.contains(&emptied_cart));
+        assert_eq!(stored_cart(&expired).await, None);
+        assert_eq!(stored_cart(&emptied).await, None);
+        assert!(stored_cart(&active).await.is_some());
+    });
+}
+
+#[test]
+fn guest_cart_changes_are_limited_per_address() {
+    run(async {
+        let (vendor, _) = vendor().await;
+        let product = product(vendor, Decimal::TEN, 10).await;
+        for _ in 0..60 {
+            let _response = call_from("198.51.100.4", set_in_guest_cart(product, 1))
+                .await
+                .unwrap();
+        }
+        let error = call_from("198.51.100.4", set_in_guest_cart(product, 1))
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            AppError::from_error(&error),
+            Some(AppError::RateLimited { .. })
+        ));
+    });
+}
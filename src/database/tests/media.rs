@@ -0,0 +1,208 @@
+//! Image uploads.
+
+use crate::database::{
+    AppError, AuthError, Email, Id, NewUserData, POOL, ProfilePicture, Url, Username, create_user,
+    login_info,
+    media::{MAX_SIZE, MediaError, media_file, media_thumbnail, thumbnail_url, upload_media},
+    products::{add_to_gallery, gallery, product_info, set_thumbnail},
+    tests::{customer, product, run, unique, vendor},
+    users::{set_customer_profile_picture, set_vendor_profile_picture},
+};
+use dioxus::{
+    CapturedError,
+    fullstack::{
+        FileStream,
+        body::{Body, to_bytes},
+        response::Response,
+    },
+};
+use http::header::CONTENT_TYPE;
+use image::{ImageFormat, RgbImage};
+use rust_decimal::Decimal;
+use sqlx::query_scalar;
+use std::io::Cursor;
+
+/// Encode a blank image.
+fn image(width: u32, height: u32, format: ImageFormat) -> Vec<u8> {
+    let mut encoded = Cursor::new(Vec::new());
+    RgbImage::new(width, height)
+        .write_to(&mut encoded, format)
+        .unwrap();
+    encoded.into_inner()
+}
+
+/// Build an upload of `data` as it would be received from a client.
+fn file(data: Vec<u8>, content_type: &str) -> FileStream {
+    FileStream::from_raw(
+        "upload".to_owned(),
+        Some(data.len() as u64),
+        content_type.to_owned(),
+        Body::from(data).into_data_stream(),
+    )
+}
+
+/// Get the type and contents of a response.
+async fn body(response: Response) -> (String, Vec<u8>) {
+    let content_type = response.headers()[CONTENT_TYPE]
+        .to_str()
+        .unwrap()
+        .to_owned();
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    (content_type, body.to_vec())
+}
+
+#[test]
+fn uploads_are_stored_with_thumbnails() {
+    run(async {
+        let (customer, session) = customer().await;
+        let png = image(800, 400, ImageFormat::Png);
+        let media = session
+            .call(upload_media(file(png.clone(), "image/png")))
+            .await
+            .unwrap();
+
+        let (content_type, data) = body(media_file(media.get()).await.unwrap()).await;
+        assert_eq!(content_type, "image/png");
+        assert_eq!(data, png);
+
+        let (content_type, data) = body(media_thumbnail(media.get()).await.unwrap()).await;
+        assert_eq!(content_type, "image/png");
+        let thumbnail = image::load_from_memory(&data).unwrap();
+        assert_eq!((thumbnail.width(), thumbnail.height()), (400, 200));
+        assert_eq!(
+            thumbnail_url(media),
+            Url::from(format!("/media/{media}/thumbnail"))
+        );
+
+        let jpeg = image(100, 100, ImageFormat::Jpeg);
+        let media = session
+            .call(upload_media(file(jpeg, "image/jpeg")))
+            .await
+            .unwrap();
+        let (content_type, _) = body(media_thumbnail(media.get()).await.unwrap()).await;
+        assert_eq!(content_type, "image/jpeg");
+
+        session
+            .call(set_customer_profile_picture(customer, media))
+            .await
+            .unwrap();
+        let login = session.call(login_info()).await.unwrap().unwrap();
+        assert_eq!(
+            login.profile_picture,
+            ProfilePicture::Customer(Some(Url::from(media)))
+        );
+
+        let missing = media_file(-1).await;
+        assert!(missing.is_err(), "Served media that does not exist.");
+    });
+}
+
+#[test]
+fn invalid_uploads_are_rejected() {
+    run(async {
+        let rejection = |error: CapturedError| error.downcast_ref::<MediaError>().copied();
+        let (_, session) = customer().await;
+        let png = image(10, 10, ImageFormat::Png);
+
+        let error = upload_media(file(png.clone(), "image/png"))
+            .await
+            .unwrap_err();
+        assert_eq!(
+            AuthError::from_error(&error),
+            Some(AuthError::Unauthenticated)
+        );
+
+        for (data, content_type) in [
+            (png.clone(), "image/gif"),
+            (png.clone(), "image/jpeg"),
+            (b"GIF89a".to_vec(), "image/png"),
+        ] {
+            let error = session
+                .call(upload_media(file(data, content_type)))
+                .await
+                .unwrap_err();
+            assert_eq!(rejection(error), Some(MediaError::UnsupportedType));
+        }
+
+        let mut truncated = png;
+        truncated.truncate(truncated.len() / 2);
+        let error = session
+            .call(upload_media(file(truncated, "image/png")))
+            .await
+            .unwrap_err();
+        assert_eq!(rejection(error), Some(MediaError::InvalidImage));
+
+        let huge = vec![0; MAX_SIZE as usize + 1];
+        let error = session
+            .call(upload_media(file(huge, "image/png")))
+            .await
+            .unwrap_err();
+        assert_eq!(rejection(error), Some(MediaError::TooLarge));
+    });
+}
+
+#[test]
+fn only_uploaded_media_can_be_set() {
+    run(async {
+        let (vendor, session) = vendor().await;
+        let product = product(vendor, Decimal::TEN, 0).await;
+        let media = session
+            .call(upload_media(file(
+                image(10, 10, ImageFormat::Png),
+                "image/png",
+            )))
+            .await
+            .unwrap();
+        let missing = Id::from(-1);
+
+        let not_found = |error| AppError::from_error(&error) == Some(AppError::NotFound);
+        assert!(not_found(
+            session
+                .call(set_thumbnail(product, missing))
+                .await
+                .unwrap_err()
+        ));
+        assert!(not_found(
+            session
+                .call(add_to_gallery(product, [media, missing].into()))
+                .await
+                .unwrap_err()
+        ));
+        assert!(not_found(
+            session
+                .call(set_vendor_profile_picture(vendor, missing))
+                .await
+                .unwrap_err()
+        ));
+        let username = Username::new(unique("user").into()).unwrap();
+        let email = Email::new(format!("{username}@example.com").into()).unwrap();
+        let data = NewUserData::Vendor {
+            profile_picture: Some(missing),
+            display_name: username.to_string().into(),
+            description: "".into(),
+        };
+        assert!(not_found(
+            create_user(username, email, "hunter2".into(), data)
+                .await
+                .unwrap_err()
+        ));
+        assert!(session.call(gallery(product)).await.unwrap().is_empty());
+
+        session.call(set_thumbnail(product, media)).await.unwrap();
+        session
+            .call(add_to_gallery(product, [media].into()))
+            .await
+            .unwrap();
+        let thumbnail = query_scalar!(
+            r#"SELECT thumbnail::TEXT AS "thumbnail!" FROM products WHERE id = $1"#,
+            product.get(),
+        )
+        .fetch_one(&*POOL)
+        .await
+        .unwrap();
+        // Products are listed with the downscaled image.
+        assert_eq!(Url::from(thumbnail), thumbnail_url(media));
+        let info = session.call(product_info(None, product)).await.unwrap();
+        assert_eq!(*info.gallery, [Url::from(media)]);
+    });
+}
@@ -0,0 +1,254 @@
+//! Joining, paying for, cancelling and expiring memberships.
+
+use crate::database::{
+    AppError, AuthError, Customer, Deal, Id, MembershipTier, POOL, QueryResultExt, StaleReason,
+    cart::{CheckoutItem, CheckoutOutcome},
+    memberships::{
+        cancel_membership, create_membership_tier, join_membership, membership, membership_tiers,
+    },
+    offers::set_special_offer_members_only,
+    payments::{CardNumber, MockProvider, PaymentFailure},
+    tests::{
+        Session, administrator,
+        checkout::{check_out, fill_cart},
+        customer, product, run, special_offer, unique, vendor,
+    },
+};
+use dioxus::prelude::Result;
+use rust_decimal::Decimal;
+use sqlx::{query, query_scalar};
+use time::Date;
+
+/// Create a membership tier of `months` months.
+async fn tier(months: u32) -> Id<MembershipTier> {
+    let (_, session) = administrator().await;
+    session
+        .call(create_membership_tier(
+            unique("Nivå").into(),
+            Decimal::from(99),
+            months,
+        ))
+        .await
+        .unwrap()
+}
+
+/// Get the date `months` months from today.
+async fn months_from_today(months: i32) -> Date {
+    query_scalar!(
+        r#"SELECT (CURRENT_DATE + make_interval(months => $1))::DATE AS "date!""#,
+        months,
+    )
+    .fetch_one(&*POOL)
+    .await
+    .unwrap()
+}
+
+/// Get the tier paid for by `customer` and its renewal date.
+async fn paid(session: &Session, customer: Id<Customer>) -> Option<(Id<MembershipTier>, Date)> {
+    session
+        .call(membership(customer))
+        .await
+        .unwrap()
+        .and_then(|membership| membership.paid)
+        .map(|(tier, until)| (tier.id, until))
+}
+
+/// Pay for `tier` with `card`.
+async fn pay(
+    session: &Session,
+    customer: Id<Customer>,
+    tier: Id<MembershipTier>,
+    card: &str,
+) -> Result<()> {
+    session
+        .call(join_membership(
+            customer,
+            Some((tier, CardNumber::new(card).unwrap())),
+        ))
+        .await
+}
+
+#[test]
+fn free_membership_lasts_until_cancelled() {
+    run(async {
+        let (customer, session) = customer().await;
+        assert_eq!(session.call(membership(customer)).await.unwrap(), None);
+
+        session.call(join_membership(customer, None)).await.unwrap();
+        let joined = session.call(membership(customer)).await.unwrap().unwrap();
+        assert_eq!(joined.paid, None);
+        session.call(join_membership(customer, None)).await.unwrap();
+        assert_eq!(
+            session.call(membership(customer)).await.unwrap(),
+            Some(joined)
+        );
+
+        session.call(cancel_membership(customer)).await.unwrap();
+        assert_eq!(session.call(membership(customer)).await.unwrap(), None);
+    });
+}
+
+#[test]
+fn paying_again_extends_membership() {
+    run(async {
+        let quarterly = tier(3).await;
+        let yearly = tier(12).await;
+        let (customer, session) = customer().await;
+        let tiers = session.call(membership_tiers()).await.unwrap();
+        assert!(
+            tiers
+                .iter()
+                .any(|tier| tier.id == quarterly && tier.months == 3)
+        );
+
+        pay(&session, customer, quarterly, MockProvider::SUCCEEDING_CARD)
+            .await
+            .unwrap();
+        assert_eq!(
+            paid(&session, customer).await,
+            Some((quarterly, months_from_today(3).await))
+        );
+        pay(&session, customer, quarterly, MockProvider::SUCCEEDING_CARD)
+            .await
+            .unwrap();
+        assert_eq!(
+            paid(&session, customer).await,
+            Some((quarterly, months_from_today(6).await))
+        );
+
+        // Another tier replaces the current one.
+        pay(&session, customer, yearly, MockProvider::SUCCEEDING_CARD)
+            .await
+            .unwrap();
+        assert_eq!(
+            paid(&session, customer).await,
+            Some((yearly, months_from_today(12).await))
+        );
+
+        let payments = query_scalar!(
+            r#"SELECT SUM(amount) AS "total!" FROM membership_payments WHERE customer = $1"#,
+            customer.get(),
+        )
+        .fetch_one(&*POOL)
+        .await
+        .unwrap();
+        assert_eq!(payments, Decimal::from(3 * 99));
+    });
+}
+
+#[test]
+fn failed_payment_leaves_membership_unchanged() {
+    run(async {
+        let tier = tier(1).await;
+        let (customer, session) = customer().await;
+
+        for (card, reason) in [
+            (MockProvider::DECLINED_CARD, PaymentFailure::Declined),
+            (MockProvider::UNCAPTURABLE_CARD, PaymentFailure::Declined),
+            (MockProvider::TIMING_OUT_CARD, PaymentFailure::TimedOut),
+        ] {
+            let error = pay(&session, customer, tier, card).await.unwrap_err();
+            assert_eq!(
+                AppError::from_error(&error),
+                Some(AppError::PaymentFailed { reason })
+            );
+            assert_eq!(session.call(membership(customer)).await.unwrap(), None);
+        }
+    });
+}
+
+#[test]
+fn only_administrators_create_tiers() {
+    run(async {
+        let (_, session) = customer().await;
+        let error = session
+            .call(create_membership_tier(
+                unique("Nivå").into(),
+                Decimal::ONE,
+                1,
+            ))
+            .await
+            .unwrap_err();
+        assert_eq!(AuthError::from_error(&error), Some(AuthError::Forbidden));
+    });
+}
+
+#[test]
+fn paid_membership_expires_on_renewal_date() {
+    run(async {
+        let tier = tier(1).await;
+        let (customer, session) = customer().await;
+        pay(&session, customer, tier, MockProvider::SUCCEEDING_CARD)
+            .await
+            .unwrap();
+
+        query!(
+            "UPDATE customers SET member_until = CURRENT_DATE WHERE id = $1",
+            customer.get(),
+        )
+        .execute(&*POOL)
+        .await
+        .map(QueryResultExt::expect_one)
+        .unwrap();
+        let expired = query_scalar!(
+            r#"
+            SELECT customer AS "customer!"
+            FROM process_membership_expiries()
+            WHERE customer = $1
+            "#,
+            customer.get(),
+        )
+        .fetch_all(&*POOL)
+        .await
+        .unwrap();
+        assert_eq!(expired, [customer.get()]);
+        assert_eq!(session.call(membership(customer)).await.unwrap(), None);
+    });
+}
+
+#[test]
+fn ended_membership_gives_no_member_offers() {
+    run(async {
+        let (vendor, vendor_session) = vendor().await;
+        let product = product(vendor, Decimal::TEN, 5).await;
+        let offer = special_offer(product, Deal::free(), None).await;
+        vendor_session
+            .call(set_special_offer_members_only(offer, true))
+            .await
+            .unwrap();
+        let tier = tier(1).await;
+        let (customer, session) = customer().await;
+        pay(&session, customer, tier, MockProvider::SUCCEEDING_CARD)
+            .await
+            .unwrap();
+        let (items, _) = fill_cart(customer, &session, product, 1).await;
+        assert_eq!(items[0].special_offer, Some(offer));
+
+        // Ended, but not yet cleared by `process_membership_expiries`.
+        query!(
+            "UPDATE customers SET member_until = CURRENT_DATE WHERE id = $1",
+            customer.get(),
+        )
+        .execute(&*POOL)
+        .await
+        .map(QueryResultExt::expect_one)
+        .unwrap();
+        assert_eq!(session.call(membership(customer)).await.unwrap(), None);
+
+        let (items, seen_at) = fill_cart(customer, &session, product, 1).await;
+        assert_eq!(items[0].special_offer, None);
+        let member_price = vec![CheckoutItem {
+            special_offer: Some(offer),
+            expected_price: Decimal::ZERO,
+            ..items[0]
+        }];
+        let CheckoutOutcome::Stale { lines, .. } =
+            check_out(&session, customer, member_price, seen_at)
+                .await
+                .unwrap()
+        else {
+            panic!("Checkout with an ended membership was not stale.");
+        };
+        assert_eq!(*lines[0].reasons, [StaleReason::NotEligible]);
+    });
+}
@@ -0,0 +1,249 @@
+//! The order lifecycle.
+
+use crate::database::{
+    AuthError, Customer, Deal, Id, Order, POOL, Product, Vendor,
+    cart::{cart_products, set_in_shopping_cart},
+    products::{InvalidTransition, OrderStatus, order_history, set_status, vendor_orders},
+    tests::{
+        Session,
+        checkout::{check_out, fill_cart, placed},
+        customer, product, run, special_offer, stock, vendor,
+    },
+};
+use rust_decimal::Decimal;
+use sqlx::{Error, query};
+
+/// Check out `number` units of `product`.
+async fn place_order(
+    customer: Id<Customer>,
+    session: &Session,
+    product: Id<Product>,
+    number: u32,
+) -> Id<Order> {
+    let (items, seen_at) = fill_cart(customer, session, product, number).await;
+    placed(check_out(session, customer, items, seen_at).await.unwrap())
+}
+
+/// Get the statuses an order has had, in order.
+async fn history(session: &Session, order: Id<Order>) -> Vec<OrderStatus> {
+    session
+        .call(order_history(order))
+        .await
+        .unwrap()
+        .iter()
+        .map(|change| change.status)
+        .collect()
+}
+
+/// Get the status `vendor` has asked `order` to be set to, if any.
+async fn decision(vendor: Id<Vendor>, session: &Session, order: Id<Order>) -> Option<OrderStatus> {
+    session
+        .call(vendor_orders(vendor, 100, 0))
+        .await
+        .unwrap()
+        .iter()
+        .find(|line| line.id == order)
+        .unwrap()
+        .decision
+}
+
+#[test]
+fn cancelling_restores_stock_and_special_offer_uses() {
+    run(async {
+        let (vendor, _) = vendor().await;
+        let product = product(vendor, Decimal::TEN, 5).await;
+        let half_price = Deal::from_repr(Some(Decimal::from(5)), None, None, Decimal::TEN).unwrap();
+        let _offer = special_offer(product, half_price, Some(1)).await;
+        let (customer, session) = customer().await;
+
+        let order = place_order(customer, &session, product, 2).await;
+        assert_eq!(stock(product).await, 3);
+        session
+            .call(set_status(order, OrderStatus::Cancelled))
+            .await
+            .unwrap();
+
+        assert_eq!(stock(product).await, 5);
+        session
+            .call(set_in_shopping_cart(customer, product, 1))
+            .await
+            .unwrap();
+        let (cart, _) = session.call(cart_products(customer)).await.unwrap();
+        assert_eq!(cart[0].special_offer_remaining_uses, Some(1));
+        assert_eq!(
+            history(&session, order).await,
+            [
+                OrderStatus::AwaitingPayment,
+                OrderStatus::Pending,
+                OrderStatus::Cancelled
+            ]
+        );
+    });
+}
+
+#[test]
+fn returns_require_vendor_approval() {
+    run(async {
+        let (vendor, vendor_session) = vendor().await;
+        let product = product(vendor, Decimal::TEN, 3).await;
+        let (customer, session) = customer().await;
+        let order = place_order(customer, &session, product, 1).await;
+
+        for (session, status) in [
+            (&vendor_session, OrderStatus::Shipped),
+            (&session, OrderStatus::Received),
+            (&session, OrderStatus::ReturnRequested),
+        ] {
+            session.call(set_status(order, status)).await.unwrap();
+        }
+        let error = session
+            .call(set_status(order, OrderStatus::Returned))
+            .await
+            .unwrap_err();
+        assert_eq!(AuthError::from_error(&error), Some(AuthError::Forbidden));
+        assert_eq!(stock(product).await, 2);
+
+        for status in [OrderStatus::Returned, OrderStatus::Refunded] {
+            vendor_session
+                .call(set_status(order, status))
+                .await
+                .unwrap();
+        }
+        assert_eq!(stock(product).await, 3);
+        assert_eq!(
+            history(&vendor_session, order).await,
+            [
+                OrderStatus::AwaitingPayment,
+                OrderStatus::Pending,
+                OrderStatus::Shipped,
+                OrderStatus::Received,
+                OrderStatus::ReturnRequested,
+                OrderStatus::Returned,
+                OrderStatus::Refunded,
+            ]
+        );
+    });
+}
+
+#[test]
+fn invalid_transitions_are_rejected() {
+    run(async {
+        let (vendor, vendor_session) = vendor().await;
+        let product = product(vendor, Decimal::TEN, 3).await;
+        let (customer, session) = customer().await;
+        let order = place_order(customer, &session, product, 1).await;
+
+        let error = vendor_session
+            .call(set_status(order, OrderStatus::Refunded))
+            .await
+            .unwrap_err();
+        assert_eq!(
+            error.downcast_ref::<InvalidTransition>(),
+            Some(&InvalidTransition(OrderStatus::Refunded))
+        );
+
+        vendor_session
+            .call(set_status(order, OrderStatus::Shipped))
+            .await
+            .unwrap();
+        let error = session
+            .call(set_status(order, OrderStatus::Cancelled))
+            .await
+            .unwrap_err();
+        assert_eq!(
+            error.downcast_ref::<InvalidTransition>(),
+            Some(&InvalidTransition(OrderStatus::Cancelled))
+        );
+
+        let result = query!(
+            "UPDATE orders SET status = 'pending' WHERE id = $1",
+            order.get(),
+        )
+        .execute(&*POOL)
+        .await;
+        assert!(matches!(result, Err(Error::Database(_))));
+        assert_eq!(stock(product).await, 2);
+    });
+}
+
+#[test]
+fn vendors_only_set_status_together() {
+    run(async {
+        let (first_vendor, first_session) = vendor().await;
+        let (second_vendor, second_session) = vendor().await;
+        let first = product(first_vendor, Decimal::TEN, 3).await;
+        let second = product(second_vendor, Decimal::ONE, 3).await;
+        let (customer, session) = customer().await;
+        let _cart = fill_cart(customer, &session, first, 1).await;
+        let order = place_order(customer, &session, second, 1).await;
+
+        // One vendor can not ship the products of another.
+        second_session
+            .call(set_status(order, OrderStatus::Shipped))
+            .await
+            .unwrap();
+        assert_eq!(
+            history(&session, order).await.last(),
+            Some(&OrderStatus::Pending)
+        );
+        assert_eq!(
+            decision(second_vendor, &second_session, order).await,
+            Some(OrderStatus::Shipped)
+        );
+        assert_eq!(decision(first_vendor, &first_session, order).await, None);
+        first_session
+            .call(set_status(order, OrderStatus::Shipped))
+            .await
+            .unwrap();
+        assert_eq!(
+            history(&session, order).await.last(),
+            Some(&OrderStatus::Shipped)
+        );
+        assert_eq!(decision(second_vendor, &second_session, order).await, None);
+
+        for status in [OrderStatus::Received, OrderStatus::ReturnRequested] {
+            session.call(set_status(order, status)).await.unwrap();
+        }
+
+        // Nor take them back, and vendors who disagree keep the return requested.
+        second_session
+            .call(set_status(order, OrderStatus::Returned))
+            .await
+            .unwrap();
+        first_session
+            .call(set_status(order, OrderStatus::Received))
+            .await
+            .unwrap();
+        assert_eq!(
+            history(&session, order).await.last(),
+            Some(&OrderStatus::ReturnRequested)
+        );
+        assert_eq!(stock(first).await, 2);
+        assert_eq!(stock(second).await, 2);
+
+        first_session
+            .call(set_status(order, OrderStatus::Returned))
+            .await
+            .unwrap();
+        assert_eq!(stock(first).await, 3);
+        assert_eq!(stock(second).await, 3);
+        for session in [&first_session, &second_session] {
+            session
+                .call(set_status(order, OrderStatus::Refunded))
+                .await
+                .unwrap();
+        }
+        assert_eq!(
+            history(&session, order).await,
+            [
+                OrderStatus::AwaitingPayment,
+                OrderStatus::Pending,
+                OrderStatus::Shipped,
+                OrderStatus::Received,
+                OrderStatus::ReturnRequested,
+                OrderStatus::Returned,
+                OrderStatus::Refunded,
+            ]
+        );
+    });
+}
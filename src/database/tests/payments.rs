@@ -0,0 +1,300 @@
+//! Authorizing, capturing and refunding payments with the mock payment provider.
+
+use crate::database::{
+    AppError, Customer, Id, Order, POOL, Product, QueryResultExt,
+    cart::{CheckoutItemRepr, CheckoutOutcome, cart_counts, checkout},
+    delivery::ShipmentRepr,
+    payments::{CardNumber, MockProvider, PaymentFailure},
+    products::{OrderStatus, customer_orders, set_status},
+    tests::{
+        Session,
+        checkout::{check_out, delivery, fill_cart, placed},
+        customer, product, run, stock, vendor,
+    },
+};
+use dioxus::prelude::Result;
+use rust_decimal::Decimal;
+use sqlx::{query, query_scalar};
+use std::num::NonZeroU32;
+
+/// Check out 2 units of `product`, paying with `card`.
+///
+/// # Errors
+///
+/// Fails if checkout fails.
+async fn pay_with(
+    session: &Session,
+    customer: Id<Customer>,
+    product: Id<Product>,
+    card: &str,
+) -> Result<CheckoutOutcome> {
+    let (items, seen_at) = fill_cart(customer, session, product, 2).await;
+    let (address, shipments) = delivery(session, customer, items.clone()).await;
+    session
+        .call(checkout(
+            customer,
+            items,
+            seen_at,
+            address,
+            shipments,
+            None,
+            CardNumber::new(card).unwrap(),
+        ))
+        .await
+}
+
+/// Place an order of 2 units of `product` without paying for it, as if the request placing it
+/// failed before the payment was authorized.
+async fn unpaid(session: &Session, customer: Id<Customer>, product: Id<Product>) -> Id<Order> {
+    let (items, seen_at) = fill_cart(customer, session, product, 2).await;
+    let (address, shipments) = delivery(session, customer, items.clone()).await;
+    let items = items
+        .into_iter()
+        .map(|item| CheckoutItemRepr::try_from(item).unwrap())
+        .collect::<Box<_>>();
+    let shipments = shipments
+        .into_iter()
+        .map(ShipmentRepr::from)
+        .collect::<Box<_>>();
+    query_scalar!(
+        r#"
+        SELECT checkout($1, $2, ($3::TIMESTAMP)::NONFUTURE_TIMESTAMP, $4, $5, NULL) AS "order!"
+        "#,
+        customer.get(),
+        &items as &[CheckoutItemRepr],
+        seen_at,
+        address.get(),
+        &shipments as &[ShipmentRepr],
+    )
+    .fetch_one(&*POOL)
+    .await
+    .map(Id::from)
+    .unwrap()
+}
+
+/// Get the status of the payment of an order.
+async fn payment_status(order: Id<Order>) -> String {
+    query_scalar!(
+        r#"
+        SELECT status::TEXT AS "status!"
+        FROM payments
+        WHERE order_id = $1
+        "#,
+        order.get(),
+    )
+    .fetch_one(&*POOL)
+    .await
+    .unwrap()
+}
+
+/// Get the current status of the most recent order of `customer`.
+async fn order_status(session: &Session, customer: Id<Customer>) -> OrderStatus {
+    session.call(customer_orders(customer, 1, 0)).await.unwrap()[0].status
+}
+
+#[test]
+fn card_numbers_are_validated() {
+    assert!(CardNumber::new("4242 4242 4242 4242").is_some());
+    assert!(CardNumber::new("4242424242424241").is_none());
+    assert!(CardNumber::new("4242-4242-4242-4242").is_none());
+    assert!(CardNumber::new("42").is_none());
+
+    let card = CardNumber::new(MockProvider::DECLINED_CARD).unwrap();
+    assert_eq!(card.last_digits(), "0002");
+    assert!(!format!("{card:?}").contains(MockProvider::DECLINED_CARD));
+
+    // Card numbers from clients are validated as well.
+    let _error = serde_json::from_str::<CardNumber>(r#""4242424242424241""#).unwrap_err();
+    assert_eq!(
+        serde_json::from_str::<CardNumber>(r#""4242 4242 4242 4242""#).ok(),
+        CardNumber::new("4242424242424242")
+    );
+}
+
+#[test]
+fn failed_authorization_cancels_order_and_restores_cart() {
+    run(async {
+        let (vendor, _) = vendor().await;
+        let product = product(vendor, Decimal::TEN, 5).await;
+        let (customer, session) = customer().await;
+
+        for (card, reason) in [
+            (MockProvider::DECLINED_CARD, PaymentFailure::Declined),
+            (MockProvider::TIMING_OUT_CARD, PaymentFailure::TimedOut),
+        ] {
+            let error = pay_with(&session, customer, product, card)
+                .await
+                .unwrap_err();
+            assert_eq!(
+                AppError::from_error(&error),
+                Some(AppError::PaymentFailed { reason })
+            );
+            assert_eq!(
+                order_status(&session, customer).await,
+                OrderStatus::Cancelled
+            );
+            assert_eq!(stock(product).await, 5);
+            let cart = session.call(cart_counts(customer)).await.unwrap();
+            assert_eq!(cart.get(&product), NonZeroU32::new(2).as_ref());
+        }
+    });
+}
+
+#[test]
+fn unpaid_orders_are_cancelled_in_time() {
+    run(async {
+        let (vendor, _) = vendor().await;
+        let product = product(vendor, Decimal::TEN, 5).await;
+        let (customer, session) = customer().await;
+        let order = unpaid(&session, customer, product).await;
+        assert_eq!(stock(product).await, 3);
+
+        let expired = async || {
+            query_scalar!(r#"SELECT order_id AS "order_id!" FROM process_payment_expiries()"#)
+                .fetch_all(&*POOL)
+                .await
+                .unwrap()
+        };
+        assert!(!expired().await.contains(&order.get()));
+
+        query!(
+            "UPDATE orders SET placed_at = placed_at - INTERVAL '1 hour' WHERE id = $1",
+            order.get(),
+        )
+        .execute(&*POOL)
+        .await
+        .map(QueryResultExt::expect_one)
+        .unwrap();
+        assert!(expired().await.contains(&order.get()));
+        assert_eq!(
+            order_status(&session, customer).await,
+            OrderStatus::Cancelled
+        );
+        assert_eq!(stock(product).await, 5);
+        let cart = session.call(cart_counts(customer)).await.unwrap();
+        assert_eq!(cart.get(&product), NonZeroU32::new(2).as_ref());
+    });
+}
+
+#[test]
+fn unpaid_orders_can_be_cancelled() {
+    run(async {
+        let (vendor, _) = vendor().await;
+        let product = product(vendor, Decimal::TEN, 5).await;
+        let (customer, session) = customer().await;
+        let order = unpaid(&session, customer, product).await;
+
+        session
+            .call(set_status(order, OrderStatus::Cancelled))
+            .await
+            .unwrap();
+        assert_eq!(stock(product).await, 5);
+    });
+}
+
+#[test]
+fn payment_is_captured_when_shipped() {
+    run(async {
+        let (vendor, vendor_session) = vendor().await;
+        let product = product(vendor, Decimal::TEN, 5).await;
+        let (customer, session) = customer().await;
+
+        let order = placed(
+            pay_with(&session, customer, product, MockProvider::SUCCEEDING_CARD)
+                .await
+                .unwrap(),
+        );
+        assert_eq!(order_status(&session, customer).await, OrderStatus::Pending);
+        assert_eq!(payment_status(order).await, "authorized");
+
+        vendor_session
+            .call(set_status(order, OrderStatus::Shipped))
+            .await
+            .unwrap();
+        assert_eq!(payment_status(order).await, "captured");
+    });
+}
+
+#[test]
+fn failed_capture_keeps_order_pending() {
+    run(async {
+        let (vendor, vendor_session) = vendor().await;
+        let product = product(vendor, Decimal::TEN, 5).await;
+        let (customer, session) = customer().await;
+
+        let order = placed(
+            pay_with(&session, customer, product, MockProvider::UNCAPTURABLE_CARD)
+                .await
+                .unwrap(),
+        );
+        let error = vendor_session
+            .call(set_status(order, OrderStatus::Shipped))
+            .await
+            .unwrap_err();
+        assert_eq!(
+            AppError::from_error(&error),
+            Some(AppError::PaymentFailed {
+                reason: PaymentFailure::Declined
+            })
+        );
+        assert_eq!(order_status(&session, customer).await, OrderStatus::Pending);
+        assert_eq!(payment_status(order).await, "authorized");
+    });
+}
+
+#[test]
+fn cancelled_order_is_refunded() {
+    run(async {
+        let (vendor, vendor_session) = vendor().await;
+        let product = product(vendor, Decimal::TEN, 5).await;
+        let (customer, session) = customer().await;
+        let (items, seen_at) = fill_cart(customer, &session, product, 1).await;
+        let order = placed(check_out(&session, customer, items, seen_at).await.unwrap());
+
+        // The authorization is released at once, rather than waiting for the vendor.
+        session
+            .call(set_status(order, OrderStatus::Cancelled))
+            .await
+            .unwrap();
+        assert_eq!(payment_status(order).await, "refunded");
+        vendor_session
+            .call(set_status(order, OrderStatus::Refunded))
+            .await
+            .unwrap();
+        assert_eq!(payment_status(order).await, "refunded");
+    });
+}
+
+#[test]
+fn shared_orders_are_settled_once_every_vendor_agrees() {
+    run(async {
+        let (first_vendor, first_session) = vendor().await;
+        let (second_vendor, second_session) = vendor().await;
+        let first = product(first_vendor, Decimal::TEN, 5).await;
+        let second = product(second_vendor, Decimal::ONE, 5).await;
+        let (customer, session) = customer().await;
+        let _cart = fill_cart(customer, &session, first, 1).await;
+        let (items, seen_at) = fill_cart(customer, &session, second, 1).await;
+        let order = placed(check_out(&session, customer, items, seen_at).await.unwrap());
+
+        for (status, unsettled, settled) in [
+            (OrderStatus::Shipped, "authorized", "captured"),
+            (OrderStatus::Returned, "captured", "captured"),
+            (OrderStatus::Refunded, "captured", "refunded"),
+        ] {
+            if status == OrderStatus::Returned {
+                for status in [OrderStatus::Received, OrderStatus::ReturnRequested] {
+                    session.call(set_status(order, status)).await.unwrap();
+                }
+            }
+
+            first_session.call(set_status(order, status)).await.unwrap();
+            assert_eq!(payment_status(order).await, unsettled, "{status:?}");
+            second_session
+                .call(set_status(order, status))
+                .await
+                .unwrap();
+            assert_eq!(payment_status(order).await, settled, "{status:?}");
+        }
+    });
+}
@@ -0,0 +1,208 @@
+//! Setting price tiers and pricing carts with them.
+
+use crate::database::{
+    AppError, AuthError, Deal, Id, POOL, PriceTier, PriceTierError, PriceTiers, Product,
+    QueryResultExt, StaleReason,
+    cart::{CheckoutItem, CheckoutOutcome, cart_products, cart_quote, set_in_shopping_cart},
+    products::{product_info, set_price, set_price_tiers},
+    tests::{
+        Session,
+        checkout::{check_out, fill_cart, placed},
+        customer, product, run, special_offer, vendor,
+    },
+};
+use rust_decimal::Decimal;
+use sqlx::{query, query_scalar};
+use std::num::NonZeroU32;
+
+/// Construct tiers of (`min_units`, `price`) for a product costing `base_price`.
+///
+/// # Errors
+///
+/// Fails if [`PriceTiers::new`] does.
+fn tiers(base_price: Decimal, tiers: &[(u32, i64)]) -> Result<PriceTiers, PriceTierError> {
+    PriceTiers::new(
+        tiers.iter().map(|&(min_units, price)| PriceTier {
+            min_units: NonZeroU32::new(min_units).unwrap(),
+            price: Decimal::from(price),
+        }),
+        base_price,
+    )
+}
+
+/// Set the tiers of `product` as its vendor.
+async fn set_tiers(session: &Session, product: Id<Product>, price_tiers: &[(u32, i64)]) {
+    session
+        .call(set_price_tiers(
+            product,
+            tiers(Decimal::TEN, price_tiers).unwrap(),
+        ))
+        .await
+        .unwrap();
+}
+
+#[test]
+fn tier_price_applies_to_every_unit() {
+    run(async {
+        let (vendor, vendor_session) = vendor().await;
+        let product = product(vendor, Decimal::TEN, 20).await;
+        set_tiers(&vendor_session, product, &[(6, 8), (3, 9)]).await;
+        let info = vendor_session
+            .call(product_info(None, product))
+            .await
+            .unwrap();
+        assert_eq!(
+            info.price_tiers,
+            tiers(Decimal::TEN, &[(3, 9), (6, 8)]).unwrap()
+        );
+
+        let (customer, session) = customer().await;
+        for (number, unit_price) in [(2, 10), (4, 9), (7, 8)] {
+            let (items, _) = fill_cart(customer, &session, product, number).await;
+            let quote = session.call(cart_quote(customer)).await.unwrap();
+            assert_eq!(
+                quote.lines[0].product.unit_price(),
+                Decimal::from(unit_price)
+            );
+            assert_eq!(quote.total, Decimal::from(unit_price * i64::from(number)));
+            assert_eq!(items, quote.items());
+        }
+
+        let quote = session.call(cart_quote(customer)).await.unwrap();
+        let order = placed(
+            check_out(&session, customer, quote.items(), quote.seen_at)
+                .await
+                .unwrap(),
+        );
+        let (total, unit_price) = query!(
+            "
+            SELECT o.total, l.unit_price
+            FROM orders o
+            JOIN order_lines l ON l.order_id = o.id
+            WHERE o.id = $1
+            ",
+            order.get(),
+        )
+        .fetch_one(&*POOL)
+        .await
+        .map(|row| (row.total, row.unit_price))
+        .unwrap();
+        assert_eq!(total, Decimal::from(56));
+        // The tier price is recorded, so that the order shows what each unit cost.
+        assert_eq!(unit_price, Decimal::from(8));
+
+        query!("DELETE FROM products WHERE id = $1", product.get())
+            .execute(&*POOL)
+            .await
+            .map(QueryResultExt::expect_one)
+            .unwrap();
+        let remaining = query_scalar!(
+            r#"SELECT COUNT(*) AS "count!" FROM price_tiers WHERE product = $1"#,
+            product.get(),
+        )
+        .fetch_one(&*POOL)
+        .await
+        .unwrap();
+        assert_eq!(remaining, 0);
+    });
+}
+
+#[test]
+fn special_offers_only_apply_where_cheaper_than_tier() {
+    run(async {
+        let (vendor, vendor_session) = vendor().await;
+        let product = product(vendor, Decimal::TEN, 20).await;
+        let deal = Deal::from_repr(Some(Decimal::new(850, 2)), None, None, Decimal::TEN).unwrap();
+        let _offer = special_offer(product, deal, None).await;
+        set_tiers(&vendor_session, product, &[(5, 8)]).await;
+        let (customer, session) = customer().await;
+
+        for (number, uses, total) in [(2, 2, Decimal::from(17)), (5, 0, Decimal::from(40))] {
+            session
+                .call(set_in_shopping_cart(customer, product, number))
+                .await
+                .unwrap();
+            let quote = session.call(cart_quote(customer)).await.unwrap();
+            assert_eq!(quote.lines[0].offer_uses, uses);
+            assert_eq!(quote.total, total);
+            let (cart, _) = session.call(cart_products(customer)).await.unwrap();
+            assert_eq!(CheckoutItem::from(&cart[0]).expected_price, total);
+        }
+
+        let quote = session.call(cart_quote(customer)).await.unwrap();
+        let _order = placed(
+            check_out(&session, customer, quote.items(), quote.seen_at)
+                .await
+                .unwrap(),
+        );
+    });
+}
+
+#[test]
+fn tiers_must_lower_the_price() {
+    run(async {
+        let base = Decimal::TEN;
+        assert_eq!(tiers(base, &[(1, 9)]), Err(PriceTierError::OutOfRange));
+        assert_eq!(
+            tiers(base, &[(3, 9), (3, 8)]),
+            Err(PriceTierError::Duplicate)
+        );
+        assert_eq!(tiers(base, &[(3, 10)]), Err(PriceTierError::NoDiscount));
+        assert_eq!(
+            tiers(base, &[(3, 8), (5, 9)]),
+            Err(PriceTierError::NoDiscount)
+        );
+
+        let (_, other_session) = vendor().await;
+        let (vendor, vendor_session) = vendor().await;
+        let product = product(vendor, base, 20).await;
+        let invalid = AppError::Invalid {
+            constraint: "price_tiers_discount".into(),
+        };
+
+        // Valid for a product costing 20, but not for this one.
+        let error = vendor_session
+            .call(set_price_tiers(
+                product,
+                tiers(Decimal::from(20), &[(3, 12)]).unwrap(),
+            ))
+            .await
+            .unwrap_err();
+        assert_eq!(AppError::from_error(&error), Some(invalid.clone()));
+
+        set_tiers(&vendor_session, product, &[(3, 9)]).await;
+        let error = vendor_session
+            .call(set_price(product, Decimal::from(9)))
+            .await
+            .unwrap_err();
+        assert_eq!(AppError::from_error(&error), Some(invalid));
+
+        let error = other_session
+            .call(set_price_tiers(product, PriceTiers::default()))
+            .await
+            .unwrap_err();
+        assert_eq!(AuthError::from_error(&error), Some(AuthError::Forbidden));
+    });
+}
+
+#[test]
+fn changed_tiers_are_stale() {
+    run(async {
+        let (vendor, vendor_session) = vendor().await;
+        let product = product(vendor, Decimal::TEN, 20).await;
+        let (customer, session) = customer().await;
+        let (items, seen_at) = fill_cart(customer, &session, product, 3).await;
+
+        set_tiers(&vendor_session, product, &[(3, 9)]).await;
+        let CheckoutOutcome::Stale { lines, .. } =
+            check_out(&session, customer, items, seen_at).await.unwrap()
+        else {
+            panic!("Checkout with changed tiers was not stale.");
+        };
+        assert_eq!(*lines[0].reasons, [StaleReason::ProductChanged]);
+        assert_eq!(
+            lines[0].current.as_ref().unwrap().unit_price(),
+            Decimal::from(9)
+        );
+    });
+}
@@ -0,0 +1,188 @@
+//! Agreement between the price calculations in the database and in [`Deal`].
+
+use crate::database::{
+    AppError, Deal, POOL,
+    offers::set_special_offer_deal,
+    products::set_price,
+    tests::{product, run, special_offer, vendor},
+};
+use rust_decimal::{Decimal, RoundingStrategy};
+use sqlx::{Error, query, query_scalar};
+use std::num::NonZeroU32;
+
+/// A selection of deals on a product costing 10.
+fn deals() -> [Deal; 4] {
+    [
+        Deal::from_repr(Some(Decimal::new(750, 2)), None, None, Decimal::TEN).unwrap(),
+        Deal::free(),
+        Deal::from_repr(None, Some(3), Some(2), Decimal::TEN).unwrap(),
+        Deal::from_repr(Some(Decimal::from(30)), Some(4), None, Decimal::TEN).unwrap(),
+    ]
+}
+
+#[test]
+fn calculate_price_matches_deal() {
+    run(async {
+        for deal in deals() {
+            let (new_price, quantity1, quantity2) = deal.database_repr().unwrap();
+            for number in 1_i32..=9 {
+                for limit in [None, Some(0), Some(1), Some(2)] {
+                    let row = query!(
+                        r#"
+                        SELECT price AS "price!", uses AS "uses!"
+                        FROM calculate_price($1::DECIMAL(10, 2), $2::INT, $3::DECIMAL(10, 2), $4, $5, $6::INT)
+                        "#,
+                        Decimal::TEN,
+                        number,
+                        new_price,
+                        quantity1,
+                        quantity2,
+                        limit.unwrap_or(number),
+                    )
+                    .fetch_one(&*POOL)
+                    .await
+                    .unwrap();
+
+                    let units = NonZeroU32::new(number.cast_unsigned()).unwrap();
+                    let expected =
+                        deal.discounted_price(units, Decimal::TEN, limit.map(i32::cast_unsigned));
+                    assert_eq!(
+                        (row.price, row.uses.cast_unsigned()),
+                        expected,
+                        "{deal:?} on {number} units with limit {limit:?}",
+                    );
+                }
+            }
+        }
+    });
+}
+
+#[test]
+fn calculate_price_matches_deal_below_base_price() {
+    run(async {
+        // The price per unit at a price tier, which some deals no longer beat.
+        for price_per_unit in [Decimal::new(725, 2), Decimal::from(7)] {
+            for deal in deals() {
+                let (new_price, quantity1, quantity2) = deal.database_repr().unwrap();
+                for number in 1_i32..=9 {
+                    let row = query!(
+                        r#"
+                        SELECT price AS "price!", uses AS "uses!"
+                        FROM calculate_price($1::DECIMAL(10, 2), $2::INT, $3::DECIMAL(10, 2), $4, $5, $2::INT)
+                        "#,
+                        price_per_unit,
+                        number,
+                        new_price,
+                        quantity1,
+                        quantity2,
+                    )
+                    .fetch_one(&*POOL)
+                    .await
+                    .unwrap();
+
+                    let units = NonZeroU32::new(number.cast_unsigned()).unwrap();
+                    assert_eq!(
+                        (row.price, row.uses.cast_unsigned()),
+                        deal.discounted_price(units, price_per_unit, None),
+                        "{deal:?} on {number} units at {price_per_unit}",
+                    );
+                }
+            }
+        }
+    });
+}
+
+#[test]
+fn calculate_price_without_deal() {
+    run(async {
+        let price = query_scalar!(
+            r#"
+            SELECT price AS "price!"
+            FROM calculate_price(2.50, 3, NULL, NULL, NULL, 0)
+            "#
+        )
+        .fetch_one(&*POOL)
+        .await
+        .unwrap();
+        assert_eq!(price, Decimal::new(750, 2));
+    });
+}
+
+#[test]
+fn average_discount_matches_deal() {
+    run(async {
+        for deal in deals() {
+            let (new_price, quantity1, quantity2) = deal.database_repr().unwrap();
+            let discount = query_scalar!(
+                r#"SELECT average_discount($1::DECIMAL(10, 2), $2::DECIMAL(10, 2), $3, $4) AS "discount!""#,
+                Decimal::TEN,
+                new_price,
+                quantity1,
+                quantity2,
+            )
+            .fetch_one(&*POOL)
+            .await
+            .unwrap();
+
+            assert_eq!(
+                discount,
+                deal.average_discount(Decimal::TEN)
+                    .round_dp_with_strategy(2, RoundingStrategy::MidpointAwayFromZero),
+                "{deal:?}",
+            );
+        }
+    });
+}
+
+#[test]
+fn average_discount_rejects_non_discounts() {
+    run(async {
+        for (new_price, quantity1, quantity2) in [
+            (Some(Decimal::TEN), None, None),
+            (None, Some(2), Some(2)),
+            (Some(Decimal::from(20)), Some(2), None),
+            (Some(Decimal::ONE), Some(2), Some(1)),
+        ] {
+            let result = query_scalar!(
+                "SELECT average_discount($1::DECIMAL(10, 2), $2::DECIMAL(10, 2), $3, $4)",
+                Decimal::TEN,
+                new_price,
+                quantity1,
+                quantity2,
+            )
+            .fetch_one(&*POOL)
+            .await;
+            assert!(
+                matches!(result, Err(Error::Database(_))),
+                "{new_price:?}, {quantity1:?}, {quantity2:?}",
+            );
+        }
+    });
+}
+
+#[test]
+fn price_change_may_not_void_special_offer() {
+    run(async {
+        let (vendor, vendor_session) = vendor().await;
+        let product = product(vendor, Decimal::TEN, 0).await;
+        let deal = Deal::from_repr(Some(Decimal::from(8)), None, None, Decimal::TEN).unwrap();
+        let offer = special_offer(product, deal, None).await;
+        let invalid = AppError::Invalid {
+            constraint: "special_offers_discount".into(),
+        };
+
+        let error = vendor_session
+            .call(set_price(product, Decimal::from(5)))
+            .await
+            .unwrap_err();
+        assert_eq!(AppError::from_error(&error), Some(invalid.clone()));
+
+        // Valid for a product costing 20, but not for this one.
+        let deal = Deal::from_repr(Some(Decimal::from(12)), None, None, Decimal::from(20)).unwrap();
+        let error = vendor_session
+            .call(set_special_offer_deal(offer, deal))
+            .await
+            .unwrap_err();
+        assert_eq!(AppError::from_error(&error), Some(invalid));
+    });
+}
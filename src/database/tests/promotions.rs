@@ -0,0 +1,321 @@
+//! Looking up promotion codes and applying them at checkout.
+
+use crate::database::{
+    AppError, AuthError, Customer, Id, POOL, Product, QueryResultExt, StaleReason, Vendor,
+    cart::{CheckoutItem, CheckoutOutcome, cart_quote, checkout},
+    products::{OrderStatus, customer_orders, set_status},
+    promotions::{PromotionBenefit, PromotionInfo, PromotionUse, create_promotion, promotion},
+    tests::{
+        Session, administrator,
+        checkout::{card, delivery, fill_cart, placed},
+        customer, now, product, run, unique, vendor,
+    },
+};
+use dioxus::prelude::Result;
+use rust_decimal::Decimal;
+use sqlx::{query, query_scalar};
+use std::num::NonZeroU32;
+use time::{Duration, PrimitiveDateTime};
+
+/// Create an active promotion, returning its code.
+async fn create(
+    benefit: PromotionBenefit,
+    minimum_spend: Decimal,
+    max_uses: Option<u32>,
+    limit_per_customer: Option<u32>,
+) -> Box<str> {
+    let code: Box<str> = unique("KOD").into();
+    let (_, session) = administrator().await;
+    let _id = session
+        .call(create_promotion(
+            code.clone(),
+            benefit,
+            minimum_spend,
+            max_uses.and_then(NonZeroU32::new),
+            limit_per_customer.and_then(NonZeroU32::new),
+            now().await - Duration::hours(1),
+            None,
+        ))
+        .await
+        .unwrap();
+    code
+}
+
+/// Look up `code` as `customer`.
+async fn look_up(session: &Session, customer: Id<Customer>, code: &str) -> Option<PromotionInfo> {
+    session
+        .call(promotion(customer, code.into()))
+        .await
+        .unwrap()
+}
+
+/// Make every shipping method of `vendor` cost `fee`.
+async fn charge_shipping(vendor: Id<Vendor>, fee: Decimal) {
+    let _updated = query!(
+        "UPDATE shipping_methods SET fee = $2::DECIMAL WHERE vendor = $1",
+        vendor.get(),
+        fee,
+    )
+    .execute(&*POOL)
+    .await
+    .unwrap();
+}
+
+/// Put `number` units of `product` in the cart, and check out with `code` expecting the discount
+/// shown for the cart.
+///
+/// # Errors
+///
+/// Fails if checkout fails.
+async fn check_out_with(
+    session: &Session,
+    customer: Id<Customer>,
+    product: Id<Product>,
+    number: u32,
+    promotion: &PromotionInfo,
+) -> Result<CheckoutOutcome> {
+    let (items, seen_at) = fill_cart(customer, session, product, number).await;
+    let total = session.call(cart_quote(customer)).await.unwrap().total;
+    let (_, shipments) = delivery(session, customer, items.clone()).await;
+    let fee = shipments.iter().map(|s| s.expected_fee).sum();
+    let promotion_use = promotion.use_on(total, fee).unwrap_or(PromotionUse {
+        promotion: promotion.id,
+        expected_discount: Decimal::ZERO,
+    });
+    pay(session, customer, (items, seen_at), promotion_use).await
+}
+
+/// Check out `items` with the first address of `customer`, expecting `promotion_use`.
+///
+/// # Errors
+///
+/// Fails if checkout fails.
+async fn pay(
+    session: &Session,
+    customer: Id<Customer>,
+    (items, seen_at): (Vec<CheckoutItem>, PrimitiveDateTime),
+    promotion_use: PromotionUse,
+) -> Result<CheckoutOutcome> {
+    let (address, shipments) = delivery(session, customer, items.clone()).await;
+    session
+        .call(checkout(
+            customer,
+            items,
+            seen_at,
+            address,
+            shipments,
+            Some(promotion_use),
+            card(),
+        ))
+        .await
+}
+
+/// Expect checkout to be rejected because of the promotion, for `reason`.
+fn expect_stale(result: Result<CheckoutOutcome>, reason: StaleReason) {
+    let error = result.unwrap_err();
+    assert_eq!(
+        AppError::from_error(&error),
+        Some(AppError::StaleCart { reason })
+    );
+}
+
+#[test]
+fn discounts_are_recorded_and_charged() {
+    run(async {
+        let (vendor, _) = vendor().await;
+        charge_shipping(vendor, Decimal::from(49)).await;
+        let product = product(vendor, Decimal::TEN, 10).await;
+        let (customer, session) = customer().await;
+
+        for (benefit, discount) in [
+            (
+                PromotionBenefit::PercentOff {
+                    percent: Decimal::new(125, 1),
+                },
+                Decimal::new(375, 2),
+            ),
+            (
+                PromotionBenefit::AmountOff {
+                    amount: Decimal::from(50),
+                },
+                Decimal::from(30),
+            ),
+            (PromotionBenefit::FreeShipping, Decimal::from(49)),
+        ] {
+            let code = create(benefit, Decimal::ZERO, None, None).await;
+            let found = look_up(&session, customer, &code.to_lowercase())
+                .await
+                .unwrap();
+            assert_eq!(found.benefit, benefit);
+            assert_eq!(
+                found.discount(Decimal::from(30), Decimal::from(49)),
+                Some(discount)
+            );
+
+            let order = placed(
+                check_out_with(&session, customer, product, 3, &found)
+                    .await
+                    .unwrap(),
+            );
+            let info = &session.call(customer_orders(customer, 1, 0)).await.unwrap()[0];
+            assert_eq!(info.discount, discount);
+            assert_eq!(info.promotion_code.as_deref(), Some(&*code));
+            let paid = query_scalar!(
+                "SELECT amount FROM payments WHERE order_id = $1",
+                order.get()
+            )
+            .fetch_one(&*POOL)
+            .await
+            .unwrap();
+            assert_eq!(paid, Decimal::from(30 + 49) - discount);
+        }
+    });
+}
+
+#[test]
+fn minimum_spend_is_required() {
+    run(async {
+        let (vendor, _) = vendor().await;
+        let product = product(vendor, Decimal::TEN, 10).await;
+        let (customer, session) = customer().await;
+        let code = create(
+            PromotionBenefit::FreeShipping,
+            Decimal::from(25),
+            None,
+            None,
+        )
+        .await;
+        let found = look_up(&session, customer, &code).await.unwrap();
+        assert_eq!(found.discount(Decimal::from(20), Decimal::ZERO), None);
+
+        let error = check_out_with(&session, customer, product, 2, &found)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            AppError::from_error(&error),
+            Some(AppError::Invalid {
+                constraint: "minimum_spend".into()
+            })
+        );
+        let _order = placed(
+            check_out_with(&session, customer, product, 3, &found)
+                .await
+                .unwrap(),
+        );
+    });
+}
+
+#[test]
+fn uses_are_limited_per_customer_and_restored_on_cancel() {
+    run(async {
+        let (vendor, _) = vendor().await;
+        let product = product(vendor, Decimal::TEN, 10).await;
+        let (other, other_session) = customer().await;
+        let (customer, session) = customer().await;
+        let code = create(PromotionBenefit::FreeShipping, Decimal::ZERO, None, Some(1)).await;
+        let found = look_up(&session, customer, &code).await.unwrap();
+
+        let order = placed(
+            check_out_with(&session, customer, product, 1, &found)
+                .await
+                .unwrap(),
+        );
+        assert_eq!(look_up(&session, customer, &code).await, None);
+        expect_stale(
+            check_out_with(&session, customer, product, 1, &found).await,
+            StaleReason::PromotionUsedUp,
+        );
+
+        // Other customers are not affected.
+        assert!(look_up(&other_session, other, &code).await.is_some());
+
+        session
+            .call(set_status(order, OrderStatus::Cancelled))
+            .await
+            .unwrap();
+        assert_eq!(look_up(&session, customer, &code).await, Some(found));
+    });
+}
+
+#[test]
+fn uses_are_limited_in_total() {
+    run(async {
+        let (vendor, _) = vendor().await;
+        let product = product(vendor, Decimal::TEN, 10).await;
+        let (first, first_session) = customer().await;
+        let (second, second_session) = customer().await;
+        let code = create(PromotionBenefit::FreeShipping, Decimal::ZERO, Some(1), None).await;
+        let first_found = look_up(&first_session, first, &code).await.unwrap();
+        let second_found = look_up(&second_session, second, &code).await.unwrap();
+
+        let _order = placed(
+            check_out_with(&first_session, first, product, 1, &first_found)
+                .await
+                .unwrap(),
+        );
+        assert_eq!(look_up(&second_session, second, &code).await, None);
+        expect_stale(
+            check_out_with(&second_session, second, product, 1, &second_found).await,
+            StaleReason::PromotionUsedUp,
+        );
+    });
+}
+
+#[test]
+fn changed_promotion_is_stale() {
+    run(async {
+        let (vendor, _) = vendor().await;
+        let product = product(vendor, Decimal::TEN, 10).await;
+        let (customer, session) = customer().await;
+        let code = create(
+            PromotionBenefit::AmountOff {
+                amount: Decimal::ONE,
+            },
+            Decimal::ZERO,
+            None,
+            None,
+        )
+        .await;
+        let found = look_up(&session, customer, &code).await.unwrap();
+        let cart = fill_cart(customer, &session, product, 1).await;
+
+        query!(
+            "UPDATE promotions SET amount_off = 2 WHERE id = $1",
+            found.id.get(),
+        )
+        .execute(&*POOL)
+        .await
+        .map(QueryResultExt::expect_one)
+        .unwrap();
+        expect_stale(
+            pay(
+                &session,
+                customer,
+                cart,
+                found.use_on(Decimal::TEN, Decimal::ZERO).unwrap(),
+            )
+            .await,
+            StaleReason::PromotionChanged,
+        );
+    });
+}
+
+#[test]
+fn only_administrators_create_promotions() {
+    run(async {
+        let (_, session) = customer().await;
+        let error = session
+            .call(create_promotion(
+                unique("KOD").into(),
+                PromotionBenefit::FreeShipping,
+                Decimal::ZERO,
+                None,
+                None,
+                now().await,
+                None,
+            ))
+            .await
+            .unwrap_err();
+        assert_eq!(AuthError::from_error(&error), Some(AuthError::Forbidden));
+    });
+}
@@ -0,0 +1,188 @@
+//! Faceted product search.
+
+use crate::database::{
+    AppError, Deal, Id, POOL, Product, QueryResultExt,
+    products::set_search_language,
+    search::{
+        OfferFilter, SearchFilters, SearchLanguage, SearchOrder, search_facets, search_products,
+    },
+    tests::{call_from, product, run, special_offer, unique, vendor},
+};
+use rust_decimal::Decimal;
+use sqlx::query;
+
+/// Rename a product, keeping names unique by appending a suffix.
+async fn rename(product: Id<Product>, name: &str) {
+    query!(
+        "UPDATE products SET name = $2 WHERE id = $1",
+        product.get(),
+        format!("{name} {}", unique("variant")),
+    )
+    .execute(&*POOL)
+    .await
+    .map(QueryResultExt::expect_one)
+    .unwrap();
+}
+
+#[test]
+fn search_filters_sorts_and_pages() {
+    run(async {
+        let (vendor, _) = vendor().await;
+        let cheap = product(vendor, Decimal::TEN, 1).await;
+        let sold_out = product(vendor, Decimal::from(20), 0).await;
+        let expensive = product(vendor, Decimal::from(30), 5).await;
+        let filters = SearchFilters {
+            vendor: Some(vendor),
+            ..SearchFilters::default()
+        };
+
+        let found = search_products(
+            "".into(),
+            filters.clone(),
+            SearchOrder::PriceDescending,
+            10,
+            0,
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            found.iter().map(|p| p.id).collect::<Vec<_>>(),
+            [expensive, sold_out, cheap]
+        );
+        let page = search_products(
+            "".into(),
+            filters.clone(),
+            SearchOrder::PriceAscending,
+            2,
+            2,
+        )
+        .await
+        .unwrap();
+        assert_eq!(page.iter().map(|p| p.id).collect::<Vec<_>>(), [expensive]);
+
+        let filters = SearchFilters {
+            min_price: Some(Decimal::from(15)),
+            in_stock_only: true,
+            ..filters
+        };
+        let found = search_products("".into(), filters.clone(), SearchOrder::Relevance, 10, 0)
+            .await
+            .unwrap();
+        assert_eq!(found.iter().map(|p| p.id).collect::<Vec<_>>(), [expensive]);
+    });
+}
+
+#[test]
+fn facets_ignore_their_own_filter() {
+    run(async {
+        let (vendor, _) = vendor().await;
+        let discounted = product(vendor, Decimal::TEN, 1).await;
+        let _sold_out = product(vendor, Decimal::from(20), 0).await;
+        let _expensive = product(vendor, Decimal::from(30), 5).await;
+        let _offer = special_offer(discounted, Deal::free(), None).await;
+
+        let filters = SearchFilters {
+            vendor: Some(vendor),
+            min_price: Some(Decimal::from(15)),
+            in_stock_only: true,
+            ..SearchFilters::default()
+        };
+        let facets = search_facets("".into(), filters.clone()).await.unwrap();
+        assert_eq!(facets.total, 1);
+        assert_eq!(facets.price_range, Some((Decimal::TEN, Decimal::from(30))));
+        assert_eq!(facets.in_stock, 1);
+        assert_eq!(facets.vendors.len(), 1);
+        assert_eq!(facets.vendors[0].value, vendor);
+        assert_eq!(facets.vendors[0].count, 1);
+        assert_eq!(facets.categories.len(), 1);
+        assert_eq!(
+            facets.ratings.iter().map(|&(_, count)| count).sum::<u64>(),
+            0
+        );
+
+        let filters = SearchFilters {
+            min_price: None,
+            offer: Some(OfferFilter::Public),
+            ..filters
+        };
+        let facets = search_facets("".into(), filters.clone()).await.unwrap();
+        assert_eq!(facets.total, 1);
+        assert_eq!(
+            *facets.offers,
+            [
+                (OfferFilter::Any, 1),
+                (OfferFilter::MembersOnly, 0),
+                (OfferFilter::Public, 1),
+            ]
+        );
+        let found = search_products("".into(), filters, SearchOrder::Relevance, 10, 0)
+            .await
+            .unwrap();
+        assert_eq!(found.iter().map(|p| p.id).collect::<Vec<_>>(), [discounted]);
+    });
+}
+
+#[test]
+fn search_tolerates_inflections_and_typos() {
+    run(async {
+        let (vendor, vendor_session) = vendor().await;
+        let milk = product(vendor, Decimal::TEN, 1).await;
+        rename(milk, "Mjölk").await;
+        let bananas = product(vendor, Decimal::TEN, 1).await;
+        rename(bananas, "Ekologiska bananer").await;
+        let filters = SearchFilters {
+            vendor: Some(vendor),
+            ..SearchFilters::default()
+        };
+        let search = async |query: &str| {
+            search_products(query.into(), filters.clone(), SearchOrder::Relevance, 10, 0)
+                .await
+                .unwrap()
+                .iter()
+                .map(|p| p.id)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(search("mjolk").await, [milk]);
+        assert_eq!(search("mjölkk").await, [milk]);
+        assert_eq!(search("banan").await, [bananas]);
+        assert!(search("kaffe").await.is_empty());
+
+        vendor_session
+            .call(set_search_language(bananas, SearchLanguage::English))
+            .await
+            .unwrap();
+        assert_eq!(search("ekologiska").await, [bananas]);
+    });
+}
+
+#[test]
+fn facets_count_towards_the_search_limit() {
+    run(async {
+        let query = || unique("nothing").into_boxed_str();
+        for _ in 0..120 {
+            let _facets = call_from(
+                "198.51.100.7",
+                search_facets(query(), SearchFilters::default()),
+            )
+            .await
+            .unwrap();
+        }
+        let error = call_from(
+            "198.51.100.7",
+            search_products(
+                query(),
+                SearchFilters::default(),
+                SearchOrder::default(),
+                10,
+                0,
+            ),
+        )
+        .await
+        .unwrap_err();
+        assert!(matches!(
+            AppError::from_error(&error),
+            Some(AppError::RateLimited { .. })
+        ));
+    });
+}
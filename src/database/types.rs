@@ -34,8 +34,8 @@ impl From<String> for Url {
 /// If no unit is specified, the quantity is assumed to be in discrete amounts, and must be an
 /// integer.
 ///
-/// This type is oblivious to any actual meaning behind the units, so it can't for example handle
-/// conversions.
+/// This type is mostly oblivious to any actual meaning behind the units, so it can't for example
+/// handle conversions. The exception is units of mass, see [`grams`](Self::grams).
 #[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 #[cfg_attr(feature = "server", derive(Type))]
 pub struct Amount {
@@ -92,6 +92,23 @@ impl Amount {
         self.unit.as_deref()
     }
 
+    /// Get the quantity in grams, or `None` if the unit is not a unit of mass.
+    ///
+    /// The units of mass recognized are "mg", "g", "hg", "kg" and "t", the same as by the database
+    /// when weighing shipments.
+    #[must_use]
+    pub fn grams(&self) -> Option<Decimal> {
+        let factor = match self.unit()? {
+            "mg" => Decimal::new(1, 3),
+            "g" => Decimal::ONE,
+            "hg" => Decimal::ONE_HUNDRED,
+            "kg" => Decimal::ONE_THOUSAND,
+            "t" => Decimal::from(1_000_000),
+            _ => return None,
+        };
+        Some(self.quantity * factor)
+    }
+
     /// Construct an [`Amount`] from its representation in the database.
     ///
     /// # Panics
@@ -562,6 +579,9 @@ impl Deal {
     ///
     /// Returns a tuple (`price`, `uses`) where `price` is the final price after discounts and
     /// `uses` is how many times the special offer was applied to get that price.
+    ///
+    /// `price_per_unit` may be lower than the price the deal was constructed with due to a
+    /// [`PriceTier`], in which case the deal is not applied if it is no longer a discount.
     #[must_use]
     pub fn discounted_price(
         self,
@@ -574,7 +594,11 @@ impl Deal {
         let Self(deal) = self;
         match deal {
             DealImpl::Discount { new_price } => {
-                let uses = limit.min(units);
+                let uses = if new_price < price_per_unit {
+                    limit.min(units)
+                } else {
+                    0
+                };
                 let price = Decimal::from(uses) * (new_price - price_per_unit)
                     + price_per_unit * Decimal::from(units);
                 (price, uses)
@@ -586,7 +610,11 @@ impl Deal {
                 (price, uses)
             },
             DealImpl::BatchPrice { take, pay } => {
-                let uses = limit.min(units / take);
+                let uses = if pay < price_per_unit * Decimal::from(take.get()) {
+                    limit.min(units / take)
+                } else {
+                    0
+                };
                 let price = pay * Decimal::from(uses)
                     + price_per_unit * Decimal::from(units - take.get() * uses);
                 (price, uses)
@@ -638,6 +666,138 @@ pub enum DealError {
     NoDiscount,
 }
 
+/// A price break of a product: the price per unit when buying at least `min_units` units, e.g.
+/// 89 kr/kg from 5 kg.
+///
+/// The price of the greatest tier reached applies to every unit bought, and special offers only
+/// apply where they are cheaper still, see [`Deal::discounted_price`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct PriceTier {
+    /// How many units must be bought for `price` to apply. At least 2.
+    pub min_units: NonZeroU32,
+    /// The price per unit.
+    pub price: Decimal,
+}
+
+/// The price tiers of a product, fewest units first, see [`PriceTier`].
+///
+/// As with [`Deal`], an instance of this type is guaranteed to lower the price with each tier at
+/// the time of construction, but a later price change in the database might make it invalid.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize, Deref)]
+#[repr(transparent)]
+pub struct PriceTiers(Box<[PriceTier]>);
+
+impl PriceTiers {
+    /// Construct new `PriceTiers` for a product costing `base_price`, in any order.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`InvalidPrice`](PriceTierError::InvalidPrice) if `base_price` is non-positive,
+    /// [`OutOfRange`](PriceTierError::OutOfRange) if a tier starts at 1 unit or has a negative
+    /// price, [`Duplicate`](PriceTierError::Duplicate) if two tiers start at the same number of
+    /// units, or [`NoDiscount`](PriceTierError::NoDiscount) if a tier is not cheaper than the one
+    /// before it, or than `base_price` for the first one.
+    pub fn new(
+        tiers: impl IntoIterator<Item = PriceTier>,
+        base_price: Decimal,
+    ) -> Result<Self, PriceTierError> {
+        if base_price <= Decimal::ZERO {
+            return Err(PriceTierError::InvalidPrice);
+        }
+
+        let mut tiers = tiers.into_iter().collect::<Box<_>>();
+        tiers.sort_unstable_by_key(|tier| tier.min_units);
+        let mut previous = None;
+        for tier in &tiers {
+            if tier.min_units.get() < 2 || tier.price < Decimal::ZERO {
+                return Err(PriceTierError::OutOfRange);
+            } else if previous
+                .is_some_and(|previous: PriceTier| previous.min_units == tier.min_units)
+            {
+                return Err(PriceTierError::Duplicate);
+            } else if tier.price >= previous.map_or(base_price, |previous| previous.price) {
+                return Err(PriceTierError::NoDiscount);
+            }
+            previous = Some(*tier);
+        }
+        Ok(Self(tiers))
+    }
+
+    /// Construct `PriceTiers` from the format used in the database.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the values do not uphold any of the database's invariants.
+    #[cfg(feature = "server")]
+    pub(super) fn from_repr(tiers: Vec<PriceTierRepr>, base_price: Decimal) -> Self {
+        Self::new(
+            tiers
+                .into_iter()
+                .map(|PriceTierRepr { min_units, price }| PriceTier {
+                    min_units: u32::try_from(min_units)
+                        .ok()
+                        .and_then(NonZeroU32::new)
+                        .expect("Database returned non-positive price tier."),
+                    price,
+                }),
+            base_price,
+        )
+        .expect("Database returned invalid price tiers.")
+    }
+
+    /// Convert `PriceTiers` into the format used in the database.
+    ///
+    /// Specifically, this returns a tuple representing the columns `min_units` and `price`
+    /// respectively on success.
+    ///
+    /// # Errors
+    ///
+    /// Fails if a number of units is greater than `i32::MAX`.
+    pub fn database_repr(&self) -> Result<(Vec<i32>, Vec<Decimal>), TryFromIntError> {
+        Ok((
+            self.iter()
+                .map(|tier| tier.min_units.get().try_into())
+                .collect::<Result<_, _>>()?,
+            self.iter().map(|tier| tier.price).collect(),
+        ))
+    }
+
+    /// Get the price per unit when buying `units` units of a product costing `base_price`.
+    #[must_use]
+    pub fn unit_price(&self, units: NonZeroU32, base_price: Decimal) -> Decimal {
+        self.iter()
+            .rev()
+            .find(|tier| tier.min_units <= units)
+            .map_or(base_price, |tier| tier.price)
+    }
+}
+
+/// A [`PriceTier`] as returned by the database function `product_price_tiers`.
+#[cfg(feature = "server")]
+#[derive(Type)]
+#[sqlx(type_name = "price_tier")]
+pub(super) struct PriceTierRepr {
+    min_units: i32,
+    price: Decimal,
+}
+
+/// Errors created by methods of [`PriceTiers`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Error)]
+pub enum PriceTierError {
+    /// The provided base price was non-positive.
+    #[error("Base price must be positive.")]
+    InvalidPrice,
+    /// A tier started at fewer than 2 units, or had a negative price.
+    #[error("Price tiers must start at 2 units or more, and prices must not be negative.")]
+    OutOfRange,
+    /// Two tiers started at the same number of units.
+    #[error("Price tiers must start at different numbers of units.")]
+    Duplicate,
+    /// A tier did not lower the price.
+    #[error("Price tier does not provide discount.")]
+    NoDiscount,
+}
+
 /// A vote on a review or comment.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
 #[cfg_attr(feature = "server", derive(Type))]
@@ -1,12 +1,16 @@
 //! Database functions for interacting with users.
 
-use crate::database::{Customer, Email, Id, ProfilePicture, Url, User, Username, Vendor};
+use crate::database::{Customer, Email, Id, Media, ProfilePicture, User, Username, Vendor};
 use dioxus::prelude::*;
 use serde::{Deserialize, Serialize};
 
 #[cfg(feature = "server")]
 use {
-    crate::database::{POOL, QueryResultExt},
+    crate::database::{
+        POOL, QueryResultExt, Url, authorize_customer, authorize_recent_authentication,
+        authorize_user, authorize_user_or_administrator, authorize_vendor, classify,
+        media::verify_stored, send_verification_email,
+    },
     sqlx::{query, query_as},
 };
 
@@ -20,14 +24,20 @@ use {
 ///
 /// Fails if:
 /// - `user` is invalid.
+/// - The caller is neither `user` nor an administrator.
+/// - The caller has not confirmed their password recently, see
+///   [`reauthenticate`](crate::database::reauthenticate).
 /// - An error occurs during communication with the database.
 #[server]
 pub async fn delete_user(user: Id<User>) -> Result<()> {
+    authorize_user_or_administrator(user).await?;
+    authorize_recent_authentication().await?;
+
     query!("CALL delete_user($1)", user.get())
         .execute(&*POOL)
         .await
         .map(QueryResultExt::procedure)
-        .map_err(Into::into)
+        .map_err(classify)
 }
 
 /// Set a customer's profile picture.
@@ -35,10 +45,17 @@ pub async fn delete_user(user: Id<User>) -> Result<()> {
 /// # Errors
 ///
 /// Fails if:
-/// - `customer` is invalid.
+/// - `customer` or `picture` is invalid.
+/// - The caller is not logged in as `customer`.
 /// - An error occurs during communication with the database.
 #[server]
-pub async fn set_customer_profile_picture(customer: Id<Customer>, url: Url) -> Result<()> {
+pub async fn set_customer_profile_picture(
+    customer: Id<Customer>,
+    picture: Id<Media>,
+) -> Result<()> {
+    authorize_customer(customer).await?;
+    verify_stored(&[picture]).await?;
+
     query!(
         "
         UPDATE customers
@@ -46,7 +63,7 @@ pub async fn set_customer_profile_picture(customer: Id<Customer>, url: Url) -> R
         WHERE id = $1
         ",
         customer.get(),
-        url as Url,
+        Url::from(picture) as Url,
     )
     .execute(&*POOL)
     .await?
@@ -59,10 +76,14 @@ pub async fn set_customer_profile_picture(customer: Id<Customer>, url: Url) -> R
 /// # Errors
 ///
 /// Fails if:
-/// - `vendor` is invalid.
+/// - `vendor` or `picture` is invalid.
+/// - The caller is not logged in as `vendor`.
 /// - An error occurs during communication with the database.
 #[server]
-pub async fn set_vendor_profile_picture(vendor: Id<Vendor>, url: Url) -> Result<()> {
+pub async fn set_vendor_profile_picture(vendor: Id<Vendor>, picture: Id<Media>) -> Result<()> {
+    authorize_vendor(vendor).await?;
+    verify_stored(&[picture]).await?;
+
     query!(
         "
         UPDATE vendors
@@ -70,7 +91,7 @@ pub async fn set_vendor_profile_picture(vendor: Id<Vendor>, url: Url) -> Result<
         WHERE id = $1
         ",
         vendor.get(),
-        url as Url,
+        Url::from(picture) as Url,
     )
     .execute(&*POOL)
     .await?
@@ -84,10 +105,13 @@ pub async fn set_vendor_profile_picture(vendor: Id<Vendor>, url: Url) -> Result<
 ///
 /// Fails if:
 /// - `user` is invalid.
-/// - `username` is already taken.
+/// - `username` is already taken, see [`AppError::Conflict`](crate::database::AppError::Conflict).
+/// - The caller is not logged in as `user`.
 /// - An error occurs during communication with the database.
 #[server]
 pub async fn set_username(user: Id<User>, username: Username) -> Result<()> {
+    authorize_user(user).await?;
+
     query!(
         "
         UPDATE users
@@ -98,21 +122,34 @@ pub async fn set_username(user: Id<User>, username: Username) -> Result<()> {
         username as Username,
     )
     .execute(&*POOL)
-    .await?
+    .await
+    .map_err(classify)?
     .by_unique_key()
     .map_err(Into::into)
 }
 
 /// Set a user's email.
 ///
+/// The new address must be verified anew, and a link to do so is mailed to it, see
+/// [`verify_email`](crate::database::verify_email).
+///
 /// # Errors
 ///
 /// Fails if:
 /// - `user` is invalid.
-/// - `email` is already associated with another user.
+/// - `email` is already associated with another user, see
+///   [`AppError::Conflict`](crate::database::AppError::Conflict).
+/// - The caller is not logged in as `user`.
+/// - The caller has not confirmed their password recently, see
+///   [`reauthenticate`](crate::database::reauthenticate).
+/// - The verification mail could not be sent.
 /// - An error occurs during communication with the database.
 #[server]
 pub async fn set_email(user: Id<User>, email: Email) -> Result<()> {
+    authorize_user(user).await?;
+    authorize_recent_authentication().await?;
+
+    let mut tx = POOL.begin().await?;
     query!(
         "
         UPDATE users
@@ -122,10 +159,13 @@ pub async fn set_email(user: Id<User>, email: Email) -> Result<()> {
         user.get(),
         &email,
     )
-    .execute(&*POOL)
-    .await?
-    .by_unique_key()
-    .map_err(Into::into)
+    .execute(&mut *tx)
+    .await
+    .map_err(classify)?
+    .by_unique_key()?;
+    send_verification_email(&mut tx, user, &email).await?;
+
+    tx.commit().await.map_err(Into::into)
 }
 
 /// Set a vendor's display name.
@@ -134,9 +174,14 @@ pub async fn set_email(user: Id<User>, email: Email) -> Result<()> {
 ///
 /// Fails if:
 /// - `vendor` is invalid.
+/// - `display_name` is already taken, see
+///   [`AppError::Conflict`](crate::database::AppError::Conflict).
+/// - The caller is not logged in as `vendor`.
 /// - An error occurs during communication with the database.
 #[server]
 pub async fn set_vendor_display_name(vendor: Id<Vendor>, display_name: Box<str>) -> Result<()> {
+    authorize_vendor(vendor).await?;
+
     query!(
         "
         UPDATE vendors
@@ -147,7 +192,8 @@ pub async fn set_vendor_display_name(vendor: Id<Vendor>, display_name: Box<str>)
         &display_name,
     )
     .execute(&*POOL)
-    .await?
+    .await
+    .map_err(classify)?
     .by_unique_key()
     .map_err(Into::into)
 }
@@ -158,9 +204,12 @@ pub async fn set_vendor_display_name(vendor: Id<Vendor>, display_name: Box<str>)
 ///
 /// Fails if:
 /// - `vendor` is invalid.
+/// - The caller is not logged in as `vendor`.
 /// - An error occurs during communication with the database.
 #[server]
 pub async fn set_vendor_description(vendor: Id<Vendor>, description: Box<str>) -> Result<()> {
+    authorize_vendor(vendor).await?;
+
     query!(
         "
         UPDATE vendors
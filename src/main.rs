@@ -15,8 +15,9 @@ use state::GlobalState;
 
 use dioxus::prelude::*;
 use views::{
-    CategoryPage, CustomerProfile, FavoritesPage, Home, Login, Product,
-    ProfilePage, Register, Search, VendorLogin, VendorPage, VendorRegister, CartPage,
+    AdminPage, CategoryPage, ChangePassword, CustomerProfile, FavoritesPage, ForgotPassword, Home, Login, Product,
+    ProfilePage, Register, ResetPassword, Search, VendorLogin, VendorPage, VendorRegister,
+    VerifyEmail, CartPage,
 };
 
 /// Structure of all non-internal endpoints.
@@ -72,9 +73,30 @@ enum Route {
     /// See [`VendorRegister`].
     #[route("/vendor-register", VendorRegister)]
     VendorRegister,
+    /// See [`ForgotPassword`].
+    #[route("/forgot-password", ForgotPassword)]
+    ForgotPassword,
+    /// See [`ResetPassword`].
+    #[route("/reset/:token", ResetPassword)]
+    ResetPassword {
+        /// The token mailed to the user.
+        token: String,
+    },
+    /// See [`ChangePassword`].
+    #[route("/change-password", ChangePassword)]
+    ChangePassword,
+    /// See [`VerifyEmail`].
+    #[route("/verify/:token", VerifyEmail)]
+    VerifyEmail {
+        /// The token mailed to the user.
+        token: String,
+    },
     /// Se [`CartPage`].
     #[route("/cart", CartPage)]
     Cart,
+    /// See [`AdminPage`].
+    #[route("/admin", AdminPage)]
+    Admin,
 }
 
 #[allow(non_snake_case)]
@@ -86,69 +108,32 @@ fn App() -> Element {
         let _task = spawn(async move {
             #[cfg(feature = "web")]
             {
-                use crate::database::{Id, RawId, User, login_info};
-                use web_sys::{HtmlDocument, wasm_bindgen::JsCast as _, window};
-                if let Some(window) = window()
-                    && let Some(document) = window.document()
-                    && let Ok(html) = document.dyn_into::<HtmlDocument>()
-                    && let Ok(cookies) = html.cookie()
-                {
-                    if let Some(value) = cookies
-                        .split(';')
-                        .filter_map(|pair| pair.split_once('='))
-                        .find(|(key, _)| key.trim() == "user_id")
-                        .map(|(_, value)| value.trim().to_string())
-                        && !value.is_empty()
-                        && let Ok(id) = value.parse::<RawId>()
-                    {
-                        match login_info(Id::<User>::from(id)).await {
-                            Ok(info) => {
-                                global_state.write().login = Some(info);
-                                let customer_id = global_state.read().customer_id();
-                                if let Some(cid) = customer_id {
-                                    // Ladda favoriter
-                                    if let Ok(favs) = crate::database::products::favorites(cid, 1000, 0).await {
-                                        global_state.write().favorites =
-                                            favs.iter().map(|p| p.id.get()).collect();
-                                    }
-                                    // Hämta local cart (lagd innan auth var klar)
-                                    let local_cart = global_state.read().cart.clone();
-                                    // Synka med DB
-                                    match crate::database::cart::cart_products(cid).await {
-                                        Ok((db_products, _)) => {
-                                            if db_products.is_empty() && !local_cart.is_empty() {
-                                                // Local → DB
-                                                for item in local_cart.iter() {
-                                                    let pid = crate::database::Id::<crate::database::Product>::from(item.product_id);
-                                                    drop(crate::database::cart::set_in_shopping_cart(cid, pid, item.quantity).await);
-                                                }
-                                            } else if !db_products.is_empty() {
-                                                // DB → local state
-                                                global_state.write().cart = db_products
-                                                    .iter()
-                                                    .map(|p| crate::state::CartItem {
-                                                        product_id: p.id.get(),
-                                                        name: p.name.to_string(),
-                                                        price: p.price.to_string().parse::<f64>().unwrap_or(0.0),
-                                                        image_url: p.thumbnail.to_string(),
-                                                        quantity: p.count.get(),
-                                                    })
-                                                    .collect();
-                                            }
-                                        }
-                                        Err(_) => {
-                                            // DB misslyckades — synka local → DB ändå
-                                            for item in local_cart.iter() {
-                                                let pid = crate::database::Id::<crate::database::Product>::from(item.product_id);
-                                                drop(crate::database::cart::set_in_shopping_cart(cid, pid, item.quantity).await);
-                                            }
-                                        }
-                                    }
-                                }
+                use crate::database::cart::{cart_products, guest_cart_products, merge_guest_cart};
+                use crate::database::login_info;
+                match login_info().await {
+                    Ok(Some(info)) => {
+                        global_state.write().login = Some(info);
+                        let customer_id = global_state.read().customer_id();
+                        if let Some(cid) = customer_id {
+                            // Ladda favoriter
+                            if let Ok(favs) = crate::database::products::favorites(cid, 1000, 0).await {
+                                global_state.write().favorites =
+                                    favs.iter().map(|p| p.id.get()).collect();
+                            }
+                            // Varor som lades i gästkorgen innan auth var klar flyttas till kundens korg.
+                            drop(merge_guest_cart(cid).await);
+                            if let Ok((products, _)) = cart_products(cid).await {
+                                global_state.write().set_cart(&products);
                             }
-                            Err(_) => {}
                         }
                     }
+                    Ok(None) => {
+                        // Gäst: hämta gästkorgen som sparats på servern.
+                        if let Ok(products) = guest_cart_products().await {
+                            global_state.write().set_cart(&products);
+                        }
+                    }
+                    Err(_) => {}
                 }
             }
             // Auth-checken är klar, oavsett resultat.
@@ -169,6 +154,41 @@ fn App() -> Element {
     }
 }
 
+#[cfg(not(feature = "server"))]
 fn main() {
     launch(App)
 }
+
+/// Serve the app with the address of each connection, which is needed to rate limit per client.
+/// Unlike [`launch`], this does not support hot reloading.
+///
+/// # Panics
+///
+/// Panics if the server could not be started.
+#[cfg(feature = "server")]
+fn main() {
+    use dioxus::{
+        cli_config::fullstack_address_or_localhost,
+        logger,
+        server::axum::{self, Router},
+    };
+    use std::net::SocketAddr;
+    use tokio::{net::TcpListener, runtime::Runtime};
+
+    logger::initialize_default();
+    Runtime::new()
+        .expect("Failed to start the server runtime.")
+        .block_on(async {
+            let address = fullstack_address_or_localhost();
+            let listener = TcpListener::bind(address)
+                .await
+                .expect("Failed to bind to the server address.");
+            let router = Router::new().serve_dioxus_application(ServeConfig::new(), App);
+            axum::serve(
+                listener,
+                router.into_make_service_with_connect_info::<SocketAddr>(),
+            )
+            .await
+            .expect("Failed to serve the app.");
+        });
+}
@@ -1,19 +1,30 @@
-use crate::database::Login;
+use crate::database::cart::{set_in_guest_cart, set_in_shopping_cart, CartProduct};
+use crate::database::{Customer, Id, Login, Product};
+use rust_decimal::Decimal;
  
-#[derive(Clone, Debug, PartialEq)]
+/// A product in the cart, as shown in the UI.
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct CartItem {
+    /// The ID of the product.
     pub product_id: i32,
+    /// The name of the product.
     pub name: String,
-    pub price: f64,
+    /// The undiscounted price of one unit.
+    pub price: Decimal,
+    /// The URL of the thumbnail of the product.
     pub image_url: String,
+    /// The number of units in the cart.
     pub quantity: u32,
 }
  
 /// Global state shared across the app.
 #[derive(Clone, Debug, Default)]
 pub struct GlobalState {
+    /// The products in the cart.
     pub cart: Vec<CartItem>,
+    /// The IDs of the customer's favorite products.
     pub favorites: Vec<i32>,
+    /// The logged in user, if any.
     pub login: Option<Login>,
     /// True while the initial auth-check (cookie → login_info) is in progress.
     pub auth_loading: bool,
@@ -28,7 +39,8 @@ impl GlobalState {
         }
     }
  
-    pub fn add_to_cart(&mut self, product_id: i32, name: String, price: f64, image_url: String) {
+    /// Add one unit of a product to the cart.
+    pub fn add_to_cart(&mut self, product_id: i32, name: String, price: Decimal, image_url: String) {
         if let Some(item) = self.cart.iter_mut().find(|i| i.product_id == product_id) {
             item.quantity += 1;
         } else {
@@ -42,6 +54,7 @@ impl GlobalState {
         }
     }
  
+    /// Set the number of units of a product in the cart, removing it at zero.
     pub fn set_quantity(&mut self, product_id: i32, quantity: u32) {
         if quantity == 0 {
             self.cart.retain(|i| i.product_id != product_id);
@@ -50,18 +63,36 @@ impl GlobalState {
         }
     }
  
+    /// Remove a product from the cart.
     pub fn remove_from_cart(&mut self, product_id: i32) {
         self.cart.retain(|i| i.product_id != product_id);
     }
  
-    pub fn cart_total(&self) -> f64 {
-        self.cart.iter().map(|i| i.price * i.quantity as f64).sum()
+    /// Replace the cart with products loaded from the server.
+    pub fn set_cart(&mut self, products: &[CartProduct]) {
+        self.cart = products
+            .iter()
+            .map(|p| CartItem {
+                product_id: p.id.get(),
+                name: p.name.to_string(),
+                price: p.price,
+                image_url: p.thumbnail.to_string(),
+                quantity: p.count.get(),
+            })
+            .collect();
     }
  
+    /// Get the undiscounted price of the cart, see [`cart_quote`](crate::database::cart::cart_quote).
+    pub fn cart_total(&self) -> Decimal {
+        self.cart.iter().map(|i| i.price * Decimal::from(i.quantity)).sum()
+    }
+ 
+    /// Get the number of units in the cart.
     pub fn cart_count(&self) -> usize {
         self.cart.iter().map(|i| i.quantity as usize).sum()
     }
  
+    /// Get the ID of the logged in customer, if the user is one.
     pub fn customer_id(&self) -> Option<crate::database::Id<crate::database::Customer>> {
         self.login.as_ref().and_then(|l| {
             if let crate::database::LoginId::Customer(id) = l.id {
@@ -72,3 +103,13 @@ impl GlobalState {
         })
     }
 }
+
+/// Save the number of units of a product in the cart on the server: in the cart of `customer` if
+/// given, and otherwise in the guest cart. Failures are ignored, as the local cart is still updated.
+pub async fn save_cart_item(customer: Option<Id<Customer>>, product_id: i32, quantity: u32) {
+    let product = Id::<Product>::from(product_id);
+    match customer {
+        Some(customer) => drop(set_in_shopping_cart(customer, product, quantity).await),
+        None => drop(set_in_guest_cart(product, quantity).await),
+    }
+}
@@ -28,10 +28,16 @@ pub use favorites::FavoritesPage;
 mod search;
 pub use search::Search;
 
-/// Auth views: Login, Register, VendorLogin, VendorRegister.
+/// Auth views: login, registration, password changes and email verification.
 mod auth;
-pub use auth::{Login, Register, VendorLogin, VendorRegister};
+pub use auth::{
+    ChangePassword, ConfirmPassword, ForgotPassword, Login, Register, ResetPassword, VendorLogin,
+    VendorRegister, VerifyEmail,
+};
 
 /// Se [`CartPage`].
 mod cart;
-pub use cart::CartPage;
\ No newline at end of file
+pub use cart::CartPage;
+/// See [`AdminPage`].
+mod admin;
+pub use admin::AdminPage;
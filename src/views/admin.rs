@@ -0,0 +1,665 @@
+#![allow(non_snake_case, reason = "Components are named in PascalCase.")]
+use crate::Route;
+use crate::database::admin::{
+    products, recent_comments, recent_reviews, set_can_review, users, CommentOverview,
+    ProductOverviewAdministrator, ReviewOverview, UserOverview,
+};
+use crate::database::categories::{
+    category_trees, create_category, delete_category, move_category, rename_category,
+    reorder_categories, CategoryTree,
+};
+use crate::database::products::set_visibility;
+use crate::database::reviews::{delete_comment, delete_review};
+use crate::database::users::delete_user;
+use crate::database::{AppError, AuthError, Category, Id, LoginId, Role, User};
+use crate::state::GlobalState;
+use crate::views::ConfirmPassword;
+use dioxus::prelude::*;
+use std::pin::Pin;
+
+// ─── Status feedback ──────────────────────────────────────────────────────────
+
+#[component]
+fn StatusMessage(message: Option<String>) -> Element {
+    rsx! {
+        if let Some(msg) = message {
+            div { class: "mb-4 bg-green-50 border border-green-200 rounded-xl p-3 text-sm text-green-800 flex items-center gap-2",
+                i { class: "fa-solid fa-circle-info" }
+                "{msg}"
+            }
+        }
+    }
+}
+
+// ─── Users tab ────────────────────────────────────────────────────────────────
+
+#[component]
+fn AdminUsersTab() -> Element {
+    let mut search = use_signal(String::new);
+    let users_resource = use_resource(move || async move {
+        users(search().into(), 100, 0).await
+    });
+    let status_msg: Signal<Option<String>> = use_signal(|| None);
+    // Användare som väntar på att tas bort tills lösenordet bekräftats
+    let mut pending_delete: Signal<Option<Id<User>>> = use_signal(|| None);
+
+    let users_read = users_resource.read();
+    let is_loading = users_read.is_none();
+    let err: Option<String> = users_read.as_ref().and_then(|r| r.as_ref().err().map(ToString::to_string));
+    let users_list: Option<Vec<UserOverview>> = users_read.as_ref()
+        .and_then(|r| r.as_ref().ok())
+        .map(|v| v.to_vec());
+
+    rsx! {
+        StatusMessage { message: status_msg() }
+        if let Some(user_id) = pending_delete() {
+            ConfirmPassword {
+                on_close: move |confirmed| {
+                    pending_delete.set(None);
+                    if confirmed {
+                        let _task = spawn(remove_user(user_id, status_msg, users_resource, pending_delete));
+                    }
+                },
+            }
+        }
+        input {
+            class: "w-full border rounded-xl px-4 py-2 mb-4 text-sm",
+            placeholder: "Sök på användarnamn eller e-post...",
+            value: "{search}",
+            oninput: move |e| search.set(e.value()),
+        }
+
+        if is_loading {
+            p { class: "text-gray-400 animate-pulse", "Laddar..." }
+        } else if let Some(e) = err {
+            p { class: "text-red-400 text-sm", "Fel: {e}" }
+        } else if let Some(list) = users_list {
+            div { class: "bg-white rounded-2xl shadow-sm divide-y",
+                for user in list {
+                    {
+                        let user_id = user.id;
+                        let role = match user.role {
+                            Role::Customer => "Kund",
+                            Role::Vendor => "Företag",
+                            Role::Administrator => "Admin",
+                        };
+                        rsx! {
+                            div { class: "flex items-center justify-between gap-4 p-4",
+                                div {
+                                    p { class: if user.deleted { "font-bold text-gray-400 line-through" } else { "font-bold text-gray-900" },
+                                        "{user.username}"
+                                    }
+                                    p { class: "text-xs text-gray-400", "{user.email} · {role}" }
+                                }
+                                div { class: "flex items-center gap-2",
+                                    if let Some(can_review) = user.can_review {
+                                        button {
+                                            class: "text-xs border border-gray-200 rounded-lg px-3 py-1.5 hover:bg-gray-50 text-gray-600 font-bold transition whitespace-nowrap",
+                                            onclick: move |_| {
+                                                let mut sm = status_msg;
+                                                let mut r = users_resource;
+                                                let _task = spawn(async move {
+                                                    match set_can_review(user_id.get().into(), !can_review).await {
+                                                        Ok(()) => {
+                                                            sm.set(Some(if can_review { "Recensioner spärrade." } else { "Recensioner tillåtna." }.into()));
+                                                            r.restart();
+                                                        }
+                                                        Err(e) => sm.set(Some(AppError::describe(&e))),
+                                                    }
+                                                });
+                                            },
+                                            if can_review {
+                                                i { class: "fa-solid fa-comment-slash mr-1" }
+                                                "Spärra recensioner"
+                                            } else {
+                                                i { class: "fa-solid fa-comment mr-1" }
+                                                "Tillåt recensioner"
+                                            }
+                                        }
+                                    }
+                                    if user.deleted {
+                                        span { class: "text-xs text-gray-400 px-3", "Borttagen" }
+                                    } else {
+                                        button {
+                                            class: "text-xs bg-red-600 text-white font-bold px-3 py-1.5 rounded-lg hover:bg-red-700 transition whitespace-nowrap",
+                                            onclick: move |_| {
+                                                let _task = spawn(remove_user(user_id, status_msg, users_resource, pending_delete));
+                                            },
+                                            i { class: "fa-solid fa-trash mr-1" }
+                                            "Ta bort"
+                                        }
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Ta bort en användare, eller be om lösenordet om det inte bekräftats nyligen.
+async fn remove_user(
+    user_id: Id<User>,
+    mut status_msg: Signal<Option<String>>,
+    mut users_resource: Resource<Result<Box<[UserOverview]>>>,
+    mut pending_delete: Signal<Option<Id<User>>>,
+) {
+    match delete_user(user_id).await {
+        Ok(()) => {
+            status_msg.set(Some("Användare borttagen.".into()));
+            users_resource.restart();
+        }
+        Err(e) if AuthError::from_error(&e) == Some(AuthError::ReauthenticationRequired) => {
+            pending_delete.set(Some(user_id));
+        }
+        Err(e) => status_msg.set(Some(AppError::describe(&e))),
+    }
+}
+
+// ─── Categories tab ───────────────────────────────────────────────────────────
+
+/// A parent category, or `None` for the roots, and the new order of its children.
+type SiblingOrder = (Option<Id<Category>>, Vec<Id<Category>>);
+
+/// Flatten a forest of categories into `(id, indented name)` pairs, in display order.
+fn flatten_categories(trees: &[CategoryTree], depth: usize, out: &mut Vec<(Id<Category>, String)>) {
+    for tree in trees {
+        out.push((tree.id, format!("{}{}", "— ".repeat(depth), tree.name)));
+        flatten_categories(&tree.subcategories, depth + 1, out);
+    }
+}
+
+#[component]
+fn CategoryNode(
+    tree: CategoryTree,
+    parent: Option<Id<Category>>,
+    siblings: Vec<Id<Category>>,
+    selected: Option<Id<Category>>,
+    on_select: EventHandler<Id<Category>>,
+    on_add: EventHandler<Id<Category>>,
+    on_delete: EventHandler<Id<Category>>,
+    on_reorder: EventHandler<SiblingOrder>,
+) -> Element {
+    let id = tree.id;
+    let index = siblings.iter().position(|&s| s == id).unwrap_or_default();
+    let is_first = index == 0;
+    let is_last = index + 1 >= siblings.len();
+    let children: Vec<Id<Category>> = tree.subcategories.iter().map(|sub| sub.id).collect();
+
+    // Syskonordningen efter att kategorin flyttats ett steg upp eller ned.
+    let mut up = siblings.clone();
+    up.swap(index, index.saturating_sub(1));
+    let mut down = siblings;
+    if !is_last {
+        down.swap(index, index + 1);
+    }
+
+    rsx! {
+        li { class: "py-1",
+            div { class: "flex items-center justify-between gap-4",
+                button {
+                    class: if selected == Some(id) { "text-sm font-bold text-green-700" } else { "text-sm text-gray-800 hover:text-green-700" },
+                    onclick: move |_| on_select.call(id),
+                    "{tree.name}"
+                }
+                div { class: "flex gap-2",
+                    button {
+                        class: "text-xs text-gray-500 hover:text-gray-800 disabled:opacity-30",
+                        title: "Flytta upp",
+                        disabled: is_first,
+                        onclick: move |_| on_reorder.call((parent, up.clone())),
+                        i { class: "fa-solid fa-arrow-up" }
+                    }
+                    button {
+                        class: "text-xs text-gray-500 hover:text-gray-800 disabled:opacity-30",
+                        title: "Flytta ned",
+                        disabled: is_last,
+                        onclick: move |_| on_reorder.call((parent, down.clone())),
+                        i { class: "fa-solid fa-arrow-down" }
+                    }
+                    button {
+                        class: "text-xs text-green-700 hover:text-green-900",
+                        title: "Lägg till underkategori",
+                        onclick: move |_| on_add.call(id),
+                        i { class: "fa-solid fa-plus" }
+                    }
+                    button {
+                        class: "text-xs text-red-500 hover:text-red-700",
+                        title: "Ta bort",
+                        onclick: move |_| on_delete.call(id),
+                        i { class: "fa-solid fa-trash" }
+                    }
+                }
+            }
+            if !tree.subcategories.is_empty() {
+                ul { class: "ml-6 border-l pl-3",
+                    for sub in tree.subcategories.iter() {
+                        CategoryNode {
+                            tree: sub.clone(),
+                            parent: Some(id),
+                            siblings: children.clone(),
+                            selected,
+                            on_select,
+                            on_add,
+                            on_delete,
+                            on_reorder,
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[component]
+fn AdminCategoriesTab() -> Element {
+    let mut trees_resource = use_resource(move || async move { category_trees().await });
+    let mut status_msg: Signal<Option<String>> = use_signal(|| None);
+    let mut name = use_signal(String::new);
+    // Förälder till nästa kategori som skapas; `None` för en rotkategori.
+    let mut parent: Signal<Option<Id<Category>>> = use_signal(|| None);
+    // Kategorin som byter namn eller flyttas.
+    let mut selected: Signal<Option<Id<Category>>> = use_signal(|| None);
+    let mut new_name = use_signal(String::new);
+    let mut new_parent: Signal<Option<Id<Category>>> = use_signal(|| None);
+
+    let trees_read = trees_resource.read();
+    let is_loading = trees_read.is_none();
+    let err: Option<String> = trees_read.as_ref().and_then(|r| r.as_ref().err().map(ToString::to_string));
+    let trees: Option<Vec<CategoryTree>> = trees_read.as_ref()
+        .and_then(|r| r.as_ref().ok())
+        .map(|v| v.to_vec());
+    let mut flat = Vec::new();
+    if let Some(trees) = &trees {
+        flatten_categories(trees, 0, &mut flat);
+    }
+    let roots: Vec<Id<Category>> = trees.iter().flatten().map(|tree| tree.id).collect();
+
+    // Kör en ändring av kategoriträdet och ladda om det.
+    let run = move |action: &'static str, future: Pin<Box<dyn Future<Output = Result<()>>>>| {
+        let _task = spawn(async move {
+            match future.await {
+                Ok(()) => {
+                    status_msg.set(Some(action.into()));
+                    trees_resource.restart();
+                }
+                Err(e) => status_msg.set(Some(AppError::describe(&e))),
+            }
+        });
+    };
+
+    let on_select = move |id: Id<Category>| {
+        selected.set(Some(id));
+        new_name.set(String::new());
+    };
+    let on_add = move |id: Id<Category>| parent.set(Some(id));
+    let on_delete = move |id: Id<Category>| run("Kategori borttagen.", Box::pin(delete_category(id)));
+    let on_reorder = move |(siblings_of, order): SiblingOrder| {
+        run("Ordning sparad.", Box::pin(reorder_categories(siblings_of, order.into())));
+    };
+
+    rsx! {
+        StatusMessage { message: status_msg() }
+        div { class: "flex gap-2 mb-4",
+            input {
+                class: "flex-1 border rounded-xl px-4 py-2 text-sm",
+                placeholder: if parent().is_some() { "Namn på underkategori" } else { "Namn på ny kategori" },
+                value: "{name}",
+                oninput: move |e| name.set(e.value()),
+            }
+            if parent().is_some() {
+                button {
+                    class: "text-xs border border-gray-200 rounded-lg px-3 hover:bg-gray-50 text-gray-600 font-bold transition",
+                    onclick: move |_| parent.set(None),
+                    "Rotkategori"
+                }
+            }
+            button {
+                class: "bg-green-700 text-white font-black px-5 py-2 rounded-full hover:bg-green-800 transition text-sm",
+                onclick: move |_| {
+                    let created = name();
+                    name.set(String::new());
+                    parent.set(None);
+                    run("Kategori skapad.", Box::pin(create_category(parent(), created.into())));
+                },
+                i { class: "fa-solid fa-plus mr-1" }
+                "Skapa"
+            }
+        }
+
+        if let Some(category) = selected() {
+            div { class: "bg-white rounded-2xl shadow-sm p-4 mb-4 flex flex-col gap-2",
+                div { class: "flex gap-2",
+                    input {
+                        class: "flex-1 border rounded-xl px-4 py-2 text-sm",
+                        placeholder: "Nytt namn",
+                        value: "{new_name}",
+                        oninput: move |e| new_name.set(e.value()),
+                    }
+                    button {
+                        class: "text-xs border border-gray-200 rounded-lg px-3 hover:bg-gray-50 text-gray-600 font-bold transition",
+                        onclick: move |_| run("Kategori omdöpt.", Box::pin(rename_category(category, new_name().into()))),
+                        i { class: "fa-solid fa-pen mr-1" }
+                        "Byt namn"
+                    }
+                }
+                div { class: "flex gap-2",
+                    select {
+                        class: "flex-1 border rounded-xl px-4 py-2 text-sm",
+                        onchange: move |e| new_parent.set(e.value().parse::<i32>().ok().map(Id::from)),
+                        option { value: "", "Ingen (rotkategori)" }
+                        for (id, label) in flat.iter() {
+                            option { value: "{id}", "{label}" }
+                        }
+                    }
+                    button {
+                        class: "text-xs border border-gray-200 rounded-lg px-3 hover:bg-gray-50 text-gray-600 font-bold transition",
+                        onclick: move |_| run("Kategori flyttad.", Box::pin(move_category(category, new_parent()))),
+                        i { class: "fa-solid fa-arrows-up-down-left-right mr-1" }
+                        "Flytta"
+                    }
+                    button {
+                        class: "text-xs text-gray-500 hover:text-gray-800 px-2",
+                        onclick: move |_| selected.set(None),
+                        i { class: "fa-solid fa-xmark" }
+                    }
+                }
+            }
+        }
+
+        if is_loading {
+            p { class: "text-gray-400 animate-pulse", "Laddar..." }
+        } else if let Some(e) = err {
+            p { class: "text-red-400 text-sm", "Fel: {e}" }
+        } else if let Some(trees) = trees {
+            ul { class: "bg-white rounded-2xl shadow-sm p-4",
+                for tree in trees {
+                    CategoryNode {
+                        tree,
+                        parent: None,
+                        siblings: roots.clone(),
+                        selected: selected(),
+                        on_select,
+                        on_add,
+                        on_delete,
+                        on_reorder,
+                    }
+                }
+            }
+        }
+    }
+}
+
+// ─── Products tab ─────────────────────────────────────────────────────────────
+
+#[component]
+fn AdminProductsTab() -> Element {
+    let mut search = use_signal(String::new);
+    let products_resource = use_resource(move || async move {
+        products(search().into(), 100, 0).await
+    });
+    let status_msg: Signal<Option<String>> = use_signal(|| None);
+
+    let products_read = products_resource.read();
+    let is_loading = products_read.is_none();
+    let err: Option<String> = products_read.as_ref().and_then(|r| r.as_ref().err().map(ToString::to_string));
+    let products_list: Option<Vec<ProductOverviewAdministrator>> = products_read.as_ref()
+        .and_then(|r| r.as_ref().ok())
+        .map(|v| v.to_vec());
+
+    rsx! {
+        StatusMessage { message: status_msg() }
+        input {
+            class: "w-full border rounded-xl px-4 py-2 mb-4 text-sm",
+            placeholder: "Sök på produkt eller företag...",
+            value: "{search}",
+            oninput: move |e| search.set(e.value()),
+        }
+
+        if is_loading {
+            p { class: "text-gray-400 animate-pulse", "Laddar..." }
+        } else if let Some(e) = err {
+            p { class: "text-red-400 text-sm", "Fel: {e}" }
+        } else if let Some(list) = products_list {
+            div { class: "bg-white rounded-2xl shadow-sm divide-y",
+                for product in list {
+                    {
+                        let product_id = product.id;
+                        let visible = product.visible;
+                        rsx! {
+                            div { class: "flex items-center justify-between gap-4 p-4",
+                                div {
+                                    Link {
+                                        to: Route::Product { id: product_id.get() },
+                                        class: if visible { "font-bold text-gray-900 hover:text-green-700" } else { "font-bold text-gray-400 hover:text-green-700" },
+                                        "{product.name}"
+                                    }
+                                    Link {
+                                        to: Route::Vendor { id: product.vendor },
+                                        class: "block text-xs text-gray-400 hover:text-green-700",
+                                        "{product.vendor_name}"
+                                    }
+                                }
+                                button {
+                                    class: "text-xs border border-gray-200 rounded-lg px-3 py-1.5 hover:bg-gray-50 text-gray-600 font-bold transition whitespace-nowrap",
+                                    onclick: move |_| {
+                                        let mut sm = status_msg;
+                                        let mut r = products_resource;
+                                        let _task = spawn(async move {
+                                            match set_visibility(product_id, !visible).await {
+                                                Ok(()) => {
+                                                    sm.set(Some(if visible { "Produkt dold." } else { "Produkt synlig." }.into()));
+                                                    r.restart();
+                                                }
+                                                Err(e) => sm.set(Some(AppError::describe(&e))),
+                                            }
+                                        });
+                                    },
+                                    if visible {
+                                        i { class: "fa-solid fa-eye-slash mr-1" }
+                                        "Dölj"
+                                    } else {
+                                        i { class: "fa-solid fa-eye mr-1" }
+                                        "Visa"
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+// ─── Moderation tab ───────────────────────────────────────────────────────────
+
+#[component]
+fn AdminModerationTab() -> Element {
+    let reviews_resource = use_resource(move || async move { recent_reviews(50, 0).await });
+    let comments_resource = use_resource(move || async move { recent_comments(50, 0).await });
+    let status_msg: Signal<Option<String>> = use_signal(|| None);
+
+    let rev_read = reviews_resource.read();
+    let rev_loading = rev_read.is_none();
+    let rev_err: Option<String> = rev_read.as_ref().and_then(|r| r.as_ref().err().map(ToString::to_string));
+    let reviews_list: Option<Vec<ReviewOverview>> = rev_read.as_ref()
+        .and_then(|r| r.as_ref().ok())
+        .map(|v| v.to_vec());
+
+    let com_read = comments_resource.read();
+    let com_loading = com_read.is_none();
+    let com_err: Option<String> = com_read.as_ref().and_then(|r| r.as_ref().err().map(ToString::to_string));
+    let comments_list: Option<Vec<CommentOverview>> = com_read.as_ref()
+        .and_then(|r| r.as_ref().ok())
+        .map(|v| v.to_vec());
+
+    rsx! {
+        StatusMessage { message: status_msg() }
+
+        h2 { class: "text-xl font-black text-gray-900 mb-4",
+            i { class: "fa-solid fa-star text-green-700 mr-2" }
+            "Senaste recensioner"
+        }
+        if rev_loading {
+            p { class: "text-gray-400 animate-pulse", "Laddar..." }
+        } else if let Some(e) = rev_err {
+            p { class: "text-red-400 text-sm", "Fel: {e}" }
+        } else if let Some(list) = reviews_list {
+            div { class: "bg-white rounded-2xl shadow-sm divide-y mb-8",
+                for review in list {
+                    {
+                        let review_id = review.id;
+                        rsx! {
+                            div { class: "flex items-start justify-between gap-4 p-4",
+                                div {
+                                    p { class: "font-bold text-gray-900", "{review.title}" }
+                                    p { class: "text-sm text-gray-600", "{review.content}" }
+                                    p { class: "text-xs text-gray-400 mt-1", "{review.username} om {review.product_name}" }
+                                }
+                                button {
+                                    class: "text-xs bg-red-600 text-white font-bold px-3 py-1.5 rounded-lg hover:bg-red-700 transition whitespace-nowrap",
+                                    onclick: move |_| {
+                                        let mut sm = status_msg;
+                                        let mut r = reviews_resource;
+                                        let mut c = comments_resource;
+                                        let _task = spawn(async move {
+                                            match delete_review(review_id).await {
+                                                Ok(()) => {
+                                                    sm.set(Some("Recension borttagen.".into()));
+                                                    r.restart();
+                                                    c.restart();
+                                                }
+                                                Err(e) => sm.set(Some(AppError::describe(&e))),
+                                            }
+                                        });
+                                    },
+                                    i { class: "fa-solid fa-trash mr-1" }
+                                    "Ta bort"
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        h2 { class: "text-xl font-black text-gray-900 mb-4",
+            i { class: "fa-solid fa-comments text-green-700 mr-2" }
+            "Senaste kommentarer"
+        }
+        if com_loading {
+            p { class: "text-gray-400 animate-pulse", "Laddar..." }
+        } else if let Some(e) = com_err {
+            p { class: "text-red-400 text-sm", "Fel: {e}" }
+        } else if let Some(list) = comments_list {
+            div { class: "bg-white rounded-2xl shadow-sm divide-y",
+                for comment in list {
+                    {
+                        let comment_id = comment.id;
+                        rsx! {
+                            div { class: "flex items-start justify-between gap-4 p-4",
+                                div {
+                                    p { class: "text-sm text-gray-600", "{comment.content}" }
+                                    p { class: "text-xs text-gray-400 mt-1", "{comment.username} om {comment.product_name}" }
+                                }
+                                button {
+                                    class: "text-xs bg-red-600 text-white font-bold px-3 py-1.5 rounded-lg hover:bg-red-700 transition whitespace-nowrap",
+                                    onclick: move |_| {
+                                        let mut sm = status_msg;
+                                        let mut c = comments_resource;
+                                        let _task = spawn(async move {
+                                            match delete_comment(comment_id).await {
+                                                Ok(()) => {
+                                                    sm.set(Some("Kommentar borttagen.".into()));
+                                                    c.restart();
+                                                }
+                                                Err(e) => sm.set(Some(AppError::describe(&e))),
+                                            }
+                                        });
+                                    },
+                                    i { class: "fa-solid fa-trash mr-1" }
+                                    "Ta bort"
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+// ─── Admin page ───────────────────────────────────────────────────────────────
+
+/// Adminpanel; hantera användare, kategorier, produkter och recensioner
+#[allow(clippy::same_name_method, reason = "Dioxus macro limitation")]
+#[component]
+pub fn AdminPage() -> Element {
+    let global_state = use_context::<Signal<GlobalState>>();
+    let login = global_state.read().login.clone();
+    let mut active_tab = use_signal(|| 0_u8);
+
+    if !login.as_ref().is_some_and(|l| matches!(l.id, LoginId::Administrator(_))) {
+        return rsx! {
+            div { class: "min-h-screen bg-gray-50 flex items-center justify-center",
+                div { class: "text-center",
+                    p { class: "text-gray-500 mb-4", "Du måste vara inloggad som administratör." }
+                    Link {
+                        to: Route::Login {},
+                        class: "bg-green-700 text-white font-black px-6 py-3 rounded-full",
+                        "Logga in"
+                    }
+                }
+            }
+        };
+    }
+
+    let tabs = [
+        ("fa-solid fa-users", "Användare"),
+        ("fa-solid fa-sitemap", "Kategorier"),
+        ("fa-solid fa-tag", "Produkter"),
+        ("fa-solid fa-gavel", "Moderering"),
+    ];
+
+    rsx! {
+        div { class: "min-h-screen bg-gray-50",
+            div { class: "max-w-5xl mx-auto p-6",
+                Link {
+                    to: Route::Home {},
+                    class: "text-green-700 hover:text-green-900 font-bold flex items-center gap-2 mb-4 transition-colors",
+                    i { class: "fa-solid fa-arrow-left" }
+                    "Tillbaka till start"
+                }
+
+                div { class: "flex items-center gap-4 mb-8",
+                    div { class: "w-20 h-20 rounded-full bg-green-100 flex items-center justify-center",
+                        i { class: "fa-solid fa-user-shield text-3xl text-green-700" }
+                    }
+                    h1 { class: "text-3xl font-black text-gray-900", "Administration" }
+                }
+
+                // Flikar
+                div { class: "flex gap-2 mb-6 border-b overflow-x-auto",
+                    for (index, (icon, label)) in (0_u8..).zip(tabs) {
+                        button {
+                            class: if active_tab() == index { "px-4 py-2 font-bold text-green-700 border-b-2 border-green-700 whitespace-nowrap" } else { "px-4 py-2 text-gray-500 hover:text-gray-700 whitespace-nowrap" },
+                            onclick: move |_| active_tab.set(index),
+                            i { class: "{icon} mr-2" }
+                            "{label}"
+                        }
+                    }
+                }
+
+                match active_tab() {
+                    0 => rsx! { AdminUsersTab {} },
+                    1 => rsx! { AdminCategoriesTab {} },
+                    2 => rsx! { AdminProductsTab {} },
+                    _ => rsx! { AdminModerationTab {} },
+                }
+            }
+        }
+    }
+}
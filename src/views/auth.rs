@@ -1,10 +1,11 @@
 #![allow(non_snake_case)]
 
-#[cfg(feature = "web")]
-use crate::database::{Id, RawId, User, login_info};
 use crate::{
     Route,
-    database::{Email, NewUserData, Username, create_user, log_in},
+    database::{
+        AppError, Email, NewUserData, Username, cart::cart_products, change_password, create_user,
+        log_in, login_info, reauthenticate, request_password_reset, reset_password, verify_email,
+    },
     state::GlobalState,
 };
 use dioxus::prelude::*;
@@ -62,29 +63,20 @@ pub fn Login() -> Element {
                                 };
                                 match log_in(username, pwd.into()).await {
                                     Ok(_) => {
-                                        #[cfg(feature = "web")]
+                                        if let Ok(Some(info)) = login_info().await {
+                                            global_state.write().login = Some(info);
+                                        }
+                                        // Gästkorgen har slagits ihop med kundens korg vid inloggningen.
+                                        let customer_id = global_state.read().customer_id();
+                                        if let Some(cid) = customer_id
+                                            && let Ok((products, _)) = cart_products(cid).await
                                         {
-                                            use web_sys::{HtmlDocument, wasm_bindgen::JsCast as _, window};
-                                            if let Some(window) = window()
-                                                && let Some(document) = window.document()
-                                                && let Ok(html) = document.dyn_into::<HtmlDocument>()
-                                                && let Ok(cookies) = html.cookie()
-                                                && let Some(value) = cookies
-                                                    .split(';')
-                                                    .filter_map(|pair| pair.split_once('='))
-                                                    .find(|(key, _)| key.trim() == "user_id")
-                                                    .map(|(_, value)| value.trim().to_string())
-                                                && let Ok(id) = value.parse::<RawId>()
-                                            {
-                                                if let Ok(info) = login_info(Id::<User>::from(id)).await {
-                                                    global_state.write().login = Some(info);
-                                                }
-                                            }
+                                            global_state.write().set_cart(&products);
                                         }
                                         let _unused = nav.push(Route::Home {});
                                     }
-                                    Err(_) => {
-                                        error_msg.set(Some("Fel användarnamn eller lösenord.".into()));
+                                    Err(e) => {
+                                        error_msg.set(Some(AppError::describe(&e)));
                                     }
                                 }
                                 loading.set(false);
@@ -96,6 +88,13 @@ pub fn Login() -> Element {
                             "Logga in"
                         }
                     }
+                    div { class: "text-right text-sm",
+                        Link {
+                            to: Route::ForgotPassword {},
+                            class: "text-gray-500 hover:text-green-700 hover:underline",
+                            "Glömt lösenordet?"
+                        }
+                    }
                 }
                 div { class: "text-center mt-6 text-sm text-gray-500",
                     "Har du inget konto? "
@@ -179,13 +178,13 @@ pub fn Register() -> Element {
                                     loading.set(false);
                                     return;
                                 };
-                                // TODO(auth): Profilbild — använd en default-URL tills vidare
+                                // TODO(auth): Profilbild — ingen tills vidare
                                 match create_user(
                                         username,
                                         email,
                                         pwd.into(),
                                         NewUserData::Customer {
-                                            profile_picture: String::new().into(),
+                                            profile_picture: None,
                                         },
                                     )
                                     .await
@@ -194,7 +193,7 @@ pub fn Register() -> Element {
                                         let _unused = nav.push(Route::Login {});
                                     }
                                     Err(e) => {
-                                        error_msg.set(Some(format!("Fel: {e}")));
+                                        error_msg.set(Some(AppError::describe(&e)));
                                     }
                                 }
                                 loading.set(false);
@@ -281,29 +280,13 @@ pub fn VendorLogin() -> Element {
                                 };
                                 match log_in(username, pwd.into()).await {
                                     Ok(_) => {
-                                        #[cfg(feature = "web")]
-                                        {
-                                            use web_sys::{HtmlDocument, wasm_bindgen::JsCast as _, window};
-                                            if let Some(window) = window()
-                                                && let Some(document) = window.document()
-                                                && let Ok(html) = document.dyn_into::<HtmlDocument>()
-                                                && let Ok(cookies) = html.cookie()
-                                                && let Some(value) = cookies
-                                                    .split(';')
-                                                    .filter_map(|pair| pair.split_once('='))
-                                                    .find(|(key, _)| key.trim() == "user_id")
-                                                    .map(|(_, value)| value.trim().to_string())
-                                                && let Ok(id) = value.parse::<RawId>()
-                                            {
-                                                if let Ok(info) = login_info(Id::<User>::from(id)).await {
-                                                    global_state.write().login = Some(info);
-                                                }
-                                            }
+                                        if let Ok(Some(info)) = login_info().await {
+                                            global_state.write().login = Some(info);
                                         }
                                         let _unused = nav.push(Route::Home {});
                                     }
-                                    Err(_) => {
-                                        error_msg.set(Some("Fel användarnamn eller lösenord.".into()));
+                                    Err(e) => {
+                                        error_msg.set(Some(AppError::describe(&e)));
                                     }
                                 }
                                 loading.set(false);
@@ -315,6 +298,13 @@ pub fn VendorLogin() -> Element {
                             "Logga in"
                         }
                     }
+                    div { class: "text-right text-sm",
+                        Link {
+                            to: Route::ForgotPassword {},
+                            class: "text-gray-500 hover:text-green-700 hover:underline",
+                            "Glömt lösenordet?"
+                        }
+                    }
                 }
                 div { class: "text-center mt-6 text-sm text-gray-500",
                     "Har du inget företagskonto? "
@@ -412,7 +402,7 @@ pub fn VendorRegister() -> Element {
                                         email,
                                         pwd.into(),
                                         NewUserData::Vendor {
-                                            profile_picture: String::new().into(),
+                                            profile_picture: None,
                                             display_name: dname.into(),
                                             description: "".into(),
                                         },
@@ -423,7 +413,7 @@ pub fn VendorRegister() -> Element {
                                         let _unused = nav.push(Route::VendorLogin {});
                                     }
                                     Err(e) => {
-                                        error_msg.set(Some(format!("Fel: {e}")));
+                                        error_msg.set(Some(AppError::describe(&e)));
                                     }
                                 }
                                 loading.set(false);
@@ -457,3 +447,346 @@ pub fn VendorRegister() -> Element {
     }
 }
 
+
+/// Begäran om återställning av lösenord
+#[component]
+pub fn ForgotPassword() -> Element {
+    let mut email_val = use_signal(String::new);
+    let mut error_msg = use_signal(|| None::<String>);
+    let mut sent = use_signal(|| false);
+    let mut loading = use_signal(|| false);
+
+    rsx! {
+        div { class: "min-h-screen bg-gray-50 flex items-center justify-center p-4",
+            div { class: "bg-white rounded-2xl shadow-sm p-8 w-full max-w-md",
+                div { class: "text-center mb-8",
+                    div { class: "text-4xl font-black italic text-green-700 mb-2", "boop" }
+                    h1 { class: "text-2xl font-black text-gray-900", "Glömt lösenordet?" }
+                }
+                if sent() {
+                    p { class: "text-green-700 text-sm bg-green-50 border border-green-200 rounded-lg p-3",
+                        i { class: "fa-solid fa-envelope mr-2" }
+                        "Om det finns ett konto med adressen har vi skickat en länk för att välja ett nytt lösenord. Länken är giltig i en timme."
+                    }
+                } else {
+                    div { class: "space-y-4",
+                        p { class: "text-sm text-gray-500",
+                            "Ange e-postadressen för ditt konto så skickar vi en länk för att välja ett nytt lösenord."
+                        }
+                        input {
+                            r#type: "email",
+                            placeholder: "E-post",
+                            class: "w-full border-2 border-gray-200 rounded-xl px-4 py-3",
+                            oninput: move |e| email_val.set(e.value()),
+                        }
+                        if let Some(err) = error_msg() {
+                            p { class: "text-red-500 text-sm", "{err}" }
+                        }
+                        button {
+                            class: "w-full bg-green-700 text-white font-black py-3 rounded-full disabled:opacity-50",
+                            disabled: loading(),
+                            onclick: move |_| {
+                                let mail = email_val();
+                                let _task = spawn(async move {
+                                    loading.set(true);
+                                    error_msg.set(None);
+                                    let Some(email) = Email::new(mail.into()) else {
+                                        error_msg.set(Some("Ogiltig e-postadress.".into()));
+                                        loading.set(false);
+                                        return;
+                                    };
+                                    match request_password_reset(email).await {
+                                        Ok(()) => sent.set(true),
+                                        Err(_) => error_msg.set(Some("Mejlet kunde inte skickas. Försök igen senare.".into())),
+                                    }
+                                    loading.set(false);
+                                });
+                            },
+                            if loading() {
+                                "Skickar..."
+                            } else {
+                                "Skicka länk"
+                            }
+                        }
+                    }
+                }
+                div { class: "text-center mt-6 text-sm text-gray-500",
+                    Link {
+                        to: Route::Login {},
+                        class: "text-green-700 font-bold hover:underline",
+                        "Tillbaka till inloggningen"
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Val av nytt lösenord via länk från mejl
+#[component]
+pub fn ResetPassword(token: String) -> Element {
+    let nav = use_navigator();
+
+    let mut password_val = use_signal(String::new);
+    let mut repeat_val = use_signal(String::new);
+    let mut error_msg = use_signal(|| None::<String>);
+    let mut loading = use_signal(|| false);
+
+    rsx! {
+        div { class: "min-h-screen bg-gray-50 flex items-center justify-center p-4",
+            div { class: "bg-white rounded-2xl shadow-sm p-8 w-full max-w-md",
+                div { class: "text-center mb-8",
+                    div { class: "text-4xl font-black italic text-green-700 mb-2", "boop" }
+                    h1 { class: "text-2xl font-black text-gray-900", "Välj ett nytt lösenord" }
+                }
+                div { class: "space-y-4",
+                    input {
+                        r#type: "password",
+                        placeholder: "Nytt lösenord",
+                        class: "w-full border-2 border-gray-200 rounded-xl px-4 py-3",
+                        oninput: move |e| password_val.set(e.value()),
+                    }
+                    input {
+                        r#type: "password",
+                        placeholder: "Upprepa lösenordet",
+                        class: "w-full border-2 border-gray-200 rounded-xl px-4 py-3",
+                        oninput: move |e| repeat_val.set(e.value()),
+                    }
+                    if let Some(err) = error_msg() {
+                        p { class: "text-red-500 text-sm", "{err}" }
+                    }
+                    button {
+                        class: "w-full bg-green-700 text-white font-black py-3 rounded-full disabled:opacity-50",
+                        disabled: loading(),
+                        onclick: move |_| {
+                            let pwd = password_val();
+                            if pwd.is_empty() || pwd != repeat_val() {
+                                error_msg.set(Some("Lösenorden matchar inte.".into()));
+                                return;
+                            }
+                            let token = token.clone();
+                            let _task = spawn(async move {
+                                loading.set(true);
+                                error_msg.set(None);
+                                match reset_password(token.into(), pwd.into()).await {
+                                    Ok(()) => {
+                                        let _unused = nav.push(Route::Login {});
+                                    }
+                                    Err(_) => {
+                                        error_msg.set(Some("Länken är ogiltig eller har gått ut. Begär en ny länk.".into()));
+                                    }
+                                }
+                                loading.set(false);
+                            });
+                        },
+                        if loading() {
+                            "Sparar..."
+                        } else {
+                            "Spara lösenord"
+                        }
+                    }
+                }
+                div { class: "text-center mt-6 text-sm text-gray-500",
+                    Link {
+                        to: Route::ForgotPassword {},
+                        class: "text-green-700 font-bold hover:underline",
+                        "Begär en ny länk"
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Bekräftelse av e-postadress via länk från mejl
+#[component]
+pub fn VerifyEmail(token: String) -> Element {
+    let result = use_resource(move || {
+        let token = token.clone();
+        async move { verify_email(token.into()).await.is_ok() }
+    });
+
+    rsx! {
+        div { class: "min-h-screen bg-gray-50 flex items-center justify-center p-4",
+            div { class: "bg-white rounded-2xl shadow-sm p-8 w-full max-w-md text-center",
+                div { class: "text-4xl font-black italic text-green-700 mb-6", "boop" }
+                match result() {
+                    None => rsx! {
+                        p { class: "text-gray-500",
+                            i { class: "fa-solid fa-spinner fa-spin mr-2" }
+                            "Bekräftar e-postadressen..."
+                        }
+                    },
+                    Some(true) => rsx! {
+                        h1 { class: "text-2xl font-black text-gray-900 mb-2", "E-postadressen är bekräftad" }
+                        p { class: "text-gray-500 mb-6", "Tack! Ditt konto är nu klart att användas." }
+                    },
+                    Some(false) => rsx! {
+                        h1 { class: "text-2xl font-black text-gray-900 mb-2", "Länken fungerar inte" }
+                        p { class: "text-gray-500 mb-6",
+                            "Länken är ogiltig, har redan använts eller har gått ut. Logga in för att få en ny."
+                        }
+                    },
+                }
+                Link {
+                    to: Route::Home {},
+                    class: "text-green-700 font-bold hover:underline",
+                    "Till startsidan"
+                }
+            }
+        }
+    }
+}
+
+/// Byte av lösenord för inloggad användare
+#[component]
+pub fn ChangePassword() -> Element {
+    let mut current_val = use_signal(String::new);
+    let mut password_val = use_signal(String::new);
+    let mut repeat_val = use_signal(String::new);
+    let mut error_msg = use_signal(|| None::<String>);
+    let mut changed = use_signal(|| false);
+    let mut loading = use_signal(|| false);
+
+    rsx! {
+        div { class: "min-h-screen bg-gray-50 flex items-center justify-center p-4",
+            div { class: "bg-white rounded-2xl shadow-sm p-8 w-full max-w-md",
+                div { class: "text-center mb-8",
+                    div { class: "text-4xl font-black italic text-green-700 mb-2", "boop" }
+                    h1 { class: "text-2xl font-black text-gray-900", "Byt lösenord" }
+                }
+                if changed() {
+                    p { class: "text-green-700 text-sm bg-green-50 border border-green-200 rounded-lg p-3",
+                        i { class: "fa-solid fa-check mr-2" }
+                        "Lösenordet är bytt. Du har loggats ut på alla andra enheter."
+                    }
+                } else {
+                    div { class: "space-y-4",
+                        input {
+                            r#type: "password",
+                            placeholder: "Nuvarande lösenord",
+                            class: "w-full border-2 border-gray-200 rounded-xl px-4 py-3",
+                            oninput: move |e| current_val.set(e.value()),
+                        }
+                        input {
+                            r#type: "password",
+                            placeholder: "Nytt lösenord",
+                            class: "w-full border-2 border-gray-200 rounded-xl px-4 py-3",
+                            oninput: move |e| password_val.set(e.value()),
+                        }
+                        input {
+                            r#type: "password",
+                            placeholder: "Upprepa det nya lösenordet",
+                            class: "w-full border-2 border-gray-200 rounded-xl px-4 py-3",
+                            oninput: move |e| repeat_val.set(e.value()),
+                        }
+                        if let Some(err) = error_msg() {
+                            p { class: "text-red-500 text-sm", "{err}" }
+                        }
+                        button {
+                            class: "w-full bg-green-700 text-white font-black py-3 rounded-full disabled:opacity-50",
+                            disabled: loading(),
+                            onclick: move |_| {
+                                let pwd = password_val();
+                                if pwd.is_empty() || pwd != repeat_val() {
+                                    error_msg.set(Some("Lösenorden matchar inte.".into()));
+                                    return;
+                                }
+                                let current = current_val();
+                                let _task = spawn(async move {
+                                    loading.set(true);
+                                    error_msg.set(None);
+                                    match change_password(current.into(), pwd.into()).await {
+                                        Ok(()) => changed.set(true),
+                                        Err(e) if AppError::from_error(&e) == Some(AppError::IncorrectPassword) => {
+                                            error_msg.set(Some("Fel nuvarande lösenord.".into()));
+                                        }
+                                        Err(e) => error_msg.set(Some(AppError::describe(&e))),
+                                    }
+                                    loading.set(false);
+                                });
+                            },
+                            if loading() {
+                                "Sparar..."
+                            } else {
+                                "Byt lösenord"
+                            }
+                        }
+                    }
+                }
+                div { class: "text-center mt-6 text-sm text-gray-500",
+                    Link {
+                        to: Route::Home {},
+                        class: "text-green-700 font-bold hover:underline",
+                        "Till startsidan"
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Bekräftelse av lösenord inför känsliga ändringar. Stängs med `true` om lösenordet bekräftades.
+#[component]
+pub fn ConfirmPassword(on_close: EventHandler<bool>) -> Element {
+    let mut password_val = use_signal(String::new);
+    let mut error_msg = use_signal(|| None::<String>);
+    let mut loading = use_signal(|| false);
+
+    rsx! {
+        div {
+            class: "fixed inset-0 bg-black/50 z-50 flex items-center justify-center p-4",
+            onclick: move |_| on_close.call(false),
+            div {
+                class: "bg-white rounded-2xl shadow-2xl w-full max-w-sm",
+                onclick: move |e| e.stop_propagation(),
+                div { class: "p-6 border-b flex justify-between items-center",
+                    h2 { class: "text-xl font-black text-gray-900",
+                        i { class: "fa-solid fa-lock text-green-700 mr-2" }
+                        "Bekräfta lösenord"
+                    }
+                    button {
+                        class: "text-gray-400 hover:text-gray-600 transition",
+                        onclick: move |_| on_close.call(false),
+                        i { class: "fa-solid fa-xmark text-xl" }
+                    }
+                }
+                div { class: "p-6 space-y-4",
+                    p { class: "text-sm text-gray-500",
+                        "Ange ditt lösenord igen för att fortsätta."
+                    }
+                    input {
+                        r#type: "password",
+                        placeholder: "Lösenord",
+                        class: "w-full border-2 border-gray-200 rounded-xl px-4 py-3",
+                        oninput: move |e| password_val.set(e.value()),
+                    }
+                    if let Some(err) = error_msg() {
+                        p { class: "text-red-500 text-sm", "{err}" }
+                    }
+                    button {
+                        class: "w-full bg-green-700 text-white font-black py-3 rounded-full disabled:opacity-50",
+                        disabled: loading(),
+                        onclick: move |_| {
+                            let pwd = password_val();
+                            let _task = spawn(async move {
+                                loading.set(true);
+                                error_msg.set(None);
+                                match reauthenticate(pwd.into()).await {
+                                    Ok(()) => on_close.call(true),
+                                    Err(e) => error_msg.set(Some(AppError::describe(&e))),
+                                }
+                                loading.set(false);
+                            });
+                        },
+                        if loading() {
+                            "Bekräftar..."
+                        } else {
+                            "Bekräfta"
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
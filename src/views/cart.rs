@@ -1,8 +1,79 @@
 #![allow(non_snake_case)]
 use crate::Route;
-use crate::database::cart::{cart_products, checkout, set_in_shopping_cart};
+use crate::components::product_card::offer_label;
+use crate::database::cart::{
+    cart_quote, checkout, set_in_shopping_cart, CheckoutItem, CheckoutOutcome, Quote,
+    StaleLine,
+};
+use crate::database::delivery::{
+    customer_addresses, shipping_options, PostalAddress, Shipment, ShippingOption,
+};
+use crate::database::payments::CardNumber;
+use crate::database::promotions::{promotion, PromotionInfo, PromotionUse};
+use crate::database::{AppError, Address, Customer, Id, Order, ShippingMethod, Vendor};
 use crate::state::GlobalState;
 use dioxus::prelude::*;
+use rust_decimal::Decimal;
+use std::collections::HashMap;
+use time::PrimitiveDateTime;
+
+/// Rader som inte längre stämmer, och när deras nya uppgifter hämtades.
+type Stale = Option<(Box<[StaleLine]>, PrimitiveDateTime)>;
+
+/// Fraktalternativen i kundvagnen, ordnade per säljare med det billigaste först.
+type ShippingOptions = Option<Box<[ShippingOption]>>;
+
+/// Välj ett fraktsätt per säljare: det kunden valt om det finns kvar, annars det billigaste.
+fn pick_shipments(
+    options: &[ShippingOption],
+    chosen: &HashMap<Id<Vendor>, Id<ShippingMethod>>,
+) -> Vec<Shipment> {
+    options
+        .chunk_by(|a, b| a.vendor == b.vendor)
+        .filter_map(|vendor_options| {
+            vendor_options
+                .iter()
+                .find(|o| chosen.get(&o.vendor) == Some(&o.method.id))
+                .or_else(|| vendor_options.first())
+        })
+        .map(Shipment::from)
+        .collect()
+}
+
+/// Genomför köpet och visa resultatet.
+#[allow(clippy::too_many_arguments, reason = "Alla signaler som köpet påverkar.")]
+async fn place_order(
+    cid: Id<Customer>,
+    items: Vec<CheckoutItem>,
+    seen_at: PrimitiveDateTime,
+    address: Id<Address>,
+    shipments: Vec<Shipment>,
+    promotion: Option<PromotionUse>,
+    card: CardNumber,
+    mut shipping: Resource<ShippingOptions>,
+    mut global_state: Signal<GlobalState>,
+    mut placed_order: Signal<Option<Id<Order>>>,
+    mut stale: Signal<Stale>,
+    mut checkout_error: Signal<Option<String>>,
+    mut checking_out: Signal<bool>,
+) {
+    match checkout(cid, items, seen_at, address, shipments, promotion, card).await {
+        Ok(CheckoutOutcome::Placed(order)) => {
+            placed_order.set(Some(order));
+            global_state.write().cart.clear();
+        }
+        Ok(CheckoutOutcome::Stale { lines, seen_at }) => {
+            stale.set(Some((lines, seen_at)));
+            checking_out.set(false);
+        }
+        Err(e) => {
+            // Fraktavgifterna kan ha ändrats, så visa de nya.
+            shipping.restart();
+            checkout_error.set(Some(AppError::describe(&e)));
+            checking_out.set(false);
+        }
+    }
+}
  
 /// Kundvagnssida med checkout.
 #[component]
@@ -10,8 +81,10 @@ pub fn CartPage() -> Element {
     let mut global_state = use_context::<Signal<GlobalState>>();
  
     let mut checkout_error = use_signal(|| None::<String>);
-    let mut checkout_done  = use_signal(|| false);
+    let placed_order       = use_signal(|| None);
     let mut checking_out   = use_signal(|| false);
+    // Avvikelser från senaste köpförsöket.
+    let mut stale          = use_signal(|| None);
  
     // Läs reaktivt från global state
     let auth_loading = global_state.read().auth_loading;
@@ -23,7 +96,7 @@ pub fn CartPage() -> Element {
         let cid = global_state.read().customer_id();
         if !auth_loading {
             if let Some(cid) = cid {
-                cart_products(cid).await.ok()
+                cart_quote(cid).await.ok()
             } else {
                 None
             }
@@ -32,11 +105,50 @@ pub fn CartPage() -> Element {
         }
     });
  
+    // Hämta leveransadresser och fraktalternativ för varorna i kundvagnen
+    let addresses_resource = use_resource(move || async move {
+        let cid = global_state.read().customer_id();
+        match cid {
+            Some(cid) => customer_addresses(cid).await.ok(),
+            None => None,
+        }
+    });
+    let shipping_resource = use_resource(move || async move {
+        let items = cart_resource.read().as_ref().and_then(|q| q.as_ref().map(Quote::items));
+        match items {
+            Some(items) if !items.is_empty() => shipping_options(items).await.ok(),
+            _ => None,
+        }
+    });
+    let mut chosen_address = use_signal(|| None::<Id<Address>>);
+    let mut chosen_methods = use_signal(HashMap::<Id<Vendor>, Id<ShippingMethod>>::new);
+    let mut card_number    = use_signal(String::new);
+    let mut promotion_code = use_signal(String::new);
+    let mut applied_promotion = use_signal(|| None::<PromotionInfo>);
+    let mut promotion_error   = use_signal(|| None::<String>);
+
+    let addresses: Box<[(Id<Address>, PostalAddress)]> =
+        addresses_resource.read().clone().flatten().unwrap_or_default();
+    let address = chosen_address()
+        .filter(|a| addresses.iter().any(|(id, _)| id == a))
+        .or_else(|| addresses.first().map(|(id, _)| *id));
+    let options: Box<[ShippingOption]> = shipping_resource.read().clone().flatten().unwrap_or_default();
+    let shipments = pick_shipments(&options, &chosen_methods.read());
+    let shipping_fee: Decimal = shipments.iter().map(|s| s.expected_fee).sum();
+    let vendor_options: Vec<Vec<ShippingOption>> = options
+        .chunk_by(|a, b| a.vendor == b.vendor)
+        .map(<[ShippingOption]>::to_vec)
+        .collect();
+
     let cart_read  = cart_resource.read();
     let cart_loading = cart_read.is_none() || auth_loading;
-    let cart_tuple: Option<(Box<[crate::database::cart::CartProduct]>, time::PrimitiveDateTime)> =
-        cart_read.as_ref().and_then(|r| r.clone());
-    let cart_empty = cart_tuple.as_ref().map(|(p, _)| p.is_empty()).unwrap_or(false);
+    let loaded: Option<Quote> = (*cart_read).clone().flatten();
+    let cart_empty = loaded.as_ref().is_some_and(|q| q.lines.is_empty());
+    // Rabattkoden gäller bara om varorna når upp till minsta köpesumman.
+    let promotion_discount = loaded
+        .as_ref()
+        .zip(applied_promotion.read().as_ref())
+        .and_then(|(q, p)| p.discount(q.total, shipping_fee));
  
     rsx! {
         div { class: "min-h-screen bg-gray-50",
@@ -52,13 +164,13 @@ pub fn CartPage() -> Element {
                     "Kundvagn"
                 }
 
-                if checkout_done() {
+                if let Some(order) = placed_order() {
                     div { class: "bg-green-50 border border-green-200 rounded-2xl p-8 text-center",
                         i { class: "fa-solid fa-circle-check text-5xl text-green-600 mb-4" }
                         h2 { class: "text-2xl font-black text-green-900 mb-2",
                             "Tack för din beställning!"
                         }
-                        p { class: "text-green-700 mb-6", "Din order har lagts." }
+                        p { class: "text-green-700 mb-6", "Din order #{order} har lagts." }
                         Link {
                             to: Route::Home {},
                             class: "bg-green-700 text-white font-black px-8 py-3 rounded-full hover:bg-green-800 transition",
@@ -80,24 +192,18 @@ pub fn CartPage() -> Element {
                                 "Börja handla"
                             }
                         }
-                    } else if let Some((products, cart_time)) = cart_tuple {
+                    } else if let Some(quote) = loaded {
                         {
-                            let total: rust_decimal::Decimal = products
-                                .iter()
-                                .map(|p| {
-                                    let ep = p
-                                        .special_offer_deal
-                                        .as_ref()
-                                        .map(|d| d.average_discount(p.price))
-                                        .unwrap_or(p.price);
-                                    ep * rust_decimal::Decimal::from(p.count.get())
-                                })
-                                .sum();
                             rsx! {
                                 div { class: "grid grid-cols-1 lg:grid-cols-3 gap-6",
                                     div { class: "lg:col-span-2 space-y-3",
-                                        for product in products.iter() {
-                                            div { class: "bg-white rounded-2xl shadow-sm p-4 flex items-center gap-4",
+                                        for (product, quoted, line) in quote.lines.iter().map(|q| {
+                                            let line = stale.read().as_ref().and_then(|(lines, _): &(Box<[StaleLine]>, _)| {
+                                                lines.iter().find(|l| l.item.product == q.product.id).cloned()
+                                            });
+                                            (&q.product, q, line)
+                                        }) {
+                                            div { class: if line.is_some() { "bg-orange-50 border-2 border-orange-300 rounded-2xl shadow-sm p-4 flex items-center gap-4" } else { "bg-white rounded-2xl shadow-sm p-4 flex items-center gap-4" },
                                                 Link {
                                                     to: Route::Product {
                                                         id: product.id.into(),
@@ -117,11 +223,25 @@ pub fn CartPage() -> Element {
                                                             "{product.name}"
                                                         }
                                                     }
-                                                    if let Some(deal) = &product.special_offer_deal {
+                                                    if quoted.price < quoted.base_price() {
                                                         p { class: "text-green-600 font-black text-sm",
-                                                            "{deal.average_discount(product.price):.2} kr"
+                                                            "{quoted.price:.2} kr"
                                                             span { class: "line-through text-gray-400 ml-1 text-xs font-normal",
-                                                                "{product.price:.2} kr"
+                                                                "{quoted.base_price():.2} kr"
+                                                            }
+                                                        }
+                                                        if product.unit_price() < product.price {
+                                                            p { class: "text-green-600 text-xs", "Mängdpris {product.unit_price():.2} kr/st" }
+                                                        }
+                                                        if quoted.offer_uses > 0 {
+                                                            if let Some(label) = offer_label(product.special_offer_deal, product.price) {
+                                                                p { class: "text-green-600 text-xs", "{label}" }
+                                                            }
+                                                        }
+                                                        // Hur enheterna fördelats på paketerbjudanden.
+                                                        for share in quoted.bundles.iter() {
+                                                            p { class: "text-green-600 text-xs",
+                                                                "{share.units} st i {share.name} ({share.price:.2} kr)"
                                                             }
                                                         }
                                                     } else {
@@ -130,6 +250,28 @@ pub fn CartPage() -> Element {
                                                     if product.in_stock < 5 && product.in_stock > 0 {
                                                         p { class: "text-orange-500 text-xs mt-1", "Endast {product.in_stock} kvar!" }
                                                     }
+                                                    if let Some(line) = &line {
+                                                        div { class: "text-orange-700 text-xs mt-1 space-y-0.5",
+                                                            for reason in line.reasons.iter() {
+                                                                p { "{reason.localized()}" }
+                                                            }
+                                                            if let Some(current) = &line.current {
+                                                                if current.price != product.price {
+                                                                    p { class: "font-bold", "Nytt pris: {current.price:.2} kr/st" }
+                                                                }
+                                                                if current.special_offer_deal != product.special_offer_deal {
+                                                                    if let Some(label) = offer_label(current.special_offer_deal, current.price) {
+                                                                        p { class: "font-bold", "Nytt erbjudande: {label}" }
+                                                                    } else {
+                                                                        p { class: "font-bold", "Erbjudandet gäller inte längre." }
+                                                                    }
+                                                                }
+                                                                if line.out_of_stock() {
+                                                                    p { class: "font-bold", "Endast {current.in_stock} i lager." }
+                                                                }
+                                                            }
+                                                        }
+                                                    }
                                                 }
                                                 div { class: "flex items-center gap-2 shrink-0",
                                                     button {
@@ -140,6 +282,7 @@ pub fn CartPage() -> Element {
                                                             move |_| {
                                                                 let new_count = count.saturating_sub(1);
                                                                 global_state.write().set_quantity(pid.get(), new_count);
+                                                                stale.set(None);
                                                                 #[allow(unused_results)]
                                                                 spawn(async move {
                                                                     drop(set_in_shopping_cart(cid, pid, new_count).await);
@@ -158,6 +301,7 @@ pub fn CartPage() -> Element {
                                                             move |_| {
                                                                 let new_count = count + 1;
                                                                 global_state.write().set_quantity(pid.get(), new_count);
+                                                                stale.set(None);
                                                                 #[allow(unused_results)]
                                                                 spawn(async move {
                                                                     drop(set_in_shopping_cart(cid, pid, new_count).await);
@@ -174,6 +318,7 @@ pub fn CartPage() -> Element {
                                                         let pid = product.id;
                                                         move |_| {
                                                             global_state.write().remove_from_cart(pid.get());
+                                                            stale.set(None);
                                                             #[allow(unused_results)]
                                                             spawn(async move {
                                                                 drop(set_in_shopping_cart(cid, pid, 0).await);
@@ -190,71 +335,269 @@ pub fn CartPage() -> Element {
                                     div { class: "bg-white rounded-2xl shadow-sm p-6 h-fit",
                                         h2 { class: "font-black text-gray-900 text-lg mb-4", "Ordersammanfattning" }
                                         div { class: "space-y-2 mb-4",
-                                            for product in products.iter() {
+                                            for quoted in quote.lines.iter() {
                                                 div { class: "flex justify-between text-sm text-gray-600",
-                                                    span { class: "truncate mr-2", "{product.name} × {product.count}" }
-                                                    span { class: "shrink-0 font-semibold",
-                                                        {
-                                                            let p = product
-                                                                .special_offer_deal
-                                                                .as_ref()
-                                                                .map(|d| d.average_discount(product.price))
-                                                                .unwrap_or(product.price);
-                                                            let line = p * rust_decimal::Decimal::from(product.count.get());
-                                                            format!("{line:.2} kr")
+                                                    span { class: "truncate mr-2", "{quoted.product.name} × {quoted.product.count}" }
+                                                    span { class: "shrink-0 font-semibold", "{quoted.price:.2} kr" }
+                                                }
+                                            }
+                                        }
+                                        if !quote.discount.is_zero() {
+                                            div { class: "border-t pt-4 space-y-1 text-sm",
+                                                div { class: "flex justify-between text-gray-600",
+                                                    span { "Delsumma" }
+                                                    span { "{quote.subtotal:.2} kr" }
+                                                }
+                                                div { class: "flex justify-between text-green-700 font-semibold",
+                                                    span { "Rabatt" }
+                                                    span { "−{quote.discount:.2} kr" }
+                                                }
+                                            }
+                                        }
+                                        div { class: "border-t pt-4 space-y-3 mb-4 text-sm",
+                                            h3 { class: "font-bold text-gray-700", "Leverans" }
+                                            if addresses.is_empty() {
+                                                p { class: "text-gray-500",
+                                                    "Du har ingen leveransadress. "
+                                                    Link {
+                                                        to: Route::CustomerProfile {},
+                                                        class: "text-green-700 font-bold hover:underline",
+                                                        "Lägg till en adress"
+                                                    }
+                                                }
+                                            } else {
+                                                select {
+                                                    class: "w-full border border-gray-200 rounded-lg px-3 py-2",
+                                                    onchange: {
+                                                        let ids: Vec<Id<Address>> = addresses.iter().map(|(id, _)| *id).collect();
+                                                        move |e: Event<FormData>| {
+                                                            let chosen = e.value().parse::<usize>().ok().and_then(|i| ids.get(i).copied());
+                                                            chosen_address.set(chosen);
+                                                        }
+                                                    },
+                                                    for (i, (id, a)) in addresses.iter().enumerate() {
+                                                        option {
+                                                            value: "{i}",
+                                                            selected: address == Some(*id),
+                                                            "{a.recipient}, {a.street}, {a.postal_code} {a.city}"
+                                                        }
+                                                    }
+                                                }
+                                            }
+                                            for group in vendor_options.into_iter() {
+                                                div {
+                                                    p { class: "text-gray-600 mb-1", "Frakt från {group[0].vendor_name}" }
+                                                    select {
+                                                        class: "w-full border border-gray-200 rounded-lg px-3 py-2",
+                                                        onchange: {
+                                                            let choices: Vec<_> = group.iter().map(|o| (o.vendor, o.method.id)).collect();
+                                                            move |e: Event<FormData>| {
+                                                                if let Some(&(vendor, method)) = e.value().parse::<usize>().ok().and_then(|i| choices.get(i)) {
+                                                                    let _previous = chosen_methods.write().insert(vendor, method);
+                                                                }
+                                                            }
+                                                        },
+                                                        for (i, o) in group.iter().enumerate() {
+                                                            option {
+                                                                value: "{i}",
+                                                                selected: shipments.iter().any(|s| s.method == o.method.id),
+                                                                "{o.method.name} – {o.fee:.2} kr"
+                                                            }
                                                         }
                                                     }
                                                 }
                                             }
                                         }
-                                        div { class: "border-t pt-4 flex justify-between items-center mb-6",
-                                            span { class: "font-bold text-gray-700", "Totalt" }
-                                            span { class: "font-black text-2xl text-gray-900", "{total:.2} kr" }
+                                        div { class: "border-t pt-4 space-y-2 mb-4 text-sm",
+                                            h3 { class: "font-bold text-gray-700", "Rabattkod" }
+                                            if let Some(p) = applied_promotion() {
+                                                div { class: "flex justify-between items-center bg-green-50 rounded-lg px-3 py-2",
+                                                    span { class: "font-mono font-bold text-green-800", "{p.code}" }
+                                                    button {
+                                                        class: "text-gray-400 hover:text-red-500 transition",
+                                                        onclick: move |_| applied_promotion.set(None),
+                                                        i { class: "fa-solid fa-xmark" }
+                                                    }
+                                                }
+                                                if promotion_discount.is_none() {
+                                                    p { class: "text-orange-600 text-xs",
+                                                        "Koden gäller när varorna kostar minst {p.minimum_spend:.2} kr."
+                                                    }
+                                                }
+                                            } else {
+                                                div { class: "flex gap-2",
+                                                    input {
+                                                        class: "flex-grow border border-gray-200 rounded-lg px-3 py-2 font-mono uppercase",
+                                                        placeholder: "Rabattkod",
+                                                        value: "{promotion_code}",
+                                                        oninput: move |e| promotion_code.set(e.value()),
+                                                    }
+                                                    button {
+                                                        class: "bg-gray-100 text-gray-700 font-bold px-4 rounded-lg hover:bg-gray-200 transition",
+                                                        disabled: promotion_code.read().trim().is_empty(),
+                                                        onclick: move |_| async move {
+                                                            promotion_error.set(None);
+                                                            match promotion(cid, promotion_code().into()).await {
+                                                                Ok(Some(p)) => {
+                                                                    applied_promotion.set(Some(p));
+                                                                    promotion_code.set(String::new());
+                                                                }
+                                                                Ok(None) => promotion_error.set(Some("Rabattkoden gäller inte.".into())),
+                                                                Err(e) => promotion_error.set(Some(AppError::describe(&e))),
+                                                            }
+                                                        },
+                                                        "Använd"
+                                                    }
+                                                }
+                                                if let Some(err) = promotion_error() {
+                                                    p { class: "text-red-500 text-xs", "{err}" }
+                                                }
+                                            }
+                                        }
+                                        div { class: "border-t pt-4 space-y-1 mb-6",
+                                            div { class: "flex justify-between text-sm text-gray-600",
+                                                span { "Frakt" }
+                                                span { "{shipping_fee:.2} kr" }
+                                            }
+                                            if let Some(discount) = promotion_discount {
+                                                div { class: "flex justify-between text-sm text-green-700 font-semibold",
+                                                    span { "Rabattkod" }
+                                                    span { "−{discount:.2} kr" }
+                                                }
+                                            }
+                                            div { class: "flex justify-between items-center",
+                                                span { class: "font-bold text-gray-700", "Totalt" }
+                                                span { class: "font-black text-2xl text-gray-900",
+                                                    {format!("{:.2} kr", quote.total + shipping_fee - promotion_discount.unwrap_or_default())}
+                                                }
+                                            }
+                                        }
+                                        div { class: "space-y-2 mb-4 text-sm",
+                                            h3 { class: "font-bold text-gray-700", "Betalning" }
+                                            input {
+                                                class: "w-full border border-gray-200 rounded-lg px-3 py-2 font-mono",
+                                                placeholder: "Kortnummer",
+                                                inputmode: "numeric",
+                                                autocomplete: "cc-number",
+                                                value: "{card_number}",
+                                                oninput: move |e| card_number.set(e.value()),
+                                            }
+                                            p { class: "text-xs text-gray-400",
+                                                "Inga riktiga betalningar görs. Använd testkortet 4242 4242 4242 4242."
+                                            }
                                         }
                                         if let Some(err) = checkout_error() {
                                             p { class: "text-red-500 text-sm mb-3 text-center bg-red-50 p-2 rounded-lg",
                                                 "{err}"
                                             }
                                         }
+                                        if let Some((lines, _)) = stale() {
+                                            div { class: "bg-orange-50 text-orange-700 text-sm mb-3 p-3 rounded-lg text-center",
+                                                p { class: "mb-2",
+                                                    "Några varor har ändrats sedan du öppnade kundvagnen. Kontrollera de markerade raderna."
+                                                }
+                                                button {
+                                                    class: "w-full bg-orange-500 text-white py-2 rounded-lg font-black hover:bg-orange-600 transition disabled:opacity-50",
+                                                    disabled: checking_out(),
+                                                    onclick: move |_| {
+                                                        let Some(card) = CardNumber::new(&card_number()) else {
+                                                            checkout_error.set(Some("Ogiltigt kortnummer.".into()));
+                                                            return;
+                                                        };
+                                                        let lines = lines.clone();
+                                                        stale.set(None);
+                                                        checking_out.set(true);
+                                                        checkout_error.set(None);
+                                                        #[allow(unused_results, reason = "Köpet körs i bakgrunden.")]
+                                                        spawn(async move {
+                                                            // Minska antalet till det som finns i lager, och ta bort varor som inte säljs längre.
+                                                            for line in &lines {
+                                                                let count = line.current.as_ref().map_or(0, |c| c.count.get().min(c.in_stock));
+                                                                if count != line.item.number.get() {
+                                                                    global_state.write().set_quantity(line.item.product.get(), count);
+                                                                    drop(set_in_shopping_cart(cid, line.item.product, count).await);
+                                                                }
+                                                            }
+                                                            cart_resource.restart();
+                                                            let Some(address) = address else {
+                                                                checking_out.set(false);
+                                                                return;
+                                                            };
+                                                            match cart_quote(cid).await {
+                                                                Ok(fresh) if fresh.lines.is_empty() => {
+                                                                    checkout_error.set(Some("Inga av varorna finns kvar att köpa.".into()));
+                                                                    checking_out.set(false);
+                                                                }
+                                                                Ok(fresh) => {
+                                                                    // Fraktavgifterna beror på varorna, så hämta dem på nytt.
+                                                                    let fresh_shipments = match shipping_options(fresh.items()).await {
+                                                                        Ok(fresh_options) => pick_shipments(&fresh_options, &chosen_methods.read()),
+                                                                        Err(e) => {
+                                                                            checkout_error.set(Some(AppError::describe(&e)));
+                                                                            checking_out.set(false);
+                                                                            return;
+                                                                        }
+                                                                    };
+                                                                    // Rabatten beror också på de nya priserna.
+                                                                    let fresh_fee = fresh_shipments.iter().map(|s| s.expected_fee).sum();
+                                                                    let promotion = applied_promotion.read().as_ref().and_then(|p| p.use_on(fresh.total, fresh_fee));
+                                                                    place_order(
+                                                                        cid,
+                                                                        fresh.items(),
+                                                                        fresh.seen_at,
+                                                                        address,
+                                                                        fresh_shipments,
+                                                                        promotion,
+                                                                        card,
+                                                                        shipping_resource,
+                                                                        global_state,
+                                                                        placed_order,
+                                                                        stale,
+                                                                        checkout_error,
+                                                                        checking_out,
+                                                                    )
+                                                                    .await;
+                                                                }
+                                                                Err(e) => {
+                                                                    checkout_error.set(Some(AppError::describe(&e)));
+                                                                    checking_out.set(false);
+                                                                }
+                                                            }
+                                                        });
+                                                    },
+                                                    "Godkänn nya priser och försök igen"
+                                                }
+                                            }
+                                        }
                                         button {
                                             class: if checking_out() { "w-full bg-gray-300 text-gray-500 py-4 rounded-xl font-black text-lg cursor-not-allowed flex items-center justify-center gap-2" } else { "w-full bg-green-700 text-white py-4 rounded-xl font-black text-lg hover:bg-green-800 transition flex items-center justify-center gap-2" },
-                                            disabled: checking_out(),
+                                            disabled: checking_out() || stale.read().is_some() || address.is_none(),
                                             onclick: move |_| {
+                                                let Some(address) = address else {
+                                                    return;
+                                                };
+                                                let Some(card) = CardNumber::new(&card_number()) else {
+                                                    checkout_error.set(Some("Ogiltigt kortnummer.".into()));
+                                                    return;
+                                                };
                                                 checking_out.set(true);
                                                 checkout_error.set(None);
-                                                let items: Vec<crate::database::cart::CheckoutItem> = products
-                                                    .iter()
-                                                    .map(|p| crate::database::cart::CheckoutItem {
-                                                        product: p.id,
-                                                        number: p.count,
-                                                        special_offer: p.special_offer_id,
-                                                        expected_price: p
-                                                            .special_offer_deal
-                                                            .as_ref()
-                                                            .map(|d| {
-                                                                d.discounted_price(
-                                                                        p.count,
-                                                                        p.price,
-                                                                        p.special_offer_remaining_uses,
-                                                                    )
-                                                                    .0
-                                                            })
-                                                            .unwrap_or(p.price * rust_decimal::Decimal::from(p.count.get())),
-                                                    })
-                                                    .collect();
                                                 #[allow(unused_results)]
-                                                spawn(async move {
-                                                    match checkout(cid, items, cart_time).await {
-                                                        Ok(()) => {
-                                                            checkout_done.set(true);
-                                                            global_state.write().cart.clear();
-                                                        }
-                                                        Err(e) => {
-                                                            checkout_error.set(Some(e.to_string()));
-                                                            checking_out.set(false);
-                                                        }
-                                                    }
-                                                });
+                                                spawn(place_order(
+                                                    cid,
+                                                    quote.items(),
+                                                    quote.seen_at,
+                                                    address,
+                                                    shipments.clone(),
+                                                    applied_promotion.read().as_ref().and_then(|p| p.use_on(quote.total, shipping_fee)),
+                                                    card,
+                                                    shipping_resource,
+                                                    global_state,
+                                                    placed_order,
+                                                    stale,
+                                                    checkout_error,
+                                                    checking_out,
+                                                ));
                                             },
                                             if checking_out() {
                                                 i { class: "fa-solid fa-spinner fa-spin text-sm" }
@@ -1,10 +1,9 @@
 use crate::Route;
 use crate::components::product_card::ProductCard;
-use crate::database::categories::category_trees;
-use crate::database::products::products_by_category;
+use crate::database::categories::{category_trees, CategoryTree};
+use crate::database::products::products_in_category_tree;
 use crate::database::{Category as CategoryMarker, Id};
 use dioxus::prelude::*;
-use rust_decimal::prelude::ToPrimitive;
 
 // A page for categorys
 
@@ -74,28 +73,23 @@ pub fn CategoryPage(id: Id<CategoryMarker>) -> Element {
                         p { class: "text-gray-400 py-20 text-center", "Laddar kategorier..." }
                     },
                     Some(trees) => rsx! {
-                        for (i , tree) in trees.iter().enumerate() {
-                            if id == Id::<CategoryMarker>::from(0) || id == tree.id {
-                                // Samla ihop alla underkategori-IDs (inklusive tree.id självt)
-                                {
-                                    let mut all_cat_ids = vec![tree.id];
-                                    for sub in tree.subcategories.iter() {
-                                        all_cat_ids.push(sub.id);
-                                        for subsub in sub.subcategories.iter() {
-                                            all_cat_ids.push(subsub.id);
-                                        }
-                                    }
-                                    rsx! {
-                                        CategorySection {
-                                            cat_id: tree.id,
-                                            cat_name: tree.name.clone().into(),
-                                            all_cat_ids,
-                                            show_all: id != Id::<CategoryMarker>::from(0),
-                                            scroll_index: i,
-                                        }
-                                    }
+                        if id == Id::<CategoryMarker>::from(0) {
+                            for (i , tree) in trees.iter().enumerate() {
+                                CategorySection {
+                                    cat_id: tree.id,
+                                    cat_name: tree.name.clone(),
+                                    show_all: false,
+                                    scroll_index: i,
                                 }
                             }
+                        } else if let Some(tree) = find_category(trees, id) {
+                            CategorySection {
+                                key: "{tree.id}",
+                                cat_id: tree.id,
+                                cat_name: tree.name.clone(),
+                                show_all: true,
+                                scroll_index: 0,
+                            }
                         }
                     },
                 }
@@ -104,6 +98,17 @@ pub fn CategoryPage(id: Id<CategoryMarker>) -> Element {
     }
 }
 
+/// Find a category anywhere in a forest of categories.
+fn find_category(trees: &[CategoryTree], id: Id<CategoryMarker>) -> Option<&CategoryTree> {
+    trees.iter().find_map(|tree| {
+        if tree.id == id {
+            Some(tree)
+        } else {
+            find_category(&tree.subcategories, id)
+        }
+    })
+}
+
 /// Props for a single category section
 #[allow(clippy::same_name_method, reason = "Dioxus macro limitation")]
 #[derive(Props, Clone, PartialEq)]
@@ -112,8 +117,6 @@ struct CategorySectionProps {
     cat_id: Id<CategoryMarker>,
     /// Category name
     cat_name: Box<str>,
-    /// All category IDs to fetch products from (including subcategories)
-    all_cat_ids: Vec<Id<CategoryMarker>>,
     /// Whether to show all products
     show_all: bool,
     /// Scroll index for animation
@@ -125,24 +128,15 @@ struct CategorySectionProps {
 fn CategorySection(props: CategorySectionProps) -> Element {
     let cat_id = props.cat_id;
     let show_all = props.show_all;
-    let all_cat_ids = props.all_cat_ids.clone();
     let mut pos = use_signal(|| 0_usize);
 
-    // Hämta produkter från alla underkategorier och slå ihop
-    let products = use_resource(move || {
-        let cat_ids = all_cat_ids.clone();
-        async move {
-            let limit_per_cat = if show_all { 50 } else { 12 };
-            let mut all_products = Vec::new();
-            for cid in cat_ids {
-                if let Ok(prods) = products_by_category(None, cid, None, limit_per_cat, 0).await {
-                    all_products.extend(prods.iter().cloned());
-                }
-            }
-            // Begränsa totalt antal
-            all_products.truncate(if show_all { 50 } else { 12 });
-            all_products
-        }
+    // Hämta produkter från kategorin och alla dess underkategorier
+    let products = use_resource(move || async move {
+        let limit = if show_all { 50 } else { 12 };
+        products_in_category_tree(None, cat_id, None, limit, 0)
+            .await
+            .map(Vec::from)
+            .unwrap_or_default()
     });
 
     let current_pos = *pos.read();
@@ -191,7 +185,7 @@ fn CategorySection(props: CategorySectionProps) -> Element {
                                     ProductCard {
                                         id: p.id.get(),
                                         name: p.name.clone(),
-                                        price: p.price.to_f64().unwrap_or_default(),
+                                        price: p.price,
                                         comparison_price: format!("{:.2} kr", p.price),
                                         image_url: p.thumbnail.to_string(),
                                         in_stock: u32::MAX,
@@ -218,7 +212,7 @@ fn CategorySection(props: CategorySectionProps) -> Element {
                                                 ProductCard {
                                                     id: p.id.get(),
                                                     name: p.name.clone(),
-                                                    price: p.price.to_f64().unwrap_or_default(),
+                                                    price: p.price,
                                                     comparison_price: format!("{:.2} kr", p.price),
                                                     image_url: p.thumbnail.to_string(),
                                                     in_stock: u32::MAX,
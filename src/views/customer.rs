@@ -2,18 +2,26 @@
 use crate::Route;
 use crate::components::product_card::ProductCard;
 use crate::database::products::{customer_orders, favorites, set_status, OrderInfo, OrderStatus};
+use crate::database::{email_verified, resend_verification_email, AppError, Customer, Id};
+use crate::database::delivery::{create_address, customer_addresses, delete_address, PostalAddress};
+use crate::database::memberships::{cancel_membership, join_membership, membership, membership_tiers};
+use crate::database::payments::CardNumber;
 use crate::state::GlobalState;
 use dioxus::prelude::*;
-use rust_decimal::prelude::ToPrimitive;
  
 // Order status badge
  
 #[component]
 fn OrderStatusBadge(status: OrderStatus) -> Element {
     let (bg, label, icon) = match status {
+        OrderStatus::AwaitingPayment => ("bg-yellow-50 text-yellow-700 border-yellow-200", "Väntar på betalning", "fa-solid fa-credit-card"),
         OrderStatus::Pending  => ("bg-amber-100 text-amber-800 border-amber-200",  "Väntar på avsändning", "fa-solid fa-clock"),
         OrderStatus::Shipped  => ("bg-blue-100 text-blue-800 border-blue-200",     "Skickad",              "fa-solid fa-truck"),
         OrderStatus::Received => ("bg-green-100 text-green-800 border-green-200",  "Mottagen",             "fa-solid fa-circle-check"),
+        OrderStatus::Cancelled       => ("bg-gray-100 text-gray-700 border-gray-200",       "Avbruten",     "fa-solid fa-ban"),
+        OrderStatus::ReturnRequested => ("bg-orange-100 text-orange-800 border-orange-200", "Retur begärd", "fa-solid fa-rotate-left"),
+        OrderStatus::Returned        => ("bg-purple-100 text-purple-800 border-purple-200", "Returnerad",   "fa-solid fa-box"),
+        OrderStatus::Refunded        => ("bg-gray-100 text-gray-700 border-gray-200",       "Återbetald",   "fa-solid fa-money-bill-wave"),
     };
     rsx! {
         span { class: "inline-flex items-center gap-1.5 px-2.5 py-1 rounded-full text-xs font-bold border {bg}",
@@ -23,6 +31,319 @@ fn OrderStatusBadge(status: OrderStatus) -> Element {
     }
 }
  
+// Unverified email notice
+ 
+/// Påminnelse om att bekräfta e-postadressen, med möjlighet att få en ny länk
+#[component]
+fn UnverifiedEmailNotice() -> Element {
+    let verified = use_resource(|| async move { email_verified().await.unwrap_or(true) });
+    let mut sent = use_signal(|| false);
+ 
+    if verified().unwrap_or(true) {
+        return rsx! {};
+    }
+    rsx! {
+        div { class: "mb-4 bg-amber-50 border border-amber-200 rounded-xl p-3 text-sm text-amber-800 flex items-center gap-2",
+            i { class: "fa-solid fa-envelope" }
+            if sent() {
+                "En ny länk har skickats till din e-postadress."
+            } else {
+                "Din e-postadress är inte bekräftad. Följ länken i mejlet vi skickade för att kunna handla och skriva recensioner."
+                button {
+                    class: "ml-auto font-bold underline hover:text-amber-900",
+                    onclick: move |_| {
+                        let _task = spawn(async move {
+                            if resend_verification_email().await.is_ok() {
+                                sent.set(true);
+                            }
+                        });
+                    },
+                    "Skicka igen"
+                }
+            }
+        }
+    }
+}
+ 
+// Address book
+
+/// Kundens sparade leveransadresser, med möjlighet att lägga till och ta bort
+#[component]
+fn AddressBook(customer_id: Id<Customer>) -> Element {
+    let mut addresses_resource = use_resource(move || async move {
+        customer_addresses(customer_id).await
+    });
+    let mut recipient   = use_signal(String::new);
+    let mut street      = use_signal(String::new);
+    let mut postal_code = use_signal(String::new);
+    let mut city        = use_signal(String::new);
+    let mut country     = use_signal(|| "Sverige".to_string());
+    let mut error       = use_signal(|| None::<String>);
+
+    let addresses_read = addresses_resource.read();
+    let is_loading = addresses_read.is_none();
+    let err_str: Option<String> = addresses_read.as_ref()
+        .and_then(|r| r.as_ref().err().map(|e| e.to_string()));
+    let addresses = addresses_read.as_ref()
+        .and_then(|r| r.as_ref().ok())
+        .map(|v| v.to_vec());
+
+    rsx! {
+        div { class: "grid grid-cols-1 md:grid-cols-2 gap-6",
+            div { class: "space-y-3",
+                if is_loading {
+                    p { class: "text-gray-400 animate-pulse", "Laddar..." }
+                } else if let Some(err) = err_str {
+                    p { class: "text-red-400 text-sm", "Fel: {err}" }
+                } else if let Some(addresses) = addresses {
+                    if addresses.is_empty() {
+                        div { class: "text-center py-16 bg-white rounded-2xl",
+                            i { class: "fa-solid fa-location-dot text-4xl text-gray-200 mb-3 block" }
+                            p { class: "text-gray-400 text-sm font-semibold", "Inga adresser ännu." }
+                        }
+                    }
+                    for (address_id, address) in addresses.into_iter() {
+                        div { class: "bg-white rounded-2xl shadow-sm p-4 flex items-start justify-between border border-gray-100",
+                            div { class: "text-sm text-gray-700",
+                                p { class: "font-bold text-gray-900", "{address.recipient}" }
+                                p { "{address.street}" }
+                                p { "{address.postal_code} {address.city}" }
+                                p { "{address.country}" }
+                            }
+                            button {
+                                class: "text-gray-300 hover:text-red-500 transition",
+                                onclick: move |_| {
+                                    #[allow(unused_results, reason = "Borttagningen körs i bakgrunden.")]
+                                    spawn(async move {
+                                        match delete_address(address_id).await {
+                                            Ok(()) => addresses_resource.restart(),
+                                            Err(e) => error.set(Some(AppError::describe(&e))),
+                                        }
+                                    });
+                                },
+                                i { class: "fa-solid fa-trash" }
+                            }
+                        }
+                    }
+                }
+            }
+
+            div { class: "bg-white rounded-2xl shadow-sm p-6 space-y-3 h-fit border border-gray-100",
+                h2 { class: "font-black text-gray-900 text-lg mb-1", "Ny adress" }
+                input {
+                    class: "w-full border border-gray-200 rounded-xl px-4 py-2 text-sm",
+                    placeholder: "Mottagare",
+                    value: "{recipient}",
+                    oninput: move |e| recipient.set(e.value()),
+                }
+                input {
+                    class: "w-full border border-gray-200 rounded-xl px-4 py-2 text-sm",
+                    placeholder: "Gatuadress",
+                    value: "{street}",
+                    oninput: move |e| street.set(e.value()),
+                }
+                div { class: "flex gap-2",
+                    input {
+                        class: "w-32 border border-gray-200 rounded-xl px-4 py-2 text-sm",
+                        placeholder: "Postnummer",
+                        value: "{postal_code}",
+                        oninput: move |e| postal_code.set(e.value()),
+                    }
+                    input {
+                        class: "flex-1 border border-gray-200 rounded-xl px-4 py-2 text-sm",
+                        placeholder: "Ort",
+                        value: "{city}",
+                        oninput: move |e| city.set(e.value()),
+                    }
+                }
+                input {
+                    class: "w-full border border-gray-200 rounded-xl px-4 py-2 text-sm",
+                    placeholder: "Land",
+                    value: "{country}",
+                    oninput: move |e| country.set(e.value()),
+                }
+                if let Some(err) = error() {
+                    p { class: "text-red-500 text-sm", "{err}" }
+                }
+                button {
+                    class: "w-full bg-green-700 text-white font-black py-3 rounded-xl hover:bg-green-800 transition",
+                    onclick: move |_| {
+                        let address = PostalAddress {
+                            recipient: recipient().trim().into(),
+                            street: street().trim().into(),
+                            postal_code: postal_code().trim().into(),
+                            city: city().trim().into(),
+                            country: country().trim().into(),
+                        };
+                        error.set(None);
+                        #[allow(unused_results, reason = "Sparandet körs i bakgrunden.")]
+                        spawn(async move {
+                            match create_address(customer_id, address).await {
+                                Ok(_) => {
+                                    recipient.set(String::new());
+                                    street.set(String::new());
+                                    postal_code.set(String::new());
+                                    city.set(String::new());
+                                    addresses_resource.restart();
+                                }
+                                Err(e) => error.set(Some(AppError::describe(&e))),
+                            }
+                        });
+                    },
+                    "Spara adress"
+                }
+            }
+        }
+    }
+}
+
+// Membership
+
+/// Kundens medlemskap, med möjlighet att gå med gratis, betala för en nivå eller avsluta
+#[component]
+fn MembershipPanel(customer_id: Id<Customer>) -> Element {
+    let mut membership_resource = use_resource(move || async move {
+        membership(customer_id).await
+    });
+    let tiers_resource = use_resource(|| async move { membership_tiers().await });
+    let mut card_number = use_signal(String::new);
+    let mut error       = use_signal(|| None::<String>);
+    let mut busy        = use_signal(|| false);
+
+    let membership_read = membership_resource.read();
+    let is_loading = membership_read.is_none();
+    let err_str: Option<String> = membership_read.as_ref()
+        .and_then(|r| r.as_ref().err().map(|e| e.to_string()));
+    let current = membership_read.as_ref()
+        .and_then(|r| r.as_ref().ok())
+        .cloned();
+    let tiers = tiers_resource.read().as_ref()
+        .and_then(|r| r.as_ref().ok())
+        .map(|v| v.to_vec())
+        .unwrap_or_default();
+
+    rsx! {
+        div { class: "grid grid-cols-1 md:grid-cols-2 gap-6",
+            div { class: "bg-white rounded-2xl shadow-sm p-6 space-y-3 h-fit border border-gray-100",
+                h2 { class: "font-black text-gray-900 text-lg mb-1", "Ditt medlemskap" }
+                if is_loading {
+                    p { class: "text-gray-400 animate-pulse", "Laddar..." }
+                } else if let Some(err) = err_str {
+                    p { class: "text-red-400 text-sm", "Fel: {err}" }
+                } else if let Some(current) = current {
+                    if let Some(current) = current {
+                        div { class: "text-sm text-gray-700 space-y-1",
+                            p { class: "font-bold text-green-700",
+                                i { class: "fa-solid fa-id-card mr-2" }
+                                "Medlem sedan {current.since.date()}"
+                            }
+                            if let Some((tier, until)) = current.paid {
+                                p { "{tier.name}, {tier.fee:.2} kr per {tier.months} mån." }
+                                p { class: "text-gray-500", "Upphör {until} om du inte förnyar." }
+                            } else {
+                                p { class: "text-gray-500", "Gratis medlemskap utan slutdatum." }
+                            }
+                        }
+                        button {
+                            class: "w-full border border-red-200 text-red-600 font-bold py-2 rounded-xl hover:bg-red-50 transition disabled:opacity-50",
+                            disabled: busy(),
+                            onclick: move |_| {
+                                busy.set(true);
+                                error.set(None);
+                                #[allow(unused_results, reason = "Avslutet körs i bakgrunden.")]
+                                spawn(async move {
+                                    match cancel_membership(customer_id).await {
+                                        Ok(()) => membership_resource.restart(),
+                                        Err(e) => error.set(Some(AppError::describe(&e))),
+                                    }
+                                    busy.set(false);
+                                });
+                            },
+                            "Avsluta medlemskap"
+                        }
+                    } else {
+                        p { class: "text-sm text-gray-500",
+                            "Du är inte medlem. Medlemmar får ta del av erbjudanden som bara gäller medlemmar."
+                        }
+                        button {
+                            class: "w-full bg-green-700 text-white font-black py-3 rounded-xl hover:bg-green-800 transition disabled:opacity-50",
+                            disabled: busy(),
+                            onclick: move |_| {
+                                busy.set(true);
+                                error.set(None);
+                                #[allow(unused_results, reason = "Anslutningen körs i bakgrunden.")]
+                                spawn(async move {
+                                    match join_membership(customer_id, None).await {
+                                        Ok(()) => membership_resource.restart(),
+                                        Err(e) => error.set(Some(AppError::describe(&e))),
+                                    }
+                                    busy.set(false);
+                                });
+                            },
+                            "Bli medlem gratis"
+                        }
+                    }
+                }
+                if let Some(err) = error() {
+                    p { class: "text-red-500 text-sm", "{err}" }
+                }
+            }
+
+            if !tiers.is_empty() {
+                div { class: "bg-white rounded-2xl shadow-sm p-6 space-y-3 h-fit border border-gray-100",
+                    h2 { class: "font-black text-gray-900 text-lg mb-1", "Betala för medlemskap" }
+                    p { class: "text-sm text-gray-500",
+                        "Betalar du för samma nivå igen förlängs ditt medlemskap."
+                    }
+                    input {
+                        class: "w-full border border-gray-200 rounded-xl px-4 py-2 text-sm font-mono",
+                        placeholder: "Kortnummer",
+                        inputmode: "numeric",
+                        autocomplete: "cc-number",
+                        value: "{card_number}",
+                        oninput: move |e| card_number.set(e.value()),
+                    }
+                    p { class: "text-xs text-gray-400",
+                        "Inga riktiga betalningar görs. Använd testkortet 4242 4242 4242 4242."
+                    }
+                    for tier in tiers.into_iter() {
+                        div { class: "flex items-center justify-between border border-gray-100 rounded-xl p-3",
+                            div { class: "text-sm",
+                                p { class: "font-bold text-gray-900", "{tier.name}" }
+                                p { class: "text-gray-500", "{tier.fee:.2} kr för {tier.months} mån." }
+                            }
+                            button {
+                                class: "bg-green-700 text-white font-bold px-4 py-2 rounded-xl hover:bg-green-800 transition disabled:opacity-50",
+                                disabled: busy(),
+                                onclick: move |_| {
+                                    let Some(card) = CardNumber::new(&card_number()) else {
+                                        error.set(Some("Ogiltigt kortnummer.".into()));
+                                        return;
+                                    };
+                                    busy.set(true);
+                                    error.set(None);
+                                    #[allow(unused_results, reason = "Betalningen körs i bakgrunden.")]
+                                    spawn(async move {
+                                        match join_membership(customer_id, Some((tier.id, card))).await {
+                                            Ok(()) => {
+                                                card_number.set(String::new());
+                                                membership_resource.restart();
+                                            }
+                                            Err(e) => error.set(Some(AppError::describe(&e))),
+                                        }
+                                        busy.set(false);
+                                    });
+                                },
+                                "Betala"
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
 // Customer profile
  
 /// Kundprofil; visar ordrar och recensioner för inloggad kund
@@ -106,6 +427,8 @@ pub fn CustomerProfile() -> Element {
                     }
                 }
 
+                UnverifiedEmailNotice {}
+
                 // Status feedback
                 if let Some(msg) = status_msg() {
                     div { class: "mb-4 bg-green-50 border border-green-200 rounded-xl p-3 text-sm text-green-800 flex items-center gap-2",
@@ -134,6 +457,18 @@ pub fn CustomerProfile() -> Element {
                         i { class: "fa-solid fa-heart mr-2" }
                         "Mina favoriter"
                     }
+                    button {
+                        class: if active_tab() == 3 { "px-4 py-2 font-bold text-green-700 border-b-2 border-green-700 whitespace-nowrap" } else { "px-4 py-2 text-gray-500 hover:text-gray-700 whitespace-nowrap" },
+                        onclick: move |_| active_tab.set(3),
+                        i { class: "fa-solid fa-location-dot mr-2" }
+                        "Mina adresser"
+                    }
+                    button {
+                        class: if active_tab() == 4 { "px-4 py-2 font-bold text-green-700 border-b-2 border-green-700 whitespace-nowrap" } else { "px-4 py-2 text-gray-500 hover:text-gray-700 whitespace-nowrap" },
+                        onclick: move |_| active_tab.set(4),
+                        i { class: "fa-solid fa-id-card mr-2" }
+                        "Medlemskap"
+                    }
                 }
 
                 // Orders tab
@@ -149,7 +484,7 @@ pub fn CustomerProfile() -> Element {
                         }
                     } else if let Some(orders) = orders_list {
                         div { class: "space-y-4",
-                            for (order_idx , order) in orders.iter().enumerate() {
+                            for order in orders.iter() {
                                 {
                                     let t = order.time;
                                     let time_str = format!(
@@ -160,21 +495,55 @@ pub fn CustomerProfile() -> Element {
                                         t.hour(),
                                         t.minute(),
                                     );
-                                    let total: rust_decimal::Decimal = order.purchases.iter().map(|p| p.paid).sum();
+                                    let os = order.status;
                                     rsx! {
                                         div { class: "bg-white rounded-2xl shadow-sm overflow-hidden border border-gray-100",
                                             div { class: "flex items-center justify-between px-5 py-3 bg-gray-50 border-b border-gray-100",
                                                 div { class: "flex items-center gap-3",
-                                                    span { class: "text-xs font-semibold text-gray-500", "Order #{order_idx + 1}" }
+                                                    span { class: "text-xs font-semibold text-gray-500", "Order #{order.id}" }
                                                     span { class: "text-gray-300", "•" }
                                                     span { class: "text-xs text-gray-400", "{time_str}" }
                                                 }
+                                                div { class: "flex items-center gap-2",
+                                                    OrderStatusBadge { status: os }
+                                                    {
+                                                        let order_id = order.id;
+                                                        let action = match os {
+                                                            OrderStatus::AwaitingPayment | OrderStatus::Pending => Some((OrderStatus::Cancelled, "Order avbruten.", "fa-solid fa-ban", "Avbryt order")),
+                                                            OrderStatus::Shipped => Some((OrderStatus::Received, "Order markerad som mottagen.", "fa-solid fa-box-open", "Markera mottagen")),
+                                                            OrderStatus::Received => Some((OrderStatus::ReturnRequested, "Retur begärd.", "fa-solid fa-rotate-left", "Begär retur")),
+                                                            _ => None,
+                                                        };
+                                                        rsx! {
+                                                            if let Some((new_status, message, icon, label)) = action {
+                                                                button {
+                                                                    class: "text-xs bg-green-700 text-white font-bold px-3 py-1 rounded-lg hover:bg-green-800 transition",
+                                                                    onclick: move |_| {
+                                                                        let mut sm = status_msg;
+                                                                        let mut r = orders_resource;
+                                                                        #[allow(unused_results)]
+                                                                        spawn(async move {
+                                                                            match set_status(order_id, new_status).await {
+                                                                                Ok(()) => {
+                                                                                    sm.set(Some(message.into()));
+                                                                                    r.restart();
+                                                                                }
+                                                                                Err(e) => sm.set(Some(AppError::describe(&e))),
+                                                                            }
+                                                                        });
+                                                                    },
+                                                                    i { class: "{icon} mr-1" }
+                                                                    "{label}"
+                                                                }
+                                                            }
+                                                        }
+                                                    }
+                                                }
                                             }
 
                                             div { class: "divide-y divide-gray-50",
                                                 for purchase in order.purchases.iter() {
                                                     {
-                                                        let ps = purchase.status;
                                                         let changed = purchase.product_changed;
                                                         rsx! {
                                                             div { class: "flex items-center gap-4 px-5 py-4",
@@ -199,38 +568,15 @@ pub fn CustomerProfile() -> Element {
                                                                 }
                                                                 div { class: "flex flex-col items-end gap-2 shrink-0",
                                                                     p { class: "font-black text-green-700 text-sm", "{purchase.paid:.2} kr" }
-                                                                    OrderStatusBadge { status: ps }
+                                                                    if purchase.deal.is_some() {
+                                                                        span { class: "text-xs text-gray-400 line-through",
+                                                                            {format!("{:.2} kr", purchase.unit_price * rust_decimal::Decimal::from(purchase.number.get()))}
+                                                                        }
+                                                                    }
                                                                     span { class: "inline-flex items-center gap-1.5 px-2.5 py-1 rounded-full text-xs font-bold border bg-green-50 text-green-700 border-green-200",
                                                                         i { class: "fa-solid fa-check text-[10px]" }
                                                                         "Betald"
                                                                     }
-
-                                                                    if ps == OrderStatus::Shipped {
-                                                                        {
-                                                                            let purchase_id = purchase.id;
-                                                                            rsx! {
-                                                                                button {
-                                                                                    class: "text-xs bg-green-700 text-white font-bold px-3 py-1 rounded-lg hover:bg-green-800 transition",
-                                                                                    onclick: move |_| {
-                                                                                        let mut sm = status_msg;
-                                                                                        let mut r = orders_resource;
-                                                                                        #[allow(unused_results)]
-                                                                                        spawn(async move {
-                                                                                            match set_status(purchase_id, OrderStatus::Received).await {
-                                                                                                Ok(()) => {
-                                                                                                    sm.set(Some("Order markerad som mottagen.".into()));
-                                                                                                    r.restart();
-                                                                                                }
-                                                                                                Err(e) => sm.set(Some(format!("Fel: {e}"))),
-                                                                                            }
-                                                                                        });
-                                                                                    },
-                                                                                    i { class: "fa-solid fa-box-open mr-1" }
-                                                                                    "Markera mottagen"
-                                                                                }
-                                                                            }
-                                                                        }
-                                                                    }
                                                                 }
                                                             }
                                                         }
@@ -240,7 +586,17 @@ pub fn CustomerProfile() -> Element {
 
                                             div { class: "px-5 py-3 bg-gray-50 border-t border-gray-100 flex justify-between items-center",
                                                 span { class: "text-xs text-gray-500", "{order.purchases.len()} produkt(er)" }
-                                                span { class: "font-black text-gray-900 text-sm", "Totalt: {total:.2} kr" }
+                                                div { class: "text-right",
+                                                    if !order.shipping_fee.is_zero() {
+                                                        p { class: "text-xs text-gray-500", "Frakt: {order.shipping_fee:.2} kr" }
+                                                    }
+                                                    if let Some(code) = &order.promotion_code {
+                                                        p { class: "text-xs text-green-700", "Rabattkod {code}: −{order.discount:.2} kr" }
+                                                    }
+                                                    span { class: "font-black text-gray-900 text-sm",
+                                                        {format!("Totalt: {:.2} kr", order.total + order.shipping_fee - order.discount)}
+                                                    }
+                                                }
                                             }
                                         }
                                     }
@@ -279,7 +635,7 @@ pub fn CustomerProfile() -> Element {
                                         ProductCard {
                                             id: p.id.get(),
                                             name: p.name.clone(),
-                                            price: p.price.to_f64().unwrap_or_default(),
+                                            price: p.price,
                                             comparison_price: format!("{:.2} kr / {}", p.price, p.amount_per_unit),
                                             image_url: p.thumbnail.to_string(),
                                             in_stock: u32::MAX,
@@ -291,6 +647,16 @@ pub fn CustomerProfile() -> Element {
                     }
                 }
 
+                // Addresses tab
+                if active_tab() == 3 {
+                    AddressBook { customer_id }
+                }
+
+                // Membership tab
+                if active_tab() == 4 {
+                    MembershipPanel { customer_id }
+                }
+
                 // Reviews tab
                 if active_tab() == 1 {
                     if rev_loading {
@@ -3,7 +3,6 @@ use crate::components::product_card::ProductCard;
 use crate::database::products::favorites;
 use crate::state::GlobalState;
 use dioxus::prelude::*;
-use rust_decimal::prelude::ToPrimitive;
 
 /// Favorites page.
 #[component]
@@ -77,7 +76,7 @@ pub fn FavoritesPage() -> Element {
                             ProductCard {
                                 id: p.id.get(),
                                 name: p.name.clone(),
-                                price: p.price.to_f64().unwrap_or_default(),
+                                price: p.price,
                                 comparison_price: format!("{:.2} kr", p.price),
                                 image_url: p.thumbnail.to_string(),
                                 in_stock: u32::MAX,
@@ -106,7 +106,6 @@ struct DiscountedSliderProps {
 
 #[component]
 fn DiscountedSlider(props: DiscountedSliderProps) -> Element {
-    use rust_decimal::prelude::ToPrimitive;
     let mut pos = use_signal(|| 0_usize);
     let items = props.products;
     let total = items.len();
@@ -140,8 +139,7 @@ fn DiscountedSlider(props: DiscountedSliderProps) -> Element {
                                     .database_repr()
                                     .ok()
                                     .and_then(|(new_price, _, _)| new_price)
-                                    .and_then(|np| np.to_f64())
-                                    .unwrap_or_else(|| p.price.to_f64().unwrap_or_default()),
+                                    .unwrap_or(p.price),
                                 comparison_price: format!("{:.2} kr / {}", p.price, p.amount_per_unit),
                                 image_url: p.thumbnail.to_string(),
                                 in_stock: u32::MAX,
@@ -188,7 +186,6 @@ struct NewestSliderProps {
 
 #[component]
 fn NewestSlider(props: NewestSliderProps) -> Element {
-    use rust_decimal::prelude::ToPrimitive;
     let mut pos = use_signal(|| 0_usize);
     let items = props.products;
     let total = items.len();
@@ -218,7 +215,7 @@ fn NewestSlider(props: NewestSliderProps) -> Element {
                             ProductCard {
                                 id: p.id.get(),
                                 name: p.name.to_string(),
-                                price: p.price.to_f64().unwrap_or_default(),
+                                price: p.price,
                                 comparison_price: format!("{:.2} kr / {}", p.price, p.amount_per_unit),
                                 image_url: p.thumbnail.to_string(),
                                 in_stock: u32::MAX,
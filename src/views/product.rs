@@ -1,16 +1,16 @@
 use crate::Route;
 use crate::components::product_card::ProductCard;
+use crate::database::bundles::product_bundles;
 use crate::database::products::{product_info, products_by_category, set_favorite, set_rating};
-use crate::database::cart::set_in_shopping_cart;
 use crate::database::reviews::{
     create_comment, create_reply, create_review, delete_comment, delete_review,
     product_reviews, product_reviews_as, set_vote_comment, set_vote_review,
     CommentTree, OwnReview, ProductReview,
 };
-use crate::database::{Category, Customer, Id, Product as DbProduct, Rating, Review, Vote};
-use crate::state::GlobalState;
+use crate::database::{AppError, Category, Customer, Id, Product as DbProduct, Rating, Review, Vote};
+use crate::state::{save_cart_item, GlobalState};
 use dioxus::prelude::*;
-use rust_decimal::prelude::ToPrimitive;
+use std::iter::once;
  
 // Breadcrumb
  
@@ -620,7 +620,7 @@ fn ReviewsSection(
                                                         refresh.set(refresh() + 1);
                                                     }
                                                     Err(e) => {
-                                                        review_error.set(Some(e.to_string()));
+                                                        review_error.set(Some(AppError::describe(&e)));
                                                         review_loading.set(false);
                                                     }
                                                 }
@@ -746,7 +746,7 @@ fn SimilarProducts(props: SimilarProductsProps) -> Element {
                     ProductCard {
                         id: p.id.get(),
                         name: p.name.clone(),
-                        price: p.price.to_f64().unwrap_or_default(),
+                        price: p.price,
                         comparison_price: format!("{:.2} kr / {}", p.price, p.amount_per_unit),
                         image_url: p.thumbnail.to_string(),
                         in_stock: u32::MAX,
@@ -779,6 +779,7 @@ pub fn Product(id: i32) -> Element {
     let heart_class = if is_favorite { "text-red-500" } else { "text-gray-400 hover:text-red-500" };
  
     let product_resource = use_resource(move || async move { product_info(customer_id, db_id).await });
+    let bundles_resource = use_resource(move || async move { product_bundles(db_id).await });
  
     // Resolve outside rsx! — early returns for loading/error
     let prod_read = product_resource.read_unchecked();
@@ -818,7 +819,7 @@ pub fn Product(id: i32) -> Element {
     let rating_count    = product.rating.count();
     let full_stars      = avg_rating.round() as usize;
     let pname           = product.name.to_string();
-    let pprice          = product.price.to_string().parse::<f64>().unwrap_or(0.0);
+    let pprice          = product.price;
     let pimage          = product.gallery.first().map(|u| u.to_string()).unwrap_or_default();
     let category_id     = product.category.last().map(|(cat_id, _)| *cat_id);
     let amount_str      = product.amount_per_unit.to_string();
@@ -842,6 +843,31 @@ pub fn Product(id: i32) -> Element {
         _ => None,
     };
  
+    // Mängdpriser som (antal förpackningar, pris per förpackning), t.ex. ("1–4 st", "99,00 kr").
+    let tier_rows: Vec<(String, String)> = if product.price_tiers.is_empty() {
+        Vec::new()
+    } else {
+        let starts = once(1).chain(product.price_tiers.iter().map(|t| t.min_units.get()));
+        let prices = once(product.price).chain(product.price_tiers.iter().map(|t| t.price));
+        let ends = product.price_tiers.iter().map(|t| Some(t.min_units.get() - 1)).chain(once(None));
+        starts.zip(ends).zip(prices)
+            .map(|((start, end), price)| {
+                let range = match end {
+                    Some(end) if end == start => format!("{start} st"),
+                    Some(end) => format!("{start}–{end} st"),
+                    None => format!("{start}+ st"),
+                };
+                (range, format!("{price:.2} kr").replace('.', ","))
+            })
+            .collect()
+    };
+ 
+    // Paketerbjudanden som produkten ingår i, t.ex. "Valfria 3 för 40 kr".
+    let bundle_names: Vec<String> = bundles_resource.read().as_ref()
+        .and_then(|r| r.as_ref().ok())
+        .map(|bundles| bundles.iter().map(|b| b.name.to_string()).collect())
+        .unwrap_or_default();
+ 
     rsx! {
         div { class: "max-w-6xl mx-auto p-4 md:p-8 bg-white",
             Breadcrumb { category_path: crumbs, product_name: product.name.clone() }
@@ -890,6 +916,30 @@ pub fn Product(id: i32) -> Element {
                                 "{lbl}"
                             }
                         }
+                        for name in bundle_names {
+                            div { class: "flex items-center gap-2 text-green-800 font-bold text-sm mt-2",
+                                i { class: "fa-solid fa-boxes-stacked" }
+                                "{name}"
+                            }
+                        }
+                        if !tier_rows.is_empty() {
+                            table { class: "mt-4 text-sm text-gray-700",
+                                thead {
+                                    tr {
+                                        th { class: "text-left font-bold pr-6 pb-1", "Mängdpris" }
+                                        th { class: "text-right font-bold pb-1", "Pris / förpackning" }
+                                    }
+                                }
+                                tbody {
+                                    for (range, price) in tier_rows {
+                                        tr {
+                                            td { class: "pr-6", "{range}" }
+                                            td { class: "text-right font-semibold", "{price}" }
+                                        }
+                                    }
+                                }
+                            }
+                        }
                         div { class: "text-gray-500 font-bold mt-3",
                             "Säljs av "
                             Link {
@@ -907,14 +957,9 @@ pub fn Product(id: i32) -> Element {
                                 class: "flex-grow h-full bg-green-700 text-white rounded-full font-black text-xl hover:bg-green-800 transition-colors shadow-md flex items-center justify-center gap-3",
                                 onclick: move |_| {
                                     global_state.write().add_to_cart(id, pname.clone(), pprice, pimage.clone());
-                                    if let Some(cid) = global_state.read().customer_id() {
-                                        let pid = Id::<DbProduct>::from(id);
-                                        #[allow(unused_results)]
-                                        #[allow(unused_results)]
-                                        spawn(async move {
-                                            drop(set_in_shopping_cart(cid, pid, 1).await);
-                                        });
-                                    }
+                                    let customer = global_state.read().customer_id();
+                                    #[allow(unused_results)]
+                                    spawn(save_cart_item(customer, id, 1));
                                 },
                                 i { class: "fa-solid fa-cart-plus" }
                                 "LÄGG I VARUKORG"
@@ -926,14 +971,9 @@ pub fn Product(id: i32) -> Element {
                                     onclick: move |_| {
                                         let new_qty = quantity - 1;
                                         global_state.write().set_quantity(id, new_qty);
-                                        if let Some(cid) = global_state.read().customer_id() {
-                                            let pid = Id::<DbProduct>::from(id);
-                                            #[allow(unused_results)]
-                                            #[allow(unused_results)]
-                                            spawn(async move {
-                                                drop(set_in_shopping_cart(cid, pid, new_qty).await);
-                                            });
-                                        }
+                                        let customer = global_state.read().customer_id();
+                                        #[allow(unused_results)]
+                                        spawn(save_cart_item(customer, id, new_qty));
                                     },
                                     i { class: "fas fa-minus" }
                                 }
@@ -943,14 +983,9 @@ pub fn Product(id: i32) -> Element {
                                     onclick: move |_| {
                                         let new_qty = quantity + 1;
                                         global_state.write().set_quantity(id, new_qty);
-                                        if let Some(cid) = global_state.read().customer_id() {
-                                            let pid = Id::<DbProduct>::from(id);
-                                            #[allow(unused_results)]
-                                            #[allow(unused_results)]
-                                            spawn(async move {
-                                                drop(set_in_shopping_cart(cid, pid, new_qty).await);
-                                            });
-                                        }
+                                        let customer = global_state.read().customer_id();
+                                        #[allow(unused_results)]
+                                        spawn(save_cart_item(customer, id, new_qty));
                                     },
                                     i { class: "fas fa-plus" }
                                 }
@@ -1,25 +1,67 @@
-#![allow(non_snake_case)]
+#![allow(non_snake_case, reason = "Components are named in PascalCase.")]
 use crate::Route;
-use crate::database::search::{SearchResult, search_products};
+use crate::components::product_card::{offer_label, ProductCard};
+use crate::database::search::{
+    search_facets, search_products, Facet, OfferFilter, SearchFacets, SearchFilters, SearchOrder,
+};
+use crate::database::Rating;
 use dioxus::prelude::*;
+use rust_decimal::Decimal;
+
+/// Antal produkter per sida
+const PAGE_SIZE: usize = 24;
+
+/// Sorteringsalternativ och deras etiketter
+const ORDERS: [(SearchOrder, &str); 7] = [
+    (SearchOrder::Relevance, "Relevans"),
+    (SearchOrder::PriceAscending, "Lägsta pris"),
+    (SearchOrder::PriceDescending, "Högsta pris"),
+    (SearchOrder::UnitPrice, "Jämförpris"),
+    (SearchOrder::Newest, "Nyast"),
+    (SearchOrder::Rating, "Högst betyg"),
+    (SearchOrder::BestDiscount, "Bästa rabatt"),
+];
 
 /// Sökresultatsida som visas när man trycker Enter i sökning
 #[component]
 pub fn Search(query: String) -> Element {
+    // Nollställ filter och sida när sökordet ändras
+    rsx! {
+        SearchPage { key: "{query}", query }
+    }
+}
+
+#[component]
+fn SearchPage(query: String) -> Element {
     let nav = use_navigator();
     let mut search_input = use_signal(|| query.clone());
+    let mut filters = use_signal(SearchFilters::default);
+    let mut order = use_signal(SearchOrder::default);
+    let mut page = use_signal(|| 0_usize);
 
-    let q = query.clone();
+    let results_query = query.clone();
     let results = use_resource(move || {
-        let q = q.clone();
-        async move {
-            if q.trim().is_empty() {
-                Ok(Vec::new())
-            } else {
-                search_products(q.into(), 50).await
-            }
-        }
+        let query = results_query.clone();
+        let filters = filters();
+        let order = order();
+        let offset = page() * PAGE_SIZE;
+        async move { search_products(query.into(), filters, order, PAGE_SIZE, offset).await }
     });
+    let facets_query = query.clone();
+    let facets = use_resource(move || {
+        let query = facets_query.clone();
+        let filters = filters();
+        async move { search_facets(query.into(), filters).await }
+    });
+
+    // Byt filter och gå tillbaka till första sidan
+    let update = move |new: SearchFilters| {
+        filters.set(new);
+        page.set(0);
+    };
+
+    let total = facets.read().as_ref().and_then(|f| f.as_ref().ok()).map(|f| f.total);
+    let pages = total.map_or(1, |total| usize::try_from(total).unwrap_or(usize::MAX).div_ceil(PAGE_SIZE).max(1));
 
     rsx! {
         div { class: "min-h-screen bg-gray-50",
@@ -37,9 +79,9 @@ pub fn Search(query: String) -> Element {
                             oninput: move |e| search_input.set(e.value()),
                             onkeydown: move |e| {
                                 if e.key() == Key::Enter {
-                                    let q = search_input.read().clone();
-                                    if !q.trim().is_empty() {
-                                        let _unused = nav.push(Route::Search { query: q });
+                                    let new_query = search_input.read().clone();
+                                    if !new_query.trim().is_empty() {
+                                        let _unused = nav.push(Route::Search { query: new_query });
                                     }
                                 }
                             },
@@ -47,55 +89,300 @@ pub fn Search(query: String) -> Element {
                     }
                 }
 
-                h1 { class: "text-2xl font-black text-gray-800 mb-2", "Sökresultat för \"{query}\"" }
+                div { class: "flex justify-between items-end mb-6 gap-4",
+                    div {
+                        h1 { class: "text-2xl font-black text-gray-800 mb-2", "Sökresultat för \"{query}\"" }
+                        if let Some(total) = total {
+                            p { class: "text-gray-400 text-sm", "{total} produkter hittades" }
+                        }
+                    }
+                    // Sortering
+                    select {
+                        class: "border rounded-full px-4 py-2 text-sm bg-white",
+                        onchange: move |e| {
+                            if let Ok(i) = e.value().parse::<usize>() && let Some(&(o, _)) = ORDERS.get(i) {
+                                order.set(o);
+                                page.set(0);
+                            }
+                        },
+                        for (i , (o , label)) in ORDERS.iter().enumerate() {
+                            option { value: "{i}", selected: *o == order(), "{label}" }
+                        }
+                    }
+                }
 
-                match &*results.read() {
-                    None => rsx! {
-                        p { class: "text-gray-400 animate-pulse mt-4", "Söker..." }
-                    },
-                    Some(Err(e)) => rsx! {
-                        p { class: "text-red-400 text-sm mt-4", "Fel: {e}" }
-                    },
-                    Some(Ok(products)) if products.is_empty() => rsx! {
-                        div { class: "text-center py-20",
-                            i { class: "fa-solid fa-magnifying-glass text-5xl text-gray-200 mb-4" }
-                            p { class: "text-gray-500 text-lg font-bold", "Inga produkter hittades" }
-                            p { class: "text-gray-400 text-sm mt-1", "Prova ett annat sökord" }
+                div { class: "flex gap-8",
+                    // Filter
+                    aside { class: "w-64 shrink-0 hidden md:block",
+                        match &*facets.read() {
+                            Some(Ok(facets)) => rsx! {
+                                FacetPanel {
+                                    facets: facets.clone(),
+                                    filters: filters(),
+                                    on_change: update,
+                                }
+                            },
+                            Some(Err(e)) => rsx! {
+                                p { class: "text-red-400 text-sm", "Fel: {e}" }
+                            },
+                            None => rsx! {
+                                p { class: "text-gray-400 animate-pulse text-sm", "Laddar filter..." }
+                            },
                         }
-                    },
-                    Some(Ok(products)) => rsx! {
-                        p { class: "text-gray-400 text-sm mb-6", "{products.len()} produkter hittades" }
-                        div { class: "grid grid-cols-1 sm:grid-cols-2 md:grid-cols-3 lg:grid-cols-4 gap-6",
-                            for p in products.iter() {
-                                SearchResultCard { result: p.clone() }
+                    }
+
+                    // Resultat
+                    div { class: "flex-grow",
+                        match &*results.read() {
+                            None => rsx! {
+                                p { class: "text-gray-400 animate-pulse mt-4", "Söker..." }
+                            },
+                            Some(Err(e)) => rsx! {
+                                p { class: "text-red-400 text-sm mt-4", "Fel: {e}" }
+                            },
+                            Some(Ok(products)) if products.is_empty() => rsx! {
+                                div { class: "text-center py-20",
+                                    i { class: "fa-solid fa-magnifying-glass text-5xl text-gray-200 mb-4" }
+                                    p { class: "text-gray-500 text-lg font-bold", "Inga produkter hittades" }
+                                    p { class: "text-gray-400 text-sm mt-1", "Prova ett annat sökord eller ta bort filter" }
+                                }
+                            },
+                            Some(Ok(products)) => rsx! {
+                                div { class: "grid grid-cols-1 sm:grid-cols-2 lg:grid-cols-3 gap-6",
+                                    for p in products.iter() {
+                                        ProductCard {
+                                            key: "{p.id}",
+                                            id: p.id.get(),
+                                            name: p.name.to_string(),
+                                            price: p.price,
+                                            comparison_price: format!("{:.2} kr / {}", p.price, p.amount_per_unit),
+                                            image_url: p.thumbnail.to_string(),
+                                            in_stock: p.in_stock,
+                                            special_offer: offer_label(p.special_offer_deal, p.price),
+                                        }
+                                    }
+                                }
+                            },
+                        }
+
+                        // Sidor
+                        if pages > 1 {
+                            div { class: "flex justify-center items-center gap-4 mt-10",
+                                button {
+                                    class: "px-4 py-2 rounded-full border bg-white text-sm font-bold disabled:opacity-40",
+                                    disabled: page() == 0,
+                                    onclick: move |_| page -= 1,
+                                    i { class: "fa-solid fa-chevron-left mr-1" }
+                                    "Föregående"
+                                }
+                                span { class: "text-sm text-gray-500", "Sida {page() + 1} av {pages}" }
+                                button {
+                                    class: "px-4 py-2 rounded-full border bg-white text-sm font-bold disabled:opacity-40",
+                                    disabled: page() + 1 >= pages,
+                                    onclick: move |_| page += 1,
+                                    "Nästa"
+                                    i { class: "fa-solid fa-chevron-right ml-1" }
+                                }
                             }
                         }
-                    },
+                    }
                 }
             }
         }
     }
 }
 
-/// sökresultat
+/// Filterpanel med antal träffar för varje val
 #[component]
-fn SearchResultCard(result: SearchResult) -> Element {
+fn FacetPanel(facets: SearchFacets, filters: SearchFilters, on_change: EventHandler<SearchFilters>) -> Element {
+    let mut min_price = use_signal(|| filters.min_price.map(|p| p.to_string()).unwrap_or_default());
+    let mut max_price = use_signal(|| filters.max_price.map(|p| p.to_string()).unwrap_or_default());
+
+    // Skapa nya filter från de nuvarande
+    let with = {
+        let filters = filters.clone();
+        move |change: &dyn Fn(&mut SearchFilters)| {
+            let mut new = filters.clone();
+            change(&mut new);
+            new
+        }
+    };
+
+    let price_placeholder = facets
+        .price_range
+        .map(|(low, high)| (format!("{low:.0}"), format!("{high:.0}")))
+        .unwrap_or_default();
+
     rsx! {
-        Link {
-            to: Route::Product {
-                id: result.id.into(),
-            },
-            class: "bg-white rounded-2xl shadow-sm overflow-hidden hover:shadow-md transition block",
-            div { class: "h-40 bg-gray-100 overflow-hidden",
-                img {
-                    src: "{result.thumbnail}",
-                    class: "w-full h-full object-cover",
-                    alt: "{result.name}",
+        div { class: "flex flex-col gap-6 bg-white rounded-2xl shadow-sm p-4 text-sm",
+            if filters != SearchFilters::default() {
+                button {
+                    class: "text-green-700 font-bold text-left hover:text-green-900",
+                    onclick: move |_| on_change.call(SearchFilters::default()),
+                    i { class: "fa-solid fa-xmark mr-1" }
+                    "Rensa alla filter"
+                }
+            }
+
+            FacetSection { title: "Kategori",
+                for Facet { value: id, name, count } in facets.categories.iter().cloned() {
+                    FacetOption {
+                        label: name,
+                        count,
+                        active: filters.category == Some(id),
+                        onclick: {
+                            let new = with(&|f| f.category = (f.category != Some(id)).then_some(id));
+                            move |()| on_change.call(new.clone())
+                        },
+                    }
+                }
+            }
+
+            FacetSection { title: "Säljare",
+                for Facet { value: id, name, count } in facets.vendors.iter().cloned() {
+                    FacetOption {
+                        label: name,
+                        count,
+                        active: filters.vendor == Some(id),
+                        onclick: {
+                            let new = with(&|f| f.vendor = (f.vendor != Some(id)).then_some(id));
+                            move |()| on_change.call(new.clone())
+                        },
+                    }
                 }
             }
-            div { class: "p-4",
-                p { class: "font-bold text-gray-900", "{result.name}" }
+
+            FacetSection { title: "Ursprung",
+                for Facet { value: origin, name, count } in facets.origins.iter().cloned() {
+                    FacetOption {
+                        label: name,
+                        count,
+                        active: filters.origin.as_ref() == Some(&origin),
+                        onclick: {
+                            let new = with(&|f| {
+                                f.origin = (f.origin.as_ref() != Some(&origin)).then(|| origin.clone());
+                            });
+                            move |()| on_change.call(new.clone())
+                        },
+                    }
+                }
             }
+
+            FacetSection { title: "Pris (kr)",
+                div { class: "flex gap-2 items-center",
+                    input {
+                        class: "w-20 border rounded-lg px-2 py-1",
+                        r#type: "number",
+                        placeholder: "{price_placeholder.0}",
+                        value: "{min_price}",
+                        oninput: move |e| min_price.set(e.value()),
+                    }
+                    "–"
+                    input {
+                        class: "w-20 border rounded-lg px-2 py-1",
+                        r#type: "number",
+                        placeholder: "{price_placeholder.1}",
+                        value: "{max_price}",
+                        oninput: move |e| max_price.set(e.value()),
+                    }
+                    button {
+                        class: "text-green-700 hover:text-green-900",
+                        title: "Använd pris",
+                        onclick: {
+                            let with = with.clone();
+                            move |_| {
+                                let new = with(&|f| {
+                                    f.min_price = min_price.read().trim().parse::<Decimal>().ok();
+                                    f.max_price = max_price.read().trim().parse::<Decimal>().ok();
+                                });
+                                on_change.call(new);
+                            }
+                        },
+                        i { class: "fa-solid fa-check" }
+                    }
+                }
+            }
+
+            FacetSection { title: "Lager",
+                FacetOption {
+                    label: "Endast i lager",
+                    count: facets.in_stock,
+                    active: filters.in_stock_only,
+                    onclick: {
+                        let new = with(&|f| f.in_stock_only = !f.in_stock_only);
+                        move |()| on_change.call(new.clone())
+                    },
+                }
+            }
+
+            FacetSection { title: "Erbjudanden",
+                for (offer , count) in facets.offers.iter().copied() {
+                    FacetOption {
+                        label: match offer {
+                            OfferFilter::Any => "Alla erbjudanden",
+                            OfferFilter::MembersOnly => "Endast för medlemmar",
+                            OfferFilter::Public => "För alla kunder",
+                        },
+                        count,
+                        active: filters.offer == Some(offer),
+                        onclick: {
+                            let new = with(&|f| f.offer = (f.offer != Some(offer)).then_some(offer));
+                            move |()| on_change.call(new.clone())
+                        },
+                    }
+                }
+            }
+
+            FacetSection { title: "Betyg",
+                for (rating , count) in facets.ratings.iter().copied().filter(|&(r, _)| r < Rating::FIVE_STARS) {
+                    FacetOption {
+                        label: format!("{} stjärnor eller mer", rating.get()).into_boxed_str(),
+                        count,
+                        active: filters.min_rating == Some(rating),
+                        onclick: {
+                            let new = with(&|f| f.min_rating = (f.min_rating != Some(rating)).then_some(rating));
+                            move |()| on_change.call(new.clone())
+                        },
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// En rubrik med filterval
+#[component]
+fn FacetSection(title: String, children: Element) -> Element {
+    rsx! {
+        div {
+            h3 { class: "font-black text-gray-800 mb-2", "{title}" }
+            ul { class: "flex flex-col gap-1", {children} }
         }
     }
-}
\ No newline at end of file
+}
+
+/// Ett filterval med antal träffar
+#[component]
+fn FacetOption(
+    #[props(into)] label: Box<str>,
+    count: u64,
+    active: bool,
+    onclick: EventHandler<()>,
+) -> Element {
+    rsx! {
+        li {
+            button {
+                class: if active { "w-full flex justify-between text-green-700 font-bold" } else { "w-full flex justify-between text-gray-600 hover:text-green-700 disabled:opacity-40" },
+                disabled: count == 0 && !active,
+                onclick: move |_| onclick.call(()),
+                span {
+                    if active {
+                        i { class: "fa-solid fa-check mr-1" }
+                    }
+                    "{label}"
+                }
+                span { class: "text-gray-400", "{count}" }
+            }
+        }
+    }
+}
@@ -3,12 +3,18 @@ use crate::Route;
 use crate::database::categories::category_trees;
 use crate::database::products::{
     add_stock, create_product, set_origin, set_overview, set_price, set_product_name,
-    set_thumbnail, set_visibility, vendor_orders, vendor_products, set_status,
+    set_search_language, set_thumbnail, set_visibility, vendor_orders, vendor_products, set_status,
     OrderStatus, OrderVendorView, ProductOverviewVendor,
 };
-use crate::database::{Amount, Id, Url, Vendor as VendorEntity};
+use crate::database::{AppError, Amount, Id, Media, Vendor as VendorEntity};
+use crate::database::delivery::{
+    create_shipping_method, delete_shipping_method, shipping_methods, ShippingRate,
+};
+use crate::database::media::{thumbnail_url, upload_media, MAX_SIZE};
+use crate::database::search::SearchLanguage;
 use crate::database::users::vendor_info;
 use crate::state::GlobalState;
+use dioxus::fullstack::FileStream;
 use dioxus::prelude::*;
 use rust_decimal::Decimal;
 use std::str::FromStr;
@@ -18,9 +24,14 @@ use std::str::FromStr;
 #[component]
 fn OrderStatusBadge(status: OrderStatus) -> Element {
     let (bg, label, icon) = match status {
+        OrderStatus::AwaitingPayment => ("bg-yellow-50 text-yellow-700 border-yellow-200", "Obetald", "fa-solid fa-credit-card"),
         OrderStatus::Pending  => ("bg-amber-100 text-amber-800 border-amber-200",  "Väntar",   "fa-solid fa-clock"),
         OrderStatus::Shipped  => ("bg-blue-100 text-blue-800 border-blue-200",     "Skickad",  "fa-solid fa-truck"),
         OrderStatus::Received => ("bg-green-100 text-green-800 border-green-200",  "Mottagen", "fa-solid fa-circle-check"),
+        OrderStatus::Cancelled       => ("bg-gray-100 text-gray-700 border-gray-200",       "Avbruten",     "fa-solid fa-ban"),
+        OrderStatus::ReturnRequested => ("bg-orange-100 text-orange-800 border-orange-200", "Retur begärd", "fa-solid fa-rotate-left"),
+        OrderStatus::Returned        => ("bg-purple-100 text-purple-800 border-purple-200", "Returnerad",   "fa-solid fa-box"),
+        OrderStatus::Refunded        => ("bg-gray-100 text-gray-700 border-gray-200",       "Återbetald",   "fa-solid fa-money-bill-wave"),
     };
     rsx! {
         span { class: "inline-flex items-center gap-1.5 px-2.5 py-1 rounded-full text-xs font-bold border {bg}",
@@ -38,6 +49,22 @@ fn VendorOrdersTab(vendor_id: Id<VendorEntity>) -> Element {
         vendor_orders(vendor_id, 100, 0).await
     });
     let mut status_msg: Signal<Option<String>> = use_signal(|| None);
+
+    // Flytta en order till en ny status och ladda om listan.
+    let set = move |order_id, new_status, message: &'static str| {
+        let mut sm = status_msg;
+        let mut r = orders_resource;
+        #[allow(unused_results)]
+        spawn(async move {
+            match set_status(order_id, new_status).await {
+                Ok(()) => {
+                    sm.set(Some(message.into()));
+                    r.restart();
+                }
+                Err(e) => sm.set(Some(AppError::describe(&e))),
+            }
+        });
+    };
  
     let orders_read  = orders_resource.read();
     let is_loading   = orders_read.is_none();
@@ -80,6 +107,7 @@ fn VendorOrdersTab(vendor_id: Id<VendorEntity>) -> Element {
                             {
                                 let order_id = order.id;
                                 let status = order.status;
+                                let decision = order.decision;
                                 let changed = order.product_changed;
                                 let t = order.time;
                                 let date_str = format!("{:04}-{:02}-{:02}", t.year(), t.month() as u8, t.day());
@@ -99,6 +127,18 @@ fn VendorOrdersTab(vendor_id: Id<VendorEntity>) -> Element {
                                                     "Produkten har ändrats sedan order lades"
                                                 }
                                             }
+                                            if let Some(address) = &order.address {
+                                                p { class: "text-xs text-gray-500 mt-0.5",
+                                                    i { class: "fa-solid fa-location-dot mr-1" }
+                                                    "{address.recipient}, {address.street}, {address.postal_code} {address.city}, {address.country}"
+                                                }
+                                            }
+                                            if let Some(method) = &order.shipping_method {
+                                                p { class: "text-xs text-gray-500 mt-0.5",
+                                                    i { class: "fa-solid fa-truck-fast mr-1" }
+                                                    "{method} ({order.shipping_fee:.2} kr)"
+                                                }
+                                            }
                                         }
                                         span { class: "text-sm text-gray-700 font-semibold text-center", "{order.number} st" }
                                         span { class: "text-xs text-gray-400 text-center", "{date_str}" }
@@ -109,30 +149,45 @@ fn VendorOrdersTab(vendor_id: Id<VendorEntity>) -> Element {
                                                 "Betald"
                                             }
                                         }
-                                        div {
+                                        div { class: "flex flex-col gap-1",
                                             if status == OrderStatus::Pending {
                                                 button {
                                                     class: "text-xs bg-blue-600 text-white font-bold px-3 py-1.5 rounded-lg hover:bg-blue-700 transition whitespace-nowrap",
-                                                    onclick: move |_| {
-                                                        let mut sm = status_msg;
-                                                        let mut r = orders_resource;
-                                                        #[allow(unused_results)]
-                                                        spawn(async move {
-                                                            match set_status(order_id, OrderStatus::Shipped).await {
-                                                                Ok(()) => {
-                                                                    sm.set(Some("Order markerad som skickad.".into()));
-                                                                    r.restart();
-                                                                }
-                                                                Err(e) => sm.set(Some(format!("Fel: {e}"))),
-                                                            }
-                                                        });
-                                                    },
+                                                    onclick: move |_| set(order_id, OrderStatus::Shipped, "Order markerad som skickad."),
                                                     i { class: "fa-solid fa-truck mr-1" }
                                                     "Markera skickad"
                                                 }
+                                            } else if status == OrderStatus::ReturnRequested {
+                                                button {
+                                                    class: "text-xs bg-purple-600 text-white font-bold px-3 py-1.5 rounded-lg hover:bg-purple-700 transition whitespace-nowrap",
+                                                    onclick: move |_| set(order_id, OrderStatus::Returned, "Retur godkänd."),
+                                                    i { class: "fa-solid fa-check mr-1" }
+                                                    "Godkänn retur"
+                                                }
+                                                button {
+                                                    class: "text-xs bg-gray-200 text-gray-700 font-bold px-3 py-1.5 rounded-lg hover:bg-gray-300 transition whitespace-nowrap",
+                                                    onclick: move |_| set(order_id, OrderStatus::Received, "Retur nekad."),
+                                                    i { class: "fa-solid fa-xmark mr-1" }
+                                                    "Neka retur"
+                                                }
+                                            } else if matches!(status, OrderStatus::Cancelled | OrderStatus::Returned) {
+                                                button {
+                                                    class: "text-xs bg-green-700 text-white font-bold px-3 py-1.5 rounded-lg hover:bg-green-800 transition whitespace-nowrap",
+                                                    onclick: move |_| set(order_id, OrderStatus::Refunded, "Order markerad som återbetald."),
+                                                    i { class: "fa-solid fa-money-bill-wave mr-1" }
+                                                    "Markera återbetald"
+                                                }
                                             } else {
                                                 span { class: "text-xs text-gray-300 px-3", "—" }
                                             }
+                                            // Ordern ändras först när alla säljare i den har valt samma status.
+                                            if let Some(decision) = decision {
+                                                span { class: "text-xs text-gray-500 text-center whitespace-nowrap",
+                                                    i { class: "fa-solid fa-hourglass-half mr-1" }
+                                                    "Väntar på övriga säljare"
+                                                }
+                                                OrderStatusBadge { status: decision }
+                                            }
                                         }
                                     }
                                 }
@@ -145,12 +200,240 @@ fn VendorOrdersTab(vendor_id: Id<VendorEntity>) -> Element {
     }
 }
  
+// ─── Shipping methods tab ─────────────────────────────────────────────────────
+
+/// Beskriv hur fraktavgiften beräknas, t.ex. "49.00 kr + 10.00 kr per påbörjat 1.00 kg".
+fn describe_rate(rate: &ShippingRate) -> String {
+    match rate {
+        ShippingRate::Flat { fee } => format!("{fee:.2} kr"),
+        ShippingRate::ByWeight { fee, fee_per_step, step } => {
+            format!("{fee:.2} kr + {fee_per_step:.2} kr per påbörjat {step}")
+        }
+    }
+}
+
+#[component]
+fn ShippingMethodsTab(vendor_id: Id<VendorEntity>) -> Element {
+    let mut methods_resource = use_resource(move || async move {
+        shipping_methods(vendor_id).await
+    });
+    let mut name         = use_signal(String::new);
+    let mut fee          = use_signal(|| "0".to_string());
+    let mut by_weight    = use_signal(|| false);
+    let mut fee_per_step = use_signal(String::new);
+    let mut step_qty     = use_signal(|| "1".to_string());
+    let mut step_unit    = use_signal(|| "kg".to_string());
+    let mut error        = use_signal(|| None::<String>);
+    let mut loading      = use_signal(|| false);
+
+    let methods_read = methods_resource.read();
+    let is_loading   = methods_read.is_none();
+    let err_str: Option<String> = methods_read.as_ref().and_then(|r| r.as_ref().err().map(|e| e.to_string()));
+    let methods_list = methods_read.as_ref().and_then(|r| r.as_ref().ok()).map(|v| v.to_vec());
+
+    rsx! {
+        div { class: "grid grid-cols-1 lg:grid-cols-2 gap-6",
+            div { class: "bg-white rounded-2xl shadow-sm p-6 border border-gray-100",
+                h2 { class: "font-black text-gray-900 text-lg mb-4", "Fraktsätt" }
+                if is_loading {
+                    p { class: "text-gray-400 animate-pulse", "Laddar..." }
+                } else if let Some(err) = err_str {
+                    p { class: "text-red-400 text-sm", "Fel: {err}" }
+                } else if let Some(methods) = methods_list {
+                    if methods.is_empty() {
+                        p { class: "text-gray-400 text-sm",
+                            "Inga fraktsätt ännu. Kunder kan inte köpa dina produkter förrän du har lagt till minst ett."
+                        }
+                    }
+                    div { class: "divide-y divide-gray-100",
+                        for method in methods.iter() {
+                            {
+                                let method_id = method.id;
+                                rsx! {
+                                    div { class: "flex items-center justify-between py-3",
+                                        div {
+                                            p { class: "font-bold text-sm text-gray-900", "{method.name}" }
+                                            p { class: "text-xs text-gray-500", "{describe_rate(&method.rate)}" }
+                                        }
+                                        button {
+                                            class: "text-gray-300 hover:text-red-500 transition",
+                                            onclick: move |_| {
+                                                #[allow(unused_results, reason = "Borttagningen körs i bakgrunden.")]
+                                                spawn(async move {
+                                                    match delete_shipping_method(method_id).await {
+                                                        Ok(()) => methods_resource.restart(),
+                                                        Err(e) => error.set(Some(AppError::describe(&e))),
+                                                    }
+                                                });
+                                            },
+                                            i { class: "fa-solid fa-trash" }
+                                        }
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+
+            div { class: "bg-white rounded-2xl shadow-sm p-6 border border-gray-100 space-y-3",
+                h2 { class: "font-black text-gray-900 text-lg mb-1", "Lägg till fraktsätt" }
+                input {
+                    class: "w-full border border-gray-200 rounded-xl px-4 py-2 text-sm",
+                    placeholder: "Namn, t.ex. Hemleverans",
+                    value: "{name}",
+                    oninput: move |e| name.set(e.value()),
+                }
+                label { class: "block text-xs font-bold text-gray-500", "Avgift (kr)" }
+                input {
+                    class: "w-full border border-gray-200 rounded-xl px-4 py-2 text-sm",
+                    r#type: "number",
+                    min: "0",
+                    step: "0.01",
+                    value: "{fee}",
+                    oninput: move |e| fee.set(e.value()),
+                }
+                label { class: "flex items-center gap-2 text-sm text-gray-700",
+                    input {
+                        r#type: "checkbox",
+                        checked: by_weight(),
+                        onchange: move |e| by_weight.set(e.checked()),
+                    }
+                    "Viktbaserad avgift"
+                }
+                if by_weight() {
+                    div { class: "flex gap-2 items-center text-sm",
+                        input {
+                            class: "w-24 border border-gray-200 rounded-xl px-3 py-2",
+                            r#type: "number",
+                            min: "0",
+                            step: "0.01",
+                            placeholder: "kr",
+                            value: "{fee_per_step}",
+                            oninput: move |e| fee_per_step.set(e.value()),
+                        }
+                        span { "kr per påbörjat" }
+                        input {
+                            class: "w-20 border border-gray-200 rounded-xl px-3 py-2",
+                            r#type: "number",
+                            min: "0",
+                            step: "0.01",
+                            value: "{step_qty}",
+                            oninput: move |e| step_qty.set(e.value()),
+                        }
+                        select {
+                            class: "border border-gray-200 rounded-xl px-3 py-2",
+                            value: "{step_unit}",
+                            onchange: move |e| step_unit.set(e.value()),
+                            option { value: "kg", "kg" }
+                            option { value: "hg", "hg" }
+                            option { value: "g", "g" }
+                        }
+                    }
+                    p { class: "text-xs text-gray-400",
+                        "Produkter vars mängd inte anges i vikt räknas som viktlösa."
+                    }
+                }
+                if let Some(err) = error() {
+                    p { class: "text-red-500 text-sm", "{err}" }
+                }
+                button {
+                    class: if loading() { "w-full bg-gray-300 text-gray-500 font-black py-3 rounded-xl cursor-not-allowed" } else { "w-full bg-green-700 text-white font-black py-3 rounded-xl hover:bg-green-800 transition" },
+                    disabled: loading(),
+                    onclick: move |_| {
+                        let name_val = name().trim().to_string();
+                        if name_val.is_empty() {
+                            error.set(Some("Ange ett namn.".to_string()));
+                            return;
+                        }
+                        let Ok(fee_val) = Decimal::from_str(fee().trim()) else {
+                            error.set(Some("Ogiltig avgift".to_string()));
+                            return;
+                        };
+                        let rate = if by_weight() {
+                            let Ok(per_step) = Decimal::from_str(fee_per_step().trim()) else {
+                                error.set(Some("Ogiltig viktavgift".to_string()));
+                                return;
+                            };
+                            let Ok(qty) = Decimal::from_str(step_qty().trim()) else {
+                                error.set(Some("Ogiltig vikt".to_string()));
+                                return;
+                            };
+                            ShippingRate::ByWeight {
+                                fee: fee_val,
+                                fee_per_step: per_step,
+                                step: Box::new(Amount::with_unit(qty, step_unit().into())),
+                            }
+                        } else {
+                            ShippingRate::Flat { fee: fee_val }
+                        };
+                        error.set(None);
+                        loading.set(true);
+                        #[allow(unused_results, reason = "Sparandet körs i bakgrunden.")]
+                        spawn(async move {
+                            match create_shipping_method(vendor_id, name_val.into(), rate).await {
+                                Ok(_) => {
+                                    name.set(String::new());
+                                    methods_resource.restart();
+                                }
+                                Err(e) => error.set(Some(AppError::describe(&e))),
+                            }
+                            loading.set(false);
+                        });
+                    },
+                    "Lägg till"
+                }
+            }
+        }
+    }
+}
+
+// ─── Image upload ─────────────────────────────────────────────────────────────
+ 
+#[component]
+fn ImageUpload(on_upload: EventHandler<Id<Media>>) -> Element {
+    let mut uploading = use_signal(|| false);
+    let mut error     = use_signal(|| None::<String>);
+    let max_mb = MAX_SIZE / (1024 * 1024);
+ 
+    rsx! {
+        div { class: "mt-2 flex items-center gap-3",
+            label { class: "inline-flex items-center gap-2 text-sm font-bold text-green-700 cursor-pointer hover:text-green-800 transition",
+                i { class: if uploading() { "fa-solid fa-spinner fa-spin" } else { "fa-solid fa-upload" } }
+                if uploading() { "Laddar upp..." } else { "Ladda upp bild" }
+                input {
+                    r#type: "file",
+                    class: "hidden",
+                    accept: "image/jpeg,image/png,image/webp",
+                    disabled: uploading(),
+                    onchange: move |e| {
+                        let Some(file) = e.files().into_iter().next() else { return };
+                        error.set(None);
+                        uploading.set(true);
+                        #[allow(unused_results)]
+                        spawn(async move {
+                            match upload_media(FileStream::from(file)).await {
+                                Ok(media) => on_upload.call(media),
+                                Err(_) => error.set(Some(format!("Bilden kunde inte laddas upp. Använd JPEG, PNG eller WebP på högst {max_mb} MB."))),
+                            }
+                            uploading.set(false);
+                        });
+                    },
+                }
+            }
+        }
+        if let Some(err) = error() {
+            p { class: "text-red-500 text-xs mt-1", "{err}" }
+        }
+    }
+}
+ 
 // ─── Add product modal ────────────────────────────────────────────────────────
  
 #[component]
 fn AddProductModal(vendor_id: Id<VendorEntity>, on_close: EventHandler<bool>) -> Element {
     let mut name        = use_signal(String::new);
-    let mut thumbnail   = use_signal(String::new);
+    let mut thumbnail   = use_signal(|| None::<Id<Media>>);
     let mut price_str   = use_signal(String::new);
     let mut overview    = use_signal(String::new);
     let mut description = use_signal(String::new);
@@ -214,14 +497,15 @@ fn AddProductModal(vendor_id: Id<VendorEntity>, on_close: EventHandler<bool>) ->
                         }
                     }
                     div {
-                        label { class: "block text-sm font-bold text-gray-700 mb-1", "Bild-URL *" }
-                        input {
-                            r#type: "url",
-                            class: "w-full border border-gray-200 rounded-lg px-3 py-2 focus:outline-none focus:ring-2 focus:ring-green-500",
-                            placeholder: "https://...",
-                            value: "{thumbnail}",
-                            oninput: move |e| thumbnail.set(e.value()),
+                        label { class: "block text-sm font-bold text-gray-700 mb-1", "Bild *" }
+                        if let Some(media) = thumbnail() {
+                            img {
+                                src: "{thumbnail_url(media)}",
+                                class: "w-32 h-32 object-cover rounded-lg border border-gray-200",
+                                alt: "Produktbild",
+                            }
                         }
+                        ImageUpload { on_upload: move |media| thumbnail.set(Some(media)) }
                     }
                     div {
                         label { class: "block text-sm font-bold text-gray-700 mb-1", "Pris (kr) *" }
@@ -337,16 +621,17 @@ fn AddProductModal(vendor_id: Id<VendorEntity>, on_close: EventHandler<bool>) ->
                             disabled: loading(),
                             onclick: move |_| {
                                 let name_val = name().trim().to_string();
-                                let thumb_val = thumbnail().trim().to_string();
+                                let thumb_val = thumbnail();
                                 let price_val = price_str().trim().to_string();
                                 let overview_val = overview().trim().to_string();
                                 let cat_val = category_id();
-                                if name_val.is_empty() || thumb_val.is_empty() || price_val.is_empty()
-                                    || overview_val.is_empty() || cat_val == 0
-                                {
+                                let Some(thumb_media) = thumb_val.filter(|_| {
+                                    !name_val.is_empty() && !price_val.is_empty()
+                                        && !overview_val.is_empty() && cat_val != 0
+                                }) else {
                                     error.set(Some("Fyll i alla obligatoriska fält (*)".to_string()));
                                     return;
-                                }
+                                };
                                 let Ok(price_dec) = Decimal::from_str(&price_val) else {
                                     error.set(Some("Ogiltigt pris".to_string()));
                                     return;
@@ -365,7 +650,6 @@ fn AddProductModal(vendor_id: Id<VendorEntity>, on_close: EventHandler<bool>) ->
                                     error.set(Some("Ogiltig mängd eller enhet".to_string()));
                                     return;
                                 };
-                                let thumb_url = Url::from(thumb_val);
                                 let desc_val = description().trim().to_string();
                                 let origin_val = origin().trim().to_string();
                                 let cat_id: Id<crate::database::Category> = cat_val.into();
@@ -376,7 +660,7 @@ fn AddProductModal(vendor_id: Id<VendorEntity>, on_close: EventHandler<bool>) ->
                                     match create_product(
                                             vendor_id,
                                             name_val.into(),
-                                            thumb_url,
+                                            thumb_media,
                                             Box::new([]),
                                             price_dec,
                                             overview_val.into(),
@@ -388,7 +672,7 @@ fn AddProductModal(vendor_id: Id<VendorEntity>, on_close: EventHandler<bool>) ->
                                         .await
                                     {
                                         Err(e) => {
-                                            error.set(Some(e.to_string()));
+                                            error.set(Some(AppError::describe(&e)));
                                             loading.set(false);
                                         }
                                         Ok(()) => on_close.call(true),
@@ -417,10 +701,12 @@ fn EditProductModal(product: ProductOverviewVendor, on_close: EventHandler<bool>
     let product_id    = product.id;
     let current_stock = product.in_stock;
     let mut name          = use_signal(|| product.name.to_string());
-    let mut thumbnail     = use_signal(|| product.thumbnail.to_string());
+    let current_thumbnail = product.thumbnail.clone();
+    let mut thumbnail     = use_signal(|| None::<Id<Media>>);
     let mut price_str     = use_signal(|| product.price.to_string());
     let mut overview_text = use_signal(|| product.overview.to_string());
     let mut origin_text   = use_signal(|| product.origin.to_string());
+    let mut language      = use_signal(|| product.search_language);
     let mut stock_add     = use_signal(|| "0".to_string());
     let mut visible       = use_signal(|| true);
     let mut error         = use_signal(|| None::<String>);
@@ -462,13 +748,16 @@ fn EditProductModal(product: ProductOverviewVendor, on_close: EventHandler<bool>
                         }
                     }
                     div {
-                        label { class: "block text-sm font-bold text-gray-700 mb-1", "Bild-URL" }
-                        input {
-                            r#type: "url",
-                            class: "w-full border border-gray-200 rounded-lg px-3 py-2 focus:outline-none focus:ring-2 focus:ring-green-500",
-                            value: "{thumbnail}",
-                            oninput: move |e| thumbnail.set(e.value()),
+                        label { class: "block text-sm font-bold text-gray-700 mb-1", "Bild" }
+                        img {
+                            src: match thumbnail() {
+                                Some(media) => thumbnail_url(media).to_string(),
+                                None => current_thumbnail.to_string(),
+                            },
+                            class: "w-32 h-32 object-cover rounded-lg border border-gray-200",
+                            alt: "Produktbild",
                         }
+                        ImageUpload { on_upload: move |media| thumbnail.set(Some(media)) }
                     }
                     div { class: "flex gap-3",
                         div { class: "flex-1",
@@ -517,6 +806,15 @@ fn EditProductModal(product: ProductOverviewVendor, on_close: EventHandler<bool>
                             oninput: move |e| origin_text.set(e.value()),
                         }
                     }
+                    div {
+                        label { class: "block text-sm font-bold text-gray-700 mb-1", "Sökspråk" }
+                        select {
+                            class: "w-full border border-gray-200 rounded-lg px-3 py-2 focus:outline-none focus:ring-2 focus:ring-green-500",
+                            onchange: move |e| language.set(if e.value() == "english" { SearchLanguage::English } else { SearchLanguage::Swedish }),
+                            option { value: "swedish", selected: language() == SearchLanguage::Swedish, "Svenska" }
+                            option { value: "english", selected: language() == SearchLanguage::English, "Engelska" }
+                        }
+                    }
                     div { class: "flex items-center gap-3",
                         input {
                             r#type: "checkbox",
@@ -548,10 +846,11 @@ fn EditProductModal(product: ProductOverviewVendor, on_close: EventHandler<bool>
                             disabled: loading(),
                             onclick: move |_| {
                                 let name_val = name().trim().to_string();
-                                let thumb_val = thumbnail().trim().to_string();
+                                let thumb_val = thumbnail();
                                 let price_val = price_str().trim().to_string();
                                 let overview_val = overview_text().trim().to_string();
                                 let origin_val = origin_text().trim().to_string();
+                                let language_val = language();
                                 let visible_val = visible();
                                 let stock_to_add = stock_add().trim().parse::<u32>().unwrap_or(0);
                                 let Ok(price_dec) = Decimal::from_str(&price_val) else {
@@ -565,26 +864,32 @@ fn EditProductModal(product: ProductOverviewVendor, on_close: EventHandler<bool>
                                 let _task = spawn(async move {
                                     let mut errs: Vec<String> = vec![];
                                     if let Err(e) = set_product_name(product_id, name_val.into()).await {
-                                        errs.push(e.to_string());
+                                        errs.push(AppError::describe(&e));
                                     }
-                                    if let Err(e) = set_thumbnail(product_id, Url::from(thumb_val)).await {
-                                        errs.push(e.to_string());
+                                    // Den nuvarande bilden behålls om ingen ny har laddats upp.
+                                    if let Some(media) = thumb_val
+                                        && let Err(e) = set_thumbnail(product_id, media).await
+                                    {
+                                        errs.push(AppError::describe(&e));
                                     }
                                     if let Err(e) = set_price(product_id, price_dec).await {
-                                        errs.push(e.to_string());
+                                        errs.push(AppError::describe(&e));
                                     }
                                     if let Err(e) = set_overview(product_id, overview_val.into()).await {
-                                        errs.push(e.to_string());
+                                        errs.push(AppError::describe(&e));
                                     }
                                     if let Err(e) = set_origin(product_id, origin_val.into()).await {
-                                        errs.push(e.to_string());
+                                        errs.push(AppError::describe(&e));
+                                    }
+                                    if let Err(e) = set_search_language(product_id, language_val).await {
+                                        errs.push(AppError::describe(&e));
                                     }
                                     if let Err(e) = set_visibility(product_id, visible_val).await {
-                                        errs.push(e.to_string());
+                                        errs.push(AppError::describe(&e));
                                     }
                                     if let Some(n) = std::num::NonZeroU32::new(stock_to_add) {
                                         if let Err(e) = add_stock(product_id, n, None).await.map(|_| ()) {
-                                            errs.push(e.to_string());
+                                            errs.push(AppError::describe(&e));
                                         }
                                     }
                                     loading.set(false);
@@ -698,6 +1003,12 @@ pub fn VendorPage(id: Id<VendorEntity>) -> Element {
                             i { class: "fa-solid fa-bag-shopping mr-2" }
                             "Ordrar"
                         }
+                        button {
+                            class: if active_tab() == 2 { "px-4 py-2 font-bold text-green-700 border-b-2 border-green-700" } else { "px-4 py-2 text-gray-500 hover:text-gray-700" },
+                            onclick: move |_| active_tab.set(2),
+                            i { class: "fa-solid fa-truck mr-2" }
+                            "Frakt"
+                        }
                     }
                 }
 
@@ -773,6 +1084,11 @@ pub fn VendorPage(id: Id<VendorEntity>) -> Element {
                 if active_tab() == 1 && is_own_profile {
                     VendorOrdersTab { vendor_id: id }
                 }
+
+                // ── Shipping tab ──
+                if active_tab() == 2 && is_own_profile {
+                    ShippingMethodsTab { vendor_id: id }
+                }
             }
         }
 